@@ -0,0 +1,89 @@
+//! Timings of the blend core on synthetic frames: `cargo bench`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{blend_pixel, compose_trail, history_age, overlay_tinted, TrailSettings};
+
+const SIZE: u32 = 2048;
+
+/// Pseudo-random but repeatable noise, so runs compare like with like
+fn noise(x: u32, y: u32, seed: u32) -> u32 {
+    let mut h = x.wrapping_mul(0x9e37_79b1) ^ y.wrapping_mul(0x85eb_ca77) ^ seed.wrapping_mul(0xc2b2_ae3d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^ (h >> 12)
+}
+
+/// A frame with echoes in about one pixel in `one_in`, the rest transparent
+fn frame(one_in: u32, seed: u32) -> RgbaImage {
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let n = noise(x, y, seed);
+        if n.is_multiple_of(one_in) {
+            let level = (n >> 8) as u8;
+            Rgba([level, level, level, 255])
+        } else {
+            Rgba([0, 0, 0, 0])
+        }
+    })
+}
+
+fn bench_blend_pixel(c: &mut Criterion) {
+    let pixels: Vec<(Rgba<u8>, Rgba<u8>, u8)> = (0..1024)
+        .map(|i| {
+            let n = noise(i, 0, 1).to_le_bytes();
+            (Rgba([n[0], n[1], n[2], 255]), Rgba([n[1], n[2], n[3], n[0]]), n[3])
+        })
+        .collect();
+    let mut group = c.benchmark_group("blend_pixel");
+    group.throughput(Throughput::Elements(pixels.len() as u64));
+    group.bench_function("1024 pixels", |b| {
+        b.iter(|| {
+            for &(dst, src, alpha) in &pixels {
+                black_box(blend_pixel(black_box(dst), black_box(src), black_box(alpha)));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_overlay_tinted(c: &mut Criterion) {
+    let canvas = RgbaImage::from_pixel(SIZE, SIZE, Rgba([0, 0, 0, 255]));
+    let mut group = c.benchmark_group("overlay_tinted");
+    group.throughput(Throughput::Elements(SIZE as u64 * SIZE as u64));
+    group.sample_size(20);
+    for (name, one_in) in [("sparse", 20), ("dense", 1)] {
+        let src = frame(one_in, 7);
+        group.bench_with_input(BenchmarkId::new(name, SIZE), &src, |b, src| {
+            b.iter_batched_ref(
+                || canvas.clone(),
+                |dst| overlay_tinted(dst, src, (255, 127, 0), 96),
+                criterion::BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_compose_trail(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compose_trail");
+    group.throughput(Throughput::Elements(SIZE as u64 * SIZE as u64));
+    group.sample_size(10);
+    for history_length in [1, 5, 10] {
+        let settings = TrailSettings { history_length, ..TrailSettings::default() };
+        let frames: Vec<RgbaImage> = (0..=history_length as u32).map(|seed| frame(20, seed)).collect();
+        let (current, earlier) = frames.split_last().unwrap();
+        let history: Vec<(&RgbaImage, f32)> = earlier.iter()
+            .enumerate()
+            .map(|(i, frame)| (frame, history_age(history_length - i, history_length)))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("history", history_length), &settings, |b, settings| {
+            b.iter(|| compose_trail(current, &history, settings))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_blend_pixel, bench_overlay_tinted, bench_compose_trail);
+criterion_main!(benches);
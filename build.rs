@@ -1,3 +1,36 @@
 fn main() {
+    #[cfg(feature = "gui")]
     slint_build::compile("ui/appwindow.slint").unwrap();
+    
+    #[cfg(feature = "ffi")]
+    ffi();
+}
+
+/// Generate the C header into `OUT_DIR`, and compile the C example against it
+/// for the test that runs it. The example is linked into the test binaries
+/// only, never into the library.
+#[cfg(feature = "ffi")]
+fn ffi() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=examples/ffi/compose.c");
+    
+    let out_dir = std::path::PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    let include = out_dir.join("include");
+    let config = cbindgen::Config::from_file("cbindgen.toml").unwrap();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/ffi.rs")
+        .generate()
+        .expect("generating the C header")
+        .write_to_file(include.join("radar_echo_trails.h"));
+    
+    // Without its main, so the test binary can call it
+    cc::Build::new()
+        .file("examples/ffi/compose.c")
+        .include(&include)
+        .define("RET_EXAMPLE_NO_MAIN", None)
+        .cargo_metadata(false)
+        .compile("ret_ffi_example");
+    println!("cargo:rustc-link-arg-tests={}", out_dir.join("libret_ffi_example.a").display());
 }
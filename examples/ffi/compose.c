@@ -0,0 +1,67 @@
+/*
+ * Composites a short sequence through the C interface: one echo pixel moving
+ * right, leaving a fading trail behind it.
+ *
+ * Build the library and the example with:
+ *   cargo rustc --lib --release --features ffi --crate-type staticlib
+ *   cc examples/ffi/compose.c -Iinclude target/release/libradar_echo_trails.a -lpthread -ldl -lm -o compose
+ */
+#include <stdio.h>
+#include <string.h>
+
+#include "radar_echo_trails.h"
+
+enum { WIDTH = 4, HEIGHT = 2, STRIDE = WIDTH * 4 };
+
+static const uint8_t *pixel(const uint8_t *image, int x, int y) {
+    return image + y * STRIDE + x * 4;
+}
+
+int ret_example_run(void) {
+    RetSettings settings = ret_settings_default();
+    settings.history_length = 2;
+
+    RetCompositor *compositor = ret_compositor_new(&settings);
+    if (compositor == NULL) {
+        fprintf(stderr, "ret_compositor_new: %s\n", ret_last_error_message());
+        return 1;
+    }
+
+    uint8_t frame[HEIGHT * STRIDE];
+    uint8_t trail[HEIGHT * STRIDE];
+    for (int i = 0; i < 3; i++) {
+        /* Transparent, apart from a white echo at (i, 0) */
+        memset(frame, 0, sizeof frame);
+        memset(frame + i * 4, 255, 4);
+
+        RetStatus status = ret_compositor_push(compositor, frame, WIDTH, HEIGHT, STRIDE, trail, STRIDE);
+        if (status != RET_STATUS_OK) {
+            fprintf(stderr, "ret_compositor_push: %s\n", ret_last_error_message());
+            ret_compositor_free(compositor);
+            return 1;
+        }
+    }
+
+    /* The current echo in green, the one before it in faded orange, no echo in black */
+    const uint8_t *current = pixel(trail, 2, 0);
+    const uint8_t *previous = pixel(trail, 1, 0);
+    const uint8_t *empty = pixel(trail, 3, 0);
+    int ok = current[0] == 0 && current[1] == 255 && current[2] == 0
+        && previous[0] > 0 && previous[0] < 255 && previous[2] == 0
+        && empty[0] == 0 && empty[1] == 0 && empty[2] == 0
+        && ret_compositor_depth(compositor) == 2;
+
+    /* Errors come back as a status, with a message */
+    RetStatus status = ret_compositor_push(compositor, frame, WIDTH, HEIGHT, 1, trail, STRIDE);
+    ok = ok && status == RET_STATUS_INVALID_ARGUMENT && ret_last_error_message() != NULL;
+
+    ret_compositor_free(compositor);
+    printf("%s\n", ok ? "trail as expected" : "unexpected trail");
+    return ok ? 0 : 1;
+}
+
+#ifndef RET_EXAMPLE_NO_MAIN
+int main(void) {
+    return ret_example_run();
+}
+#endif
@@ -0,0 +1,122 @@
+#ifndef RADAR_ECHO_TRAILS_H
+#define RADAR_ECHO_TRAILS_H
+
+/* Generated by cbindgen from src/ffi.rs when building with the ffi feature; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a call through the C interface
+ */
+typedef enum RetStatus {
+  RET_STATUS_OK = 0,
+  /**
+   * A required pointer was null
+   */
+  RET_STATUS_NULL_POINTER = 1,
+  /**
+   * A size, stride or setting was out of range
+   */
+  RET_STATUS_INVALID_ARGUMENT = 2,
+  /**
+   * The engine failed unexpectedly; the handle should not be used again
+   */
+  RET_STATUS_PANIC = 3,
+} RetStatus;
+
+/**
+ * A streaming compositor, created by [`ret_compositor_new`]
+ */
+typedef struct RetCompositor RetCompositor;
+
+/**
+ * Render settings as plain C types. Colors are red, green, blue.
+ */
+typedef struct RetSettings {
+  /**
+   * Frames of trail behind the current one
+   */
+  uint32_t history_length;
+  uint8_t background[3];
+  uint8_t current[3];
+  uint8_t history[3];
+} RetSettings;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The app's default settings
+ */
+struct RetSettings ret_settings_default(void);
+
+/**
+ * Create a compositor, or return null and set the last error message.
+ *
+ * # Safety
+ *
+ * `settings` must be null or point to a valid `RetSettings`.
+ */
+struct RetCompositor *ret_compositor_new(const struct RetSettings *settings);
+
+/**
+ * Destroy a compositor. Null is ignored.
+ *
+ * # Safety
+ *
+ * `compositor` must be null or a handle from [`ret_compositor_new`] not yet destroyed.
+ */
+void ret_compositor_free(struct RetCompositor *compositor);
+
+/**
+ * Composite an RGBA frame with the frames pushed before it, writing the
+ * trail into `out`, which has the same width and height. Rows are `stride`
+ * and `out_stride` bytes apart, at least `width * 4`.
+ *
+ * # Safety
+ *
+ * `compositor` must be a live handle. `pixels` must point to `height` rows of
+ * `stride` bytes and `out` to `height` rows of `out_stride` writable bytes.
+ */
+enum RetStatus ret_compositor_push(struct RetCompositor *compositor,
+                                   const uint8_t *pixels,
+                                   uint32_t width,
+                                   uint32_t height,
+                                   size_t stride,
+                                   uint8_t *out,
+                                   size_t out_stride);
+
+/**
+ * Frames currently held in the compositor's history window, or 0 for null
+ *
+ * # Safety
+ *
+ * `compositor` must be null or a live handle.
+ */
+uint32_t ret_compositor_depth(const struct RetCompositor *compositor);
+
+/**
+ * Forget the history window, at a boundary between sequences
+ *
+ * # Safety
+ *
+ * `compositor` must be null or a live handle.
+ */
+void ret_compositor_reset(struct RetCompositor *compositor);
+
+/**
+ * The message of the last failure on this thread, or null if there was none.
+ * The string stays valid until the next failing call on the same thread.
+ */
+const char *ret_last_error_message(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RADAR_ECHO_TRAILS_H */
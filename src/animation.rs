@@ -0,0 +1,53 @@
+//! Frame timing of animated outputs
+//!
+//! Every animated encoder takes its timing from one [`AnimationTiming`], so
+//! the frame rate, looping and real-time playback behave the same whatever the
+//! format. At a fixed rate every frame is held for `1 / fps` seconds. In real
+//! time each frame is held in proportion to the time to the next scan, so a
+//! ten minute gap lasts ten times a one minute one: the usual (median) interval
+//! plays at `fps`, and no frame is held longer than `max_frame_ms`. The last
+//! frame, with no scan after it, is held as long as the usual interval.
+//! Sequences whose file names don't all carry scan times play at the fixed rate.
+
+use crate::settings::AnimationTiming;
+
+/// Shortest a frame is held, in milliseconds: GIF frames take whole hundredths
+/// of a second, and players treat shorter ones as a default
+pub const MIN_FRAME_MS: u32 = 10;
+
+/// How long each frame scanned at `times`, in seconds since the Unix epoch,
+/// is held, in milliseconds
+pub fn frame_durations(times: &[Option<i64>], timing: &AnimationTiming) -> Vec<u32> {
+    let frame_ms = 1000.0 / timing.fps as f64;
+    let fixed = vec![(frame_ms.round() as u32).max(MIN_FRAME_MS); times.len()];
+    if !timing.realtime {
+        return fixed;
+    }
+    let Some(times) = times.iter().copied().collect::<Option<Vec<i64>>>() else { return fixed };
+    let mut intervals: Vec<i64> = times.windows(2).map(|pair| pair[1] - pair[0]).filter(|&dt| dt > 0).collect();
+    intervals.sort_unstable();
+    let Some(&median) = intervals.get(intervals.len() / 2) else { return fixed };
+
+    let longest = timing.max_frame_ms.max(MIN_FRAME_MS);
+    (0..times.len())
+        .map(|index| {
+            let interval = times.get(index + 1).map_or(median, |next| next - times[index]);
+            let ms = frame_ms * interval.max(0) as f64 / median as f64;
+            (ms.round() as u32).clamp(MIN_FRAME_MS, longest)
+        })
+        .collect()
+}
+
+
+/// `durations` of a sequence of scans, with each scan but the last sharing its
+/// time evenly with the `frames` interpolated after it, in sequence order
+pub fn interpolated_durations(durations: &[u32], frames: u32) -> Vec<u32> {
+    let last = durations.len().saturating_sub(1);
+    durations.iter()
+        .enumerate()
+        .flat_map(|(index, &ms)| {
+            let shares = if index < last { frames as usize + 1 } else { 1 };
+            std::iter::repeat_n((ms / shares as u32).max(MIN_FRAME_MS), shares)
+        })
+        .collect()
+}
@@ -0,0 +1,65 @@
+//! A map drawn under the trails
+//!
+//! County outlines, range rings and the like can be given as an image the
+//! trails are drawn over in place of the flat background color. It is decoded
+//! once as each folder starts, before any frame, and shared by every frame
+//! drawn. It must be the size the frames are drawn at unless it is set to be
+//! stretched to them. Where it is transparent, or a frame reaches past it,
+//! the background color shows.
+
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::engine::{TrailColors, TrailSettings};
+
+#[cfg(feature = "native")]
+use {
+    anyhow::{bail, Context, Result},
+    std::path::Path,
+    crate::engine,
+};
+
+/// A frame of `width` by `height` in the background color, or transparent
+/// with `settings.transparent_background`, with the background image of
+/// `settings`, if any, over it from the top left corner
+pub fn backdrop(settings: &TrailSettings, width: u32, height: u32) -> RgbaImage {
+    let (r, g, b) = TrailColors::from_settings(settings).background;
+    let fill = match settings.transparent_background {
+        true => Rgba([0, 0, 0, 0]),
+        false => Rgba([r, g, b, 255]),
+    };
+    let mut output = RgbaImage::from_pixel(width, height, fill);
+    if let Some(image) = &settings.background {
+        imageops::overlay(&mut output, &**image, 0, 0);
+    }
+    output
+}
+
+/// The size the frames of a folder are drawn at: the canvas's, or that of
+/// its first frame, `first`
+#[cfg(feature = "native")]
+pub fn frame_size(first: &Path, settings: &TrailSettings) -> Result<(u32, u32)> {
+    match settings.canvas.size {
+        Some(size) => Ok(size),
+        None => Ok(engine::load_frame(first, settings)?.dimensions()),
+    }
+}
+
+/// Decode the background image at `path` for frames drawn at `size`:
+/// stretched to it with `settings.fit_background_image`, and otherwise an
+/// error when it is another size
+#[cfg(feature = "native")]
+pub fn load(path: &Path, size: (u32, u32), settings: &TrailSettings) -> Result<RgbaImage> {
+    let image = image::open(path)
+        .with_context(|| format!("loading background image {}", path.display()))?
+        .to_rgba8();
+    if image.dimensions() == size {
+        return Ok(image);
+    }
+    if !settings.fit_background_image {
+        bail!(
+            "background image {} is {}x{} but the frames are {}x{}; turn on Stretch Background Image to fit it to them",
+            path.display(), image.width(), image.height(), size.0, size.1
+        );
+    }
+    Ok(engine::resize_frame(&image, size, settings.resize_filter))
+}
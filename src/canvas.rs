@@ -0,0 +1,72 @@
+//! Frames placed on a larger canvas before they are drawn
+//!
+//! Trails meant for a broadcast frame, such as 1920 by 1080, need margins for
+//! titles and a legend around the radar image. With a canvas size set, each
+//! frame is placed on a transparent canvas of that size as it is decoded, in
+//! the middle, at the top left or at a set offset, after everything that
+//! reads the frame's own pixels: sidecar masks, static masks, the range
+//! correction and the echo rule. Everything drawn after that sees the canvas:
+//! the background fills all of it, and motion vectors, tracks and forecast
+//! stamps are placed over the whole of it.
+//!
+//! A frame larger than the canvas at its offset cannot be placed and fails.
+//! Positions written out for other programs, in `cells.csv` and the motion
+//! vectors' JSON, are moved back to the frame's own pixels, and coverage is
+//! the share of the frame, not of the canvas, that has echo.
+
+use image::{imageops, RgbaImage};
+
+use crate::settings::{CanvasSettings, Placement};
+
+/// Top-left pixel of the canvas a frame of `size` is placed at; `(0, 0)`
+/// without a canvas. Offsets are clamped at 0 for frames that don't fit.
+pub fn origin(canvas: &CanvasSettings, size: (u32, u32)) -> (u32, u32) {
+    let Some((width, height)) = canvas.size else { return (0, 0) };
+    match canvas.placement {
+        Placement::Center => (width.saturating_sub(size.0) / 2, height.saturating_sub(size.1) / 2),
+        Placement::TopLeft => (0, 0),
+        Placement::Custom { x, y } => (x, y),
+    }
+}
+
+/// Whether a frame of `size` fits the canvas at its offset, described for the
+/// user when it doesn't
+pub fn check_fits(canvas: &CanvasSettings, size: (u32, u32)) -> Result<(), String> {
+    let Some((width, height)) = canvas.size else { return Ok(()) };
+    let (x, y) = origin(canvas, size);
+    if x as u64 + size.0 as u64 > width as u64 || y as u64 + size.1 as u64 > height as u64 {
+        return Err(format!(
+            "a {}x{} frame placed at {},{} does not fit the {}x{} canvas",
+            size.0, size.1, x, y, width, height
+        ));
+    }
+    Ok(())
+}
+
+/// `frame` on a transparent canvas at its offset; the frame itself without a
+/// canvas. The part of a frame that doesn't fit is cut off.
+pub fn place(frame: RgbaImage, canvas: &CanvasSettings) -> RgbaImage {
+    let Some((width, height)) = canvas.size else { return frame };
+    if frame.dimensions() == (width, height) {
+        return frame;
+    }
+    let (x, y) = origin(canvas, frame.dimensions());
+    let mut placed = RgbaImage::new(width, height);
+    imageops::replace(&mut placed, &frame, x as i64, y as i64);
+    placed
+}
+
+/// `frame` centered on a transparent image of `size`, cut off at the sides
+/// where it is larger
+pub fn center(frame: &RgbaImage, (width, height): (u32, u32)) -> RgbaImage {
+    let mut centered = RgbaImage::new(width, height);
+    let x = (width as i64 - frame.width() as i64).div_euclid(2);
+    let y = (height as i64 - frame.height() as i64).div_euclid(2);
+    imageops::replace(&mut centered, frame, x, y);
+    centered
+}
+
+/// `position` on the canvas as a pixel of the frame placed at `origin`
+pub fn to_frame((x, y): (f32, f32), origin: (u32, u32)) -> (f32, f32) {
+    (x - origin.0 as f32, y - origin.1 as f32)
+}
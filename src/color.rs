@@ -0,0 +1,240 @@
+//! Telling the trail colors apart, including under common color vision deficiencies
+
+use std::fmt;
+
+use crate::settings::{self, GradientSpace, ProcessingSettings};
+
+/// Smallest CIE76 difference between two colors that still reads as clearly distinct
+pub const MIN_COLOR_DISTANCE: f32 = 20.0;
+
+/// Color vision the contrast check simulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vision {
+    Typical,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Vision {
+    pub const ALL: [Vision; 4] = [Vision::Typical, Vision::Protanopia, Vision::Deuteranopia, Vision::Tritanopia];
+
+    /// Machado et al. (2009) simulation matrix for linear RGB, at full severity
+    fn matrix(self) -> Option<[[f32; 3]; 3]> {
+        match self {
+            Vision::Typical => None,
+            Vision::Protanopia => Some([
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ]),
+            Vision::Deuteranopia => Some([
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ]),
+            Vision::Tritanopia => Some([
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ]),
+        }
+    }
+}
+
+impl fmt::Display for Vision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Vision::Typical => "typical color vision",
+            Vision::Protanopia => "protanopia",
+            Vision::Deuteranopia => "deuteranopia",
+            Vision::Tritanopia => "tritanopia",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Two of the trail colors that are too close to tell apart
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContrastWarning {
+    pub first: &'static str,
+    pub second: &'static str,
+    /// The vision under which the pair is hardest to distinguish
+    pub vision: Vision,
+    pub distance: f32,
+}
+
+impl fmt::Display for ContrastWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The {} and {} colors are hard to tell apart with {} (ΔE {:.0})",
+            self.first, self.second, self.vision, self.distance
+        )
+    }
+}
+
+/// Names of the history colors in contrast warnings, by how many frames back
+/// they color; the last names any further back
+const HISTORY_AGES: [&str; 9] = [
+    "history 1 frame back",
+    "history 2 frames back",
+    "history 3 frames back",
+    "history 4 frames back",
+    "history 5 frames back",
+    "history 6 frames back",
+    "history 7 frames back",
+    "history 8 frames back",
+    "older history",
+];
+
+/// Compare each pair of the background, current and history colors under every
+/// simulated vision, warning once per pair whose closest distance falls below
+/// [`MIN_COLOR_DISTANCE`]. Colors that do not parse are left to validation.
+pub fn check_contrast(settings: &ProcessingSettings) -> Vec<ContrastWarning> {
+    let colors = [
+        ("background", &settings.background_color),
+        ("current", &settings.current_color),
+        ("history", &settings.history_color),
+    ];
+    let mut parsed: Vec<_> = colors.iter()
+        .filter_map(|(name, hex)| settings::parse_hex_color(hex).ok().map(|rgb| (*name, rgb)))
+        .collect();
+    // A gradient's ends take the place of the history color
+    if let Ok(Some((newest, oldest))) = settings::parse_history_gradient(&settings.history_gradient) {
+        parsed.retain(|&(name, _)| name != "history");
+        parsed.push(("newest history", newest));
+        if oldest != newest {
+            parsed.push(("oldest history", oldest));
+        }
+    }
+    // As do the colors of each age
+    let by_age: Vec<_> = settings.history_colors.iter().filter_map(|hex| settings::parse_hex_color(hex).ok()).collect();
+    if !by_age.is_empty() {
+        parsed.retain(|&(name, _)| !name.ends_with("history"));
+        for (i, &color) in by_age.iter().enumerate() {
+            if !by_age[..i].contains(&color) {
+                parsed.push((HISTORY_AGES[i.min(HISTORY_AGES.len() - 1)], color));
+            }
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for (i, &(first, a)) in parsed.iter().enumerate() {
+        for &(second, b) in &parsed[i + 1..] {
+            let closest = Vision::ALL.iter()
+                .map(|&vision| (vision, distance(a, b, vision)))
+                .min_by(|x, y| x.1.total_cmp(&y.1));
+            if let Some((vision, distance)) = closest && distance < MIN_COLOR_DISTANCE {
+                warnings.push(ContrastWarning { first, second, vision, distance });
+            }
+        }
+    }
+    warnings
+}
+
+/// Approximate perceptual distance (CIE76) between two sRGB colors as seen with `vision`
+pub fn distance(a: (u8, u8, u8), b: (u8, u8, u8), vision: Vision) -> f32 {
+    let a = to_lab(simulate(linear_rgb(a), vision));
+    let b = to_lab(simulate(linear_rgb(b), vision));
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// The color `t` of the way from `a` to `b`, mixed in `space`
+///
+/// ```
+/// use radar_echo_trails::color::mix;
+/// use radar_echo_trails::settings::GradientSpace;
+///
+/// assert_eq!(mix((255, 0, 0), (0, 0, 255), 0.0, GradientSpace::LinearRgb), (255, 0, 0));
+/// // Half of each in linear light, brighter than the (128, 0, 128) of sRGB
+/// assert_eq!(mix((255, 0, 0), (0, 0, 255), 0.5, GradientSpace::LinearRgb), (188, 0, 188));
+/// assert_eq!(mix((255, 0, 0), (0, 0, 255), 0.5, GradientSpace::Hsv), (255, 0, 255));
+/// ```
+pub fn mix(a: (u8, u8, u8), b: (u8, u8, u8), t: f32, space: GradientSpace) -> (u8, u8, u8) {
+    let t = t.clamp(0.0, 1.0);
+    match space {
+        GradientSpace::LinearRgb => {
+            let (a, b) = (linear_rgb(a), linear_rgb(b));
+            srgb([0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t))
+        }
+        GradientSpace::Hsv => {
+            let (a, b) = (to_hsv(a), to_hsv(b));
+            // A grey has no hue of its own, so it takes the other end's
+            let (hue_a, hue_b) = match (a[1] == 0.0, b[1] == 0.0) {
+                (true, false) => (b[0], b[0]),
+                (false, true) => (a[0], a[0]),
+                _ => (a[0], b[0]),
+            };
+            let turn = (hue_b - hue_a + 540.0) % 360.0 - 180.0;
+            let hue = (hue_a + turn * t).rem_euclid(360.0);
+            from_hsv([hue, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t])
+        }
+    }
+}
+
+fn linear_rgb((r, g, b): (u8, u8, u8)) -> [f32; 3] {
+    [r, g, b].map(|c| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    })
+}
+
+fn srgb(rgb: [f32; 3]) -> (u8, u8, u8) {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        let c = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+        (c * 255.0).round() as u8
+    });
+    (r, g, b)
+}
+
+/// Hue in degrees, saturation and value from 0 to 1
+fn to_hsv((r, g, b): (u8, u8, u8)) -> [f32; 3] {
+    let [r, g, b] = [r, g, b].map(|c| c as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let range = max - r.min(g).min(b);
+    let hue = if range == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / range).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / range + 2.0)
+    } else {
+        60.0 * ((r - g) / range + 4.0)
+    };
+    [hue, if max == 0.0 { 0.0 } else { range / max }, max]
+}
+
+fn from_hsv([hue, saturation, value]: [f32; 3]) -> (u8, u8, u8) {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let byte = |c: f32| ((c + value - chroma) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (byte(r), byte(g), byte(b))
+}
+
+fn simulate(rgb: [f32; 3], vision: Vision) -> [f32; 3] {
+    let Some(m) = vision.matrix() else { return rgb };
+    [0, 1, 2].map(|row| (m[row][0] * rgb[0] + m[row][1] * rgb[1] + m[row][2] * rgb[2]).clamp(0.0, 1.0))
+}
+
+/// Linear sRGB to CIELAB under the D65 white point
+fn to_lab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.119192 * g + 0.9503041 * b) / 1.08883;
+    let f = |t: f32| {
+        const DELTA: f32 = 6.0 / 29.0;
+        if t > DELTA.powi(3) { t.cbrt() } else { t / (3.0 * DELTA * DELTA) + 4.0 / 29.0 }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
@@ -1,52 +1,1485 @@
 //! Settings persistence
 
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::queue::SortOrder;
+
+use crate::processing::{
+    self, default_folder_parallelism, default_output_name_template, default_stride, AlphaSource, AnimationTiming, CanvasSettings, CollisionPolicy, CurrentStyle, ErrorPolicy, GifDither, GradientSpace, HistoryStyle, InterpolationSettings,
+    InvalidField, LayerOutput, MotionSettings, NowcastSettings, OdimSelection, Preroll, ProcessingSettings, RepeatPolicy, ResampleSettings,
+    RangeCorrection, ResizeFilter, SecondarySettings, SizeMismatch, StaticMaskSettings, TrackingSettings, TrailMode,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
-    pub history_length: i32,
+    /// Frames to render per folder; files from before this field was optional store 0 for no limit
+    #[serde(default, deserialize_with = "deserialize_limit")]
+    pub limit: Option<usize>,
+    /// Frames skipped at the start of each folder
+    #[serde(default)]
+    pub offset: usize,
+    /// Render every this many frames after the offset and limit
+    #[serde(default = "default_stride")]
+    pub stride: usize,
+    #[serde(default)]
+    pub preflight: bool,
+    #[serde(default)]
+    pub repeated_frames: RepeatPolicy,
+    #[serde(default)]
+    pub on_error: ErrorPolicy,
+    #[serde(default)]
+    pub ignore_manifests: bool,
+    /// Keep hidden and system files when scanning folders
+    #[serde(default)]
+    pub include_hidden: bool,
+    /// Order of a folder's frames when no manifest gives one
+    #[serde(default)]
+    pub sort_order: SortOrder,
+    /// `FROM=TO` prefixes rewritten when importing a queue from another machine
+    #[serde(default)]
+    pub import_path_mappings: Vec<String>,
+    /// Parent directory watched for new frame folders
+    #[serde(default)]
+    pub watch_folder: Option<PathBuf>,
+    /// Seconds a new folder's file count must stay unchanged before it is queued
+    #[serde(default = "default_watch_settle_secs")]
+    pub watch_settle_secs: i32,
+    /// Start processing as soon as a watched folder is queued
+    #[serde(default)]
+    pub watch_auto_start: bool,
+    /// Give re-added folders the settings they were last processed with, without asking
+    #[serde(default)]
+    pub reuse_previous_settings: bool,
+    /// Format of the settings file in the config directory
+    #[serde(default)]
+    pub config_format: ConfigFormat,
+    /// Pick up edits made to the settings file while the app runs
+    #[serde(default)]
+    pub reload_settings_file: bool,
+    /// Earlier versions of the settings file kept as `settings.json.1`, `.2`, ...
+    #[serde(default = "default_settings_backups")]
+    pub settings_backups: usize,
+    /// Preset the settings were last set from, until one of them is changed
+    #[serde(default)]
+    pub active_preset: Option<String>,
+    // Groups come last so TOML can write them as tables after the plain values
+    #[serde(default)]
+    pub rendering: RenderingSettings,
+    #[serde(default)]
+    pub output: OutputSettings,
+    #[serde(default)]
+    pub performance: PerformanceSettings,
+    /// Product drawn from ODIM_H5 radar files, with the `hdf5` feature
+    #[serde(default)]
+    pub odim: OdimSelection,
+    /// Motion arrows over the trails
+    #[serde(default)]
+    pub motion: MotionSettings,
+    /// Centroid tracks over the trails
+    #[serde(default)]
+    pub tracking: TrackingSettings,
+    /// Forecast frames after the sequence
+    #[serde(default)]
+    pub nowcast: NowcastSettings,
+    /// Frames synthesised between scans
+    #[serde(default)]
+    pub interpolation: InterpolationSettings,
+    /// Regular time grid each folder is resampled onto
+    #[serde(default)]
+    pub resample: ResampleSettings,
+    /// Frame rate and looping of animated outputs
+    #[serde(default)]
+    pub animation: AnimationTiming,
+    /// Second folder composited over the trails
+    #[serde(default)]
+    pub secondary: SecondarySettings,
+    /// Brightening of echo far from the radar
+    #[serde(default)]
+    pub range_correction: RangeCorrection,
+    /// Finding legends and logos that are in every frame, to mask them out
+    #[serde(default)]
+    pub static_mask: StaticMaskSettings,
+    /// Larger canvas each frame is placed on, with margins for titles and a legend
+    #[serde(default)]
+    pub canvas: CanvasSettings,
+}
+
+/// How the trails are drawn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderingSettings {
+    #[serde(deserialize_with = "deserialize_count")]
+    pub history_length: usize,
     pub background_color: String,
     pub current_color: String,
     pub history_color: String,
-    pub threads: i32,
-    pub limit: i32,
+    /// Colors of the newest and oldest history frames, such as `#ff0000:#0000ff`
+    pub history_gradient: String,
+    /// Color space the history gradient is mixed in
+    pub gradient_space: GradientSpace,
+    /// Color of each history frame, nearest first, in place of the history
+    /// color and gradient
+    pub history_colors: Vec<String>,
+    /// Smear history frames along their motion into streaks
+    pub motion_blur: bool,
+    /// Draw only every this many history frames; 0 and 1 draw them all
+    pub strobe: u32,
+    /// Opacity of the history frames the strobe leaves out, 0 to skip them
+    pub strobe_dim: f32,
+    /// Fraction of full resolution the history is drawn at
+    pub history_scale: f32,
+    /// Frames mark empty areas white
+    pub light_background: bool,
+    /// Where each pixel's opacity comes from: the alpha channel or the luminance
+    pub alpha_source: AlphaSource,
+    /// Image the trails are drawn over, such as a map
+    pub background_image: Option<PathBuf>,
+    /// Stretch the background image to the frames' size
+    pub fit_background_image: bool,
+    /// Leave the background transparent in the output
+    pub transparent_background: bool,
+    /// Which pixels count as echo, e.g. `alpha>0 & luma>0`
+    pub echo_rule: String,
+    /// History drawn under the first frames from before the sequence
+    pub preroll: Preroll,
+    /// Filter frames are resized with
+    pub resize_filter: ResizeFilter,
+    /// What is done with frames of another size than the first of their folder
+    pub size_mismatch: SizeMismatch,
+    /// Fade the history as earlier versions did
+    pub legacy_fade: bool,
+    /// Opacity of each history frame, nearest first, in place of the fade
+    pub history_weights: Vec<f32>,
+    /// How the fade is bent: linear, ease-in, ease-out, exponential or a gamma
+    pub fade_curve: String,
+    /// Opacity out of 255 of the nearest history frame
+    pub history_opacity: u8,
+    /// Whether each frame redraws its history or fades a buffer of it
+    pub trail_mode: TrailMode,
+    /// Opacity the decay buffer keeps each frame; 0 derives it from the history length
+    pub decay: f32,
+    /// Whether the current frame keeps its intensity or is drawn flat
+    pub current_style: CurrentStyle,
+    /// Whether the history is tinted or keeps the frames' own colors
+    pub history_style: HistoryStyle,
+}
+
+impl Default for RenderingSettings {
+    fn default() -> Self {
+        let defaults = ProcessingSettings::default();
+        RenderingSettings {
+            history_length: defaults.history_length,
+            background_color: defaults.background_color,
+            current_color: defaults.current_color,
+            history_color: defaults.history_color,
+            history_gradient: defaults.history_gradient,
+            gradient_space: defaults.gradient_space,
+            history_colors: defaults.history_colors,
+            motion_blur: defaults.motion_blur,
+            strobe: defaults.strobe,
+            strobe_dim: defaults.strobe_dim,
+            history_scale: defaults.history_scale,
+            light_background: defaults.light_background,
+            alpha_source: defaults.alpha_source,
+            background_image: defaults.background_image,
+            fit_background_image: defaults.fit_background_image,
+            transparent_background: defaults.transparent_background,
+            echo_rule: defaults.echo_rule,
+            preroll: defaults.preroll,
+            resize_filter: defaults.resize_filter,
+            size_mismatch: defaults.size_mismatch,
+            legacy_fade: defaults.legacy_fade,
+            history_weights: defaults.history_weights,
+            fade_curve: defaults.fade_curve,
+            history_opacity: defaults.history_opacity,
+            trail_mode: defaults.trail_mode,
+            decay: defaults.decay,
+            current_style: defaults.current_style,
+            history_style: defaults.history_style,
+        }
+    }
+}
+
+/// Where the rendered frames go and what happens to earlier output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputSettings {
+    /// Directory outputs are written under instead of next to each folder
+    pub default_root: Option<PathBuf>,
+    /// Output directory name, with `{folder}`, `{parent}` and `{history}` filled in
+    pub name_template: String,
+    /// Reprocess folders even when their output already matches the settings
+    pub force: bool,
+    /// Render only the frames added since the run already in the output directory
+    pub append: bool,
+    /// Keep the up-to-date trails of an interrupted run in the output directory
+    pub resume: bool,
+    pub on_collision: CollisionPolicy,
+    /// History and current layers written beside or instead of each trail
+    pub layers: LayerOutput,
+    /// Chart the echo coverage of every frame in the output folder
+    pub coverage_plot: bool,
+    /// Write PNG trails as indexed-color images
+    pub png_palette: bool,
+    /// Write trails as `z/x/y` tiles of the performance tile size
+    pub tile_output: bool,
+    /// Copy of the newest trail, relative to the output directory unless absolute
+    pub latest_image: Option<PathBuf>,
+    /// Describe the latest image in a JSON file beside it
+    pub latest_json: bool,
+    /// Name trails by their place in the sequence after this prefix, with an index map beside them
+    pub renumber: Option<String>,
+    /// Digits the renumbered index is padded to; 0 fits the frame count
+    pub renumber_width: u32,
+    /// JSON or JSON Lines index of the frames each trail was drawn from,
+    /// relative to the output directory unless absolute
+    pub emit_index: Option<PathBuf>,
+    /// Animated GIF of the trails, relative to the output directory unless absolute
+    pub animate_gif: Option<PathBuf>,
+    /// Colors in the animated GIF's palette, from 2 to 256
+    pub gif_colors: usize,
+    /// How the animated GIF makes up colors its palette lacks
+    pub gif_dither: GifDither,
+    /// H.264 MP4 of the trails encoded by ffmpeg, relative to the output directory unless absolute
+    pub mp4: Option<PathBuf>,
+    /// H.264 quality of the MP4, from 0, lossless, to 51
+    pub mp4_crf: u32,
+    /// Also save the trail images when writing an MP4
+    pub keep_frames: bool,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        OutputSettings {
+            default_root: None,
+            name_template: default_output_name_template(),
+            force: false,
+            append: false,
+            resume: false,
+            on_collision: CollisionPolicy::default(),
+            layers: LayerOutput::default(),
+            coverage_plot: false,
+            png_palette: false,
+            tile_output: false,
+            latest_image: None,
+            latest_json: false,
+            renumber: None,
+            renumber_width: 0,
+            emit_index: None,
+            animate_gif: None,
+            gif_colors: processing::default_gif_colors(),
+            gif_dither: GifDither::default(),
+            mp4: None,
+            mp4_crf: processing::default_mp4_crf(),
+            keep_frames: false,
+        }
+    }
+}
+
+/// How much of the machine a run may use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PerformanceSettings {
+    /// Worker threads; 0 uses one per core
+    #[serde(deserialize_with = "deserialize_count")]
+    pub threads: usize,
+    /// Frames read or written at once; 0 uses one per core, up to 8
+    #[serde(deserialize_with = "deserialize_count")]
+    pub io_threads: usize,
+    /// Folders of the queue rendered at once, sharing the worker threads
+    #[serde(default = "default_folder_parallelism", deserialize_with = "deserialize_count")]
+    pub folder_parallelism: usize,
+    /// Megabytes of decoded frames kept to draw again as history; 0 keeps as many as the workers need
+    pub cache_mb: u32,
+    /// Square tiles frames are composited in, in pixels; 0 composites them whole
+    pub tile_size: u32,
+}
+
+impl Default for PerformanceSettings {
+    fn default() -> Self {
+        PerformanceSettings {
+            threads: 0,
+            io_threads: 0,
+            folder_parallelism: default_folder_parallelism(),
+            cache_mb: 0,
+            tile_size: 0,
+        }
+    }
+}
+
+/// Fields the flat layout kept at the top level, as (group, old name, name in the group)
+const GROUPED_FIELDS: &[(&str, &str, &str)] = &[
+    ("rendering", "history_length", "history_length"),
+    ("rendering", "background_color", "background_color"),
+    ("rendering", "current_color", "current_color"),
+    ("rendering", "history_color", "history_color"),
+    ("output", "default_output_root", "default_root"),
+    ("output", "output_name_template", "name_template"),
+    ("output", "force", "force"),
+    ("output", "on_collision", "on_collision"),
+    ("performance", "threads", "threads"),
+];
+
+/// Move the fields of the flat layout, used before schema version 2, into their groups.
+/// Settings already in the grouped layout are left as they are.
+fn migrate_flat_settings(value: &mut serde_json::Value) {
+    let Some(fields) = value.as_object_mut() else { return };
+    let mut flat = false;
+    for (group, old, new) in GROUPED_FIELDS {
+        let Some(field) = fields.remove(*old) else { continue };
+        flat = true;
+        let group = fields.entry(*group).or_insert_with(|| serde_json::Value::Object(Default::default()));
+        if let Some(group) = group.as_object_mut() {
+            group.entry(*new).or_insert(field);
+        }
+    }
+    // The flat layout kept the limit as a signed number, and a negative one
+    // limited nothing, like 0
+    if flat && fields.get("limit").and_then(serde_json::Value::as_i64).is_some_and(|limit| limit < 0) {
+        fields.insert("limit".to_string(), 0.into());
+    }
+}
+
+/// Parse settings in either layout
+fn parse_settings(format: ConfigFormat, content: &str) -> Result<Settings, SettingsError> {
+    let mut value: serde_json::Value = format.parse(content)?;
+    migrate_flat_settings(&mut value);
+    settings_from_value(value)
+}
+
+/// Read settings in the grouped layout, naming the field at fault when one
+/// holds a value of the wrong type or out of range for it
+fn settings_from_value(value: serde_json::Value) -> Result<Settings, SettingsError> {
+    serde_json::from_value(value.clone()).map_err(|e| {
+        let mut error = json_error(e);
+        let defaults = serde_json::to_value(Settings::default()).expect("settings serialize");
+        let at_fault = settings_fields(&defaults, &value).into_iter().find(|(pointer, field)| {
+            let mut alone = defaults.clone();
+            match alone.pointer_mut(pointer) {
+                Some(slot) => *slot = field.clone(),
+                None => return false,
+            }
+            serde_json::from_value::<Settings>(alone).is_err()
+        });
+        if let (SettingsError::Parse { message, .. }, Some((pointer, _))) = (&mut error, at_fault) {
+            *message = format!("{}: {}", field_name(&pointer), message);
+        }
+        error
+    })
+}
+
+/// The fields of `loaded` that `defaults` has too, by JSON pointer; a
+/// group's fields are listed one by one
+fn settings_fields(defaults: &serde_json::Value, loaded: &serde_json::Value) -> Vec<(String, serde_json::Value)> {
+    let mut fields = Vec::new();
+    for (key, value) in loaded.as_object().into_iter().flatten() {
+        match (defaults.get(key), value) {
+            (Some(serde_json::Value::Object(_)), serde_json::Value::Object(group)) => {
+                fields.extend(group.iter().map(|(name, value)| (format!("/{}/{}", key, name), value.clone())));
+            }
+            (Some(_), _) => fields.push((format!("/{}", key), value.clone())),
+            (None, _) => {}
+        }
+    }
+    fields
+}
+
+/// The dotted name of the field at a JSON `pointer`, like `performance.threads`
+fn field_name(pointer: &str) -> String {
+    pointer.trim_start_matches('/').replace('/', ".")
+}
+
+/// File formats settings can be stored in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    /// Only readable and writable when built with the `toml` feature
+    Toml,
+}
+
+impl std::fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "JSON"),
+            ConfigFormat::Toml => write!(f, "TOML"),
+        }
+    }
+}
+
+impl ConfigFormat {
+    /// The format a path's extension names, if any
+    pub fn from_path(path: &Path) -> Option<ConfigFormat> {
+        let extension = path.extension()?.to_str()?;
+        [ConfigFormat::Json, ConfigFormat::Toml].into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+        }
+    }
+
+    /// Parse a whole file, reporting the line and column of a failure
+    fn parse<T: DeserializeOwned>(self, content: &str) -> Result<T, SettingsError> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(content).map_err(json_error),
+            #[cfg(feature = "toml")]
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| toml_error(&e, content)),
+            #[cfg(not(feature = "toml"))]
+            ConfigFormat::Toml => Err(SettingsError::Parse {
+                format: self,
+                location: None,
+                message: "this build does not include TOML support".to_string(),
+            }),
+        }
+    }
+
+    fn serialize<T: Serialize>(self, value: &T) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::to_string_pretty(value)?),
+            #[cfg(feature = "toml")]
+            ConfigFormat::Toml => Ok(toml::to_string_pretty(value)?),
+            #[cfg(not(feature = "toml"))]
+            ConfigFormat::Toml => Err("this build does not include TOML support".into()),
+        }
+    }
 }
 
+fn json_error(e: serde_json::Error) -> SettingsError {
+    let location = (e.line() > 0).then(|| (e.line(), e.column()));
+    // serde_json appends the location to its message; it is reported separately
+    let message = e.to_string();
+    let suffix = format!(" at line {} column {}", e.line(), e.column());
+    let message = message.strip_suffix(&suffix).unwrap_or(&message).to_string();
+    SettingsError::Parse { format: ConfigFormat::Json, location, message }
+}
+
+#[cfg(feature = "toml")]
+fn toml_error(e: &toml::de::Error, content: &str) -> SettingsError {
+    let location = e.span().map(|span| {
+        let before = &content[..span.start.min(content.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        (line, column)
+    });
+    SettingsError::Parse {
+        format: ConfigFormat::Toml,
+        location,
+        message: e.message().to_string(),
+    }
+}
+
+fn default_watch_settle_secs() -> i32 {
+    60
+}
+
+fn default_settings_backups() -> usize {
+    1
+}
+
+/// Most backups of the settings file that may be kept
+pub const MAX_SETTINGS_BACKUPS: usize = 10;
+
+/// Read a count stored as any integer, rejecting negative values instead of wrapping them
+fn deserialize_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let value = i64::deserialize(deserializer)?;
+    usize::try_from(value)
+        .map_err(|_| D::Error::custom(format!("{} is negative, expected a whole number of at least 0", value)))
+}
+
+/// Read a frame limit as null or a count, taking the legacy 0 to mean no limit
+fn deserialize_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    match Option::<i64>::deserialize(deserializer)? {
+        None | Some(0) => Ok(None),
+        Some(value) => usize::try_from(value)
+            .map(Some)
+            .map_err(|_| D::Error::custom(format!("{} is negative, expected 0 (no limit) or a positive whole number", value))),
+    }
+}
+
+impl From<Settings> for ProcessingSettings {
+    fn from(settings: Settings) -> Self {
+        ProcessingSettings {
+            history_length: settings.rendering.history_length,
+            background_color: settings.rendering.background_color,
+            current_color: settings.rendering.current_color,
+            history_color: settings.rendering.history_color,
+            history_gradient: settings.rendering.history_gradient,
+            gradient_space: settings.rendering.gradient_space,
+            history_colors: settings.rendering.history_colors,
+            motion_blur: settings.rendering.motion_blur,
+            strobe: settings.rendering.strobe,
+            strobe_dim: settings.rendering.strobe_dim,
+            history_scale: settings.rendering.history_scale,
+            light_background: settings.rendering.light_background,
+            alpha_source: settings.rendering.alpha_source,
+            background_image: settings.rendering.background_image,
+            fit_background_image: settings.rendering.fit_background_image,
+            transparent_background: settings.rendering.transparent_background,
+            echo_rule: settings.rendering.echo_rule,
+            preroll: settings.rendering.preroll,
+            resize_filter: settings.rendering.resize_filter,
+            size_mismatch: settings.rendering.size_mismatch,
+            legacy_fade: settings.rendering.legacy_fade,
+            history_weights: settings.rendering.history_weights,
+            fade_curve: settings.rendering.fade_curve,
+            history_opacity: settings.rendering.history_opacity,
+            trail_mode: settings.rendering.trail_mode,
+            decay: settings.rendering.decay,
+            current_style: settings.rendering.current_style,
+            history_style: settings.rendering.history_style,
+            threads: settings.performance.threads,
+            io_threads: settings.performance.io_threads,
+            folder_parallelism: settings.performance.folder_parallelism,
+            cache_mb: settings.performance.cache_mb,
+            tile_size: settings.performance.tile_size,
+            limit: settings.limit,
+            offset: settings.offset,
+            stride: settings.stride,
+            preflight: settings.preflight,
+            repeated_frames: settings.repeated_frames,
+            on_error: settings.on_error,
+            ignore_manifests: settings.ignore_manifests,
+            force: settings.output.force,
+            append: settings.output.append,
+            resume: settings.output.resume,
+            on_collision: settings.output.on_collision,
+            output_root: settings.output.default_root,
+            output_name_template: settings.output.name_template,
+            layers: settings.output.layers,
+            coverage_plot: settings.output.coverage_plot,
+            png_palette: settings.output.png_palette,
+            tile_output: settings.output.tile_output,
+            latest_image: settings.output.latest_image,
+            latest_json: settings.output.latest_json,
+            renumber: settings.output.renumber,
+            renumber_width: settings.output.renumber_width,
+            emit_index: settings.output.emit_index,
+            animate_gif: settings.output.animate_gif,
+            gif_colors: settings.output.gif_colors,
+            gif_dither: settings.output.gif_dither,
+            mp4: settings.output.mp4,
+            mp4_crf: settings.output.mp4_crf,
+            keep_frames: settings.output.keep_frames,
+            odim: settings.odim,
+            motion: settings.motion,
+            tracking: settings.tracking,
+            nowcast: settings.nowcast,
+            interpolation: settings.interpolation,
+            resample: settings.resample,
+            animation: settings.animation,
+            secondary: settings.secondary,
+            range_correction: settings.range_correction,
+            static_mask: settings.static_mask,
+            canvas: settings.canvas,
+            shader: None,
+            frame_overrides: Default::default(),
+            auto_mask: None,
+            background: None,
+            frame_size: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Take the fields a queue document carries, keeping the rest
+    pub fn set_processing(&mut self, processing: &ProcessingSettings) {
+        self.rendering.history_length = processing.history_length;
+        self.rendering.background_color = processing.background_color.clone();
+        self.rendering.current_color = processing.current_color.clone();
+        self.rendering.history_color = processing.history_color.clone();
+        self.rendering.history_gradient = processing.history_gradient.clone();
+        self.rendering.gradient_space = processing.gradient_space;
+        self.rendering.history_colors = processing.history_colors.clone();
+        self.rendering.motion_blur = processing.motion_blur;
+        self.rendering.strobe = processing.strobe;
+        self.rendering.strobe_dim = processing.strobe_dim;
+        self.rendering.history_scale = processing.history_scale;
+        self.rendering.light_background = processing.light_background;
+        self.rendering.alpha_source = processing.alpha_source;
+        self.rendering.background_image = processing.background_image.clone();
+        self.rendering.fit_background_image = processing.fit_background_image;
+        self.rendering.transparent_background = processing.transparent_background;
+        self.rendering.echo_rule = processing.echo_rule.clone();
+        self.rendering.preroll = processing.preroll;
+        self.rendering.resize_filter = processing.resize_filter;
+        self.rendering.size_mismatch = processing.size_mismatch;
+        self.rendering.legacy_fade = processing.legacy_fade;
+        self.rendering.history_weights = processing.history_weights.clone();
+        self.rendering.fade_curve = processing.fade_curve.clone();
+        self.rendering.history_opacity = processing.history_opacity;
+        self.rendering.trail_mode = processing.trail_mode;
+        self.rendering.decay = processing.decay;
+        self.rendering.current_style = processing.current_style;
+        self.rendering.history_style = processing.history_style;
+        self.performance.threads = processing.threads;
+        self.performance.io_threads = processing.io_threads;
+        self.performance.folder_parallelism = processing.folder_parallelism;
+        self.performance.cache_mb = processing.cache_mb;
+        self.performance.tile_size = processing.tile_size;
+        self.limit = processing.limit;
+        self.offset = processing.offset;
+        self.stride = processing.stride;
+        self.preflight = processing.preflight;
+        self.repeated_frames = processing.repeated_frames;
+        self.on_error = processing.on_error;
+        self.ignore_manifests = processing.ignore_manifests;
+        self.output.force = processing.force;
+        self.output.append = processing.append;
+        self.output.resume = processing.resume;
+        self.output.on_collision = processing.on_collision;
+        self.output.default_root = processing.output_root.clone();
+        self.output.name_template = processing.output_name_template.clone();
+        self.output.layers = processing.layers;
+        self.output.coverage_plot = processing.coverage_plot;
+        self.output.png_palette = processing.png_palette;
+        self.output.tile_output = processing.tile_output;
+        self.output.latest_image = processing.latest_image.clone();
+        self.output.latest_json = processing.latest_json;
+        self.output.renumber = processing.renumber.clone();
+        self.output.renumber_width = processing.renumber_width;
+        self.output.emit_index = processing.emit_index.clone();
+        self.output.animate_gif = processing.animate_gif.clone();
+        self.output.gif_colors = processing.gif_colors;
+        self.output.gif_dither = processing.gif_dither;
+        self.output.mp4 = processing.mp4.clone();
+        self.output.mp4_crf = processing.mp4_crf;
+        self.output.keep_frames = processing.keep_frames;
+        self.odim = processing.odim.clone();
+        self.motion = processing.motion.clone();
+        self.tracking = processing.tracking.clone();
+        self.nowcast = processing.nowcast.clone();
+        self.interpolation = processing.interpolation.clone();
+        self.resample = processing.resample.clone();
+        self.animation = processing.animation.clone();
+        self.secondary = processing.secondary.clone();
+        self.range_correction = processing.range_correction.clone();
+        self.static_mask = processing.static_mask.clone();
+        self.canvas = processing.canvas.clone();
+    }
+
+    /// These settings with the fields that belong to this machine taken from `local`
+    pub fn with_local_fields(self, local: &Settings) -> Settings {
+        Settings {
+            import_path_mappings: local.import_path_mappings.clone(),
+            watch_folder: local.watch_folder.clone(),
+            output: OutputSettings {
+                default_root: local.output.default_root.clone(),
+                ..self.output
+            },
+            ..self
+        }
+    }
+
+    /// Names of the fields that differ from `earlier`; fields in a group are named by their path
+    pub fn changed_fields(&self, earlier: &Settings) -> Vec<String> {
+        let (Ok(now), Ok(before)) = (serde_json::to_value(self), serde_json::to_value(earlier)) else {
+            return Vec::new();
+        };
+        let mut changed = Vec::new();
+        for (name, value) in now.as_object().into_iter().flatten() {
+            match (value, before.get(name)) {
+                (serde_json::Value::Object(group), Some(serde_json::Value::Object(earlier_group))) => {
+                    changed.extend(group.iter()
+                        .filter(|(field, value)| earlier_group.get(*field) != Some(value))
+                        .map(|(field, _)| format!("{}.{}", name, field)));
+                }
+                (value, earlier_value) if earlier_value != Some(value) => changed.push(name.clone()),
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    /// Check every field with the same rules as the processing settings, listing all
+    /// problems. Fields in a group are named by their path, e.g. `rendering.history_length`.
+    pub fn validate(&self) -> Result<(), Vec<InvalidField>> {
+        let rendering = &self.rendering;
+        let mut invalid: Vec<InvalidField> = [
+            processing::check_history_length(rendering.history_length)
+                .map(|field| InvalidField { field: "rendering.history_length".to_string(), ..field }),
+            processing::check_color("rendering.background_color", &rendering.background_color),
+            processing::check_color("rendering.current_color", &rendering.current_color),
+            processing::check_color("rendering.history_color", &rendering.history_color),
+            processing::check_history_gradient("rendering.history_gradient", &rendering.history_gradient),
+            processing::check_history_colors("rendering.history_colors", &rendering.history_colors),
+            processing::check_opacity("rendering.strobe_dim", rendering.strobe_dim),
+            processing::check_history_scale("rendering.history_scale", rendering.history_scale),
+            processing::check_echo_rule("rendering.echo_rule", &rendering.echo_rule),
+            processing::check_history_weights("rendering.history_weights", &rendering.history_weights, rendering.history_length),
+            processing::check_fade_curve("rendering.fade_curve", &rendering.fade_curve),
+            processing::check_history_opacity("rendering.history_opacity", rendering.history_opacity),
+            processing::check_decay("rendering.decay", rendering.decay),
+            processing::check_tile_size("performance.tile_size", self.performance.tile_size),
+            processing::check_folder_parallelism("performance.folder_parallelism", self.performance.folder_parallelism),
+            processing::check_stride("stride", self.stride),
+            processing::check_output_root("output.default_root", self.output.default_root.as_deref()),
+            processing::check_latest_image("output.latest_image", self.output.latest_image.as_deref()),
+            processing::check_output_name_template("output.name_template", &self.output.name_template),
+            processing::check_renumber_prefix("output.renumber", self.output.renumber.as_deref()),
+            processing::check_renumber_width("output.renumber_width", self.output.renumber_width),
+            processing::check_emit_index("output.emit_index", self.output.emit_index.as_deref()),
+            processing::check_animate_gif("output.animate_gif", self.output.animate_gif.as_deref()),
+            processing::check_gif_colors("output.gif_colors", self.output.gif_colors),
+            processing::check_mp4("output.mp4", self.output.mp4.as_deref()),
+            processing::check_mp4_crf("output.mp4_crf", self.output.mp4_crf),
+            processing::check_odim_dataset("odim.dataset", self.odim.dataset),
+            processing::check_odim_quantity("odim.quantity", &self.odim.quantity),
+            processing::check_grid_spacing("motion.grid_spacing", self.motion.grid_spacing),
+            processing::check_vector_scale("motion.vector_scale", self.motion.vector_scale),
+            processing::check_coherence("motion.min_coherence", self.motion.min_coherence),
+            processing::check_color("motion.color", &self.motion.color),
+            processing::check_pixel_size("motion.pixel_size_km", self.motion.pixel_size_km),
+            processing::check_tracking_size("tracking.min_area", self.tracking.min_area),
+            processing::check_tracking_size("tracking.max_displacement", self.tracking.max_displacement),
+            processing::check_color("tracking.color", &self.tracking.color),
+            processing::check_nowcast_frames("nowcast.frames", self.nowcast.frames),
+            processing::check_interpolated_frames("interpolation.frames", self.interpolation.frames),
+            processing::check_resample_interval("resample.interval_minutes", self.resample.interval_minutes),
+            processing::check_fps("animation.fps", self.animation.fps),
+            processing::check_max_frame_ms("animation.max_frame_ms", self.animation.max_frame_ms),
+            processing::check_color("secondary.color", &self.secondary.color),
+            processing::check_color("secondary.history_color", &self.secondary.history_color),
+            processing::check_opacity("secondary.opacity", self.secondary.opacity),
+            processing::check_range_gain("range_correction.gain_per_100km", self.range_correction.gain_per_100km),
+            processing::check_pixel_size("range_correction.pixel_size_km", Some(self.range_correction.pixel_size_km)),
+            processing::check_max_range_gain("range_correction.max_gain", self.range_correction.max_gain),
+            processing::check_static_mask_samples("static_mask.samples", self.static_mask.samples),
+            processing::check_static_mask_dilate("static_mask.dilate", self.static_mask.dilate),
+            processing::check_canvas_size("canvas.size", self.canvas.size),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if self.settings_backups > MAX_SETTINGS_BACKUPS {
+            invalid.push(InvalidField::new(
+                "settings_backups",
+                self.settings_backups,
+                &format!("at most {}", MAX_SETTINGS_BACKUPS),
+            ));
+        }
+        if !(10..=3600).contains(&self.watch_settle_secs) {
+            invalid.push(InvalidField::new("watch_settle_secs", self.watch_settle_secs, "between 10 and 3600 seconds"));
+        }
+        if invalid.is_empty() { Ok(()) } else { Err(invalid) }
+    }
+}
+
+/// Why the saved settings could not be loaded as they are
+#[derive(Debug)]
+pub enum SettingsError {
+    /// No configuration directory is known for this platform
+    NoConfigDir,
+    Io(std::io::Error),
+    /// The file is not valid settings in its format; `location` is the line and column
+    Parse {
+        format: ConfigFormat,
+        location: Option<(usize, usize)>,
+        message: String,
+    },
+    /// The file parsed, but these fields hold values that cannot be used
+    Invalid(Vec<InvalidField>),
+    /// An exported settings file from a newer version of the app
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// The settings file could not be read, so its most recent readable backup was loaded
+    RestoredFromBackup {
+        backup: PathBuf,
+        problem: Box<SettingsError>,
+    },
+    /// Portable mode is on but the executable's directory cannot be written to
+    PortableNotWritable {
+        dir: PathBuf,
+        /// The standard config directory used without portable mode
+        fallback: Option<PathBuf>,
+        error: std::io::Error,
+    },
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::NoConfigDir => write!(f, "could not determine config directory"),
+            SettingsError::Io(e) => write!(f, "cannot read settings: {}", e),
+            SettingsError::Parse { format, location: Some((line, column)), message } => {
+                write!(f, "settings file is not valid {} at line {}, column {}: {}", format, line, column, message)
+            }
+            SettingsError::Parse { format, location: None, message } => {
+                write!(f, "settings file is not valid {}: {}", format, message)
+            }
+            SettingsError::Invalid(fields) => {
+                write!(f, "{} invalid setting(s):", fields.len())?;
+                for field in fields {
+                    write!(f, "\n  {}", field)?;
+                }
+                Ok(())
+            }
+            SettingsError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "settings file uses schema version {}, but this version of the app reads up to {}",
+                found, supported
+            ),
+            SettingsError::RestoredFromBackup { backup, problem } => {
+                write!(f, "{}\n\nThe backup {} was loaded instead.", problem, backup.display())
+            }
+            SettingsError::PortableNotWritable { dir, fallback, error } => {
+                write!(f, "cannot save settings in the portable folder {}: {}. Make it writable", dir.display(), error)?;
+                if let Some(fallback) = fallback {
+                    write!(
+                        f,
+                        ", or remove {} and the settings file there to keep settings in {} instead",
+                        PORTABLE_FLAG_FILE,
+                        fallback.display()
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
 impl Default for Settings {
     fn default() -> Self {
         Settings {
-            history_length: 5,
-            background_color: "#000000".to_string(),
-            current_color: "#00ff00".to_string(),
-            history_color: "#ff7f00".to_string(),
-            threads: 0,
-            limit: 0,
+            limit: None,
+            offset: 0,
+            stride: default_stride(),
+            preflight: false,
+            repeated_frames: RepeatPolicy::default(),
+            on_error: ErrorPolicy::default(),
+            ignore_manifests: false,
+            include_hidden: false,
+            sort_order: SortOrder::default(),
+            import_path_mappings: Vec::new(),
+            watch_folder: None,
+            watch_settle_secs: default_watch_settle_secs(),
+            watch_auto_start: false,
+            reuse_previous_settings: false,
+            config_format: ConfigFormat::default(),
+            reload_settings_file: false,
+            settings_backups: default_settings_backups(),
+            active_preset: None,
+            rendering: RenderingSettings::default(),
+            output: OutputSettings::default(),
+            performance: PerformanceSettings::default(),
+            odim: OdimSelection::default(),
+            motion: MotionSettings::default(),
+            tracking: TrackingSettings::default(),
+            nowcast: NowcastSettings::default(),
+            interpolation: InterpolationSettings::default(),
+            resample: ResampleSettings::default(),
+            animation: AnimationTiming::default(),
+            secondary: SecondarySettings::default(),
+            range_correction: RangeCorrection::default(),
+            static_mask: StaticMaskSettings::default(),
+            canvas: CanvasSettings::default(),
         }
     }
 }
 
-fn settings_path() -> Option<PathBuf> {
-    directories::ProjectDirs::from("com", "imsel", "radar_echo_trails")
-        .map(|dirs| dirs.config_dir().join("settings.json"))
+/// File next to the executable that turns on portable mode
+pub const PORTABLE_FLAG_FILE: &str = "portable.flag";
+
+/// Where settings and the app's other files are kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StorageMode {
+    /// The platform's config and data directories
+    Standard,
+    /// The directory holding the executable, for running from a shared drive
+    /// where the user profile cannot be written to
+    Portable(PathBuf),
+}
+
+static STORAGE_MODE: OnceLock<StorageMode> = OnceLock::new();
+
+impl StorageMode {
+    /// Portable when requested, or when the executable's directory holds a
+    /// `portable.flag` or a settings file
+    fn detect(force_portable: bool) -> StorageMode {
+        let Some(dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) else {
+            return StorageMode::Standard;
+        };
+        let marked = dir.join(PORTABLE_FLAG_FILE).is_file()
+            || [ConfigFormat::Json, ConfigFormat::Toml].into_iter()
+                .any(|format| settings_path_in(&dir, format).is_file());
+        if force_portable || marked { StorageMode::Portable(dir) } else { StorageMode::Standard }
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        match self {
+            StorageMode::Standard => directories::ProjectDirs::from("com", "imsel", "radar_echo_trails")
+                .map(|dirs| dirs.config_dir().to_path_buf()),
+            StorageMode::Portable(dir) => Some(dir.clone()),
+        }
+    }
+
+    fn data_dir(&self) -> Option<PathBuf> {
+        match self {
+            StorageMode::Standard => directories::ProjectDirs::from("com", "imsel", "radar_echo_trails")
+                .map(|dirs| dirs.data_dir().to_path_buf()),
+            StorageMode::Portable(dir) => Some(dir.join("data")),
+        }
+    }
+}
+
+/// Decide the storage mode for this run. Called once at startup; the first
+/// decision stands for the rest of the run.
+pub fn init_storage_mode(force_portable: bool) -> &'static StorageMode {
+    STORAGE_MODE.get_or_init(|| StorageMode::detect(force_portable))
+}
+
+pub fn storage_mode() -> &'static StorageMode {
+    init_storage_mode(false)
+}
+
+fn config_dir() -> Option<PathBuf> {
+    storage_mode().config_dir()
+}
+
+/// The settings file in the mode's config directory. If files in both formats
+/// exist, the one saved last wins.
+fn settings_path(mode: &StorageMode) -> Option<PathBuf> {
+    let dir = mode.config_dir()?;
+    let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    [ConfigFormat::Json, ConfigFormat::Toml].into_iter()
+        .map(|format| settings_path_in(&dir, format))
+        .filter_map(|path| modified(&path).map(|time| (time, path)))
+        .max_by_key(|(time, _)| *time)
+        .map(|(_, path)| path)
+        .or_else(|| Some(settings_path_in(&dir, ConfigFormat::default())))
+}
+
+/// Names the settings file may have in the config directory
+pub const SETTINGS_FILE_NAMES: &[&str] = &["settings.json", "settings.toml"];
+
+/// Directory holding the settings file, for watching it
+pub fn settings_dir() -> Option<PathBuf> {
+    config_dir()
+}
+
+fn settings_path_in(dir: &Path, format: ConfigFormat) -> PathBuf {
+    dir.join(format!("settings.{}", format.extension()))
+}
+
+/// Directory for data the app can regenerate, such as cached thumbnails
+pub fn data_dir() -> Option<PathBuf> {
+    storage_mode().data_dir()
+}
+
+/// Load the saved settings, rejecting the file if any field is invalid
+pub fn load_settings() -> Result<Settings, SettingsError> {
+    let path = settings_path(storage_mode()).ok_or(SettingsError::NoConfigDir)?;
+    read_settings_file(&path)
 }
 
-pub fn load_settings() -> Result<Settings, Box<dyn std::error::Error>> {
-    let path = settings_path().ok_or("Could not determine config directory")?;
-    let content = std::fs::read_to_string(path)?;
-    let settings: Settings = serde_json::from_str(&content)?;
+/// Read and validate a settings file, in the format its extension names (JSON otherwise)
+fn read_settings_file(path: &Path) -> Result<Settings, SettingsError> {
+    let content = std::fs::read_to_string(path).map_err(SettingsError::Io)?;
+    let format = ConfigFormat::from_path(path).unwrap_or_default();
+    // Left behind by an interrupted write; an empty TOML file would otherwise load as all defaults
+    if content.trim().is_empty() {
+        return Err(SettingsError::Parse { format, location: None, message: "the file is empty".to_string() });
+    }
+    let settings = parse_settings(format, &content)?;
+    settings.validate().map_err(SettingsError::Invalid)?;
     Ok(settings)
 }
 
+/// Load the saved settings, replacing each unusable field with its default.
+///
+/// A file that cannot be read or parsed at all is replaced by its most recent
+/// backup that loads. Returns the settings to use and, when anything was
+/// discarded or a backup was used, why. A missing settings file is not reported.
+pub fn load_or_default_with_report() -> (Settings, Option<SettingsError>) {
+    let error = match load_settings() {
+        Ok(settings) => return (settings, None),
+        Err(SettingsError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return (Settings::default(), None);
+        }
+        Err(e) => e,
+    };
+    if matches!(error, SettingsError::Io(_) | SettingsError::Parse { .. })
+        && let Some(path) = settings_path(storage_mode())
+    {
+        let backups = (1..=MAX_SETTINGS_BACKUPS).map(|n| backup_path(&path, n)).take_while(|backup| backup.exists());
+        for backup in backups {
+            if let Ok(settings) = read_settings_file(&backup) {
+                return (settings, Some(SettingsError::RestoredFromBackup { backup, problem: Box::new(error) }));
+            }
+        }
+    }
+    match error {
+        e @ (SettingsError::NoConfigDir
+            | SettingsError::Io(_)
+            | SettingsError::UnsupportedVersion { .. }
+            | SettingsError::RestoredFromBackup { .. }
+            | SettingsError::PortableNotWritable { .. }) => {
+            return (Settings::default(), Some(e));
+        }
+        // Salvage what we can below
+        SettingsError::Parse { .. } | SettingsError::Invalid(_) => {}
+    }
+    let Some(path) = settings_path(storage_mode()) else {
+        return (Settings::default(), Some(SettingsError::NoConfigDir));
+    };
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => return (Settings::default(), Some(SettingsError::Io(e))),
+    };
+    let format = ConfigFormat::from_path(&path).unwrap_or_default();
+    let mut loaded = match format.parse::<serde_json::Value>(&content) {
+        Ok(loaded @ serde_json::Value::Object(_)) => loaded,
+        Ok(_) => {
+            let message = "expected a table of settings".to_string();
+            return (Settings::default(), Some(SettingsError::Parse { format, location: None, message }));
+        }
+        Err(e) => return (Settings::default(), Some(e)),
+    };
+    migrate_flat_settings(&mut loaded);
+    
+    let defaults = serde_json::to_value(Settings::default()).expect("settings serialize");
+    let mut merged = defaults.clone();
+    let parse = |merged: &serde_json::Value| serde_json::from_value::<Settings>(merged.clone());
+    
+    // Take loaded fields one at a time so a value of the wrong type only loses that field
+    let mut discarded = Vec::new();
+    for (pointer, value) in settings_fields(&defaults, &loaded) {
+        let Some(slot) = merged.pointer_mut(&pointer) else { continue };
+        let previous = std::mem::replace(slot, value.clone());
+        if parse(&merged).is_err() {
+            if let Some(slot) = merged.pointer_mut(&pointer) {
+                *slot = previous;
+            }
+            discarded.push(InvalidField::new(&field_name(&pointer), value, "a value of the right type"));
+        }
+    }
+    
+    let mut settings = parse(&merged).unwrap_or_default();
+    if let Err(invalid) = settings.validate() {
+        for field in &invalid {
+            let pointer = format!("/{}", field.field.replace('.', "/"));
+            if let (Some(default), Some(slot)) = (defaults.pointer(&pointer), merged.pointer_mut(&pointer)) {
+                *slot = default.clone();
+            }
+        }
+        settings = parse(&merged).unwrap_or_default();
+        discarded.extend(invalid);
+    }
+    
+    let report = (!discarded.is_empty()).then_some(SettingsError::Invalid(discarded));
+    (settings, report)
+}
+
+/// The `n`th most recent backup of the file at `path`, e.g. `settings.json.1`
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", n));
+    path.with_file_name(name)
+}
+
+/// Copy the file at `path` to the first of `depth` backups, shifting the older
+/// ones along and removing any beyond `depth`
+fn rotate_backups(path: &Path, depth: usize) -> std::io::Result<()> {
+    let mut excess = depth + 1;
+    while backup_path(path, excess).exists() {
+        std::fs::remove_file(backup_path(path, excess))?;
+        excess += 1;
+    }
+    if depth == 0 || !path.exists() {
+        return Ok(());
+    }
+    for n in (1..depth).rev() {
+        let older = backup_path(path, n);
+        if older.exists() {
+            std::fs::rename(&older, backup_path(path, n + 1))?;
+        }
+    }
+    std::fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Replace the file at `path` without ever leaving it partly written: write a
+/// temporary file beside it, flush that to disk and rename it over `path`
+pub fn write_atomically(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&temp_path, path)
+}
+
+/// Save the settings in their preferred format, removing a file left in the other one.
+/// Invalid settings are refused so the file always loads as saved. The file is
+/// replaced atomically, and the version it replaces becomes the first backup.
 pub fn save_settings(settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
-    let path = settings_path().ok_or("Could not determine config directory")?;
+    settings.validate().map_err(SettingsError::Invalid)?;
+    let mode = storage_mode();
+    let dir = mode.config_dir().ok_or("Could not determine config directory")?;
+    let content = settings.config_format.serialize(settings)?;
     
-    // Create parent directory if it doesn't exist
+    let path = settings_path_in(&dir, settings.config_format);
+    // Rewriting identical settings would only push real history out of the backups
+    let unchanged = std::fs::read(&path).is_ok_and(|saved| saved == content.as_bytes());
+    let written = if unchanged {
+        Ok(())
+    } else {
+        // Create the directory if it doesn't exist
+        std::fs::create_dir_all(&dir)
+            .and_then(|()| rotate_backups(&path, settings.settings_backups))
+            .and_then(|()| write_atomically(&path, content.as_bytes()))
+    };
+    match written {
+        Ok(()) => {}
+        Err(error) if matches!(mode, StorageMode::Portable(_)) => {
+            let fallback = StorageMode::Standard.config_dir();
+            return Err(SettingsError::PortableNotWritable { dir, fallback, error }.into());
+        }
+        Err(error) => return Err(error.into()),
+    }
+    for format in [ConfigFormat::Json, ConfigFormat::Toml] {
+        if format != settings.config_format {
+            let _ = std::fs::remove_file(settings_path_in(&dir, format));
+        }
+    }
+    Ok(())
+}
+
+/// Presets shipped with the app; they cannot be overwritten or deleted
+const BUILTIN_PRESETS: &[&str] = &[
+    "Classic",
+    "Print Light",
+    "Light Background Products",
+    "Colorblind Safe",
+    "Colorblind Blue/Vermillion",
+    "Colorblind Teal/Magenta",
+];
+
+fn builtin_preset(name: &str) -> Option<Settings> {
+    let colors = |background: &str, current: &str, history: &str| Settings {
+        rendering: RenderingSettings {
+            background_color: background.to_string(),
+            current_color: current.to_string(),
+            history_color: history.to_string(),
+            ..RenderingSettings::default()
+        },
+        ..Settings::default()
+    };
+    let name = BUILTIN_PRESETS.iter().find(|builtin| builtin.eq_ignore_ascii_case(name))?;
+    match *name {
+        "Classic" => Some(Settings::default()),
+        // Dark trails on white paper
+        "Print Light" => Some(colors("#ffffff", "#1a1a1a", "#d62728")),
+        // Web products that mark empty areas white, keyed out onto a white canvas
+        "Light Background Products" => {
+            let mut settings = colors("#ffffff", "#1a1a1a", "#d62728");
+            settings.rendering.light_background = true;
+            Some(settings)
+        }
+        // Okabe-Ito sky blue and orange stay distinct under every common color vision deficiency
+        "Colorblind Safe" => Some(colors("#000000", "#56b4e9", "#e69f00")),
+        // Okabe-Ito blue and vermillion, for light backgrounds and print
+        "Colorblind Blue/Vermillion" => Some(colors("#ffffff", "#0072b2", "#d55e00")),
+        // Paul Tol's vibrant cyan and magenta; the Okabe-Ito bluish green and
+        // reddish purple fall below the contrast threshold under deuteranopia
+        "Colorblind Teal/Magenta" => Some(colors("#000000", "#33bbee", "#ee3377")),
+        _ => None,
+    }
+}
+
+pub fn is_builtin_preset(name: &str) -> bool {
+    builtin_preset(name).is_some()
+}
+
+fn presets_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("presets"))
+}
+
+/// File holding a user preset, refusing names that would escape the presets directory
+fn preset_path(name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', ':']) {
+        return Err(format!("\"{}\" is not a valid preset name", name).into());
+    }
+    let dir = presets_dir().ok_or("Could not determine config directory")?;
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+/// Save `settings` as a named preset, replacing a user preset of the same name.
+///
+/// The watched folder and import path mappings belong to the machine, not the
+/// look of the output, so they are left out.
+pub fn save_preset(name: &str, settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    if is_builtin_preset(name) {
+        return Err(format!("\"{}\" is a built-in preset and cannot be overwritten", name.trim()).into());
+    }
+    let path = preset_path(name)?;
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
+    let preset = Settings {
+        watch_folder: None,
+        import_path_mappings: Vec::new(),
+        active_preset: None,
+        ..settings.clone()
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&preset)?)?;
+    Ok(())
+}
+
+/// Load a built-in or user preset by name
+pub fn load_preset(name: &str) -> Result<Settings, SettingsError> {
+    if let Some(settings) = builtin_preset(name) {
+        return Ok(settings);
+    }
+    let path = preset_path(name).map_err(|_| {
+        SettingsError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, format!("no preset named \"{}\"", name)))
+    })?;
+    read_settings_file(&path)
+}
+
+/// Names of all presets, built-in ones first, then user presets alphabetically
+pub fn list_presets() -> Vec<String> {
+    let mut user: Vec<String> = presets_dir()
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .filter(|name| !is_builtin_preset(name))
+        .collect();
+    user.sort_by_key(|name| name.to_lowercase());
+    BUILTIN_PRESETS.iter().map(|name| name.to_string()).chain(user).collect()
+}
+
+/// Delete a user preset; built-in presets are read-only
+pub fn delete_preset(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if is_builtin_preset(name) {
+        return Err(format!("\"{}\" is a built-in preset and cannot be deleted", name.trim()).into());
+    }
+    std::fs::remove_file(preset_path(name)?)?;
+    Ok(())
+}
+
+/// Version of the exported settings file format. Version 2 moved fields into
+/// the `rendering`, `output` and `performance` groups.
+pub const SETTINGS_SCHEMA_VERSION: u32 = 2;
+
+/// Settings exported for sharing, tagged with what produced them
+#[derive(Serialize, Deserialize)]
+struct SettingsDocument<S> {
+    schema_version: u32,
+    app_version: String,
+    settings: S,
+}
+
+/// How imported settings combine with the ones in use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Take the imported settings but keep this machine's watched folder and path mappings
+    Merge,
+    /// Take the imported settings as they are
+    Replace,
+}
+
+impl ImportMode {
+    pub fn apply(self, current: &Settings, imported: Settings) -> Settings {
+        match self {
+            ImportMode::Merge => imported.with_local_fields(current),
+            ImportMode::Replace => imported,
+        }
+    }
+}
+
+/// Write `settings` to `path` with the schema and app version, for another machine to import.
+///
+/// A `.toml` path is written as TOML, anything else as JSON.
+pub fn export_settings(settings: &Settings, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let document = SettingsDocument {
+        schema_version: SETTINGS_SCHEMA_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        settings,
+    };
+    let format = ConfigFormat::from_path(path).unwrap_or_default();
+    std::fs::write(path, format.serialize(&document)?)?;
+    Ok(())
+}
+
+/// Read settings exported by [`export_settings`], upgrading older schemas and validating every field.
+///
+/// A `.toml` path is read as TOML, anything else as JSON. A bare settings
+/// file copied from another machine's config directory is accepted too, as
+/// schema version 0.
+pub fn import_settings(path: &Path) -> Result<Settings, SettingsError> {
+    let content = std::fs::read_to_string(path).map_err(SettingsError::Io)?;
+    let format = ConfigFormat::from_path(path).unwrap_or_default();
+    let value: serde_json::Value = format.parse(&content)?;
+    let (version, settings) = match value.get("schema_version") {
+        Some(_) => {
+            let document: SettingsDocument<serde_json::Value> = serde_json::from_value(value).map_err(json_error)?;
+            (document.schema_version, document.settings)
+        }
+        None => (0, value),
+    };
+    if version > SETTINGS_SCHEMA_VERSION {
+        return Err(SettingsError::UnsupportedVersion { found: version, supported: SETTINGS_SCHEMA_VERSION });
+    }
+    // Versions 0 and 1 used the flat layout; missing fields take their defaults
+    let mut settings = settings;
+    if version < 2 {
+        migrate_flat_settings(&mut settings);
+    }
+    let settings = settings_from_value(settings)?;
+    settings.validate().map_err(SettingsError::Invalid)?;
+    Ok(settings)
+}
+
+/// Folders remembered in the per-folder settings history, at most
+const FOLDER_HISTORY_MAX_ENTRIES: usize = 500;
+/// Age in days after which a folder's remembered settings are dropped
+const FOLDER_HISTORY_MAX_DAYS: u64 = 365;
+
+/// Settings a folder was last processed with
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FolderHistoryEntry {
+    pub settings: ProcessingSettings,
+    /// Unix time the folder finished processing
+    pub completed_at: u64,
+}
+
+/// Held while the history is read and written back, for folders finishing at once
+static FOLDER_HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+fn folder_history_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("folder_history.json"))
+}
+
+/// The history keyed by canonical folder path; unreadable history is treated as empty
+fn load_folder_history() -> BTreeMap<PathBuf, FolderHistoryEntry> {
+    folder_history_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Settings `folder` was last processed with, if remembered
+pub fn folder_history(folder: &Path) -> Option<FolderHistoryEntry> {
+    let canonical = folder.canonicalize().unwrap_or_else(|_| folder.to_path_buf());
+    load_folder_history().remove(&canonical)
+}
+
+/// Remember the settings `folder` was just processed with, pruning old entries
+pub fn record_folder_history(folder: &Path, settings: &ProcessingSettings) -> Result<(), Box<dyn std::error::Error>> {
+    let path = folder_history_path().ok_or("Could not determine config directory")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let canonical = folder.canonicalize().unwrap_or_else(|_| folder.to_path_buf());
     
-    let content = serde_json::to_string_pretty(settings)?;
-    std::fs::write(path, content)?;
+    let _lock = FOLDER_HISTORY_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut history = load_folder_history();
+    history.insert(canonical, FolderHistoryEntry { settings: settings.clone(), completed_at: now });
+    prune_folder_history(&mut history, now);
+    
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&history)?)?;
     Ok(())
 }
+
+/// Drop entries older than the age limit, then the oldest beyond the size limit
+fn prune_folder_history(history: &mut BTreeMap<PathBuf, FolderHistoryEntry>, now: u64) {
+    let cutoff = now.saturating_sub(FOLDER_HISTORY_MAX_DAYS * 24 * 60 * 60);
+    history.retain(|_, entry| entry.completed_at >= cutoff);
+    if history.len() > FOLDER_HISTORY_MAX_ENTRIES {
+        let mut by_age: Vec<(u64, PathBuf)> = history.iter()
+            .map(|(path, entry)| (entry.completed_at, path.clone()))
+            .collect();
+        by_age.sort();
+        for (_, path) in by_age.into_iter().take(history.len() - FOLDER_HISTORY_MAX_ENTRIES) {
+            history.remove(&path);
+        }
+    }
+}
+
+/// Folders kept in the recent-folders list, at most
+const RECENT_FOLDERS_MAX: usize = 10;
+
+/// A folder added to the queue, and whether it was added with its subfolders
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentFolder {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+/// Folders recently added to the queue, most recent first, one entry per canonical path
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RecentFolders {
+    folders: Vec<RecentFolder>,
+}
+
+impl RecentFolders {
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("recent_folders.json"))
+    }
+
+    /// Load the list, dropping folders that no longer exist
+    pub fn load() -> Self {
+        let mut recent: RecentFolders = Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        recent.folders.retain(|folder| folder.path.is_dir());
+        recent
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path().ok_or("Could not determine config directory")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Move `path` to the front, replacing an entry for the same folder under another path
+    fn add(&mut self, path: &Path, recursive: bool) {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.folders.retain(|folder| {
+            folder.path.canonicalize().unwrap_or_else(|_| folder.path.clone()) != canonical
+        });
+        self.folders.insert(0, RecentFolder { path: canonical, recursive });
+        self.folders.truncate(RECENT_FOLDERS_MAX);
+    }
+}
+
+/// Record a folder added to the queue
+pub fn add_recent(path: &Path, recursive: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut recent = RecentFolders::load();
+    recent.add(path, recursive);
+    recent.save()
+}
+
+/// Recently added folders that still exist, most recent first
+pub fn recent() -> Vec<RecentFolder> {
+    RecentFolders::load().folders
+}
+
+pub fn clear_recent() -> Result<(), Box<dyn std::error::Error>> {
+    RecentFolders::default().save()
+}
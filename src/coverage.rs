@@ -0,0 +1,161 @@
+//! Echo coverage of each frame, and its chart over a sequence
+//!
+//! Coverage is the fraction of a frame's pixels with echo, those the
+//! [`EchoPredicate`] of the settings counts. At the end of a run it is
+//! drawn as a line chart against the scan time, or against the frame number
+//! when the file names carry no times, always from 0 to 100% so the charts of
+//! different events compare at a glance. The line breaks where scans are
+//! missing: at frames that could not be rendered, and where the time to the
+//! next scan is over one and a half times the median, which is shaded.
+
+use image::{Rgba, RgbaImage};
+
+use crate::draw;
+use crate::echo::EchoPredicate;
+use crate::nowcast;
+
+/// One frame's point on the chart
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoverageSample {
+    /// Scan time, in seconds since the Unix epoch
+    pub time: Option<i64>,
+    /// Fraction of the frame with echo, from 0 to 1; `None` for a frame that
+    /// could not be rendered
+    pub coverage: Option<f32>,
+}
+
+/// Name of the chart a run writes to its output folder
+pub const COVERAGE_CHART_NAME: &str = "coverage_timeseries.png";
+
+/// Size of the chart, in pixels
+pub const CHART_SIZE: (u32, u32) = (800, 400);
+
+/// Fraction of the pixels of `frame` that `echo` counts, from 0 to 1
+pub fn coverage(frame: &RgbaImage, echo: &EchoPredicate) -> f32 {
+    let pixels = frame.width() as usize * frame.height() as usize;
+    if pixels == 0 {
+        return 0.0;
+    }
+    let echoes = frame.pixels().filter(|pixel| echo.is_echo(pixel)).count();
+    echoes as f32 / pixels as f32
+}
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const INK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const GRID: Rgba<u8> = Rgba([208, 208, 208, 255]);
+const GAP: Rgba<u8> = Rgba([240, 240, 240, 255]);
+const LINE: Rgba<u8> = Rgba([0, 90, 200, 255]);
+
+/// The plot area, inside the margins for the title and the axis labels
+const LEFT: f32 = 70.0;
+const RIGHT: f32 = 780.0;
+const TOP: f32 = 40.0;
+const BOTTOM: f32 = 350.0;
+
+/// Draw the coverage of `samples`, in sequence order, as the module describes.
+/// The x axis shows scan times when every sample has one.
+pub fn chart(samples: &[CoverageSample]) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(CHART_SIZE.0, CHART_SIZE.1, BACKGROUND);
+    let times: Option<Vec<i64>> = samples.iter().map(|sample| sample.time).collect();
+    let xs: Vec<f64> = match &times {
+        Some(times) => times.iter().map(|&time| time as f64).collect(),
+        None => (0..samples.len()).map(|index| index as f64).collect(),
+    };
+    let (low, high) = xs.iter().fold((f64::MAX, f64::MIN), |(low, high), &x| (low.min(x), high.max(x)));
+    let (low, high) = if low < high { (low, high) } else { (low.min(high) - 1.0, low.min(high) + 1.0) };
+    let x_at = |x: f64| LEFT + ((x - low) / (high - low)) as f32 * (RIGHT - LEFT);
+    let y_at = |coverage: f32| BOTTOM - coverage.clamp(0.0, 1.0) * (BOTTOM - TOP);
+
+    // Scans missing from a timed sequence
+    let gaps: Vec<bool> = match &times {
+        Some(times) => {
+            let mut intervals: Vec<i64> = times.windows(2).map(|pair| pair[1] - pair[0]).filter(|&dt| dt > 0).collect();
+            intervals.sort_unstable();
+            let median = intervals.get(intervals.len() / 2).copied();
+            times.windows(2).map(|pair| median.is_some_and(|median| (pair[1] - pair[0]) * 2 > median * 3)).collect()
+        }
+        None => vec![false; samples.len().saturating_sub(1)],
+    };
+    for (index, _) in gaps.iter().enumerate().filter(|&(_, &gap)| gap) {
+        let (from, to) = (x_at(xs[index]).ceil() as u32, x_at(xs[index + 1]).floor() as u32);
+        for x in from..=to {
+            for y in TOP as u32..=BOTTOM as u32 {
+                image.put_pixel(x, y, GAP);
+            }
+        }
+    }
+
+    for percent in [0, 25, 50, 75, 100] {
+        let y = y_at(percent as f32 / 100.0);
+        draw::draw_line(&mut image, (LEFT, y), (RIGHT, y), if percent == 0 { INK } else { GRID });
+        let label = format!("{}%", percent);
+        let width = draw::text_width(&label, 2) as i64;
+        draw::draw_text(&mut image, (LEFT as i64 - 8 - width, y as i64 - 5), &label, 2, INK);
+    }
+    draw::draw_line(&mut image, (LEFT, TOP), (LEFT, BOTTOM), INK);
+
+    let (step, label): (f64, fn(f64, f64) -> String) = match &times {
+        Some(_) => (time_step(high - low), time_label),
+        None => (index_step(high - low), |x, _| format!("{}", x as i64)),
+    };
+    let mut tick = (low / step).ceil() * step;
+    while tick <= high {
+        let x = x_at(tick);
+        draw::draw_line(&mut image, (x, BOTTOM), (x, BOTTOM + 5.0), INK);
+        let text = label(tick, step);
+        let width = draw::text_width(&text, 2) as f32;
+        draw::draw_text(&mut image, ((x - width / 2.0) as i64, BOTTOM as i64 + 10), &text, 2, INK);
+        tick += step;
+    }
+    let axis = if times.is_some() { "TIME UTC" } else { "FRAME" };
+    let width = draw::text_width(axis, 2) as f32;
+    draw::draw_text(&mut image, (((LEFT + RIGHT - width) / 2.0) as i64, BOTTOM as i64 + 30), axis, 2, INK);
+    draw::draw_text(&mut image, (LEFT as i64, 12), "ECHO COVERAGE", 2, INK);
+
+    let points: Vec<Option<(f32, f32)>> = samples.iter()
+        .zip(&xs)
+        .map(|(sample, &x)| sample.coverage.map(|coverage| (x_at(x), y_at(coverage))))
+        .collect();
+    for (index, pair) in points.windows(2).enumerate() {
+        if let [Some(from), Some(to)] = *pair
+            && !gaps[index]
+        {
+            draw::draw_line(&mut image, from, to, LINE);
+            draw::draw_line(&mut image, (from.0, from.1 - 1.0), (to.0, to.1 - 1.0), LINE);
+        }
+    }
+    for &point in points.iter().flatten() {
+        draw::draw_dot(&mut image, point, 2.0, LINE);
+    }
+    image
+}
+
+/// Seconds between time ticks, for at most eight over `span` seconds
+fn time_step(span: f64) -> f64 {
+    const STEPS: [f64; 12] = [60.0, 120.0, 300.0, 600.0, 900.0, 1800.0, 3600.0, 7200.0, 10_800.0, 21_600.0, 43_200.0, 86_400.0];
+    let step = STEPS.into_iter().find(|&step| span / step <= 8.0);
+    step.unwrap_or_else(|| (span / 8.0 / 86_400.0).ceil() * 86_400.0)
+}
+
+/// `HH:MM` for ticks within a day of each other, `MM-DD` for whole days
+fn time_label(time: f64, step: f64) -> String {
+    let digits = nowcast::scan_time_digits(time as i64);
+    if step >= 86_400.0 {
+        format!("{}-{}", &digits[4..6], &digits[6..8])
+    } else {
+        format!("{}:{}", &digits[8..10], &digits[10..12])
+    }
+}
+
+/// Frames between index ticks, 1, 2 or 5 times a power of ten, for at most ten over `span`
+fn index_step(span: f64) -> f64 {
+    let mut power = 1.0;
+    loop {
+        for step in [power, 2.0 * power, 5.0 * power] {
+            if span / step <= 10.0 {
+                return step;
+            }
+        }
+        power *= 10.0;
+    }
+}
@@ -0,0 +1,240 @@
+//! The decay trail mode: a buffer of the history, faded a step each frame
+//!
+//! In the fade mode every frame redraws its `history_length` history frames,
+//! so a long history costs as many passes over the frame. A [`DecayBuffer`]
+//! instead keeps the history drawn so far: each frame it fades the whole
+//! buffer by the decay factor and stamps the frame before on it, at the
+//! opacity of the nearest history frame, and the trail is the buffer over the
+//! background with the current frame on top. The frame `k` frames back is
+//! then at `decay^(k - 1)` of that opacity, and a frame costs the same however
+//! long the history is. Each frame depends on the one before, so a sequence
+//! is rendered one frame after another, with the pixels of each frame in
+//! parallel where rayon is available.
+//!
+//! The history is drawn in the history color, or the newest color of a
+//! gradient, since a frame's age is not kept once it is in the buffer. The
+//! options that draw history frames each by their age — history weights, the
+//! strobe, pre-roll, the history scale, motion blur and centroid tracks — are
+//! those of the fade mode.
+
+use image::RgbaImage;
+#[cfg(feature = "native")]
+use rayon::prelude::*;
+
+use crate::basemap;
+use crate::echo::EchoPredicate;
+use crate::engine::{self, history_age, history_alpha, Original, PixelShader, Tint, TrailColors, TrailSettings};
+use crate::settings::HistoryStyle;
+
+/// Opacity the buffer keeps from one frame to the next under `settings`: its
+/// `decay`, or when that is 0, the factor at which the frame `history_length`
+/// back is as faint as the oldest frame of the fade, `1 / history_length` of
+/// the nearest. A history of one frame or none keeps nothing.
+///
+/// ```
+/// use radar_echo_trails::decay::decay_factor;
+/// use radar_echo_trails::engine::TrailSettings;
+///
+/// let settings = TrailSettings { history_length: 50, ..TrailSettings::default() };
+/// assert!((decay_factor(&settings) - 0.923).abs() < 0.001);
+/// assert_eq!(decay_factor(&TrailSettings { decay: 0.85, ..settings }), 0.85);
+/// ```
+pub fn decay_factor(settings: &TrailSettings) -> f32 {
+    if settings.decay > 0.0 {
+        return settings.decay.min(1.0);
+    }
+    match settings.history_length {
+        0 | 1 => 0.0,
+        length => (length as f32).powf(-1.0 / (length - 1) as f32),
+    }
+}
+
+/// Frames back the history of a buffer stamping frames at `alpha` out of 255
+/// and fading by `decay` reaches: the nearest frame and those after it still
+/// drawn at a level out of 255, at most `cap`
+pub fn decay_reach(decay: f32, alpha: u8, cap: usize) -> usize {
+    if decay <= 0.0 || alpha <= 1 {
+        return 1.min(cap);
+    }
+    // alpha * decay^(k - 1) >= 1
+    let further = (1.0 / alpha as f32).ln() / decay.ln();
+    if !further.is_finite() {
+        return cap;
+    }
+    (further.floor() as usize).saturating_add(1).min(cap)
+}
+
+/// Opacity under which a pixel of the buffer is cleared, far below a level
+/// out of 255, so long runs don't leave it fading through the subnormals
+const CLEARED: f32 = 1.0 / 65536.0;
+
+/// The history of a sequence so far, for the decay mode
+///
+/// ```
+/// use image::{Rgba, RgbaImage};
+/// use radar_echo_trails::decay::DecayBuffer;
+/// use radar_echo_trails::engine::TrailSettings;
+///
+/// let settings = TrailSettings { decay: 0.5, ..TrailSettings::default() };
+/// let echo = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+/// let empty = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0]));
+/// let mut buffer = DecayBuffer::new(&settings);
+///
+/// buffer.advance(Some(&echo));
+/// assert_eq!(buffer.compose(&empty).get_pixel(0, 0), &Rgba([128, 63, 0, 255]));
+/// // A frame later, at half the opacity
+/// buffer.advance(None);
+/// assert_eq!(buffer.compose(&empty).get_pixel(0, 0), &Rgba([64, 31, 0, 255]));
+/// ```
+pub struct DecayBuffer {
+    settings: TrailSettings,
+    echo: EchoPredicate,
+    decay: f32,
+    /// Opacity out of 255 a frame is stamped at: that of the nearest history frame
+    alpha: u8,
+    width: u32,
+    height: u32,
+    /// Premultiplied color and opacity, from 0 to 1, of each pixel, row by row
+    pixels: Vec<[f32; 4]>,
+}
+
+impl DecayBuffer {
+    /// An empty buffer for a sequence rendered with `settings`
+    pub fn new(settings: &TrailSettings) -> Self {
+        DecayBuffer {
+            echo: settings.echo_predicate(),
+            decay: decay_factor(settings),
+            alpha: history_alpha(1.0, 1.0, settings),
+            settings: settings.clone(),
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+        }
+    }
+
+    /// The factor the buffer fades by each frame
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    /// Opacity out of 255 each frame is stamped at
+    pub fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
+    /// Fade the history a frame further, then stamp `frame` on it as the
+    /// nearest history frame; `None` only fades it, for a frame that could not
+    /// be read or is left out of the history. A frame of another size than
+    /// those before starts the buffer over.
+    pub fn advance(&mut self, frame: Option<&RgbaImage>) {
+        if let Some(frame) = frame
+            && frame.dimensions() != (self.width, self.height)
+        {
+            (self.width, self.height) = frame.dimensions();
+            self.pixels = vec![[0.0; 4]; self.width as usize * self.height as usize];
+        }
+        let colors = TrailColors::from_settings(&self.settings);
+        let tint: Box<dyn PixelShader> = match self.settings.history_style {
+            HistoryStyle::Tinted => Box::new(Tint(colors.history)),
+            HistoryStyle::Original => Box::new(Original),
+        };
+        let shader: &dyn PixelShader = match &self.settings.shader {
+            Some(shader) => &*shader.0,
+            None => &*tint,
+        };
+        let age = history_age(1, self.settings.history_length);
+        let (decay, alpha, echo, width) = (self.decay, self.alpha, &self.echo, self.width as usize);
+        let stamp = |(y, row): (usize, &mut [[f32; 4]])| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = if pixel[3] * decay < CLEARED { [0.0; 4] } else { pixel.map(|channel| channel * decay) };
+                let Some(frame) = frame else { continue };
+                let src = *frame.get_pixel(x as u32, y as u32);
+                if !echo.is_echo(&src) {
+                    continue;
+                }
+                let Some(shaded) = shader.shade(src, age, x as u32, y as u32) else { continue };
+                // As blend_pixel draws the nearest history frame
+                let src_alpha = ((shaded[3] as u32 * alpha as u32) / 255) as u8;
+                if src_alpha == 0 {
+                    continue;
+                }
+                let a = src_alpha as f32 / 255.0;
+                let keep = 1.0 - a;
+                *pixel = [
+                    shaded[0] as f32 * a + pixel[0] * keep,
+                    shaded[1] as f32 * a + pixel[1] * keep,
+                    shaded[2] as f32 * a + pixel[2] * keep,
+                    a + pixel[3] * keep,
+                ];
+            }
+        };
+        if width == 0 {
+            return;
+        }
+        #[cfg(feature = "native")]
+        self.pixels.par_chunks_mut(width).enumerate().for_each(stamp);
+        #[cfg(not(feature = "native"))]
+        self.pixels.chunks_mut(width).enumerate().for_each(stamp);
+    }
+
+    /// The trail of `current`: the buffer over the background, and `current`
+    /// drawn on top as [`engine::compose_trail`] draws it. Where `current`
+    /// reaches past the buffer, there is no history.
+    pub fn compose(&self, current: &RgbaImage) -> RgbaImage {
+        let colors = TrailColors::from_settings(&self.settings);
+        let mut output = basemap::backdrop(&self.settings, current.width(), current.height());
+        let row_length = current.width() as usize * 4;
+        let (pixels, width, height) = (&self.pixels, self.width as usize, self.height as usize);
+        let transparent = self.settings.transparent_background;
+        let history = |(y, row): (usize, &mut [u8])| {
+            if y >= height {
+                return;
+            }
+            for (x, out) in row.chunks_exact_mut(4).take(width).enumerate() {
+                let pixel = pixels[y * width + x];
+                if pixel[3] <= 0.0 {
+                    continue;
+                }
+                let keep = 1.0 - pixel[3];
+                if !transparent {
+                    for channel in 0..3 {
+                        out[channel] = (pixel[channel] + out[channel] as f32 * keep) as u8;
+                    }
+                    continue;
+                }
+                // The buffer's colors are premultiplied by its alpha, and what
+                // is behind it shows through in proportion to its own
+                let behind = keep * out[3] as f32 / 255.0;
+                let alpha = pixel[3] + behind;
+                for channel in 0..3 {
+                    out[channel] = ((pixel[channel] + out[channel] as f32 * behind) / alpha) as u8;
+                }
+                out[3] = (alpha * 255.0).round() as u8;
+            }
+        };
+        if row_length > 0 {
+            #[cfg(feature = "native")]
+            output.par_chunks_mut(row_length).enumerate().for_each(history);
+            #[cfg(not(feature = "native"))]
+            output.chunks_mut(row_length).enumerate().for_each(history);
+        }
+        let blend = engine::blend_for(&self.settings);
+        match &self.settings.shader {
+            Some(shader) => {
+                let shader = &engine::EchoOnly { echo: &self.echo, shader: &*shader.0 };
+                engine::overlay_blended(&mut output, current, shader, 0.0, 255, blend, None);
+            }
+            None => {
+                let current_tint = engine::current_tint(colors.current, self.settings.current_style);
+                let shader = &engine::EchoOnly { echo: &self.echo, shader: &current_tint };
+                engine::overlay_blended(&mut output, current, shader, 0.0, 255, blend, None);
+            }
+        }
+        output
+    }
+
+    /// Forget the history, at a boundary between sequences
+    pub fn reset(&mut self) {
+        self.pixels.iter_mut().for_each(|pixel| *pixel = [0.0; 4]);
+    }
+}
@@ -0,0 +1,46 @@
+//! Frames republished under a new name, found by their pixels
+//!
+//! Upstream feeds sometimes publish an earlier scan again minutes later under a
+//! new file name, which makes the trail jump back in time. Every frame is
+//! hashed as it is decoded for rendering, and a frame whose hash matches any
+//! earlier frame of the sequence, not only the one before, is a repeat of it.
+//! Only the 128-bit hash of each frame is kept, so long sequences cost a few
+//! bytes per frame whatever their size.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// A frame with the same pixels as an earlier one of its sequence
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepeatedFrame {
+    pub path: PathBuf,
+    /// The earliest frame it repeats
+    pub first: PathBuf,
+}
+
+/// 128-bit FNV-1a hash of the size and pixels of `frame`, the same across builds
+pub fn frame_hash(frame: &RgbaImage) -> u128 {
+    const OFFSET: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+    let size = [frame.width().to_le_bytes(), frame.height().to_le_bytes()].concat();
+    size.iter()
+        .chain(frame.as_raw())
+        .fold(OFFSET, |hash, &byte| (hash ^ byte as u128).wrapping_mul(PRIME))
+}
+
+/// Each frame whose hash matches an earlier one's, as `(repeat, first)`
+/// indices into `hashes`, in sequence order. Frames without a hash, those that
+/// could not be read, repeat nothing.
+pub fn repeats(hashes: &[Option<u128>]) -> Vec<(usize, usize)> {
+    let mut seen: HashMap<u128, usize> = HashMap::new();
+    hashes.iter()
+        .enumerate()
+        .filter_map(|(index, hash)| {
+            let first = *seen.entry((*hash)?).or_insert(index);
+            (first != index).then_some((index, first))
+        })
+        .collect()
+}
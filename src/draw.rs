@@ -0,0 +1,148 @@
+//! Lines, arrows, dots and text drawn over a composite
+//!
+//! Each blends its color over the image at the color's own alpha. Text uses a
+//! built-in three by five pixel font of digits, capital letters and a few
+//! signs, enough for labels and stamps without a font file. Lowercase letters
+//! are drawn as capitals.
+
+use image::{Rgba, RgbaImage};
+
+use crate::engine::blend_pixel;
+
+/// Draw a one pixel wide line from `from` to `to`, clipped to the image
+pub fn draw_line(image: &mut RgbaImage, from: (f32, f32), to: (f32, f32), color: Rgba<u8>) {
+    let (mut x, mut y) = (from.0.round() as i64, from.1.round() as i64);
+    let (x1, y1) = (to.0.round() as i64, to.1.round() as i64);
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (step_x, step_y) = (if x < x1 { 1 } else { -1 }, if y < y1 { 1 } else { -1 });
+    let mut error = dx + dy;
+    loop {
+        if x >= 0 && y >= 0 && x < image.width() as i64 && y < image.height() as i64 {
+            let pixel = image.get_pixel_mut(x as u32, y as u32);
+            *pixel = blend_pixel(*pixel, color, 255);
+        }
+        if x == x1 && y == y1 {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Draw an arrow from `from` pointing at `to`, its head a third of its length
+/// but at least three pixels
+pub fn draw_arrow(image: &mut RgbaImage, from: (f32, f32), to: (f32, f32), color: Rgba<u8>) {
+    draw_line(image, from, to, color);
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = dx.hypot(dy);
+    if length < 1.0 {
+        return;
+    }
+    let head = (length / 3.0).max(3.0);
+    let angle = dy.atan2(dx);
+    for side in [-0.5f32, 0.5] {
+        let barb = angle + std::f32::consts::PI + side;
+        draw_line(image, to, (to.0 + head * barb.cos(), to.1 + head * barb.sin()), color);
+    }
+}
+
+/// Draw a filled disk of `radius` pixels around `center`, clipped to the image
+pub fn draw_dot(image: &mut RgbaImage, center: (f32, f32), radius: f32, color: Rgba<u8>) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let (left, right) = ((center.0 - radius).floor() as i64, (center.0 + radius).ceil() as i64);
+    let (top, bottom) = ((center.1 - radius).floor() as i64, (center.1 + radius).ceil() as i64);
+    for y in top.max(0)..=bottom.min(height - 1) {
+        for x in left.max(0)..=right.min(width - 1) {
+            if (x as f32 - center.0).hypot(y as f32 - center.1) <= radius {
+                let pixel = image.get_pixel_mut(x as u32, y as u32);
+                *pixel = blend_pixel(*pixel, color, 255);
+            }
+        }
+    }
+}
+
+/// Rows of a glyph of the built-in font, top first, each three bits wide with
+/// the leftmost pixel in the highest bit; blank for characters it lacks
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _ => [0; 5],
+    }
+}
+
+/// Width of `text` drawn at `scale`, in pixels
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * 4).saturating_sub(1) * scale
+}
+
+/// Draw `text` with its top left corner at `origin`, each font pixel `scale`
+/// pixels square, clipped to the image
+pub fn draw_text(image: &mut RgbaImage, origin: (i64, i64), text: &str, scale: u32, color: Rgba<u8>) {
+    let scale = scale.max(1) as i64;
+    for (i, c) in text.chars().enumerate() {
+        let left = origin.0 + i as i64 * 4 * scale;
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for y in origin.1 + row as i64 * scale..origin.1 + (row as i64 + 1) * scale {
+                    for x in left + column * scale..left + (column + 1) * scale {
+                        if x >= 0 && y >= 0 && x < image.width() as i64 && y < image.height() as i64 {
+                            let pixel = image.get_pixel_mut(x as u32, y as u32);
+                            *pixel = blend_pixel(*pixel, color, 255);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
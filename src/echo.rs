@@ -0,0 +1,212 @@
+//! What counts as echo in a frame, and what is empty
+//!
+//! Every part of the pipeline that asks whether a pixel is echo asks the same
+//! [`EchoPredicate`]: the trail drawing, the coverage chart, cell tracking and
+//! motion, and repeat detection. It is built once from the `echo_rule`
+//! setting, a compact expression of up to three kinds of rule joined by `&`:
+//!
+//! ```text
+//! alpha>10 & luma>30 & !color(#ffffff,12)
+//! ```
+//!
+//! A pixel is tested in this order, and the first rule it fails makes it empty:
+//!
+//! 1. `alpha>N`: its alpha must be above N. Fully transparent pixels are never
+//!    echo, so without the rule alpha must be above 0.
+//! 2. `!color(#rrggbb,T)`: it must not be within T levels in every channel of
+//!    the color, such as a white or grey background; T defaults to 0. Any
+//!    number of colors may be excluded.
+//! 3. `luma>N`: the luma of its color, 0.299 R + 0.587 G + 0.114 B regardless
+//!    of alpha, must be above N. Without the rule any luma is echo.
+//!
+//! Frames read from disk have their empty pixels cleared to transparent as
+//! they are decoded, so everything drawn or measured from them agrees.
+
+use std::fmt;
+
+use image::{Rgba, RgbaImage};
+
+/// The rule used when the settings give none: any visible pixel that isn't black
+pub const DEFAULT_ECHO_RULE: &str = "alpha>0 & luma>0";
+
+/// A color a pixel may not be near to count as echo
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyColor {
+    pub color: (u8, u8, u8),
+    /// Largest difference in any channel still counted as the color
+    pub tolerance: u8,
+}
+
+impl KeyColor {
+    fn matches(&self, pixel: &Rgba<u8>) -> bool {
+        let (r, g, b) = self.color;
+        [r, g, b].iter().zip(&pixel.0[..3]).all(|(&key, &channel)| key.abs_diff(channel) <= self.tolerance)
+    }
+}
+
+/// Which pixels of a frame are echo, as the module describes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EchoPredicate {
+    /// Alpha a pixel must be above
+    pub alpha_above: u8,
+    /// Colors excluded, in the order given
+    pub excluded: Vec<KeyColor>,
+    /// Luma a pixel's color must be above, if any
+    pub luma_above: Option<u8>,
+}
+
+impl Default for EchoPredicate {
+    fn default() -> Self {
+        EchoPredicate { alpha_above: 0, excluded: Vec::new(), luma_above: Some(0) }
+    }
+}
+
+/// Luma of a pixel's color, from 0 to 255, not weighted by its alpha
+pub fn luma(pixel: &Rgba<u8>) -> f32 {
+    0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32
+}
+
+/// Give each pixel of `frame` an alpha from its luma, for frames whose empty
+/// areas are black rather than transparent. The color is brightened until its
+/// strongest channel is full, so it isn't dimmed twice when it is tinted.
+pub fn alpha_from_luma(frame: &mut RgbaImage) {
+    for pixel in frame.pixels_mut() {
+        let alpha = (luma(pixel) * pixel[3] as f32 / 255.0).round() as u8;
+        let brightest = pixel.0[..3].iter().copied().max().unwrap_or(0);
+        *pixel = match alpha {
+            0 => Rgba([0, 0, 0, 0]),
+            _ => {
+                let brighten = |channel: u8| (channel as f32 * 255.0 / brightest as f32).round() as u8;
+                Rgba([brighten(pixel[0]), brighten(pixel[1]), brighten(pixel[2]), alpha])
+            }
+        };
+    }
+}
+
+impl EchoPredicate {
+    /// Parse an echo rule such as `alpha>10 & luma>30 & !color(#ffffff,12)`;
+    /// an empty rule is [`DEFAULT_ECHO_RULE`]
+    pub fn parse(rule: &str) -> Result<EchoPredicate, EchoRuleError> {
+        if rule.trim().is_empty() {
+            return Ok(EchoPredicate::default());
+        }
+        let mut predicate = EchoPredicate { alpha_above: 0, excluded: Vec::new(), luma_above: None };
+        let mut alpha_given = false;
+        let mut start = 0;
+        for term in rule.split('&') {
+            let column = start + term.len() - term.trim_start().len();
+            start += term.len() + 1;
+            let term = term.trim();
+            let error = |offset: usize, token: &str, expected: &'static str| EchoRuleError {
+                column: column + offset + 1,
+                token: token.to_string(),
+                expected,
+            };
+            if term.is_empty() {
+                return Err(error(0, "&", "a rule: alpha>N, luma>N or !color(#rrggbb,T)"));
+            }
+            let threshold = |name: &str| -> Result<u8, EchoRuleError> {
+                let rest = term[name.len()..].trim_start();
+                let offset = term.len() - rest.len();
+                let Some(number) = rest.strip_prefix('>') else {
+                    return Err(error(offset, rest, "> after the name"));
+                };
+                let number = number.trim();
+                number.parse::<u8>()
+                    .ok()
+                    .filter(|&n| n < 255)
+                    .ok_or_else(|| error(term.len() - number.len(), number, "a whole number from 0 to 254"))
+            };
+            if term.starts_with("alpha") {
+                if alpha_given {
+                    return Err(error(0, term, "each of alpha and luma at most once"));
+                }
+                predicate.alpha_above = threshold("alpha")?;
+                alpha_given = true;
+            } else if term.starts_with("luma") {
+                if predicate.luma_above.is_some() {
+                    return Err(error(0, term, "each of alpha and luma at most once"));
+                }
+                predicate.luma_above = Some(threshold("luma")?);
+            } else if let Some(arguments) = term.strip_prefix("!color(") {
+                let Some(arguments) = arguments.strip_suffix(')') else {
+                    return Err(error(0, term, "a closing parenthesis"));
+                };
+                let offset = "!color(".len();
+                let (hex, tolerance) = arguments.split_once(',').unwrap_or((arguments, "0"));
+                let color = crate::settings::parse_hex_color(hex.trim())
+                    .map_err(|_| error(offset, hex.trim(), "a color like #ffffff"))?;
+                let tolerance = tolerance.trim().parse::<u8>()
+                    .map_err(|_| error(offset + hex.len() + 1, tolerance.trim(), "a tolerance from 0 to 255"))?;
+                predicate.excluded.push(KeyColor { color, tolerance });
+            } else {
+                let token = term.split(|c: char| !c.is_ascii_alphanumeric() && c != '!').next().unwrap_or(term);
+                return Err(error(0, if token.is_empty() { term } else { token }, "alpha>N, luma>N or !color(#rrggbb,T)"));
+            }
+        }
+        Ok(predicate)
+    }
+
+    /// Whether `pixel` is echo
+    #[inline]
+    pub fn is_echo(&self, pixel: &Rgba<u8>) -> bool {
+        pixel[3] > self.alpha_above
+            && !self.excluded.iter().any(|key| key.matches(pixel))
+            && self.luma_above.is_none_or(|above| luma(pixel) > above as f32)
+    }
+
+    /// Clear every pixel of `frame` that isn't echo to transparent
+    pub fn clear_empty(&self, frame: &mut RgbaImage) {
+        for pixel in frame.pixels_mut().filter(|pixel| !self.is_echo(pixel)) {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+
+    /// The tests a pixel goes through, in order, for the user
+    pub fn describe(&self) -> String {
+        let mut steps = vec![format!("alpha above {}", self.alpha_above)];
+        for key in &self.excluded {
+            let (r, g, b) = key.color;
+            steps.push(match key.tolerance {
+                0 => format!("not #{:02x}{:02x}{:02x}", r, g, b),
+                tolerance => format!("not within {} of #{:02x}{:02x}{:02x}", tolerance, r, g, b),
+            });
+        }
+        if let Some(above) = self.luma_above {
+            steps.push(format!("luma above {}", above));
+        }
+        format!("Echo: {}", steps.join(", then "))
+    }
+}
+
+impl fmt::Display for EchoPredicate {
+    /// The predicate as a rule [`EchoPredicate::parse`] reads back
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "alpha>{}", self.alpha_above)?;
+        for key in &self.excluded {
+            let (r, g, b) = key.color;
+            write!(f, " & !color(#{:02x}{:02x}{:02x},{})", r, g, b, key.tolerance)?;
+        }
+        if let Some(above) = self.luma_above {
+            write!(f, " & luma>{}", above)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why an echo rule could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EchoRuleError {
+    /// Position of the offending token, counting characters from 1
+    pub column: usize,
+    pub token: String,
+    pub expected: &'static str,
+}
+
+impl fmt::Display for EchoRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at column {}, \"{}\": expected {}", self.column, self.token, self.expected)
+    }
+}
+
+impl std::error::Error for EchoRuleError {}
@@ -0,0 +1,3159 @@
+//! The trail compositor: rendering a frame sequence with its fading history
+//!
+//! [`render_sequence`] renders a whole sequence in parallel on the current
+//! rayon thread pool, handing each frame to a [`FrameSink`]. The lower-level
+//! [`composite_frame`] renders a single frame from disk, and [`compose_trail`]
+//! from frames already decoded. [`TrailCompositor`] composites frames as they
+//! arrive, one at a time.
+//!
+//! Rendering from disk and in parallel needs the `native` feature; the
+//! in-memory compositing builds without it, for targets such as WebAssembly.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+
+use image::imageops::{self, FilterType};
+use image::{GenericImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::basemap;
+use crate::canvas;
+use crate::color;
+use crate::decay::DecayBuffer;
+use crate::echo::EchoPredicate;
+use crate::motion::{self, MotionGrid};
+use crate::tracking::{self, CellTracker, LabelledCell};
+use crate::settings::{self, parse_fade_curve, CurrentStyle, FadeCurve, GradientSpace, HistoryStyle, Preroll, ProcessingSettings, ResizeFilter};
+
+// Rendering sequences from disk on a thread pool
+#[cfg(feature = "native")]
+use {
+    anyhow::{anyhow, Context, Result},
+    rayon::prelude::*,
+    std::fs,
+    std::ops::ControlFlow,
+    std::path::{Path, PathBuf},
+    std::sync::{Condvar, Mutex},
+    std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    std::time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    crate::coverage::{self, CoverageSample, COVERAGE_CHART_NAME},
+    crate::decay::decay_reach,
+    crate::sequence_writer::SequenceWriter,
+    crate::interpolation,
+    crate::motion::MotionVector,
+    crate::motion_export::{self, FrameMotion},
+    crate::nowcast,
+    crate::palette::Palette,
+    crate::light,
+    crate::range,
+    crate::sidecar,
+    crate::settings::{ErrorPolicy, LayerOutput, MotionExportFormat, MotionSettings, TrailMode, MAX_AUTO_IO_THREADS},
+    std::collections::{HashMap, HashSet},
+};
+
+/// The merged render settings: history length, colors, frame limit, error policy
+/// and the output options the queue uses
+pub type TrailSettings = ProcessingSettings;
+
+/// Background, current and history colors resolved from the settings
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrailColors {
+    pub background: (u8, u8, u8),
+    pub current: (u8, u8, u8),
+    /// Color of the newest history frame, and of them all without a gradient
+    pub history: (u8, u8, u8),
+    /// Color of the oldest history frame; the history color without a gradient
+    pub oldest_history: (u8, u8, u8),
+    /// Color space the ages between the newest and oldest are mixed in
+    pub gradient_space: GradientSpace,
+    /// Color of each history frame by how many frames back it is, from 1,
+    /// with the last for any older; in place of the gradient when there are any
+    pub by_age: Vec<(u8, u8, u8)>,
+}
+
+impl TrailColors {
+    /// The colors of `settings`, falling back to the defaults for any that do not parse
+    pub fn from_settings(settings: &TrailSettings) -> Self {
+        let history = settings::parse_hex_color(&settings.history_color).unwrap_or((255, 127, 0));
+        let (history, oldest_history) = settings::parse_history_gradient(&settings.history_gradient)
+            .ok()
+            .flatten()
+            .unwrap_or((history, history));
+        let by_age: Vec<_> = settings.history_colors.iter().filter_map(|hex| settings::parse_hex_color(hex).ok()).collect();
+        let (history, oldest_history) = match (by_age.first(), by_age.last()) {
+            (Some(&newest), Some(&oldest)) => (newest, oldest),
+            _ => (history, oldest_history),
+        };
+        TrailColors {
+            background: settings::parse_hex_color(&settings.background_color).unwrap_or((0, 0, 0)),
+            current: settings::parse_hex_color(&settings.current_color).unwrap_or((0, 255, 0)),
+            history,
+            oldest_history,
+            gradient_space: settings.gradient_space,
+            by_age,
+        }
+    }
+
+    /// Color of a history frame of age `age` when `history_count` are drawn:
+    /// mixed from the newest's color, a frame back, to the oldest's,
+    /// `history_count` back, and the history color alone without a gradient.
+    /// With history colors, the one of the nearest whole number of frames back.
+    ///
+    /// ```
+    /// use radar_echo_trails::engine::{history_age, TrailColors, TrailSettings};
+    ///
+    /// let settings = TrailSettings { history_gradient: "#ff0000:#0000ff".to_string(), ..TrailSettings::default() };
+    /// let colors = TrailColors::from_settings(&settings);
+    /// assert_eq!(colors.history_at(history_age(1, 5), 5), (255, 0, 0));
+    /// assert_eq!(colors.history_at(history_age(3, 5), 5), (188, 0, 188));
+    /// assert_eq!(colors.history_at(history_age(5, 5), 5), (0, 0, 255));
+    ///
+    /// let history_colors = vec!["#ffff00".to_string(), "#aa2200".to_string()];
+    /// let colors = TrailColors::from_settings(&TrailSettings { history_colors, ..settings });
+    /// assert_eq!(colors.history_at(history_age(1, 5), 5), (255, 255, 0));
+    /// assert_eq!(colors.history_at(history_age(3, 5), 5), (170, 34, 0));
+    /// ```
+    pub fn history_at(&self, age: f32, history_count: usize) -> (u8, u8, u8) {
+        if !self.by_age.is_empty() {
+            let back = (age * (history_count + 1) as f32).round() as usize;
+            return self.by_age[back.clamp(1, self.by_age.len()) - 1];
+        }
+        if self.history == self.oldest_history || history_count < 2 {
+            return self.history;
+        }
+        let back = age * (history_count + 1) as f32;
+        color::mix(self.history, self.oldest_history, (back - 1.0) / (history_count - 1) as f32, self.gradient_space)
+    }
+}
+
+/// Where rendered frames go. Frames arrive from several threads, in no particular order.
+///
+/// Any `Fn(usize, &Path, RgbaImage) -> anyhow::Result<()>` closure is a sink:
+///
+/// ```
+/// use std::path::Path;
+/// use std::sync::Mutex;
+/// use radar_echo_trails::engine::FrameSink;
+///
+/// let sizes = Mutex::new(Vec::new());
+/// let sink = |index: usize, _source: &Path, frame: image::RgbaImage| {
+///     sizes.lock().unwrap().push((index, frame.dimensions()));
+///     Ok(())
+/// };
+/// sink.write_frame(0, Path::new("frame_000.png"), image::RgbaImage::new(4, 3)).unwrap();
+/// assert_eq!(*sizes.lock().unwrap(), vec![(0, (4, 3))]);
+/// ```
+#[cfg(feature = "native")]
+pub trait FrameSink: Sync {
+    /// Take the rendered frame `index`, made from the source frame at `source`
+    fn write_frame(&self, index: usize, source: &Path, frame: RgbaImage) -> Result<()>;
+
+    /// When the trail of frame `index`, made from `source`, was written by an
+    /// earlier run, if the sink still holds it and taking it as written would
+    /// leave nothing out, for resuming that run. Sinks that cannot tell say
+    /// it was not written.
+    fn written_at(&self, index: usize, source: &Path) -> Option<SystemTime> {
+        let _ = (index, source);
+        None
+    }
+
+    /// Take the motion vectors drawn on frame `index`, when the settings ask
+    /// for them to be saved. Sinks that have nowhere to put them ignore them.
+    fn write_motion(&self, index: usize, source: &Path, vectors: &[MotionVector]) -> Result<()> {
+        let _ = (index, source, vectors);
+        Ok(())
+    }
+
+    /// Take the motion grid of frame `index`, when the settings ask for it to
+    /// be exported. Sinks that have nowhere to put it ignore it.
+    fn write_motion_grid(&self, index: usize, motion: &FrameMotion) -> Result<()> {
+        let _ = (index, motion);
+        Ok(())
+    }
+
+    /// Take the labelled cells of every frame of the sequence, in order, when
+    /// the settings ask for them to be saved. Sinks that have nowhere to put
+    /// them ignore them.
+    fn write_cells(&self, cells: &[Vec<LabelledCell>]) -> Result<()> {
+        let _ = cells;
+        Ok(())
+    }
+
+    /// Take the echo coverage of every frame of the sequence, in order, when
+    /// the settings ask for a chart of it. Sinks that have nowhere to put it
+    /// ignore it.
+    fn write_coverage(&self, samples: &[CoverageSample]) -> Result<()> {
+        let _ = samples;
+        Ok(())
+    }
+
+    /// Take the range correction gain over the frame, once a sequence, when
+    /// the settings ask for it. Sinks that have nowhere to put it ignore it.
+    fn write_range_gain(&self, field: &RgbaImage) -> Result<()> {
+        let _ = field;
+        Ok(())
+    }
+
+    /// Take the trail interpolated `step` frames after frame `before`, from 1
+    /// up to the interpolated frame count, named `source` as
+    /// [`interpolated_name`] gives. Sinks that only keep the scans drop it.
+    fn write_interpolated(&self, before: usize, step: u32, source: &Path, frame: RgbaImage) -> Result<()> {
+        let _ = (before, step, source, frame);
+        Ok(())
+    }
+
+    /// Hear that the trail of frame `index` failed, so neither it nor the
+    /// trails interpolated after it will come. Sinks that keep the trails
+    /// in order stop waiting for them; the rest ignore it.
+    fn skip_frame(&self, index: usize) {
+        let _ = index;
+    }
+
+    /// Hear that the trail interpolated `step` frames after frame `before`
+    /// failed, as [`skip_frame`](Self::skip_frame) does for a scan
+    fn skip_interpolated(&self, before: usize, step: u32) {
+        let _ = (before, step);
+    }
+
+    /// Take the separate layers of frame `index`, when the settings ask for
+    /// them. Sinks that only keep the flattened trails drop them.
+    fn write_layers(&self, index: usize, source: &Path, layers: TrailLayers) -> Result<()> {
+        let _ = (index, source, layers);
+        Ok(())
+    }
+
+    /// Take what each trail written was drawn from, in order, when the
+    /// settings ask for a provenance index. Sinks that have nowhere to put
+    /// it ignore it.
+    fn write_provenance(&self, records: &[Provenance]) -> Result<()> {
+        let _ = records;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "native")]
+impl<F> FrameSink for F
+where
+    F: Fn(usize, &Path, RgbaImage) -> Result<()> + Sync,
+{
+    fn write_frame(&self, index: usize, source: &Path, frame: RgbaImage) -> Result<()> {
+        self(index, source, frame)
+    }
+}
+
+/// The file format a [`DirectorySink`] writes
+#[cfg(feature = "native")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The format of the source frame's extension; ODIM radar files give PNG
+    #[default]
+    MatchSource,
+    /// PNG, whatever the source
+    Png,
+    /// GeoTIFF with the source frame's georeference, for frames that are
+    /// GeoTIFFs themselves. Frames without one, or whose size no longer
+    /// matches it, are written as they would be with `MatchSource`.
+    GeoTiff,
+}
+
+/// Saves frames under an output directory, mirroring their path below a source directory.
+///
+/// Each frame is written under a temporary name and renamed into place, so an
+/// abandoned run never leaves a partial file. The image format follows the
+/// source file's extension unless [`DirectorySink::with_format`] sets another;
+/// trails of ODIM radar files are saved as PNG.
+#[cfg(feature = "native")]
+pub struct DirectorySink {
+    output_dir: PathBuf,
+    source_root: PathBuf,
+    format: OutputFormat,
+    interpolated: bool,
+    layers: bool,
+    /// Name each frame is written under, by index, in place of its source's
+    names: Vec<PathBuf>,
+    /// Folder motion grids are exported to, with their format and pixel size
+    motion_export: Option<(PathBuf, MotionExportFormat, Option<f32>)>,
+    /// Palette the trails written as PNG are indexed with
+    palette: Option<Palette>,
+    /// Size of the `z/x/y` tiles trails are written as; 0 writes them whole
+    tile_size: u32,
+    /// Copy of the newest trail kept up to date, for displays that show one image
+    latest: Option<LatestImage>,
+    /// File the provenance index is written to
+    index: Option<PathBuf>,
+    /// Animations each trail is also encoded into
+    animations: Vec<SequenceWriter>,
+    /// Frames interpolated after each scan that the animations take between it and the next
+    animated_steps: u32,
+    /// Whether the trails themselves are saved, rather than only encoded
+    trails: bool,
+}
+
+/// Where a [`DirectorySink`] keeps a copy of its newest trail
+#[cfg(feature = "native")]
+struct LatestImage {
+    path: PathBuf,
+    /// Also describe it in a JSON file beside it
+    json: bool,
+    /// Index of the newest frame written there; frames arrive out of order
+    newest: Mutex<Option<usize>>,
+}
+
+#[cfg(feature = "native")]
+impl DirectorySink {
+    /// Write into `output_dir`; sources below `source_root` keep their subfolders,
+    /// others are written by file name alone
+    pub fn new(output_dir: impl Into<PathBuf>, source_root: impl Into<PathBuf>) -> Self {
+        DirectorySink {
+            output_dir: output_dir.into(),
+            source_root: source_root.into(),
+            format: OutputFormat::MatchSource,
+            interpolated: false,
+            layers: false,
+            names: Vec::new(),
+            motion_export: None,
+            palette: None,
+            tile_size: 0,
+            latest: None,
+            index: None,
+            animations: Vec::new(),
+            animated_steps: 0,
+            trails: true,
+        }
+    }
+
+    /// The name frame `index`, made from `source`, is written under
+    fn name<'p>(&'p self, index: usize, source: &'p Path) -> &'p Path {
+        self.names.get(index).map_or(source, PathBuf::as_path)
+    }
+
+    /// Where the trail of frame `index`, made from `source`, is written
+    pub fn trail_file(&self, index: usize, source: &Path) -> PathBuf {
+        self.trail_path(self.name(index, source))
+    }
+
+    /// Where the frame named `name` goes, before its extension is changed
+    fn output_path(&self, name: &Path) -> PathBuf {
+        match name.strip_prefix(&self.source_root) {
+            Ok(relative) => self.output_dir.join(relative),
+            Err(_) => self.output_dir.join(name.file_name().unwrap_or("frame.png".as_ref())),
+        }
+    }
+
+    /// Write frames in `format` instead of the source's
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Also write interpolated trails, beside the trails of the scans
+    pub fn with_interpolated(mut self, write: bool) -> Self {
+        self.interpolated = write;
+        self
+    }
+
+    /// Also write the layers of each trail, beside the trails
+    pub fn with_layers(mut self, write: bool) -> Self {
+        self.layers = write;
+        self
+    }
+
+    /// Write frame `index` as if it were made from `names[index]`, for
+    /// sequences that show a source more than once; frames past the end of
+    /// `names` are named by their source
+    pub fn with_names(mut self, names: Vec<PathBuf>) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Export motion grids into `dir`, in the format and with the pixel size of `motion`
+    pub fn with_motion_export(mut self, dir: impl Into<PathBuf>, motion: &MotionSettings) -> Self {
+        self.motion_export = Some((dir.into(), motion.export_format, motion.pixel_size_km));
+        self
+    }
+
+    /// Write the trails saved as PNG as indexed-color images with `palette`
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Write each trail as square tiles `tile_size` pixels wide, for web maps,
+    /// instead of one image: the trail `radar/0130.png` becomes
+    /// `radar/0130/<z>/<x>/<y>.png`, at the zoom level [`tile_zoom`] gives.
+    /// Tiles along the right and bottom edges are padded with transparency to
+    /// the full size. A size of 0 writes trails whole.
+    pub fn with_tiles(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Keep a copy of the newest trail at `path`, relative to the output
+    /// directory unless absolute, replaced as each newer frame is written;
+    /// with `json`, also `<path>.json` naming its source and scan time.
+    /// Where symbolic links are available, a link named after `path` without
+    /// its extension, such as `latest`, points at the newest trail itself.
+    pub fn with_latest(mut self, path: impl Into<PathBuf>, json: bool) -> Self {
+        self.latest = Some(LatestImage { path: self.output_dir.join(path.into()), json, newest: Mutex::new(None) });
+        self
+    }
+
+    /// Write the provenance index to `path`, relative to the output directory
+    /// unless absolute: a line of JSON per trail when it ends in `.jsonl`,
+    /// and a JSON array of them otherwise
+    pub fn with_index(mut self, path: impl Into<PathBuf>) -> Self {
+        self.index = Some(self.output_dir.join(path.into()));
+        self
+    }
+
+    /// Also encode each trail into the animation `writer` writes; see
+    /// [`finish_animations`](Self::finish_animations)
+    pub fn with_animation(mut self, writer: SequenceWriter) -> Self {
+        self.animations.push(writer);
+        self
+    }
+
+    /// Also encode the `frames` trails interpolated after each scan into the
+    /// animations, between it and the next. The trail interpolated `step`
+    /// frames after frame `index` then goes at `index * (frames + 1) + step`
+    /// in the animations, and the scan's own at `index * (frames + 1)`, so
+    /// their writers count from the first scan's position.
+    pub fn with_animated_interpolation(mut self, frames: u32) -> Self {
+        self.animated_steps = frames;
+        self
+    }
+
+    /// Save the trails themselves, as well as encoding them into animations
+    /// and keeping the newest; on by default
+    pub fn with_trails(mut self, write: bool) -> Self {
+        self.trails = write;
+        self
+    }
+
+    /// Trails encoded into every animation so far, when any are being written
+    pub fn animations_encoded(&self) -> Option<usize> {
+        self.animations.iter().map(SequenceWriter::encoded).min()
+    }
+
+    /// Encode the trails the animations are still waiting for and complete
+    /// them, returning how many frames each holds
+    pub fn finish_animations(&mut self) -> Vec<Result<usize>> {
+        self.animations.drain(..).map(SequenceWriter::finish).collect()
+    }
+
+    /// Pass over `position` in every animation, its trail having failed
+    fn skip_animated(&self, position: usize) {
+        for animation in &self.animations {
+            animation.skip(position);
+        }
+    }
+
+    /// Encode `frame` into every animation at `position`
+    fn animate(&self, position: usize, frame: RgbaImage) {
+        if let Some((last, others)) = self.animations.split_last() {
+            for animation in others {
+                animation.push(position, frame.clone());
+            }
+            last.push(position, frame);
+        }
+    }
+
+    /// Where the trail named `name` is saved
+    fn trail_path(&self, name: &Path) -> PathBuf {
+        let output_path = self.output_path(name);
+        // Radar files are drawn as images, so their trails are saved as PNG
+        #[cfg(feature = "hdf5")]
+        let output_path = if crate::odim::is_odim_file(&output_path) {
+            output_path.with_extension("png")
+        } else {
+            output_path
+        };
+        match self.format {
+            OutputFormat::Png => output_path.with_extension("png"),
+            _ => output_path,
+        }
+    }
+
+    /// Save the trail made from `source` under `name`
+    fn save_trail(&self, name: &Path, source: &Path, frame: &RgbaImage) -> Result<()> {
+        let output_path = self.trail_path(name);
+        if self.tile_size > 0 {
+            let grid = tiles(frame.dimensions(), self.tile_size);
+            let zoom_dir = output_path.with_extension("").join(tile_zoom(&grid).to_string());
+            let extension = output_path.extension().unwrap_or("png".as_ref()).to_os_string();
+            for tile in grid {
+                let mut image = RgbaImage::new(self.tile_size, self.tile_size);
+                image.copy_from(&*imageops::crop_imm(frame, tile.x, tile.y, tile.width, tile.height), 0, 0)?;
+                let path = zoom_dir.join(tile.column.to_string()).join(tile.row.to_string()).with_extension(&extension);
+                self.save_image(&path, &image)?;
+            }
+            return Ok(());
+        }
+        let georeference = match self.format {
+            OutputFormat::GeoTiff if crate::geotiff::is_tiff_file(source) => crate::geotiff::read_georeference(source)?
+                .filter(|geo| (geo.width, geo.height) == frame.dimensions()),
+            _ => None,
+        };
+        match georeference {
+            Some(geo) => save_atomically(&output_path, |temp_path| crate::geotiff::save(temp_path, frame, &geo)),
+            None => self.save_image(&output_path, frame),
+        }
+    }
+
+    /// Replace the latest trail with frame `index`, written under `name` from
+    /// `source`, unless a newer frame is there already
+    fn update_latest(&self, index: usize, name: &Path, source: &Path, frame: &RgbaImage) -> Result<()> {
+        let Some(latest) = &self.latest else { return Ok(()) };
+        // Held while writing, so an older frame can't replace a newer one
+        let mut newest = latest.newest.lock().unwrap();
+        if newest.is_some_and(|newest| newest > index) {
+            return Ok(());
+        }
+        let trail = self.trail_path(name);
+        save_atomically(&latest.path, |temp_path| save_in_format(frame, temp_path, image::ImageFormat::from_path(&latest.path)?))?;
+        if latest.json {
+            let written_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let json = serde_json::json!({
+                "frame": index,
+                "source": source.file_name().unwrap_or_default().to_string_lossy(),
+                "trail": trail,
+                "scan_time": nowcast::scan_time(source),
+                "written_at": written_at,
+            });
+            let path = latest.path.with_extension("json");
+            save_atomically(&path, |temp_path| fs::write(temp_path, serde_json::to_string_pretty(&json)?).map_err(Into::into))?;
+        }
+        #[cfg(unix)]
+        if self.tile_size == 0 {
+            let target = fs::canonicalize(&trail).unwrap_or(trail);
+            save_atomically(&latest.path.with_extension(""), |temp_path| {
+                let _ = fs::remove_file(temp_path);
+                std::os::unix::fs::symlink(&target, temp_path).map_err(Into::into)
+            })?;
+        }
+        *newest = Some(index);
+        Ok(())
+    }
+
+    /// Save `image` to `output_path` in the format of its extension, indexed
+    /// with the palette if it is a PNG and there is one
+    fn save_image(&self, output_path: &Path, image: &RgbaImage) -> Result<()> {
+        save_atomically(output_path, |temp_path| match &self.palette {
+            Some(palette) if image::ImageFormat::from_path(output_path).ok() == Some(image::ImageFormat::Png) => {
+                palette.save(temp_path, image)
+            }
+            _ => save_in_format(image, temp_path, image::ImageFormat::from_path(output_path)?),
+        })
+    }
+}
+
+/// Save `image` to `path` as `format`, without its alpha for formats that
+/// have none, such as JPEG
+#[cfg(feature = "native")]
+fn save_in_format(image: &RgbaImage, path: &Path, format: image::ImageFormat) -> Result<()> {
+    use image::buffer::ConvertBuffer;
+    if format == image::ImageFormat::Jpeg {
+        let rgb: image::RgbImage = image.convert();
+        rgb.save_with_format(path, format)?;
+    } else {
+        image.save_with_format(path, format)?;
+    }
+    Ok(())
+}
+
+/// Save an image to `output_path` with `save`, through a temporary file renamed
+/// into place, creating the directory it goes in
+#[cfg(feature = "native")]
+fn save_atomically(output_path: &Path, save: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("creating {}", parent.display()))?;
+    }
+
+    let temp_path = output_path.with_file_name(format!(
+        ".{}.partial",
+        output_path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    if let Err(e) = save(&temp_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e).with_context(|| format!("saving {}", output_path.display()));
+    }
+    fs::rename(&temp_path, output_path)
+        .with_context(|| format!("saving {}", output_path.display()))
+}
+
+#[cfg(feature = "native")]
+impl FrameSink for DirectorySink {
+    fn write_frame(&self, index: usize, source: &Path, frame: RgbaImage) -> Result<()> {
+        let name = self.name(index, source);
+        if self.trails {
+            self.save_trail(name, source, &frame)?;
+        }
+        self.update_latest(index, name, source, &frame)?;
+        self.animate(index * (self.animated_steps as usize + 1), frame);
+        Ok(())
+    }
+
+    /// The modification time of the trail file. Trails saved as tiles, or
+    /// also encoded into an animation that needs every one, are never taken
+    /// as written.
+    fn written_at(&self, index: usize, source: &Path) -> Option<SystemTime> {
+        if !self.trails || self.tile_size > 0 || !self.animations.is_empty() {
+            return None;
+        }
+        fs::metadata(self.trail_file(index, source)).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Saved beside the frame as `<name>.motion.json`
+    fn write_motion(&self, index: usize, source: &Path, vectors: &[MotionVector]) -> Result<()> {
+        let path = self.output_path(self.name(index, source)).with_extension("motion.json");
+        let json = serde_json::json!({
+            "source": source.file_name().unwrap_or_default().to_string_lossy(),
+            "vectors": vectors,
+        });
+        fs::write(&path, serde_json::to_string_pretty(&json)?)
+            .with_context(|| format!("saving {}", path.display()))
+    }
+
+    /// Written like a scan's trail, under its interpolated name, when asked
+    /// for, and encoded into the animations between the scans when they take
+    /// interpolated trails
+    fn write_interpolated(&self, before: usize, step: u32, source: &Path, frame: RgbaImage) -> Result<()> {
+        if self.interpolated {
+            match self.names.get(before) {
+                Some(name) => self.save_trail(&interpolated_name(name, step), source, &frame)?,
+                None => self.save_trail(source, source, &frame)?,
+            }
+        }
+        if step <= self.animated_steps {
+            self.animate(before * (self.animated_steps as usize + 1) + step as usize, frame);
+        }
+        Ok(())
+    }
+
+    /// The scan's position in the animations and those of the trails
+    /// interpolated after it are passed over
+    fn skip_frame(&self, index: usize) {
+        let position = index * (self.animated_steps as usize + 1);
+        for position in position..=position + self.animated_steps as usize {
+            self.skip_animated(position);
+        }
+    }
+
+    fn skip_interpolated(&self, before: usize, step: u32) {
+        if step <= self.animated_steps {
+            self.skip_animated(before * (self.animated_steps as usize + 1) + step as usize);
+        }
+    }
+
+    /// Saved in the export folder, when one is set, as `<name>.uv.json` or
+    /// `<name>.uv.bin`; see [`motion_export`]
+    fn write_motion_grid(&self, index: usize, motion: &FrameMotion) -> Result<()> {
+        let Some((dir, format, pixel_size_km)) = &self.motion_export else { return Ok(()) };
+        let path = dir.join(motion_export::export_name(self.name(index, motion.source), *format));
+        let bytes = motion_export::encode(motion, *format, *pixel_size_km);
+        save_atomically(&path, |temp_path| fs::write(temp_path, bytes).map_err(Into::into))
+    }
+
+    /// Saved beside the trail as `history_<name>.png` and `current_<name>.png`,
+    /// PNG whatever the trail's format, to keep their transparency
+    fn write_layers(&self, index: usize, source: &Path, layers: TrailLayers) -> Result<()> {
+        if !self.layers {
+            return Ok(());
+        }
+        let output_path = self.output_path(self.name(index, source));
+        let stem = output_path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        for (prefix, layer) in [("history", &layers.history), ("current", &layers.current)] {
+            let path = output_path.with_file_name(format!("{}_{}.png", prefix, stem));
+            save_atomically(&path, |temp_path| {
+                layer.save_with_format(temp_path, image::ImageFormat::Png).map_err(Into::into)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Charted in the output folder as `coverage_timeseries.png`
+    fn write_coverage(&self, samples: &[CoverageSample]) -> Result<()> {
+        let chart = coverage::chart(samples);
+        save_atomically(&self.output_dir.join(COVERAGE_CHART_NAME), |temp_path| {
+            chart.save_with_format(temp_path, image::ImageFormat::Png).map_err(Into::into)
+        })
+    }
+
+    /// Saved in the output folder as `range_gain.png`
+    fn write_range_gain(&self, field: &RgbaImage) -> Result<()> {
+        save_atomically(&self.output_dir.join(range::RANGE_GAIN_NAME), |temp_path| {
+            field.save_with_format(temp_path, image::ImageFormat::Png).map_err(Into::into)
+        })
+    }
+
+    /// Saved in the output folder as `cells.csv`, one row per cell per frame
+    fn write_cells(&self, cells: &[Vec<LabelledCell>]) -> Result<()> {
+        let path = self.output_dir.join(CELLS_FILE_NAME);
+        let mut csv = String::from("frame,id,centroid_x,centroid_y,area,mean_intensity\n");
+        for (frame, labelled) in cells.iter().enumerate() {
+            for LabelledCell { id, cell } in labelled {
+                csv.push_str(&format!(
+                    "{},{},{:.2},{:.2},{},{:.2}\n",
+                    frame, id, cell.centroid.0, cell.centroid.1, cell.area, cell.mean_intensity,
+                ));
+            }
+        }
+        fs::create_dir_all(&self.output_dir)
+            .with_context(|| format!("creating {}", self.output_dir.display()))?;
+        fs::write(&path, csv).with_context(|| format!("saving {}", path.display()))
+    }
+
+    /// Saved where [`DirectorySink::with_index`] says, when it was called,
+    /// naming each trail's file relative to the output folder and its sources
+    /// relative to the source folder
+    fn write_provenance(&self, records: &[Provenance]) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct Entry {
+            output: PathBuf,
+            #[serde(flatten)]
+            record: Provenance,
+        }
+
+        let Some(path) = &self.index else { return Ok(()) };
+        let relative = |path: &Path| path.strip_prefix(&self.source_root).unwrap_or(path).to_path_buf();
+        let entries = records.iter().map(|record| {
+            let output = self.trail_file(record.index, &record.source);
+            let mut record = record.clone();
+            record.source = relative(&record.source);
+            for past in &mut record.history {
+                past.source = relative(&past.source);
+            }
+            Entry { output: output.strip_prefix(&self.output_dir).map_or(output.clone(), Path::to_path_buf), record }
+        });
+        let json = if path.extension().is_some_and(|extension| extension == "jsonl") {
+            entries.map(|entry| serde_json::to_string(&entry).map(|line| line + "\n")).collect::<serde_json::Result<String>>()?
+        } else {
+            serde_json::to_string_pretty(&entries.collect::<Vec<_>>())?
+        };
+        save_atomically(path, |temp_path| fs::write(temp_path, json).map_err(Into::into))
+    }
+}
+
+/// Name of the table of labelled cells a [`DirectorySink`] writes
+#[cfg(feature = "native")]
+pub const CELLS_FILE_NAME: &str = "cells.csv";
+
+/// What a trail was drawn from, for the provenance index: its current frame
+/// and each history frame drawn under it, with the weight it was drawn at
+#[cfg(feature = "native")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Provenance {
+    /// Index of the trail's frame in the sequence
+    pub index: usize,
+    /// The current frame
+    pub source: PathBuf,
+    /// Scan time in the current frame's name, in seconds since the Unix epoch
+    pub time: Option<i64>,
+    /// Color the current frame is drawn in, as `#rrggbb`, or `shader` when a
+    /// custom shader colors the trail
+    pub tint: String,
+    /// The history frames, oldest first, in the order they were drawn
+    pub history: Vec<HistorySource>,
+}
+
+/// A history frame a trail was drawn from; see [`Provenance`]
+#[cfg(feature = "native")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct HistorySource {
+    /// The scan, or the scan an interpolated frame follows
+    pub source: PathBuf,
+    /// Which of the frames interpolated after `source` it is, from 1, for
+    /// interpolated frames
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<u32>,
+    /// Scan time in the source's name, in seconds since the Unix epoch
+    pub time: Option<i64>,
+    /// Frames before the current one, counted along the history
+    pub frames_back: usize,
+    /// Age fraction, from 0 at the current frame to 1
+    pub age: f32,
+    /// Fraction of the history opacity it was drawn at, after the fade or
+    /// history weights and the strobe
+    pub weight: f32,
+    /// Opacity out of 255 it was drawn at; 0 for frames the strobe left out
+    pub alpha: u8,
+    /// Color it was drawn in, as `#rrggbb`, or `shader`
+    pub tint: String,
+}
+
+/// A frame that has just been written
+#[cfg(feature = "native")]
+#[derive(Clone, Copy, Debug)]
+pub struct FrameProgress<'a> {
+    pub index: usize,
+    pub source: &'a Path,
+    /// Frames written so far, this one included
+    pub frames_done: usize,
+    pub frames_total: usize,
+}
+
+/// How a call to [`render_sequence`] went
+#[cfg(feature = "native")]
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    /// Frames to render, after the frame limit
+    pub frames_total: usize,
+    /// Frames written, or kept from an earlier run when resuming
+    pub frames_completed: usize,
+    /// Frames of `frames_completed` whose trails an earlier run left up to
+    /// date, and were kept rather than rendered
+    pub frames_skipped: usize,
+    /// Frames that could not be rendered or written, with the reason
+    pub failures: Vec<(PathBuf, String)>,
+    /// The run ended before every frame was attempted, by the progress callback
+    /// or the error policy
+    pub stopped: bool,
+    pub elapsed: Duration,
+    /// What each history age added to the trails, nearest first
+    pub age_contributions: Vec<AgeContribution>,
+    /// Where the workers spent their time
+    pub timing: RunTiming,
+}
+
+/// Where the worker threads of a run spent their time, summed over the threads,
+/// so the parts can add up to more than the elapsed time
+#[cfg(feature = "native")]
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RunTiming {
+    /// Wall-clock time of the run
+    pub elapsed_seconds: f64,
+    /// Reading frames and writing outputs
+    pub io_seconds: f64,
+    /// Waiting for one of the `io_threads` reads and writes already running to finish
+    pub io_wait_seconds: f64,
+    /// The rest of the workers' time: compositing, motion, overlays and encoding
+    pub compute_seconds: f64,
+    /// Frames that could be read or written at once
+    pub io_threads: usize,
+    /// Times a scan was decoded to draw trails; once per scan when the frame
+    /// cache holds every scan for as long as trails need it
+    #[serde(default)]
+    pub frames_decoded: usize,
+}
+
+/// Frames read or written at once for an `io_threads` setting, where 0 is one
+/// per core up to [`MAX_AUTO_IO_THREADS`]
+#[cfg(feature = "native")]
+pub fn io_thread_count(io_threads: usize) -> usize {
+    if io_threads == 0 { num_cpus::get().min(MAX_AUTO_IO_THREADS) } else { io_threads }
+}
+
+/// Limits how many reads and writes the render workers run at once, so a run
+/// with many workers doesn't swamp a slow disk or network share, and times them
+#[cfg(feature = "native")]
+struct IoGate {
+    limit: usize,
+    running: Mutex<usize>,
+    freed: Condvar,
+    io_nanos: AtomicU64,
+    wait_nanos: AtomicU64,
+}
+
+#[cfg(feature = "native")]
+impl IoGate {
+    fn new(limit: usize) -> Self {
+        IoGate { limit: limit.max(1), running: Mutex::new(0), freed: Condvar::new(), io_nanos: AtomicU64::new(0), wait_nanos: AtomicU64::new(0) }
+    }
+
+    /// Run `io` once fewer than `limit` others are running. Calls must not nest.
+    fn run<T>(&self, io: impl FnOnce() -> T) -> T {
+        let asked = Instant::now();
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.limit {
+            running = self.freed.wait(running).unwrap();
+        }
+        *running += 1;
+        drop(running);
+        let started = Instant::now();
+        // Frees the slot even if `io` panics
+        let _slot = IoSlot(self);
+        let result = io();
+        self.wait_nanos.fetch_add((started - asked).as_nanos() as u64, Ordering::Relaxed);
+        self.io_nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+
+    fn seconds(nanos: &AtomicU64) -> f64 {
+        nanos.load(Ordering::Relaxed) as f64 / 1e9
+    }
+}
+
+#[cfg(feature = "native")]
+struct IoSlot<'a>(&'a IoGate);
+
+#[cfg(feature = "native")]
+impl Drop for IoSlot<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+/// Scans decoded by one render worker, kept for the others whose frames draw
+/// them as history, so each is read once however many trails it is part of.
+///
+/// The workers take the frames in order, so the scans one needs are mostly
+/// those the last few frames needed; the least recently used are
+/// dropped once more than `capacity` are held, or more than `budget` bytes.
+/// A scan another worker is still reading is read again rather than waited
+/// for, so a worker never blocks on another.
+#[cfg(feature = "native")]
+struct SharedFrames {
+    capacity: usize,
+    budget: Option<usize>,
+    state: Mutex<SharedState>,
+    /// Scans read, each time one was
+    decoded: AtomicUsize,
+}
+
+#[cfg(feature = "native")]
+#[derive(Default)]
+struct SharedState {
+    /// Each scan held, with the last time it was asked for
+    frames: HashMap<(Layer, usize), (Arc<RgbaImage>, u64)>,
+    clock: u64,
+    bytes: usize,
+}
+
+#[cfg(feature = "native")]
+impl SharedFrames {
+    /// Room for the scans `workers` rendering at once need: each frame's
+    /// history, the frame itself, and the scan after it to interpolate
+    /// towards. `cache_mb`, unless 0, caps the bytes held as well.
+    fn new(settings: &TrailSettings, workers: usize) -> Self {
+        let per_worker = settings.history_length + 2;
+        SharedFrames {
+            capacity: per_worker * workers.max(1),
+            budget: (settings.cache_mb > 0).then(|| settings.cache_mb as usize * 1024 * 1024),
+            state: Mutex::new(SharedState::default()),
+            decoded: AtomicUsize::new(0),
+        }
+    }
+
+    /// The scan `index` of `layer`, read with `load` unless it is held
+    fn get(&self, layer: Layer, index: usize, load: impl FnOnce() -> Result<RgbaImage>) -> Result<Arc<RgbaImage>> {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.clock += 1;
+            let now = state.clock;
+            if let Some((frame, used)) = state.frames.get_mut(&(layer, index)) {
+                *used = now;
+                return Ok(frame.clone());
+            }
+        }
+        self.decoded.fetch_add(1, Ordering::Relaxed);
+        let frame = Arc::new(load()?);
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let now = state.clock;
+        if let Some((earlier, _)) = state.frames.insert((layer, index), (frame.clone(), now)) {
+            state.bytes -= earlier.as_raw().len();
+        }
+        state.bytes += frame.as_raw().len();
+        while state.frames.len() > self.capacity || self.budget.is_some_and(|budget| state.bytes > budget) {
+            let Some(&oldest) = state.frames.iter().min_by_key(|(_, (_, used))| *used).map(|(key, _)| key) else { break };
+            let (dropped, _) = state.frames.remove(&oldest).expect("found above");
+            state.bytes -= dropped.as_raw().len();
+        }
+        Ok(frame)
+    }
+}
+
+/// What the history frames of one age added to the trails of a run, counted
+/// by [`compose_trail_counted`] over every scan that had a frame that far back
+#[cfg(feature = "native")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AgeContribution {
+    /// How many frames before the current one, from 1
+    pub frames_back: usize,
+    /// Mean number of pixels a frame of this age added to a trail
+    pub mean_pixels: f64,
+    /// Share of every pixel the history frames added that frames of this age added, from 0 to 1
+    pub share: f64,
+}
+
+/// [`AgeContribution`]s from the pixels each age added, summed over `frames`
+/// scans with a frame of that age
+#[cfg(feature = "native")]
+fn age_contributions(added: &[(u64, u64)]) -> Vec<AgeContribution> {
+    let total: u64 = added.iter().map(|&(pixels, _)| pixels).sum();
+    added.iter()
+        .enumerate()
+        .map(|(index, &(pixels, frames))| AgeContribution {
+            frames_back: index + 1,
+            mean_pixels: if frames > 0 { pixels as f64 / frames as f64 } else { 0.0 },
+            share: if total > 0 { pixels as f64 / total as f64 } else { 0.0 },
+        })
+        .collect()
+}
+
+/// Render every frame of `paths` with its history trail and hand it to `sink`.
+///
+/// Only the first `settings.limit` paths are rendered when a limit is set.
+/// Frames are rendered in parallel on the current rayon thread pool, so wrap
+/// the call in [`rayon::ThreadPool::install`] to choose the thread count; at
+/// most [`io_thread_count`] of them read or write a frame at once. The workers
+/// take the frames in order, so the scans decoded at once are about the
+/// history of a frame for each worker, however long the sequence is.
+/// `progress` is called from the worker threads after each frame is written;
+/// returning [`ControlFlow::Break`] stops the frames not yet started. With
+/// [`ErrorPolicy::FailFolder`] the first failure stops them too.
+///
+/// ```no_run
+/// use std::ops::ControlFlow;
+/// use std::path::PathBuf;
+/// use radar_echo_trails::engine::{self, DirectorySink, TrailSettings};
+///
+/// let mut frames: Vec<PathBuf> = std::fs::read_dir("radar/2024-06-01")?
+///     .map(|entry| entry.map(|e| e.path()))
+///     .collect::<Result<_, _>>()?;
+/// frames.sort();
+///
+/// let settings = TrailSettings { history_length: 8, ..TrailSettings::default() };
+/// let sink = DirectorySink::new("radar/2024-06-01_trail_8", "radar/2024-06-01");
+/// let summary = engine::render_sequence(&frames, &settings, &sink, |progress| {
+///     println!("{}/{}", progress.frames_done, progress.frames_total);
+///     ControlFlow::Continue(())
+/// });
+/// assert!(summary.failures.is_empty());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(feature = "native")]
+pub fn render_sequence<S, P>(paths: &[PathBuf], settings: &TrailSettings, sink: &S, progress: P) -> RunSummary
+where
+    S: FrameSink + ?Sized,
+    P: Fn(FrameProgress<'_>) -> ControlFlow<()> + Sync,
+{
+    render_paired_sequence(paths, &[], settings, sink, progress)
+}
+
+/// [`render_sequence`] with a second folder's trail drawn over each frame's.
+///
+/// `secondary` holds the frame paired with each of `paths`, as
+/// [`crate::secondary::pair`] gives them. The secondary frames are rendered
+/// through the same trail logic, in the colors and at the opacity of
+/// `settings.secondary`, and composited above the primary trail and below the
+/// overlays, which follow the primary frames only. A frame with no secondary
+/// frame, or one that cannot be read, is drawn without the layer. Forecast
+/// frames extrapolate the primary folder alone.
+#[cfg(feature = "native")]
+pub fn render_paired_sequence<S, P>(
+    paths: &[PathBuf],
+    secondary: &[Option<PathBuf>],
+    settings: &TrailSettings,
+    sink: &S,
+    progress: P,
+) -> RunSummary
+where
+    S: FrameSink + ?Sized,
+    P: Fn(FrameProgress<'_>) -> ControlFlow<()> + Sync,
+{
+    render_paired_sequence_from(paths, 0, secondary, settings, sink, || false, progress)
+}
+
+/// [`render_paired_sequence`] of the frames from `first` on, for extending
+/// the output of an earlier run of `paths[..first]`.
+///
+/// The frames before `first` are only read as history, as far back as the
+/// history reaches, so the first new frames get their full trails; they are
+/// not written again. The interpolated frames between the last earlier frame
+/// and the first new one are written, since the earlier run had no frame to
+/// interpolate towards. The summary, progress and coverage chart cover the
+/// new frames alone, with frame indices into the whole of `paths`.
+///
+/// With `settings.resume`, a frame whose trail `sink` holds from an earlier
+/// run, written after the last change to every file it is drawn from, is
+/// kept rather than rendered, as [`FrameSink::written_at`] tells; so are the
+/// frames interpolated after it. It counts towards the progress and the
+/// frames completed, and has no sample in the coverage chart. Nothing is
+/// kept when only the layers are written, or with a provenance index, which
+/// needs every trail drawn.
+///
+/// `cancelled` is polled by the workers before they take a frame and before
+/// they write one. Once it returns true no frame is read or written beyond
+/// those being written at that moment: the frames drawn and not yet written
+/// are dropped, as are the cell CSV, range gain field, coverage chart and
+/// provenance index, and the summary says the run stopped. Returning
+/// [`ControlFlow::Break`] from `progress` instead lets the frames in flight
+/// finish.
+///
+/// In [`TrailMode::Decay`] the frames are drawn one after another over a
+/// [`DecayBuffer`] of their history instead, as [`crate::decay`] describes,
+/// and the earlier frames are read as far back as the buffer still shows
+/// them. That mode draws the primary trail and its overlays alone: the
+/// secondary folder, layers, interpolated and forecast frames and the
+/// provenance index are the fade mode's.
+#[cfg(feature = "native")]
+pub fn render_paired_sequence_from<S, C, P>(
+    paths: &[PathBuf],
+    first: usize,
+    secondary: &[Option<PathBuf>],
+    settings: &TrailSettings,
+    sink: &S,
+    cancelled: C,
+    progress: P,
+) -> RunSummary
+where
+    S: FrameSink + ?Sized,
+    C: Fn() -> bool + Sync,
+    P: Fn(FrameProgress<'_>) -> ControlFlow<()> + Sync,
+{
+    if settings.trail_mode == TrailMode::Decay {
+        return render_decay_from(paths, first, settings, sink, cancelled, progress);
+    }
+    let start = Instant::now();
+    let io = IoGate::new(io_thread_count(settings.io_threads));
+    // Time the workers spent on frames, IO included
+    let busy_nanos = AtomicU64::new(0);
+    let first = first.min(paths.len());
+    let paths = &paths[..settings.limit.map_or(paths.len(), |limit| first + limit).min(paths.len())];
+    let frames_total = paths.len() - first;
+    let frames_done = AtomicUsize::new(0);
+    let frames_skipped = AtomicUsize::new(0);
+    let frames_failed = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let stopped = || stop.load(Ordering::Relaxed) || cancelled();
+    let resume = resumes(settings);
+    let labels = label_sequence(paths, settings, &io, &cancelled);
+    let shared = SharedFrames::new(settings, rayon::current_num_threads());
+    let Placement { placed, origin, coverage_scale } = Placement::of(paths.get(first), settings, &io);
+    let coverage = Mutex::new(vec![None; paths.len()]);
+    // Frame size, for the range gain field
+    let size = Mutex::new(placed);
+    // Pixels added and scans counted, per history age
+    let added = Mutex::new(vec![(0, 0); settings.history_length]);
+    let provenance = Mutex::new(Vec::new());
+    let fail = |source: &Path, e: anyhow::Error| {
+        if settings.on_error == ErrorPolicy::FailFolder {
+            stop.store(true, Ordering::Relaxed);
+        }
+        (source.to_path_buf(), format!("{:#}", e))
+    };
+    let done = |index: usize, source: &Path| {
+        let frames_done = frames_done.fetch_add(1, Ordering::Relaxed) + 1;
+        if progress(FrameProgress { index, source, frames_done, frames_total }).is_break() {
+            stop.store(true, Ordering::Relaxed);
+        }
+    };
+    // The frames between a scan and the next, from the scans already decoded
+    let interpolate = |frames: &mut FrameCache, index: usize, source: &Path| -> Vec<(PathBuf, String)> {
+        let steps = if index + 1 < paths.len() { settings.interpolation.frames } else { 0 };
+        (1..=steps)
+            .take_while(|_| !stopped())
+            .filter_map(|step| {
+                let name = interpolated_name(source, step);
+                render_frame(frames, Moment::Between { before: index, step }, settings, None)
+                    .and_then(|rendered| io.run(|| sink.write_interpolated(index, step, &name, rendered.trail)))
+                    .err()
+                    .map(|e| {
+                        sink.skip_interpolated(index, step);
+                        fail(&name, e)
+                    })
+            })
+            .collect()
+    };
+
+    // Render a scan and the frames interpolated after it, returning the failures
+    let render_index = |index: usize| -> Vec<(PathBuf, String)> {
+        let started = Instant::now();
+        let busy = || busy_nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        let source = &paths[index];
+        let labels = labels.as_ref().map(|labels| labels[index].as_slice());
+        let mut frames = FrameCache::new(paths, settings, &io).with_secondary(secondary).with_shared(&shared);
+        if index < first {
+            let failures = interpolate(&mut frames, index, source);
+            busy();
+            return failures;
+        }
+        if resume && up_to_date(sink, index, source, &drawn_from(paths, secondary, index, settings)) {
+            frames_skipped.fetch_add(1, Ordering::Relaxed);
+            done(index, source);
+            busy();
+            return Vec::new();
+        }
+        let rendered = render_frame(&mut frames, Moment::Scan(index), settings, labels);
+        if cancelled() {
+            busy();
+            return Vec::new();
+        }
+        let written = rendered.and_then(|Rendered { trail, motion, layers, echo, history_added, provenance: drawn_from }| {
+            coverage.lock().unwrap()[index] = echo.map(|echo| (echo * coverage_scale).min(1.0));
+            size.lock().unwrap().get_or_insert(trail.dimensions());
+            for (sum, pixels) in added.lock().unwrap().iter_mut().zip(history_added) {
+                *sum = (sum.0 + pixels, sum.1 + 1);
+            }
+            if let Some(layers) = layers {
+                io.run(|| sink.write_layers(index, source, layers))?;
+            }
+            if settings.layers != LayerOutput::Only {
+                io.run(|| sink.write_frame(index, source, trail))?;
+                provenance.lock().unwrap().extend(drawn_from);
+            }
+            match motion {
+                Some(grid) => write_motion(paths, index, &grid, origin, settings, sink, &io),
+                None => Ok(()),
+            }
+        });
+        if let Err(e) = written {
+            frames_failed.fetch_add(1, Ordering::Relaxed);
+            sink.skip_frame(index);
+            busy();
+            return vec![fail(source, e)];
+        }
+        done(index, source);
+        let failures = interpolate(&mut frames, index, source);
+        busy();
+        failures
+    };
+
+    // Frames are handed to the workers in order, so those in flight, the scans
+    // they draw and the trails waiting for an animation stay within a few
+    // frames of each other however long the sequence is. The last earlier scan
+    // is read for the frames between it and the first new one.
+    let next = AtomicUsize::new(first.saturating_sub(1));
+    let failed = Mutex::new(Vec::new());
+    rayon::scope(|scope| {
+        for _ in 0..rayon::current_num_threads() {
+            scope.spawn(|_| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= paths.len() || stopped() {
+                    break;
+                }
+                let failures = render_index(index);
+                if !failures.is_empty() {
+                    failed.lock().unwrap().push((index, failures));
+                }
+            });
+        }
+    });
+    let mut failed = failed.into_inner().unwrap();
+    failed.sort_by_key(|&(index, _)| index);
+    let mut failures: Vec<(PathBuf, String)> = failed.into_iter().flat_map(|(_, failures)| failures).collect();
+
+    let frames_completed = frames_done.into_inner();
+    let stopped = frames_completed + frames_failed.into_inner() < frames_total;
+    let cancelled = cancelled();
+    if !stopped && settings.nowcast.frames > 0 {
+        let started = Instant::now();
+        failures.extend(render_forecasts(paths, settings, sink, &io));
+        busy_nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+    if let Some(path) = settings.emit_index.as_ref().filter(|_| !cancelled) {
+        let mut records = provenance.into_inner().unwrap();
+        records.sort_by_key(|record| record.index);
+        if let Err(e) = io.run(|| sink.write_provenance(&records)) {
+            failures.push((path.clone(), format!("{:#}", e)));
+        }
+    }
+    if !cancelled {
+        let outputs = SequenceOutputs { labels, origin, size: size.into_inner().unwrap(), coverage: coverage.into_inner().unwrap() };
+        failures.extend(outputs.write(paths, first, settings, sink, &io));
+    }
+    let elapsed = start.elapsed();
+    let (io_seconds, io_wait_seconds) = (IoGate::seconds(&io.io_nanos), IoGate::seconds(&io.wait_nanos));
+    let timing = RunTiming {
+        elapsed_seconds: elapsed.as_secs_f64(),
+        io_seconds,
+        io_wait_seconds,
+        compute_seconds: (IoGate::seconds(&busy_nanos) - io_seconds - io_wait_seconds).max(0.0),
+        io_threads: io.limit,
+        frames_decoded: shared.decoded.into_inner(),
+    };
+    RunSummary {
+        frames_total,
+        frames_completed,
+        frames_skipped: frames_skipped.into_inner(),
+        stopped,
+        failures,
+        elapsed,
+        age_contributions: age_contributions(&added.into_inner().unwrap()),
+        timing,
+    }
+}
+
+/// [`render_paired_sequence_from`] in [`TrailMode::Decay`]: the frames drawn in
+/// order over a [`DecayBuffer`], each one read while the one before it is
+/// drawn and written while the one after it is
+#[cfg(feature = "native")]
+fn render_decay_from<S, C, P>(paths: &[PathBuf], first: usize, settings: &TrailSettings, sink: &S, cancelled: C, progress: P) -> RunSummary
+where
+    S: FrameSink + ?Sized,
+    C: Fn() -> bool + Sync,
+    P: Fn(FrameProgress<'_>) -> ControlFlow<()> + Sync,
+{
+    let start = Instant::now();
+    let io = IoGate::new(io_thread_count(settings.io_threads));
+    let first = first.min(paths.len());
+    let paths = &paths[..settings.limit.map_or(paths.len(), |limit| first + limit).min(paths.len())];
+    let frames_total = paths.len() - first;
+    let labels = label_sequence(paths, settings, &io, &cancelled);
+    let Placement { placed, origin, coverage_scale } = Placement::of(paths.get(first), settings, &io);
+    let mut coverage = vec![None; paths.len()];
+    let mut size = placed;
+    let frames_decoded = AtomicUsize::new(0);
+    let load = |index: usize| {
+        frames_decoded.fetch_add(1, Ordering::Relaxed);
+        io.run(|| load_frame(&paths[index], settings))
+    };
+    let mut buffer = DecayBuffer::new(settings);
+    let mut previous = None;
+    // The earlier frames still showing in the first new frame's trail
+    let earlier = first - decay_reach(buffer.decay(), buffer.alpha(), first);
+    for (index, path) in paths.iter().enumerate().take(first).skip(earlier) {
+        let frame = load(index).ok();
+        buffer.advance(frame.as_ref().filter(|_| in_history(path, settings)));
+        previous = frame;
+    }
+
+    let resume = resumes(settings);
+    let mut failures = Vec::new();
+    let mut frames_completed = 0;
+    let mut frames_skipped = 0;
+    let mut frames_failed = 0;
+    let mut stop = false;
+    let mut fail = |index: usize, e: anyhow::Error, stop: &mut bool| {
+        frames_failed += 1;
+        *stop |= settings.on_error == ErrorPolicy::FailFolder;
+        sink.skip_frame(index);
+        failures.push((paths[index].clone(), format!("{:#}", e)));
+    };
+    let write = |(index, trail, motion): (usize, RgbaImage, Option<MotionGrid>)| {
+        let written = io.run(|| sink.write_frame(index, &paths[index], trail))
+            .and_then(|()| motion.map_or(Ok(()), |grid| write_motion(paths, index, &grid, origin, settings, sink, &io)));
+        (index, written)
+    };
+    // A frame drawn and waiting to be written
+    let mut pending: Option<(usize, RgbaImage, Option<MotionGrid>)> = None;
+    let mut next = (first < paths.len()).then(|| load(first));
+    // A step past the last frame writes it
+    for index in first..=paths.len() {
+        let loaded = next.take();
+        // The frame drawn last is dropped rather than written once cancelled
+        if (loaded.is_none() && pending.is_none()) || cancelled() {
+            break;
+        }
+        // Kept when the buffer's history of it is as old as its trail
+        let kept = resume
+            && loaded.as_ref().is_some_and(|loaded| loaded.is_ok())
+            && up_to_date(sink, index, &paths[index], &paths[index - decay_reach(buffer.decay(), buffer.alpha(), index)..index].iter().map(PathBuf::as_path).collect::<Vec<_>>());
+        let draw = |current: &RgbaImage| {
+            let mut trail = buffer.compose(current);
+            let labels = labels.as_ref().map_or(&[][..], |labels| labels[index].as_slice());
+            let motion = draw_overlays(&mut trail, current, &[], previous.as_ref(), labels, settings);
+            (trail, motion)
+        };
+        let ahead = index + 1 < paths.len() && !stop && !cancelled();
+        let (drawn, (written, read)) = rayon::join(
+            || loaded.as_ref().filter(|_| !kept).and_then(|loaded| loaded.as_ref().ok()).map(draw),
+            || rayon::join(|| pending.take().map(write), || ahead.then(|| load(index + 1))),
+        );
+        if let Some((written_index, written)) = written {
+            let source = &paths[written_index];
+            match written {
+                Ok(()) => {
+                    frames_completed += 1;
+                    let update = FrameProgress { index: written_index, source, frames_done: frames_completed, frames_total };
+                    stop |= progress(update).is_break();
+                }
+                Err(e) => fail(written_index, e, &mut stop),
+            }
+        }
+        let Some(loaded) = loaded else { continue };
+        let source = &paths[index];
+        match (loaded, drawn) {
+            (Ok(current), _) if kept => {
+                frames_completed += 1;
+                frames_skipped += 1;
+                stop |= progress(FrameProgress { index, source, frames_done: frames_completed, frames_total }).is_break();
+                buffer.advance(Some(&current).filter(|_| in_history(source, settings)));
+                previous = Some(current);
+            }
+            (Ok(current), Some((trail, motion))) => {
+                coverage[index] = settings.coverage_plot
+                    .then(|| (coverage::coverage(&current, &settings.echo_predicate()) * coverage_scale).min(1.0));
+                size.get_or_insert(trail.dimensions());
+                buffer.advance(Some(&current).filter(|_| in_history(source, settings)));
+                pending = Some((index, trail, motion));
+                previous = Some(current);
+            }
+            (loaded, _) => {
+                fail(index, loaded.err().unwrap_or_else(|| anyhow!("the frame was not drawn")), &mut stop);
+                buffer.advance(None);
+                previous = None;
+            }
+        }
+        // Once stopped, only the frame drawn last is still written
+        next = read.filter(|_| !stop);
+    }
+
+    let stopped = frames_completed + frames_failed < frames_total;
+    if !cancelled() {
+        let outputs = SequenceOutputs { labels, origin, size, coverage };
+        failures.extend(outputs.write(paths, first, settings, sink, &io));
+    }
+    let elapsed = start.elapsed();
+    let (io_seconds, io_wait_seconds) = (IoGate::seconds(&io.io_nanos), IoGate::seconds(&io.wait_nanos));
+    let timing = RunTiming {
+        elapsed_seconds: elapsed.as_secs_f64(),
+        io_seconds,
+        io_wait_seconds,
+        compute_seconds: (elapsed.as_secs_f64() - io_seconds - io_wait_seconds).max(0.0),
+        io_threads: io.limit,
+        frames_decoded: frames_decoded.into_inner(),
+    };
+    RunSummary { frames_total, frames_completed, frames_skipped, stopped, failures, elapsed, age_contributions: Vec::new(), timing }
+}
+
+/// Where the frames of a sequence sit on the canvas, from the first one rendered
+#[cfg(feature = "native")]
+struct Placement {
+    /// Size of the frames placed on a canvas
+    placed: Option<(u32, u32)>,
+    /// Top left corner of the frames on the canvas
+    origin: (u32, u32),
+    /// Canvas area over frame area; coverage is of the frame, not of the margins around it
+    coverage_scale: f32,
+}
+
+#[cfg(feature = "native")]
+impl Placement {
+    fn of(first: Option<&PathBuf>, settings: &TrailSettings, io: &IoGate) -> Self {
+        let placed = match (settings.canvas.size, first) {
+            (Some(_), Some(path)) => io.run(|| decode_frame(path, settings)).ok().map(|frame| frame.dimensions()),
+            _ => None,
+        };
+        let coverage_scale = match (settings.canvas.size, placed) {
+            (Some(canvas), Some(frame)) => (canvas.0 as f32 * canvas.1 as f32) / (frame.0 as f32 * frame.1 as f32).max(1.0),
+            _ => 1.0,
+        };
+        Placement { placed, origin: placed.map_or((0, 0), |size| canvas::origin(&settings.canvas, size)), coverage_scale }
+    }
+}
+
+/// Hand the motion `grid` drawn on frame `index` of `paths` to `sink`, as the
+/// settings ask, in the pixels of the frame placed at `origin`
+#[cfg(feature = "native")]
+fn write_motion<S: FrameSink + ?Sized>(
+    paths: &[PathBuf],
+    index: usize,
+    grid: &MotionGrid,
+    origin: (u32, u32),
+    settings: &TrailSettings,
+    sink: &S,
+    io: &IoGate,
+) -> Result<()> {
+    let source = &paths[index];
+    if settings.motion.save_json {
+        let vectors: Vec<MotionVector> = grid.vectors.iter()
+            .map(|vector| MotionVector { x: vector.x.saturating_sub(origin.0), y: vector.y.saturating_sub(origin.1), ..*vector })
+            .collect();
+        io.run(|| sink.write_motion(index, source, &vectors))?;
+    }
+    if settings.motion.export_dir.is_some() {
+        let time = nowcast::scan_time(source);
+        let before = index.checked_sub(1).and_then(|before| nowcast::scan_time(&paths[before]));
+        let interval = time.zip(before).map(|(now, before)| now - before);
+        io.run(|| sink.write_motion_grid(index, &FrameMotion { source, time, interval, grid }))?;
+    }
+    Ok(())
+}
+
+/// What a run writes once its frames are done, besides the provenance index
+#[cfg(feature = "native")]
+struct SequenceOutputs {
+    labels: Option<Vec<Vec<LabelledCell>>>,
+    origin: (u32, u32),
+    /// Frame size, for the range gain field
+    size: Option<(u32, u32)>,
+    /// Echo coverage of each frame of the sequence, where it was measured
+    coverage: Vec<Option<f32>>,
+}
+
+#[cfg(feature = "native")]
+impl SequenceOutputs {
+    /// Write the cell CSV, range gain field and coverage chart the settings
+    /// ask for, covering the frames of `paths` from `first` on, returning the
+    /// failures
+    fn write<S: FrameSink + ?Sized>(self, paths: &[PathBuf], first: usize, settings: &TrailSettings, sink: &S, io: &IoGate) -> Vec<(PathBuf, String)> {
+        let mut failures = Vec::new();
+        if let Some(mut labels) = self.labels.filter(|_| settings.tracking.save_csv) {
+            // Written in the pixels of the frames, not of the canvas
+            for labelled in labels.iter_mut().flatten() {
+                labelled.cell.centroid = canvas::to_frame(labelled.cell.centroid, self.origin);
+            }
+            if let Err(e) = io.run(|| sink.write_cells(&labels)) {
+                failures.push((PathBuf::from(CELLS_FILE_NAME), format!("{:#}", e)));
+            }
+        }
+        if let Some(size) = self.size
+            && settings.range_correction.is_active()
+            && settings.range_correction.emit_weights
+            && let Err(e) = io.run(|| sink.write_range_gain(&range::gain_field(&settings.range_correction, size)))
+        {
+            failures.push((PathBuf::from(range::RANGE_GAIN_NAME), format!("{:#}", e)));
+        }
+        if settings.coverage_plot {
+            let samples: Vec<CoverageSample> = paths[first..].iter()
+                .zip(self.coverage.into_iter().skip(first))
+                .map(|(path, coverage)| CoverageSample { time: nowcast::scan_time(path), coverage })
+                .collect();
+            if let Err(e) = io.run(|| sink.write_coverage(&samples)) {
+                failures.push((PathBuf::from(COVERAGE_CHART_NAME), format!("{:#}", e)));
+            }
+        }
+        failures
+    }
+}
+
+/// Opacity of the most recent history frame unless `settings.history_opacity`
+/// says otherwise; older frames fade towards transparent
+pub const HISTORY_MAX_ALPHA: f32 = 128.0;
+
+/// Render frame `frame_idx` of `files` with the `settings.history_length` frames
+/// before it, loading them from disk. History frames that cannot be read are
+/// left out. See [`compose_trail`] for how the frames are combined, and
+/// [`draw_overlays`] for what may be drawn over them.
+#[cfg(feature = "native")]
+pub fn composite_frame(files: &[PathBuf], frame_idx: usize, settings: &TrailSettings) -> Result<RgbaImage> {
+    render_frame(&mut FrameCache::new(files, settings, &IoGate::new(usize::MAX)), Moment::Scan(frame_idx), settings, None).map(|rendered| rendered.trail)
+}
+
+/// Write `settings.nowcast.frames` forecast frames after the last of `paths`,
+/// as [`nowcast`] describes, through the same trail pipeline: each has the real
+/// and forecast frames before it as history, and is handed to `sink` as the
+/// frame after them, named like `forecast_+05min.png` beside the last source.
+/// Forecasts are only ever history for later forecasts. Returns the failures.
+#[cfg(feature = "native")]
+fn render_forecasts<S: FrameSink + ?Sized>(paths: &[PathBuf], settings: &TrailSettings, sink: &S, io: &IoGate) -> Vec<(PathBuf, String)> {
+    let [.., before_last, last] = paths else { return Vec::new() };
+    let loaded = io.run(|| load_frame(before_last, settings)).and_then(|before| Ok((before, io.run(|| load_frame(last, settings))?)));
+    let (before, last_frame) = match loaded {
+        Ok(frames) => frames,
+        Err(e) => return vec![(last.clone(), format!("forecasting: {:#}", e))],
+    };
+    let step = nowcast::mean_motion(&motion::estimate(&before, &last_frame, &settings.motion)).unwrap_or((0.0, 0.0));
+    let interval = nowcast::median_interval(paths);
+
+    // Oldest first, ending with the last observed frame
+    let history_start = paths.len().saturating_sub(settings.history_length);
+    let reduce = |frame: RgbaImage| if settings.history_scale < 1.0 { downsample_history(&frame, settings.history_scale, settings.resize_filter) } else { frame };
+    let mut window: VecDeque<RgbaImage> = paths[history_start..paths.len() - 1].iter()
+        .filter(|path| in_history(path, settings))
+        .filter_map(|path| io.run(|| load_frame(path, settings)).ok().map(reduce))
+        .collect();
+    if settings.history_length > 0 && in_history(last, settings) {
+        window.push_back(reduce(last_frame.clone()));
+    }
+
+    let mut failures = Vec::new();
+    for lead in 1..=settings.nowcast.frames {
+        let forecast = nowcast::advect(&last_frame, (step.0 * lead as f32, step.1 * lead as f32));
+        let count = window.len();
+        let history: Vec<(&RgbaImage, f32)> = window.iter()
+            .enumerate()
+            .map(|(i, past)| (past, history_age(count - i, count)))
+            .collect();
+        let mut trail = compose_trail(&nowcast::hatch(&forecast), &history, settings);
+        nowcast::stamp(&mut trail, &nowcast::lead_time(lead, interval));
+
+        let source = last.with_file_name(nowcast::forecast_name(lead, interval));
+        let index = paths.len() + lead as usize - 1;
+        if let Err(e) = io.run(|| sink.write_frame(index, &source, trail)) {
+            sink.skip_frame(index);
+            failures.push((source, format!("{:#}", e)));
+        }
+        if settings.history_length > 0 {
+            if window.len() == settings.history_length {
+                window.pop_front();
+            }
+            window.push_back(reduce(forecast));
+        }
+    }
+    failures
+}
+
+/// Label the cells of every frame of `paths` in order with a [`CellTracker`],
+/// when the tracking settings ask for IDs to be drawn or saved. Frames that
+/// cannot be read have no cells, nor have those left once `cancelled`.
+#[cfg(feature = "native")]
+fn label_sequence<C>(paths: &[PathBuf], settings: &TrailSettings, io: &IoGate, cancelled: &C) -> Option<Vec<Vec<LabelledCell>>>
+where
+    C: Fn() -> bool + Sync,
+{
+    let tracking = &settings.tracking;
+    if !tracking.enabled || !(tracking.label_ids || tracking.save_csv) {
+        return None;
+    }
+    // Finding cells is independent per frame; only the labelling runs in order
+    let cells: Vec<Vec<tracking::Cell>> = paths.par_iter()
+        .map(|path| match cancelled() {
+            true => Vec::new(),
+            false => io.run(|| load_frame(path, settings)).map_or_else(|_| Vec::new(), |frame| tracking::find_cells(&frame, tracking)),
+        })
+        .collect();
+    let mut tracker = CellTracker::new(tracking.clone());
+    Some(cells.iter().map(|cells| tracker.label(cells)).collect())
+}
+
+/// A frame of the sequence as rendered: a scan, or one interpolated after it
+#[cfg(feature = "native")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Moment {
+    Scan(usize),
+    /// Frame `step`, from 1, of those interpolated between scans `before` and `before + 1`
+    Between { before: usize, step: u32 },
+}
+
+#[cfg(feature = "native")]
+impl Moment {
+    /// The moments drawn as history under this one, oldest first, with their
+    /// ages. With `settings.interpolation.in_history` the history window counts
+    /// interpolated frames like scans; otherwise it holds scans only, and an
+    /// interpolated frame has the history of the scan after it, aged to its
+    /// own moment, so its trail grows smoothly from one scan's to the next.
+    /// Moments before the first of the `scans` are taken from the sequence as
+    /// `settings.preroll` says, or left out.
+    fn history(self, settings: &TrailSettings, scans: usize) -> Vec<(Moment, f32)> {
+        let per_scan = settings.interpolation.frames as usize + 1;
+        let preroll = |back: usize| preroll_source(settings.preroll, back, scans);
+        if settings.interpolation.in_history {
+            let position = match self {
+                Moment::Scan(index) => index * per_scan,
+                Moment::Between { before, step } => before * per_scan + step as usize,
+            } as isize;
+            let moments: Vec<(Moment, isize)> = (position - settings.history_length as isize..position)
+                .filter_map(|at| {
+                    let (scan, step) = (at.div_euclid(per_scan as isize), at.rem_euclid(per_scan as isize) as u32);
+                    let scan = if scan < 0 { preroll(scan.unsigned_abs())? } else { scan as usize };
+                    let moment = match step {
+                        0 => Moment::Scan(scan),
+                        // Frames between scans before the sequence need the scan after theirs
+                        _ if scan + 1 >= scans => Moment::Scan(scan),
+                        step => Moment::Between { before: scan, step },
+                    };
+                    Some((moment, at))
+                })
+                .collect();
+            let count = moments.len();
+            moments.into_iter()
+                .map(|(moment, at)| (moment, history_age((position - at) as usize, count)))
+                .collect()
+        } else {
+            let (next_scan, time) = match self {
+                Moment::Scan(index) => (index, index as f32),
+                Moment::Between { before, step } => (before + 1, before as f32 + step as f32 / per_scan as f32),
+            };
+            let scans: Vec<(usize, isize)> = (next_scan as isize - settings.history_length as isize..next_scan as isize)
+                .filter_map(|at| Some((if at < 0 { preroll(at.unsigned_abs())? } else { at as usize }, at)))
+                .collect();
+            let count = scans.len();
+            scans.into_iter()
+                .map(|(index, at)| (Moment::Scan(index), (time - at as f32) / (count + 1) as f32))
+                .collect()
+        }
+    }
+}
+
+/// Which folder's frames: the one being rendered, or the one drawn over it
+#[cfg(feature = "native")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Layer {
+    Primary,
+    Secondary,
+}
+
+/// The frames of a sequence decoded or interpolated so far, so each is made once
+/// however many renders draw it
+#[cfg(feature = "native")]
+struct FrameCache<'a> {
+    files: &'a [PathBuf],
+    /// The secondary frame paired with each of `files`; empty without a secondary folder
+    secondary: &'a [Option<PathBuf>],
+    settings: &'a TrailSettings,
+    io: &'a IoGate,
+    /// Scans decoded for other frames rendered at the same time
+    shared: Option<&'a SharedFrames>,
+    frames: HashMap<(Layer, Moment), Arc<RgbaImage>>,
+    /// History frames at `settings.history_scale`, when it is below 1
+    reduced: HashMap<(Layer, Moment), RgbaImage>,
+    /// Motion from each scan to the next, for interpolating along it
+    flows: HashMap<(Layer, usize), Vec<MotionVector>>,
+}
+
+#[cfg(feature = "native")]
+impl<'a> FrameCache<'a> {
+    fn new(files: &'a [PathBuf], settings: &'a TrailSettings, io: &'a IoGate) -> Self {
+        FrameCache { files, secondary: &[], settings, io, shared: None, frames: HashMap::new(), reduced: HashMap::new(), flows: HashMap::new() }
+    }
+
+    fn with_secondary(self, secondary: &'a [Option<PathBuf>]) -> Self {
+        FrameCache { secondary, ..self }
+    }
+
+    /// Take scans from `shared`, and leave those read there, rather than reading them again
+    fn with_shared(self, shared: &'a SharedFrames) -> Self {
+        FrameCache { shared: Some(shared), ..self }
+    }
+
+    /// Read the scan `index` of `layer`, or take it from the shared frames
+    fn read(&self, layer: Layer, index: usize) -> Result<Arc<RgbaImage>> {
+        let load = || match layer {
+            Layer::Primary => self.io.run(|| load_frame(&self.files[index], self.settings)),
+            Layer::Secondary => match self.secondary.get(index).and_then(Option::as_ref) {
+                Some(path) => self.io.run(|| load_frame(path, self.settings)),
+                None => anyhow::bail!("no secondary frame is paired with {}", self.files[index].display()),
+            },
+        };
+        match self.shared {
+            Some(shared) => shared.get(layer, index, load),
+            None => load().map(Arc::new),
+        }
+    }
+
+    /// Make the frame of `layer` at `moment` if it is not made yet. Interpolated
+    /// frames follow the flow between their scans when motion vectors are on,
+    /// and cross-fade them otherwise.
+    fn load(&mut self, layer: Layer, moment: Moment) -> Result<()> {
+        if self.frames.contains_key(&(layer, moment)) {
+            return Ok(());
+        }
+        let frame = match moment {
+            Moment::Scan(index) => self.read(layer, index)?,
+            Moment::Between { before, step } => {
+                self.load(layer, Moment::Scan(before))?;
+                self.load(layer, Moment::Scan(before + 1))?;
+                let (from, to) = (&self.frames[&(layer, Moment::Scan(before))], &self.frames[&(layer, Moment::Scan(before + 1))]);
+                let t = step as f32 / (self.settings.interpolation.frames + 1) as f32;
+                Arc::new(if self.settings.motion.enabled && from.dimensions() == to.dimensions() {
+                    let vectors = self.flows.entry((layer, before))
+                        .or_insert_with(|| motion::estimate(from, to, &self.settings.motion));
+                    interpolation::along_flow(from, to, t, vectors, self.settings.motion.grid_spacing)
+                } else {
+                    interpolation::cross_fade(from, to, t)
+                })
+            }
+        };
+        self.frames.insert((layer, moment), frame);
+        Ok(())
+    }
+
+    fn get(&self, layer: Layer, moment: Moment) -> Option<&RgbaImage> {
+        self.frames.get(&(layer, moment)).map(Arc::as_ref)
+    }
+
+    /// Make the frame of `layer` at `moment` and as many of its `history`
+    /// frames as can be made; history frames that cannot be are left out.
+    /// Below full history scale the history frames are kept reduced, and the
+    /// full frames made only to reduce them are dropped.
+    fn load_trail(&mut self, layer: Layer, moment: Moment, history: &[(Moment, f32)]) -> Result<()> {
+        self.load(layer, moment)?;
+        let scale = self.settings.history_scale;
+        for &(past, _) in history {
+            if scale >= 1.0 {
+                let _ = self.load(layer, past);
+                continue;
+            }
+            if self.reduced.contains_key(&(layer, past)) {
+                continue;
+            }
+            let made: HashSet<(Layer, Moment)> = self.frames.keys().copied().collect();
+            if self.load(layer, past).is_ok() {
+                self.reduced.insert((layer, past), downsample_history(&self.frames[&(layer, past)], scale, self.settings.resize_filter));
+            }
+            self.frames.retain(|key, _| made.contains(key));
+        }
+        Ok(())
+    }
+
+    /// The frame of `layer` at `moment` and its history frames already made,
+    /// oldest first with their ages, so the newest frames are drawn on top
+    fn trail(&self, layer: Layer, moment: Moment, history: &[(Moment, f32)]) -> Option<(&RgbaImage, Vec<(&RgbaImage, f32)>)> {
+        let current = self.get(layer, moment)?;
+        let history = self.drawn(layer, history)
+            .into_iter()
+            .map(|(_, frame, age)| (frame, age))
+            .collect();
+        Some((current, history))
+    }
+
+    /// The frames of `history` already made for `layer`, as [`FrameCache::trail`] draws them
+    fn drawn(&self, layer: Layer, history: &[(Moment, f32)]) -> Vec<(Moment, &RgbaImage, f32)> {
+        let reduced = self.settings.history_scale < 1.0;
+        history.iter()
+            .filter_map(|&(past, age)| {
+                let frame = if reduced { self.reduced.get(&(layer, past))? } else { self.get(layer, past)? };
+                Some((past, frame, age))
+            })
+            .collect()
+    }
+}
+
+/// A frame as [`render_frame`] made it
+#[cfg(feature = "native")]
+struct Rendered {
+    trail: RgbaImage,
+    /// The motion grid whose vectors are drawn on the trail, if they are
+    motion: Option<MotionGrid>,
+    /// The trail's layers, when the settings ask for them
+    layers: Option<TrailLayers>,
+    /// The echo coverage of the frame, when the settings ask for a chart of it
+    echo: Option<f32>,
+    /// Pixels each history frame added to the trail, nearest first
+    history_added: Vec<u64>,
+    /// What a scan's trail was drawn from, when the settings ask for an index
+    provenance: Option<Provenance>,
+}
+
+/// [`composite_frame`] for any moment of the sequence, taking frames from
+/// `frames`, also returning the motion grid drawn on it and, for scans,
+/// its layers. `labels` are the frame's cells from [`label_sequence`], if they
+/// were labelled. Interpolated frames get no motion vectors, labels or layers
+/// of their own.
+#[cfg(feature = "native")]
+fn render_frame(
+    frames: &mut FrameCache,
+    moment: Moment,
+    settings: &TrailSettings,
+    labels: Option<&[LabelledCell]>,
+) -> Result<Rendered> {
+    let history: Vec<(Moment, f32)> = moment.history(settings, frames.files.len())
+        .into_iter()
+        .filter(|&(past, _)| !matches!(past, Moment::Scan(index) if !in_history(&frames.files[index], settings)))
+        .collect();
+    // Loaded before the history so it stays at full resolution for the motion
+    let previous = match moment {
+        Moment::Scan(index) if settings.motion.enabled && index > 0 => Some(Moment::Scan(index - 1)),
+        _ => None,
+    };
+    if let Some(previous) = previous {
+        let _ = frames.load(Layer::Primary, previous);
+    }
+    frames.load_trail(Layer::Primary, moment, &history)?;
+    // Without its secondary frame, a frame is drawn without the layer
+    if !frames.secondary.is_empty() {
+        let _ = frames.load_trail(Layer::Secondary, moment, &history);
+    }
+    let previous = previous.and_then(|previous| frames.get(Layer::Primary, previous));
+
+    let (current, trail_history) = frames.trail(Layer::Primary, moment, &history).expect("loaded above");
+    let (mut trail, history_added) = compose_trail_counted(current, &trail_history, settings);
+    if let Some((secondary, secondary_history)) = frames.trail(Layer::Secondary, moment, &history) {
+        overlay_secondary(&mut trail, secondary, &secondary_history, settings);
+    }
+    let motion = draw_overlays(&mut trail, current, &trail_history, previous, labels.unwrap_or_default(), settings);
+    let layers = (settings.layers != LayerOutput::Off && matches!(moment, Moment::Scan(_)))
+        .then(|| compose_layers(current, &trail_history, settings));
+    let echo = settings.coverage_plot.then(|| coverage::coverage(current, &settings.echo_predicate()));
+    let provenance = match moment {
+        Moment::Scan(index) if settings.emit_index.is_some() => Some(provenance(frames, index, &history, settings)),
+        _ => None,
+    };
+    Ok(Rendered { trail, motion, layers, echo, history_added, provenance })
+}
+
+/// What the trail of scan `index` is drawn from: the frames of `history`
+/// [`render_frame`] drew, weighed as [`history_weights`] weighs them
+#[cfg(feature = "native")]
+fn provenance(frames: &FrameCache, index: usize, history: &[(Moment, f32)], settings: &TrailSettings) -> Provenance {
+    let colors = TrailColors::from_settings(settings);
+    let tint = |(r, g, b): (u8, u8, u8)| match settings.shader {
+        Some(_) => "shader".to_string(),
+        None => format!("#{:02x}{:02x}{:02x}", r, g, b),
+    };
+    let drawn: Vec<(Moment, f32)> = frames.drawn(Layer::Primary, history)
+        .into_iter()
+        .map(|(past, _, age)| (past, age))
+        .collect();
+    let ages: Vec<f32> = drawn.iter().map(|&(_, age)| age).collect();
+    let history = drawn.iter()
+        .zip(history_weights(&ages, 1.0, settings))
+        .map(|(&(past, _), weight)| {
+            let (scan, step) = match past {
+                Moment::Scan(scan) => (scan, None),
+                Moment::Between { before, step } => (before, Some(step)),
+            };
+            let source = frames.files[scan].clone();
+            HistorySource {
+                time: nowcast::scan_time(&source),
+                source,
+                step,
+                frames_back: weight.frames_back,
+                age: weight.age,
+                weight: weight.weight,
+                alpha: weight.alpha,
+                tint: tint(colors.history_at(weight.age, ages.len())),
+            }
+        })
+        .collect();
+    let source = frames.files[index].clone();
+    Provenance { index, time: nowcast::scan_time(&source), source, tint: tint(colors.current), history }
+}
+
+/// Where the trail interpolated `step` frames after `source` is written: beside
+/// it, as `<name>_interp<step>.png`
+#[cfg(feature = "native")]
+pub fn interpolated_name(source: &Path, step: u32) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    source.with_file_name(format!("{}_interp{}.png", stem, step))
+}
+
+/// Draw what the settings ask for over a composited trail: the centroid tracks
+/// of `settings.tracking` through the history with the IDs of `labels`, the
+/// current frame's cells as a [`CellTracker`] labelled them, and the motion
+/// vectors of `settings.motion` from `previous`, the frame just before
+/// `current`, whose grid is returned. See [`tracking`] and [`motion`].
+pub fn draw_overlays(
+    trail: &mut RgbaImage,
+    current: &RgbaImage,
+    history: &[(&RgbaImage, f32)],
+    previous: Option<&RgbaImage>,
+    labels: &[LabelledCell],
+    settings: &TrailSettings,
+) -> Option<MotionGrid> {
+    if settings.tracking.enabled {
+        let tracks = tracking::track_cells(current, history, &settings.tracking);
+        tracking::draw_tracks(trail, &tracks, &settings.tracking);
+        if settings.tracking.label_ids {
+            tracking::draw_labels(trail, labels, &settings.tracking);
+        }
+    }
+    let previous = previous.filter(|_| settings.motion.enabled)?;
+    let grid = motion::estimate_grid(previous, current, &settings.motion);
+    motion::draw_vectors(trail, &grid.vectors, &settings.motion);
+    Some(grid)
+}
+
+/// Decode a frame from disk. ODIM_H5 radar files, with the `hdf5` feature, are
+/// drawn from the product `settings.odim` selects; other files are images.
+/// Light-background frames are keyed out with [`light::key_out`] when
+/// `settings.light_background` is on, then the mask and offset of the frame's
+/// [`sidecar`] overrides, if any, are applied, the overlays
+/// `settings.auto_mask` holds are cleared, and the echo is brightened
+/// with its range from the radar by [`range::correct`] when
+/// `settings.range_correction` is on. Then the pixels that aren't echo
+/// under `settings.echo_rule` are cleared to transparent, and the rest take
+/// their alpha from their luma with [`crate::echo::alpha_from_luma`] when
+/// `settings.alpha_source` says so. A frame of another size than
+/// `settings.frame_size` is then failed, resized or centered at it as
+/// `settings.size_mismatch` says. Last, the frame is
+/// placed on `settings.canvas`, when it has a size; a frame that doesn't fit
+/// the canvas fails.
+#[cfg(feature = "native")]
+pub fn load_frame(path: &Path, settings: &TrailSettings) -> Result<RgbaImage> {
+    let mut frame = decode_frame(path, settings)?;
+    if settings.light_background {
+        light::key_out(&mut frame);
+    }
+    let echo = settings.echo_predicate();
+    if let Some(overrides) = settings.frame_overrides.get(path) {
+        sidecar::apply(&mut frame, overrides, path, &echo)?;
+    }
+    if let Some(mask) = &settings.auto_mask {
+        mask.apply(&mut frame);
+    }
+    if settings.range_correction.is_active() {
+        range::correct(&mut frame, &settings.range_correction);
+    }
+    echo.clear_empty(&mut frame);
+    if settings.alpha_source == settings::AlphaSource::Luminance {
+        crate::echo::alpha_from_luma(&mut frame);
+    }
+    if let Some(size) = settings.frame_size
+        && frame.dimensions() != size
+    {
+        frame = match settings.size_mismatch {
+            settings::SizeMismatch::Fail => anyhow::bail!(
+                "{}: a {}x{} frame among {}x{} frames; set Other Frame Sizes to resize or pad it",
+                path.display(), frame.width(), frame.height(), size.0, size.1
+            ),
+            settings::SizeMismatch::Resize => resize_frame(&frame, size, settings.resize_filter),
+            settings::SizeMismatch::Pad => canvas::center(&frame, size),
+        };
+    }
+    canvas::check_fits(&settings.canvas, frame.dimensions()).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    Ok(canvas::place(frame, &settings.canvas))
+}
+
+/// Size of the frame at `path` as it is decoded, before it is matched to the
+/// others or placed on the canvas
+#[cfg(feature = "native")]
+pub fn decoded_size(path: &Path, settings: &TrailSettings) -> Result<(u32, u32)> {
+    Ok(decode_frame(path, settings)?.dimensions())
+}
+
+#[cfg(feature = "native")]
+#[cfg_attr(not(feature = "hdf5"), allow(unused_variables))]
+fn decode_frame(path: &Path, settings: &TrailSettings) -> Result<RgbaImage> {
+    #[cfg(feature = "hdf5")]
+    if crate::odim::is_odim_file(path) {
+        return crate::odim::read_frame(path, &settings.odim);
+    }
+    Ok(image::open(path)
+        .with_context(|| format!("loading {}", path.display()))?
+        .to_rgba8())
+}
+
+/// Whether `path` may be drawn in other frames' trails, as its sidecar says
+#[cfg(feature = "native")]
+fn in_history(path: &Path, settings: &TrailSettings) -> bool {
+    settings.frame_overrides.get(path).is_none_or(|overrides| !overrides.exclude_from_history)
+}
+
+/// Whether a run with `settings` keeps the trails an earlier run left up to date
+#[cfg(feature = "native")]
+fn resumes(settings: &TrailSettings) -> bool {
+    settings.resume && settings.layers != LayerOutput::Only && settings.emit_index.is_none()
+}
+
+/// The files other than scan `index` its trail is drawn from: its history,
+/// the scan before it for the motion, and their secondary frames
+#[cfg(feature = "native")]
+fn drawn_from<'p>(paths: &'p [PathBuf], secondary: &'p [Option<PathBuf>], index: usize, settings: &TrailSettings) -> Vec<&'p Path> {
+    let mut scans: Vec<usize> = Moment::Scan(index).history(settings, paths.len())
+        .into_iter()
+        .flat_map(|(moment, _)| match moment {
+            Moment::Scan(scan) => vec![scan],
+            Moment::Between { before, .. } => vec![before, before + 1],
+        })
+        .collect();
+    if settings.motion.enabled && index > 0 {
+        scans.push(index - 1);
+    }
+    scans.sort_unstable();
+    scans.dedup();
+    scans.retain(|&scan| scan != index);
+    let mut inputs: Vec<&Path> = scans.iter().map(|&scan| paths[scan].as_path()).collect();
+    inputs.extend(scans.into_iter().chain([index]).filter_map(|scan| secondary.get(scan).and_then(Option::as_deref)));
+    inputs
+}
+
+/// Whether the trail of scan `index` that `sink` holds from an earlier run
+/// was written after the last change to `source` and every one of `inputs`
+#[cfg(feature = "native")]
+fn up_to_date<S: FrameSink + ?Sized>(sink: &S, index: usize, source: &Path, inputs: &[&Path]) -> bool {
+    let Some(written) = sink.written_at(index, source) else { return false };
+    std::iter::once(source)
+        .chain(inputs.iter().copied())
+        .all(|input| fs::metadata(input).and_then(|metadata| metadata.modified()).is_ok_and(|modified| modified < written))
+}
+
+/// The frame of a sequence of `frames` drawn as history `back` frames, from 1,
+/// before its first frame under `preroll`, if any is
+pub fn preroll_source(preroll: Preroll, back: usize, frames: usize) -> Option<usize> {
+    if frames == 0 {
+        return None;
+    }
+    match preroll {
+        Preroll::None => None,
+        Preroll::Hold => Some(0),
+        // Reflected about the first frame, and back again off the last
+        Preroll::Mirror if frames == 1 => Some(0),
+        Preroll::Mirror => {
+            let period = 2 * (frames - 1);
+            let at = back % period;
+            Some(if at < frames { at } else { period - at })
+        }
+        Preroll::Wrap => Some((frames - back % frames) % frames),
+    }
+}
+
+/// Age fraction of the history frame `frames_back` frames before the current one,
+/// when `history_count` history frames are drawn
+pub fn history_age(frames_back: usize, history_count: usize) -> f32 {
+    frames_back as f32 / (history_count + 1) as f32
+}
+
+/// Fraction of the history opacity, `settings.history_opacity` out of 255, a
+/// history frame of age `age` is drawn at, when `history_count` history
+/// frames are drawn.
+///
+/// The frame `k` frames before the current one, of `n`, has the age
+/// `k / (n + 1)` and is drawn at `(n + 1 - k) / n`: the nearest frame at the
+/// full history opacity and the oldest at `1 / n` of it, so each of the
+/// `n` frames is a step of its own and none is invisible. For other ages the
+/// same line, `(1 - age) * (n + 1) / n`, is clamped to `0.0..=1.0`.
+///
+/// With `settings.legacy_fade` it is `1 - age`, the fade of earlier versions,
+/// in which the nearest frame never reaches the full history opacity.
+///
+/// `settings.fade_curve` then bends either, as [`FadeCurve::shape`] says,
+/// with the oldest history frame at the fade the line gives it.
+///
+/// `settings.history_weights`, when given, replace all of that: the frame `k`
+/// frames back is drawn at the `k`th weight as it is. Frames between two
+/// history frames, such as interpolated ones, get a weight between theirs.
+pub fn history_fade(age: f32, history_count: usize, settings: &TrailSettings) -> f32 {
+    let age = age.clamp(0.0, 1.0);
+    let weights = &settings.history_weights;
+    if !weights.is_empty() {
+        // Frames back, between two whole numbers for frames between scans
+        let back = age * (history_count + 1) as f32;
+        let back = if (back - back.round()).abs() < 1e-4 { back.round() } else { back };
+        let back = back.clamp(1.0, weights.len() as f32);
+        let nearer = back.floor() as usize;
+        let further = (nearer + 1).min(weights.len());
+        let weight = weights[nearer - 1] + (weights[further - 1] - weights[nearer - 1]) * back.fract();
+        return weight.clamp(0.0, 1.0);
+    }
+    let line = |age: f32| {
+        if settings.legacy_fade || history_count == 0 {
+            return 1.0 - age;
+        }
+        let count = history_count as f32;
+        ((1.0 - age) * (count + 1.0) / count).clamp(0.0, 1.0)
+    };
+    match parse_fade_curve(&settings.fade_curve).unwrap_or_default() {
+        FadeCurve::Linear => line(age),
+        curve => curve.shape(line(age), line(history_age(history_count, history_count))),
+    }
+}
+
+/// Opacity out of 255 of a history frame faded to `fade` of
+/// `settings.history_opacity`, then by `opacity`. Legacy fades are truncated,
+/// as they were, and exact ones rounded, so the nearest frame gets all of it.
+pub fn history_alpha(fade: f32, opacity: f32, settings: &TrailSettings) -> u8 {
+    let alpha = fade * settings.history_opacity as f32 * opacity;
+    if settings.legacy_fade { alpha as u8 } else { alpha.round() as u8 }
+}
+
+/// How much of its faded opacity the history frame `frames_back` frames before
+/// the current one keeps under `settings.strobe`: all of it when `frames_back`
+/// is a multiple of the strobe, `settings.strobe_dim` otherwise, and all of it
+/// for every frame when the strobe is off. Frames at 0 are not drawn.
+pub fn strobe_opacity(frames_back: usize, settings: &TrailSettings) -> f32 {
+    match settings.strobe as usize {
+        0 | 1 => 1.0,
+        every if frames_back.is_multiple_of(every) => 1.0,
+        _ => settings.strobe_dim.clamp(0.0, 1.0),
+    }
+}
+
+/// How [`compose_trail`] draws a frame of a trail's history
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistoryWeight {
+    /// Frames before the current one, from 1, counted along the history
+    pub frames_back: usize,
+    /// Age fraction, clamped to `0.0..=1.0`
+    pub age: f32,
+    /// Fraction of the history opacity the frame is drawn at: its fade or
+    /// set weight, times the strobe and the trail's opacity
+    pub weight: f32,
+    /// Opacity out of 255 the frame is drawn at; frames at 0 are not drawn
+    pub alpha: u8,
+}
+
+/// How each frame of a history of `ages`, oldest first, is drawn under a
+/// trail at `opacity`: with the fade of [`history_fade`], dimmed by
+/// [`strobe_opacity`], at the opacity [`history_alpha`] gives.
+///
+/// ```
+/// use radar_echo_trails::engine::{history_weights, TrailSettings};
+///
+/// let settings = TrailSettings { strobe: 2, strobe_dim: 0.0, ..TrailSettings::default() };
+/// let weights = history_weights(&[0.75, 0.5, 0.25], 1.0, &settings);
+/// assert_eq!(weights.iter().map(|weight| weight.frames_back).collect::<Vec<_>>(), vec![3, 2, 1]);
+/// assert_eq!(weights.iter().map(|weight| weight.alpha).collect::<Vec<_>>(), vec![0, 85, 0]);
+/// ```
+pub fn history_weights(ages: &[f32], opacity: f32, settings: &TrailSettings) -> Vec<HistoryWeight> {
+    ages.iter()
+        .enumerate()
+        .map(|(i, &age)| {
+            let frames_back = ages.len() - i;
+            let age = age.clamp(0.0, 1.0);
+            let fade = history_fade(age, ages.len(), settings);
+            let strobe = strobe_opacity(frames_back, settings);
+            let alpha = history_alpha(fade, opacity * strobe, settings);
+            HistoryWeight { frames_back, age, weight: fade * opacity * strobe, alpha }
+        })
+        .collect()
+}
+
+/// Composite a trail from decoded frames, without touching the filesystem.
+///
+/// The canvas is the size of `current`, filled with the background color.
+/// Each history frame is then drawn in the order given, oldest first, tinted
+/// with the history color, and `current` last in the current color at full
+/// opacity. See [`overlay_tinted`] for the tint and blend. A custom
+/// [`PixelShader`] in `settings.shader` replaces the tint for every frame.
+///
+/// With `settings.motion_blur`, each history frame is first smeared along its
+/// motion towards the frame after it, the next history frame or `current`, so
+/// the trail reads as streaks rather than separate copies; see
+/// [`motion::smear`]. Frames without motion to follow are drawn as they are.
+///
+/// The age fraction of a history frame sets how far it has faded. The renderer
+/// gives the frame `k` frames before the current one, of `n` history frames,
+/// the age `k / (n + 1)` ([`history_age`]), and draws it at `(n + 1 - k) / n`
+/// of `settings.history_opacity` out of 255, rounded ([`history_fade`]): the
+/// nearest frame at the full history opacity and the oldest at `1 / n` of it,
+/// so all `n` frames show as steps of their own. `settings.fade_curve` bends
+/// the steps between them.
+///
+/// With `settings.strobe`, only every so many history frames are drawn, counted
+/// back from `current` along `history`; see [`strobe_opacity`]. Those drawn
+/// keep the opacity of their own age, so the gaps between them stay even.
+///
+/// Below a `settings.history_scale` of 1, the history is drawn at that scale
+/// and scaled up under `current`, which stays sharp; see [`downsample_history`].
+///
+/// ```
+/// use image::{Rgba, RgbaImage};
+/// use radar_echo_trails::engine::{compose_trail, TrailSettings};
+///
+/// let settings = TrailSettings::default(); // black background, green current, orange history
+/// let echo = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+/// let empty = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0]));
+///
+/// // The previous frame of a one-frame history is at full history opacity: 128/255 of orange over black
+/// let trail = compose_trail(&empty, &[(&echo, 0.5)], &settings);
+/// assert_eq!(trail.get_pixel(0, 0), &Rgba([128, 63, 0, 255]));
+///
+/// // The current frame covers the history where it has an echo
+/// let trail = compose_trail(&echo, &[(&echo, 0.5)], &settings);
+/// assert_eq!(trail.get_pixel(0, 0), &Rgba([0, 255, 0, 255]));
+/// ```
+pub fn compose_trail(current: &RgbaImage, history: &[(&RgbaImage, f32)], settings: &TrailSettings) -> RgbaImage {
+    compose(current, history, settings, None)
+}
+
+/// [`compose_trail`], also counting the pixels each history frame adds to the
+/// trail: those it draws that no newer frame, the current one included, draws
+/// over. A pixel counts as drawn when the shader colors it and it is not fully
+/// transparent at the frame's opacity, so frames the strobe leaves out add
+/// nothing. The count of the frame `k` frames back is at index `k - 1`.
+///
+/// ```
+/// use image::{Rgba, RgbaImage};
+/// use radar_echo_trails::engine::{compose_trail_counted, TrailSettings};
+///
+/// let echo = RgbaImage::from_pixel(2, 1, Rgba([255, 255, 255, 255]));
+/// let mut half = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+/// half.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+///
+/// // The older frame adds only the pixel the newer one leaves empty
+/// let (_, added) = compose_trail_counted(&half, &[(&echo, 2.0 / 3.0), (&echo, 1.0 / 3.0)], &TrailSettings::default());
+/// assert_eq!(added, vec![1, 0]);
+/// ```
+pub fn compose_trail_counted(current: &RgbaImage, history: &[(&RgbaImage, f32)], settings: &TrailSettings) -> (RgbaImage, Vec<u64>) {
+    let mut owners = vec![NOT_DRAWN; current.width() as usize * current.height() as usize];
+    let trail = compose(current, history, settings, Some(&mut owners));
+    let mut added = vec![0; history.len()];
+    for &frames_back in &owners {
+        if let Some(count) = (frames_back as usize).checked_sub(1).and_then(|index| added.get_mut(index)) {
+            *count += 1;
+        }
+    }
+    (trail, added)
+}
+
+/// Owner of a pixel no frame has drawn, for [`compose_trail_counted`]
+const NOT_DRAWN: u16 = u16::MAX;
+
+/// [`compose_trail`], recording in `owners`, when given, how many frames back
+/// from `current` the frame that last drew each pixel is
+fn compose(current: &RgbaImage, history: &[(&RgbaImage, f32)], settings: &TrailSettings, owners: Option<&mut [u16]>) -> RgbaImage {
+    let tile_size = settings.tile_size;
+    // Blur and reduced history reach across pixels, so they are drawn whole
+    let local = !settings.motion_blur && settings.history_scale >= 1.0;
+    if tile_size > 0 && local && (current.width() > tile_size || current.height() > tile_size) {
+        return compose_tiled(current, history, settings, owners);
+    }
+    let colors = TrailColors::from_settings(settings);
+    let mut output = basemap::backdrop(settings, current.width(), current.height());
+
+    match &settings.shader {
+        Some(shader) => draw_trail(&mut output, current, history, &*shader.0, &*shader.0, 1.0, settings, owners),
+        None => {
+            let current_tint = current_tint(colors.current, settings.current_style);
+            let history_tint = history_tint(colors, settings.history_style, history.len());
+            draw_trail(&mut output, current, history, &current_tint, &history_tint, 1.0, settings, owners)
+        }
+    }
+
+    output
+}
+
+/// [`compose`] a tile of `settings.tile_size` at a time, in parallel where rayon
+/// is available, and stitch the tiles into the whole trail. Each pixel is drawn
+/// from the pixels under it alone, so the seams are exact. Tiles no frame has an
+/// echo in are left as background without drawing them.
+fn compose_tiled(current: &RgbaImage, history: &[(&RgbaImage, f32)], settings: &TrailSettings, owners: Option<&mut [u16]>) -> RgbaImage {
+    let (width, height) = current.dimensions();
+    let echo = settings.echo_predicate();
+    let counted = owners.is_some();
+    let render = |tile: &Tile| -> Option<(Tile, RgbaImage, Option<Vec<u16>>)> {
+        fn crop<'f>(frame: &'f RgbaImage, tile: &Tile) -> image::SubImage<&'f RgbaImage> {
+            imageops::crop_imm(frame, tile.x, tile.y, tile.width, tile.height)
+        }
+        let empty = std::iter::once(current)
+            .chain(history.iter().map(|&(frame, _)| frame))
+            .all(|frame| !crop(frame, tile).pixels().any(|(_, _, pixel)| echo.is_echo(&pixel)));
+        if empty {
+            return None;
+        }
+        let tile_current = crop(current, tile).to_image();
+        let tile_history: Vec<(RgbaImage, f32)> = history.iter().map(|&(frame, age)| (crop(frame, tile).to_image(), age)).collect();
+        let tile_history: Vec<(&RgbaImage, f32)> = tile_history.iter().map(|(frame, age)| (frame, *age)).collect();
+        // Custom shaders are told where the pixel is in the whole frame, and
+        // the background image is cut to the tile
+        let tile_settings = match (&settings.shader, &settings.background) {
+            (None, None) => Cow::Borrowed(settings),
+            (shader, background) => Cow::Owned(TrailSettings {
+                shader: shader.as_ref().map(|shader| Shader(Arc::new(Offset { shader: shader.0.clone(), x: tile.x, y: tile.y }))),
+                background: background.as_ref().map(|image| Arc::new(crop(image, tile).to_image())),
+                ..settings.clone()
+            }),
+        };
+        let mut tile_owners = counted.then(|| vec![NOT_DRAWN; tile.width as usize * tile.height as usize]);
+        let trail = compose(&tile_current, &tile_history, &tile_settings, tile_owners.as_deref_mut());
+        Some((*tile, trail, tile_owners))
+    };
+    let grid = tiles((width, height), settings.tile_size);
+    #[cfg(feature = "native")]
+    let drawn: Vec<_> = grid.par_iter().filter_map(render).collect();
+    #[cfg(not(feature = "native"))]
+    let drawn: Vec<_> = grid.iter().filter_map(render).collect();
+
+    let mut output = basemap::backdrop(settings, width, height);
+    let mut owners = owners;
+    for (tile, trail, tile_owners) in drawn {
+        output.copy_from(&trail, tile.x, tile.y).expect("tiles lie within the frame");
+        if let (Some(owners), Some(tile_owners)) = (owners.as_deref_mut(), tile_owners) {
+            for (row, tile_row) in tile_owners.chunks(tile.width as usize).enumerate() {
+                let start = (tile.y as usize + row) * width as usize + tile.x as usize;
+                owners[start..start + tile_row.len()].copy_from_slice(tile_row);
+            }
+        }
+    }
+    output
+}
+
+/// A rectangle of a frame, from [`tiles`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    /// Position in the grid of tiles, from the top left
+    pub column: u32,
+    pub row: u32,
+    /// Top left pixel in the frame
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The tiles `tile_size` pixels square covering a `width` by `height` frame,
+/// row by row; those along the right and bottom edges are cut to fit it
+///
+/// ```
+/// use radar_echo_trails::engine::tiles;
+///
+/// let grid = tiles((300, 200), 128);
+/// assert_eq!(grid.len(), 6);
+/// assert_eq!((grid[5].x, grid[5].y, grid[5].width, grid[5].height), (256, 128, 44, 72));
+/// ```
+pub fn tiles((width, height): (u32, u32), tile_size: u32) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    (0..height.div_ceil(tile_size))
+        .flat_map(|row| (0..width.div_ceil(tile_size)).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let (x, y) = (column * tile_size, row * tile_size);
+            Tile { column, row, x, y, width: tile_size.min(width - x), height: tile_size.min(height - y) }
+        })
+        .collect()
+}
+
+/// Zoom level of a web map at which `tiles` are the tiles of the map: the
+/// lowest whose `2^z` by `2^z` grid has room for them all
+pub fn tile_zoom(tiles: &[Tile]) -> u32 {
+    let across = tiles.iter().map(|tile| tile.column.max(tile.row) + 1).max().unwrap_or(1);
+    across.next_power_of_two().trailing_zeros()
+}
+
+/// Draw the trail of a secondary folder's frames over `output`, a trail
+/// [`compose_trail`] made: `current` and `history` as there, tinted with the
+/// colors of `settings.secondary` and faded by its opacity. A custom shader
+/// applies to the primary trail only.
+pub fn overlay_secondary(output: &mut RgbaImage, current: &RgbaImage, history: &[(&RgbaImage, f32)], settings: &TrailSettings) {
+    let secondary = &settings.secondary;
+    let current_tint = Tint(settings::parse_hex_color(&secondary.color).unwrap_or((0, 191, 255)));
+    let history_tint = Tint(settings::parse_hex_color(&secondary.history_color).unwrap_or((191, 0, 255)));
+    draw_trail(output, current, history, &current_tint, &history_tint, secondary.opacity.clamp(0.0, 1.0), settings, None);
+}
+
+/// Draw `history` and then `current` over `output` as [`compose_trail`]
+/// describes, with every frame's opacity scaled by `opacity`, recording in
+/// `owners` which frame drew each pixel last
+#[allow(clippy::too_many_arguments)]
+fn draw_trail<C: PixelShader + ?Sized, H: PixelShader + ?Sized>(
+    output: &mut RgbaImage,
+    current: &RgbaImage,
+    history: &[(&RgbaImage, f32)],
+    current_shader: &C,
+    history_shader: &H,
+    opacity: f32,
+    settings: &TrailSettings,
+    mut owners: Option<&mut [u16]>,
+) {
+    let echo = settings.echo_predicate();
+    let current_shader = &EchoOnly { echo: &echo, shader: current_shader };
+    let history_shader = &EchoOnly { echo: &echo, shader: history_shader };
+    let blend = blend_for(settings);
+    if settings.history_scale < 1.0 {
+        let ghosts = scaled_history(output.dimensions(), current, history, history_shader, opacity, settings, owners.as_deref_mut());
+        for (pixel, ghost) in output.pixels_mut().zip(ghosts.pixels()) {
+            *pixel = blend(*pixel, *ghost, 255);
+        }
+    } else {
+        draw_history(output, current, history, history_shader, opacity, settings, blend, owners.as_deref_mut());
+    }
+    let owners = owners.map(|owners| (owners, 0));
+    overlay_blended(output, current, current_shader, 0.0, (255.0 * opacity) as u8, blend, owners);
+}
+
+/// How the trail is blended onto its background: [`blend_over`] when the
+/// background is transparent, keeping the trail's opacity, and otherwise
+/// [`blend_pixel`]
+pub(crate) fn blend_for(settings: &TrailSettings) -> fn(Rgba<u8>, Rgba<u8>, u8) -> Rgba<u8> {
+    match settings.transparent_background {
+        true => blend_over,
+        false => blend_pixel,
+    }
+}
+
+/// Draw the `history` of `current` over `output`, oldest first, blending each
+/// pixel with `blend` and recording in `owners` which frame drew it last
+#[allow(clippy::too_many_arguments)]
+fn draw_history<S: PixelShader + ?Sized>(
+    output: &mut RgbaImage,
+    current: &RgbaImage,
+    history: &[(&RgbaImage, f32)],
+    shader: &S,
+    opacity: f32,
+    settings: &TrailSettings,
+    blend: fn(Rgba<u8>, Rgba<u8>, u8) -> Rgba<u8>,
+    mut owners: Option<&mut [u16]>,
+) {
+    let ages: Vec<f32> = history.iter().map(|&(_, age)| age).collect();
+    for (i, (&(frame, _), weight)) in history.iter().zip(history_weights(&ages, opacity, settings)).enumerate() {
+        // Nothing at 0 would show
+        if weight.alpha == 0 {
+            continue;
+        }
+        let blurred = settings.motion_blur.then(|| {
+            let next = history.get(i + 1).map_or(current, |&(next, _)| next);
+            motion::blur_towards(frame, next, &settings.motion)
+        });
+        let frame = blurred.as_ref().and_then(Option::as_ref).unwrap_or(frame);
+        let frames_back = weight.frames_back.min(NOT_DRAWN as usize - 1) as u16;
+        overlay_blended(output, frame, shader, weight.age, weight.alpha, blend, owners.as_deref_mut().map(|owners| (owners, frames_back)));
+    }
+}
+
+/// Size of a `width` by `height` frame at `scale` of its resolution, at least
+/// a pixel each way
+pub fn scaled_size((width, height): (u32, u32), scale: f32) -> (u32, u32) {
+    let scaled = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// `frame` reduced to `scale` of its resolution with `filter`, as
+/// [`resize_frame`] resizes it, to be drawn as history at `settings.history_scale`.
+///
+/// The history is drawn onto a transparent canvas of that scale and scaled up
+/// to full size before the current frame goes on top, so its ghosts are softer
+/// but cost a fraction of the time and memory. Sizes in the settings stay in
+/// full resolution pixels: for motion blur, the vector grid is scaled down with
+/// the frames.
+///
+/// ```
+/// use image::RgbaImage;
+/// use radar_echo_trails::engine::downsample_history;
+/// use radar_echo_trails::settings::ResizeFilter;
+///
+/// let reduced = downsample_history(&RgbaImage::new(800, 600), 0.25, ResizeFilter::Bilinear);
+/// assert_eq!(reduced.dimensions(), (200, 150));
+/// ```
+pub fn downsample_history(frame: &RgbaImage, scale: f32, filter: ResizeFilter) -> RgbaImage {
+    resize_frame(frame, scaled_size(frame.dimensions(), scale), filter)
+}
+
+/// `frame` resized to `width` by `height` with `filter`.
+///
+/// The colors are filtered with premultiplied alpha, so the transparent pixels
+/// around an echo don't bleed into its edges. A frame whose alpha is all either
+/// opaque or transparent, such as a mask or a frame cleared by the echo rule,
+/// has its alpha resized with the nearest filter instead, so its edges stay as
+/// hard as they were; its colors are still filtered.
+pub fn resize_frame(frame: &RgbaImage, (width, height): (u32, u32), filter: ResizeFilter) -> RgbaImage {
+    let filter_type = match filter {
+        ResizeFilter::Nearest => FilterType::Nearest,
+        ResizeFilter::Bilinear => FilterType::Triangle,
+        ResizeFilter::CatmullRom => FilterType::CatmullRom,
+        ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+    };
+    if frame.dimensions() == (width, height) {
+        return frame.clone();
+    }
+    let mut premultiplied = frame.clone();
+    for pixel in premultiplied.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in 0..3 {
+            pixel[channel] = ((pixel[channel] as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+    let mut scaled = imageops::resize(&premultiplied, width, height, filter_type);
+    for pixel in scaled.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in 0..3 {
+            if let Some(value) = (pixel[channel] as u32 * 255 + alpha / 2).checked_div(alpha) {
+                pixel[channel] = value.min(255) as u8;
+            }
+        }
+    }
+    let binary = frame.pixels().all(|pixel| pixel[3] == 0 || pixel[3] == 255);
+    if binary && filter != ResizeFilter::Nearest {
+        let nearest = imageops::resize(frame, width, height, FilterType::Nearest);
+        for (pixel, hard) in scaled.pixels_mut().zip(nearest.pixels()) {
+            *pixel = match (hard[3], pixel[3]) {
+                (0, _) => Rgba([0, 0, 0, 0]),
+                // Inside the edge but filtered from transparent pixels only
+                (_, 0) => *hard,
+                _ => Rgba([pixel[0], pixel[1], pixel[2], 255]),
+            };
+        }
+    }
+    scaled
+}
+
+/// The `history` of `current` drawn as [`draw_history`] does, onto a
+/// transparent canvas at `settings.history_scale` of `size`, then scaled up to
+/// `size`. History frames larger than the reduced canvas are reduced first;
+/// smaller ones are taken as reduced already. Each of the full size `owners`
+/// takes the owner of the nearest reduced pixel.
+#[allow(clippy::too_many_arguments)]
+fn scaled_history<S: PixelShader + ?Sized>(
+    size: (u32, u32),
+    current: &RgbaImage,
+    history: &[(&RgbaImage, f32)],
+    shader: &S,
+    opacity: f32,
+    settings: &TrailSettings,
+    owners: Option<&mut [u16]>,
+) -> RgbaImage {
+    let scale = settings.history_scale;
+    let reduced_size = scaled_size(size, scale);
+    fn reduce(frame: &RgbaImage, size: (u32, u32), scale: f32, filter: ResizeFilter) -> Cow<'_, RgbaImage> {
+        if frame.width() > size.0 || frame.height() > size.1 {
+            Cow::Owned(downsample_history(frame, scale, filter))
+        } else {
+            Cow::Borrowed(frame)
+        }
+    }
+    let reduced: Vec<(Cow<RgbaImage>, f32)> = history.iter()
+        .map(|&(frame, age)| (reduce(frame, reduced_size, scale, settings.resize_filter), age))
+        .collect();
+    let reduced: Vec<(&RgbaImage, f32)> = reduced.iter().map(|(frame, age)| (frame.as_ref(), *age)).collect();
+
+    // The current frame is only what the newest ghost is blurred towards, and
+    // the blur's grid is in full resolution pixels
+    let blur_settings;
+    let (current, settings) = if settings.motion_blur {
+        let grid_spacing = ((settings.motion.grid_spacing as f32 * scale).round() as u32).max(1);
+        let motion = settings::MotionSettings { grid_spacing, ..settings.motion.clone() };
+        blur_settings = TrailSettings { motion, ..settings.clone() };
+        (reduce(current, reduced_size, scale, settings.resize_filter), &blur_settings)
+    } else {
+        (Cow::Borrowed(current), settings)
+    };
+
+    let mut canvas = RgbaImage::new(reduced_size.0, reduced_size.1);
+    let mut reduced_owners = owners.as_ref().map(|_| vec![NOT_DRAWN; reduced_size.0 as usize * reduced_size.1 as usize]);
+    draw_history(&mut canvas, &current, &reduced, shader, opacity, settings, blend_over, reduced_owners.as_deref_mut());
+    if let (Some(owners), Some(reduced_owners)) = (owners, reduced_owners) {
+        for (index, owner) in owners.iter_mut().enumerate() {
+            let (x, y) = (index as u64 % size.0 as u64, index as u64 / size.0 as u64);
+            let (x, y) = (x * reduced_size.0 as u64 / size.0 as u64, y * reduced_size.1 as u64 / size.1 as u64);
+            *owner = reduced_owners[(y * reduced_size.0 as u64 + x) as usize];
+        }
+    }
+    resize_frame(&canvas, size, settings.resize_filter)
+}
+
+/// A trail split into layers with transparent backgrounds, for compositing
+/// elsewhere: laid over the background color in order, they make the trail
+/// [`compose_trail`] draws, give or take rounding
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrailLayers {
+    /// The faded history frames alone
+    pub history: RgbaImage,
+    /// The recolored current frame alone
+    pub current: RgbaImage,
+}
+
+/// Split the trail of `current` and its `history` into layers, drawn as in
+/// [`compose_trail`] but onto transparent canvases with [`blend_over`], so each
+/// keeps its own alpha. Overlays and a secondary folder are not part of either.
+///
+/// ```
+/// use image::{Rgba, RgbaImage};
+/// use radar_echo_trails::engine::{compose_layers, TrailSettings};
+///
+/// let echo = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+/// let empty = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0]));
+/// let layers = compose_layers(&empty, &[(&echo, 0.5)], &TrailSettings::default());
+/// assert_eq!(layers.history.get_pixel(0, 0), &Rgba([255, 127, 0, 128]));
+/// assert_eq!(layers.current.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+/// ```
+pub fn compose_layers(current: &RgbaImage, history: &[(&RgbaImage, f32)], settings: &TrailSettings) -> TrailLayers {
+    let colors = TrailColors::from_settings(settings);
+    match &settings.shader {
+        Some(shader) => draw_layers(current, history, &*shader.0, &*shader.0, settings),
+        None => {
+            let current_tint = current_tint(colors.current, settings.current_style);
+            let history_tint = history_tint(colors, settings.history_style, history.len());
+            draw_layers(current, history, &current_tint, &history_tint, settings)
+        }
+    }
+}
+
+/// The layers of [`compose_layers`], drawn with these shaders
+fn draw_layers<C: PixelShader + ?Sized, H: PixelShader + ?Sized>(
+    current: &RgbaImage,
+    history: &[(&RgbaImage, f32)],
+    current_shader: &C,
+    history_shader: &H,
+    settings: &TrailSettings,
+) -> TrailLayers {
+    let (width, height) = current.dimensions();
+    let mut layers = TrailLayers { history: RgbaImage::new(width, height), current: RgbaImage::new(width, height) };
+    let echo = settings.echo_predicate();
+    let current_shader = &EchoOnly { echo: &echo, shader: current_shader };
+    let history_shader = &EchoOnly { echo: &echo, shader: history_shader };
+    if settings.history_scale < 1.0 {
+        layers.history = scaled_history((width, height), current, history, history_shader, 1.0, settings, None);
+    } else {
+        draw_history(&mut layers.history, current, history, history_shader, 1.0, settings, blend_over, None);
+    }
+    overlay_blended(&mut layers.current, current, current_shader, 0.0, 255, blend_over, None);
+    layers
+}
+
+/// Composites a stream of frames as they arrive, keeping only the history window.
+///
+/// Each pushed frame is composited with up to `history_length` frames pushed
+/// before it, exactly as [`composite_frame`] would render the same sequence
+/// from disk, and then joins the window. Memory stays bounded by the window.
+/// Under [`Preroll::Hold`] the first frame pushed into an empty window also
+/// fills it; the frames [`Preroll::Mirror`] and [`Preroll::Wrap`] take are
+/// further down the stream, so they are handed to [`TrailCompositor::preroll`].
+/// In [`settings::TrailMode::Decay`] the history is a [`DecayBuffer`] instead,
+/// and the window holds the last frame alone, for the motion from it.
+///
+/// ```
+/// use image::{Rgba, RgbaImage};
+/// use radar_echo_trails::engine::{TrailCompositor, TrailSettings};
+///
+/// let settings = TrailSettings { history_length: 2, ..TrailSettings::default() };
+/// let mut compositor = TrailCompositor::new(settings);
+/// let frames = (0..4).map(|_| RgbaImage::from_pixel(8, 8, Rgba([200, 200, 200, 255])));
+/// for frame in frames {
+///     let trail = compositor.push(frame);
+///     assert_eq!(trail.dimensions(), (8, 8));
+/// }
+/// assert_eq!(compositor.depth(), 2);
+///
+/// // A new sequence starts without the previous one's trail
+/// compositor.reset();
+/// assert_eq!(compositor.depth(), 0);
+/// ```
+pub struct TrailCompositor {
+    settings: TrailSettings,
+    echo: EchoPredicate,
+    /// The most recent frames, oldest first
+    window: VecDeque<RgbaImage>,
+    /// IDs of the cells seen so far, when they are drawn
+    tracker: CellTracker,
+    /// The history in the decay mode
+    decay: Option<DecayBuffer>,
+}
+
+impl TrailCompositor {
+    pub fn new(settings: TrailSettings) -> Self {
+        TrailCompositor {
+            window: VecDeque::with_capacity(settings.history_length),
+            tracker: CellTracker::new(settings.tracking.clone()),
+            echo: settings.echo_predicate(),
+            decay: (settings.trail_mode == settings::TrailMode::Decay).then(|| DecayBuffer::new(&settings)),
+            settings,
+        }
+    }
+
+    pub fn settings(&self) -> &TrailSettings {
+        &self.settings
+    }
+
+    /// Composite `frame` with the frames in the window, then add it to the window.
+    /// It is masked and range corrected, its pixels that aren't echo are
+    /// cleared and it is placed on the canvas first, as [`load_frame`] does to
+    /// frames from disk; here a frame larger than the canvas is cut off.
+    pub fn push(&mut self, mut frame: RgbaImage) -> RgbaImage {
+        self.prepare(&mut frame);
+        if let Some(buffer) = &mut self.decay {
+            let mut trail = buffer.compose(&frame);
+            let labels = match &self.settings.tracking {
+                tracking if tracking.enabled && tracking.label_ids => self.tracker.push(&frame),
+                _ => Vec::new(),
+            };
+            draw_overlays(&mut trail, &frame, &[], self.window.back(), &labels, &self.settings);
+            buffer.advance(Some(&frame));
+            self.window.clear();
+            self.window.push_back(frame);
+            return trail;
+        }
+        if self.settings.preroll == Preroll::Hold && self.window.is_empty() {
+            self.window.extend(std::iter::repeat_n(&frame, self.settings.history_length).cloned());
+        }
+        let count = self.window.len();
+        let history: Vec<(&RgbaImage, f32)> = self.window.iter()
+            .enumerate()
+            .map(|(i, past)| (past, history_age(count - i, count)))
+            .collect();
+        let mut trail = compose_trail(&frame, &history, &self.settings);
+        let labels = match &self.settings.tracking {
+            tracking if tracking.enabled && tracking.label_ids => self.tracker.push(&frame),
+            _ => Vec::new(),
+        };
+        draw_overlays(&mut trail, &frame, &history, self.window.back(), &labels, &self.settings);
+
+        if self.settings.history_length > 0 {
+            if self.window.len() == self.settings.history_length {
+                self.window.pop_front();
+            }
+            self.window.push_back(frame);
+        }
+        trail
+    }
+
+    /// Frames currently in the history window; the trail is fully grown once this
+    /// reaches [`TrailCompositor::capacity`]
+    pub fn depth(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Frames the history window holds when full: the history length
+    pub fn capacity(&self) -> usize {
+        self.settings.history_length
+    }
+
+    /// Fill the window with `frames`, oldest first, as history for the frames
+    /// pushed next without compositing them: the start of the sequence in
+    /// reverse for [`Preroll::Mirror`], or its end for [`Preroll::Wrap`], as
+    /// [`preroll_source`] picks them. Only the last `history_length` are kept;
+    /// in the decay mode they are stamped on its buffer in turn.
+    pub fn preroll<I>(&mut self, frames: I)
+    where
+        I: IntoIterator<Item = RgbaImage>,
+    {
+        for mut frame in frames {
+            if self.settings.history_length == 0 {
+                break;
+            }
+            self.prepare(&mut frame);
+            if let Some(buffer) = &mut self.decay {
+                buffer.advance(Some(&frame));
+                self.window.clear();
+            } else if self.window.len() == self.settings.history_length {
+                self.window.pop_front();
+            }
+            self.window.push_back(frame);
+        }
+    }
+
+    fn prepare(&self, frame: &mut RgbaImage) {
+        if let Some(mask) = &self.settings.auto_mask {
+            mask.apply(frame);
+        }
+        if self.settings.range_correction.is_active() {
+            crate::range::correct(frame, &self.settings.range_correction);
+        }
+        self.echo.clear_empty(frame);
+        if self.settings.canvas.size.is_some() {
+            *frame = canvas::place(std::mem::take(frame), &self.settings.canvas);
+        }
+    }
+
+    /// Forget the window and the cell IDs, at a boundary between sequences
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.tracker.reset();
+        if let Some(buffer) = &mut self.decay {
+            buffer.reset();
+        }
+    }
+
+    /// Composite each frame of `frames` in turn, lazily
+    pub fn composite<I>(self, frames: I) -> Trails<I::IntoIter>
+    where
+        I: IntoIterator<Item = RgbaImage>,
+    {
+        Trails { compositor: self, frames: frames.into_iter() }
+    }
+}
+
+/// Iterator over the trail composites of a frame iterator, from [`TrailCompositor::composite`]
+pub struct Trails<I> {
+    compositor: TrailCompositor,
+    frames: I,
+}
+
+impl<I> Trails<I> {
+    pub fn compositor(&self) -> &TrailCompositor {
+        &self.compositor
+    }
+}
+
+impl<I: Iterator<Item = RgbaImage>> Iterator for Trails<I> {
+    type Item = RgbaImage;
+
+    fn next(&mut self) -> Option<RgbaImage> {
+        self.frames.next().map(|frame| self.compositor.push(frame))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.frames.size_hint()
+    }
+}
+
+/// Per-pixel logic replacing the built-in tint, for rules the color settings
+/// cannot express, such as a lookup table or a mask.
+///
+/// `shade` is called for each pixel of each frame drawn, from several threads.
+/// `age` is 0.0 for the current frame and the frame's age fraction, above 0.0
+/// and at most 1.0, for history frames (see [`compose_trail`]). The returned
+/// color is blended over the canvas with its alpha times the frame's fade
+/// opacity; `None` leaves the canvas pixel as it is. The built-in tint is the
+/// [`Tint`] shader.
+///
+/// A custom shader is called through dynamic dispatch. Measured in a release
+/// build, overlaying a 1024×1024 frame took 16-19 ms both through a
+/// `&dyn PixelShader` and with the statically dispatched built-in tint: the
+/// difference stayed within run-to-run noise of about 5%. Without a custom
+/// shader the static path is used all the same.
+///
+/// ```
+/// use std::sync::Arc;
+/// use image::{Rgba, RgbaImage};
+/// use radar_echo_trails::engine::{compose_trail, PixelShader, Shader, TrailSettings};
+///
+/// /// Echoes above a threshold in red, the rest left out
+/// struct Threshold(u8);
+///
+/// impl PixelShader for Threshold {
+///     fn shade(&self, src: Rgba<u8>, _age: f32, _x: u32, _y: u32) -> Option<Rgba<u8>> {
+///         (src[0] >= self.0).then_some(Rgba([255, 0, 0, 255]))
+///     }
+/// }
+///
+/// let settings = TrailSettings { shader: Some(Shader(Arc::new(Threshold(100)))), ..TrailSettings::default() };
+/// let frame = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([50, 50, 50, 255]) } else { Rgba([200, 200, 200, 255]) });
+/// let trail = compose_trail(&frame, &[], &settings);
+/// assert_eq!(trail.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+/// assert_eq!(trail.get_pixel(1, 0), &Rgba([255, 0, 0, 255]));
+/// ```
+pub trait PixelShader: Send + Sync {
+    fn shade(&self, src: Rgba<u8>, age: f32, x: u32, y: u32) -> Option<Rgba<u8>>;
+}
+
+/// A custom shader carried by the settings. It is set from code only, and is
+/// not saved with the settings or part of their output fingerprint.
+#[derive(Clone)]
+pub struct Shader(pub Arc<dyn PixelShader>);
+
+impl fmt::Debug for Shader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Shader(..)")
+    }
+}
+
+/// `shader` for a tile of a frame whose top left pixel is at `x`, `y`
+struct Offset {
+    shader: Arc<dyn PixelShader>,
+    x: u32,
+    y: u32,
+}
+
+impl PixelShader for Offset {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, age: f32, x: u32, y: u32) -> Option<Rgba<u8>> {
+        self.shader.shade(src, age, self.x + x, self.y + y)
+    }
+}
+
+/// `shader` for the pixels `echo` counts, leaving the rest undrawn
+pub(crate) struct EchoOnly<'a, S: PixelShader + ?Sized> {
+    pub(crate) echo: &'a EchoPredicate,
+    pub(crate) shader: &'a S,
+}
+
+impl<S: PixelShader + ?Sized> PixelShader for EchoOnly<'_, S> {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, age: f32, x: u32, y: u32) -> Option<Rgba<u8>> {
+        if !self.echo.is_echo(&src) {
+            return None;
+        }
+        self.shader.shade(src, age, x, y)
+    }
+}
+
+/// The built-in shading: each pixel's grey level scales the tint color, keeping
+/// the pixel's alpha. Fully transparent pixels are skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tint(pub (u8, u8, u8));
+
+impl PixelShader for Tint {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, _age: f32, _x: u32, _y: u32) -> Option<Rgba<u8>> {
+        if src[3] == 0 {
+            return None;
+        }
+
+        // Convert to grayscale for intensity
+        let intensity = (0.299 * src[0] as f32
+                      + 0.587 * src[1] as f32
+                      + 0.114 * src[2] as f32) / 255.0;
+
+        // Apply tint based on intensity
+        let (r, g, b) = self.0;
+        Some(Rgba([
+            (r as f32 * intensity) as u8,
+            (g as f32 * intensity) as u8,
+            (b as f32 * intensity) as u8,
+            src[3],
+        ]))
+    }
+}
+
+/// Flat shading: the tint color at full opacity wherever the pixel is not
+/// fully transparent, whatever its grey level or alpha
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Solid(pub (u8, u8, u8));
+
+impl PixelShader for Solid {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, _age: f32, _x: u32, _y: u32) -> Option<Rgba<u8>> {
+        let (r, g, b) = self.0;
+        (src[3] > 0).then_some(Rgba([r, g, b, 255]))
+    }
+}
+
+/// Each pixel as it is, for frames that carry colors of their own. Fully
+/// transparent pixels are skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Original;
+
+impl PixelShader for Original {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, _age: f32, _x: u32, _y: u32) -> Option<Rgba<u8>> {
+        (src[3] > 0).then_some(src)
+    }
+}
+
+/// The built-in shader of the current frame, in one of its styles. It is
+/// matched per pixel rather than called through a `dyn PixelShader`, so the
+/// built-in tints keep their static dispatch.
+pub(crate) enum CurrentTint {
+    Scaled(Tint),
+    Solid(Solid),
+    Original(Original),
+}
+
+impl PixelShader for CurrentTint {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, age: f32, x: u32, y: u32) -> Option<Rgba<u8>> {
+        match self {
+            CurrentTint::Scaled(tint) => tint.shade(src, age, x, y),
+            CurrentTint::Solid(solid) => solid.shade(src, age, x, y),
+            CurrentTint::Original(original) => original.shade(src, age, x, y),
+        }
+    }
+}
+
+/// The built-in shader of the current frame in `style`
+pub(crate) fn current_tint(color: (u8, u8, u8), style: CurrentStyle) -> CurrentTint {
+    match style {
+        CurrentStyle::Scaled => CurrentTint::Scaled(Tint(color)),
+        CurrentStyle::Solid => CurrentTint::Solid(Solid(color)),
+        CurrentStyle::Original => CurrentTint::Original(Original),
+    }
+}
+
+/// [`Tint`] in the color of each history frame's age along the history
+/// gradient or from the history colors, of `count` history frames; see
+/// [`TrailColors::history_at`]
+struct GradientTint {
+    colors: TrailColors,
+    count: usize,
+    /// Color of the frame each whole number of frames back, from 1
+    tints: Vec<(u8, u8, u8)>,
+}
+
+impl PixelShader for GradientTint {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, age: f32, x: u32, y: u32) -> Option<Rgba<u8>> {
+        let back = age * (self.count + 1) as f32;
+        let tint = match self.tints.get((back.round() as usize).wrapping_sub(1)) {
+            Some(&tint) if (back - back.round()).abs() < 1e-4 => tint,
+            // Frames between scans are between two ages
+            _ => self.colors.history_at(age, self.count),
+        };
+        Tint(tint).shade(src, age, x, y)
+    }
+}
+
+/// The built-in shader of the history frames, statically dispatched as
+/// [`CurrentTint`] is
+enum HistoryTint {
+    Tint(Tint),
+    Gradient(GradientTint),
+    Original(Original),
+}
+
+impl PixelShader for HistoryTint {
+    #[inline]
+    fn shade(&self, src: Rgba<u8>, age: f32, x: u32, y: u32) -> Option<Rgba<u8>> {
+        match self {
+            HistoryTint::Tint(tint) => tint.shade(src, age, x, y),
+            HistoryTint::Gradient(gradient) => gradient.shade(src, age, x, y),
+            HistoryTint::Original(original) => original.shade(src, age, x, y),
+        }
+    }
+}
+
+/// The built-in shader of `history_count` history frames in `colors`: the
+/// history color, its gradient from the newest frame to the oldest, or the
+/// color of each age, unless the frames keep their own colors
+fn history_tint(colors: TrailColors, style: HistoryStyle, history_count: usize) -> HistoryTint {
+    if style == HistoryStyle::Original {
+        return HistoryTint::Original(Original);
+    }
+    if colors.by_age.iter().all(|&color| color == colors.history) && colors.history == colors.oldest_history {
+        return HistoryTint::Tint(Tint(colors.history));
+    }
+    let tints = (1..=history_count)
+        .map(|frames_back| colors.history_at(history_age(frames_back, history_count), history_count))
+        .collect();
+    HistoryTint::Gradient(GradientTint { colors, count: history_count, tints })
+}
+
+/// Overlay a tinted version of `src` onto `dst`.
+///
+/// Each pixel's grey level scales `tint`, and the result is blended over `dst`
+/// with `alpha` times the pixel's own alpha. Fully transparent pixels are skipped.
+///
+/// ```
+/// use image::{Rgba, RgbaImage};
+/// use radar_echo_trails::engine::overlay_tinted;
+///
+/// let mut canvas = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255]));
+/// let echo = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+/// overlay_tinted(&mut canvas, &echo, (0, 255, 0), 255);
+/// assert_eq!(canvas.get_pixel(0, 0), &Rgba([0, 255, 0, 255]));
+/// ```
+pub fn overlay_tinted(dst: &mut RgbaImage, src: &RgbaImage, tint: (u8, u8, u8), alpha: u8) {
+    overlay_shaded(dst, src, &Tint(tint), 0.0, alpha);
+}
+
+/// Overlay `src` onto `dst` as `shader` colors it, for a frame of age fraction
+/// `age` drawn at opacity `alpha`
+pub fn overlay_shaded<S: PixelShader + ?Sized>(dst: &mut RgbaImage, src: &RgbaImage, shader: &S, age: f32, alpha: u8) {
+    overlay_blended(dst, src, shader, age, alpha, blend_pixel, None);
+}
+
+/// [`overlay_shaded`] with each pixel blended by `blend`. Where the pixel is
+/// drawn, not fully transparent, `owner` sets the owners slice, row by row over
+/// `dst`, to its value.
+pub(crate) fn overlay_blended<S: PixelShader + ?Sized>(
+    dst: &mut RgbaImage,
+    src: &RgbaImage,
+    shader: &S,
+    age: f32,
+    alpha: u8,
+    blend: fn(Rgba<u8>, Rgba<u8>, u8) -> Rgba<u8>,
+    mut owner: Option<(&mut [u16], u16)>,
+) {
+    let (width, height) = src.dimensions();
+
+    for y in 0..height.min(dst.height()) {
+        for x in 0..width.min(dst.width()) {
+            let Some(shaded) = shader.shade(*src.get_pixel(x, y), age, x, y) else { continue };
+            let blended = blend(*dst.get_pixel(x, y), shaded, alpha);
+            dst.put_pixel(x, y, blended);
+            if let Some((owners, frames_back)) = &mut owner
+                && shaded[3] as u32 * alpha as u32 >= 255
+            {
+                owners[(y * dst.width() + x) as usize] = *frames_back;
+            }
+        }
+    }
+}
+
+/// Blend `src` over `dst` at `alpha` times the source pixel's own alpha.
+///
+/// The result is opaque. A source that ends up fully transparent leaves `dst`
+/// as it was, alpha included.
+///
+/// ```
+/// use image::Rgba;
+/// use radar_echo_trails::engine::blend_pixel;
+///
+/// let black = Rgba([0, 0, 0, 255]);
+/// assert_eq!(blend_pixel(black, Rgba([255, 127, 0, 255]), 128), Rgba([128, 63, 0, 255]));
+/// assert_eq!(blend_pixel(black, Rgba([255, 127, 0, 0]), 255), black);
+/// ```
+#[inline]
+pub fn blend_pixel(dst: Rgba<u8>, src: Rgba<u8>, alpha: u8) -> Rgba<u8> {
+    let src_alpha = ((src[3] as u32 * alpha as u32) / 255) as u8;
+    if src_alpha == 0 {
+        return dst;
+    }
+
+    let blend_alpha = src_alpha as f32 / 255.0;
+    let inv_alpha = 1.0 - blend_alpha;
+    let new_r = (src[0] as f32 * blend_alpha + dst[0] as f32 * inv_alpha) as u8;
+    let new_g = (src[1] as f32 * blend_alpha + dst[1] as f32 * inv_alpha) as u8;
+    let new_b = (src[2] as f32 * blend_alpha + dst[2] as f32 * inv_alpha) as u8;
+    Rgba([new_r, new_g, new_b, 255])
+}
+
+/// Blend `src` over `dst` at `alpha` times the source pixel's own alpha,
+/// keeping the result's transparency: the "over" operator on straight alpha.
+///
+/// Over an opaque `dst` this matches [`blend_pixel`] up to rounding.
+///
+/// ```
+/// use image::Rgba;
+/// use radar_echo_trails::engine::blend_over;
+///
+/// let clear = Rgba([0, 0, 0, 0]);
+/// assert_eq!(blend_over(clear, Rgba([255, 127, 0, 255]), 128), Rgba([255, 127, 0, 128]));
+/// assert_eq!(blend_over(Rgba([255, 127, 0, 128]), Rgba([0, 255, 0, 255]), 255), Rgba([0, 255, 0, 255]));
+/// ```
+#[inline]
+pub fn blend_over(dst: Rgba<u8>, src: Rgba<u8>, alpha: u8) -> Rgba<u8> {
+    let src_alpha = (src[3] as f32 * alpha as f32) / (255.0 * 255.0);
+    if src_alpha <= 0.0 {
+        return dst;
+    }
+
+    let dst_alpha = dst[3] as f32 / 255.0 * (1.0 - src_alpha);
+    let out_alpha = src_alpha + dst_alpha;
+    let channel = |i: usize| ((src[i] as f32 * src_alpha + dst[i] as f32 * dst_alpha) / out_alpha).round() as u8;
+    Rgba([channel(0), channel(1), channel(2), (out_alpha * 255.0).round() as u8])
+}
@@ -0,0 +1,191 @@
+//! C interface to the streaming compositor, built with the `ffi` feature
+//!
+//! The header `include/radar_echo_trails.h` is generated from this file by
+//! cbindgen during the build. Functions return a [`RetStatus`]; on failure
+//! [`ret_last_error_message`] describes what went wrong on the calling thread.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+use image::RgbaImage;
+
+use crate::engine::{TrailColors, TrailCompositor, TrailSettings};
+
+/// Result of a call through the C interface
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetStatus {
+    Ok = 0,
+    /// A required pointer was null
+    NullPointer = 1,
+    /// A size, stride or setting was out of range
+    InvalidArgument = 2,
+    /// The engine failed unexpectedly; the handle should not be used again
+    Panic = 3,
+}
+
+/// Render settings as plain C types. Colors are red, green, blue.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RetSettings {
+    /// Frames of trail behind the current one
+    pub history_length: u32,
+    pub background: [u8; 3],
+    pub current: [u8; 3],
+    pub history: [u8; 3],
+}
+
+/// A streaming compositor, created by [`ret_compositor_new`]
+pub struct RetCompositor(TrailCompositor);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(status: RetStatus, message: impl Into<String>) -> RetStatus {
+    let message = CString::new(message.into()).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    status
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// The app's default settings
+#[unsafe(no_mangle)]
+pub extern "C" fn ret_settings_default() -> RetSettings {
+    let colors = TrailColors::from_settings(&TrailSettings::default());
+    let color = |(r, g, b): (u8, u8, u8)| [r, g, b];
+    RetSettings {
+        history_length: TrailSettings::default().history_length as u32,
+        background: color(colors.background),
+        current: color(colors.current),
+        history: color(colors.history),
+    }
+}
+
+/// Create a compositor, or return null and set the last error message.
+///
+/// # Safety
+///
+/// `settings` must be null or point to a valid `RetSettings`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ret_compositor_new(settings: *const RetSettings) -> *mut RetCompositor {
+    // SAFETY: the caller passes null or a valid pointer
+    let Some(settings) = (unsafe { settings.as_ref() }) else {
+        fail(RetStatus::NullPointer, "settings is null");
+        return std::ptr::null_mut();
+    };
+    let tuple = |[r, g, b]: [u8; 3]| (r, g, b);
+    let settings = TrailSettings {
+        history_length: settings.history_length as usize,
+        background_color: hex(tuple(settings.background)),
+        current_color: hex(tuple(settings.current)),
+        history_color: hex(tuple(settings.history)),
+        ..TrailSettings::default()
+    };
+    Box::into_raw(Box::new(RetCompositor(TrailCompositor::new(settings))))
+}
+
+/// Destroy a compositor. Null is ignored.
+///
+/// # Safety
+///
+/// `compositor` must be null or a handle from [`ret_compositor_new`] not yet destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ret_compositor_free(compositor: *mut RetCompositor) {
+    if !compositor.is_null() {
+        // SAFETY: the handle came from Box::into_raw in ret_compositor_new
+        drop(unsafe { Box::from_raw(compositor) });
+    }
+}
+
+/// Composite an RGBA frame with the frames pushed before it, writing the
+/// trail into `out`, which has the same width and height. Rows are `stride`
+/// and `out_stride` bytes apart, at least `width * 4`.
+///
+/// # Safety
+///
+/// `compositor` must be a live handle. `pixels` must point to `height` rows of
+/// `stride` bytes and `out` to `height` rows of `out_stride` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ret_compositor_push(
+    compositor: *mut RetCompositor,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    stride: usize,
+    out: *mut u8,
+    out_stride: usize,
+) -> RetStatus {
+    if compositor.is_null() || pixels.is_null() || out.is_null() {
+        return fail(RetStatus::NullPointer, "compositor, pixels and out must not be null");
+    }
+    let row_bytes = width as usize * 4;
+    if width == 0 || height == 0 || stride < row_bytes || out_stride < row_bytes {
+        return fail(
+            RetStatus::InvalidArgument,
+            format!("a {}x{} frame needs strides of at least {} bytes", width, height, row_bytes),
+        );
+    }
+    let buffer_len = |stride: usize| stride.checked_mul(height as usize - 1)?.checked_add(row_bytes);
+    let (Some(input_len), Some(output_len)) = (buffer_len(stride), buffer_len(out_stride)) else {
+        return fail(RetStatus::InvalidArgument, "frame size overflows");
+    };
+
+    // SAFETY: the caller guarantees `height` rows of `stride` bytes
+    let input = unsafe { std::slice::from_raw_parts(pixels, input_len) };
+    let mut frame = Vec::with_capacity(row_bytes * height as usize);
+    for row in input.chunks(stride).take(height as usize) {
+        frame.extend_from_slice(&row[..row_bytes]);
+    }
+    let Some(frame) = RgbaImage::from_raw(width, height, frame) else {
+        return fail(RetStatus::InvalidArgument, "frame buffer is too small");
+    };
+
+    // SAFETY: the caller passes a live handle
+    let compositor = unsafe { &mut *compositor };
+    let Ok(trail) = panic::catch_unwind(AssertUnwindSafe(|| compositor.0.push(frame))) else {
+        return fail(RetStatus::Panic, "compositing the frame panicked");
+    };
+
+    // SAFETY: the caller guarantees `height` writable rows of `out_stride` bytes
+    let output = unsafe { std::slice::from_raw_parts_mut(out, output_len) };
+    for (row, trail_row) in output.chunks_mut(out_stride).zip(trail.chunks(row_bytes)) {
+        row[..row_bytes].copy_from_slice(trail_row);
+    }
+    RetStatus::Ok
+}
+
+/// Frames currently held in the compositor's history window, or 0 for null
+///
+/// # Safety
+///
+/// `compositor` must be null or a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ret_compositor_depth(compositor: *const RetCompositor) -> u32 {
+    // SAFETY: the caller passes null or a live handle
+    unsafe { compositor.as_ref() }.map_or(0, |compositor| compositor.0.depth() as u32)
+}
+
+/// Forget the history window, at a boundary between sequences
+///
+/// # Safety
+///
+/// `compositor` must be null or a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ret_compositor_reset(compositor: *mut RetCompositor) {
+    // SAFETY: the caller passes null or a live handle
+    if let Some(compositor) = unsafe { compositor.as_mut() } {
+        compositor.0.reset();
+    }
+}
+
+/// The message of the last failure on this thread, or null if there was none.
+/// The string stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn ret_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
@@ -0,0 +1,154 @@
+//! Georeferencing carried from GeoTIFF frames to their trails
+//!
+//! A GeoTIFF places its raster on the map with the model tags (pixel scale and
+//! tie points, or a full transformation matrix) and names its coordinate
+//! reference system in the GeoKey directory. The pixels are decoded like any
+//! other frame; the tags are read here and written unchanged into the trail,
+//! so the output drops into GIS software in the same place as its input.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use rayon::prelude::*;
+use tiff::decoder::Decoder;
+use tiff::encoder::{colortype, TiffEncoder};
+use tiff::tags::Tag;
+
+/// Extensions of TIFF files, which may carry a georeference
+pub const TIFF_EXTENSIONS: &[&str] = &["tif", "tiff"];
+
+pub fn is_tiff_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| TIFF_EXTENSIONS.iter().any(|tiff| tiff.eq_ignore_ascii_case(ext)))
+}
+
+/// Where a raster lies on the map, as stored in its GeoTIFF tags
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeoReference {
+    /// Size of the raster the tags describe
+    pub width: u32,
+    pub height: u32,
+    /// ModelPixelScaleTag: the size of a pixel in model units, x, y and z
+    pub pixel_scale: Option<Vec<f64>>,
+    /// ModelTiepointTag: raster (i, j, k) and model (x, y, z) coordinates, six per tie point
+    pub tiepoints: Option<Vec<f64>>,
+    /// ModelTransformationTag: the 4×4 raster-to-model matrix, row by row
+    pub transformation: Option<Vec<f64>>,
+    /// GeoKeyDirectoryTag, which names the coordinate reference system
+    pub geo_keys: Vec<u16>,
+    /// GeoDoubleParamsTag and GeoAsciiParamsTag, holding values the GeoKeys point into
+    pub double_params: Option<Vec<f64>>,
+    pub ascii_params: Option<String>,
+}
+
+impl GeoReference {
+    /// The georeference of the `width` by `height` window whose top-left pixel
+    /// is at (`x`, `y`) in this raster: tie points move by the offset, and the
+    /// transformation's translation by the model distance it spans.
+    pub fn cropped(&self, x: u32, y: u32, width: u32, height: u32) -> GeoReference {
+        let (dx, dy) = (x as f64, y as f64);
+        let tiepoints = self.tiepoints.as_ref().map(|points| {
+            let mut points = points.clone();
+            for point in points.chunks_exact_mut(6) {
+                point[0] -= dx;
+                point[1] -= dy;
+            }
+            points
+        });
+        let transformation = self.transformation.as_ref().map(|matrix| {
+            let mut matrix = matrix.clone();
+            if matrix.len() == 16 {
+                matrix[3] += matrix[0] * dx + matrix[1] * dy;
+                matrix[7] += matrix[4] * dx + matrix[5] * dy;
+            }
+            matrix
+        });
+        GeoReference { width, height, tiepoints, transformation, ..self.clone() }
+    }
+}
+
+/// Read the georeference of a TIFF file; `None` when it has no model tags
+pub fn read_georeference(path: &Path) -> Result<Option<GeoReference>> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut decoder = Decoder::new(BufReader::new(file)).with_context(|| format!("reading {}", path.display()))?;
+    let (width, height) = decoder.dimensions()?;
+
+    let pixel_scale = decoder.find_tag(Tag::ModelPixelScaleTag)?.map(|v| v.into_f64_vec()).transpose()?;
+    let tiepoints = decoder.find_tag(Tag::ModelTiepointTag)?.map(|v| v.into_f64_vec()).transpose()?;
+    let transformation = decoder.find_tag(Tag::ModelTransformationTag)?.map(|v| v.into_f64_vec()).transpose()?;
+    if tiepoints.is_none() && transformation.is_none() {
+        return Ok(None);
+    }
+    let geo_keys = decoder.find_tag(Tag::GeoKeyDirectoryTag)?.map(|v| v.into_u16_vec()).transpose()?;
+    let double_params = decoder.find_tag(Tag::GeoDoubleParamsTag)?.map(|v| v.into_f64_vec()).transpose()?;
+    let ascii_params = decoder.find_tag(Tag::GeoAsciiParamsTag)?.map(|v| v.into_string()).transpose()?;
+
+    Ok(Some(GeoReference {
+        width,
+        height,
+        pixel_scale,
+        tiepoints,
+        transformation,
+        geo_keys: geo_keys.unwrap_or_default(),
+        double_params,
+        ascii_params,
+    }))
+}
+
+/// Write `frame` to `path` as an RGBA GeoTIFF carrying `geo`'s tags
+pub fn save(path: &Path, frame: &RgbaImage, geo: &GeoReference) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut encoder = TiffEncoder::new(BufWriter::new(file))?;
+    let (width, height) = frame.dimensions();
+    let mut image = encoder.new_image::<colortype::RGBA8>(width, height)?;
+
+    let tags = image.encoder();
+    if let Some(scale) = &geo.pixel_scale {
+        tags.write_tag(Tag::ModelPixelScaleTag, scale.as_slice())?;
+    }
+    if let Some(tiepoints) = &geo.tiepoints {
+        tags.write_tag(Tag::ModelTiepointTag, tiepoints.as_slice())?;
+    }
+    if let Some(matrix) = &geo.transformation {
+        tags.write_tag(Tag::ModelTransformationTag, matrix.as_slice())?;
+    }
+    if !geo.geo_keys.is_empty() {
+        tags.write_tag(Tag::GeoKeyDirectoryTag, geo.geo_keys.as_slice())?;
+    }
+    if let Some(params) = &geo.double_params {
+        tags.write_tag(Tag::GeoDoubleParamsTag, params.as_slice())?;
+    }
+    if let Some(params) = &geo.ascii_params {
+        tags.write_tag(Tag::GeoAsciiParamsTag, params.as_str())?;
+    }
+    image.write_data(frame.as_raw())?;
+    Ok(())
+}
+
+/// How many of a folder's frames are georeferenced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Georeferencing {
+    /// None of them; the folder is rendered as it always was
+    None,
+    /// Every frame, so the trails can be georeferenced too
+    All,
+    /// Some of them, which leaves no single kind of output that suits them all
+    Mixed { georeferenced: usize, plain: usize },
+}
+
+/// Check which of `files` carry a georeference. TIFFs whose tags cannot be
+/// read count as plain.
+pub fn survey(files: &[PathBuf]) -> Georeferencing {
+    let georeferenced = files.par_iter()
+        .filter(|path| is_tiff_file(path) && matches!(read_georeference(path), Ok(Some(_))))
+        .count();
+    match (georeferenced, files.len() - georeferenced) {
+        (0, _) => Georeferencing::None,
+        (_, 0) => Georeferencing::All,
+        (georeferenced, plain) => Georeferencing::Mixed { georeferenced, plain },
+    }
+}
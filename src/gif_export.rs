@@ -0,0 +1,381 @@
+//! Animated GIF of a folder's trails, encoded as they are rendered
+//!
+//! A [`GifSequence`] takes the trails in order from a
+//! [`SequenceWriter`](crate::sequence_writer::SequenceWriter). Frames are held
+//! as [`animation::frame_durations`] gives, the same timing as every animated
+//! output, and the GIF is renamed into place once the last frame is written.
+//!
+//! Every frame is drawn in one global palette of at most
+//! [`GifOptions::colors`] colors, so the background and echo keep the same
+//! color from frame to frame and the frames compress alike. A [`Palette`]
+//! worked out from the settings is used when it fits. Otherwise the palette
+//! holds the colors of the first frames when there are few enough of them, or
+//! a NeuQuant reduction of them when there are more. Colors the palette lacks
+//! take the nearest entry, or are dithered as [`GifOptions::dither`] says;
+//! colors it holds are never dithered.
+//!
+//! Trails on an opaque background are written as the rectangle that changed
+//! since the frame before, over which the pixels that kept their color are
+//! transparent, and each frame is kept in place under the next. Trails on a
+//! transparent background can't be built up that way, since a pixel can't
+//! turn transparent again under a later frame, so each is written whole and
+//! cleared before the next; pixels less than half opaque are left transparent.
+//!
+//! [`animation::frame_durations`]: crate::animation::frame_durations
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use color_quant::NeuQuant;
+use gif::{DisposalMethod, Encoder, Repeat};
+use image::RgbaImage;
+
+use crate::engine::TrailSettings;
+use crate::palette::Palette;
+use crate::sequence_writer::SequenceEncoder;
+use crate::settings::{GifDither, MAX_GIF_COLORS};
+
+/// Widest and tallest frame a GIF holds
+pub const MAX_GIF_SIDE: u32 = u16::MAX as u32;
+
+/// Most frames held back to work the palette out from
+const PALETTE_SAMPLE_FRAMES: usize = 8;
+
+/// Most pixels of the frames held back to work the palette out from; large
+/// frames stop the sample early to bound the memory it takes
+const PALETTE_SAMPLE_PIXELS: u64 = 16 * 1024 * 1024;
+
+/// Pixels NeuQuant learns from, one in this many, from 1, the best and
+/// slowest, to 30
+const NEUQUANT_SAMPLING: i32 = 10;
+
+/// The 4 by 4 Bayer matrix of ordered dithering
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// How an animated GIF is encoded
+#[derive(Clone, Debug)]
+pub struct GifOptions {
+    /// Times the GIF plays, or 0 to loop forever
+    pub loops: u32,
+    /// The colors the trails are drawn in, when the settings foretell them
+    pub palette: Option<Palette>,
+    /// Most colors in the palette, transparency included, from 2 to 256
+    pub colors: usize,
+    pub dither: GifDither,
+    /// The trails are on a transparent background
+    pub transparent: bool,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions { loops: 0, palette: None, colors: MAX_GIF_COLORS, dither: GifDither::None, transparent: false }
+    }
+}
+
+impl GifOptions {
+    /// The options `settings` ask for
+    pub fn for_settings(settings: &TrailSettings) -> GifOptions {
+        GifOptions {
+            loops: settings.animation.loops,
+            // Trails whose colors can't be foretold get a palette of their own
+            palette: Palette::for_settings(settings).ok(),
+            colors: settings.gif_colors.clamp(2, MAX_GIF_COLORS),
+            dither: settings.gif_dither,
+            transparent: settings.transparent_background,
+        }
+    }
+}
+
+/// Encodes the trails of a sequence into an animated GIF
+pub struct GifSequence {
+    path: PathBuf,
+    temp_path: PathBuf,
+    durations: Vec<u32>,
+    options: GifOptions,
+    /// The file until the palette is known and the encoder can start
+    file: Option<BufWriter<File>>,
+    encoder: Option<Encoder<BufWriter<File>>>,
+    /// Frames held back, at their positions, to work the palette out from
+    sample: Vec<(usize, RgbaImage)>,
+    colors: Vec<[u8; 3]>,
+    /// The palette index of each color met so far
+    nearest: HashMap<[u8; 3], u8>,
+    transparent_index: Option<u8>,
+    size: (u32, u32),
+    /// Palette indices of the picture the last frame left, for the next to
+    /// write only what changed
+    shown: Option<Vec<u8>>,
+}
+
+impl GifSequence {
+    /// Start a GIF at `path`, the frame at position `i` held for
+    /// `durations[i]` milliseconds, or as long as the last of them past their
+    /// end, and encoded as `options` say
+    pub fn create(path: &Path, durations: Vec<u32>, options: GifOptions) -> Result<GifSequence> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let temp_path = path.with_file_name(format!(".{}.partial", path.file_name().unwrap_or_default().to_string_lossy()));
+        let file = File::create(&temp_path).with_context(|| format!("saving {}", path.display()))?;
+        Ok(GifSequence {
+            path: path.to_path_buf(),
+            temp_path,
+            durations,
+            options,
+            file: Some(BufWriter::new(file)),
+            encoder: None,
+            sample: Vec::new(),
+            colors: Vec::new(),
+            nearest: HashMap::new(),
+            transparent_index: None,
+            size: (0, 0),
+            shown: None,
+        })
+    }
+
+    /// Most colors the palette holds besides the transparent one
+    fn visible_colors(&self) -> usize {
+        match self.options.transparent {
+            true => self.options.colors.min(MAX_GIF_COLORS) - 1,
+            false => self.options.colors.min(MAX_GIF_COLORS),
+        }
+    }
+
+    /// The palette from the settings when it fits, and otherwise from the
+    /// frames held back
+    fn choose_palette(&self) -> Vec<[u8; 3]> {
+        let room = self.visible_colors();
+        if let Some(palette) = &self.options.palette
+            && palette.colors().len() <= room
+        {
+            return palette.colors().to_vec();
+        }
+        let opaque = |pixel: &&image::Rgba<u8>| !self.options.transparent || pixel[3] >= 128;
+        let mut seen: Vec<[u8; 3]> = Vec::new();
+        let mut lookup = std::collections::HashSet::new();
+        'frames: for (_, frame) in &self.sample {
+            for pixel in frame.pixels().filter(opaque) {
+                let color = [pixel[0], pixel[1], pixel[2]];
+                if lookup.insert(color) {
+                    seen.push(color);
+                    if seen.len() > room {
+                        break 'frames;
+                    }
+                }
+            }
+        }
+        if seen.len() <= room {
+            return seen;
+        }
+        let pixels: Vec<u8> = self.sample.iter()
+            .flat_map(|(_, frame)| frame.pixels().filter(opaque).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]))
+            .collect();
+        NeuQuant::new(NEUQUANT_SAMPLING, room, &pixels)
+            .color_map_rgb()
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect()
+    }
+
+    /// Settle the palette, start the encoder and encode the frames held back
+    fn start(&mut self) -> Result<()> {
+        let Some((_, first)) = self.sample.first() else { return Ok(()) };
+        self.size = first.dimensions();
+        self.colors = self.choose_palette();
+        if self.colors.is_empty() {
+            // Frames with nothing opaque in them
+            self.colors.push([0, 0, 0]);
+        }
+        self.nearest = self.colors.iter().enumerate().map(|(index, &color)| (color, index as u8)).collect();
+        // Transparent trails take one of the colors for the background
+        if self.options.transparent {
+            self.transparent_index = Some(self.colors.len() as u8);
+        }
+        let mut table: Vec<u8> = self.colors.concat();
+        if self.transparent_index.is_some() {
+            table.extend([0, 0, 0]);
+        }
+        let file = self.file.take().expect("the encoder starts once");
+        let mut encoder = Encoder::new(file, self.size.0 as u16, self.size.1 as u16, &table)?;
+        encoder.set_repeat(match self.options.loops {
+            0 => Repeat::Infinite,
+            plays => Repeat::Finite(plays.saturating_sub(1).min(u16::MAX as u32) as u16),
+        })?;
+        self.encoder = Some(encoder);
+        for (position, frame) in std::mem::take(&mut self.sample) {
+            self.encode(position, &frame)?;
+        }
+        Ok(())
+    }
+
+    /// The palette entry nearest `color`
+    fn index_of(&mut self, color: [u8; 3]) -> u8 {
+        let colors = &self.colors;
+        *self.nearest.entry(color).or_insert_with(|| {
+            let distance = |entry: &[u8; 3]| -> u32 {
+                (0..3).map(|channel| (entry[channel] as i32 - color[channel] as i32).pow(2) as u32).sum()
+            };
+            let (index, _) = colors.iter().enumerate().min_by_key(|(_, entry)| distance(entry)).expect("a palette holds a color");
+            index as u8
+        })
+    }
+
+    /// The palette index of each pixel of `frame`, row by row, dithered as the
+    /// options say
+    fn indices(&mut self, frame: &RgbaImage) -> Vec<u8> {
+        let (width, height) = frame.dimensions();
+        let exact: HashMap<[u8; 3], u8> = self.colors.iter().enumerate().map(|(index, &color)| (color, index as u8)).collect();
+        let spread = 255.0 / (self.colors.len() as f32).cbrt();
+        // Error carried to this row and the next, with a column of margin either side
+        let mut errors = [vec![[0f32; 3]; width as usize + 2], vec![[0f32; 3]; width as usize + 2]];
+        let mut indices = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = frame.get_pixel(x, y);
+                if self.options.transparent && pixel[3] < 128 {
+                    indices.push(self.transparent_index.unwrap_or(0));
+                    continue;
+                }
+                let color = [pixel[0], pixel[1], pixel[2]];
+                if let Some(&index) = exact.get(&color) {
+                    indices.push(index);
+                    continue;
+                }
+                let wanted: [f32; 3] = match self.options.dither {
+                    GifDither::None => color.map(f32::from),
+                    GifDither::Ordered => {
+                        let offset = ((BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0 - 0.5) * spread;
+                        color.map(|channel| channel as f32 + offset)
+                    }
+                    GifDither::FloydSteinberg => {
+                        let carried = errors[0][x as usize + 1];
+                        [0, 1, 2].map(|channel| color[channel] as f32 + carried[channel])
+                    }
+                };
+                let index = self.index_of(wanted.map(|channel| channel.round().clamp(0.0, 255.0) as u8));
+                if self.options.dither == GifDither::FloydSteinberg {
+                    let chosen = self.colors[index as usize];
+                    let error = [0, 1, 2].map(|channel| wanted[channel] - chosen[channel] as f32);
+                    let column = x as usize + 1;
+                    for (row, dx, weight) in [(0, 1, 7.0), (1, -1, 3.0), (1, 0, 5.0), (1, 1, 1.0)] {
+                        let at = &mut errors[row][(column as isize + dx) as usize];
+                        for channel in 0..3 {
+                            at[channel] += error[channel] * weight / 16.0;
+                        }
+                    }
+                }
+                indices.push(index);
+            }
+            errors.swap(0, 1);
+            errors[1].fill([0.0; 3]);
+        }
+        indices
+    }
+
+    /// Write `frame` at `position` in the sequence
+    fn encode(&mut self, position: usize, frame: &RgbaImage) -> Result<()> {
+        if frame.dimensions() != self.size {
+            anyhow::bail!("a {}x{} frame among {}x{} frames", frame.width(), frame.height(), self.size.0, self.size.1);
+        }
+        let ms = self.durations.get(position).or(self.durations.last()).copied().unwrap_or(100);
+        let indices = self.indices(frame);
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+        let mut gif_frame = gif::Frame {
+            delay: ms.div_ceil(10).min(u16::MAX as u32) as u16,
+            transparent: self.transparent_index,
+            width: width as u16,
+            height: height as u16,
+            ..gif::Frame::default()
+        };
+        if self.options.transparent {
+            gif_frame.dispose = DisposalMethod::Background;
+            gif_frame.buffer = indices.into();
+        } else {
+            gif_frame.dispose = DisposalMethod::Keep;
+            match &self.shown {
+                None => gif_frame.buffer = indices.clone().into(),
+                Some(shown) => {
+                    let changed = changed_rectangle(shown, &indices, width);
+                    // A frame the same as the last still holds its time, as one pixel
+                    let (left, top, right, bottom) = changed.unwrap_or((0, 0, 1, 1));
+                    let mut buffer = Vec::with_capacity((right - left) * (bottom - top));
+                    for y in top..bottom {
+                        for x in left..right {
+                            let at = y * width + x;
+                            buffer.push(indices[at]);
+                        }
+                    }
+                    gif_frame.left = left as u16;
+                    gif_frame.top = top as u16;
+                    gif_frame.width = (right - left) as u16;
+                    gif_frame.height = (bottom - top) as u16;
+                    gif_frame.buffer = buffer.into();
+                }
+            }
+            self.shown = Some(indices);
+        }
+        self.encoder.as_mut().expect("the encoder has started").write_frame(&gif_frame)?;
+        Ok(())
+    }
+
+    /// Write the frames held back and the trailer
+    fn complete(&mut self) -> Result<()> {
+        self.start()?;
+        let file = match self.encoder.take() {
+            Some(encoder) => encoder.into_inner()?,
+            None => self.file.take().expect("the file is kept until the encoder starts"),
+        };
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(())
+    }
+}
+
+/// The left, top, right and bottom edges, exclusive of the last two, of the
+/// pixels that differ between `before` and `after`, frames `width` wide;
+/// `None` when none do
+fn changed_rectangle(before: &[u8], after: &[u8], width: usize) -> Option<(usize, usize, usize, usize)> {
+    let mut rectangle: Option<(usize, usize, usize, usize)> = None;
+    for (at, _) in before.iter().zip(after).enumerate().filter(|(_, (a, b))| a != b) {
+        let (x, y) = (at % width, at / width);
+        rectangle = Some(match rectangle {
+            None => (x, y, x + 1, y + 1),
+            Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1)),
+        });
+    }
+    rectangle
+}
+
+impl SequenceEncoder for GifSequence {
+    fn add(&mut self, position: usize, frame: RgbaImage) -> Result<()> {
+        let (width, height) = frame.dimensions();
+        if width > MAX_GIF_SIDE || height > MAX_GIF_SIDE {
+            anyhow::bail!("a {}x{} frame is larger than a GIF holds", width, height);
+        }
+        if self.encoder.is_some() {
+            return self.encode(position, &frame);
+        }
+        self.sample.push((position, frame));
+        let fits = self.options.palette.as_ref().is_some_and(|palette| palette.colors().len() <= self.visible_colors());
+        let pixels: u64 = self.sample.iter().map(|(_, frame)| frame.width() as u64 * frame.height() as u64).sum();
+        if fits || self.sample.len() >= PALETTE_SAMPLE_FRAMES || pixels >= PALETTE_SAMPLE_PIXELS {
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, encoded: Result<()>) -> Result<()> {
+        let written = encoded.and_then(|()| self.complete());
+        let GifSequence { path, temp_path, encoder, file, .. } = *self;
+        drop((encoder, file));
+        match written {
+            Ok(()) => fs::rename(&temp_path, &path).with_context(|| format!("saving {}", path.display())),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(e).with_context(|| format!("saving {}", path.display()))
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Frames synthesised between two scans, to smooth animated trails
+//!
+//! A frame a fraction `t` of the way from one scan to the next is either a
+//! cross-fade of the two, or, when motion vectors are estimated, each scan
+//! moved along the flow towards that moment and then cross-faded, so echoes
+//! travel instead of dissolving, along the [`FlowField`] of the vectors.
+
+use image::{Rgba, RgbaImage};
+
+use crate::motion::{FlowField, MotionVector};
+
+/// The pixel of `frame` at the fractional position (`x`, `y`), sampled
+/// bilinearly with the colors weighted by alpha, so transparent neighbours do
+/// not darken it. Outside the frame is transparent.
+pub fn sample(frame: &RgbaImage, x: f32, y: f32) -> Rgba<u8> {
+    let (width, height) = frame.dimensions();
+    let at = |x: i64, y: i64| -> [f32; 4] {
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            return [0.0; 4];
+        }
+        frame.get_pixel(x as u32, y as u32).0.map(f32::from)
+    };
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let corners = [
+        (at(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (at(x0 + 1, y0), fx * (1.0 - fy)),
+        (at(x0, y0 + 1), (1.0 - fx) * fy),
+        (at(x0 + 1, y0 + 1), fx * fy),
+    ];
+    mix(&corners)
+}
+
+/// Alpha-weighted mix of pixels given as float channels with their weights
+fn mix(pixels: &[([f32; 4], f32)]) -> Rgba<u8> {
+    let alpha: f32 = pixels.iter().map(|(pixel, weight)| pixel[3] * weight).sum();
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |i: usize| {
+        let sum: f32 = pixels.iter().map(|(pixel, weight)| pixel[i] * pixel[3] * weight).sum();
+        (sum / alpha).round() as u8
+    };
+    Rgba([channel(0), channel(1), channel(2), alpha.round() as u8])
+}
+
+/// `before` faded into `after`, a fraction `t` of the way
+pub fn cross_fade(before: &RgbaImage, after: &RgbaImage, t: f32) -> RgbaImage {
+    RgbaImage::from_fn(before.width(), before.height(), |x, y| {
+        let after = after.get_pixel_checked(x, y).map_or([0.0; 4], |pixel| pixel.0.map(f32::from));
+        mix(&[(before.get_pixel(x, y).0.map(f32::from), 1.0 - t), (after, t)])
+    })
+}
+
+/// The frame a fraction `t` of the way from `before` to `after`, each moved
+/// along the flow of `vectors`, estimated between them on a grid of `spacing`
+/// pixels, towards that moment and then cross-faded
+pub fn along_flow(before: &RgbaImage, after: &RgbaImage, t: f32, vectors: &[MotionVector], spacing: u32) -> RgbaImage {
+    let flow = FlowField::new(vectors, before.width(), before.height(), spacing);
+    RgbaImage::from_fn(before.width(), before.height(), |x, y| {
+        let (dx, dy) = flow.at(x, y);
+        let (fx, fy) = (x as f32, y as f32);
+        // Where the echo here was at the scan before, and where it will be at the scan after
+        let from = sample(before, fx - t * dx, fy - t * dy).0.map(f32::from);
+        let to = sample(after, fx + (1.0 - t) * dx, fy + (1.0 - t) * dy).0.map(f32::from);
+        mix(&[(from, 1.0 - t), (to, t)])
+    })
+}
@@ -0,0 +1,94 @@
+//! RadarEchoTrails - motion trails for radar image sequences
+//!
+//! The trail engine behind the RadarEchoTrails app, for use from other Rust
+//! programs. [`engine::render_sequence`] renders a sequence of frames with the
+//! fading history of the frames before each one, configured by
+//! [`engine::TrailSettings`]:
+//!
+//! ```no_run
+//! # #[cfg(feature = "native")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::ops::ControlFlow;
+//! use radar_echo_trails::engine::{self, DirectorySink, TrailSettings};
+//! use radar_echo_trails::queue::{self, ScanOptions};
+//!
+//! let folder = std::path::Path::new("radar/2024-06-01");
+//! let scan = queue::scan_folder(folder, &ScanOptions::default())?;
+//! let settings = TrailSettings::default();
+//! let sink = DirectorySink::new(radar_echo_trails::processing::output_dir_for(folder, &settings), folder);
+//! let summary = engine::render_sequence(&scan.files, &settings, &sink, |_| ControlFlow::Continue(()));
+//! println!("{} of {} frames rendered", summary.frames_completed, summary.frames_total);
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "native"))]
+//! # fn main() {}
+//! ```
+//!
+//! [`Trailer`] wraps the same rendering behind one type with its own error,
+//! for frames in memory or a single folder.
+//!
+//! The app itself runs whole queues of folders with `processing::process_folders`,
+//! which plans the queue, picks output directories and reports progress over a
+//! channel, rendering each folder with the same engine.
+//!
+//! The queue, settings storage and rendering from disk come with the `native`
+//! feature, on by default. Without it the in-memory compositing in [`engine`]
+//! still builds, for WebAssembly and other targets without a filesystem or
+//! threads; the `wasm` feature adds JavaScript bindings for it.
+
+pub mod animation;
+pub mod basemap;
+pub mod canvas;
+pub mod color;
+#[cfg(feature = "native")]
+pub mod config;
+pub mod coverage;
+pub mod decay;
+pub mod dedup;
+pub mod draw;
+pub mod echo;
+pub mod engine;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "native")]
+pub mod geotiff;
+#[cfg(feature = "native")]
+pub mod gif_export;
+pub mod interpolation;
+pub mod light;
+#[cfg(feature = "native")]
+pub mod metadata;
+pub mod motion;
+#[cfg(feature = "native")]
+pub mod motion_export;
+#[cfg(feature = "native")]
+pub mod mp4_export;
+pub mod nowcast;
+#[cfg(feature = "hdf5")]
+pub mod odim;
+#[cfg(feature = "native")]
+pub mod palette;
+#[cfg(feature = "native")]
+pub mod processing;
+#[cfg(feature = "native")]
+pub mod queue;
+pub mod range;
+pub mod renumber;
+pub mod resample;
+pub mod secondary;
+#[cfg(feature = "native")]
+pub mod sequence_writer;
+#[cfg(feature = "native")]
+pub mod session;
+pub mod settings;
+pub mod sidecar;
+pub mod static_mask;
+pub mod tracking;
+pub mod trailer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "native")]
+pub mod watch;
+
+pub use settings::ProcessingSettings;
+pub use trailer::{Trailer, TrailerError};
@@ -0,0 +1,46 @@
+//! Products drawn on a white or light grey background
+//!
+//! Many web radar products mark empty areas white rather than black or
+//! transparent. Read as they are, the whole background counts as echo and the
+//! trail is a solid block of the current color. With `light_background` on,
+//! each frame is turned around as it is decoded: near-white pixels become
+//! transparent, and every other pixel becomes white with an alpha that grows
+//! as the pixel darkens, so the darkest echoes are the strongest.
+
+use image::{Rgba, RgbaImage};
+
+use crate::motion;
+
+/// How far below 255 each channel of a pixel may be for it to count as the background
+pub const WHITE_TOLERANCE: u8 = 48;
+
+/// Share of a folder's first frame that is near white above which a hint
+/// suggests `light_background`
+pub const HINT_FRACTION: f32 = 0.7;
+
+/// Whether `pixel` is opaque enough to be seen and close to white in every channel
+pub fn is_near_white(pixel: &Rgba<u8>) -> bool {
+    pixel[3] > 0 && pixel.0[..3].iter().all(|&channel| channel >= 255 - WHITE_TOLERANCE)
+}
+
+/// Fraction of the pixels of `frame` that are near white, from 0 to 1
+pub fn near_white_fraction(frame: &RgbaImage) -> f32 {
+    let pixels = frame.width() as usize * frame.height() as usize;
+    if pixels == 0 {
+        return 0.0;
+    }
+    frame.pixels().filter(|pixel| is_near_white(pixel)).count() as f32 / pixels as f32
+}
+
+/// Turn a light-background frame into the form trails are drawn from, as the
+/// module describes
+pub fn key_out(frame: &mut RgbaImage) {
+    for pixel in frame.pixels_mut() {
+        *pixel = if is_near_white(pixel) {
+            Rgba([0, 0, 0, 0])
+        } else {
+            let darkness = 255.0 - motion::intensity(&Rgba([pixel[0], pixel[1], pixel[2], 255]));
+            Rgba([255, 255, 255, (darkness * pixel[3] as f32 / 255.0).round() as u8])
+        };
+    }
+}
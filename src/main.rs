@@ -4,93 +4,85 @@
 
 slint::include_modules!();
 
-mod processing;
-mod queue;
-mod config;
-
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{mpsc, Arc};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use slint::{ModelRc, SharedString, VecModel};
+use slint::{Model, ModelRc, SharedString, VecModel};
 
-/// Parse a hex color string like "#ff0000" to (r, g, b) tuple
-fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8)> {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() != 6 {
-        return None;
-    }
-    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
-    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
-    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
-    Some((r, g, b))
-}
+use radar_echo_trails::echo::EchoPredicate;
+use radar_echo_trails::{color, config, metadata, processing, queue, session, watch};
+
+/// The rendering thread pool, with the thread count setting it was built for
+type RenderPool = (usize, Arc<rayon::ThreadPool>);
 
 fn main() -> Result<(), slint::PlatformError> {
+    let storage = config::init_storage_mode(std::env::args().skip(1).any(|arg| arg == "--portable"));
     let ui = AppWindow::new()?;
+    ui.set_odim_available(cfg!(feature = "hdf5"));
     
     // Shared state
-    let folders: Rc<RefCell<Vec<queue::FolderInfo>>> = Rc::new(RefCell::new(Vec::new()));
+    let folders: Arc<Mutex<queue::JobQueue>> = Arc::new(Mutex::new(queue::JobQueue::default()));
     let processing_handle: Rc<RefCell<Option<thread::JoinHandle<()>>>> = Rc::new(RefCell::new(None));
     let stop_flag: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    // Rendering threads, kept from run to run while the thread count stays the same
+    let thread_pool: Rc<RefCell<Option<RenderPool>>> = Rc::new(RefCell::new(None));
     // Keep timer alive by storing it in shared state
     let progress_timer: Rc<RefCell<Option<slint::Timer>>> = Rc::new(RefCell::new(None));
+    let watcher: Rc<RefCell<Option<watch::FolderWatcher>>> = Rc::new(RefCell::new(None));
+    let watch_timer = slint::Timer::default();
+    // Sends settings reloaded from the file to the running processing thread
+    let reload_tx: Rc<RefCell<Option<mpsc::Sender<processing::ProcessingSettings>>>> = Rc::new(RefCell::new(None));
+    let settings_timer = slint::Timer::default();
 
     
-    // Load saved settings
-    if let Ok(settings) = config::load_settings() {
-        ui.set_history_length(settings.history_length);
-        ui.set_threads(settings.threads);
-        ui.set_limit(settings.limit);
-        
-        // Parse hex colors to RGB components
-        if let Some((r, g, b)) = parse_hex_color(&settings.background_color) {
-            ui.set_bg_r(r as i32);
-            ui.set_bg_g(g as i32);
-            ui.set_bg_b(b as i32);
-        }
-        if let Some((r, g, b)) = parse_hex_color(&settings.current_color) {
-            ui.set_cur_r(r as i32);
-            ui.set_cur_g(g as i32);
-            ui.set_cur_b(b as i32);
-        }
-        if let Some((r, g, b)) = parse_hex_color(&settings.history_color) {
-            ui.set_hist_r(r as i32);
-            ui.set_hist_g(g as i32);
-            ui.set_hist_b(b as i32);
-        }
+    // Load saved settings, keeping the usable fields of a hand-edited file
+    let (settings, load_problem) = config::load_or_default_with_report();
+    if let Some(problem) = load_problem {
+        let description = match problem {
+            config::SettingsError::RestoredFromBackup { .. } => problem.to_string(),
+            _ => format!("{}\n\nDefaults are used instead.", problem),
+        };
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Settings problem")
+            .set_description(description)
+            .set_buttons(rfd::MessageButtons::Ok)
+            .show();
+    }
+    apply_settings_to_ui(&ui, &settings);
+    if let config::StorageMode::Portable(dir) = storage {
+        ui.set_status_text(SharedString::from(format!("Portable mode: settings are kept in {}", dir.display())));
+    }
+    // The preset last applied, while the settings are still as it left them
+    let active_preset = settings.active_preset.as_deref().filter(|name| config::list_presets().iter().any(|preset| preset == name));
+    refresh_presets(&ui, active_preset.unwrap_or(""));
+    refresh_recent(&ui);
+    offer_session_restore(&ui, &folders);
+    if let Some(parent) = &settings.watch_folder {
+        start_watching(&ui, &watcher, parent);
     }
     
-    // Add folder callback
+    // Add folder callbacks
     {
         let ui_weak = ui.as_weak();
         let folders = folders.clone();
         ui.on_add_folder(move || {
             let ui = ui_weak.unwrap();
-            if let Some(path) = rfd::FileDialog::new()
-                .set_title("Select folder containing image frames")
-                .pick_folder()
-            {
-                let image_count = queue::count_image_files(&path);
-                let folder_name = path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("Unknown")
-                    .to_string();
-                
-                let folder_info = queue::FolderInfo {
-                    path: path.clone(),
-                    name: folder_name.clone(),
-                    file_count: image_count,
-                    status: queue::FolderStatus::Pending,
-                    progress: 0.0,
-                    error_message: None,
-                };
-                
-                folders.borrow_mut().push(folder_info);
-                update_folder_model(&ui, &folders.borrow());
-            }
+            pick_and_add_folder(&ui, &folders, false);
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let folders = folders.clone();
+        ui.on_add_folder_recursive(move || {
+            let ui = ui_weak.unwrap();
+            pick_and_add_folder(&ui, &folders, true);
         });
     }
     
@@ -100,11 +92,19 @@ fn main() -> Result<(), slint::PlatformError> {
         let folders = folders.clone();
         ui.on_remove_folder(move |index| {
             let ui = ui_weak.unwrap();
-            let mut folders_mut = folders.borrow_mut();
-            if (index as usize) < folders_mut.len() {
-                folders_mut.remove(index as usize);
-                drop(folders_mut);
-                update_folder_model(&ui, &folders.borrow());
+            let mut queue = folders.lock().unwrap();
+            let Some(folder) = queue.folders().get(index as usize) else { return };
+            
+            // The folder being processed is cancelled instead; the rest of the queue carries on
+            if matches!(folder.status, queue::FolderStatus::Processing) {
+                let name = folder.name.clone();
+                if queue.cancel(index as usize) {
+                    ui.set_status_text(SharedString::from(format!("Cancelling {}...", name)));
+                }
+                return;
+            }
+            if queue.remove(index as usize).is_some() {
+                update_folder_model(&ui, queue.folders());
             }
         });
     }
@@ -115,11 +115,9 @@ fn main() -> Result<(), slint::PlatformError> {
         let folders = folders.clone();
         ui.on_move_folder_up(move |index| {
             let ui = ui_weak.unwrap();
-            let mut folders_mut = folders.borrow_mut();
-            if index > 0 && (index as usize) < folders_mut.len() {
-                folders_mut.swap(index as usize, (index - 1) as usize);
-                drop(folders_mut);
-                update_folder_model(&ui, &folders.borrow());
+            let moved = index > 0 && folders.lock().unwrap().move_folder(index as usize, (index - 1) as usize);
+            if moved {
+                update_folder_model(&ui, folders.lock().unwrap().folders());
             }
         });
     }
@@ -130,47 +128,286 @@ fn main() -> Result<(), slint::PlatformError> {
         let folders = folders.clone();
         ui.on_move_folder_down(move |index| {
             let ui = ui_weak.unwrap();
-            let mut folders_mut = folders.borrow_mut();
-            if ((index + 1) as usize) < folders_mut.len() {
-                folders_mut.swap(index as usize, (index + 1) as usize);
-                drop(folders_mut);
-                update_folder_model(&ui, &folders.borrow());
+            let moved = folders.lock().unwrap().move_folder(index as usize, (index + 1) as usize);
+            if moved {
+                update_folder_model(&ui, folders.lock().unwrap().folders());
+            }
+        });
+    }
+    
+    // Move folder to front callback
+    {
+        let ui_weak = ui.as_weak();
+        let folders = folders.clone();
+        ui.on_move_folder_to_front(move |index| {
+            let ui = ui_weak.unwrap();
+            let moved = folders.lock().unwrap().move_to_front(index as usize);
+            if moved {
+                update_folder_model(&ui, folders.lock().unwrap().folders());
+            }
+        });
+    }
+    
+    // Reprocess folder callback, for folders skipped as already processed
+    {
+        let ui_weak = ui.as_weak();
+        let folders = folders.clone();
+        ui.on_reprocess_folder(move |index| {
+            let ui = ui_weak.unwrap();
+            let mut queue = folders.lock().unwrap();
+            if queue.reprocess(index as usize) {
+                update_folder_model(&ui, queue.folders());
             }
         });
     }
     
+    // Queue import/export callbacks
+    {
+        let ui_weak = ui.as_weak();
+        let folders = folders.clone();
+        ui.on_export_queue(move || {
+            let ui = ui_weak.unwrap();
+            let Some(path) = rfd::FileDialog::new()
+                .set_title("Export queue")
+                .add_filter("Queue", &["json"])
+                .set_file_name("queue.json")
+                .save_file()
+            else {
+                return;
+            };
+            let document = folders.lock().unwrap().to_document(&processing_settings_from_ui(&ui));
+            let status = match queue::save_queue_document(&path, &document) {
+                Ok(()) => format!("Exported {} folders to {}", document.folders.len(), path.display()),
+                Err(e) => format!("Export failed: {:#}", e),
+            };
+            ui.set_status_text(SharedString::from(status));
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let folders = folders.clone();
+        ui.on_import_queue(move || {
+            let ui = ui_weak.unwrap();
+            import_queue(&ui, &folders);
+        });
+    }
+    
+    // Recent folder callbacks
+    {
+        let ui_weak = ui.as_weak();
+        let folders = folders.clone();
+        ui.on_open_recent(move |index| {
+            let ui = ui_weak.unwrap();
+            // Look the entry up by path, as folders may have gone since the menu was built
+            let Some(item) = ui.get_recent_folders().row_data(index as usize) else { return };
+            let path = PathBuf::from(item.text.as_str());
+            let Some(folder) = config::recent().into_iter().find(|recent| recent.path == path) else {
+                ui.set_status_text(SharedString::from(format!("{} no longer exists", path.display())));
+                refresh_recent(&ui);
+                return;
+            };
+            add_folder_to_queue(&ui, &folders, &folder.path, folder.recursive);
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_clear_recent(move || {
+            let ui = ui_weak.unwrap();
+            let _ = config::clear_recent();
+            refresh_recent(&ui);
+        });
+    }
+    
+    // Settings file callbacks
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_export_settings(move || {
+            let ui = ui_weak.unwrap();
+            let Some(path) = rfd::FileDialog::new()
+                .set_title("Export settings")
+                .add_filter("Settings", &["json", "toml"])
+                .set_file_name("radar_echo_trails_settings.json")
+                .save_file()
+            else {
+                return;
+            };
+            let status = match config::export_settings(&settings_from_ui(&ui), &path) {
+                Ok(()) => format!("Exported settings to {}", path.display()),
+                Err(e) => format!("Export failed: {}", e),
+            };
+            ui.set_status_text(SharedString::from(status));
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let watcher = watcher.clone();
+        ui.on_import_settings(move || {
+            let ui = ui_weak.unwrap();
+            import_settings(&ui, &watcher);
+        });
+    }
+    
     // Clear queue callback
     {
         let ui_weak = ui.as_weak();
         let folders = folders.clone();
         ui.on_clear_queue(move || {
             let ui = ui_weak.unwrap();
-            folders.borrow_mut().clear();
-            update_folder_model(&ui, &folders.borrow());
+            folders.lock().unwrap().clear();
+            update_folder_model(&ui, folders.lock().unwrap().folders());
         });
     }
     
-    // Settings changed callback
+    // Watch folder callbacks
     {
-        ui.on_settings_changed(move |history_length, threads, limit, bg_r, bg_g, bg_b, cur_r, cur_g, cur_b, hist_r, hist_g, hist_b| {
-            let settings = config::Settings {
-                history_length,
-                background_color: format!("#{:02x}{:02x}{:02x}", bg_r, bg_g, bg_b),
-                current_color: format!("#{:02x}{:02x}{:02x}", cur_r, cur_g, cur_b),
-                history_color: format!("#{:02x}{:02x}{:02x}", hist_r, hist_g, hist_b),
-                threads,
-                limit,
+        let ui_weak = ui.as_weak();
+        let watcher = watcher.clone();
+        ui.on_start_watching(move || {
+            let ui = ui_weak.unwrap();
+            let Some(parent) = rfd::FileDialog::new()
+                .set_title("Select folder to watch for new frame folders")
+                .pick_folder()
+            else {
+                return;
             };
-            let _ = config::save_settings(&settings);
+            start_watching(&ui, &watcher, &parent);
+            let _ = config::save_settings(&settings_from_ui(&ui));
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let watcher = watcher.clone();
+        ui.on_stop_watching(move || {
+            let ui = ui_weak.unwrap();
+            if let Some(stopped) = watcher.borrow_mut().take() {
+                ui.set_status_text(SharedString::from(format!("Stopped watching {}", stopped.parent().display())));
+            }
+            ui.set_watch_folder(SharedString::new());
+            let _ = config::save_settings(&settings_from_ui(&ui));
+        });
+    }
+    
+    // Poll the watcher for folders that have finished arriving
+    {
+        let ui_weak = ui.as_weak();
+        let folders = folders.clone();
+        let watcher = watcher.clone();
+        watch_timer.start(
+            slint::TimerMode::Repeated,
+            Duration::from_secs(1),
+            move || {
+                let Some(ui) = ui_weak.upgrade() else { return };
+                let settled = match watcher.borrow_mut().as_mut() {
+                    Some(watcher) => watcher.poll(),
+                    None => return,
+                };
+                if enqueue_watched_folders(&ui, &folders, settled) > 0
+                    && ui.get_watch_auto_start()
+                    && !ui.get_is_processing()
+                {
+                    ui.invoke_start_processing();
+                }
+            },
+        );
+    }
+    
+    // Poll the settings file for edits made outside the app
+    if let Some(dir) = config::settings_dir()
+        && std::fs::create_dir_all(&dir).is_ok()
+        && let Ok(mut settings_watcher) = watch::FileWatcher::start(&dir, config::SETTINGS_FILE_NAMES)
+    {
+        let ui_weak = ui.as_weak();
+        let reload_tx = reload_tx.clone();
+        settings_timer.start(
+            slint::TimerMode::Repeated,
+            Duration::from_secs(1),
+            move || {
+                let Some(ui) = ui_weak.upgrade() else { return };
+                if settings_watcher.poll() && ui.get_reload_settings_file() {
+                    reload_settings_file(&ui, &reload_tx);
+                }
+            },
+        );
+    }
+    
+    // Settings changed callback
+    {
+        let ui_weak = ui.as_weak();
+        let watcher = watcher.clone();
+        ui.on_settings_changed(move || {
+            let ui = ui_weak.unwrap();
+            if let Some(watcher) = watcher.borrow_mut().as_mut() {
+                watcher.set_settle(Duration::from_secs(ui.get_watch_settle_secs().max(0) as u64));
+            }
+            let settings = settings_from_ui(&ui);
+            show_color_warnings(&ui, &settings);
+            ui.set_history_length(settings.rendering.history_length as i32);
+            // Edited by hand, so no longer exactly the selected preset
+            ui.set_current_preset(SharedString::new());
+            ui.set_preset_deletable(false);
+            if let Err(e) = config::save_settings(&config::Settings { active_preset: None, ..settings }) {
+                ui.set_status_text(SharedString::from(format!("Settings not saved: {}", e)));
+            }
+        });
+    }
+    
+    // Preset callbacks
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_apply_preset(move |name| {
+            let ui = ui_weak.unwrap();
+            match config::load_preset(&name) {
+                Ok(preset) => {
+                    apply_settings_to_ui(&ui, &preset.with_local_fields(&settings_from_ui(&ui)));
+                    refresh_presets(&ui, &name);
+                    let _ = config::save_settings(&settings_from_ui(&ui));
+                    ui.set_status_text(SharedString::from(format!("Applied preset {}", name)));
+                }
+                Err(e) => {
+                    ui.set_status_text(SharedString::from(format!("Preset {} not applied: {}", name, e)));
+                }
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_save_preset(move |name| {
+            let ui = ui_weak.unwrap();
+            let name = name.trim();
+            match config::save_preset(name, &settings_from_ui(&ui)) {
+                Ok(()) => {
+                    refresh_presets(&ui, name);
+                    ui.set_status_text(SharedString::from(format!("Saved preset {}", name)));
+                }
+                Err(e) => ui.set_status_text(SharedString::from(format!("Preset not saved: {}", e))),
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_delete_preset(move |name| {
+            let ui = ui_weak.unwrap();
+            match config::delete_preset(&name) {
+                Ok(()) => {
+                    refresh_presets(&ui, "");
+                    ui.set_status_text(SharedString::from(format!("Deleted preset {}", name)));
+                }
+                Err(e) => ui.set_status_text(SharedString::from(format!("Preset not deleted: {}", e))),
+            }
         });
     }
     
+    // A preset named at launch replaces the saved look, keeping the machine's folders
+    if let Some(name) = preset_argument(std::env::args().skip(1)) {
+        ui.invoke_apply_preset(name.into());
+    }
+    
     // Parse hex callback - parses hex string and updates picker RGB values
     {
         let ui_weak = ui.as_weak();
         ui.on_parse_hex(move |hex_str| {
             let ui = ui_weak.unwrap();
-            if let Some((r, g, b)) = parse_hex_color(&hex_str.to_string()) {
+            if let Ok((r, g, b)) = processing::parse_hex_color(hex_str.as_ref()) {
                 ui.set_picker_r(r as i32);
                 ui.set_picker_g(g as i32);
                 ui.set_picker_b(b as i32);
@@ -185,6 +422,8 @@ fn main() -> Result<(), slint::PlatformError> {
         let processing_handle = processing_handle.clone();
         let stop_flag = stop_flag.clone();
         let progress_timer = progress_timer.clone();
+        let reload_tx = reload_tx.clone();
+        let thread_pool = thread_pool.clone();
         
         ui.on_start_processing(move || {
             let ui = ui_weak.unwrap();
@@ -199,30 +438,30 @@ fn main() -> Result<(), slint::PlatformError> {
             stop_flag.store(false, Ordering::Relaxed);
             
             // Get settings
-            let bg_r = ui.get_bg_r() as u8;
-            let bg_g = ui.get_bg_g() as u8;
-            let bg_b = ui.get_bg_b() as u8;
-            let cur_r = ui.get_cur_r() as u8;
-            let cur_g = ui.get_cur_g() as u8;
-            let cur_b = ui.get_cur_b() as u8;
-            let hist_r = ui.get_hist_r() as u8;
-            let hist_g = ui.get_hist_g() as u8;
-            let hist_b = ui.get_hist_b() as u8;
-            
-            let settings = processing::ProcessingSettings {
-                history_length: ui.get_history_length() as usize,
-                background_color: format!("#{:02x}{:02x}{:02x}", bg_r, bg_g, bg_b),
-                current_color: format!("#{:02x}{:02x}{:02x}", cur_r, cur_g, cur_b),
-                history_color: format!("#{:02x}{:02x}{:02x}", hist_r, hist_g, hist_b),
-                threads: ui.get_threads() as usize,
-                limit: if ui.get_limit() == 0 { None } else { Some(ui.get_limit() as usize) },
-            };
+            let settings = processing_settings_from_ui(&ui);
+            if let Err(invalid) = settings.validate() {
+                let problems: Vec<String> = invalid.iter().map(|field| field.to_string()).collect();
+                ui.set_status_text(SharedString::from(format!("Cannot start: {}", problems.join("; "))));
+                return;
+            }
             
-            // Get folder list
-            let folder_list: Vec<queue::FolderInfo> = folders.borrow().clone();
-            if folder_list.is_empty() {
+            if folders.lock().unwrap().is_empty() {
                 return;
             }
+
+            let pool = {
+                let mut cached = thread_pool.borrow_mut();
+                match &*cached {
+                    Some((threads, pool)) if *threads == settings.threads => pool.clone(),
+                    _ => match processing::thread_pool(settings.threads) {
+                        Ok(pool) => cached.insert((settings.threads, Arc::new(pool))).1.clone(),
+                        Err(e) => {
+                            ui.set_status_text(SharedString::from(format!("Cannot start: {}", e)));
+                            return;
+                        }
+                    },
+                }
+            };
             
             // Create progress channel
             let (tx, rx) = mpsc::channel::<processing::ProgressUpdate>();
@@ -231,25 +470,40 @@ fn main() -> Result<(), slint::PlatformError> {
             ui.set_is_processing(true);
             ui.set_is_complete(false);
             ui.set_status_text("Starting...".into());
-            ui.set_folders_completed(0);
+            ui.set_warning_text("".into());
             ui.set_files_completed(0);
             ui.set_files_total(0);
-            ui.set_overall_progress(0.0);
             
-            // Reset progress for all folders
+            // Reset progress, keeping folders finished earlier unless nothing else is left
             {
-                let mut folders_mut = folders.borrow_mut();
-                for folder in folders_mut.iter_mut() {
-                    folder.status = queue::FolderStatus::Pending;
-                    folder.progress = 0.0;
+                let mut queue = folders.lock().unwrap();
+                let rerun_all = queue.folders().iter()
+                    .all(|f| matches!(f.status, queue::FolderStatus::Complete));
+                for folder in queue.iter_mut() {
+                    if rerun_all || !matches!(folder.status, queue::FolderStatus::Complete) {
+                        folder.status = queue::FolderStatus::Pending;
+                        folder.progress = 0.0;
+                        folder.error_message = None;
+                        folder.cancel_requested.store(false, Ordering::Relaxed);
+                    }
                 }
+                let already_complete = queue.folders().iter()
+                    .filter(|f| matches!(f.status, queue::FolderStatus::Complete))
+                    .count();
+                ui.set_folders_completed(already_complete as i32);
+                ui.set_overall_progress(already_complete as f32 / queue.folders().len() as f32);
+                update_folder_model(&ui, queue.folders());
+                let _ = session::save(&session::snapshot(&queue, &settings));
             }
-            update_folder_model(&ui, &folders.borrow());
             
-            // Spawn processing thread
+            // Spawn processing thread; it pulls folders from the shared queue as it goes
             let stop_flag_clone = stop_flag.clone();
+            let queue = folders.clone();
+            let run_settings = settings.clone();
+            let (settings_tx, reload_rx) = mpsc::channel();
+            *reload_tx.borrow_mut() = Some(settings_tx);
             let handle = thread::spawn(move || {
-                processing::process_folders(folder_list, settings, tx, stop_flag_clone);
+                processing::process_folders_on(&pool, queue, settings, tx, stop_flag_clone, reload_rx);
             });
             
             *processing_handle.borrow_mut() = Some(handle);
@@ -258,6 +512,11 @@ fn main() -> Result<(), slint::PlatformError> {
             let ui_weak_poll = ui.as_weak();
             let folders_poll = folders.clone();
             let processing_handle_poll = processing_handle.clone();
+            let mut session_saved = Instant::now();
+            // Folders rendering at once, in queue order, shown together in the progress bar
+            let mut in_flight: BTreeMap<u64, FolderFiles> = BTreeMap::new();
+            // Files that failed in each folder, listed under its error
+            let mut file_errors: HashMap<u64, Vec<String>> = HashMap::new();
             
             let timer = slint::Timer::default();
             timer.start(
@@ -269,77 +528,233 @@ fn main() -> Result<(), slint::PlatformError> {
                         None => return,
                     };
                     
-                    // Process all pending updates
+                    // Process all pending updates, saving the session whenever a folder changes state
+                    let mut folder_changed = false;
                     while let Ok(update) = rx.try_recv() {
+                        folder_changed |= matches!(
+                            update,
+                            processing::ProgressUpdate::FolderStarted { .. }
+                                | processing::ProgressUpdate::FolderCompleted { .. }
+                                | processing::ProgressUpdate::FolderError { .. }
+                                | processing::ProgressUpdate::FolderSkipped { .. }
+                                | processing::ProgressUpdate::FolderCancelled { .. }
+                                | processing::ProgressUpdate::FolderStopped { .. }
+                        );
+                        if let processing::ProgressUpdate::FolderCompleted { folder_id, .. }
+                            | processing::ProgressUpdate::FolderError { folder_id, .. }
+                            | processing::ProgressUpdate::FolderSkipped { folder_id, .. }
+                            | processing::ProgressUpdate::FolderCancelled { folder_id, .. }
+                            | processing::ProgressUpdate::FolderStopped { folder_id, .. } = &update
+                            && in_flight.remove(folder_id).is_some()
+                        {
+                            show_in_flight(&ui, &in_flight);
+                        }
                         match update {
-                            processing::ProgressUpdate::FolderStarted { folder_index, folder_name } => {
-                                ui.set_current_folder(folder_name.into());
-                                ui.set_status_text(SharedString::from(format!("Processing folder {}", folder_index + 1)));
+                            processing::ProgressUpdate::FolderStarted { folder_id, folder_name, estimated_bytes, frames_found, frames_selected } => {
+                                in_flight.insert(folder_id, FolderFiles { name: folder_name, done: 0, total: frames_selected, per_second: 0.0 });
+                                file_errors.remove(&folder_id);
+                                show_in_flight(&ui, &in_flight);
+                                let mut queue = folders_poll.lock().unwrap();
+                                let position = queue.position(folder_id).map_or(0, |i| i + 1);
+                                let mut details = Vec::new();
+                                if frames_selected != frames_found {
+                                    details.push(format!("{} of {} frames", frames_selected, frames_found));
+                                }
+                                if let Some(bytes) = estimated_bytes {
+                                    details.push(format!("~{} output", format_bytes(bytes)));
+                                }
+                                let status = match details.is_empty() {
+                                    true => format!("Processing folder {}", position),
+                                    false => format!("Processing folder {} ({})", position, details.join(", ")),
+                                };
+                                ui.set_status_text(SharedString::from(status));
                                 
-                                let mut folders_mut = folders_poll.borrow_mut();
-                                if folder_index < folders_mut.len() {
-                                    folders_mut[folder_index].status = queue::FolderStatus::Processing;
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.status = queue::FolderStatus::Processing;
                                 }
-                                drop(folders_mut);
-                                update_folder_model(&ui, &folders_poll.borrow());
+                                update_folder_model(&ui, queue.folders());
                             }
                             processing::ProgressUpdate::FileProgress { 
-                                folder_index, 
+                                folder_id, 
                                 files_done, 
                                 files_total, 
                                 current_file,
                                 files_per_second,
                             } => {
-                                let folder_progress = files_done as f32 / files_total.max(1) as f32;
-                                ui.set_folder_progress(folder_progress);
-                                ui.set_files_completed(files_done as i32);
-                                ui.set_files_total(files_total as i32);
                                 ui.set_current_file(current_file.into());
-                                ui.set_files_per_second(files_per_second as f32);
+                                if let Some(files) = in_flight.get_mut(&folder_id) {
+                                    (files.done, files.total, files.per_second) = (files_done, files_total, files_per_second);
+                                }
+                                show_in_flight(&ui, &in_flight);
                                 
                                 // Update folder progress
-                                let mut folders_mut = folders_poll.borrow_mut();
-                                if folder_index < folders_mut.len() {
-                                    folders_mut[folder_index].progress = folder_progress;
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.progress = files_done as f32 / files_total.max(1) as f32;
+                                }
+                                update_folder_model(&ui, queue.folders());
+                            }
+                            processing::ProgressUpdate::AnimationProgress { folder_id: _, frames_encoded, frames_total } => {
+                                ui.set_status_text(SharedString::from(format!(
+                                    "Animated GIF: {} of {} frames encoded",
+                                    frames_encoded, frames_total
+                                )));
+                            }
+                            processing::ProgressUpdate::AnimationWritten { folder_id: _, path, bytes } => {
+                                ui.set_status_text(SharedString::from(format!(
+                                    "Wrote {} ({})",
+                                    path.file_name().unwrap_or_default().to_string_lossy(),
+                                    format_bytes(bytes)
+                                )));
+                            }
+                            processing::ProgressUpdate::InsufficientSpace { destination, required_bytes, available_bytes } => {
+                                ui.set_warning_text(SharedString::from(format!(
+                                    "Output may not fit: ~{} needed near {}, {} free",
+                                    format_bytes(required_bytes),
+                                    destination.display(),
+                                    format_bytes(available_bytes)
+                                )));
+                            }
+                            processing::ProgressUpdate::FramesSkipped { folder_id, skipped } => {
+                                ui.set_status_text(SharedString::from(format!(
+                                    "{} radar files skipped without the selected product",
+                                    skipped.len()
+                                )));
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    let details: Vec<String> = skipped.iter()
+                                        .map(|(path, reason)| format!("{}: {}", path.display(), reason))
+                                        .collect();
+                                    folder.error_message = Some(details.join("\n"));
                                 }
-                                drop(folders_mut);
-                                update_folder_model(&ui, &folders_poll.borrow());
+                            }
+                            processing::ProgressUpdate::FolderWarning { folder_id, message } => {
+                                ui.set_status_text(SharedString::from(message.clone()));
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.error_message = Some(message);
+                                }
+                            }
+                            processing::ProgressUpdate::PreflightComplete { folder_id, frames_checked, corrupt } => {
+                                let mut queue = folders_poll.lock().unwrap();
+                                let text = if corrupt.is_empty() {
+                                    format!("Pre-flight: all {} frames OK", frames_checked)
+                                } else if ui.get_stop_on_error() {
+                                    let position = queue.position(folder_id).map_or(0, |i| i + 1);
+                                    format!("Pre-flight: {} corrupt frames, folder {} will fail", corrupt.len(), position)
+                                } else {
+                                    format!("Pre-flight: {} corrupt frames will be skipped", corrupt.len())
+                                };
+                                ui.set_status_text(SharedString::from(text));
                                 
-                                // Calculate ETA
-                                if files_per_second > 0.0 {
-                                    let remaining = files_total - files_done;
-                                    let eta_secs = (remaining as f64 / files_per_second) as u64;
-                                    let eta_mins = eta_secs / 60;
-                                    let eta_secs_rem = eta_secs % 60;
-                                    ui.set_eta_text(SharedString::from(format!("{:02}:{:02}", eta_mins, eta_secs_rem)));
+                                if !corrupt.is_empty() && let Some(folder) = queue.get_mut(folder_id) {
+                                    let details: Vec<String> = corrupt.iter()
+                                        .map(|(path, err)| format!("{}: {}", path.display(), err))
+                                        .collect();
+                                    folder.error_message = Some(details.join("\n"));
                                 }
                             }
-                            processing::ProgressUpdate::FolderCompleted { folder_index } => {
-                                let mut folders_mut = folders_poll.borrow_mut();
-                                if folder_index < folders_mut.len() {
-                                    folders_mut[folder_index].status = queue::FolderStatus::Complete;
-                                    folders_mut[folder_index].progress = 1.0;
+                            processing::ProgressUpdate::FolderCompleted { folder_id, frames_rendered, frames_skipped } => {
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.status = queue::FolderStatus::Complete;
+                                    folder.progress = 1.0;
+                                    if frames_skipped > 0 {
+                                        ui.set_status_text(SharedString::from(format!(
+                                            "Resumed {}: {} frames kept from the interrupted run, {} rendered",
+                                            folder.name, frames_skipped, frames_rendered
+                                        )));
+                                    }
                                 }
                                 ui.set_folders_completed(ui.get_folders_completed() + 1);
                                 
                                 // Update overall progress
-                                let total_folders = folders_mut.len() as f32;
-                                let completed = folders_mut.iter()
+                                let total_folders = queue.folders().len() as f32;
+                                let completed = queue.folders().iter()
                                     .filter(|f| matches!(f.status, queue::FolderStatus::Complete))
                                     .count() as f32;
                                 ui.set_overall_progress(completed / total_folders);
                                 
-                                drop(folders_mut);
-                                update_folder_model(&ui, &folders_poll.borrow());
+                                update_folder_model(&ui, queue.folders());
                             }
-                            processing::ProgressUpdate::FolderError { folder_index, error } => {
-                                let mut folders_mut = folders_poll.borrow_mut();
-                                if folder_index < folders_mut.len() {
-                                    folders_mut[folder_index].status = queue::FolderStatus::Error;
-                                    folders_mut[folder_index].error_message = Some(error);
+                            processing::ProgressUpdate::FileError { folder_id, file, error } => {
+                                file_errors.entry(folder_id).or_default().push(format!("{}: {}", file.display(), error));
+                            }
+                            processing::ProgressUpdate::FolderError { folder_id, error } => {
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.status = queue::FolderStatus::Error;
+                                    let mut lines = vec![error];
+                                    lines.extend(file_errors.remove(&folder_id).unwrap_or_default());
+                                    folder.error_message = Some(lines.join("\n"));
+                                }
+                                update_folder_model(&ui, queue.folders());
+                            }
+                            processing::ProgressUpdate::WorkPlanned { notes } => {
+                                let mut queue = folders_poll.lock().unwrap();
+                                for (folder_id, note) in notes {
+                                    if let Some(folder) = queue.get_mut(folder_id) {
+                                        folder.error_message = Some(note);
+                                    }
                                 }
-                                drop(folders_mut);
-                                update_folder_model(&ui, &folders_poll.borrow());
+                                update_folder_model(&ui, queue.folders());
+                            }
+                            processing::ProgressUpdate::FolderSkipped { folder_id, reason } => {
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.status = queue::FolderStatus::Skipped;
+                                    folder.progress = 1.0;
+                                    ui.set_status_text(SharedString::from(format!("Skipped {}: {}", folder.name, reason)));
+                                    folder.error_message = Some(reason);
+                                }
+                                update_folder_model(&ui, queue.folders());
+                            }
+                            processing::ProgressUpdate::OutputSettingsDiffer { folder_id, existing_dir, differences } => {
+                                let queue = folders_poll.lock().unwrap();
+                                let name = queue.position(folder_id)
+                                    .map(|i| queue.folders()[i].name.clone())
+                                    .unwrap_or_default();
+                                let action = if ui.get_overwrite_changed() { "overwriting it" } else { "writing to a new folder" };
+                                ui.set_warning_text(SharedString::from(format!(
+                                    "{}: {} was made with different settings ({}); {}",
+                                    name,
+                                    existing_dir.display(),
+                                    differences.join(", "),
+                                    action
+                                )));
+                            }
+                            processing::ProgressUpdate::FolderCancelled { folder_id, frames_completed } => {
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.status = queue::FolderStatus::Cancelled;
+                                    folder.error_message = Some(format!(
+                                        "Cancelled after {} of {} frames",
+                                        frames_completed, folder.file_count
+                                    ));
+                                    ui.set_status_text(SharedString::from(format!("Cancelled {}", folder.name)));
+                                }
+                                update_folder_model(&ui, queue.folders());
+                            }
+                            processing::ProgressUpdate::FolderStopped { folder_id, frames_completed } => {
+                                // Pending again, so the next run picks it up where resuming allows
+                                let mut queue = folders_poll.lock().unwrap();
+                                if let Some(folder) = queue.get_mut(folder_id) {
+                                    folder.status = queue::FolderStatus::Pending;
+                                    folder.error_message = Some(format!(
+                                        "Stopped after {} of {} frames",
+                                        frames_completed, folder.file_count
+                                    ));
+                                }
+                                update_folder_model(&ui, queue.folders());
+                            }
+                            processing::ProgressUpdate::QueueChanged => {
+                                update_folder_model(&ui, folders_poll.lock().unwrap().folders());
+                            }
+                            processing::ProgressUpdate::SettingsReloaded { changed } => {
+                                ui.set_status_text(SharedString::from(format!(
+                                    "Using reloaded settings from the next folder on ({})",
+                                    changed.join(", ")
+                                )));
                             }
                             processing::ProgressUpdate::AllComplete => {
                                 ui.set_is_processing(false);
@@ -347,15 +762,30 @@ fn main() -> Result<(), slint::PlatformError> {
                                 ui.set_overall_progress(1.0);
                                 ui.set_status_text("Processing complete!".into());
                                 ui.set_eta_text("--:--".into());
+                                session::remove();
                                 
                                 // Clean up handle
                                 if let Some(handle) = processing_handle_poll.borrow_mut().take() {
                                     let _ = handle.join();
                                 }
+                                
+                                // Watched folders may have arrived while this run was busy
+                                let more_pending = folders_poll.lock().unwrap().folders().iter()
+                                    .any(|f| matches!(f.status, queue::FolderStatus::Pending));
+                                if more_pending && ui.get_watch_auto_start() && !ui.get_watch_folder().is_empty() {
+                                    // Deferred: starting replaces the timer running this closure
+                                    let ui_weak = ui.as_weak();
+                                    slint::Timer::single_shot(Duration::ZERO, move || {
+                                        if let Some(ui) = ui_weak.upgrade() {
+                                            ui.invoke_start_processing();
+                                        }
+                                    });
+                                }
                             }
                             processing::ProgressUpdate::Cancelled => {
                                 ui.set_is_processing(false);
                                 ui.set_status_text("Cancelled".into());
+                                let _ = session::save(&session::snapshot(&folders_poll.lock().unwrap(), &run_settings));
                                 
                                 // Clean up handle
                                 if let Some(handle) = processing_handle_poll.borrow_mut().take() {
@@ -364,6 +794,11 @@ fn main() -> Result<(), slint::PlatformError> {
                             }
                         }
                     }
+                    
+                    if ui.get_is_processing() && (folder_changed || session_saved.elapsed() >= SESSION_SAVE_INTERVAL) {
+                        let _ = session::save(&session::snapshot(&folders_poll.lock().unwrap(), &run_settings));
+                        session_saved = Instant::now();
+                    }
                 },
             );
             
@@ -381,21 +816,1017 @@ fn main() -> Result<(), slint::PlatformError> {
         });
     }
     
-    ui.run()
+    ui.run()?;
+    // Closed while idle: nothing was interrupted, so there is nothing to restore
+    if !ui.get_is_processing() {
+        session::remove();
+    }
+    Ok(())
+}
+
+/// How often the session is saved while a folder is processing
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Apply an edit made to the settings file outside the app. A valid edit
+/// replaces the settings in the UI and, during a run, is handed to the
+/// processing thread for the folders that have not started; an invalid one is
+/// reported and ignored.
+fn reload_settings_file(ui: &AppWindow, reload_tx: &Rc<RefCell<Option<mpsc::Sender<processing::ProcessingSettings>>>>) {
+    let reloaded = match config::load_settings() {
+        Ok(reloaded) => reloaded,
+        Err(e) => {
+            ui.set_status_text(SharedString::from(format!("Ignored edit to the settings file: {}", e)));
+            return;
+        }
+    };
+    // The app's own saves land here too, and change nothing
+    let changed = reloaded.changed_fields(&settings_from_ui(ui));
+    if changed.is_empty() {
+        return;
+    }
+    apply_settings_to_ui(ui, &reloaded);
+    refresh_presets(ui, reloaded.active_preset.as_deref().unwrap_or(""));
+    
+    let sent = ui.get_is_processing()
+        && reload_tx.borrow().as_ref().is_some_and(|tx| tx.send(reloaded.into()).is_ok());
+    let status = if sent {
+        format!("Settings file changed ({}); applying from the next folder", changed.join(", "))
+    } else {
+        format!("Reloaded the settings file ({})", changed.join(", "))
+    };
+    ui.set_status_text(SharedString::from(status));
+}
+
+/// Offer to queue the folders of a session that ended without finishing
+fn offer_session_restore(ui: &AppWindow, folders: &Arc<Mutex<queue::JobQueue>>) {
+    let saved = match session::load() {
+        Ok(Some(saved)) => saved,
+        Ok(None) => return,
+        Err(e) => {
+            ui.set_status_text(SharedString::from(format!("Last session not restored: {:#}", e)));
+            session::remove();
+            return;
+        }
+    };
+    let restore = saved.unfinished() > 0 && rfd::MessageDialog::new()
+        .set_title("Restore last session")
+        .set_description(format!(
+            "The last session ended with {} of {} folders unfinished.\n\nRestore its queue?",
+            saved.unfinished(),
+            saved.folders.len()
+        ))
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show() == rfd::MessageDialogResult::Yes;
+    if !restore {
+        session::remove();
+        return;
+    }
+    
+    // The session's settings are the ones its folders were being processed with
+    let mut settings = settings_from_ui(ui);
+    settings.set_processing(&saved.settings);
+    apply_settings_to_ui(ui, &settings);
+    let _ = config::save_settings(&settings);
+    
+    let report = session::restore(&mut folders.lock().unwrap(), &saved);
+    for &index in &report.added {
+        estimate_folder_output(ui, folders, index);
+        thumbnail_folder(folders, index);
+    }
+    let queue = folders.lock().unwrap();
+    let completed = queue.folders().iter()
+        .filter(|f| matches!(f.status, queue::FolderStatus::Complete))
+        .count();
+    ui.set_folders_completed(completed as i32);
+    update_folder_model(ui, queue.folders());
+    
+    let mut status = format!("Restored {} folders from the last session", report.added.len());
+    if !report.rejected.is_empty() {
+        let names: Vec<String> = report.rejected.iter()
+            .map(|(path, reason)| format!("{}: {}", path.display(), reason))
+            .collect();
+        status.push_str(&format!("; not restored: {}", names.join(", ")));
+    }
+    ui.set_status_text(SharedString::from(status));
+}
+
+/// Push persisted settings into the UI properties
+fn apply_settings_to_ui(ui: &AppWindow, settings: &config::Settings) {
+    ui.set_history_length(settings.rendering.history_length as i32);
+    ui.set_threads(settings.performance.threads as i32);
+    ui.set_io_threads(settings.performance.io_threads as i32);
+    ui.set_folder_parallelism(settings.performance.folder_parallelism as i32);
+    ui.set_cache_mb(settings.performance.cache_mb as i32);
+    ui.set_tile_size(settings.performance.tile_size as i32);
+    ui.set_limit(settings.limit.unwrap_or(0) as i32);
+    ui.set_frame_offset(settings.offset as i32);
+    ui.set_frame_stride(settings.stride as i32);
+    ui.set_preflight(settings.preflight);
+    ui.set_light_background(settings.rendering.light_background);
+    ui.set_luminance_alpha(settings.rendering.alpha_source == processing::AlphaSource::Luminance);
+    ui.set_legacy_fade(settings.rendering.legacy_fade);
+    ui.set_history_weights(processing::format_history_weights(&settings.rendering.history_weights).into());
+    ui.set_fade_curve(settings.rendering.fade_curve.clone().into());
+    ui.set_history_opacity(settings.rendering.history_opacity as i32);
+    ui.set_decay_trails(settings.rendering.trail_mode == processing::TrailMode::Decay);
+    ui.set_decay(settings.rendering.decay);
+    ui.set_history_gradient(settings.rendering.history_gradient.as_str().into());
+    ui.set_history_colors(processing::format_history_colors(&settings.rendering.history_colors).into());
+    ui.set_gradient_hsv(settings.rendering.gradient_space == processing::GradientSpace::Hsv);
+    ui.set_current_solid(settings.rendering.current_style == processing::CurrentStyle::Solid);
+    ui.set_current_original(settings.rendering.current_style == processing::CurrentStyle::Original);
+    ui.set_history_original(settings.rendering.history_style == processing::HistoryStyle::Original);
+    ui.set_echo_rule(settings.rendering.echo_rule.as_str().into());
+    ui.set_find_repeats(settings.repeated_frames != processing::RepeatPolicy::Off);
+    ui.set_keep_repeats(settings.repeated_frames == processing::RepeatPolicy::Flag);
+    ui.set_stop_on_error(settings.on_error == processing::ErrorPolicy::FailFolder);
+    ui.set_ignore_manifests(settings.ignore_manifests);
+    ui.set_include_hidden(settings.include_hidden);
+    ui.set_sort_order(match settings.sort_order {
+        queue::SortOrder::Natural => 0,
+        queue::SortOrder::Lexical => 1,
+        queue::SortOrder::Mtime => 2,
+    });
+    ui.set_force(settings.output.force);
+    ui.set_append(settings.output.append);
+    ui.set_resume(settings.output.resume);
+    ui.set_overwrite_changed(settings.output.on_collision == processing::CollisionPolicy::Overwrite);
+    ui.set_path_mappings(settings.import_path_mappings.join("; ").into());
+    ui.set_background_image(settings.rendering.background_image.as_deref()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_fit_background_image(settings.rendering.fit_background_image);
+    ui.set_transparent_background(settings.rendering.transparent_background);
+    ui.set_output_root(settings.output.default_root.as_deref()
+        .map(|root| root.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_output_name_template(settings.output.name_template.as_str().into());
+    ui.set_write_layers(settings.output.layers != processing::LayerOutput::Off);
+    ui.set_coverage_plot(settings.output.coverage_plot);
+    ui.set_png_palette(settings.output.png_palette);
+    ui.set_tile_output(settings.output.tile_output);
+    ui.set_canvas_size(processing::format_canvas_size(settings.canvas.size).into());
+    ui.set_canvas_placement(match settings.canvas.placement {
+        processing::Placement::Center => 0,
+        processing::Placement::TopLeft => 1,
+        processing::Placement::Custom { .. } => 2,
+    });
+    ui.set_canvas_offset(match settings.canvas.placement {
+        processing::Placement::Custom { x, y } => format!("{},{}", x, y).into(),
+        _ => SharedString::new(),
+    });
+    ui.set_latest_image(settings.output.latest_image.as_deref()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_latest_json(settings.output.latest_json);
+    ui.set_renumber(settings.output.renumber.is_some());
+    ui.set_renumber_prefix(settings.output.renumber.as_deref().unwrap_or_default().into());
+    ui.set_renumber_width(settings.output.renumber_width as i32);
+    ui.set_emit_index(settings.output.emit_index.as_deref()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_animate_gif(settings.output.animate_gif.as_deref()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_gif_colors(settings.output.gif_colors as i32);
+    ui.set_gif_dither(match settings.output.gif_dither {
+        processing::GifDither::None => 0,
+        processing::GifDither::Ordered => 1,
+        processing::GifDither::FloydSteinberg => 2,
+    });
+    ui.set_mp4(settings.output.mp4.as_deref()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_mp4_crf(settings.output.mp4_crf as i32);
+    ui.set_keep_frames(settings.output.keep_frames);
+    ui.set_animation_fps(settings.animation.fps);
+    ui.set_animation_loops(settings.animation.loops as i32);
+    ui.set_animation_realtime(settings.animation.realtime);
+    ui.set_animation_max_frame_ms(settings.animation.max_frame_ms as i32);
+    ui.set_layers_only(settings.output.layers == processing::LayerOutput::Only);
+    ui.set_watch_settle_secs(settings.watch_settle_secs);
+    ui.set_watch_auto_start(settings.watch_auto_start);
+    ui.set_reuse_previous_settings(settings.reuse_previous_settings);
+    ui.set_toml_settings(settings.config_format == config::ConfigFormat::Toml);
+    ui.set_reload_settings_file(settings.reload_settings_file);
+    ui.set_settings_backups(settings.settings_backups as i32);
+    ui.set_odim_dataset(settings.odim.dataset as i32);
+    ui.set_odim_quantity(settings.odim.quantity.as_str().into());
+    ui.set_motion_vectors(settings.motion.enabled);
+    ui.set_motion_blur(settings.rendering.motion_blur);
+    ui.set_strobe(settings.rendering.strobe as i32);
+    ui.set_strobe_dim(settings.rendering.strobe_dim);
+    ui.set_history_scale(settings.rendering.history_scale);
+    ui.set_size_mismatch(match settings.rendering.size_mismatch {
+        processing::SizeMismatch::Fail => 0,
+        processing::SizeMismatch::Resize => 1,
+        processing::SizeMismatch::Pad => 2,
+    });
+    ui.set_resize_filter(match settings.rendering.resize_filter {
+        processing::ResizeFilter::Nearest => 0,
+        processing::ResizeFilter::Bilinear => 1,
+        processing::ResizeFilter::CatmullRom => 2,
+        processing::ResizeFilter::Lanczos3 => 3,
+    });
+    ui.set_preroll(match settings.rendering.preroll {
+        processing::Preroll::None => 0,
+        processing::Preroll::Hold => 1,
+        processing::Preroll::Mirror => 2,
+        processing::Preroll::Wrap => 3,
+    });
+    ui.set_motion_grid(settings.motion.grid_spacing as i32);
+    ui.set_motion_scale(settings.motion.vector_scale);
+    ui.set_motion_min_intensity(settings.motion.min_intensity as i32);
+    ui.set_motion_min_coherence(settings.motion.min_coherence);
+    ui.set_motion_color(settings.motion.color.as_str().into());
+    ui.set_motion_save_json(settings.motion.save_json);
+    ui.set_motion_export_dir(settings.motion.export_dir.as_deref()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_motion_export_binary(settings.motion.export_format == processing::MotionExportFormat::Binary);
+    ui.set_motion_pixel_size(settings.motion.pixel_size_km.unwrap_or(0.0));
+    ui.set_track_centroids(settings.tracking.enabled);
+    ui.set_track_min_intensity(settings.tracking.min_intensity as i32);
+    ui.set_track_min_area(settings.tracking.min_area as i32);
+    ui.set_track_max_displacement(settings.tracking.max_displacement as i32);
+    ui.set_track_color(settings.tracking.color.as_str().into());
+    ui.set_track_label_ids(settings.tracking.label_ids);
+    ui.set_track_max_missed(settings.tracking.max_missed as i32);
+    ui.set_track_save_csv(settings.tracking.save_csv);
+    ui.set_nowcast_frames(settings.nowcast.frames as i32);
+    ui.set_interpolate_frames(settings.interpolation.frames as i32);
+    ui.set_interpolate_write(settings.interpolation.write_frames);
+    ui.set_interpolate_history(settings.interpolation.in_history);
+    ui.set_resample_minutes(settings.resample.interval_minutes as i32);
+    ui.set_resample_source_names(settings.resample.naming == processing::ResampleNaming::Source);
+    ui.set_secondary_folder(settings.secondary.folder.as_deref()
+        .map(|folder| folder.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .into());
+    ui.set_secondary_color(settings.secondary.color.as_str().into());
+    ui.set_secondary_history_color(settings.secondary.history_color.as_str().into());
+    ui.set_secondary_opacity(settings.secondary.opacity);
+    ui.set_range_gain(settings.range_correction.gain_per_100km);
+    ui.set_range_center(processing::format_radar_center(settings.range_correction.center).into());
+    ui.set_range_pixel_size(settings.range_correction.pixel_size_km);
+    ui.set_range_max_gain(settings.range_correction.max_gain);
+    ui.set_range_emit_weights(settings.range_correction.emit_weights);
+    ui.set_static_mask_samples(settings.static_mask.samples as i32);
+    ui.set_static_mask_tolerance(settings.static_mask.tolerance as i32);
+    ui.set_static_mask_dilate(settings.static_mask.dilate as i32);
+    
+    // Parse hex colors to RGB components
+    if let Ok((r, g, b)) = processing::parse_hex_color(&settings.rendering.background_color) {
+        ui.set_bg_r(r as i32);
+        ui.set_bg_g(g as i32);
+        ui.set_bg_b(b as i32);
+    }
+    if let Ok((r, g, b)) = processing::parse_hex_color(&settings.rendering.current_color) {
+        ui.set_cur_r(r as i32);
+        ui.set_cur_g(g as i32);
+        ui.set_cur_b(b as i32);
+    }
+    if let Ok((r, g, b)) = processing::parse_hex_color(&settings.rendering.history_color) {
+        ui.set_hist_r(r as i32);
+        ui.set_hist_g(g as i32);
+        ui.set_hist_b(b as i32);
+    }
+    show_color_warnings(ui, settings);
+}
+
+/// List color pairs that are hard to tell apart beside the color pickers, and
+/// spell out the echo rule or where it fails to parse
+fn show_color_warnings(ui: &AppWindow, settings: &config::Settings) {
+    let warnings: Vec<String> = color::check_contrast(&processing::ProcessingSettings::from(settings.clone()))
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect();
+    ui.set_color_warnings(warnings.join("\n").into());
+    let note = match EchoPredicate::parse(&settings.rendering.echo_rule) {
+        Ok(predicate) => predicate.describe(),
+        Err(e) => format!("⚠ Echo rule {}", e),
+    };
+    ui.set_echo_rule_note(note.into());
+    let note = match processing::parse_history_weights(&ui.get_history_weights()) {
+        Ok(weights) => processing::check_history_weights("weights", &weights, weights.len())
+            .map(|invalid| format!("⚠ History weights must be {}", invalid.expected))
+            .unwrap_or_default(),
+        Err(e) => format!("⚠ History weights: {}", e),
+    };
+    ui.set_history_weights_note(note.into());
+    let note = match processing::parse_fade_curve(&ui.get_fade_curve()) {
+        Ok(_) => String::new(),
+        Err(e) => format!("⚠ Fade curve: {}; fading linearly", e),
+    };
+    ui.set_fade_curve_note(note.into());
+    let note = match processing::parse_history_gradient(&ui.get_history_gradient()) {
+        Ok(_) => String::new(),
+        Err(e) => format!("⚠ History gradient: {}; using the history color", e),
+    };
+    ui.set_history_gradient_note(note.into());
+    let history_colors = processing::parse_history_colors(&ui.get_history_colors());
+    let note = match processing::check_history_colors("colors", &history_colors) {
+        None => String::new(),
+        Some(_) => "⚠ History colors: not all are hex colors; those that are not are skipped".to_string(),
+    };
+    ui.set_history_colors_note(note.into());
+    let note = match processing::parse_radar_center(&ui.get_range_center()) {
+        Ok(_) => String::new(),
+        Err(e) => format!("⚠ Radar site: {}; using the middle of the frame", e),
+    };
+    ui.set_range_center_note(note.into());
+    let note = match processing::parse_canvas_size(&ui.get_canvas_size()) {
+        Ok(size) => processing::check_canvas_size("size", size)
+            .map(|invalid| format!("⚠ Canvas size must be {}", invalid.expected))
+            .unwrap_or_default(),
+        Err(e) => format!("⚠ Canvas size: {}; drawing on the frames as they are", e),
+    };
+    ui.set_canvas_size_note(note.into());
+    let note = match processing::parse_canvas_offset(&ui.get_canvas_offset()) {
+        Ok(_) => String::new(),
+        Err(e) => format!("⚠ Placement offset: {}; placing at the top left", e),
+    };
+    ui.set_canvas_offset_note(note.into());
+}
+
+/// List recently added folders in the Recent menu, followed by an entry clearing them
+fn refresh_recent(ui: &AppWindow) {
+    let recent = config::recent();
+    let mut items: Vec<MenuItem> = recent.iter()
+        .map(|folder| MenuItem {
+            text: folder.path.to_string_lossy().as_ref().into(),
+            trailing_text: if folder.recursive { "recursive".into() } else { SharedString::new() },
+            enabled: true,
+            ..Default::default()
+        })
+        .collect();
+    items.push(MenuItem {
+        text: "Clear Recent".into(),
+        enabled: !recent.is_empty(),
+        ..Default::default()
+    });
+    ui.set_recent_folders(ModelRc::new(VecModel::from(items)));
+}
+
+/// List the presets in the drop-down and mark `current` as selected
+fn refresh_presets(ui: &AppWindow, current: &str) {
+    let items: Vec<MenuItem> = config::list_presets().into_iter()
+        .map(|name| MenuItem {
+            trailing_text: if config::is_builtin_preset(&name) { "built-in".into() } else { SharedString::new() },
+            text: name.into(),
+            enabled: true,
+            ..Default::default()
+        })
+        .collect();
+    ui.set_presets(ModelRc::new(VecModel::from(items)));
+    ui.set_current_preset(current.into());
+    ui.set_preset_deletable(!current.is_empty() && !config::is_builtin_preset(current));
+}
+
+/// The preset named on the command line by `--preset <name>` or `--preset=<name>`
+fn preset_argument(mut args: impl Iterator<Item = String>) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == "--preset" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--preset=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// Collect the current UI properties into persistable settings
+fn settings_from_ui(ui: &AppWindow) -> config::Settings {
+    // Weights that don't parse are left out; the note under the field says why
+    let history_weights = processing::parse_history_weights(&ui.get_history_weights()).unwrap_or_default();
+    config::Settings {
+        limit: (ui.get_limit() > 0).then(|| ui.get_limit() as usize),
+        offset: ui.get_frame_offset().max(0) as usize,
+        stride: ui.get_frame_stride().max(1) as usize,
+        preflight: ui.get_preflight(),
+        repeated_frames: match (ui.get_find_repeats(), ui.get_keep_repeats()) {
+            (false, _) => processing::RepeatPolicy::Off,
+            (true, false) => processing::RepeatPolicy::Drop,
+            (true, true) => processing::RepeatPolicy::Flag,
+        },
+        on_error: if ui.get_stop_on_error() {
+            processing::ErrorPolicy::FailFolder
+        } else {
+            processing::ErrorPolicy::SkipFrame
+        },
+        ignore_manifests: ui.get_ignore_manifests(),
+        include_hidden: ui.get_include_hidden(),
+        sort_order: sort_order_from_ui(ui),
+        import_path_mappings: ui.get_path_mappings()
+            .split(';')
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect(),
+        watch_folder: if ui.get_watch_folder().is_empty() {
+            None
+        } else {
+            Some(ui.get_watch_folder().as_str().into())
+        },
+        watch_settle_secs: ui.get_watch_settle_secs(),
+        watch_auto_start: ui.get_watch_auto_start(),
+        reuse_previous_settings: ui.get_reuse_previous_settings(),
+        config_format: if ui.get_toml_settings() {
+            config::ConfigFormat::Toml
+        } else {
+            config::ConfigFormat::Json
+        },
+        reload_settings_file: ui.get_reload_settings_file(),
+        settings_backups: ui.get_settings_backups().max(0) as usize,
+        active_preset: Some(ui.get_current_preset().to_string()).filter(|name| !name.is_empty()),
+        rendering: config::RenderingSettings {
+            // The weights, one for each history frame, set the history length
+            history_length: match history_weights.len() {
+                0 => ui.get_history_length().max(0) as usize,
+                count => count,
+            },
+            background_color: format!("#{:02x}{:02x}{:02x}", ui.get_bg_r(), ui.get_bg_g(), ui.get_bg_b()),
+            current_color: format!("#{:02x}{:02x}{:02x}", ui.get_cur_r(), ui.get_cur_g(), ui.get_cur_b()),
+            history_color: format!("#{:02x}{:02x}{:02x}", ui.get_hist_r(), ui.get_hist_g(), ui.get_hist_b()),
+            history_gradient: ui.get_history_gradient().trim().to_string(),
+            history_colors: processing::parse_history_colors(&ui.get_history_colors()),
+            gradient_space: match ui.get_gradient_hsv() {
+                true => processing::GradientSpace::Hsv,
+                false => processing::GradientSpace::LinearRgb,
+            },
+            motion_blur: ui.get_motion_blur(),
+            strobe: ui.get_strobe().max(0) as u32,
+            strobe_dim: ui.get_strobe_dim(),
+            history_scale: ui.get_history_scale(),
+            resize_filter: match ui.get_resize_filter() {
+                0 => processing::ResizeFilter::Nearest,
+                2 => processing::ResizeFilter::CatmullRom,
+                3 => processing::ResizeFilter::Lanczos3,
+                _ => processing::ResizeFilter::Bilinear,
+            },
+            size_mismatch: match ui.get_size_mismatch() {
+                1 => processing::SizeMismatch::Resize,
+                2 => processing::SizeMismatch::Pad,
+                _ => processing::SizeMismatch::Fail,
+            },
+            preroll: match ui.get_preroll() {
+                1 => processing::Preroll::Hold,
+                2 => processing::Preroll::Mirror,
+                3 => processing::Preroll::Wrap,
+                _ => processing::Preroll::None,
+            },
+            light_background: ui.get_light_background(),
+            alpha_source: match ui.get_luminance_alpha() {
+                true => processing::AlphaSource::Luminance,
+                false => processing::AlphaSource::AlphaChannel,
+            },
+            background_image: match ui.get_background_image().trim() {
+                "" => None,
+                path => Some(path.into()),
+            },
+            fit_background_image: ui.get_fit_background_image(),
+            transparent_background: ui.get_transparent_background(),
+            legacy_fade: ui.get_legacy_fade(),
+            history_weights,
+            fade_curve: ui.get_fade_curve().to_string(),
+            history_opacity: ui.get_history_opacity().clamp(1, 255) as u8,
+            trail_mode: match ui.get_decay_trails() {
+                true => processing::TrailMode::Decay,
+                false => processing::TrailMode::Fade,
+            },
+            decay: ui.get_decay(),
+            current_style: match (ui.get_current_original(), ui.get_current_solid()) {
+                (true, _) => processing::CurrentStyle::Original,
+                (false, true) => processing::CurrentStyle::Solid,
+                (false, false) => processing::CurrentStyle::Scaled,
+            },
+            history_style: match ui.get_history_original() {
+                true => processing::HistoryStyle::Original,
+                false => processing::HistoryStyle::Tinted,
+            },
+            echo_rule: ui.get_echo_rule().trim().to_string(),
+        },
+        output: config::OutputSettings {
+            default_root: match ui.get_output_root().trim() {
+                "" => None,
+                root => Some(root.into()),
+            },
+            name_template: match ui.get_output_name_template().trim() {
+                "" => processing::default_output_name_template(),
+                template => template.to_string(),
+            },
+            force: ui.get_force(),
+            append: ui.get_append(),
+            resume: ui.get_resume(),
+            on_collision: collision_policy_from_ui(ui),
+            layers: match (ui.get_write_layers(), ui.get_layers_only()) {
+                (false, _) => processing::LayerOutput::Off,
+                (true, false) => processing::LayerOutput::Alongside,
+                (true, true) => processing::LayerOutput::Only,
+            },
+            coverage_plot: ui.get_coverage_plot(),
+            png_palette: ui.get_png_palette(),
+            tile_output: ui.get_tile_output(),
+            latest_image: match ui.get_latest_image().trim() {
+                "" => None,
+                path => Some(path.into()),
+            },
+            latest_json: ui.get_latest_json(),
+            renumber: ui.get_renumber().then(|| ui.get_renumber_prefix().trim().to_string()),
+            renumber_width: ui.get_renumber_width().max(0) as u32,
+            emit_index: match ui.get_emit_index().trim() {
+                "" => None,
+                path => Some(path.into()),
+            },
+            animate_gif: match ui.get_animate_gif().trim() {
+                "" => None,
+                path => Some(path.into()),
+            },
+            gif_colors: ui.get_gif_colors().clamp(2, processing::MAX_GIF_COLORS as i32) as usize,
+            gif_dither: match ui.get_gif_dither() {
+                1 => processing::GifDither::Ordered,
+                2 => processing::GifDither::FloydSteinberg,
+                _ => processing::GifDither::None,
+            },
+            mp4: match ui.get_mp4().trim() {
+                "" => None,
+                path => Some(path.into()),
+            },
+            mp4_crf: ui.get_mp4_crf().max(0) as u32,
+            keep_frames: ui.get_keep_frames(),
+        },
+        performance: config::PerformanceSettings {
+            threads: ui.get_threads().max(0) as usize,
+            io_threads: ui.get_io_threads().max(0) as usize,
+            folder_parallelism: ui.get_folder_parallelism().max(1) as usize,
+            cache_mb: ui.get_cache_mb().max(0) as u32,
+            tile_size: ui.get_tile_size().max(0) as u32,
+        },
+        odim: processing::OdimSelection {
+            dataset: ui.get_odim_dataset().max(1) as u32,
+            quantity: ui.get_odim_quantity().trim().to_string(),
+        },
+        motion: processing::MotionSettings {
+            enabled: ui.get_motion_vectors(),
+            grid_spacing: ui.get_motion_grid().max(0) as u32,
+            vector_scale: ui.get_motion_scale(),
+            min_intensity: ui.get_motion_min_intensity().clamp(0, 255) as u8,
+            min_coherence: ui.get_motion_min_coherence(),
+            color: ui.get_motion_color().trim().to_string(),
+            save_json: ui.get_motion_save_json(),
+            export_dir: match ui.get_motion_export_dir().trim() {
+                "" => None,
+                dir => Some(dir.into()),
+            },
+            export_format: if ui.get_motion_export_binary() {
+                processing::MotionExportFormat::Binary
+            } else {
+                processing::MotionExportFormat::Json
+            },
+            pixel_size_km: Some(ui.get_motion_pixel_size()).filter(|&size| size > 0.0),
+        },
+        tracking: processing::TrackingSettings {
+            enabled: ui.get_track_centroids(),
+            min_intensity: ui.get_track_min_intensity().clamp(0, 255) as u8,
+            min_area: ui.get_track_min_area().max(0) as u32,
+            max_displacement: ui.get_track_max_displacement().max(0) as u32,
+            color: ui.get_track_color().trim().to_string(),
+            label_ids: ui.get_track_label_ids(),
+            max_missed: ui.get_track_max_missed().max(0) as u32,
+            save_csv: ui.get_track_save_csv(),
+        },
+        nowcast: processing::NowcastSettings {
+            frames: ui.get_nowcast_frames().max(0) as u32,
+        },
+        interpolation: processing::InterpolationSettings {
+            frames: ui.get_interpolate_frames().max(0) as u32,
+            write_frames: ui.get_interpolate_write(),
+            in_history: ui.get_interpolate_history(),
+        },
+        resample: processing::ResampleSettings {
+            interval_minutes: ui.get_resample_minutes().max(0) as u32,
+            naming: if ui.get_resample_source_names() {
+                processing::ResampleNaming::Source
+            } else {
+                processing::ResampleNaming::Grid
+            },
+        },
+        animation: processing::AnimationTiming {
+            fps: ui.get_animation_fps(),
+            loops: ui.get_animation_loops().max(0) as u32,
+            realtime: ui.get_animation_realtime(),
+            max_frame_ms: ui.get_animation_max_frame_ms().max(0) as u32,
+        },
+        secondary: processing::SecondarySettings {
+            folder: match ui.get_secondary_folder().trim() {
+                "" => None,
+                folder => Some(folder.into()),
+            },
+            color: ui.get_secondary_color().trim().to_string(),
+            history_color: ui.get_secondary_history_color().trim().to_string(),
+            opacity: ui.get_secondary_opacity(),
+        },
+        range_correction: processing::RangeCorrection {
+            gain_per_100km: ui.get_range_gain(),
+            center: processing::parse_radar_center(&ui.get_range_center()).unwrap_or_default(),
+            pixel_size_km: ui.get_range_pixel_size(),
+            max_gain: ui.get_range_max_gain(),
+            emit_weights: ui.get_range_emit_weights(),
+        },
+        static_mask: processing::StaticMaskSettings {
+            samples: ui.get_static_mask_samples().max(0) as usize,
+            tolerance: ui.get_static_mask_tolerance().clamp(0, 255) as u8,
+            dilate: ui.get_static_mask_dilate().max(0) as u32,
+        },
+        canvas: processing::CanvasSettings {
+            size: processing::parse_canvas_size(&ui.get_canvas_size()).unwrap_or_default(),
+            placement: match ui.get_canvas_placement() {
+                1 => processing::Placement::TopLeft,
+                2 => {
+                    let (x, y) = processing::parse_canvas_offset(&ui.get_canvas_offset()).unwrap_or_default();
+                    processing::Placement::Custom { x, y }
+                }
+                _ => processing::Placement::Center,
+            },
+        },
+    }
+}
+
+/// Build the processing settings from the current UI properties
+fn processing_settings_from_ui(ui: &AppWindow) -> processing::ProcessingSettings {
+    settings_from_ui(ui).into()
+}
+
+fn collision_policy_from_ui(ui: &AppWindow) -> processing::CollisionPolicy {
+    if ui.get_overwrite_changed() {
+        processing::CollisionPolicy::Overwrite
+    } else {
+        processing::CollisionPolicy::NewSuffix
+    }
+}
+
+/// Ask the user for a folder, scan it and append it to the queue
+fn pick_and_add_folder(ui: &AppWindow, folders: &Arc<Mutex<queue::JobQueue>>, recursive: bool) {
+    let mut dialog = rfd::FileDialog::new().set_title("Select folder containing image frames");
+    // Start next to the folder added last, since the same archive roots come up again
+    if let Some(parent) = config::recent().first().and_then(|recent| recent.path.parent().map(Path::to_path_buf)) {
+        dialog = dialog.set_directory(parent);
+    }
+    let Some(path) = dialog.pick_folder() else {
+        return;
+    };
+    add_folder_to_queue(ui, folders, &path, recursive);
+}
+
+/// Add a folder to the queue, asking before accepting an overlap with a queued entry
+fn add_folder_to_queue(ui: &AppWindow, folders: &Arc<Mutex<queue::JobQueue>>, path: &Path, recursive: bool) {
+    let scan_options = scan_options_from_ui(ui, recursive);
+    let folder_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Unknown")
+        .to_string();
+    
+    let first_try = folders.lock().unwrap().add_folder(path, scan_options.clone(), false);
+    let result = match first_try {
+        Err(err @ queue::AddFolderError::Overlaps { .. }) => {
+            // Overlaps are sometimes intended; let the user decide
+            let confirmed = rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title("Overlapping folder")
+                .set_description(format!("{} {}.\n\nAdd it anyway?", folder_name, err))
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .show();
+            if confirmed != rfd::MessageDialogResult::Yes {
+                return;
+            }
+            folders.lock().unwrap().add_folder(path, scan_options, true)
+        }
+        other => other,
+    };
+    
+    match result {
+        Ok(index) => {
+            let _ = config::add_recent(path, recursive);
+            refresh_recent(ui);
+            offer_previous_settings(ui, folders, index, true);
+            estimate_folder_output(ui, folders, index);
+            thumbnail_folder(folders, index);
+            
+            // Report entries we could not read so a short count isn't a mystery
+            let queue = folders.lock().unwrap();
+            let scan = &queue.folders()[index].scan;
+            let unreadable: Vec<_> = scan.unreadable().collect();
+            if scan.files.is_empty() {
+                ui.set_status_text(SharedString::from(format!("Added {}: {}", folder_name, scan.describe_empty())));
+            } else if let Some((first_path, reason)) = unreadable.first() {
+                ui.set_status_text(SharedString::from(format!(
+                    "Added {} ({} unreadable, e.g. {}: {})",
+                    folder_name,
+                    unreadable.len(),
+                    first_path.display(),
+                    reason
+                )));
+            } else if scan.hidden_count() > 0 {
+                ui.set_status_text(SharedString::from(format!(
+                    "Added {} ({} hidden, system or empty files skipped)",
+                    folder_name,
+                    scan.hidden_count()
+                )));
+            }
+        }
+        Err(e) => {
+            ui.set_status_text(SharedString::from(format!("Not added: {} {}", folder_name, e)));
+            return;
+        }
+    }
+    
+    update_folder_model(ui, folders.lock().unwrap().folders());
+}
+
+/// Ask for an exported settings file and apply it, merged with or replacing the local-only fields
+fn import_settings(ui: &AppWindow, watcher: &Rc<RefCell<Option<watch::FolderWatcher>>>) {
+    let Some(path) = rfd::FileDialog::new()
+        .set_title("Import settings")
+        .add_filter("Settings", &["json", "toml"])
+        .pick_file()
+    else {
+        return;
+    };
+    let imported = match config::import_settings(&path) {
+        Ok(imported) => imported,
+        Err(e) => {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Settings not imported")
+                .set_description(format!("{}: {}", path.display(), e))
+                .set_buttons(rfd::MessageButtons::Ok)
+                .show();
+            return;
+        }
+    };
+    
+    let mode = match rfd::MessageDialog::new()
+        .set_title("Import settings")
+        .set_description("Keep this machine's watched folder and import path mappings?\n\nChoose No to replace them with the imported ones too.")
+        .set_buttons(rfd::MessageButtons::YesNoCancel)
+        .show()
+    {
+        rfd::MessageDialogResult::Yes => config::ImportMode::Merge,
+        rfd::MessageDialogResult::No => config::ImportMode::Replace,
+        _ => return,
+    };
+    let current = settings_from_ui(ui);
+    let settings = mode.apply(&current, imported);
+    apply_settings_to_ui(ui, &settings);
+    refresh_presets(ui, "");
+    if settings.watch_folder != current.watch_folder {
+        match &settings.watch_folder {
+            Some(parent) => start_watching(ui, watcher, parent),
+            None => {
+                *watcher.borrow_mut() = None;
+                ui.set_watch_folder(SharedString::new());
+            }
+        }
+    }
+    let _ = config::save_settings(&settings_from_ui(ui));
+    ui.set_status_text(SharedString::from(format!("Imported settings from {}", path.display())));
+}
+
+/// Ask for a queue document and add its folders and settings
+fn import_queue(ui: &AppWindow, folders: &Arc<Mutex<queue::JobQueue>>) {
+    let Some(path) = rfd::FileDialog::new()
+        .set_title("Import queue")
+        .add_filter("Queue", &["json"])
+        .pick_file()
+    else {
+        return;
+    };
+    let document = match queue::load_queue_document(&path) {
+        Ok(document) => document,
+        Err(e) => {
+            ui.set_status_text(SharedString::from(format!("Import failed: {:#}", e)));
+            return;
+        }
+    };
+    
+    let mut mappings = Vec::new();
+    for mapping in settings_from_ui(ui).import_path_mappings {
+        match mapping.parse::<queue::PathMapping>() {
+            Ok(mapping) => mappings.push(mapping),
+            Err(e) => {
+                ui.set_status_text(SharedString::from(format!("Import failed: bad path mapping: {}", e)));
+                return;
+            }
+        }
+    }
+    
+    // The document's settings replace the current ones, keeping UI-only preferences
+    let mut settings = settings_from_ui(ui);
+    let mut processing = document.settings.clone();
+    processing.output_root = processing.output_root.map(|root| queue::remap_path(&root, &mappings));
+    processing.background_image = processing.background_image.map(|path| queue::remap_path(&path, &mappings));
+    settings.set_processing(&processing);
+    apply_settings_to_ui(ui, &settings);
+    let _ = config::save_settings(&settings);
+    
+    let report = folders.lock().unwrap().import(&document, &mappings);
+    for &index in &report.added {
+        let folder = folders.lock().unwrap().folders()[index].clone();
+        let _ = config::add_recent(&folder.path, folder.scan_options.recursive);
+        estimate_folder_output(ui, folders, index);
+        thumbnail_folder(folders, index);
+    }
+    refresh_recent(ui);
+    update_folder_model(ui, folders.lock().unwrap().folders());
+    
+    ui.set_status_text(SharedString::from(format!(
+        "Imported {} of {} folders from {}",
+        report.added.len(),
+        document.folders.len(),
+        path.display()
+    )));
+    if !report.rejected.is_empty() {
+        let lines: Vec<String> = report.rejected.iter()
+            .map(|(path, reason)| format!("{}: {}", path.display(), reason))
+            .collect();
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Some folders were not imported")
+            .set_description(lines.join("\n"))
+            .set_buttons(rfd::MessageButtons::Ok)
+            .show();
+    }
+}
+
+/// The frame order chosen in the settings panel
+fn sort_order_from_ui(ui: &AppWindow) -> queue::SortOrder {
+    match ui.get_sort_order() {
+        1 => queue::SortOrder::Lexical,
+        2 => queue::SortOrder::Mtime,
+        _ => queue::SortOrder::Natural,
+    }
+}
+
+/// Scan options for folders added from the UI
+fn scan_options_from_ui(ui: &AppWindow, recursive: bool) -> queue::ScanOptions {
+    let mut scan_options = queue::ScanOptions {
+        recursive,
+        include_hidden: ui.get_include_hidden(),
+        sort: sort_order_from_ui(ui),
+        ..Default::default()
+    };
+    if ui.get_ignore_manifests() {
+        scan_options.manifest_name = None;
+    }
+    scan_options
+}
+
+/// Give a re-added folder the settings it was last processed with, when they
+/// differ from the current ones. They are used without asking when
+/// "Reuse Previous Settings" is on; otherwise only if `ask` and the user agrees.
+fn offer_previous_settings(ui: &AppWindow, folders: &Arc<Mutex<queue::JobQueue>>, index: usize, ask: bool) {
+    let (id, path, name) = {
+        let queue = folders.lock().unwrap();
+        let folder = &queue.folders()[index];
+        (folder.id, folder.path.clone(), folder.name.clone())
+    };
+    let Some(previous) = config::folder_history(&path) else { return };
+    let current = processing_settings_from_ui(ui);
+    let differences = current.output_differences(&previous.settings);
+    if differences.is_empty() {
+        return;
+    }
+    
+    let reuse = ui.get_reuse_previous_settings() || (ask && rfd::MessageDialog::new()
+        .set_title("Previous settings")
+        .set_description(format!(
+            "{} was processed on {} with different settings:\n\n{}\n\nUse the previous settings for this folder?",
+            name,
+            metadata::format_date(previous.completed_at),
+            differences.join("\n")
+        ))
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show() == rfd::MessageDialogResult::Yes);
+    if reuse && let Some(folder) = folders.lock().unwrap().get_mut(id) {
+        folder.settings = Some(previous.settings);
+    }
+}
+
+/// Size the expected output of a queued folder with the settings as they are now
+fn estimate_folder_output(ui: &AppWindow, folders: &Arc<Mutex<queue::JobQueue>>, index: usize) {
+    let (id, files, settings) = {
+        let queue = folders.lock().unwrap();
+        let folder = &queue.folders()[index];
+        let settings = folder.effective_settings(&processing_settings_from_ui(ui));
+        (folder.id, settings.select_frames(&folder.scan.files), settings)
+    };
+
+    // Render the sample without holding the lock the processing thread needs
+    let estimate = processing::estimate_output_bytes(&files, &settings);
+    if let Some(folder) = folders.lock().unwrap().get_mut(id) {
+        folder.estimated_output_bytes = estimate;
+    }
+}
+
+/// Pixel size of the queue thumbnails' longer side
+const THUMBNAIL_PX: u32 = 96;
+
+/// Fetch or make the thumbnail of a queued folder; failures leave the placeholder
+fn thumbnail_folder(folders: &Arc<Mutex<queue::JobQueue>>, index: usize) {
+    let folder = folders.lock().unwrap().folders()[index].clone();
+    let thumbnail = queue::cached_thumbnail(&folder, THUMBNAIL_PX).ok();
+    if let Some(queued) = folders.lock().unwrap().get_mut(folder.id) {
+        queued.thumbnail = thumbnail;
+    }
+}
+
+/// Start watching `parent` for new frame folders, replacing any previous watch
+fn start_watching(ui: &AppWindow, watcher: &Rc<RefCell<Option<watch::FolderWatcher>>>, parent: &Path) {
+    let settle = Duration::from_secs(ui.get_watch_settle_secs().max(0) as u64);
+    match watch::FolderWatcher::start(parent, settle) {
+        Ok(started) => {
+            *watcher.borrow_mut() = Some(started);
+            ui.set_watch_folder(SharedString::from(parent.to_string_lossy().as_ref()));
+            ui.set_status_text(SharedString::from(format!("Watching {}", parent.display())));
+        }
+        Err(e) => {
+            *watcher.borrow_mut() = None;
+            ui.set_watch_folder(SharedString::new());
+            ui.set_status_text(SharedString::from(format!("Cannot watch {}: {}", parent.display(), e)));
+        }
+    }
+}
+
+/// Queue settled folders reported by the watcher, returning how many were added.
+///
+/// Trail outputs written next to their sources and folders whose output already
+/// holds run metadata from an earlier session are left alone.
+fn enqueue_watched_folders(ui: &AppWindow, folders: &Arc<Mutex<queue::JobQueue>>, candidates: Vec<PathBuf>) -> usize {
+    let settings = processing_settings_from_ui(ui);
+    let mut added = 0;
+    
+    let include_hidden = ui.get_include_hidden();
+    for path in candidates {
+        let hidden = path.file_name().is_some_and(queue::is_hidden_name);
+        if (hidden && !include_hidden) || metadata::has_run_metadata(&path) {
+            continue;
+        }
+        let is_queued_output = folders.lock().unwrap().folders().iter()
+            .any(|f| processing::is_output_dir_of(&f.path, &settings, &path));
+        if is_queued_output || metadata::has_run_metadata(&processing::output_dir_for(&path, &settings)) {
+            continue;
+        }
+        
+        // Duplicates and overlaps with queued folders are silently skipped
+        let result = folders.lock().unwrap().add_folder(&path, scan_options_from_ui(ui, false), false);
+        if let Ok(index) = result {
+            offer_previous_settings(ui, folders, index, false);
+            estimate_folder_output(ui, folders, index);
+            thumbnail_folder(folders, index);
+            added += 1;
+        }
+    }
+    
+    if added > 0 {
+        ui.set_status_text(SharedString::from(format!("Watch: queued {} new folder(s)", added)));
+        update_folder_model(ui, folders.lock().unwrap().folders());
+    }
+    added
 }
 
 /// Update the folder model in the UI from the internal state
 fn update_folder_model(ui: &AppWindow, folders: &[queue::FolderInfo]) {
+    let global = processing_settings_from_ui(ui);
     let items: Vec<FolderItem> = folders.iter().map(|f| {
         FolderItem {
             path: f.path.to_string_lossy().to_string().into(),
             name: f.name.clone().into(),
-            file_count: f.file_count as i32,
+            // The frames the offset, limit and stride leave to render
+            file_count: f.effective_settings(&global).selected_count(f.file_count) as i32,
+            uses_manifest: f.scan.manifest.is_some(),
+            own_settings: f.settings.is_some(),
+            has_thumbnail: f.thumbnail.is_some(),
+            thumbnail: f.thumbnail.as_deref()
+                .and_then(|path| slint::Image::load_from_path(path).ok())
+                .unwrap_or_default(),
+            size_text: match f.estimated_output_bytes {
+                Some(estimate) => format!("{} → ~{}", format_bytes(f.scan.total_bytes), format_bytes(estimate)),
+                None => format_bytes(f.scan.total_bytes),
+            }.into(),
             status: match f.status {
                 queue::FolderStatus::Pending => "pending".into(),
                 queue::FolderStatus::Processing => "processing".into(),
                 queue::FolderStatus::Complete => "complete".into(),
                 queue::FolderStatus::Error => "error".into(),
+                queue::FolderStatus::Cancelled => "cancelled".into(),
+                queue::FolderStatus::Skipped => "skipped".into(),
             },
             progress: f.progress,
             error_message: f.error_message.clone().unwrap_or_default().into(),
@@ -404,4 +1835,59 @@ fn update_folder_model(ui: &AppWindow, folders: &[queue::FolderInfo]) {
     
     let model = Rc::new(VecModel::from(items));
     ui.set_folders(ModelRc::from(model));
+    
+    let estimated_total: u64 = folders.iter().filter_map(|f| f.estimated_output_bytes).sum();
+    ui.set_estimated_output_text(if estimated_total > 0 {
+        SharedString::from(format!("~{} output", format_bytes(estimated_total)))
+    } else {
+        SharedString::new()
+    });
+}
+
+/// Format a byte count for display, e.g. "1.4 GB"
+/// Progress of a folder being rendered
+struct FolderFiles {
+    name: String,
+    done: usize,
+    total: usize,
+    per_second: f64,
+}
+
+/// Show the folders being rendered in the progress bar, their files added
+/// together when several render at once
+fn show_in_flight(ui: &AppWindow, in_flight: &BTreeMap<u64, FolderFiles>) {
+    let Some(first) = in_flight.values().next() else { return };
+    let name = match in_flight.len() {
+        1 => first.name.clone(),
+        count => format!("{} and {} more", first.name, count - 1),
+    };
+    ui.set_current_folder(name.into());
+    let done: usize = in_flight.values().map(|files| files.done).sum();
+    let total: usize = in_flight.values().map(|files| files.total).sum();
+    let per_second: f64 = in_flight.values().map(|files| files.per_second).sum();
+    ui.set_folder_progress(done as f32 / total.max(1) as f32);
+    ui.set_files_completed(done as i32);
+    ui.set_files_total(total as i32);
+    ui.set_files_per_second(per_second as f32);
+    
+    // Calculate ETA
+    if per_second > 0.0 {
+        let eta_secs = (total.saturating_sub(done) as f64 / per_second) as u64;
+        ui.set_eta_text(SharedString::from(format!("{:02}:{:02}", eta_secs / 60, eta_secs % 60)));
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
 }
@@ -0,0 +1,133 @@
+//! Run metadata written alongside each folder's output
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::dedup::RepeatedFrame;
+use crate::engine::{AgeContribution, RunTiming};
+use crate::sidecar::AppliedOverrides;
+use crate::processing::ProcessingSettings;
+use crate::queue::ManifestUsage;
+
+/// File name of the metadata inside an output directory
+pub const RUN_METADATA_FILE: &str = "run_parameters.json";
+
+/// What produced a folder's output and from which frames
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub app_version: String,
+    pub source: PathBuf,
+    /// Seconds since the Unix epoch when the folder finished
+    pub completed_at: u64,
+    pub frames_total: usize,
+    pub frames_failed: usize,
+    /// Frames written before the run ended
+    #[serde(default)]
+    pub frames_completed: usize,
+    /// Frames of `frames_completed` an interrupted run had already written,
+    /// kept rather than rendered again
+    #[serde(default)]
+    pub frames_skipped: usize,
+    /// The folder was cancelled part way; its output is incomplete
+    #[serde(default)]
+    pub cancelled: bool,
+    pub settings: ProcessingSettings,
+    /// [`ProcessingSettings::output_hash`] of `settings`, to recognise outputs that are up to date
+    #[serde(default)]
+    pub settings_hash: String,
+    /// Manifest that defined the frame list, if one was used
+    pub manifest: Option<ManifestUsage>,
+    /// How overlapping queue entries changed the frame list
+    #[serde(default)]
+    pub plan_notes: Vec<String>,
+    /// Pixels each history age added to the trails, nearest first
+    #[serde(default)]
+    pub age_contributions: Vec<AgeContribution>,
+    /// Where the run's workers spent their time
+    #[serde(default)]
+    pub timing: Option<RunTiming>,
+    /// Frames repeating an earlier frame's pixels, dropped or kept as the settings say
+    #[serde(default)]
+    pub repeated_frames: Vec<RepeatedFrame>,
+    /// Frames whose handling their sidecar files changed
+    #[serde(default)]
+    pub frame_overrides: Vec<AppliedOverrides>,
+    /// The source frames written, in order and relative to `source`, which
+    /// a run appending to this output carries on from
+    #[serde(default)]
+    pub sources: Vec<PathBuf>,
+}
+
+impl RunMetadata {
+    pub fn new(source: &Path, settings: &ProcessingSettings) -> Self {
+        RunMetadata {
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            source: source.to_path_buf(),
+            completed_at: 0,
+            frames_total: 0,
+            frames_failed: 0,
+            frames_completed: 0,
+            frames_skipped: 0,
+            cancelled: false,
+            settings: settings.clone(),
+            settings_hash: settings.output_hash(),
+            manifest: None,
+            plan_notes: Vec::new(),
+            age_contributions: Vec::new(),
+            timing: None,
+            repeated_frames: Vec::new(),
+            frame_overrides: Vec::new(),
+            sources: Vec::new(),
+        }
+    }
+
+    /// The output hash of the settings, computed for metadata written before it was recorded
+    pub fn output_hash(&self) -> String {
+        if self.settings_hash.is_empty() {
+            self.settings.output_hash()
+        } else {
+            self.settings_hash.clone()
+        }
+    }
+}
+
+/// Read the metadata from `output_dir`, if there is a readable one
+pub fn read_run_metadata(output_dir: &Path) -> Option<RunMetadata> {
+    let content = std::fs::read_to_string(output_dir.join(RUN_METADATA_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Whether `output_dir` holds the metadata of a run that was not cancelled
+pub fn has_run_metadata(output_dir: &Path) -> bool {
+    read_run_metadata(output_dir).is_some_and(|m| !m.cancelled)
+}
+
+/// Format seconds since the Unix epoch as a UTC calendar date, e.g. "2024-05-17"
+pub fn format_date(unix_secs: u64) -> String {
+    // Days to civil date, after Howard Hinnant's algorithm
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Stamp the completion time and write the metadata into `output_dir`
+pub fn write_run_metadata(output_dir: &Path, metadata: &mut RunMetadata) -> Result<()> {
+    metadata.completed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = output_dir.join(RUN_METADATA_FILE);
+    let content = serde_json::to_string_pretty(metadata)?;
+    std::fs::write(&path, content).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,318 @@
+//! Motion vectors between consecutive frames, drawn as arrows over the trail
+//! or used to smear history frames into streaks
+//!
+//! Echo displacement is estimated by block matching on the frames' intensity,
+//! coarse to fine: each grid cell is first searched for over the whole grid
+//! spacing on a downsampled copy of the previous frame, then the match is
+//! refined within two pixels on each finer copy down to full resolution.
+//! Dim cells, and cells whose best match is barely better than the rest, such
+//! as uniform areas, get no vector.
+
+use image::{Rgba, RgbaImage};
+use serde::Serialize;
+
+use crate::draw;
+use crate::settings::{self, MotionSettings};
+
+/// Displacement of the echo in one grid cell from the previous frame to this one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct MotionVector {
+    /// Centre of the cell, in pixels
+    pub x: u32,
+    pub y: u32,
+    /// Displacement in pixels, positive right and down
+    pub dx: f32,
+    pub dy: f32,
+    /// How much better the match is than the average candidate, from 0 to 1
+    pub coherence: f32,
+}
+
+/// Intensity of a frame, or a downsampled copy of it
+struct Plane {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+
+/// Echo intensity of a pixel, out of 255: its grey level weighted by its
+/// opacity, so transparent pixels count as empty
+pub fn intensity(pixel: &Rgba<u8>) -> f32 {
+    (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32) * pixel[3] as f32 / 255.0
+}
+
+impl Plane {
+    fn from_frame(frame: &RgbaImage) -> Plane {
+        let values = frame.pixels().map(intensity).collect();
+        Plane { width: frame.width() as usize, height: frame.height() as usize, values }
+    }
+
+    /// Half the size, each pixel the mean of four
+    fn halved(&self) -> Plane {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let mut values = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let at = |dx: usize, dy: usize| self.values[(2 * y + dy) * self.width + 2 * x + dx];
+                values.push((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) / 4.0);
+            }
+        }
+        Plane { width, height, values }
+    }
+
+    fn block_mean(&self, x: usize, y: usize, size: usize) -> f32 {
+        let sum: f32 = (y..y + size)
+            .map(|row| self.values[row * self.width + x..row * self.width + x + size].iter().sum::<f32>())
+            .sum();
+        sum / (size * size) as f32
+    }
+}
+
+/// Mean absolute difference between the block of `current` at (`x`, `y`) and the
+/// block of `previous` it came from when displaced by (`dx`, `dy`); `None` when
+/// that block lies outside the frame
+fn block_difference(previous: &Plane, current: &Plane, x: usize, y: usize, size: usize, dx: i64, dy: i64) -> Option<f32> {
+    let (px, py) = (x as i64 - dx, y as i64 - dy);
+    if px < 0 || py < 0 || px as usize + size > previous.width || py as usize + size > previous.height {
+        return None;
+    }
+    let (px, py) = (px as usize, py as usize);
+    let mut sum = 0.0;
+    for row in 0..size {
+        let now = &current.values[(y + row) * current.width + x..][..size];
+        let before = &previous.values[(py + row) * previous.width + px..][..size];
+        sum += now.iter().zip(before).map(|(a, b)| (a - b).abs()).sum::<f32>();
+    }
+    Some(sum / (size * size) as f32)
+}
+
+/// The displacement within `radius` of `guess` that matches best, with its difference
+fn best_match(
+    previous: &Plane, current: &Plane, x: usize, y: usize, size: usize, guess: (i64, i64), radius: i64,
+) -> Option<((i64, i64), f32, f32)> {
+    let mut best: Option<((i64, i64), f32)> = None;
+    let (mut total, mut count) = (0.0, 0);
+    for dy in guess.1 - radius..=guess.1 + radius {
+        for dx in guess.0 - radius..=guess.0 + radius {
+            let Some(difference) = block_difference(previous, current, x, y, size, dx, dy) else { continue };
+            total += difference;
+            count += 1;
+            // Ties go to the smaller displacement, so still echoes stay still
+            let better = best.is_none_or(|(at, least)| {
+                difference < least || (difference == least && dx.abs() + dy.abs() < at.0.abs() + at.1.abs())
+            });
+            if better {
+                best = Some(((dx, dy), difference));
+            }
+        }
+    }
+    best.map(|(at, difference)| (at, difference, total / count as f32))
+}
+
+/// What the estimate made of one grid cell, with the code it is exported as
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CellQuality {
+    /// Matched; the cell has a vector
+    Valid = 0,
+    /// Dimmer than the minimum intensity, so not matched
+    TooDim = 1,
+    /// The best match was less distinct than the minimum coherence
+    Incoherent = 2,
+}
+
+/// The motion estimate over the whole grid, cells without a vector included
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MotionGrid {
+    /// Side of a cell, in pixels
+    pub spacing: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// Quality of each cell, row by row
+    pub quality: Vec<CellQuality>,
+    /// Vectors of the valid cells, row by row
+    pub vectors: Vec<MotionVector>,
+}
+
+/// Estimate the motion from `previous` to `current` on a grid of `settings.grid_spacing`
+pub fn estimate(previous: &RgbaImage, current: &RgbaImage, settings: &MotionSettings) -> Vec<MotionVector> {
+    estimate_grid(previous, current, settings).vectors
+}
+
+/// [`estimate`], keeping what became of every cell. Frames of different sizes
+/// have an empty grid.
+pub fn estimate_grid(previous: &RgbaImage, current: &RgbaImage, settings: &MotionSettings) -> MotionGrid {
+    let spacing = settings.grid_spacing.max(1) as usize;
+    if previous.dimensions() != current.dimensions() {
+        return MotionGrid { spacing: spacing as u32, ..MotionGrid::default() };
+    }
+
+    // Halve until a cell is about four pixels across, to search the whole spacing cheaply
+    let mut levels = vec![(Plane::from_frame(previous), Plane::from_frame(current))];
+    while spacing >> levels.len() >= 4 {
+        let (before, now) = levels.last().unwrap();
+        let halved = (before.halved(), now.halved());
+        levels.push(halved);
+    }
+    let full = &levels[0].1;
+
+    let mut grid = MotionGrid {
+        spacing: spacing as u32,
+        columns: (full.width / spacing) as u32,
+        rows: (full.height / spacing) as u32,
+        ..MotionGrid::default()
+    };
+    for y in (0..full.height.saturating_sub(spacing - 1)).step_by(spacing) {
+        for x in (0..full.width.saturating_sub(spacing - 1)).step_by(spacing) {
+            if full.block_mean(x, y, spacing) < settings.min_intensity as f32 {
+                grid.quality.push(CellQuality::TooDim);
+                continue;
+            }
+
+            let coarsest = levels.len() - 1;
+            let (before, now) = &levels[coarsest];
+            let size = spacing >> coarsest;
+            let Some((mut at, least, mean)) = best_match(before, now, x >> coarsest, y >> coarsest, size, (0, 0), size as i64)
+            else {
+                grid.quality.push(CellQuality::Incoherent);
+                continue;
+            };
+            let coherence = if mean > 0.0 { 1.0 - least / mean } else { 0.0 };
+            if coherence < settings.min_coherence {
+                grid.quality.push(CellQuality::Incoherent);
+                continue;
+            }
+            for level in (0..coarsest).rev() {
+                let (before, now) = &levels[level];
+                let guess = (at.0 * 2, at.1 * 2);
+                at = best_match(before, now, x >> level, y >> level, spacing >> level, guess, 2)
+                    .map_or(guess, |(at, _, _)| at);
+            }
+
+            grid.quality.push(CellQuality::Valid);
+            grid.vectors.push(MotionVector {
+                x: (x + spacing / 2) as u32,
+                y: (y + spacing / 2) as u32,
+                dx: at.0 as f32,
+                dy: at.1 as f32,
+                coherence,
+            });
+        }
+    }
+    grid
+}
+
+/// Displacement per frame at any pixel, from motion vectors on a grid: that
+/// of the nearest cell with a vector, within a cell of the pixel's own. Cells
+/// without one count as still.
+pub struct FlowField {
+    spacing: u32,
+    columns: u32,
+    rows: u32,
+    cells: Vec<Option<(f32, f32)>>,
+}
+
+impl FlowField {
+    /// The field of `vectors` estimated on a grid of `spacing` pixels over a
+    /// frame of `width` by `height`
+    pub fn new(vectors: &[MotionVector], width: u32, height: u32, spacing: u32) -> FlowField {
+        let spacing = spacing.max(1);
+        let (columns, rows) = (width.div_ceil(spacing), height.div_ceil(spacing));
+        let mut cells = vec![None; (columns * rows) as usize];
+        for vector in vectors {
+            let (column, row) = (vector.x / spacing, vector.y / spacing);
+            if column < columns && row < rows {
+                cells[(row * columns + column) as usize] = Some((vector.dx, vector.dy));
+            }
+        }
+        FlowField { spacing, columns, rows, cells }
+    }
+
+    /// Displacement at the pixel (`x`, `y`)
+    pub fn at(&self, x: u32, y: u32) -> (f32, f32) {
+        let (column, row) = ((x / self.spacing) as i64, (y / self.spacing) as i64);
+        let centre = |c: i64| (c as f32 + 0.5) * self.spacing as f32;
+        let mut nearest: Option<(f32, (f32, f32))> = None;
+        for r in (row - 1).max(0)..=(row + 1).min(self.rows as i64 - 1) {
+            for c in (column - 1).max(0)..=(column + 1).min(self.columns as i64 - 1) {
+                let Some(vector) = self.cells[(r * self.columns as i64 + c) as usize] else { continue };
+                let distance = (centre(c) - x as f32).hypot(centre(r) - y as f32);
+                if nearest.is_none_or(|(least, _)| distance < least) {
+                    nearest = Some((distance, vector));
+                }
+            }
+        }
+        nearest.map_or((0.0, 0.0), |(_, vector)| vector)
+    }
+}
+
+/// `frame` with each echo pixel drawn as a streak along its displacement in
+/// `flow`, from where it is to where it is going, instead of a point. A pixel's
+/// opacity is spread evenly along its streak, and each point of the streak is
+/// shared between the four pixels around it, so streaks are anti-aliased and
+/// a solid echo keeps its opacity inside. Pixels moving less than a pixel stay
+/// points.
+pub fn smear(frame: &RgbaImage, flow: &FlowField) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    // Colors weighted by alpha, then alpha, per pixel
+    let mut ink = vec![[0.0f32; 4]; width as usize * height as usize];
+    let mut deposit = |x: f32, y: f32, pixel: &Rgba<u8>, share: f32| {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let corners = [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)];
+        for (dx, dy, weight) in corners {
+            let (px, py) = (x0 as i64 + dx, y0 as i64 + dy);
+            if weight == 0.0 || px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                continue;
+            }
+            let alpha = pixel[3] as f32 * share * weight;
+            let cell = &mut ink[py as usize * width as usize + px as usize];
+            for i in 0..3 {
+                cell[i] += pixel[i] as f32 * alpha;
+            }
+            cell[3] += alpha;
+        }
+    };
+    for (x, y, pixel) in frame.enumerate_pixels().filter(|(_, _, pixel)| pixel[3] > 0) {
+        let (dx, dy) = flow.at(x, y);
+        let length = dx.hypot(dy);
+        if length < 1.0 {
+            deposit(x as f32, y as f32, pixel, 1.0);
+            continue;
+        }
+        let points = length.ceil() as usize + 1;
+        for i in 0..points {
+            let t = i as f32 / (points - 1) as f32;
+            deposit(x as f32 + t * dx, y as f32 + t * dy, pixel, 1.0 / points as f32);
+        }
+    }
+    RgbaImage::from_fn(width, height, |x, y| {
+        let [r, g, b, alpha] = ink[y as usize * width as usize + x as usize];
+        if alpha <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let channel = |sum: f32| (sum / alpha).round() as u8;
+        Rgba([channel(r), channel(g), channel(b), alpha.min(255.0).round() as u8])
+    })
+}
+
+/// `frame` smeared along its motion towards `next`, the frame after it, as
+/// [`estimate`] finds it on the grid of `settings`; `None` when there is no
+/// motion to follow, as between frames of different sizes or without echo
+pub fn blur_towards(frame: &RgbaImage, next: &RgbaImage, settings: &MotionSettings) -> Option<RgbaImage> {
+    let vectors = estimate(frame, next, settings);
+    if vectors.iter().all(|v| v.dx == 0.0 && v.dy == 0.0) {
+        return None;
+    }
+    Some(smear(frame, &FlowField::new(&vectors, frame.width(), frame.height(), settings.grid_spacing)))
+}
+
+/// Draw each vector as an arrow from its cell centre, `settings.vector_scale`
+/// times the displacement long. Cells that did not move get no arrow.
+pub fn draw_vectors(canvas: &mut RgbaImage, vectors: &[MotionVector], settings: &MotionSettings) {
+    let (r, g, b) = settings::parse_hex_color(&settings.color).unwrap_or((255, 255, 255));
+    for vector in vectors.iter().filter(|v| v.dx != 0.0 || v.dy != 0.0) {
+        let from = (vector.x as f32, vector.y as f32);
+        let to = (from.0 + vector.dx * settings.vector_scale, from.1 + vector.dy * settings.vector_scale);
+        draw::draw_arrow(canvas, from, to, Rgba([r, g, b, 255]));
+    }
+}
@@ -0,0 +1,151 @@
+//! Motion grids exported for other programs, one file per frame
+//!
+//! Each file holds the grid the arrows are drawn from, as [`motion::estimate_grid`]
+//! finds it with the same spacing and thresholds: the `u` (right) and `v`
+//! (down) displacement of every cell in pixels per scan, and a quality flag
+//! per cell. Cells without a vector have no displacement: `null` in JSON and
+//! NaN in the binary form. When the scan times are known from the file names,
+//! the header carries the frame's time and the interval from the frame before,
+//! and with a pixel size the speeds follow in km/h as `u_kmh` and `v_kmh`.
+//!
+//! The JSON form is a single document. The binary form starts with the length
+//! of a JSON header as a little-endian `u32`, then the header, then the
+//! arrays back to back, each described in the header's `arrays` list by name,
+//! type (`f32le` or `u8`), byte offset from the end of the header and length.
+//! Arrays run row by row from the top left cell.
+//!
+//! [`motion::estimate_grid`]: crate::motion::estimate_grid
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::metadata;
+use crate::motion::MotionGrid;
+use crate::settings::MotionExportFormat;
+
+/// The motion of one frame and when it was scanned
+#[derive(Clone, Copy, Debug)]
+pub struct FrameMotion<'a> {
+    pub source: &'a Path,
+    /// Scan time, in seconds since the Unix epoch
+    pub time: Option<i64>,
+    /// Seconds since the frame before, which the displacement is measured from
+    pub interval: Option<i64>,
+    pub grid: &'a MotionGrid,
+}
+
+/// Name of the exported file for the frame made from `source`, such as `scan_0001.uv.json`
+pub fn export_name(source: &Path, format: MotionExportFormat) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = match format {
+        MotionExportFormat::Json => "uv.json",
+        MotionExportFormat::Binary => "uv.bin",
+    };
+    PathBuf::from(format!("{}.{}", stem, extension))
+}
+
+/// The displacement arrays: `u` and `v` in pixels per scan, then, when the
+/// speed is known, `u_kmh` and `v_kmh`, all with NaN where there is no vector
+fn arrays(motion: &FrameMotion, pixel_size_km: Option<f32>) -> Vec<(&'static str, Vec<f32>)> {
+    let grid = motion.grid;
+    let cells = (grid.columns * grid.rows) as usize;
+    let (mut u, mut v) = (vec![f32::NAN; cells], vec![f32::NAN; cells]);
+    for vector in &grid.vectors {
+        let (column, row) = (vector.x / grid.spacing, vector.y / grid.spacing);
+        if column < grid.columns && row < grid.rows {
+            let cell = (row * grid.columns + column) as usize;
+            (u[cell], v[cell]) = (vector.dx, vector.dy);
+        }
+    }
+    let speeds = kmh_per_pixel(motion, pixel_size_km)
+        .map(|kmh| (u.iter().map(|u| u * kmh).collect(), v.iter().map(|v| v * kmh).collect()));
+    let mut arrays = vec![("u", u), ("v", v)];
+    if let Some((u_kmh, v_kmh)) = speeds {
+        arrays.extend([("u_kmh", u_kmh), ("v_kmh", v_kmh)]);
+    }
+    arrays
+}
+
+/// Speed in km/h of a displacement of one pixel per scan, when both the pixel
+/// size and the interval are known
+fn kmh_per_pixel(motion: &FrameMotion, pixel_size_km: Option<f32>) -> Option<f32> {
+    let interval = motion.interval.filter(|&seconds| seconds > 0)?;
+    Some(pixel_size_km? * 3600.0 / interval as f32)
+}
+
+/// Format seconds since the Unix epoch as a UTC time, e.g. "2024-06-01T12:05:00Z"
+fn format_time(seconds: i64) -> String {
+    let of_day = seconds.rem_euclid(86_400);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        metadata::format_date(seconds.max(0) as u64),
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60
+    )
+}
+
+/// What the file describes, apart from the arrays
+fn header(motion: &FrameMotion, pixel_size_km: Option<f32>) -> Value {
+    let grid = motion.grid;
+    json!({
+        "source": motion.source.file_name().unwrap_or_default().to_string_lossy(),
+        "time": motion.time,
+        "time_utc": motion.time.map(format_time),
+        "interval_seconds": motion.interval,
+        "grid_spacing": grid.spacing,
+        "columns": grid.columns,
+        "rows": grid.rows,
+        "units": "pixels per scan, positive right and down",
+        "pixel_size_km": pixel_size_km,
+        "quality_codes": {
+            "0": "valid",
+            "1": "below the minimum intensity",
+            "2": "below the minimum coherence",
+        },
+    })
+}
+
+/// Encode a frame's motion as a JSON document
+pub fn to_json(motion: &FrameMotion, pixel_size_km: Option<f32>) -> Vec<u8> {
+    let mut document = header(motion, pixel_size_km);
+    for (name, values) in arrays(motion, pixel_size_km) {
+        let values: Vec<Value> = values.into_iter()
+            .map(|value| if value.is_nan() { Value::Null } else { json!(value) })
+            .collect();
+        document[name] = Value::Array(values);
+    }
+    document["quality"] = json!(motion.grid.quality.iter().map(|&quality| quality as u8).collect::<Vec<u8>>());
+    serde_json::to_vec(&document).expect("a JSON value always serializes")
+}
+
+/// Encode a frame's motion as a JSON header followed by flat arrays
+pub fn to_binary(motion: &FrameMotion, pixel_size_km: Option<f32>) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut described = Vec::new();
+    for (name, values) in arrays(motion, pixel_size_km) {
+        described.push(json!({ "name": name, "type": "f32le", "offset": data.len(), "length": values.len() }));
+        data.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+    }
+    let quality: Vec<u8> = motion.grid.quality.iter().map(|&quality| quality as u8).collect();
+    described.push(json!({ "name": "quality", "type": "u8", "offset": data.len(), "length": quality.len() }));
+    data.extend(quality);
+
+    let mut header = header(motion, pixel_size_km);
+    header["arrays"] = Value::Array(described);
+    let header = serde_json::to_vec(&header).expect("a JSON value always serializes");
+    let mut bytes = Vec::with_capacity(4 + header.len() + data.len());
+    bytes.extend((header.len() as u32).to_le_bytes());
+    bytes.extend(header);
+    bytes.extend(data);
+    bytes
+}
+
+/// Encode a frame's motion in `format`
+pub fn encode(motion: &FrameMotion, format: MotionExportFormat, pixel_size_km: Option<f32>) -> Vec<u8> {
+    match format {
+        MotionExportFormat::Json => to_json(motion, pixel_size_km),
+        MotionExportFormat::Binary => to_binary(motion, pixel_size_km),
+    }
+}
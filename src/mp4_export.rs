@@ -0,0 +1,172 @@
+//! H.264 MP4 of a folder's trails, encoded by ffmpeg as they are rendered
+//!
+//! An [`Mp4Sequence`] starts ffmpeg once the first trail is in, when the frame
+//! size is known, and streams the trails to it as raw RGBA in order, from a
+//! [`SequenceWriter`](crate::sequence_writer::SequenceWriter), so a long
+//! sequence doesn't need thousands of PNGs written first. The video plays at
+//! the animation frame rate. In real time, a trail is repeated for as many
+//! video frames as [`animation::frame_durations`] holds it, so gaps in the
+//! archive play as gaps at a constant frame rate.
+//!
+//! ffmpeg writes to a hidden `.partial` file that is renamed into place once
+//! it exits cleanly. When it fails, the file is removed and the last lines
+//! it wrote to stderr are part of the error.
+//!
+//! [`animation::frame_durations`]: crate::animation::frame_durations
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::RgbaImage;
+
+use crate::sequence_writer::SequenceEncoder;
+
+/// Program run to encode MP4 output, looked up on the PATH
+pub const FFMPEG: &str = "ffmpeg";
+
+/// Lines at the end of ffmpeg's stderr kept to explain a failure
+const STDERR_LINES: usize = 12;
+
+/// Check that `ffmpeg` can be run, described for the user when it can't
+pub fn check_ffmpeg(ffmpeg: &Path) -> Result<()> {
+    let status = Command::new(ffmpeg)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!("{} -version failed ({})", ffmpeg.display(), status),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            bail!("{} was not found on the PATH; install ffmpeg to write MP4 output", ffmpeg.display())
+        }
+        Err(e) => Err(e).with_context(|| format!("running {}", ffmpeg.display())),
+    }
+}
+
+/// Encodes the trails of a sequence into an H.264 MP4 with ffmpeg
+pub struct Mp4Sequence {
+    ffmpeg: PathBuf,
+    path: PathBuf,
+    temp_path: PathBuf,
+    durations: Vec<u32>,
+    fps: f32,
+    crf: u32,
+    /// ffmpeg, once the first trail gave the frame size
+    encoding: Option<Encoding>,
+}
+
+/// A running ffmpeg and the frame size it was started for
+struct Encoding {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    stderr: JoinHandle<String>,
+    size: (u32, u32),
+}
+
+impl Mp4Sequence {
+    /// Start an MP4 at `path`, written by the program `ffmpeg`, at `fps`
+    /// frames a second and quality `crf`, the frame at position `i` held for
+    /// `durations[i]` milliseconds, or as long as the last of them past their end
+    pub fn create(ffmpeg: &Path, path: &Path, durations: Vec<u32>, fps: f32, crf: u32) -> Result<Mp4Sequence> {
+        check_ffmpeg(ffmpeg)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        Ok(Mp4Sequence {
+            ffmpeg: ffmpeg.to_path_buf(),
+            path: path.to_path_buf(),
+            temp_path: path.with_file_name(format!(".{}.partial", path.file_name().unwrap_or_default().to_string_lossy())),
+            durations,
+            fps,
+            crf,
+            encoding: None,
+        })
+    }
+
+    fn start(&self, (width, height): (u32, u32)) -> Result<Encoding> {
+        let mut child = Command::new(&self.ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-s", &format!("{}x{}", width, height)])
+            .args(["-framerate", &self.fps.to_string(), "-i", "-"])
+            // H.264 in 4:2:0 takes even sizes only
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .args(["-c:v", "libx264", "-crf", &self.crf.to_string(), "-pix_fmt", "yuv420p"])
+            .args(["-movflags", "+faststart", "-f", "mp4"])
+            .arg(&self.temp_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("starting {}", self.ffmpeg.display()))?;
+        let stdin = BufWriter::new(child.stdin.take().ok_or_else(|| anyhow!("ffmpeg has no stdin"))?);
+        let stderr = child.stderr.take().ok_or_else(|| anyhow!("ffmpeg has no stderr"))?;
+        // Read as it comes, so ffmpeg never blocks on a full pipe
+        let stderr = thread::spawn(move || {
+            let mut tail = VecDeque::with_capacity(STDERR_LINES);
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if tail.len() == STDERR_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            Vec::from(tail).join("\n")
+        });
+        Ok(Encoding { child, stdin, stderr, size: (width, height) })
+    }
+}
+
+impl SequenceEncoder for Mp4Sequence {
+    fn add(&mut self, position: usize, frame: RgbaImage) -> Result<()> {
+        let encoding = match &mut self.encoding {
+            Some(encoding) => encoding,
+            None => self.encoding.insert(self.start(frame.dimensions())?),
+        };
+        if frame.dimensions() != encoding.size {
+            bail!(
+                "a {}x{} frame can't follow {}x{} ones in a video",
+                frame.width(), frame.height(), encoding.size.0, encoding.size.1
+            );
+        }
+        let ms = self.durations.get(position).or(self.durations.last()).copied().unwrap_or(100);
+        let repeats = ((ms as f64 * self.fps as f64 / 1000.0).round() as usize).max(1);
+        for _ in 0..repeats {
+            encoding.stdin.write_all(frame.as_raw()).context("ffmpeg stopped reading frames")?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>, encoded: Result<()>) -> Result<()> {
+        let Mp4Sequence { path, temp_path, encoding, .. } = *self;
+        let Some(Encoding { mut child, mut stdin, stderr, .. }) = encoding else {
+            // Not a single trail came, so there is nothing to encode
+            return encoded.with_context(|| format!("saving {}", path.display()));
+        };
+        let encoded = encoded.and_then(|()| stdin.flush().context("ffmpeg stopped reading frames"));
+        drop(stdin);
+        if encoded.is_err() {
+            let _ = child.kill();
+        }
+        let status = child.wait().with_context(|| format!("waiting for ffmpeg to write {}", path.display()));
+        let stderr = stderr.join().unwrap_or_default();
+        let error = match (encoded, status) {
+            (Ok(()), Ok(status)) if status.success() => {
+                return fs::rename(&temp_path, &path).with_context(|| format!("saving {}", path.display()));
+            }
+            (Ok(()), Ok(status)) => anyhow!("ffmpeg failed ({})", status),
+            (Err(e), _) | (_, Err(e)) => e,
+        };
+        let _ = fs::remove_file(&temp_path);
+        let error = match stderr.trim() {
+            "" => error,
+            tail => anyhow!("{:#}\n{}", error, tail),
+        };
+        Err(error.context(format!("saving {}", path.display())))
+    }
+}
@@ -0,0 +1,129 @@
+//! Forecast frames extrapolated past the end of a sequence
+//!
+//! The echoes of the last frame are moved on along the mean motion between
+//! the last two frames, estimated as in [`crate::motion`], one step per scan
+//! interval. Forecast frames are drawn hatched and fainter than a real current
+//! frame and stamped `FORECAST`, and are named by their lead time when the
+//! scan interval can be read from the file names.
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use crate::draw;
+use crate::interpolation;
+use crate::motion::MotionVector;
+
+/// Scan time encoded in a file name as `YYYYMMDDHHMM`, optionally followed by
+/// seconds, in seconds since the Unix epoch. The digits may be split by
+/// separators, as in `radar_20240601_1205.png`; the first run of digits that
+/// reads as a valid date and time is used.
+pub fn scan_time(path: &Path) -> Option<i64> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let digits: Vec<i64> = stem.chars().filter_map(|c| c.to_digit(10)).map(i64::from).collect();
+    let number = |at: &[i64]| at.iter().fold(0, |n, d| n * 10 + d);
+    (0..digits.len().saturating_sub(11)).find_map(|start| {
+        let at = &digits[start..];
+        let (year, month, day) = (number(&at[..4]), number(&at[4..6]), number(&at[6..8]));
+        let (hour, minute) = (number(&at[8..10]), number(&at[10..12]));
+        let second = at.get(12..14).map(number).filter(|&s| s < 60).unwrap_or(0);
+        let valid = (1970..2100).contains(&year)
+            && (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && hour < 24
+            && minute < 60;
+        valid.then(|| days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+    })
+}
+
+/// `seconds` since the Unix epoch as the digits [`scan_time`] reads, `YYYYMMDDHHMMSS`
+pub fn scan_time_digits(seconds: i64) -> String {
+    // Days to civil date, after Howard Hinnant's algorithm
+    let days = seconds.div_euclid(86_400) + 719_468;
+    let of_day = seconds.rem_euclid(86_400);
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}{:02}{:02}{:02}{:02}{:02}", year, month, day, of_day / 3600, of_day / 60 % 60, of_day % 60)
+}
+
+/// Days from 1970-01-01 to the given date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Median time between consecutive frames, in seconds, from the scan times in
+/// their file names; `None` unless every frame has one and they increase
+pub fn median_interval(paths: &[PathBuf]) -> Option<i64> {
+    let times: Vec<i64> = paths.iter().map(|path| scan_time(path)).collect::<Option<_>>()?;
+    let mut intervals: Vec<i64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    if intervals.is_empty() || intervals.iter().any(|&interval| interval <= 0) {
+        return None;
+    }
+    intervals.sort_unstable();
+    Some(intervals[intervals.len() / 2])
+}
+
+/// Mean displacement of the cells that have echo, in pixels per frame; `None`
+/// when there are no vectors
+pub fn mean_motion(vectors: &[MotionVector]) -> Option<(f32, f32)> {
+    if vectors.is_empty() {
+        return None;
+    }
+    let count = vectors.len() as f32;
+    let (dx, dy) = vectors.iter().fold((0.0, 0.0), |(x, y), v| (x + v.dx, y + v.dy));
+    Some((dx / count, dy / count))
+}
+
+/// `frame` moved by `offset` pixels, sampled bilinearly. Areas moved in from
+/// outside the frame are transparent.
+pub fn advect(frame: &RgbaImage, offset: (f32, f32)) -> RgbaImage {
+    RgbaImage::from_fn(frame.width(), frame.height(), |x, y| {
+        interpolation::sample(frame, x as f32 - offset.0, y as f32 - offset.1)
+    })
+}
+
+/// Opacity of a forecast frame drawn as the current frame, out of 1
+pub const FORECAST_OPACITY: f32 = 0.6;
+
+/// `frame` as a forecast's current layer: fainter, and cut by diagonal hatching
+/// so it cannot be mistaken for an observation
+pub fn hatch(frame: &RgbaImage) -> RgbaImage {
+    let mut hatched = frame.clone();
+    for (x, y, pixel) in hatched.enumerate_pixels_mut() {
+        pixel[3] = if (x + y) % 6 < 2 { 0 } else { (pixel[3] as f32 * FORECAST_OPACITY) as u8 };
+    }
+    hatched
+}
+
+/// Lead time of forecast `step`, for its stamp and file name: `+05min` when the
+/// scan interval is known, `+1` otherwise
+pub fn lead_time(step: u32, interval: Option<i64>) -> String {
+    match interval {
+        Some(seconds) => format!("+{:02}min", (seconds * step as i64 + 30) / 60),
+        None => format!("+{}", step),
+    }
+}
+
+/// File name of forecast `step`, such as `forecast_+05min.png`
+pub fn forecast_name(step: u32, interval: Option<i64>) -> String {
+    format!("forecast_{}.png", lead_time(step, interval))
+}
+
+/// Stamp `FORECAST` and the lead time in the top left corner, white on a dark
+/// shadow so it reads on any background
+pub fn stamp(canvas: &mut RgbaImage, lead_time: &str) {
+    let text = format!("FORECAST {}", lead_time);
+    draw::draw_text(canvas, (9, 9), &text, 3, Rgba([0, 0, 0, 255]));
+    draw::draw_text(canvas, (8, 8), &text, 3, Rgba([255, 255, 255, 255]));
+}
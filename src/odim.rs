@@ -0,0 +1,202 @@
+//! ODIM_H5 radar files as frames, built with the `hdf5` feature
+//!
+//! Folders of `.h5` and `.hdf` files in the OPERA Data Information Model are
+//! queued and rendered like folders of images. The selected quantity of the
+//! selected scan is scaled to physical values with its `gain` and `offset` and
+//! drawn as a grey level, which the trail colors then tint; `nodata` and
+//! `undetect` pixels are transparent. Polar scans are drawn as a plan position
+//! indicator centred on the radar, Cartesian products as they are stored.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use hdf5::types::{FixedAscii, VarLenAscii};
+use hdf5::Group;
+use image::{Rgba, RgbaImage};
+
+use crate::queue::{FolderScan, SkipReason};
+use crate::settings::OdimSelection;
+
+/// Extensions of ODIM_H5 files
+pub const ODIM_EXTENSIONS: &[&str] = &["h5", "hdf"];
+
+/// Reflectivity at the bottom and top of the grey ramp, in dBZ. Weaker echoes
+/// are drawn black and stronger ones at full intensity.
+pub const DBZ_DISPLAY_RANGE: (f64, f64) = (-10.0, 70.0);
+
+pub fn is_odim_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ODIM_EXTENSIONS.iter().any(|odim| odim.eq_ignore_ascii_case(ext)))
+}
+
+/// What an ODIM file holds, read from its metadata without the pixel data
+#[derive(Clone, Debug, Default)]
+pub struct OdimInfo {
+    /// Start of the selected scan, or the file's nominal time, as `YYYYMMDDHHMMSS`
+    pub timestamp: Option<String>,
+    /// Quantities the selected scan holds, in file order
+    pub quantities: Vec<String>,
+}
+
+/// Read the timestamp and quantities of the scan `selection` names
+pub fn inspect(path: &Path, selection: &OdimSelection) -> Result<OdimInfo> {
+    let file = open(path)?;
+    let scan = scan_group(&file, selection)?;
+    let quantities = data_groups(&scan)?.iter()
+        .filter_map(|data| what_string(data, "quantity"))
+        .collect();
+    Ok(OdimInfo { timestamp: timestamp(&file, &scan), quantities })
+}
+
+/// Draw the product `selection` names as a frame
+pub fn read_frame(path: &Path, selection: &OdimSelection) -> Result<RgbaImage> {
+    let file = open(path)?;
+    let scan = scan_group(&file, selection)?;
+    let groups = data_groups(&scan)?;
+    let Some(data) = groups.iter().find(|data| what_string(data, "quantity").as_deref() == Some(&selection.quantity)) else {
+        let available: Vec<String> = groups.iter().filter_map(|data| what_string(data, "quantity")).collect();
+        return Err(anyhow!(
+            "{} has no {} in dataset{} (has {})",
+            path.display(), selection.quantity, selection.dataset, available.join(", ")
+        ));
+    };
+
+    let array = data.dataset("data").with_context(|| format!("reading {} from {}", selection, path.display()))?;
+    let shape = array.shape();
+    let &[rows, cols] = shape.as_slice() else {
+        return Err(anyhow!("{} in {} is not a two-dimensional array", selection, path.display()));
+    };
+    if rows == 0 || cols == 0 {
+        return Err(anyhow!("{} in {} is empty", selection, path.display()));
+    }
+    let raw: Vec<f64> = array.read_raw().with_context(|| format!("reading {} from {}", selection, path.display()))?;
+
+    // Attributes of a data group fall back to its scan's, then to the file's
+    let levels: [&Group; 3] = [data, &scan, &file];
+    let gain = what_f64(&levels, "gain").unwrap_or(1.0);
+    let offset = what_f64(&levels, "offset").unwrap_or(0.0);
+    let nodata = what_f64(&levels, "nodata");
+    let undetect = what_f64(&levels, "undetect");
+    let valid = |value: f64| Some(value) != nodata && Some(value) != undetect;
+
+    let (low, high) = if is_reflectivity(&selection.quantity) {
+        DBZ_DISPLAY_RANGE
+    } else {
+        // No fixed scale for other quantities; stretch over the values present
+        raw.iter().copied()
+            .filter(|&value| valid(value))
+            .map(|value| value * gain + offset)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)))
+    };
+    let span = if high > low { high - low } else { 1.0 };
+    let pixel = |value: f64| {
+        if !valid(value) {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let level = (((value * gain + offset - low) / span).clamp(0.0, 1.0) * 255.0).round() as u8;
+        Rgba([level, level, level, 255])
+    };
+
+    let object = what_string(&file, "object").unwrap_or_default();
+    if matches!(object.as_str(), "PVOL" | "SCAN") {
+        Ok(plan_position(&raw, rows, cols, pixel))
+    } else {
+        Ok(RgbaImage::from_fn(cols as u32, rows as u32, |x, y| pixel(raw[y as usize * cols + x as usize])))
+    }
+}
+
+/// Keep the ODIM files of a scan that hold the selected product and record the
+/// rest as skipped. Without a manifest the files are put in timestamp order;
+/// other files, and ODIM files without a time, keep their sorted places first.
+pub fn select_frames(scan: &mut FolderScan, selection: &OdimSelection) {
+    let mut timestamps: HashMap<PathBuf, String> = HashMap::new();
+    let mut kept = Vec::with_capacity(scan.files.len());
+    for path in std::mem::take(&mut scan.files) {
+        if !is_odim_file(&path) {
+            kept.push(path);
+            continue;
+        }
+        let reason = match inspect(&path, selection) {
+            Ok(info) if info.quantities.contains(&selection.quantity) => {
+                if let Some(timestamp) = info.timestamp {
+                    timestamps.insert(path.clone(), timestamp);
+                }
+                kept.push(path);
+                continue;
+            }
+            Ok(info) => SkipReason::MissingQuantity { wanted: selection.to_string(), available: info.quantities },
+            Err(e) => SkipReason::Unreadable(format!("{:#}", e)),
+        };
+        scan.total_bytes = scan.total_bytes.saturating_sub(std::fs::metadata(&path).map_or(0, |m| m.len()));
+        scan.skipped.push((path, reason));
+    }
+    if scan.manifest.is_none() {
+        kept.sort_by(|a, b| timestamps.get(a).cmp(&timestamps.get(b)));
+    }
+    scan.files = kept;
+}
+
+fn open(path: &Path) -> Result<hdf5::File> {
+    hdf5::File::open(path).with_context(|| format!("opening {}", path.display()))
+}
+
+fn scan_group(file: &hdf5::File, selection: &OdimSelection) -> Result<Group> {
+    file.group(&format!("dataset{}", selection.dataset))
+        .map_err(|_| anyhow!("no dataset{} in the file", selection.dataset))
+}
+
+/// The scan's `dataN` groups, in order of `N`
+fn data_groups(scan: &Group) -> Result<Vec<Group>> {
+    let mut numbered: Vec<(u32, String)> = scan.member_names()?
+        .into_iter()
+        .filter_map(|name| Some((name.strip_prefix("data")?.parse().ok()?, name)))
+        .collect();
+    numbered.sort();
+    numbered.iter()
+        .map(|(_, name)| scan.group(name).map_err(Into::into))
+        .collect()
+}
+
+fn is_reflectivity(quantity: &str) -> bool {
+    quantity.starts_with("DBZ") || matches!(quantity, "TH" | "TV")
+}
+
+/// Start of the scan, falling back to the file's nominal time
+fn timestamp(file: &hdf5::File, scan: &Group) -> Option<String> {
+    let scan_start = what_string(scan, "startdate").zip(what_string(scan, "starttime"));
+    let nominal = || what_string(file, "date").zip(what_string(file, "time"));
+    scan_start.or_else(nominal).map(|(date, time)| format!("{}{}", date, time))
+}
+
+/// An attribute of the group's `what` subgroup, as a string
+fn what_string(group: &Group, name: &str) -> Option<String> {
+    let attr = group.group("what").ok()?.attr(name).ok()?;
+    // ODIM strings are fixed length, but some writers use variable-length ones
+    attr.read_scalar::<FixedAscii<256>>().map(|s| s.as_str().to_string())
+        .or_else(|_| attr.read_scalar::<VarLenAscii>().map(|s| s.as_str().to_string()))
+        .ok()
+}
+
+/// A numeric `what` attribute from the first of `levels` that has it
+fn what_f64(levels: &[&Group], name: &str) -> Option<f64> {
+    levels.iter().find_map(|group| group.group("what").ok()?.attr(name).ok()?.read_scalar::<f64>().ok())
+}
+
+/// Project a polar scan of `rays` rays of `bins` range bins onto a square
+/// image centred on the radar, north up. Rays run clockwise from north.
+fn plan_position(raw: &[f64], rays: usize, bins: usize, pixel: impl Fn(f64) -> Rgba<u8>) -> RgbaImage {
+    let size = (bins * 2) as u32;
+    RgbaImage::from_fn(size, size, |x, y| {
+        let dx = x as f64 + 0.5 - bins as f64;
+        let dy = bins as f64 - (y as f64 + 0.5);
+        let bin = dx.hypot(dy) as usize;
+        if bin >= bins {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let azimuth = dx.atan2(dy).to_degrees().rem_euclid(360.0);
+        let ray = ((azimuth / 360.0 * rays as f64) as usize).min(rays - 1);
+        pixel(raw[ray * bins + bin])
+    })
+}
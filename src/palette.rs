@@ -0,0 +1,166 @@
+//! Indexed-color PNG output, from a palette worked out from the settings
+//!
+//! A trail holds few distinct colors: the background, the current color
+//! scaled by each grey level, the history color, or the color of each age
+//! along its gradient, faded to the opacity of each age over the background,
+//! and the colors of the overlays. [`Palette`] lists
+//! them directly, up to 256, rather than deriving a palette from each frame, so
+//! every frame of a folder shares it and nothing is dithered. Each pixel is
+//! written as the nearest palette entry; where ghosts overlap each other the
+//! nearest entry stands in for the blend. An 8-bit indexed PNG takes a
+//! fraction of the space of a true-color one.
+//!
+//! Trails whose colors the settings can't foretell, those of a custom shader or
+//! with a secondary folder blended in, can't be palettised.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::engine::{self, TrailColors, TrailSettings};
+use crate::settings::{self, CurrentStyle, HistoryStyle};
+
+/// Most colors an 8-bit palette holds
+pub const MAX_COLORS: usize = 256;
+
+/// The colors trails drawn with some settings are written in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// The palette of trails drawn with `settings`, or why they can't be palettised
+    pub fn for_settings(settings: &TrailSettings) -> Result<Palette, String> {
+        if settings.shader.is_some() {
+            return Err("a custom shader colors the frames".to_string());
+        }
+        if settings.current_style == CurrentStyle::Original || settings.history_style == HistoryStyle::Original {
+            return Err("the frames keep their own colors".to_string());
+        }
+        if settings.transparent_background {
+            return Err("the background is transparent".to_string());
+        }
+        if settings.background.is_some() {
+            return Err("a background image lies under the trails".to_string());
+        }
+        if settings.secondary.folder.is_some() {
+            return Err("the secondary folder's trail is blended over the primary one".to_string());
+        }
+        let colors = TrailColors::from_settings(settings);
+        let rgb = |(r, g, b): (u8, u8, u8)| [r, g, b];
+
+        let mut palette = Palette { colors: Vec::new() };
+        palette.add(rgb(colors.background));
+        if settings.motion.enabled {
+            palette.add(rgb(settings::parse_hex_color(&settings.motion.color).unwrap_or((255, 255, 255))));
+        }
+        if settings.tracking.enabled {
+            palette.add(rgb(settings::parse_hex_color(&settings.tracking.color).unwrap_or((255, 255, 255))));
+        }
+        if settings.nowcast.frames > 0 {
+            // The lead time stamped on forecasts
+            palette.add([0, 0, 0]);
+            palette.add([255, 255, 255]);
+        }
+
+        // Color and opacity of each history age drawn, nearest first
+        let count = settings.history_length;
+        let mut ages: Vec<((u8, u8, u8), u8)> = (1..=count)
+            .map(|frames_back| {
+                let age = engine::history_age(frames_back, count);
+                let fade = engine::history_fade(age, count, settings);
+                (colors.history_at(age, count), engine::history_alpha(fade, engine::strobe_opacity(frames_back, settings), settings))
+            })
+            .filter(|&(_, alpha)| alpha > 0)
+            .collect();
+        ages.dedup();
+        // With more ages than room for them, an even spread of them
+        let room = MAX_COLORS - palette.colors.len() - 1;
+        if ages.len() > room {
+            ages = (0..room).map(|i| ages[i * ages.len() / room]).collect();
+        }
+
+        // The rest of the palette goes to grey levels, the same for every ramp
+        let levels = (MAX_COLORS - palette.colors.len()) / (ages.len() + 1);
+        let background = Rgba([colors.background.0, colors.background.1, colors.background.2, 255]);
+        for level in 1..=levels {
+            let shade = |(r, g, b): (u8, u8, u8)| {
+                let intensity = level as f32 / levels as f32;
+                Rgba([(r as f32 * intensity) as u8, (g as f32 * intensity) as u8, (b as f32 * intensity) as u8, 255])
+            };
+            let current = shade(colors.current);
+            palette.add([current[0], current[1], current[2]]);
+            for &(color, alpha) in &ages {
+                let ghost = engine::blend_pixel(background, shade(color), alpha);
+                palette.add([ghost[0], ghost[1], ghost[2]]);
+            }
+        }
+        Ok(palette)
+    }
+
+    /// Add `color` unless it is there already or the palette is full
+    fn add(&mut self, color: [u8; 3]) {
+        if self.colors.len() < MAX_COLORS && !self.colors.contains(&color) {
+            self.colors.push(color);
+        }
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// The index of the palette entry nearest each pixel of `frame`, row by row.
+    /// Alpha is not kept; trails are opaque.
+    pub fn indices(&self, frame: &RgbaImage) -> Vec<u8> {
+        let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+        frame.pixels()
+            .map(|pixel| {
+                let color = [pixel[0], pixel[1], pixel[2]];
+                *nearest.entry(color).or_insert_with(|| self.nearest(color))
+            })
+            .collect()
+    }
+
+    /// `frame` as it reads back once written with the palette
+    pub fn quantize(&self, frame: &RgbaImage) -> RgbaImage {
+        let indices = self.indices(frame);
+        RgbaImage::from_fn(frame.width(), frame.height(), |x, y| {
+            let [r, g, b] = self.colors[indices[(y * frame.width() + x) as usize] as usize];
+            Rgba([r, g, b, 255])
+        })
+    }
+
+    fn nearest(&self, color: [u8; 3]) -> u8 {
+        let distance = |entry: &[u8; 3]| -> u32 {
+            (0..3).map(|channel| (entry[channel] as i32 - color[channel] as i32).pow(2) as u32).sum()
+        };
+        let (index, _) = self.colors.iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| distance(entry))
+            .expect("a palette always holds the background");
+        index as u8
+    }
+
+    /// Encode `frame` as an 8-bit indexed PNG into `writer`
+    pub fn encode<W: Write>(&self, frame: &RgbaImage, writer: W) -> Result<()> {
+        let mut encoder = png::Encoder::new(writer, frame.width(), frame.height());
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(self.colors.concat());
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.indices(frame))?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Write `frame` to `path` as an 8-bit indexed PNG
+    pub fn save(&self, path: &Path, frame: &RgbaImage) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        self.encode(frame, BufWriter::new(file))
+    }
+}
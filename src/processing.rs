@@ -1,281 +1,1562 @@
 //! RadarEchoTrails processing logic
 //!
-//! Motion trail generation for radar image sequences
+//! Running the folder queue: planning, output locations, checks and progress
+//! reporting. Frames are rendered by [`crate::engine`].
 
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::mpsc::Sender;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use anyhow::{anyhow, Context, Result};
-use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use anyhow::{anyhow, Result};
 use rayon::prelude::*;
 
+use crate::animation;
+use crate::basemap;
+use crate::config;
+use crate::dedup::{self, RepeatedFrame};
+use crate::engine::{self, DirectorySink, OutputFormat, RunSummary};
+use crate::geotiff::{self, Georeferencing};
+use crate::gif_export::{GifOptions, GifSequence};
+use crate::mp4_export::{self, Mp4Sequence};
+use crate::light;
+use crate::metadata::{self, RunMetadata};
+use crate::nowcast;
+use crate::palette::Palette;
+use crate::renumber::{self, MapEntry};
+use crate::resample::{self, Slot};
+use crate::secondary::{self, PairedBy};
+use crate::sequence_writer::SequenceWriter;
+use crate::sidecar::{self, AppliedOverrides, FrameOverrides};
+use crate::static_mask;
+use crate::queue::{
+    self, FolderInfo, FolderScan, FolderStatus, JobQueue, ManifestUsage, PlannedFolder, ScanError, SkipReason,
+};
 
-use crate::queue::{self, FolderInfo};
-
-#[derive(Clone)]
-pub struct ProcessingSettings {
-    pub history_length: usize,
-    pub background_color: String,
-    pub current_color: String,
-    pub history_color: String,
-    pub threads: usize,
-    pub limit: Option<usize>,
-}
+pub use crate::settings::{
+    check_animate_gif, check_canvas_size, check_coherence, check_color, check_echo_rule, check_fps, check_max_frame_ms, check_grid_spacing, check_history_length, check_history_scale,
+    check_decay, check_emit_index, check_history_colors, check_history_gradient, check_history_weights, check_mp4, check_mp4_crf, default_mp4_crf, check_gif_colors, default_gif_colors, GifDither, MAX_GIF_COLORS, check_interpolated_frames, check_latest_image, check_nowcast_frames, check_odim_dataset, check_odim_quantity, check_opacity,
+    check_max_range_gain, check_output_name_template, check_output_root, check_pixel_size, check_range_gain, check_renumber_prefix, check_renumber_width, check_resample_interval, check_static_mask_dilate, check_static_mask_samples, check_stride, check_tile_size, check_folder_parallelism, default_folder_parallelism, MAX_FOLDER_PARALLELISM,
+    check_fade_curve, check_history_opacity, default_history_opacity, parse_fade_curve, FadeCurve,
+    check_tracking_size, check_vector_scale, default_echo_rule, default_output_name_template, default_stride, format_history_colors, format_history_weights,
+    format_canvas_size, format_radar_center, parse_canvas_offset, parse_canvas_size, parse_hex_color, parse_history_colors, parse_history_gradient, parse_history_weights, parse_radar_center, AlphaSource, AnimationTiming, CanvasSettings, CollisionPolicy, CurrentStyle, ErrorPolicy, GradientSpace, HistoryStyle, InterpolationSettings, InvalidField,
+    LayerOutput, MotionExportFormat, MotionSettings, NowcastSettings, OdimSelection, Placement, ProcessingSettings,
+    Preroll, RangeCorrection, RepeatPolicy, ResampleNaming, ResizeFilter, ResampleSettings, SecondarySettings, SizeMismatch, StaticMaskSettings, TrackingSettings, TrailMode, DEFAULT_OUTPUT_NAME_TEMPLATE,
+};
 
 #[derive(Debug)]
 pub enum ProgressUpdate {
-    FolderStarted { folder_index: usize, folder_name: String },
+    FolderStarted {
+        folder_id: u64,
+        folder_name: String,
+        /// Estimated size of the folder's output, when a sample frame could be rendered
+        estimated_bytes: Option<u64>,
+        /// Frames the folder has to render before the offset, limit and stride
+        frames_found: usize,
+        /// Frames selected to render from them
+        frames_selected: usize,
+    },
+    /// The estimated output of the folders writing to `destination`'s volume exceeds its free space
+    InsufficientSpace {
+        destination: PathBuf,
+        required_bytes: u64,
+        available_bytes: u64,
+    },
     FileProgress { 
-        folder_index: usize, 
+        folder_id: u64, 
         files_done: usize, 
         files_total: usize,
         current_file: String,
         files_per_second: f64,
     },
-    FolderCompleted { folder_index: usize },
-    FolderError { folder_index: usize, error: String },
+    /// Trails of the folder encoded into its animated GIF so far
+    AnimationProgress {
+        folder_id: u64,
+        frames_encoded: usize,
+        frames_total: usize,
+    },
+    /// An animation of the folder's trails is written to `path`, `bytes` long
+    AnimationWritten { folder_id: u64, path: PathBuf, bytes: u64 },
+    /// Radar files left out of the folder because they lack the selected product, with why
+    FramesSkipped {
+        folder_id: u64,
+        skipped: Vec<(PathBuf, String)>,
+    },
+    /// Something about the folder the user should know, which does not stop it rendering
+    FolderWarning { folder_id: u64, message: String },
+    /// Pre-flight check finished; `corrupt` frames are dropped or fail the folder per the error policy
+    PreflightComplete {
+        folder_id: u64,
+        frames_checked: usize,
+        corrupt: Vec<(PathBuf, String)>,
+    },
+    /// Every frame of the folder is written; `frames_skipped` of them were
+    /// kept from an interrupted run rather than rendered again
+    FolderCompleted { folder_id: u64, frames_rendered: usize, frames_skipped: usize },
+    FolderError { folder_id: u64, error: String },
+    /// A frame, or another file of the folder's output, could not be read or
+    /// written; `error` holds its causes, outermost first. The folder carries
+    /// on past it unless it stops on errors, and ends in a
+    /// [`ProgressUpdate::FolderError`] counting the failures.
+    FileError { folder_id: u64, file: PathBuf, error: String },
+    /// Overlapping entries were resolved; notes explain how each affected folder changed
+    WorkPlanned { notes: Vec<(u64, String)> },
+    /// The folder's output already exists with the same settings, or it was merged into another entry
+    FolderSkipped { folder_id: u64, reason: String },
+    /// `existing_dir` holds output made with different settings; the folder is
+    /// reprocessed according to the collision policy
+    OutputSettingsDiffer {
+        folder_id: u64,
+        existing_dir: PathBuf,
+        differences: Vec<String>,
+    },
+    /// The folder was cancelled on its own; the rest of the queue carries on
+    FolderCancelled { folder_id: u64, frames_completed: usize },
+    /// The run was stopped part way through the folder; it is left to be
+    /// processed again, and [`ProgressUpdate::Cancelled`] follows once every
+    /// folder in flight has stopped
+    FolderStopped { folder_id: u64, frames_completed: usize },
+    /// The worker noticed folders were added, removed or reordered since it last looked
+    QueueChanged,
+    /// Settings reloaded from the settings file now apply to the folders still to start
+    SettingsReloaded { changed: Vec<String> },
     AllComplete,
     Cancelled,
 }
 
-/// Parse a hex color string to RGB
-fn parse_hex_color(hex: &str) -> Result<(u8, u8, u8)> {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() != 6 {
-        return Err(anyhow!("Invalid hex color: {}", hex));
+/// Receives the updates of a run. Reports come from several rendering threads
+/// at once, and a sink that can no longer deliver them drops them.
+pub trait ProgressSink: Sync {
+    fn report(&self, update: ProgressUpdate);
+}
+
+impl ProgressSink for mpsc::Sender<ProgressUpdate> {
+    fn report(&self, update: ProgressUpdate) {
+        let _ = self.send(update);
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+impl ProgressSink for crossbeam_channel::Sender<ProgressUpdate> {
+    fn report(&self, update: ProgressUpdate) {
+        let _ = self.send(update);
+    }
+}
+
+impl<S: ProgressSink + ?Sized> ProgressSink for &S {
+    fn report(&self, update: ProgressUpdate) {
+        (**self).report(update);
+    }
+}
+
+/// Passes each update to a closure
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use radar_echo_trails::processing::{ProgressFn, ProgressSink, ProgressUpdate};
+///
+/// let completed = AtomicUsize::new(0);
+/// let sink = ProgressFn(|update| {
+///     if let ProgressUpdate::FolderCompleted { .. } = update {
+///         completed.fetch_add(1, Ordering::Relaxed);
+///     }
+/// });
+/// sink.report(ProgressUpdate::FolderCompleted { folder_id: 1, frames_rendered: 24, frames_skipped: 0 });
+/// assert_eq!(completed.load(Ordering::Relaxed), 1);
+/// ```
+pub struct ProgressFn<F>(pub F);
+
+impl<F: Fn(ProgressUpdate) + Sync> ProgressSink for ProgressFn<F> {
+    fn report(&self, update: ProgressUpdate) {
+        (self.0)(update);
+    }
+}
+
+/// Discards every update
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn report(&self, _update: ProgressUpdate) {}
+}
+
+/// Output directory for a folder: the name template filled in, under the output
+/// root or else next to the folder, e.g. `<folder>_trail_<history_length>`
+pub fn output_dir_for(folder_path: &Path, settings: &ProcessingSettings) -> PathBuf {
+    let name_of = |path: Option<&Path>| path
+        .and_then(|p| p.file_name())
+        .and_then(|n| n.to_str())
+        .unwrap_or("output")
+        .to_string();
+    let output_folder_name = settings.output_name_template
+        .replace("{folder}", &name_of(Some(folder_path)))
+        .replace("{parent}", &name_of(folder_path.parent()))
+        .replace("{history}", &settings.history_length.to_string());
+    match (&settings.output_root, folder_path.parent()) {
+        (Some(root), _) => root.join(&output_folder_name),
+        (None, Some(parent)) => parent.join(&output_folder_name),
+        (None, None) => folder_path.join("trails_output"),
+    }
+}
+
+/// Whether `candidate` is, or would be, an output directory of the folder at `folder_path`
+pub fn is_output_dir_of(folder_path: &Path, settings: &ProcessingSettings, candidate: &Path) -> bool {
+    let base = output_dir_for(folder_path, settings);
+    if candidate == base {
+        return true;
+    }
+    let (Some(base_name), Some(name)) = (base.file_name(), candidate.file_name()) else { return false };
+    candidate.parent() == base.parent()
+        && name.to_string_lossy()
+            .strip_prefix(&format!("{}_", base_name.to_string_lossy()))
+            .is_some_and(|n| n.parse::<u32>().is_ok())
+}
+
+/// Where a folder's output should go, given what earlier runs left behind
+enum OutputTarget {
+    Write(PathBuf),
+    /// A run with the same settings already produced this output
+    AlreadyDone { completed_at: u64 },
+    /// Appending to the output of this earlier run with the same settings
+    Append { dir: PathBuf, previous: Box<RunMetadata> },
+    /// Appending was asked for, but the output was made with other settings
+    Mismatched(PathBuf),
+    /// Appending was asked for, but another folder of the queue is writing the output now
+    InUse(PathBuf),
+}
+
+/// Pick the output directory for a folder.
+///
+/// An output holding a run with the same settings means the folder is done.
+/// One made with different settings is reported, then either overwritten or
+/// left alone in favour of the next free `_2`, `_3`, ... directory. Outputs
+/// without run metadata, or from a cancelled run, are overwritten.
+///
+/// When appending, only the first directory is looked at: a run with the same
+/// settings there is appended to, and one with other settings is reported and
+/// left alone.
+///
+/// Directories in `in_use`, which other folders of the queue are writing at
+/// the same time, are passed over for the next free one.
+fn resolve_output_dir(
+    folder: &FolderInfo,
+    settings: &ProcessingSettings,
+    progress: &dyn ProgressSink,
+    in_use: &HashSet<PathBuf>,
+) -> OutputTarget {
+    let base = output_dir_for(&folder.path, settings);
+    if settings.force || folder.force {
+        return OutputTarget::Write((1..).map(|suffix| suffixed(&base, suffix)).find(|dir| !in_use.contains(dir)).unwrap());
     }
     
-    let r = u8::from_str_radix(&hex[0..2], 16)?;
-    let g = u8::from_str_radix(&hex[2..4], 16)?;
-    let b = u8::from_str_radix(&hex[4..6], 16)?;
+    let hash = settings.output_hash();
+    if settings.append {
+        if in_use.contains(&base) {
+            return OutputTarget::InUse(base);
+        }
+        return match metadata::read_run_metadata(&base).filter(|m| !m.cancelled) {
+            None => OutputTarget::Write(base),
+            Some(existing) if existing.output_hash() == hash => OutputTarget::Append { dir: base, previous: Box::new(existing) },
+            Some(existing) => {
+                progress.report(ProgressUpdate::OutputSettingsDiffer {
+                    folder_id: folder.id,
+                    existing_dir: base.clone(),
+                    differences: settings.output_differences(&existing.settings),
+                });
+                OutputTarget::Mismatched(base)
+            }
+        };
+    }
+    let mut candidate = base.clone();
+    let mut suffix = 2;
+    loop {
+        if in_use.contains(&candidate) {
+            candidate = suffixed(&base, suffix);
+            suffix += 1;
+            continue;
+        }
+        let Some(existing) = metadata::read_run_metadata(&candidate).filter(|m| !m.cancelled) else {
+            return OutputTarget::Write(candidate);
+        };
+        if existing.output_hash() == hash {
+            return OutputTarget::AlreadyDone { completed_at: existing.completed_at };
+        }
+        
+        if candidate == base {
+            progress.report(ProgressUpdate::OutputSettingsDiffer {
+                folder_id: folder.id,
+                existing_dir: candidate.clone(),
+                differences: settings.output_differences(&existing.settings),
+            });
+        }
+        if settings.on_collision == CollisionPolicy::Overwrite {
+            return OutputTarget::Write(candidate);
+        }
+        candidate = suffixed(&base, suffix);
+        suffix += 1;
+    }
+}
+
+/// `base` with `_<suffix>` after its name, or `base` itself for suffix 1
+fn suffixed(base: &Path, suffix: usize) -> PathBuf {
+    if suffix == 1 {
+        return base.to_path_buf();
+    }
+    let mut name = base.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("_{}", suffix));
+    base.with_file_name(name)
+}
+
+/// The finished run of the folder made with the same output settings, looking
+/// through the same directories `resolve_output_dir` would
+pub fn completed_output(folder_path: &Path, settings: &ProcessingSettings) -> Option<RunMetadata> {
+    let base = output_dir_for(folder_path, settings);
+    let hash = settings.output_hash();
+    let mut candidate = base.clone();
+    let mut suffix = 2;
+    while let Some(existing) = metadata::read_run_metadata(&candidate).filter(|m| !m.cancelled) {
+        if existing.output_hash() == hash {
+            return Some(existing);
+        }
+        candidate = suffixed(&base, suffix);
+        suffix += 1;
+    }
+    None
+}
+
+/// Estimate the output size of a frame sequence in bytes.
+///
+/// Renders one frame with a full history window and PNG-encodes it in memory;
+/// that sample size is the per-frame calibration, scaled by the frame count.
+pub fn estimate_output_bytes(files: &[PathBuf], settings: &ProcessingSettings) -> Option<u64> {
+    if files.is_empty() {
+        return Some(0);
+    }
+    let sample_idx = settings.history_length.min(files.len() - 1);
+    let sample = engine::composite_frame(files, sample_idx, settings).ok()?;
     
-    Ok((r, g, b))
+    let mut encoded = Vec::new();
+    sample.write_to(&mut std::io::Cursor::new(&mut encoded), image::ImageFormat::Png).ok()?;
+    // Each layer is counted as a whole trail, which it never exceeds by much
+    let images_per_frame = match settings.layers {
+        LayerOutput::Off => 1,
+        LayerOutput::Alongside => 3,
+        LayerOutput::Only => 2,
+    };
+    Some(encoded.len() as u64 * files.len() as u64 * images_per_frame)
 }
 
-/// Process all folders in the queue
+/// Warn when the estimated output for a volume exceeds its free space.
+///
+/// Output directories are grouped by the file system statistics of their
+/// nearest existing ancestor, so folders writing to the same volume are summed.
+fn check_free_space(output_dirs: &[PathBuf], estimates: &[Option<u64>], progress: &dyn ProgressSink) {
+    // (total, available) identifies a volume well enough at a single instant
+    let mut volumes: Vec<((u64, u64), PathBuf, u64)> = Vec::new();
+    
+    for (dir, estimate) in output_dirs.iter().zip(estimates) {
+        let Some(estimate) = estimate else { continue };
+        let Some(existing) = dir.ancestors().find(|p| p.exists()) else { continue };
+        let Ok(stats) = fs4::statvfs(existing) else { continue };
+        let key = (stats.total_space(), stats.available_space());
+        
+        match volumes.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, _, required)) => *required += estimate,
+            None => volumes.push((key, dir.clone(), *estimate)),
+        }
+    }
+    
+    for ((_, available_bytes), destination, required_bytes) in volumes {
+        if required_bytes > available_bytes {
+            progress.report(ProgressUpdate::InsufficientSpace {
+                destination,
+                required_bytes,
+                available_bytes,
+            });
+        }
+    }
+}
+
+/// A rendering thread pool of `threads` threads, or one per core for 0, for
+/// [`process_folders_on`]
+pub fn thread_pool(threads: usize) -> Result<rayon::ThreadPool, rayon::ThreadPoolBuildError> {
+    let threads = if threads == 0 { num_cpus::get() } else { threads };
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build()
+}
+
+/// Process folders from the shared queue until none are left pending, on a
+/// thread pool of `settings.threads` built for the call. See
+/// [`process_folders_on`].
 pub fn process_folders(
-    folders: Vec<FolderInfo>,
+    queue: Arc<Mutex<JobQueue>>,
     settings: ProcessingSettings,
-    tx: Sender<ProgressUpdate>,
+    progress: impl ProgressSink,
     stop_flag: Arc<AtomicBool>,
+    reload: Receiver<ProcessingSettings>,
 ) {
-    let threads = if settings.threads == 0 {
-        num_cpus::get()
-    } else {
-        settings.threads
-    };
-    
-    let pool = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
-        Ok(p) => p,
+    match thread_pool(settings.threads) {
+        Ok(pool) => process_folders_on(&pool, queue, settings, progress, stop_flag, reload),
         Err(e) => {
-            let _ = tx.send(ProgressUpdate::FolderError {
-                folder_index: 0,
-                error: format!("Failed to create thread pool: {}", e),
-            });
-            return;
+            if let Some(folder) = queue.lock().unwrap().claim_next() {
+                progress.report(ProgressUpdate::FolderError {
+                    folder_id: folder.id,
+                    error: format!("Failed to create thread pool: {}", e),
+                });
+            }
         }
-    };
+    }
+}
+
+/// Process folders from the shared queue until none are left pending, doing
+/// all the parallel work of every folder on `pool`.
+///
+/// The queue is consulted between folders, so the GUI can reorder, add or
+/// remove entries while a run is in progress. The folder being rendered has
+/// been claimed and is unaffected. Updates go to `progress`, from this thread
+/// and from the rendering threads.
+///
+/// Up to `settings.folder_parallelism` folders are rendered at once, each
+/// taken by a worker thread as it finishes the one before and all drawing
+/// their frames on `pool`. Their updates then interleave, so each carries
+/// the folder's ID. Two folders that would write to the same output
+/// directory never do so at once: the later one takes the next free
+/// directory, as if the first had a different output there. Stopping the run
+/// stops every folder in flight.
+///
+/// The caller owns `pool` and may share it with other work; `settings.threads`
+/// is not consulted, and the global rayon pool is never used or configured.
+///
+/// ```no_run
+/// use std::sync::{mpsc, Arc, Mutex};
+/// use std::sync::atomic::AtomicBool;
+/// use radar_echo_trails::processing::{self, NoProgress, ProcessingSettings};
+/// use radar_echo_trails::queue::JobQueue;
+///
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build()?;
+/// let queue = Arc::new(Mutex::new(JobQueue::default()));
+/// let (_reload, reloads) = mpsc::channel();
+/// let stop = Arc::new(AtomicBool::new(false));
+/// processing::process_folders_on(&pool, queue, ProcessingSettings::default(), NoProgress, stop, reloads);
+/// # Ok::<(), rayon::ThreadPoolBuildError>(())
+/// ```
+pub fn process_folders_on(
+    pool: &rayon::ThreadPool,
+    queue: Arc<Mutex<JobQueue>>,
+    settings: ProcessingSettings,
+    progress: impl ProgressSink,
+    stop_flag: Arc<AtomicBool>,
+    reload: Receiver<ProcessingSettings>,
+) {
+    // Plan the queue as it stands now, resolving overlapping entries, and size it
+    // so a shortfall is reported before any work starts
+    let mut entries: Vec<FolderInfo> = queue.lock().unwrap().folders().to_vec();
+    let mut scan_errors: HashMap<u64, String> = HashMap::new();
+    for entry in entries.iter_mut().filter(|e| matches!(e.status, FolderStatus::Pending)) {
+        match resolve_scan(entry, &entry.effective_settings(&settings)) {
+            Ok(scan) => entry.scan = scan,
+            Err(e) => {
+                // Plan it as empty; the error is reported when the folder comes up
+                entry.scan = FolderScan::default();
+                scan_errors.insert(entry.id, e.to_string());
+            }
+        }
+    }
+    let work_plan = queue::plan(&entries);
+    let notes: Vec<(u64, String)> = work_plan.folders.iter()
+        .filter(|planned| !planned.notes.is_empty())
+        .map(|planned| (planned.id, planned.notes.join("; ")))
+        .collect();
+    if !notes.is_empty() {
+        progress.report(ProgressUpdate::WorkPlanned { notes });
+    }
     
-    // Parse colors
-    let background_rgb = parse_hex_color(&settings.background_color).unwrap_or((0, 0, 0));
-    let current_rgb = parse_hex_color(&settings.current_color).unwrap_or((0, 255, 0));
-    let history_rgb = parse_hex_color(&settings.history_color).unwrap_or((255, 127, 0));
+    let pending: Vec<(&FolderInfo, &PlannedFolder)> = entries.iter().zip(&work_plan.folders)
+        .filter(|(entry, _)| matches!(entry.status, FolderStatus::Pending))
+        .collect();
+    let estimates: Vec<Option<u64>> = pool.install(|| {
+        pending.par_iter()
+            .map(|(entry, planned)| {
+                let settings = entry.effective_settings(&settings);
+                estimate_output_bytes(&settings.select_frames(&planned.files), &settings)
+            })
+            .collect()
+    });
+    let output_dirs: Vec<PathBuf> = pending.iter()
+        .map(|(entry, _)| output_dir_for(&entry.path, &entry.effective_settings(&settings)))
+        .collect();
+    check_free_space(&output_dirs, &estimates, &progress);
+    let prepared: HashMap<u64, PreparedFolder> = pending.iter()
+        .zip(estimates)
+        .map(|((entry, planned), estimated_bytes)| {
+            (entry.id, PreparedFolder {
+                planned: (*planned).clone(),
+                manifest: entry.scan.manifest.clone(),
+                missing_products: missing_products(&entry.scan),
+                sidecars: entry.scan.sidecars.clone(),
+                estimated_bytes,
+                scan_error: scan_errors.remove(&entry.id),
+                empty_reason: empty_reason(&entry.scan, planned),
+            })
+        })
+        .collect();
     
-    for (folder_idx, folder) in folders.iter().enumerate() {
-        // Check stop flag
+    let parallelism = settings.folder_parallelism.clamp(1, MAX_FOLDER_PARALLELISM);
+    let dispatch = Mutex::new(Dispatch {
+        seen_revision: queue.lock().unwrap().revision(),
+        settings,
+        prepared,
+        reload,
+    });
+    let outputs = Mutex::new(HashSet::new());
+    let cancelled = AtomicBool::new(false);
+    let worker = || {
+        while let Some((folder, settings, prepared)) = next_folder(&dispatch, &queue, &progress, &stop_flag, &cancelled) {
+            process_folder(pool, folder, settings, prepared, &outputs, &progress, &stop_flag);
+        }
+    };
+    // Each worker takes the next folder as soon as it is done with one, and
+    // their frames share the pool, so its threads stay busy between folders
+    std::thread::scope(|scope| {
+        for _ in 1..parallelism {
+            scope.spawn(worker);
+        }
+        worker();
+    });
+    
+    if cancelled.into_inner() {
+        progress.report(ProgressUpdate::Cancelled);
+    } else {
+        progress.report(ProgressUpdate::AllComplete);
+    }
+}
+
+/// What the workers of [`process_folders_on`] share to take the next folder
+struct Dispatch {
+    /// Settings for the folders still to start, as last reloaded
+    settings: ProcessingSettings,
+    /// Folders planned when the run started, by ID
+    prepared: HashMap<u64, PreparedFolder>,
+    reload: Receiver<ProcessingSettings>,
+    /// Revision of the queue when a worker last looked
+    seen_revision: u64,
+}
+
+/// Claim the next pending folder for a worker, with its settings and plan.
+/// Reloaded settings are taken up first. Returns `None` once no folder is
+/// left, or when the run is stopped, which sets `cancelled`.
+fn next_folder(
+    dispatch: &Mutex<Dispatch>,
+    queue: &Mutex<JobQueue>,
+    progress: &dyn ProgressSink,
+    stop_flag: &AtomicBool,
+    cancelled: &AtomicBool,
+) -> Option<(FolderInfo, ProcessingSettings, PreparedFolder)> {
+    loop {
         if stop_flag.load(Ordering::Relaxed) {
-            let _ = tx.send(ProgressUpdate::Cancelled);
-            return;
+            cancelled.store(true, Ordering::Relaxed);
+            return None;
         }
-        
-        let _ = tx.send(ProgressUpdate::FolderStarted {
-            folder_index: folder_idx,
-            folder_name: folder.name.clone(),
-        });
-        
-        // Get image files
-        let mut image_files = queue::get_image_files(&folder.path);
-        
-        // Apply limit if set
-        if let Some(limit) = settings.limit {
-            image_files.truncate(limit);
+        let (folder, settings, prepared) = {
+            let mut dispatch = dispatch.lock().unwrap();
+            let dispatch = &mut *dispatch;
+            
+            // Reloaded settings take over between folders, never part way through one.
+            // The thread pool is the caller's and keeps its size.
+            while let Ok(reloaded) = dispatch.reload.try_recv() {
+                let changed = reloaded.changed_fields(&dispatch.settings);
+                dispatch.settings = reloaded;
+                if changed.iter().any(|field| field == "ignore_manifests" || field == "odim") {
+                    // Planned with the old manifest rule or radar product; plan each folder again as it comes up
+                    dispatch.prepared.clear();
+                }
+                if !changed.is_empty() {
+                    progress.report(ProgressUpdate::SettingsReloaded { changed });
+                }
+            }
+            
+            let folder = {
+                let mut queue = queue.lock().unwrap();
+                if queue.revision() != dispatch.seen_revision {
+                    dispatch.seen_revision = queue.revision();
+                    progress.report(ProgressUpdate::QueueChanged);
+                }
+                queue.claim_next()
+            }?;
+            
+            // Cancelled between being claimed and started
+            if folder.cancel_requested.load(Ordering::Relaxed) {
+                progress.report(ProgressUpdate::FolderCancelled { folder_id: folder.id, frames_completed: 0 });
+                continue;
+            }
+            let settings = folder.effective_settings(&dispatch.settings);
+            let prepared = dispatch.prepared.remove(&folder.id);
+            (folder, settings, prepared)
+        };
+        // Added after the run started, or to be planned again after a settings reload
+        let prepared = prepared.unwrap_or_else(|| prepare_late(&folder, queue, &settings));
+        return Some((folder, settings, prepared));
+    }
+}
+
+/// Render a claimed folder into its output directory and report how it went.
+/// `outputs` holds the output directories of the folders rendering at the
+/// same time, so that no two of them write to the same one.
+fn process_folder(
+    pool: &rayon::ThreadPool,
+    folder: FolderInfo,
+    settings: ProcessingSettings,
+    prepared: PreparedFolder,
+    outputs: &Mutex<HashSet<PathBuf>>,
+    progress: &dyn ProgressSink,
+    stop_flag: &AtomicBool,
+) {
+    let folder_id = folder.id;
+    match run_folder(pool, &folder, settings, prepared, outputs, progress, stop_flag) {
+        Ok((frames_rendered, frames_skipped)) => {
+            progress.report(ProgressUpdate::FolderCompleted { folder_id, frames_rendered, frames_skipped });
         }
-        
-        let files_total = image_files.len();
-        
-        if files_total == 0 {
-            let _ = tx.send(ProgressUpdate::FolderError {
-                folder_index: folder_idx,
-                error: "No image files found".to_string(),
-            });
-            continue;
+        Err(Halt::Error(error)) => progress.report(ProgressUpdate::FolderError { folder_id, error }),
+        Err(Halt::Skipped(reason)) => progress.report(ProgressUpdate::FolderSkipped { folder_id, reason }),
+        // Before any frame is rendered only the update tells the two apart
+        Err(Halt::Stopped(frames_completed)) => match folder.cancel_requested.load(Ordering::Relaxed) {
+            true => progress.report(ProgressUpdate::FolderCancelled { folder_id, frames_completed }),
+            false => progress.report(ProgressUpdate::FolderStopped { folder_id, frames_completed }),
+        },
+    }
+}
+
+/// Why a folder ended without completing, reported by [`process_folder`]
+enum Halt {
+    Error(String),
+    /// Nothing to render, with why
+    Skipped(String),
+    /// Stopped or cancelled after this many frames
+    Stopped(usize),
+}
+
+/// The frames of a claimed folder, selected, checked and put in order
+struct FolderFrames {
+    image_files: Vec<PathBuf>,
+    /// Frames of the earlier run being appended to, read as history only
+    warm_up: Vec<PathBuf>,
+    /// Index map of the earlier run, which renumbering carries on from
+    previous_map: Vec<MapEntry>,
+    /// Every frame's overrides from the sidecar files, for the run metadata
+    applied_overrides: Vec<AppliedOverrides>,
+    repeated: Vec<RepeatedFrame>,
+    /// Resampled slots, when resampling
+    slots: Vec<Slot>,
+    /// Secondary frame paired with each frame, when blending a secondary folder
+    secondary: Vec<Option<PathBuf>>,
+}
+
+impl FolderFrames {
+    /// Index of the first new frame; those before it are only read as history
+    fn first(&self) -> usize {
+        self.image_files.iter().take_while(|path| self.warm_up.contains(path)).count()
+    }
+}
+
+/// Render a claimed folder, returning the frames rendered and kept from an
+/// interrupted run, or why it ended without completing
+fn run_folder(
+    pool: &rayon::ThreadPool,
+    folder: &FolderInfo,
+    mut settings: ProcessingSettings,
+    prepared: PreparedFolder,
+    outputs: &Mutex<HashSet<PathBuf>>,
+    progress: &dyn ProgressSink,
+    stop_flag: &AtomicBool,
+) -> Result<(usize, usize), Halt> {
+    let folder_id = folder.id;
+    let stopping = || stop_flag.load(Ordering::Relaxed) || folder.cancel_requested.load(Ordering::Relaxed);
+    if let Some(error) = prepared.scan_error.clone() {
+        return Err(Halt::Error(error));
+    }
+    if prepared.planned.merged_into.is_some() {
+        return Err(Halt::Skipped(prepared.planned.notes.join("; ")));
+    }
+
+    let (output_dir, previous) = claim_output(folder, &settings, outputs, progress)?;
+    let _reserved = Reserved { outputs, dir: output_dir.clone() };
+    let output_dir = &output_dir;
+    if settings.resume {
+        // Only trails made with these settings are kept, or those of a run that never got to say
+        settings.resume = !settings.force
+            && !folder.force
+            && metadata::read_run_metadata(output_dir).is_none_or(|existing| existing.output_hash() == settings.output_hash());
+    }
+
+    let mut frames = select_frames(folder, &mut settings, &prepared, output_dir, previous.as_deref(), progress)?;
+    check_frames(pool, &mut frames, &mut settings, folder_id, progress, &stopping)?;
+    arrange_frames(folder, &mut frames, &settings, progress)?;
+    let first = frames.first();
+    let files_total = frames.image_files.len() - first;
+    if files_total == 0 {
+        return Err(Halt::Error(prepared.empty_reason.clone()));
+    }
+    fs::create_dir_all(output_dir).map_err(|e| Halt::Error(format!("Failed to create output directory: {}", e)))?;
+
+    let output_format = output_format(pool, &frames.image_files, &settings, folder_id, progress);
+    if settings.static_mask.samples > 0 {
+        settings.auto_mask = detect_static_mask(pool, &frames.image_files, &settings, output_dir, folder_id, progress);
+    }
+    let (mut sink, animation_frames) = folder_sink(folder, &frames, &settings, output_format, output_dir, progress)?;
+    let summary = render_frames(pool, &frames, &settings, &sink, folder_id, animation_frames, progress, &stopping);
+    for (file, error) in &summary.failures {
+        progress.report(ProgressUpdate::FileError { folder_id, file: file.clone(), error: error.clone() });
+    }
+    let animation_error = finish_animations(&mut sink, &settings, output_dir, folder_id, animation_frames, progress);
+    // Stopped with frames left to render, rather than after the last one
+    let cancelled = summary.stopped && stopping();
+    write_sidecars(folder, &settings, &prepared, frames, &sink, &summary, previous, cancelled, output_dir, progress)?;
+
+    if cancelled {
+        Err(Halt::Stopped(summary.frames_completed))
+    } else if let Some(error) = animation_error {
+        Err(Halt::Error(error))
+    } else if !summary.failures.is_empty() {
+        Err(Halt::Error(format!("{} files failed to process", summary.failures.len())))
+    } else {
+        // Remembered so re-adding the folder later can offer the same settings
+        let _ = config::record_folder_history(&folder.canonical_path, &settings);
+        Ok((summary.frames_completed - summary.frames_skipped, summary.frames_skipped))
+    }
+}
+
+/// Reserve the output directory of `folder` among those of the folders
+/// rendering at the same time, with the earlier run it appends to
+fn claim_output(
+    folder: &FolderInfo,
+    settings: &ProcessingSettings,
+    outputs: &Mutex<HashSet<PathBuf>>,
+    progress: &dyn ProgressSink,
+) -> Result<(PathBuf, Option<Box<RunMetadata>>), Halt> {
+    let target = {
+        let mut in_use = outputs.lock().unwrap();
+        let target = resolve_output_dir(folder, settings, progress, &in_use);
+        if let OutputTarget::Write(dir) | OutputTarget::Append { dir, .. } = &target {
+            in_use.insert(dir.clone());
         }
-        
-        // Create output directory as sibling with _trail_N suffix
-        let folder_name = folder.path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("output");
-        let output_folder_name = format!("{}_trail_{}", folder_name, settings.history_length);
-        let output_dir = folder.path.parent()
-            .map(|p| p.join(&output_folder_name))
-            .unwrap_or_else(|| folder.path.join("trails_output"));
-        if let Err(e) = fs::create_dir_all(&output_dir) {
-            let _ = tx.send(ProgressUpdate::FolderError {
-                folder_index: folder_idx,
-                error: format!("Failed to create output directory: {}", e),
-            });
-            continue;
+        target
+    };
+    match target {
+        OutputTarget::Write(dir) => Ok((dir, None)),
+        OutputTarget::Append { dir, previous } => Ok((dir, Some(previous))),
+        OutputTarget::AlreadyDone { completed_at } => {
+            Err(Halt::Skipped(format!("already processed on {}", metadata::format_date(completed_at))))
         }
-        
-        // Pre-load images for history access
-        // For efficiency, we process in order and maintain a sliding window
-        let history_len = settings.history_length;
-        let files_done = AtomicUsize::new(0);
-        let start_time = Instant::now();
-        let last_update = Mutex::new(Instant::now());
-        let tx_clone = tx.clone();
-        let stop_flag_clone = stop_flag.clone();
-        
-        // Process frames sequentially for history consistency, but parallelize compositing
-        let results: Vec<Result<()>> = pool.install(|| {
-            (0..files_total).into_par_iter().map(|frame_idx| -> Result<()> {
-                // Check stop flag
-                if stop_flag_clone.load(Ordering::Relaxed) {
-                    return Ok(());
-                }
-                
-                let current_path = &image_files[frame_idx];
-                
-                // Load current frame
-                let current_img = image::open(current_path)
-                    .with_context(|| format!("loading {}", current_path.display()))?;
-                
-                let (width, height) = current_img.dimensions();
-                
-                // Create output image with background
-                let mut output = RgbaImage::from_pixel(
-                    width, height,
-                    Rgba([background_rgb.0, background_rgb.1, background_rgb.2, 255])
-                );
-                
-                // Calculate history range
-                let history_start = if frame_idx >= history_len {
-                    frame_idx - history_len
-                } else {
-                    0
-                };
-                
-                // Draw history frames (oldest to newest, with increasing opacity)
-                let history_frames: Vec<_> = (history_start..frame_idx).collect();
-                let history_count = history_frames.len();
-                
-                for (hist_idx, &frame_i) in history_frames.iter().enumerate() {
-                    let hist_path = &image_files[frame_i];
-                    if let Ok(hist_img) = image::open(hist_path) {
-                        // Calculate fade: older = more transparent
-                        let alpha = ((hist_idx + 1) as f32 / (history_count + 1) as f32 * 128.0) as u8;
-                        overlay_tinted(&mut output, &hist_img, history_rgb, alpha);
-                    }
+        OutputTarget::Mismatched(dir) => {
+            Err(Halt::Error(format!("Cannot append to {}: it was made with different settings", dir.display())))
+        }
+        OutputTarget::InUse(dir) => {
+            Err(Halt::Error(format!("Cannot append to {}: another folder of the queue is writing to it", dir.display())))
+        }
+    }
+}
+
+/// The frames the settings select, after those of the `previous` run when
+/// appending, with the overrides of their sidecar files read into `settings`
+fn select_frames(
+    folder: &FolderInfo,
+    settings: &mut ProcessingSettings,
+    prepared: &PreparedFolder,
+    output_dir: &Path,
+    previous: Option<&RunMetadata>,
+    progress: &dyn ProgressSink,
+) -> Result<FolderFrames, Halt> {
+    let folder_id = folder.id;
+    let mut image_files = settings.select_frames(&prepared.planned.files);
+    let warm_up = match previous {
+        Some(previous) => append_after(&folder.path, previous, &mut image_files, settings.history_length),
+        None => Vec::new(),
+    };
+    if let Some(previous) = previous.filter(|_| image_files.len() == warm_up.len()) {
+        return Err(Halt::Skipped(format!("no new frames since {}", metadata::format_date(previous.completed_at))));
+    }
+    // Renumbering carries on from the last index the earlier run wrote
+    let previous_map = match (&settings.renumber, previous) {
+        (Some(_), Some(_)) => read_index_map(output_dir).map_err(Halt::Error)?,
+        _ => Vec::new(),
+    };
+
+    progress.report(ProgressUpdate::FolderStarted {
+        folder_id,
+        folder_name: folder.name.clone(),
+        estimated_bytes: prepared.estimated_bytes,
+        frames_found: prepared.planned.files.len(),
+        frames_selected: image_files.len() - warm_up.len(),
+    });
+    if !prepared.missing_products.is_empty() {
+        progress.report(ProgressUpdate::FramesSkipped {
+            folder_id,
+            skipped: prepared.missing_products.clone(),
+        });
+    }
+
+    let read = read_overrides(&prepared.sidecars, &mut image_files, settings).map_err(Halt::Error)?;
+    for message in read.warnings {
+        progress.report(ProgressUpdate::FolderWarning { folder_id, message });
+    }
+    settings.frame_overrides = Arc::new(read.overrides);
+    Ok(FolderFrames {
+        image_files,
+        warm_up,
+        previous_map,
+        applied_overrides: read.applied,
+        repeated: Vec::new(),
+        slots: Vec::new(),
+        secondary: Vec::new(),
+    })
+}
+
+/// Size the frames to the first, load the background image, and check the
+/// frames for corruption, a light background and repeats as the settings ask
+fn check_frames(
+    pool: &rayon::ThreadPool,
+    frames: &mut FolderFrames,
+    settings: &mut ProcessingSettings,
+    folder_id: u64,
+    progress: &dyn ProgressSink,
+    stopping: &(dyn Fn() -> bool + Sync),
+) -> Result<(), Halt> {
+    let image_files = &mut frames.image_files;
+    // Frames of other sizes are matched to the first; one that can't be read
+    // leaves them as they are, and fails as it comes up
+    if let Some(first) = image_files.first() {
+        settings.frame_size = engine::decoded_size(first, settings).ok();
+    }
+
+    // Decoded once, before any frame, and shared by all of them
+    if let (Some(path), Some(first)) = (&settings.background_image, image_files.first()) {
+        let image = basemap::frame_size(first, settings)
+            .and_then(|size| basemap::load(path, size, settings))
+            .map_err(|e| Halt::Error(format!("{:#}", e)))?;
+        settings.background = Some(Arc::new(image));
+    }
+
+    if settings.preflight {
+        let frames_checked = image_files.len();
+        let corrupt: Vec<(PathBuf, String)> = pool.install(|| {
+            image_files.par_iter()
+                .filter(|_| !stopping())
+                .filter_map(|path| preflight_frame(path, settings).err().map(|e| (path.clone(), format!("{:#}", e))))
+                .collect()
+        });
+        if stopping() {
+            return Err(Halt::Stopped(0));
+        }
+
+        progress.report(ProgressUpdate::PreflightComplete {
+            folder_id,
+            frames_checked,
+            corrupt: corrupt.clone(),
+        });
+
+        if !corrupt.is_empty() {
+            match settings.on_error {
+                ErrorPolicy::SkipFrame => {
+                    image_files.retain(|p| !corrupt.iter().any(|(bad, _)| bad == p));
                 }
-                
-                // Draw current frame on top
-                overlay_tinted(&mut output, &current_img, current_rgb, 255);
-                
-                // Save output
-                let output_name = current_path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("frame.png");
-                let output_path = output_dir.join(output_name);
-                
-                output.save(&output_path)
-                    .with_context(|| format!("saving {}", output_path.display()))?;
-                
-                // Update progress
-                let done = files_done.fetch_add(1, Ordering::Relaxed) + 1;
-                
-                // Only send updates every 100ms to avoid flooding
-                let mut last = last_update.lock().unwrap();
-                if last.elapsed().as_millis() >= 100 || done == files_total {
-                    *last = Instant::now();
-                    
-                    let elapsed = start_time.elapsed().as_secs_f64();
-                    let files_per_second = if elapsed > 0.0 { done as f64 / elapsed } else { 0.0 };
-                    
-                    let current_file = current_path
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("")
-                        .to_string();
-                    
-                    let _ = tx_clone.send(ProgressUpdate::FileProgress {
-                        folder_index: folder_idx,
-                        files_done: done,
-                        files_total,
-                        current_file,
-                        files_per_second,
-                    });
+                ErrorPolicy::FailFolder => {
+                    let names: Vec<_> = corrupt.iter()
+                        .map(|(p, _)| p.file_name().unwrap_or_default().to_string_lossy())
+                        .collect();
+                    return Err(Halt::Error(format!("{} corrupt frames: {}", corrupt.len(), names.join(", "))));
                 }
-                
-                Ok(())
-            }).collect()
+            }
+        }
+    }
+
+    if !settings.light_background
+        && let Some(first) = image_files.first()
+        && let Ok(white) = engine::load_frame(first, settings).map(|frame| light::near_white_fraction(&frame))
+        && white > light::HINT_FRACTION
+    {
+        progress.report(ProgressUpdate::FolderWarning {
+            folder_id,
+            message: format!(
+                "{:.0}% of the first frame is near white; if white marks empty areas in this product, turn on Light Background",
+                white * 100.0
+            ),
         });
-        
-        // Check for errors
-        let errors: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
-        if !errors.is_empty() {
-            let _ = tx.send(ProgressUpdate::FolderError {
-                folder_index: folder_idx,
-                error: format!("{} files failed to process", errors.len()),
+    }
+
+    let policy = settings.repeated_frames;
+    if policy != RepeatPolicy::Off {
+        let repeated = pool.install(|| find_repeats(image_files, settings, stopping));
+        if stopping() {
+            return Err(Halt::Stopped(0));
+        }
+        if !repeated.is_empty() {
+            let names: Vec<String> = repeated.iter()
+                .map(|repeat| format!(
+                    "{} (same as {})",
+                    repeat.path.file_name().unwrap_or_default().to_string_lossy(),
+                    repeat.first.file_name().unwrap_or_default().to_string_lossy(),
+                ))
+                .collect();
+            let action = if policy == RepeatPolicy::Drop { "left out" } else { "kept" };
+            progress.report(ProgressUpdate::FolderWarning {
+                folder_id,
+                message: format!("{} repeated frames {}: {}", repeated.len(), action, names.join(", ")),
+            });
+        }
+        if policy == RepeatPolicy::Drop {
+            image_files.retain(|path| !repeated.iter().any(|repeat| &repeat.path == path));
+        }
+        frames.repeated = repeated;
+    }
+    Ok(())
+}
+
+/// Put the frames on the resampled time grid and pair them with the
+/// secondary folder's, as the settings ask
+fn arrange_frames(
+    folder: &FolderInfo,
+    frames: &mut FolderFrames,
+    settings: &ProcessingSettings,
+    progress: &dyn ProgressSink,
+) -> Result<(), Halt> {
+    let folder_id = folder.id;
+    if settings.resample.interval_minutes > 0 {
+        let interval = settings.resample.interval_minutes as i64 * 60;
+        match resample::resample(&frames.image_files, interval, settings.resample.naming) {
+            Some(slots) => {
+                frames.image_files = slots.iter().map(|slot| slot.source.clone()).collect();
+                frames.slots = slots;
+            }
+            None => progress.report(ProgressUpdate::FolderWarning {
+                folder_id,
+                message: "not every file name carries a scan time, so the frames are not resampled".to_string(),
+            }),
+        }
+    }
+
+    if let Some(dir) = &settings.secondary.folder {
+        let (secondary, warning) = pair_secondary(folder, &folder.path.join(dir), &mut frames.image_files, settings)
+            .map_err(Halt::Error)?;
+        if let Some(message) = warning {
+            progress.report(ProgressUpdate::FolderWarning { folder_id, message });
+        }
+        frames.secondary = secondary;
+    }
+    Ok(())
+}
+
+/// The format the trails of `image_files` are written in: GeoTIFF when every
+/// one is georeferenced, and PNG when some are, or when JPEG can't hold a
+/// transparent background
+fn output_format(
+    pool: &rayon::ThreadPool,
+    image_files: &[PathBuf],
+    settings: &ProcessingSettings,
+    folder_id: u64,
+    progress: &dyn ProgressSink,
+) -> OutputFormat {
+    let output_format = match pool.install(|| geotiff::survey(image_files)) {
+        Georeferencing::None => OutputFormat::MatchSource,
+        Georeferencing::All => OutputFormat::GeoTiff,
+        Georeferencing::Mixed { georeferenced, plain } => {
+            progress.report(ProgressUpdate::FolderWarning {
+                folder_id,
+                message: format!(
+                    "{} frames are georeferenced and {} are not; writing plain PNG frames",
+                    georeferenced, plain
+                ),
+            });
+            OutputFormat::Png
+        }
+    };
+    let jpeg = |path: &PathBuf| image::ImageFormat::from_path(path).ok() == Some(image::ImageFormat::Jpeg);
+    match output_format {
+        OutputFormat::MatchSource if settings.transparent_background && image_files.iter().any(jpeg) => {
+            progress.report(ProgressUpdate::FolderWarning {
+                folder_id,
+                message: "JPEG can't hold a transparent background; writing the trails as PNG".to_string(),
             });
+            OutputFormat::Png
+        }
+        format => format,
+    }
+}
+
+/// The overlays that stay still across `image_files`, saved into
+/// `output_dir` for checking, or none when they can't be found
+fn detect_static_mask(
+    pool: &rayon::ThreadPool,
+    image_files: &[PathBuf],
+    settings: &ProcessingSettings,
+    output_dir: &Path,
+    folder_id: u64,
+    progress: &dyn ProgressSink,
+) -> Option<Arc<static_mask::StaticMask>> {
+    let detection = match pool.install(|| static_mask::detect(image_files, settings)) {
+        Ok(detection) => detection,
+        Err(e) => {
+            progress.report(ProgressUpdate::FolderWarning {
+                folder_id,
+                message: format!("Not masking static overlays: {:#}", e),
+            });
+            return None;
+        }
+    };
+    let image = detection.mask.to_image();
+    if let Err(e) = image.save_with_format(output_dir.join(static_mask::AUTO_MASK_NAME), image::ImageFormat::Png) {
+        progress.report(ProgressUpdate::FolderWarning {
+            folder_id,
+            message: format!("Failed to save {}: {}", static_mask::AUTO_MASK_NAME, e),
+        });
+    }
+    if detection.reference.is_none() && detection.mask.masked_count() > 0 {
+        progress.report(ProgressUpdate::FolderWarning {
+            folder_id,
+            message: format!(
+                "No frame free of weather was found to confirm the static mask; {} may hold weather that stayed still",
+                static_mask::AUTO_MASK_NAME
+            ),
+        });
+    }
+    Some(Arc::new(detection.mask))
+}
+
+/// The sink writing the folder's trails, and its other outputs, into
+/// `output_dir`, with how many frames its animations take
+fn folder_sink(
+    folder: &FolderInfo,
+    frames: &FolderFrames,
+    settings: &ProcessingSettings,
+    output_format: OutputFormat,
+    output_dir: &Path,
+    progress: &dyn ProgressSink,
+) -> Result<(DirectorySink, usize), Halt> {
+    let folder_id = folder.id;
+    let (image_files, first) = (&frames.image_files, frames.first());
+    let mut sink = DirectorySink::new(output_dir, &folder.path)
+        .with_trails(settings.writes_trails())
+        .with_format(output_format)
+        .with_interpolated(settings.interpolation.write_frames)
+        .with_layers(settings.layers != LayerOutput::Off)
+        .with_names(frames.slots.iter().map(|slot| slot.name.clone()).collect());
+    if let Some(prefix) = &settings.renumber {
+        let start = renumber_start(&frames.previous_map);
+        let width = match settings.renumber_width {
+            0 => renumber::width_of(&frames.previous_map, prefix)
+                .unwrap_or_else(|| renumber::auto_width(start + image_files.len() - first)),
+            width => width as usize,
+        };
+        let names = image_files.iter()
+            .enumerate()
+            .map(|(index, source)| match index.checked_sub(first) {
+                Some(emitted) => renumber::name(source, prefix, start + emitted, width),
+                None => source.clone(),
+            })
+            .collect();
+        sink = sink.with_names(names);
+    }
+    if !frames.slots.is_empty() && let Err(message) = write_slots(output_dir, &frames.slots) {
+        progress.report(ProgressUpdate::FolderWarning { folder_id, message });
+    }
+    if let Some(dir) = &settings.motion.export_dir {
+        // Folders exporting to the same absolute folder each get their own subfolder
+        let dir = if dir.is_absolute() {
+            dir.join(output_dir.file_name().unwrap_or_default())
         } else {
-            let _ = tx.send(ProgressUpdate::FolderCompleted { folder_index: folder_idx });
+            output_dir.join(dir)
+        };
+        sink = sink.with_motion_export(dir, &settings.motion);
+    }
+    if settings.tile_output {
+        sink = sink.with_tiles(settings.tile_size);
+    }
+    if let Some(latest) = &settings.latest_image {
+        sink = sink.with_latest(latest, settings.latest_json);
+    }
+    if let Some(index) = &settings.emit_index {
+        sink = sink.with_index(index);
+    }
+    let (writers, animation_frames) = start_animations(output_dir, image_files, first, settings)
+        .map_err(|e| Halt::Error(format!("{:#}", e)))?;
+    for writer in writers {
+        sink = sink.with_animation(writer);
+    }
+    sink = sink.with_animated_interpolation(animated_steps(settings));
+    if settings.png_palette {
+        match Palette::for_settings(settings) {
+            Ok(palette) => sink = sink.with_palette(palette),
+            Err(reason) => progress.report(ProgressUpdate::FolderWarning {
+                folder_id,
+                message: format!("Writing true-color PNG frames: {}", reason),
+            }),
         }
     }
-    
-    let _ = tx.send(ProgressUpdate::AllComplete);
+    Ok((sink, animation_frames))
+}
+
+/// Index the first new frame of a renumbered run takes, after those of the earlier run
+fn renumber_start(previous_map: &[MapEntry]) -> usize {
+    previous_map.last().map_or(0, |entry| entry.index + 1)
+}
+
+/// Render the new frames into `sink` on `pool`, reporting progress at most every 100ms
+#[allow(clippy::too_many_arguments)]
+fn render_frames(
+    pool: &rayon::ThreadPool,
+    frames: &FolderFrames,
+    settings: &ProcessingSettings,
+    sink: &DirectorySink,
+    folder_id: u64,
+    animation_frames: usize,
+    progress: &dyn ProgressSink,
+    stopping: &(dyn Fn() -> bool + Sync),
+) -> RunSummary {
+    let files_total = frames.image_files.len() - frames.first();
+    let start_time = Instant::now();
+    let last_update = Mutex::new(Instant::now());
+    pool.install(|| {
+        engine::render_paired_sequence_from(&frames.image_files, frames.first(), &frames.secondary, settings, sink, stopping, |frame| {
+            let mut last = last_update.lock().unwrap();
+            if last.elapsed().as_millis() >= 100 || frame.frames_done == files_total {
+                *last = Instant::now();
+
+                let elapsed = start_time.elapsed().as_secs_f64();
+                let files_per_second = if elapsed > 0.0 { frame.frames_done as f64 / elapsed } else { 0.0 };
+
+                let current_file = frame.source
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("")
+                    .to_string();
+
+                progress.report(ProgressUpdate::FileProgress {
+                    folder_id,
+                    files_done: frame.frames_done,
+                    files_total,
+                    current_file,
+                    files_per_second,
+                });
+                if let Some(frames_encoded) = sink.animations_encoded() {
+                    progress.report(ProgressUpdate::AnimationProgress { folder_id, frames_encoded, frames_total: animation_frames });
+                }
+            }
+            ControlFlow::Continue(())
+        })
+    })
+}
+
+/// Complete the animations, which may still be behind the workers, and
+/// report their sizes; returns why one of them failed
+fn finish_animations(
+    sink: &mut DirectorySink,
+    settings: &ProcessingSettings,
+    output_dir: &Path,
+    folder_id: u64,
+    animation_frames: usize,
+    progress: &dyn ProgressSink,
+) -> Option<String> {
+    let mut animation_error = None;
+    for finished in sink.finish_animations() {
+        match finished {
+            Ok(frames_encoded) => {
+                progress.report(ProgressUpdate::AnimationProgress { folder_id, frames_encoded, frames_total: animation_frames });
+            }
+            Err(e) => animation_error = Some(format!("{:#}", e)),
+        }
+    }
+    if animation_error.is_none() {
+        for name in settings.mp4.iter().chain(&settings.animate_gif) {
+            let path = output_dir.join(name);
+            if let Ok(metadata) = fs::metadata(&path) {
+                progress.report(ProgressUpdate::AnimationWritten { folder_id, path, bytes: metadata.len() });
+            }
+        }
+    }
+    animation_error
+}
+
+/// Write the index map of a renumbered run and the run metadata into
+/// `output_dir`, carrying on those of the `previous` run when appending
+#[allow(clippy::too_many_arguments)]
+fn write_sidecars(
+    folder: &FolderInfo,
+    settings: &ProcessingSettings,
+    prepared: &PreparedFolder,
+    frames: FolderFrames,
+    sink: &DirectorySink,
+    summary: &RunSummary,
+    previous: Option<Box<RunMetadata>>,
+    cancelled: bool,
+    output_dir: &Path,
+    progress: &dyn ProgressSink,
+) -> Result<(), Halt> {
+    let first = frames.first();
+    // The new frames written, rather than failed or left for a stopped run
+    let written: Vec<(usize, &PathBuf)> = match summary.stopped {
+        true => Vec::new(),
+        false => frames.image_files.iter()
+            .enumerate()
+            .skip(first)
+            .filter(|(_, path)| !summary.failures.iter().any(|(failed, _)| failed == *path))
+            .collect(),
+    };
+    let relative = |path: &PathBuf| path.strip_prefix(&folder.path).unwrap_or(path).to_path_buf();
+    if settings.renumber.is_some() {
+        let start = renumber_start(&frames.previous_map);
+        let mut entries = frames.previous_map;
+        entries.extend(written.iter().map(|&(index, path)| MapEntry {
+            index: start + index - first,
+            output: sink.trail_file(index, path).strip_prefix(output_dir).unwrap_or(path).to_path_buf(),
+            time: nowcast::scan_time(path),
+            source: relative(path),
+        }));
+        if let Err(message) = write_index_map(output_dir, &entries) {
+            progress.report(ProgressUpdate::FolderWarning { folder_id: folder.id, message });
+        }
+    }
+
+    let mut run_metadata = RunMetadata::new(&folder.path, settings);
+    run_metadata.frames_total = frames.image_files.len() - first;
+    run_metadata.frames_failed = summary.failures.len();
+    run_metadata.frames_completed = summary.frames_completed;
+    run_metadata.frames_skipped = summary.frames_skipped;
+    run_metadata.cancelled = cancelled;
+    run_metadata.manifest = prepared.manifest.clone();
+    run_metadata.plan_notes = prepared.planned.notes.clone();
+    run_metadata.age_contributions = summary.age_contributions.clone();
+    run_metadata.timing = Some(summary.timing.clone());
+    run_metadata.repeated_frames = frames.repeated;
+    run_metadata.frame_overrides = frames.applied_overrides;
+    run_metadata.sources.extend(written.iter().map(|&(_, path)| relative(path)));
+    if let Some(previous) = previous {
+        run_metadata.frames_total += previous.frames_total;
+        run_metadata.frames_failed += previous.frames_failed;
+        run_metadata.frames_completed += previous.frames_completed;
+        run_metadata.frames_skipped += previous.frames_skipped;
+        run_metadata.sources.splice(0..0, previous.sources);
+    }
+    metadata::write_run_metadata(output_dir, &mut run_metadata).map_err(|e| Halt::Error(format!("{:#}", e)))
+}
+
+/// An output directory a folder is writing to, given up when it is done
+struct Reserved<'a> {
+    outputs: &'a Mutex<HashSet<PathBuf>>,
+    dir: PathBuf,
+}
+
+impl Drop for Reserved<'_> {
+    fn drop(&mut self) {
+        self.outputs.lock().unwrap().remove(&self.dir);
+    }
+}
+
+/// The folder's frames, re-scanning unless the scan taken when it was queued is still fresh
+fn resolve_scan(folder: &FolderInfo, settings: &ProcessingSettings) -> Result<FolderScan, ScanError> {
+    let mut options = folder.scan_options.clone();
+    if settings.ignore_manifests {
+        options.manifest_name = None;
+    }
+    let scan = if folder.scan.is_fresh() && options.manifest_name == folder.scan_options.manifest_name {
+        folder.scan.clone()
+    } else {
+        queue::scan_folder(&folder.path, &options)?
+    };
+    #[cfg(feature = "hdf5")]
+    let scan = {
+        let mut scan = scan;
+        crate::odim::select_frames(&mut scan, &settings.odim);
+        scan
+    };
+    Ok(scan)
+}
+
+/// Leave in `image_files` only the frames after the last source the `previous`
+/// run of the folder at `folder` wrote, and put in front of them the sources
+/// their history reaches back to, which are returned. Sources no longer on
+/// disk are left out. Nothing is put in front when there are no new frames.
+fn append_after(folder: &Path, previous: &RunMetadata, image_files: &mut Vec<PathBuf>, history_length: usize) -> Vec<PathBuf> {
+    let written: Vec<PathBuf> = previous.sources.iter().map(|source| folder.join(source)).collect();
+    let known: HashSet<&PathBuf> = written.iter().collect();
+    if let Some(last) = image_files.iter().rposition(|path| known.contains(path)) {
+        image_files.drain(..=last);
+    }
+    if image_files.is_empty() {
+        return Vec::new();
+    }
+    let warm_up: Vec<PathBuf> = written[written.len().saturating_sub(history_length)..].iter()
+        .filter(|path| path.is_file())
+        .cloned()
+        .collect();
+    image_files.splice(0..0, warm_up.iter().cloned());
+    warm_up
+}
+
+/// Pair the frames of the secondary folder `dir` with `image_files`, as
+/// [`secondary::pair`] does, scanning it with the folder's own options. Paired
+/// by position, the frames past the end of the shorter folder are left out.
+/// Returns the secondary frame of each remaining frame, and a warning for the
+/// user when the folders do not match frame for frame.
+fn pair_secondary(
+    folder: &FolderInfo,
+    dir: &Path,
+    image_files: &mut Vec<PathBuf>,
+    settings: &ProcessingSettings,
+) -> Result<(Vec<Option<PathBuf>>, Option<String>), String> {
+    let mut options = folder.scan_options.clone();
+    if settings.ignore_manifests {
+        options.manifest_name = None;
+    }
+    let scan = queue::scan_folder(dir, &options)
+        .map_err(|e| format!("Secondary folder {}: {}", dir.display(), e))?;
+    if scan.files.is_empty() {
+        return Err(format!("Secondary folder {} has no frames", dir.display()));
+    }
+    let pairing = secondary::pair(image_files, &scan.files);
+    let (unpaired, unused) = (pairing.unpaired(), pairing.unused);
+    let warning = match pairing.paired_by {
+        PairedBy::Index if unpaired > 0 => Some(format!(
+            "the secondary folder has only {} of {} frames; the last {} are left out",
+            scan.files.len(), image_files.len(), unpaired
+        )),
+        PairedBy::Index if unused > 0 => Some(format!(
+            "the secondary folder has {} frames more than this one; they are left out",
+            unused
+        )),
+        PairedBy::Time if unpaired > 0 || unused > 0 => Some(format!(
+            "paired with the secondary folder by scan time: {} frames have no secondary frame, {} secondary frames are unused",
+            unpaired, unused
+        )),
+        _ => None,
+    };
+    let mut frames = pairing.frames;
+    if pairing.paired_by == PairedBy::Index {
+        frames.truncate(scan.files.len());
+        image_files.truncate(scan.files.len());
+    }
+    Ok((frames, warning))
+}
+
+/// Frames interpolated after each scan, which the animations take between
+/// the scans; decay trails have none
+fn animated_steps(settings: &ProcessingSettings) -> u32 {
+    match settings.trail_mode {
+        TrailMode::Decay => 0,
+        _ => settings.interpolation.frames,
+    }
+}
+
+/// Encoders of the animations the settings ask for, of the frames of
+/// `image_files` from `first` on and those interpolated between them, held as
+/// their scan times say, with how many frames they take; the MP4 is started
+/// first, since ffmpeg may be missing
+fn start_animations(output_dir: &Path, image_files: &[PathBuf], first: usize, settings: &ProcessingSettings) -> Result<(Vec<SequenceWriter>, usize)> {
+    let steps = animated_steps(settings);
+    // An appended run renders the frames interpolated towards its first new
+    // scan, so its animations start with them
+    let from = if steps > 0 { first.saturating_sub(1) } else { first };
+    let times: Vec<Option<i64>> = image_files[from..].iter().map(|path| nowcast::scan_time(path)).collect();
+    let mut durations = animation::interpolated_durations(&animation::frame_durations(&times, &settings.animation), steps);
+    let mut start = from * (steps as usize + 1);
+    if from < first {
+        // The earlier scan is in the earlier run's animation
+        durations.remove(0);
+        start += 1;
+    }
+    let frames = durations.len();
+    let mut writers = Vec::new();
+    if let Some(mp4) = &settings.mp4 {
+        let video = Mp4Sequence::create(mp4_export::FFMPEG.as_ref(), &output_dir.join(mp4), durations.clone(), settings.animation.fps, settings.mp4_crf)?;
+        writers.push(SequenceWriter::spawn(start, video)?);
+    }
+    if let Some(gif) = &settings.animate_gif {
+        let gif = GifSequence::create(&output_dir.join(gif), durations, GifOptions::for_settings(settings))?;
+        writers.push(SequenceWriter::spawn(start, gif)?);
+    }
+    Ok((writers, frames))
 }
 
-/// Overlay a tinted version of src onto dst
-fn overlay_tinted(dst: &mut RgbaImage, src: &DynamicImage, tint: (u8, u8, u8), alpha: u8) {
-    let src_rgba = src.to_rgba8();
-    let (width, height) = src_rgba.dimensions();
+/// Write the table of a resampled folder's slots into `output_dir`
+fn write_slots(output_dir: &Path, slots: &[Slot]) -> Result<(), String> {
+    let path = output_dir.join(resample::RESAMPLE_FILE_NAME);
+    fs::write(&path, resample::to_csv(slots)).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+/// The index map of the renumbered run already in `output_dir`
+fn read_index_map(output_dir: &Path) -> Result<Vec<MapEntry>, String> {
+    let path = output_dir.join(renumber::INDEX_MAP_NAME);
+    let csv = fs::read_to_string(&path)
+        .map_err(|e| format!("Cannot continue numbering from {}: {}", path.display(), e))?;
+    renumber::parse_csv(&csv).ok_or_else(|| format!("Cannot continue numbering from {}: it does not read as an index map", path.display()))
+}
+
+/// Write the index map of a renumbered folder into `output_dir`
+fn write_index_map(output_dir: &Path, entries: &[MapEntry]) -> Result<(), String> {
+    let path = output_dir.join(renumber::INDEX_MAP_NAME);
+    fs::write(&path, renumber::to_csv(entries)).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+/// Files of a scan left out for lacking the selected radar product, described for the user
+fn missing_products(scan: &FolderScan) -> Vec<(PathBuf, String)> {
+    scan.skipped.iter()
+        .filter(|(_, reason)| matches!(reason, SkipReason::MissingQuantity { .. }))
+        .map(|(path, reason)| (path.clone(), reason.to_string()))
+        .collect()
+}
+
+/// A folder's frames after planning, ready to render
+struct PreparedFolder {
+    planned: PlannedFolder,
+    manifest: Option<ManifestUsage>,
+    /// Radar files without the selected product
+    missing_products: Vec<(PathBuf, String)>,
+    /// Sidecar files of frame overrides found by the scan
+    sidecars: Vec<PathBuf>,
+    estimated_bytes: Option<u64>,
+    /// Why the folder could not be listed, if it could not
+    scan_error: Option<String>,
+    /// What to report if the folder turns out to have no frames to render
+    empty_reason: String,
+}
+
+/// Explain a folder with nothing to render
+fn empty_reason(scan: &FolderScan, planned: &PlannedFolder) -> String {
+    if scan.files.is_empty() {
+        scan.describe_empty()
+    } else if planned.files.is_empty() {
+        "All of its frames are rendered by other queue entries".to_string()
+    } else {
+        "No frames left to render".to_string()
+    }
+}
+
+/// Plan a folder queued after the run started against the queue as it is now
+fn prepare_late(folder: &FolderInfo, queue: &Mutex<JobQueue>, settings: &ProcessingSettings) -> PreparedFolder {
+    let mut entries = queue.lock().unwrap().folders().to_vec();
+    let (scan, scan_error) = match resolve_scan(folder, settings) {
+        Ok(scan) => (scan, None),
+        Err(e) => (FolderScan::default(), Some(e.to_string())),
+    };
+    let manifest = scan.manifest.clone();
+    let missing_products = missing_products(&scan);
+    let sidecars = scan.sidecars.clone();
+    match entries.iter_mut().find(|e| e.id == folder.id) {
+        Some(entry) => entry.scan = scan,
+        None => entries.push(FolderInfo { scan, ..folder.clone() }),
+    }
     
-    for y in 0..height.min(dst.height()) {
-        for x in 0..width.min(dst.width()) {
-            let src_pixel = src_rgba.get_pixel(x, y);
-            
-            // Skip fully transparent pixels
-            if src_pixel[3] == 0 {
-                continue;
+    let planned = queue::plan(&entries).folders.into_iter()
+        .find(|planned| planned.id == folder.id)
+        .unwrap_or_default();
+    let estimated_bytes = estimate_output_bytes(&settings.select_frames(&planned.files), settings);
+    let scan = &entries.iter().find(|e| e.id == folder.id).expect("entry was just added").scan;
+    let empty_reason = empty_reason(scan, &planned);
+    PreparedFolder { planned, manifest, missing_products, sidecars, estimated_bytes, scan_error, empty_reason }
+}
+
+/// The sidecar overrides of a folder's frames
+struct ReadOverrides {
+    /// Overrides of the frames still to render, by path
+    overrides: HashMap<PathBuf, FrameOverrides>,
+    /// Every frame's overrides, skipped frames included, for the run metadata
+    applied: Vec<AppliedOverrides>,
+    /// Unknown keys, and sidecars that could not be read and the frames left out for them
+    warnings: Vec<String>,
+}
+
+/// Read the `sidecars` of the frames in `image_files`, and leave out the frames
+/// they skip. A sidecar that cannot be read leaves its frame out too, or under
+/// [`ErrorPolicy::FailFolder`] fails the folder with the returned error.
+fn read_overrides(sidecars: &[PathBuf], image_files: &mut Vec<PathBuf>, settings: &ProcessingSettings) -> Result<ReadOverrides, String> {
+    let mut read = ReadOverrides { overrides: HashMap::new(), applied: Vec::new(), warnings: Vec::new() };
+    let mut left_out = HashSet::new();
+    for path in sidecars {
+        let Some(frame) = sidecar::frame_path(path).filter(|frame| image_files.contains(frame)) else { continue };
+        let overrides = match sidecar::read(path) {
+            Ok((overrides, unknown)) => {
+                if !unknown.is_empty() {
+                    read.warnings.push(format!("{}: ignoring unknown keys {}", path.display(), unknown.join(", ")));
+                }
+                overrides
             }
-            
-            // Convert to grayscale for intensity
-            let intensity = (0.299 * src_pixel[0] as f32 
-                          + 0.587 * src_pixel[1] as f32 
-                          + 0.114 * src_pixel[2] as f32) / 255.0;
-            
-            // Apply tint based on intensity
-            let r = (tint.0 as f32 * intensity) as u8;
-            let g = (tint.1 as f32 * intensity) as u8;
-            let b = (tint.2 as f32 * intensity) as u8;
-            
-            // Blend with alpha
-            let src_alpha = ((src_pixel[3] as u32 * alpha as u32) / 255) as u8;
-            
-            if src_alpha > 0 {
-                let dst_pixel = dst.get_pixel(x, y);
-                let blend_alpha = src_alpha as f32 / 255.0;
-                let inv_alpha = 1.0 - blend_alpha;
-                
-                let new_r = (r as f32 * blend_alpha + dst_pixel[0] as f32 * inv_alpha) as u8;
-                let new_g = (g as f32 * blend_alpha + dst_pixel[1] as f32 * inv_alpha) as u8;
-                let new_b = (b as f32 * blend_alpha + dst_pixel[2] as f32 * inv_alpha) as u8;
-                
-                dst.put_pixel(x, y, Rgba([new_r, new_g, new_b, 255]));
+            Err(e) if settings.on_error == ErrorPolicy::FailFolder => return Err(format!("{:#}", e)),
+            Err(e) => {
+                read.warnings.push(format!("{:#}; leaving the frame out", e));
+                left_out.insert(frame);
+                continue;
             }
+        };
+        if overrides == FrameOverrides::default() {
+            continue;
+        }
+        if overrides.skip {
+            left_out.insert(frame.clone());
+        } else {
+            read.overrides.insert(frame.clone(), overrides.clone());
         }
+        read.applied.push(AppliedOverrides { frame, overrides });
+    }
+    image_files.retain(|path| !left_out.contains(path));
+    read.applied.sort_by(|a, b| a.frame.cmp(&b.frame));
+    Ok(read)
+}
+
+/// Frames of `files` whose pixels, as decoded for rendering, repeat an earlier
+/// one's, as [`dedup`] describes. Frames that cannot be read are left for the
+/// render to report, and those left once `stopping` are not read.
+fn find_repeats(files: &[PathBuf], settings: &ProcessingSettings, stopping: &(dyn Fn() -> bool + Sync)) -> Vec<RepeatedFrame> {
+    let hashes: Vec<Option<u128>> = files.par_iter()
+        .map(|path| match stopping() {
+            true => None,
+            false => engine::load_frame(path, settings).ok().map(|frame| dedup::frame_hash(&frame)),
+        })
+        .collect();
+    dedup::repeats(&hashes)
+        .into_iter()
+        .map(|(repeat, first)| RepeatedFrame { path: files[repeat].clone(), first: files[first].clone() })
+        .collect()
+}
+
+/// Cheaply check that a frame is decodable before committing to a long run.
+///
+/// Reads the image header, then confirms the format's trailer (PNG IEND,
+/// JPEG EOI, GIF terminator) appears near the end of the file to catch
+/// truncated files without decoding pixels. A little trailing padding is
+/// tolerated. BMP and TGA headers don't reveal truncation, so those are
+/// fully decoded, and so are ODIM radar files.
+#[cfg_attr(not(feature = "hdf5"), allow(unused_variables))]
+fn preflight_frame(path: &Path, settings: &ProcessingSettings) -> Result<()> {
+    #[cfg(feature = "hdf5")]
+    if crate::odim::is_odim_file(path) {
+        return crate::odim::read_frame(path, &settings.odim).map(drop);
+    }
+    let reader = image::ImageReader::open(path)?.with_guessed_format()?;
+    let format = reader.format().ok_or_else(|| anyhow!("unrecognised image format"))?;
+    
+    let trailer: &[u8] = match format {
+        image::ImageFormat::Png => &[0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82],
+        image::ImageFormat::Jpeg => &[0xff, 0xd9],
+        image::ImageFormat::Gif => &[0x3b],
+        _ => {
+            reader.decode()?;
+            return Ok(());
+        }
+    };
+    
+    reader.into_dimensions()?;
+    
+    const TAIL_WINDOW: u64 = 64;
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let tail_len = len.min(TAIL_WINDOW);
+    let mut tail = vec![0u8; tail_len as usize];
+    file.seek(SeekFrom::End(-(tail_len as i64)))?;
+    file.read_exact(&mut tail)?;
+    if !tail.windows(trailer.len()).any(|w| w == trailer) {
+        return Err(anyhow!("file is truncated (missing end marker)"));
     }
+    Ok(())
 }
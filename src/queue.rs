@@ -1,67 +1,1049 @@
 //! Folder queue management
 
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
 
-#[derive(Clone, Debug)]
+use anyhow::{anyhow, Context};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::processing::ProcessingSettings;
+use crate::sidecar;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum FolderStatus {
     Pending,
     Processing,
     Complete,
     Error,
+    Cancelled,
+    /// Output already exists with the same settings
+    Skipped,
 }
 
 #[derive(Clone, Debug)]
 pub struct FolderInfo {
+    /// Stable identifier assigned by the [`JobQueue`], unaffected by reordering
+    pub id: u64,
     pub path: PathBuf,
     pub name: String,
     pub file_count: usize,
     pub status: FolderStatus,
     pub progress: f32,
     pub error_message: Option<String>,
+    /// Canonical form of `path`, used to spot duplicate and overlapping entries
+    pub canonical_path: PathBuf,
+    /// Options the folder was scanned with, reused when re-scanning
+    pub scan_options: ScanOptions,
+    /// Scan taken when the folder was added, reused at processing time while fresh
+    pub scan: FolderScan,
+    /// Expected size of the rendered output, estimated from a sample frame
+    pub estimated_output_bytes: Option<u64>,
+    /// Set to abandon this folder; shared with the copy the processing thread works on
+    pub cancel_requested: Arc<AtomicBool>,
+    /// Reprocess even when the output already matches the settings
+    pub force: bool,
+    /// Settings this folder keeps instead of the global ones, such as those of an earlier run
+    pub settings: Option<ProcessingSettings>,
+    /// Secondary folder composited over this one's trails, in place of the
+    /// settings' `secondary.folder`
+    pub secondary: Option<PathBuf>,
+    /// Cached thumbnail of a representative frame; `None` shows a placeholder
+    pub thumbnail: Option<PathBuf>,
 }
 
-/// Supported image extensions
-const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "tga", "gif"];
-
-/// Count image files in a directory
-pub fn count_image_files(path: &PathBuf) -> usize {
-    std::fs::read_dir(path)
-        .map(|entries| {
-            entries
-                .filter_map(|e| e.ok())
-                .filter(|e| {
-                    e.path()
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .map(|ext| {
-                            IMAGE_EXTENSIONS.iter().any(|ie| ie.eq_ignore_ascii_case(ext))
-                        })
-                        .unwrap_or(false)
-                })
-                .count()
-        })
-        .unwrap_or(0)
-}
-
-/// Get list of image files in a directory, sorted
-pub fn get_image_files(path: &PathBuf) -> Vec<PathBuf> {
-    let mut files: Vec<PathBuf> = std::fs::read_dir(path)
-        .map(|entries| {
-            entries
-                .filter_map(|e| e.ok())
-                .map(|e| e.path())
-                .filter(|p| {
-                    p.extension()
-                        .and_then(|ext| ext.to_str())
-                        .map(|ext| {
-                            IMAGE_EXTENSIONS.iter().any(|ie| ie.eq_ignore_ascii_case(ext))
-                        })
-                        .unwrap_or(false)
+impl FolderInfo {
+    /// The settings to process this folder with. Its own settings decide the
+    /// output; how the run behaves (threads, error and collision policy,
+    /// pre-flight, reprocessing) still follows `global`.
+    pub fn effective_settings(&self, global: &ProcessingSettings) -> ProcessingSettings {
+        let mut settings = match &self.settings {
+            Some(own) => ProcessingSettings {
+                threads: global.threads,
+                preflight: global.preflight,
+                on_error: global.on_error,
+                force: global.force,
+                append: global.append,
+                on_collision: global.on_collision,
+                shader: global.shader.clone(),
+                ..own.clone()
+            },
+            None => global.clone(),
+        };
+        if let Some(secondary) = &self.secondary {
+            settings.secondary.folder = Some(secondary.clone());
+        }
+        settings
+    }
+}
+
+/// How a folder being added relates to an entry already in the queue
+#[derive(Clone, Copy, Debug)]
+pub enum Overlap {
+    /// The new folder contains the existing entry
+    Ancestor,
+    /// The new folder lies inside the existing entry
+    Descendant,
+}
+
+/// A folder whose listing could not be read at all
+#[derive(Debug)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub source: std::io::Error,
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot read directory {}: {}", self.path.display(), self.source)
+    }
+}
+
+impl std::error::Error for ScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Why a folder was not added to the queue
+#[derive(Debug)]
+pub enum AddFolderError {
+    /// The path could not be resolved
+    Unresolvable(std::io::Error),
+    /// The folder's listing could not be read
+    Unreadable(ScanError),
+    /// The same folder is already queued at this index
+    Duplicate { existing: usize },
+    /// The folder overlaps a recursive entry at this index; retry with `allow_overlap`
+    Overlaps { existing: usize, overlap: Overlap },
+}
+
+impl std::fmt::Display for AddFolderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddFolderError::Unresolvable(e) => write!(f, "cannot resolve folder: {}", e),
+            AddFolderError::Unreadable(e) => write!(f, "{}", e),
+            AddFolderError::Duplicate { existing } => {
+                write!(f, "already queued as entry {}", existing + 1)
+            }
+            AddFolderError::Overlaps { existing, overlap: Overlap::Ancestor } => {
+                write!(f, "contains queued entry {}, whose frames would be processed twice", existing + 1)
+            }
+            AddFolderError::Overlaps { existing, overlap: Overlap::Descendant } => {
+                write!(f, "is inside queued entry {}, whose frames would be processed twice", existing + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AddFolderError {}
+
+/// The processing queue, shared between the GUI and the processing thread.
+///
+/// The worker claims one pending folder at a time, so reordering, adding or
+/// removing entries only affects folders that have not started yet.
+#[derive(Debug, Default)]
+pub struct JobQueue {
+    folders: Vec<FolderInfo>,
+    next_id: u64,
+    /// Bumped whenever entries are added, removed or reordered
+    revision: u64,
+}
+
+impl JobQueue {
+    pub fn folders(&self) -> &[FolderInfo] {
+        &self.folders
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folders.is_empty()
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Index of the folder with `id`, if it is still queued
+    pub fn position(&self, id: u64) -> Option<usize> {
+        self.folders.iter().position(|f| f.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut FolderInfo> {
+        self.folders.iter_mut().find(|f| f.id == id)
+    }
+
+    /// Mutable access to every entry without changing the order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut FolderInfo> {
+        self.folders.iter_mut()
+    }
+
+    /// Scan `path` and append it to the queue, returning its index.
+    ///
+    /// Exact duplicates (after resolving symlinks) are always rejected. A folder
+    /// nested inside, or containing, an entry that is scanned recursively is
+    /// rejected unless `allow_overlap` is set.
+    pub fn add_folder(
+        &mut self,
+        path: &Path,
+        scan_options: ScanOptions,
+        allow_overlap: bool,
+    ) -> Result<usize, AddFolderError> {
+        let canonical_path = path.canonicalize().map_err(AddFolderError::Unresolvable)?;
+
+        for (existing, folder) in self.folders.iter().enumerate() {
+            if folder.canonical_path == canonical_path {
+                return Err(AddFolderError::Duplicate { existing });
+            }
+            if allow_overlap {
+                continue;
+            }
+            if folder.scan_options.recursive && canonical_path.starts_with(&folder.canonical_path) {
+                return Err(AddFolderError::Overlaps { existing, overlap: Overlap::Descendant });
+            }
+            if scan_options.recursive && folder.canonical_path.starts_with(&canonical_path) {
+                return Err(AddFolderError::Overlaps { existing, overlap: Overlap::Ancestor });
+            }
+        }
+
+        let scan = scan_folder(path, &scan_options).map_err(AddFolderError::Unreadable)?;
+        let name = path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("Unknown")
+            .to_string();
+
+        self.folders.push(FolderInfo {
+            id: self.next_id,
+            path: path.to_path_buf(),
+            name,
+            file_count: scan.files.len(),
+            status: FolderStatus::Pending,
+            progress: 0.0,
+            error_message: None,
+            canonical_path,
+            scan_options,
+            scan,
+            estimated_output_bytes: None,
+            cancel_requested: Arc::new(AtomicBool::new(false)),
+            force: false,
+            settings: None,
+            secondary: None,
+            thumbnail: None,
+        });
+        self.next_id += 1;
+        self.revision += 1;
+        Ok(self.folders.len() - 1)
+    }
+
+    /// Remove the entry at `index` unless it is being processed
+    pub fn remove(&mut self, index: usize) -> Option<FolderInfo> {
+        if matches!(self.folders.get(index)?.status, FolderStatus::Processing) {
+            return None;
+        }
+        self.revision += 1;
+        Some(self.folders.remove(index))
+    }
+
+    /// Move the entry at `from` so it ends up at `to`
+    pub fn move_folder(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.folders.len() || to >= self.folders.len() || from == to {
+            return false;
+        }
+        let folder = self.folders.remove(from);
+        self.folders.insert(to, folder);
+        self.revision += 1;
+        true
+    }
+
+    /// Move the entry at `index` to the front so it is processed next
+    pub fn move_to_front(&mut self, index: usize) -> bool {
+        self.move_folder(index, 0)
+    }
+
+    /// Cancel the entry at `index` without touching the rest of the queue.
+    ///
+    /// A pending folder is marked cancelled so it is never started; the folder
+    /// being processed abandons its remaining frames.
+    pub fn cancel(&mut self, index: usize) -> bool {
+        let Some(folder) = self.folders.get_mut(index) else { return false };
+        match folder.status {
+            FolderStatus::Pending => folder.status = FolderStatus::Cancelled,
+            FolderStatus::Processing => folder.cancel_requested.store(true, Ordering::Relaxed),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Queue a skipped or finished folder again, bypassing the existing-output check
+    pub fn reprocess(&mut self, index: usize) -> bool {
+        let Some(folder) = self.folders.get_mut(index) else { return false };
+        if matches!(folder.status, FolderStatus::Pending | FolderStatus::Processing) {
+            return false;
+        }
+        folder.status = FolderStatus::Pending;
+        folder.progress = 0.0;
+        folder.error_message = None;
+        folder.force = true;
+        folder.cancel_requested.store(false, Ordering::Relaxed);
+        true
+    }
+
+    /// Remove every entry except the one being processed
+    pub fn clear(&mut self) {
+        self.folders.retain(|f| matches!(f.status, FolderStatus::Processing));
+        self.revision += 1;
+    }
+
+    /// Mark the first pending folder as processing and return a copy of it
+    pub fn claim_next(&mut self) -> Option<FolderInfo> {
+        let folder = self.folders.iter_mut().find(|f| matches!(f.status, FolderStatus::Pending))?;
+        folder.status = FolderStatus::Processing;
+        Some(folder.clone())
+    }
+}
+
+/// A queue entry's final frame list once overlapping entries are resolved
+#[derive(Clone, Debug, Default)]
+pub struct PlannedFolder {
+    pub id: u64,
+    pub files: Vec<PathBuf>,
+    /// Earlier entry naming the same folder; this one is not processed separately
+    pub merged_into: Option<u64>,
+    /// How the entry was changed by overlaps, for the user
+    pub notes: Vec<String>,
+}
+
+/// The queue resolved into the frames each entry will actually render, in queue order
+#[derive(Clone, Debug, Default)]
+pub struct WorkPlan {
+    pub folders: Vec<PlannedFolder>,
+}
+
+/// Resolve overlapping entries into a work plan.
+///
+/// Entries are compared by canonical path. When two entries name the same
+/// folder, the first keeps it and the later one is merged into it. Frames a
+/// recursive entry finds inside another entry's folder are left to that entry,
+/// so the more specific entry's per-folder settings win; when that entry isn't
+/// recursive, only the frames directly in its folder are left to it and the
+/// recursive entry keeps the deeper ones. Frame lists come from
+/// each entry's `scan`, so callers wanting fresh results re-scan first.
+pub fn plan(entries: &[FolderInfo]) -> WorkPlan {
+    let mut folders = Vec::with_capacity(entries.len());
+
+    for (i, entry) in entries.iter().enumerate() {
+        if let Some(first) = entries[..i].iter().find(|e| e.canonical_path == entry.canonical_path) {
+            folders.push(PlannedFolder {
+                id: entry.id,
+                files: Vec::new(),
+                merged_into: Some(first.id),
+                notes: vec![format!("same folder as {}, merged into it", first.name)],
+            });
+            continue;
+        }
+
+        let mut files = entry.scan.files.clone();
+        let mut notes = Vec::new();
+        for other in entries {
+            if other.canonical_path == entry.canonical_path {
+                continue;
+            }
+            if entry.scan_options.recursive && other.canonical_path.starts_with(&entry.canonical_path) {
+                let Ok(relative) = other.canonical_path.strip_prefix(&entry.canonical_path) else { continue };
+                let subtree = entry.path.join(relative);
+                let before = files.len();
+                match other.scan_options.recursive {
+                    true => files.retain(|f| !f.starts_with(&subtree)),
+                    false => files.retain(|f| f.parent() != Some(subtree.as_path())),
+                }
+                if files.len() < before {
+                    notes.push(format!("{} frames under {} left to its own entry", before - files.len(), other.name));
+                }
+            } else if other.scan_options.recursive && entry.canonical_path.starts_with(&other.canonical_path) {
+                notes.push(format!("takes these frames over from recursive entry {}", other.name));
+            }
+        }
+
+        folders.push(PlannedFolder {
+            id: entry.id,
+            files,
+            merged_into: None,
+            notes,
+        });
+    }
+    WorkPlan { folders }
+}
+
+/// Version of the queue document format written by [`JobQueue::to_document`]
+pub const QUEUE_DOCUMENT_VERSION: u32 = 1;
+
+/// A whole queue and the settings to run it with, for moving it between machines
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueDocument {
+    pub version: u32,
+    pub settings: ProcessingSettings,
+    pub folders: Vec<QueuedFolder>,
+}
+
+/// One queue entry in a [`QueueDocument`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedFolder {
+    pub path: PathBuf,
+    pub scan_options: ScanOptions,
+    /// Per-folder override of the existing-output check
+    #[serde(default)]
+    pub force: bool,
+    /// Per-folder settings replacing the document's
+    #[serde(default)]
+    pub settings: Option<ProcessingSettings>,
+    /// Per-folder secondary folder replacing the settings' one
+    #[serde(default)]
+    pub secondary: Option<PathBuf>,
+}
+
+/// Rewrites a path prefix when importing a queue, since mount points differ between machines
+#[derive(Clone, Debug)]
+pub struct PathMapping {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl std::str::FromStr for PathMapping {
+    type Err = String;
+
+    /// Parse `FROM=TO`, e.g. `/Volumes/radar=/mnt/radar`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s.split_once('=')
+            .ok_or_else(|| format!("expected FROM=TO, got \"{}\"", s))?;
+        let (from, to) = (from.trim(), to.trim());
+        if from.is_empty() || to.is_empty() {
+            return Err(format!("expected FROM=TO, got \"{}\"", s));
+        }
+        Ok(PathMapping { from: from.into(), to: to.into() })
+    }
+}
+
+/// Apply the first mapping whose prefix matches `path`, comparing whole components
+pub fn remap_path(path: &Path, mappings: &[PathMapping]) -> PathBuf {
+    mappings.iter()
+        .find_map(|m| path.strip_prefix(&m.from).ok().map(|rest| m.to.join(rest)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Outcome of importing a queue document
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Indices of the entries added to the queue
+    pub added: Vec<usize>,
+    /// Folders that could not be queued (after remapping), with the reason
+    pub rejected: Vec<(PathBuf, String)>,
+}
+
+impl JobQueue {
+    /// Describe the queue and `settings` as a self-contained document
+    pub fn to_document(&self, settings: &ProcessingSettings) -> QueueDocument {
+        QueueDocument {
+            version: QUEUE_DOCUMENT_VERSION,
+            settings: settings.clone(),
+            folders: self.folders.iter()
+                .map(|f| QueuedFolder {
+                    path: f.path.clone(),
+                    scan_options: f.scan_options.clone(),
+                    force: f.force,
+                    settings: f.settings.clone(),
+                    secondary: f.secondary.clone(),
                 })
-                .collect()
-        })
-        .unwrap_or_default();
-    
-    files.sort();
-    files
+                .collect(),
+        }
+    }
+
+    /// Queue every folder in `document`, remapping paths first.
+    ///
+    /// Each folder is validated on its own; unreachable or duplicate entries
+    /// are reported and the rest are still added. Overlaps were accepted when
+    /// the queue was built, so they are allowed here.
+    pub fn import(&mut self, document: &QueueDocument, mappings: &[PathMapping]) -> ImportReport {
+        let mut report = ImportReport::default();
+        for entry in &document.folders {
+            let path = remap_path(&entry.path, mappings);
+            if !path.is_dir() {
+                report.rejected.push((path, "not a reachable folder".to_string()));
+                continue;
+            }
+            match self.add_folder(&path, entry.scan_options.clone(), true) {
+                Ok(index) => {
+                    self.folders[index].force = entry.force;
+                    self.folders[index].settings = entry.settings.clone().map(|mut settings| {
+                        settings.output_root = settings.output_root.map(|root| remap_path(&root, mappings));
+                        settings
+                    });
+                    self.folders[index].secondary = entry.secondary.as_deref().map(|path| remap_path(path, mappings));
+                    report.added.push(index);
+                }
+                Err(e) => report.rejected.push((path, e.to_string())),
+            }
+        }
+        report
+    }
+}
+
+/// Write a queue document as pretty-printed JSON
+pub fn save_queue_document(path: &Path, document: &QueueDocument) -> anyhow::Result<()> {
+    let content = serde_json::to_string_pretty(document)?;
+    std::fs::write(path, content).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+/// Read a queue document, refusing formats newer than this build understands
+pub fn load_queue_document(path: &Path) -> anyhow::Result<QueueDocument> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let document: QueueDocument = serde_json::from_str(&content)
+        .with_context(|| format!("parsing {}", path.display()))?;
+    if document.version > QUEUE_DOCUMENT_VERSION {
+        return Err(anyhow!("queue format version {} is newer than this app supports", document.version));
+    }
+    Ok(document)
+}
+
+/// The frame a folder is pictured by: the middle one, which is more typical than the first
+fn representative_frame(folder: &FolderInfo) -> Option<&PathBuf> {
+    folder.scan.files.get(folder.scan.files.len() / 2)
+}
+
+/// Decode a folder's representative frame and shrink it to fit within
+/// `max_px` on each side, keeping its aspect ratio
+pub fn make_thumbnail(folder: &FolderInfo, max_px: u32) -> anyhow::Result<RgbaImage> {
+    let frame = representative_frame(folder).ok_or_else(|| anyhow!("no frames to picture"))?;
+    let image = crate::engine::load_frame(frame, &ProcessingSettings::default())?;
+    Ok(image::DynamicImage::ImageRgba8(image).thumbnail(max_px, max_px).to_rgba8())
+}
+
+/// Path of the folder's thumbnail in the on-disk cache, made on first use.
+///
+/// Cache entries are keyed by the frame's path and modification time, so
+/// later launches reuse them until the frame changes.
+pub fn cached_thumbnail(folder: &FolderInfo, max_px: u32) -> anyhow::Result<PathBuf> {
+    let frame = representative_frame(folder).ok_or_else(|| anyhow!("no frames to picture"))?;
+    let mtime = std::fs::metadata(frame)?.modified()?;
+    let mut hasher = DefaultHasher::new();
+    (frame, mtime, max_px).hash(&mut hasher);
+
+    let dir = crate::config::data_dir()
+        .ok_or_else(|| anyhow!("could not determine data directory"))?
+        .join("thumbnails");
+    let path = dir.join(format!("{:016x}.png", hasher.finish()));
+    if !path.is_file() {
+        let thumbnail = make_thumbnail(folder, max_px)?;
+        std::fs::create_dir_all(&dir)?;
+        thumbnail.save(&path).with_context(|| format!("saving {}", path.display()))?;
+    }
+    Ok(path)
+}
+
+/// Supported image extensions
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "tga", "gif", "tif", "tiff"];
+
+/// Files operating systems leave behind in folders, skipped along with dotfiles
+const SYSTEM_FILE_NAMES: &[&str] = &["Thumbs.db", "ehthumbs.db", "desktop.ini", "Icon\r"];
+
+/// What to do when a scan meets a symbolic link
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    Follow,
+    Skip,
+}
+
+/// Manifest written by the ingest system to define the authoritative frame order
+pub const DEFAULT_MANIFEST_NAME: &str = "order.txt";
+
+/// The order a scan puts a folder's frames in, without a manifest
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// By name, with the numbers in names compared as numbers; see [`natural_cmp`]
+    #[default]
+    Natural,
+    /// By name, character by character, so `frame_10` comes before `frame_2`
+    Lexical,
+    /// By modification time, oldest first, and by name between files of the same time
+    Mtime,
+}
+
+/// Compare two names as people read them: runs of digits are compared as
+/// numbers, and the text between them character by character. Names that
+/// only differ in the zeros a number is padded with, such as `img007` and
+/// `img7`, are ordered by their characters, so no two names compare equal.
+///
+/// ```
+/// use radar_echo_trails::queue::natural_cmp;
+///
+/// let mut names = vec!["frame_10.png", "frame_2.png", "img10", "img007", "frame_1.png"];
+/// names.sort_by(|a, b| natural_cmp(a, b));
+/// assert_eq!(names, ["frame_1.png", "frame_2.png", "frame_10.png", "img007", "img10"]);
+/// ```
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    /// The next run of digits or of other characters at the start of `text`
+    fn run(text: &str) -> (&str, &str) {
+        let digits = text.starts_with(|c: char| c.is_ascii_digit());
+        let end = text.find(|c: char| c.is_ascii_digit() != digits).unwrap_or(text.len());
+        text.split_at(end)
+    }
+
+    let (mut left, mut right) = (a, b);
+    while !left.is_empty() && !right.is_empty() {
+        let ((left_run, left_rest), (right_run, right_rest)) = (run(left), run(right));
+        let numbers = left_run.starts_with(|c: char| c.is_ascii_digit()) && right_run.starts_with(|c: char| c.is_ascii_digit());
+        let order = if numbers {
+            let (left_number, right_number) = (left_run.trim_start_matches('0'), right_run.trim_start_matches('0'));
+            left_number.len().cmp(&right_number.len()).then_with(|| left_number.cmp(right_number))
+        } else {
+            left_run.cmp(right_run)
+        };
+        if order.is_ne() {
+            return order;
+        }
+        (left, right) = (left_rest, right_rest);
+    }
+    left.len().cmp(&right.len()).then_with(|| a.cmp(b))
+}
+
+/// [`natural_cmp`] of two paths a component at a time, so a folder's files
+/// stay together
+fn natural_path_cmp(a: &Path, b: &Path) -> std::cmp::Ordering {
+    let (mut a, mut b) = (a.components(), b.components());
+    loop {
+        match (a.next(), b.next()) {
+            (Some(a), Some(b)) => {
+                let order = natural_cmp(&a.as_os_str().to_string_lossy(), &b.as_os_str().to_string_lossy());
+                if order.is_ne() {
+                    return order;
+                }
+            }
+            (a, b) => return a.is_some().cmp(&b.is_some()),
+        }
+    }
+}
+
+/// Sort `files` in `order`
+fn sort_files(files: &mut [PathBuf], order: SortOrder) {
+    let natural = |a: &PathBuf, b: &PathBuf| natural_path_cmp(a, b);
+    match order {
+        SortOrder::Natural => files.sort_by(|a, b| natural(a, b)),
+        SortOrder::Lexical => files.sort(),
+        SortOrder::Mtime => {
+            let mut timed: Vec<(Option<SystemTime>, PathBuf)> = files.iter()
+                .map(|path| (std::fs::metadata(path).and_then(|m| m.modified()).ok(), path.clone()))
+                .collect();
+            timed.sort_by(|(a_time, a), (b_time, b)| a_time.cmp(b_time).then_with(|| natural(a, b)));
+            for (file, (_, path)) in files.iter_mut().zip(timed) {
+                *file = path;
+            }
+        }
+    }
+}
+
+/// Options controlling how a folder is scanned
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// Descend into subdirectories
+    pub recursive: bool,
+    /// Policy for links pointing at directories (only relevant when recursive)
+    pub dir_symlinks: SymlinkPolicy,
+    /// Policy for links pointing at files
+    pub file_symlinks: SymlinkPolicy,
+    /// File inside the folder listing the frames to use, in order; `None` ignores manifests
+    pub manifest_name: Option<String>,
+    /// Keep dotfiles (including AppleDouble `._` files) and OS system files;
+    /// empty files are skipped either way, since they can never decode
+    #[serde(default)]
+    pub include_hidden: bool,
+    /// Order of the frames when no manifest gives one
+    #[serde(default)]
+    pub sort: SortOrder,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            recursive: false,
+            dir_symlinks: SymlinkPolicy::Skip,
+            file_symlinks: SymlinkPolicy::Follow,
+            manifest_name: Some(DEFAULT_MANIFEST_NAME.to_string()),
+            include_hidden: false,
+            sort: SortOrder::default(),
+        }
+    }
+}
+
+/// Why a directory entry was left out of a scan
+#[derive(Clone, Debug)]
+pub enum SkipReason {
+    /// Extension is not one of the supported image formats
+    NotAnImage,
+    /// Entry or its metadata could not be read
+    Unreadable(String),
+    /// Symbolic link whose target does not exist
+    BrokenSymlink,
+    /// Symbolic link left out by the scan's symlink policy
+    SymlinkSkipped,
+    /// Directory already scanned under another path (symlink cycle or alias)
+    AlreadyVisited,
+    /// File resolves to the same target as an earlier file
+    Duplicate(PathBuf),
+    /// Image left out of the folder's manifest on purpose
+    NotInManifest,
+    /// Listed in the manifest but not present on disk
+    MissingFromDisk,
+    /// Dotfile, AppleDouble resource fork or OS system file
+    Hidden,
+    /// Zero-byte file, such as a frame whose copy never completed
+    Empty,
+    /// ODIM_H5 file without the selected product; lists the quantities its scan has
+    MissingQuantity { wanted: String, available: Vec<String> },
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::NotAnImage => write!(f, "not a supported image"),
+            SkipReason::Unreadable(e) => write!(f, "unreadable ({})", e),
+            SkipReason::BrokenSymlink => write!(f, "broken symlink"),
+            SkipReason::SymlinkSkipped => write!(f, "symlink skipped"),
+            SkipReason::AlreadyVisited => write!(f, "directory already scanned"),
+            SkipReason::Duplicate(first) => write!(f, "same file as {}", first.display()),
+            SkipReason::NotInManifest => write!(f, "excluded by manifest"),
+            SkipReason::MissingFromDisk => write!(f, "listed in manifest but missing"),
+            SkipReason::Hidden => write!(f, "hidden or system file"),
+            SkipReason::Empty => write!(f, "empty file"),
+            SkipReason::MissingQuantity { wanted, available } if available.is_empty() => {
+                write!(f, "no {} (the scan has no quantities)", wanted)
+            }
+            SkipReason::MissingQuantity { wanted, available } => {
+                write!(f, "no {} (has {})", wanted, available.join(", "))
+            }
+        }
+    }
+}
+
+/// Result of a single pass over a folder
+#[derive(Clone, Debug, Default)]
+pub struct FolderScan {
+    /// Image files in the options' sort order, or in manifest order when a manifest was used
+    pub files: Vec<PathBuf>,
+    /// Combined size of `files` in bytes
+    pub total_bytes: u64,
+    /// Entries that were seen but not included
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+    /// Modification time of every directory entered (and the manifest), as of the scan
+    pub mtimes: Vec<(PathBuf, SystemTime)>,
+    /// Manifest that defined the frame list, if one was found
+    pub manifest: Option<ManifestUsage>,
+    /// Sidecar files of frame overrides, named after an image
+    pub sidecars: Vec<PathBuf>,
+}
+
+/// How a folder's manifest shaped its frame list
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestUsage {
+    pub path: PathBuf,
+    /// Entries listed in the manifest
+    pub listed: usize,
+    /// Listed entries with no matching image on disk
+    pub missing: usize,
+    /// Images on disk the manifest leaves out
+    pub excluded: usize,
+}
+
+impl FolderScan {
+    /// Entries that could not be read, as opposed to ones left out on purpose
+    pub fn unreadable(&self) -> impl Iterator<Item = &(PathBuf, SkipReason)> {
+        self.skipped.iter()
+            .filter(|(_, reason)| matches!(reason, SkipReason::Unreadable(_) | SkipReason::BrokenSymlink))
+    }
+
+    /// Number of hidden, system and empty files left out of the scan
+    pub fn hidden_count(&self) -> usize {
+        self.skipped.iter()
+            .filter(|(_, reason)| matches!(reason, SkipReason::Hidden | SkipReason::Empty))
+            .count()
+    }
+
+    /// Explain a scan that found no frames, telling unreadable entries apart
+    /// from a readable folder without matching images
+    pub fn describe_empty(&self) -> String {
+        let unreadable: Vec<_> = self.unreadable().collect();
+        match unreadable.first() {
+            Some((path, reason)) => format!(
+                "No images found; {} entries could not be read, e.g. {}: {}",
+                unreadable.len(),
+                path.display(),
+                reason
+            ),
+            None if self.manifest.is_some() => "No images found; the frame manifest lists none that exist".to_string(),
+            None => format!(
+                "Folder is readable but contains no supported images ({} other entries)",
+                self.skipped.len()
+            ),
+        }
+    }
+
+    /// Whether nothing the scan depended on has changed since it was taken
+    pub fn is_fresh(&self) -> bool {
+        !self.mtimes.is_empty()
+            && self.mtimes.iter().all(|(path, scanned)| folder_mtime(path) == Some(*scanned))
+    }
+}
+
+fn folder_mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Whether a file or folder name is a dotfile (which covers AppleDouble `._`
+/// resource forks and `.DS_Store`) or a known OS system file
+pub fn is_hidden_name(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with('.') || SYSTEM_FILE_NAMES.iter().any(|system| system.eq_ignore_ascii_case(&name))
+}
+
+fn is_image_file(path: &Path) -> bool {
+    #[cfg(feature = "hdf5")]
+    if crate::odim::is_odim_file(path) {
+        return true;
+    }
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.iter().any(|ie| ie.eq_ignore_ascii_case(ext)))
+        .unwrap_or(false)
+}
+
+/// Scan a directory once, collecting image files, their total size and anything skipped.
+///
+/// Only failing to list `path` itself is an error; entries and subdirectories
+/// that cannot be read are recorded in [`FolderScan::skipped`].
+pub fn scan_folder(path: &Path, options: &ScanOptions) -> Result<FolderScan, ScanError> {
+    let mut scan = FolderScan::default();
+
+    let manifest_path = options.manifest_name.as_ref()
+        .map(|name| path.join(name))
+        .filter(|p| p.is_file());
+
+    let mut walk = Walk {
+        options,
+        manifest_path: manifest_path.as_deref(),
+        visited_dirs: HashSet::new(),
+        seen_files: HashMap::new(),
+        file_sizes: HashMap::new(),
+    };
+    let root = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    walk.visited_dirs.insert(root.clone());
+    walk.scan_dir(path, &root, &mut scan)
+        .map_err(|source| ScanError { path: path.to_path_buf(), source })?;
+
+    sort_files(&mut scan.files, options.sort);
+
+    if let Some(manifest_path) = &manifest_path {
+        match std::fs::read_to_string(manifest_path) {
+            Ok(content) => apply_manifest(path, manifest_path, &content, &walk.file_sizes, &mut scan),
+            Err(e) => scan.skipped.push((manifest_path.clone(), SkipReason::Unreadable(e.to_string()))),
+        }
+    }
+    Ok(scan)
+}
+
+/// Replace the scanned frame list with the manifest's, in manifest order.
+///
+/// The manifest lists one path per line relative to the folder; blank lines
+/// and lines starting with `#` are ignored.
+fn apply_manifest(
+    root: &Path,
+    manifest_path: &Path,
+    content: &str,
+    file_sizes: &HashMap<PathBuf, u64>,
+    scan: &mut FolderScan,
+) {
+    let mut usage = ManifestUsage {
+        path: manifest_path.to_path_buf(),
+        listed: 0,
+        missing: 0,
+        excluded: 0,
+    };
+    let mut listed = HashSet::new();
+    let mut files = Vec::new();
+    let mut total_bytes = 0;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        usage.listed += 1;
+        let relative: PathBuf = Path::new(line).components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect();
+        let file_path = root.join(relative);
+        match file_sizes.get(&file_path) {
+            Some(size) if listed.insert(file_path.clone()) => {
+                total_bytes += size;
+                files.push(file_path);
+            }
+            Some(_) => {}
+            None => {
+                usage.missing += 1;
+                scan.skipped.push((file_path, SkipReason::MissingFromDisk));
+            }
+        }
+    }
+
+    for file in scan.files.drain(..) {
+        if !listed.contains(&file) {
+            usage.excluded += 1;
+            scan.skipped.push((file, SkipReason::NotInManifest));
+        }
+    }
+
+    if let Some(mtime) = folder_mtime(manifest_path) {
+        scan.mtimes.push((manifest_path.to_path_buf(), mtime));
+    }
+    scan.files = files;
+    scan.total_bytes = total_bytes;
+    scan.manifest = Some(usage);
+}
+
+/// State shared across one recursive scan
+struct Walk<'a> {
+    options: &'a ScanOptions,
+    /// Manifest file, left out of the scan results
+    manifest_path: Option<&'a Path>,
+    /// Canonical paths of directories already entered
+    visited_dirs: HashSet<PathBuf>,
+    /// Canonical file targets mapped to the path they were first found under
+    seen_files: HashMap<PathBuf, PathBuf>,
+    /// Size of every file added, for re-totalling after a manifest is applied
+    file_sizes: HashMap<PathBuf, u64>,
+}
+
+impl Walk<'_> {
+    /// Scan one directory, failing only when it cannot be listed at all
+    fn scan_dir(&mut self, dir: &Path, canonical_dir: &Path, scan: &mut FolderScan) -> std::io::Result<()> {
+        let entries = std::fs::read_dir(dir)?;
+        if let Some(mtime) = folder_mtime(dir) {
+            scan.mtimes.push((dir.to_path_buf(), mtime));
+        }
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(e) => e,
+                Err(e) => {
+                    scan.skipped.push((dir.to_path_buf(), SkipReason::Unreadable(e.to_string())));
+                    continue;
+                }
+            };
+            let entry_path = entry.path();
+            if Some(entry_path.as_path()) == self.manifest_path {
+                continue;
+            }
+            if !self.options.include_hidden && is_hidden_name(&entry.file_name()) {
+                scan.skipped.push((entry_path, SkipReason::Hidden));
+                continue;
+            }
+            let file_type = match entry.file_type() {
+                Ok(t) => t,
+                Err(e) => {
+                    scan.skipped.push((entry_path, SkipReason::Unreadable(e.to_string())));
+                    continue;
+                }
+            };
+
+            if file_type.is_symlink() {
+                // Resolve the link target; a dangling link is reported, never fatal
+                let target_meta = match std::fs::metadata(&entry_path) {
+                    Ok(m) => m,
+                    Err(_) => {
+                        scan.skipped.push((entry_path, SkipReason::BrokenSymlink));
+                        continue;
+                    }
+                };
+                let canonical = match entry_path.canonicalize() {
+                    Ok(c) => c,
+                    Err(e) => {
+                        scan.skipped.push((entry_path, SkipReason::Unreadable(e.to_string())));
+                        continue;
+                    }
+                };
+                if target_meta.is_dir() {
+                    if !self.options.recursive {
+                        continue;
+                    }
+                    if self.options.dir_symlinks == SymlinkPolicy::Skip {
+                        scan.skipped.push((entry_path, SkipReason::SymlinkSkipped));
+                        continue;
+                    }
+                    self.enter_dir(&entry_path, canonical, scan);
+                } else {
+                    if !is_image_file(&entry_path) {
+                        scan.skipped.push((entry_path, SkipReason::NotAnImage));
+                        continue;
+                    }
+                    if self.options.file_symlinks == SymlinkPolicy::Skip {
+                        scan.skipped.push((entry_path, SkipReason::SymlinkSkipped));
+                        continue;
+                    }
+                    self.add_file(entry_path, canonical, target_meta.len(), scan);
+                }
+            } else if file_type.is_dir() {
+                if self.options.recursive {
+                    let canonical = canonical_dir.join(entry.file_name());
+                    self.enter_dir(&entry_path, canonical, scan);
+                }
+            } else {
+                if sidecar::frame_path(&entry_path).is_some_and(|frame| is_image_file(&frame)) {
+                    scan.sidecars.push(entry_path);
+                    continue;
+                }
+                if !is_image_file(&entry_path) {
+                    scan.skipped.push((entry_path, SkipReason::NotAnImage));
+                    continue;
+                }
+                match entry.metadata() {
+                    Ok(meta) => {
+                        let canonical = canonical_dir.join(entry.file_name());
+                        self.add_file(entry_path, canonical, meta.len(), scan);
+                    }
+                    Err(e) => scan.skipped.push((entry_path, SkipReason::Unreadable(e.to_string()))),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn enter_dir(&mut self, dir: &Path, canonical: PathBuf, scan: &mut FolderScan) {
+        if !self.visited_dirs.insert(canonical.clone()) {
+            scan.skipped.push((dir.to_path_buf(), SkipReason::AlreadyVisited));
+            return;
+        }
+        if let Err(e) = self.scan_dir(dir, &canonical, scan) {
+            scan.skipped.push((dir.to_path_buf(), SkipReason::Unreadable(e.to_string())));
+        }
+    }
+
+    fn add_file(&mut self, path: PathBuf, canonical: PathBuf, len: u64, scan: &mut FolderScan) {
+        if len == 0 {
+            scan.skipped.push((path, SkipReason::Empty));
+            return;
+        }
+        if let Some(first) = self.seen_files.get(&canonical) {
+            scan.skipped.push((path, SkipReason::Duplicate(first.clone())));
+            return;
+        }
+        self.seen_files.insert(canonical, path.clone());
+        self.file_sizes.insert(path.clone(), len);
+        scan.total_bytes += len;
+        scan.files.push(path);
+    }
 }
@@ -0,0 +1,69 @@
+//! Compensation for echo growing fainter with range from the radar
+//!
+//! A single radar's beam widens and climbs with distance, so the same storm
+//! returns weaker echo far from the site than close to it, and distant trails
+//! are drawn fainter than nearby ones. With a gain per 100 km set, each frame
+//! is brightened as it is decoded, before the echo rule and the tint see it,
+//! by a gain growing in a straight line with each pixel's distance from the
+//! radar. The gain is capped, so the noise at the edge of the range is not
+//! amplified without bound. Current and history frames are decoded alike, so
+//! both get the same gain, as do the coverage and cell statistics.
+
+use image::{Rgba, RgbaImage};
+
+use crate::settings::RangeCorrection;
+
+/// File name of the gain field written to the output folder
+pub const RANGE_GAIN_NAME: &str = "range_gain.png";
+
+/// Most a pixel's intensity may be multiplied by
+pub const MAX_RANGE_GAIN: f32 = 10.0;
+
+/// The radar site's pixel in a frame of `size`: the one set, or the centre
+pub fn site(correction: &RangeCorrection, size: (u32, u32)) -> (u32, u32) {
+    correction.center.unwrap_or((size.0 / 2, size.1 / 2))
+}
+
+/// Gain of the pixel at `x`, `y` in a frame of `size`: 1 at the site, growing
+/// by `gain_per_100km` every 100 km, up to `max_gain`
+pub fn gain(correction: &RangeCorrection, size: (u32, u32), x: u32, y: u32) -> f32 {
+    let (cx, cy) = site(correction, size);
+    let pixels = (x as f32 - cx as f32).hypot(y as f32 - cy as f32);
+    let km = pixels * correction.pixel_size_km;
+    (1.0 + correction.gain_per_100km * km / 100.0).min(correction.max_gain).max(1.0)
+}
+
+/// Multiply every channel of each pixel with any alpha by its gain, up to
+/// 255. Alpha is scaled too, as it carries the intensity of frames keyed off
+/// a light background.
+pub fn correct(frame: &mut RgbaImage, correction: &RangeCorrection) {
+    let size = frame.dimensions();
+    for (x, y, pixel) in frame.enumerate_pixels_mut() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let gain = gain(correction, size, x, y);
+        for channel in pixel.0.iter_mut() {
+            *channel = (*channel as f32 * gain).round().min(255.0) as u8;
+        }
+    }
+}
+
+/// The gain over a frame of `size`, for checking the geometry: black where
+/// nothing is added, white at `max_gain`, with a red cross on the site
+pub fn gain_field(correction: &RangeCorrection, size: (u32, u32)) -> RgbaImage {
+    let span = (correction.max_gain - 1.0).max(f32::EPSILON);
+    let mut field = RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let level = ((gain(correction, size, x, y) - 1.0) / span * 255.0).round() as u8;
+        Rgba([level, level, level, 255])
+    });
+    let (cx, cy) = site(correction, size);
+    for offset in -4i64..=4 {
+        for (x, y) in [(cx as i64 + offset, cy as i64), (cx as i64, cy as i64 + offset)] {
+            if x >= 0 && y >= 0 && (x as u32) < size.0 && (y as u32) < size.1 {
+                field.put_pixel(x as u32, y as u32, Rgba([255, 0, 0, 255]));
+            }
+        }
+    }
+    field
+}
@@ -0,0 +1,90 @@
+//! Naming trails by their place in the rendered sequence
+//!
+//! Trails are written under their source's name, which players and encoders
+//! expecting `frame_0000.png`, `frame_0001.png`, ... cannot read in order.
+//! Renumbered, each trail is named by a prefix and its index in the sequence
+//! as it is rendered, counted from 0 and padded with zeros: after offset,
+//! limit, stride and resampling, so the numbers run without gaps over the
+//! frames selected. The index is padded to fit the frame count, unless a
+//! width is set.
+//!
+//! An index map is written beside the trails, pairing each name with its
+//! source and the scan time in the source's name, so a trail can still be
+//! traced back. Appending to a renumbered output reads the map to carry on
+//! from the last index written, at the width the earlier run used.
+
+use std::path::{Path, PathBuf};
+
+use crate::nowcast;
+
+/// Name of the table of renumbered trails written to the output folder
+pub const INDEX_MAP_NAME: &str = "index_map.csv";
+
+/// One trail of the index map
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapEntry {
+    /// Place of the trail in the rendered sequence
+    pub index: usize,
+    /// The trail's file, relative to the output folder
+    pub output: PathBuf,
+    /// Scan time of the source, in seconds since the Unix epoch, when its name carries one
+    pub time: Option<i64>,
+    /// The frame the trail was made from, relative to the source folder
+    pub source: PathBuf,
+}
+
+/// Digits that fit every index of `count` frames, at least one
+pub fn auto_width(count: usize) -> usize {
+    count.saturating_sub(1).max(1).ilog10() as usize + 1
+}
+
+/// The name the trail of `source` is written under as the `index`th of the
+/// sequence: beside `source`, keeping its extension
+pub fn name(source: &Path, prefix: &str, index: usize, width: usize) -> PathBuf {
+    let stem = format!("{}{:0width$}", prefix, index, width = width);
+    match source.extension() {
+        Some(extension) => source.with_file_name(format!("{}.{}", stem, extension.to_string_lossy())),
+        None => source.with_file_name(stem),
+    }
+}
+
+/// Width of the indices of a map written with `prefix`, read from its first
+/// name; `None` for an empty map
+pub fn width_of(entries: &[MapEntry], prefix: &str) -> Option<usize> {
+    let stem = entries.first()?.output.file_stem()?.to_string_lossy().into_owned();
+    let digits = stem.strip_prefix(prefix)?;
+    Some(digits.chars().take_while(char::is_ascii_digit).count()).filter(|&width| width > 0)
+}
+
+/// The index map as CSV. The source comes last, as it is the one column
+/// whose name may hold a comma.
+pub fn to_csv(entries: &[MapEntry]) -> String {
+    let mut csv = String::from("index,output,time,source\n");
+    for entry in entries {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            entry.index,
+            entry.output.to_string_lossy(),
+            entry.time.map(nowcast::scan_time_digits).unwrap_or_default(),
+            entry.source.to_string_lossy(),
+        ));
+    }
+    csv
+}
+
+/// An index map [`to_csv`] wrote; `None` when a row doesn't read
+pub fn parse_csv(csv: &str) -> Option<Vec<MapEntry>> {
+    csv.lines()
+        .skip(1)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut columns = line.splitn(4, ',');
+            let index = columns.next()?.parse().ok()?;
+            let output = PathBuf::from(columns.next()?);
+            let time = columns.next()?;
+            let source = PathBuf::from(columns.next()?);
+            let time = if time.is_empty() { None } else { Some(nowcast::scan_time(Path::new(time))?) };
+            Some(MapEntry { index, output, time, source })
+        })
+        .collect()
+}
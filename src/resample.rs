@@ -0,0 +1,123 @@
+//! Resampling an irregularly scanned sequence onto a regular time grid
+//!
+//! Archives often change scan interval, with one minute scans during storms
+//! and ten minute scans otherwise, so a history of so many frames spans very
+//! different durations. Resampled, a sequence has one frame per slot of a fixed
+//! interval, each slot holding the most recent frame scanned at or before it,
+//! without interpolation, and a history length always covers the same time.
+//!
+//! Slots fall on whole multiples of the interval, counted from the Unix epoch:
+//! from the first at or after the first scan to the last at or before the last
+//! scan. Scans between two slots, when the interval is longer than the scan
+//! interval, are left out; a slot with no new scan holds the frame before it.
+//! The scan times come from the file names, as [`nowcast::scan_time`] reads them.
+
+use std::path::{Path, PathBuf};
+
+use crate::nowcast;
+use crate::settings::ResampleNaming;
+
+/// One slot of the regular grid
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slot {
+    /// The frame shown in the slot
+    pub source: PathBuf,
+    /// Time of the slot, in seconds since the Unix epoch
+    pub time: i64,
+    /// The frame was already shown in an earlier slot
+    pub held: bool,
+    /// The name the slot's trail is written under, beside `source`
+    pub name: PathBuf,
+}
+
+/// Name of the table of slots written to the output folder of a resampled sequence
+pub const RESAMPLE_FILE_NAME: &str = "resampled.csv";
+
+/// Resample `paths` onto a grid of `interval` seconds, as the module describes,
+/// naming each slot by `naming`. `None` when a file name carries no scan time
+/// or there are no frames.
+pub fn resample(paths: &[PathBuf], interval: i64, naming: ResampleNaming) -> Option<Vec<Slot>> {
+    let mut scans: Vec<(i64, &PathBuf)> = paths.iter()
+        .map(|path| Some((nowcast::scan_time(path)?, path)))
+        .collect::<Option<_>>()?;
+    scans.sort_by_key(|&(time, _)| time);
+    let (&(first, _), &(last, _)) = (scans.first()?, scans.last()?);
+    let interval = interval.max(1);
+
+    let mut slots: Vec<Slot> = Vec::new();
+    let mut holds = 0;
+    let mut next = 0;
+    let mut time = first + (interval - first.rem_euclid(interval)) % interval;
+    while time <= last {
+        while next + 1 < scans.len() && scans[next + 1].0 <= time {
+            next += 1;
+        }
+        let (scanned, source) = scans[next];
+        let held = slots.last().is_some_and(|slot| &slot.source == source);
+        holds = if held { holds + 1 } else { 0 };
+        let name = match naming {
+            ResampleNaming::Grid => retimed(source, scanned, time),
+            ResampleNaming::Source => None,
+        };
+        let name = name.unwrap_or_else(|| match holds {
+            0 => source.clone(),
+            holds => suffixed(source, &format!("_hold{}", holds)),
+        });
+        slots.push(Slot { source: source.clone(), time, held, name });
+        time += interval;
+    }
+    Some(slots)
+}
+
+/// `source` with the scan time `from` in its file name changed to `to`, keeping
+/// the separators and whether the name has seconds
+fn retimed(source: &Path, from: i64, to: i64) -> Option<PathBuf> {
+    let stem = source.file_stem()?.to_string_lossy();
+    let digits: String = stem.chars().filter(char::is_ascii_digit).collect();
+    let (from, to) = (nowcast::scan_time_digits(from), nowcast::scan_time_digits(to));
+    let (start, to) = match digits.find(&from) {
+        Some(start) => (start, &to[..]),
+        None => (digits.find(&from[..12])?, &to[..12]),
+    };
+    // Replace the digits from the `start`th on, leaving the separators between them
+    let mut replacement = to.chars();
+    let mut seen = 0;
+    let renamed: String = stem.chars()
+        .map(|c| {
+            if !c.is_ascii_digit() {
+                return c;
+            }
+            seen += 1;
+            if seen > start { replacement.next().unwrap_or(c) } else { c }
+        })
+        .collect();
+    Some(source.with_file_name(with_extension(&renamed, source)))
+}
+
+/// `source` with `suffix` added to its file stem
+fn suffixed(source: &Path, suffix: &str) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    source.with_file_name(with_extension(&format!("{}{}", stem, suffix), source))
+}
+
+/// `stem` with the extension of `source`, if it has one
+fn with_extension(stem: &str, source: &Path) -> String {
+    match source.extension() {
+        Some(extension) => format!("{}.{}", stem, extension.to_string_lossy()),
+        None => stem.to_string(),
+    }
+}
+
+/// The slots as CSV: the slot's time as `YYYYMMDDHHMMSS`, the frame shown,
+/// the name written and whether the frame is held from an earlier slot
+pub fn to_csv(slots: &[Slot]) -> String {
+    let mut csv = String::from("slot,time,source,output,held\n");
+    for (index, slot) in slots.iter().enumerate() {
+        let name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            index, nowcast::scan_time_digits(slot.time), name(&slot.source), name(&slot.name), slot.held,
+        ));
+    }
+    csv
+}
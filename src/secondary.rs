@@ -0,0 +1,82 @@
+//! Pairing the frames of a second folder with those of the primary one
+//!
+//! A second product of the same radar, such as velocity beside reflectivity,
+//! can be drawn as its own trail over the primary one. Each primary frame is
+//! given the secondary frame from the same moment: by scan time when every
+//! file name in both folders carries one, as [`nowcast::scan_time`] reads it,
+//! and by position in the folder otherwise.
+//!
+//! By time, a primary frame takes the secondary frame nearest to it within half
+//! the primary scan interval, and has no secondary frame if none is that close.
+//! By position, the primary frames past the end of the secondary folder have
+//! none; the caller decides whether to leave them out.
+
+use std::path::PathBuf;
+
+use crate::nowcast;
+
+/// How the frames of the two folders were matched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PairedBy {
+    /// Scan times in the file names
+    Time,
+    /// Position in each folder's frame list
+    Index,
+}
+
+/// The secondary frame paired with each primary frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pairing {
+    /// One entry per primary frame, in the same order
+    pub frames: Vec<Option<PathBuf>>,
+    pub paired_by: PairedBy,
+    /// Secondary frames no primary frame was paired with
+    pub unused: usize,
+}
+
+impl Pairing {
+    /// Primary frames left without a secondary frame
+    pub fn unpaired(&self) -> usize {
+        self.frames.iter().filter(|frame| frame.is_none()).count()
+    }
+}
+
+/// Pair each of the `primary` frames with one of the `secondary` frames, both
+/// in sequence order, as the module describes
+pub fn pair(primary: &[PathBuf], secondary: &[PathBuf]) -> Pairing {
+    let times = |paths: &[PathBuf]| paths.iter().map(|path| nowcast::scan_time(path)).collect::<Option<Vec<i64>>>();
+    let (Some(primary_times), Some(secondary_times)) = (times(primary), times(secondary)) else {
+        return by_index(primary, secondary);
+    };
+    if primary.is_empty() || secondary.is_empty() {
+        return by_index(primary, secondary);
+    }
+
+    let tolerance = nowcast::median_interval(primary).map_or(0, |interval| interval / 2);
+    let mut used = vec![false; secondary.len()];
+    let frames = primary_times.iter()
+        .map(|&time| {
+            let (nearest, distance) = secondary_times.iter()
+                .map(|&other| (other - time).abs())
+                .enumerate()
+                .min_by_key(|&(_, distance)| distance)?;
+            (distance <= tolerance).then(|| {
+                used[nearest] = true;
+                secondary[nearest].clone()
+            })
+        })
+        .collect();
+    Pairing {
+        frames,
+        paired_by: PairedBy::Time,
+        unused: used.iter().filter(|&&used| !used).count(),
+    }
+}
+
+fn by_index(primary: &[PathBuf], secondary: &[PathBuf]) -> Pairing {
+    Pairing {
+        frames: (0..primary.len()).map(|index| secondary.get(index).cloned()).collect(),
+        paired_by: PairedBy::Index,
+        unused: secondary.len().saturating_sub(primary.len()),
+    }
+}
@@ -0,0 +1,116 @@
+//! Animated outputs encoded in order while the trails render out of order
+//!
+//! Trails are rendered in parallel and arrive in no particular order, but an
+//! animation takes its frames in order. A [`SequenceWriter`] hands each trail
+//! to an encoder thread, which keeps the trails that arrive early until those
+//! before them are in, so the workers never wait for the encoder. What the
+//! frames are encoded into is up to its [`SequenceEncoder`]: a GIF in
+//! [`gif_export`](crate::gif_export), an MP4 in [`mp4_export`](crate::mp4_export).
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Context, Result};
+use image::RgbaImage;
+
+/// Encodes the frames of an animation, one at a time and in order
+pub trait SequenceEncoder: Send + 'static {
+    /// Encode the trail at `position` in the sequence, counting from 0; a
+    /// position is skipped when its frame failed
+    fn add(&mut self, position: usize, frame: RgbaImage) -> Result<()>;
+
+    /// Complete the animation after its last frame, or, when `encoded` holds
+    /// the error that stopped it, clean up what was written of it
+    fn finish(self: Box<Self>, encoded: Result<()>) -> Result<()>;
+}
+
+/// Feeds the trails of a sequence, in any order, to a [`SequenceEncoder`] on a thread of its own
+pub struct SequenceWriter {
+    /// Each trail at its index, or `None` for one that failed
+    frames: mpsc::Sender<(usize, Option<RgbaImage>)>,
+    encoded: Arc<AtomicUsize>,
+    encoder: JoinHandle<Result<usize>>,
+}
+
+impl SequenceWriter {
+    /// Encode the frames from index `first` on with `encoder`, at their
+    /// position after `first`
+    pub fn spawn(first: usize, encoder: impl SequenceEncoder) -> Result<SequenceWriter> {
+        let (frames, received) = mpsc::channel();
+        let encoded = Arc::new(AtomicUsize::new(0));
+        let count = encoded.clone();
+        let encoder = thread::Builder::new()
+            .name("sequence encoder".to_string())
+            .spawn(move || {
+                let mut encoder = Box::new(encoder);
+                let result = encode(&mut *encoder, first, received, &count);
+                encoder.finish(result).map(|()| count.load(Ordering::Relaxed))
+            })
+            .context("starting the animation encoder")?;
+        Ok(SequenceWriter { frames, encoded, encoder })
+    }
+
+    /// Add the trail of frame `index`, in any order
+    pub fn push(&self, index: usize, frame: RgbaImage) {
+        // The encoder only stops early on an error, which `finish` reports
+        let _ = self.frames.send((index, Some(frame)));
+    }
+
+    /// Skip frame `index`, whose trail failed, so the frames after it are
+    /// encoded without waiting for it
+    pub fn skip(&self, index: usize) {
+        let _ = self.frames.send((index, None));
+    }
+
+    /// Frames encoded so far
+    pub fn encoded(&self) -> usize {
+        self.encoded.load(Ordering::Relaxed)
+    }
+
+    /// Encode the frames still waiting, in order, skipping those that never
+    /// came, and complete the animation, returning how many frames it holds
+    pub fn finish(self) -> Result<usize> {
+        drop(self.frames);
+        self.encoder.join().map_err(|_| anyhow!("the animation encoder stopped unexpectedly"))?
+    }
+}
+
+/// Encode the frames `received` in order, from index `first` on, until every
+/// sender is gone, passing over the indices skipped
+fn encode(
+    encoder: &mut dyn SequenceEncoder,
+    first: usize,
+    received: mpsc::Receiver<(usize, Option<RgbaImage>)>,
+    count: &AtomicUsize,
+) -> Result<()> {
+    let mut add = |index: usize, frame| {
+        encoder.add(index.saturating_sub(first), frame)?;
+        count.fetch_add(1, Ordering::Relaxed);
+        anyhow::Ok(())
+    };
+    let mut waiting = BTreeMap::new();
+    let mut next = first;
+    for (index, frame) in received {
+        // A skip after the frame itself came, as when a frame fails past its
+        // trail, leaves the frame in
+        if frame.is_none() && (index < next || waiting.contains_key(&index)) {
+            continue;
+        }
+        waiting.insert(index, frame);
+        while let Some(frame) = waiting.remove(&next) {
+            if let Some(frame) = frame {
+                add(next, frame)?;
+            }
+            next += 1;
+        }
+    }
+    // Every frame is in; those after a gap left by a frame never sent follow it
+    for (index, frame) in waiting {
+        if let Some(frame) = frame {
+            add(index, frame)?;
+        }
+    }
+    Ok(())
+}
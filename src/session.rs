@@ -0,0 +1,141 @@
+//! The live queue, saved while processing so it can be restored after a crash
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::processing::{self, ProcessingSettings};
+use crate::queue::{FolderStatus, ImportReport, JobQueue, QueuedFolder};
+
+/// Version of the session file format written by [`snapshot`]
+pub const SESSION_VERSION: u32 = 1;
+
+/// The queue as it stood when the session was last saved
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    /// Global settings of the run; folders with their own settings keep them in `folders`
+    pub settings: ProcessingSettings,
+    pub folders: Vec<SessionFolder>,
+}
+
+/// One queue entry and how far it had got
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionFolder {
+    #[serde(flatten)]
+    pub folder: QueuedFolder,
+    pub status: FolderStatus,
+    #[serde(default)]
+    pub progress: f32,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl Session {
+    /// Folders that had not finished when the session was saved
+    pub fn unfinished(&self) -> usize {
+        self.folders.iter()
+            .filter(|f| matches!(f.status, FolderStatus::Pending | FolderStatus::Processing))
+            .count()
+    }
+}
+
+fn session_path() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("session.json"))
+}
+
+/// Describe the queue and the global `settings` it runs with
+pub fn snapshot(queue: &JobQueue, settings: &ProcessingSettings) -> Session {
+    let document = queue.to_document(settings);
+    Session {
+        version: SESSION_VERSION,
+        settings: document.settings,
+        folders: document.folders.into_iter()
+            .zip(queue.folders())
+            .map(|(folder, info)| SessionFolder {
+                folder,
+                status: info.status.clone(),
+                progress: info.progress,
+                message: info.error_message.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// Replace the saved session, so a crash part way through never leaves a truncated file
+pub fn save(session: &Session) -> anyhow::Result<()> {
+    let path = session_path().ok_or_else(|| anyhow!("could not determine data directory"))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let content = serde_json::to_string_pretty(session)?;
+    config::write_atomically(&path, content.as_bytes()).with_context(|| format!("writing {}", path.display()))
+}
+
+/// The session left behind by a run that did not finish, if there is one
+pub fn load() -> anyhow::Result<Option<Session>> {
+    let Some(path) = session_path().filter(|path| path.exists()) else { return Ok(None) };
+    let content = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let session: Session = serde_json::from_str(&content)
+        .with_context(|| format!("parsing {}", path.display()))?;
+    if session.version > SESSION_VERSION {
+        return Err(anyhow!("session format version {} is newer than this app supports", session.version));
+    }
+    Ok(Some(session))
+}
+
+/// Forget the saved session, once its queue has finished or been declined
+pub fn remove() {
+    if let Some(path) = session_path() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Queue the session's folders again, checking each status against the outputs on disk.
+///
+/// A folder that was processing is pending again unless its output was finished
+/// before the crash; a folder marked complete whose output has gone is pending too.
+pub fn restore(queue: &mut JobQueue, session: &Session) -> ImportReport {
+    let mut report = ImportReport::default();
+    for entry in &session.folders {
+        let path = &entry.folder.path;
+        if !path.is_dir() {
+            report.rejected.push((path.clone(), "not a reachable folder".to_string()));
+            continue;
+        }
+        let index = match queue.add_folder(path, entry.folder.scan_options.clone(), true) {
+            Ok(index) => index,
+            Err(e) => {
+                report.rejected.push((path.clone(), e.to_string()));
+                continue;
+            }
+        };
+        let Some(folder) = queue.iter_mut().nth(index) else { continue };
+        folder.force = entry.folder.force;
+        folder.settings = entry.folder.settings.clone();
+        folder.secondary = entry.folder.secondary.clone();
+        folder.error_message = entry.message.clone();
+
+        let settings = folder.effective_settings(&session.settings);
+        let finished = processing::completed_output(&folder.path, &settings).is_some();
+        (folder.status, folder.progress) = match &entry.status {
+            FolderStatus::Processing | FolderStatus::Complete if finished => (FolderStatus::Complete, 1.0),
+            FolderStatus::Processing => {
+                folder.error_message = Some(format!(
+                    "Interrupted at {:.0}% in the last session",
+                    entry.progress * 100.0
+                ));
+                (FolderStatus::Pending, entry.progress)
+            }
+            FolderStatus::Complete => {
+                folder.error_message = Some("Output from the last session is missing".to_string());
+                (FolderStatus::Pending, 0.0)
+            }
+            status => (status.clone(), entry.progress),
+        };
+        report.added.push(index);
+    }
+    report
+}
@@ -0,0 +1,1913 @@
+//! Settings a run is rendered with, and the checks that keep them usable
+//!
+//! These are the engine's settings, so they build without the filesystem and
+//! thread support of the `native` feature.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::echo::{EchoPredicate, DEFAULT_ECHO_RULE};
+use crate::engine;
+use crate::sidecar::FrameOverrides;
+use crate::static_mask::StaticMask;
+
+/// What to do when a frame cannot be read or written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorPolicy {
+    /// Leave the bad frame out and keep going with the rest of the folder
+    #[default]
+    SkipFrame,
+    /// Stop the folder at the first bad frame and mark it as failed
+    FailFolder,
+}
+
+/// Where output goes when the output directory holds a run with different settings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollisionPolicy {
+    /// Write to the next free `_2`, `_3`, ... directory and keep the earlier run
+    #[default]
+    NewSuffix,
+    /// Replace the earlier run's frames
+    Overwrite,
+}
+
+/// Whether each frame's trail is also written as separate layers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayerOutput {
+    /// The flattened trail only
+    #[default]
+    Off,
+    /// The flattened trail, and its history and current layers as transparent PNGs
+    Alongside,
+    /// The layers instead of the flattened trail
+    Only,
+}
+
+/// What to do with a frame whose pixels repeat an earlier frame of its sequence
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepeatPolicy {
+    /// Don't look for repeats
+    #[default]
+    Off,
+    /// Leave repeats out of the sequence
+    Drop,
+    /// Keep repeats, and list them in the run metadata and a warning
+    Flag,
+}
+
+/// Where the history of a sequence's first frames comes from, so they get
+/// trails as long as the rest
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preroll {
+    /// Early frames have only the frames before them
+    #[default]
+    None,
+    /// The first frame, repeated as if it had existed before the sequence
+    Hold,
+    /// The frames after the first, in reverse
+    Mirror,
+    /// The frames at the end of the sequence, for animations that loop
+    Wrap,
+}
+
+impl std::fmt::Display for Preroll {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Preroll::None => "none",
+            Preroll::Hold => "hold",
+            Preroll::Mirror => "mirror",
+            Preroll::Wrap => "wrap",
+        })
+    }
+}
+
+/// How the current frame is recolored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CurrentStyle {
+    /// The current color scaled by each pixel's grey level, keeping its alpha,
+    /// so weak echo and anti-aliased edges stay faint
+    #[default]
+    Scaled,
+    /// The current color at full strength and opacity wherever there is echo
+    Solid,
+    /// The frame's own colors and alpha, such as a reflectivity palette, as they are
+    Original,
+}
+
+impl std::fmt::Display for CurrentStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CurrentStyle::Scaled => "scaled",
+            CurrentStyle::Solid => "solid",
+            CurrentStyle::Original => "original",
+        })
+    }
+}
+
+/// What is done with a frame of another size than the first of its folder
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizeMismatch {
+    /// It fails, as a frame that can't be read does
+    #[default]
+    Fail,
+    /// It is resized to the first frame's size with the resize filter
+    Resize,
+    /// It is centered at the first frame's size: a smaller frame with empty
+    /// margins, a larger one cut off at the sides
+    Pad,
+}
+
+impl std::fmt::Display for SizeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SizeMismatch::Fail => "fail",
+            SizeMismatch::Resize => "resize",
+            SizeMismatch::Pad => "pad",
+        })
+    }
+}
+
+/// Where the opacity of each pixel of a frame comes from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlphaSource {
+    /// The frame's alpha channel, opaque for formats without one
+    #[default]
+    AlphaChannel,
+    /// The luma of each pixel's color, so black is empty and bright echo opaque
+    Luminance,
+}
+
+impl std::fmt::Display for AlphaSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AlphaSource::AlphaChannel => "alpha-channel",
+            AlphaSource::Luminance => "luminance",
+        })
+    }
+}
+
+/// How the history frames are recolored; either way they fade with age
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoryStyle {
+    /// The history color, gradient or colors by age, scaled by each pixel's grey level
+    #[default]
+    Tinted,
+    /// The frames' own colors, as they are
+    Original,
+}
+
+impl std::fmt::Display for HistoryStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HistoryStyle::Tinted => "tinted",
+            HistoryStyle::Original => "original",
+        })
+    }
+}
+
+/// The filter frames are resized with; frames whose alpha is all either
+/// opaque or transparent keep that hard edge whichever is chosen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+    CatmullRom,
+    Lanczos3,
+}
+
+impl std::fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ResizeFilter::Nearest => "nearest",
+            ResizeFilter::Bilinear => "bilinear",
+            ResizeFilter::CatmullRom => "catmullrom",
+            ResizeFilter::Lanczos3 => "lanczos3",
+        })
+    }
+}
+
+/// How the colors of an animated GIF's frames that its palette lacks are made up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GifDither {
+    /// Each pixel takes the nearest palette color
+    #[default]
+    None,
+    /// A fixed 4 by 4 Bayer pattern, which stays put from frame to frame
+    Ordered,
+    /// Each pixel's error is spread over its neighbours
+    FloydSteinberg,
+}
+
+impl std::fmt::Display for GifDither {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GifDither::None => "none",
+            GifDither::Ordered => "ordered",
+            GifDither::FloydSteinberg => "floyd-steinberg",
+        })
+    }
+}
+
+/// A setting whose value cannot be used, with the form it should take
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidField {
+    pub field: String,
+    pub value: String,
+    pub expected: String,
+}
+
+impl std::fmt::Display for InvalidField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is {}, expected {}", self.field, self.value, self.expected)
+    }
+}
+
+impl InvalidField {
+    pub fn new(field: &str, value: impl std::fmt::Display, expected: &str) -> Self {
+        InvalidField {
+            field: field.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+}
+
+/// Check a history length; a trail needs at least the frame before the current one
+pub fn check_history_length(value: usize) -> Option<InvalidField> {
+    (value < 1).then(|| InvalidField::new("history_length", value, "a whole number of at least 1"))
+}
+
+/// Smallest fraction of full resolution history frames may be drawn at
+pub const MIN_HISTORY_SCALE: f32 = 0.25;
+
+pub fn default_history_scale() -> f32 {
+    1.0
+}
+
+pub fn default_stride() -> usize {
+    1
+}
+
+/// Most folders [`ProcessingSettings::folder_parallelism`] lets render at once
+pub const MAX_FOLDER_PARALLELISM: usize = 16;
+
+pub fn default_folder_parallelism() -> usize {
+    1
+}
+
+/// Check how many folders may render at once
+pub fn check_folder_parallelism(field: &str, value: usize) -> Option<InvalidField> {
+    (!(1..=MAX_FOLDER_PARALLELISM).contains(&value))
+        .then(|| InvalidField::new(field, value, &format!("between 1 and {}", MAX_FOLDER_PARALLELISM)))
+}
+
+/// Check a frame stride; 0 would select nothing
+pub fn check_stride(field: &str, value: usize) -> Option<InvalidField> {
+    (value < 1).then(|| InvalidField::new(field, value, "a whole number of at least 1"))
+}
+
+/// Smallest tile [`ProcessingSettings::tile_size`] splits frames into
+pub const MIN_TILE_SIZE: u32 = 64;
+
+/// Check a tile size; 0 turns tiling off
+pub fn check_tile_size(field: &str, value: u32) -> Option<InvalidField> {
+    (value != 0 && value < MIN_TILE_SIZE)
+        .then(|| InvalidField::new(field, value, &format!("0 or a whole number of at least {}", MIN_TILE_SIZE)))
+}
+
+/// Check a history scale, a fraction of full resolution
+pub fn check_history_scale(field: &str, value: f32) -> Option<InvalidField> {
+    (!(MIN_HISTORY_SCALE..=1.0).contains(&value))
+        .then(|| InvalidField::new(field, value, &format!("a number from {} to 1", MIN_HISTORY_SCALE)))
+}
+
+/// Most frames read or written at once when the IO thread count is left automatic
+pub const MAX_AUTO_IO_THREADS: usize = 8;
+
+/// Output directory name used unless the settings give another template
+pub const DEFAULT_OUTPUT_NAME_TEMPLATE: &str = "{folder}_trail_{history}";
+
+/// Placeholders an output name template may contain
+const OUTPUT_NAME_PLACEHOLDERS: &[&str] = &["folder", "parent", "history"];
+
+pub fn default_output_name_template() -> String {
+    DEFAULT_OUTPUT_NAME_TEMPLATE.to_string()
+}
+
+pub fn default_echo_rule() -> String {
+    DEFAULT_ECHO_RULE.to_string()
+}
+
+/// Color space a history gradient is mixed in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GradientSpace {
+    /// Each channel mixed in linear light, so the colors between the ends are
+    /// no darker than they are
+    #[default]
+    LinearRgb,
+    /// Hue turned the short way round the color wheel, with saturation and
+    /// value mixed, so red to blue passes through magenta rather than grey
+    Hsv,
+}
+
+impl std::fmt::Display for GradientSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GradientSpace::LinearRgb => "linear RGB",
+            GradientSpace::Hsv => "HSV",
+        })
+    }
+}
+
+/// How the history is carried from one frame to the next
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrailMode {
+    /// Every frame drawn over its `history_length` history frames, each faded by its age
+    #[default]
+    Fade,
+    /// A buffer of the history faded by the decay factor each frame and the
+    /// last frame stamped on it, so the history costs the same however long
+    /// it is; the frames are rendered one after another
+    Decay,
+}
+
+impl std::fmt::Display for TrailMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TrailMode::Fade => "fade",
+            TrailMode::Decay => "decay",
+        })
+    }
+}
+
+/// Check a decay factor, the opacity the history keeps from one frame to the
+/// next; 0 derives it from the history length
+pub fn check_decay(field: &str, value: f32) -> Option<InvalidField> {
+    (!(0.0..1.0).contains(&value)).then(|| InvalidField::new(field, value, "0, for one from the history length, or a number below 1"))
+}
+
+/// Colors of the newest and the oldest history frame
+pub type HistoryGradient = ((u8, u8, u8), (u8, u8, u8));
+
+/// Read a history gradient such as `#ff0000:#0000ff`; a single color is both
+/// ends, and an empty gradient is none
+pub fn parse_history_gradient(text: &str) -> Result<Option<HistoryGradient>, String> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let colors = text.split(':')
+        .map(|color| parse_hex_color(color.trim()).map_err(|_| format!("\"{}\" is not a hex color", color.trim())))
+        .collect::<Result<Vec<_>, _>>()?;
+    match colors[..] {
+        [color] => Ok(Some((color, color))),
+        [newest, oldest] => Ok(Some((newest, oldest))),
+        _ => Err(format!("{} colors where at most two go", colors.len())),
+    }
+}
+
+/// Check that a history gradient is one or two hex colors, or empty
+pub fn check_history_gradient(field: &str, value: &str) -> Option<InvalidField> {
+    parse_history_gradient(value).err()
+        .map(|_| InvalidField::new(field, format!("\"{}\"", value), "one or two hex colors such as \"#ff0000:#0000ff\", or none"))
+}
+
+/// Read a comma-separated list of history colors such as
+/// `#ffff00,#ff8800,#aa2200`; an empty list is no colors
+pub fn parse_history_colors(text: &str) -> Vec<String> {
+    text.split(',').map(str::trim).filter(|color| !color.is_empty()).map(str::to_string).collect()
+}
+
+/// Write history colors back as [`parse_history_colors`] reads them
+pub fn format_history_colors(colors: &[String]) -> String {
+    colors.join(",")
+}
+
+/// Check that each history color is a hex color
+pub fn check_history_colors(field: &str, colors: &[String]) -> Option<InvalidField> {
+    colors.iter().any(|color| parse_hex_color(color).is_err())
+        .then(|| InvalidField::new(field, format!("\"{}\"", format_history_colors(colors)), "hex colors such as \"#ffff00,#ff8800,#aa2200\", or none"))
+}
+
+/// Read a comma-separated list of history weights such as `0.9,0.7,0.5`;
+/// an empty list is no weights
+pub fn parse_history_weights(text: &str) -> Result<Vec<f32>, String> {
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',')
+        .map(|weight| weight.trim().parse::<f32>().map_err(|_| format!("\"{}\" is not a number", weight.trim())))
+        .collect()
+}
+
+/// Write history weights back as [`parse_history_weights`] reads them
+pub fn format_history_weights(weights: &[f32]) -> String {
+    weights.iter().map(f32::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// Check history weights: each from 0 to 1, and one for each of the
+/// `history_length` history frames when there are any
+pub fn check_history_weights(field: &str, weights: &[f32], history_length: usize) -> Option<InvalidField> {
+    if weights.iter().any(|weight| !(0.0..=1.0).contains(weight)) {
+        return Some(InvalidField::new(field, format_history_weights(weights), "numbers from 0 to 1"));
+    }
+    (!weights.is_empty() && weights.len() != history_length).then(|| InvalidField::new(
+        field,
+        format_history_weights(weights),
+        &format!("one weight for each of the {} history frames", history_length),
+    ))
+}
+
+/// How the fade of the history is bent between the nearest frame and the oldest
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FadeCurve {
+    /// The fade as it is
+    #[default]
+    Linear,
+    /// Near full opacity for the nearest frames, falling faster towards the oldest
+    EaseIn,
+    /// Falling fast behind the nearest frame, leaving a long faint tail
+    EaseOut,
+    /// Each frame back a constant fraction of the one after it, from the
+    /// nearest frame to the oldest, which keep their opacities
+    Exponential,
+    /// The fade raised to this power, above 0: above 1 drops sooner, below 1 lingers
+    Gamma(f32),
+}
+
+impl FadeCurve {
+    /// Bend `fade`, from 0 to 1, where the oldest history frame is at `oldest`
+    pub fn shape(self, fade: f32, oldest: f32) -> f32 {
+        match self {
+            FadeCurve::Linear => fade,
+            FadeCurve::EaseIn => 1.0 - (1.0 - fade).powi(2),
+            FadeCurve::EaseOut => fade.powi(2),
+            FadeCurve::Exponential if oldest > 0.0 && oldest < 1.0 => oldest.powf((1.0 - fade) / (1.0 - oldest)),
+            FadeCurve::Exponential => fade,
+            FadeCurve::Gamma(gamma) => fade.powf(gamma),
+        }
+    }
+}
+
+impl std::fmt::Display for FadeCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FadeCurve::Linear => f.write_str("linear"),
+            FadeCurve::EaseIn => f.write_str("ease-in"),
+            FadeCurve::EaseOut => f.write_str("ease-out"),
+            FadeCurve::Exponential => f.write_str("exponential"),
+            FadeCurve::Gamma(gamma) => write!(f, "{}", gamma),
+        }
+    }
+}
+
+/// Read a fade curve: `linear`, `ease-in`, `ease-out`, `exponential` or a
+/// gamma such as `2.2`; an empty one is linear
+pub fn parse_fade_curve(text: &str) -> Result<FadeCurve, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "" | "linear" => Ok(FadeCurve::Linear),
+        "ease-in" => Ok(FadeCurve::EaseIn),
+        "ease-out" => Ok(FadeCurve::EaseOut),
+        "exponential" => Ok(FadeCurve::Exponential),
+        other => match other.parse::<f32>() {
+            Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Ok(FadeCurve::Gamma(gamma)),
+            Ok(_) => Err(format!("a gamma of {} does not fade; it must be above 0", other)),
+            Err(_) => Err(format!("\"{}\" is not a curve or a number", other)),
+        },
+    }
+}
+
+/// Check that a fade curve parses
+pub fn check_fade_curve(field: &str, value: &str) -> Option<InvalidField> {
+    parse_fade_curve(value).err()
+        .map(|_| InvalidField::new(field, format!("\"{}\"", value), "linear, ease-in, ease-out, exponential or a gamma above 0"))
+}
+
+pub fn default_history_opacity() -> u8 {
+    engine::HISTORY_MAX_ALPHA as u8
+}
+
+/// Check the opacity of the nearest history frame; at 0 no history is drawn
+pub fn check_history_opacity(field: &str, value: u8) -> Option<InvalidField> {
+    (value == 0).then(|| InvalidField::new(field, value, "a level from 1 to 255"))
+}
+
+/// Check that an echo rule parses
+pub fn check_echo_rule(field: &str, value: &str) -> Option<InvalidField> {
+    EchoPredicate::parse(value).err().map(|e| InvalidField {
+        field: field.to_string(),
+        value: format!("\"{}\"", value),
+        expected: format!("a rule like \"{}\" ({})", DEFAULT_ECHO_RULE, e),
+    })
+}
+
+/// Check an output name template: known placeholders only, `{folder}` among
+/// them so folders don't share an output, and no absolute or `..` paths
+pub fn check_output_name_template(field: &str, value: &str) -> Option<InvalidField> {
+    let invalid = || Some(InvalidField::new(
+        field,
+        format!("\"{}\"", value),
+        "a relative name containing {folder}, using only the placeholders {folder}, {parent} and {history}",
+    ));
+    let mut rest = value;
+    let mut has_folder = false;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return invalid();
+        }
+        let Some(close) = rest[open..].find('}') else { return invalid() };
+        let name = &rest[open + 1..open + close];
+        if !OUTPUT_NAME_PLACEHOLDERS.contains(&name) {
+            return invalid();
+        }
+        has_folder |= name == "folder";
+        rest = &rest[open + close + 1..];
+    }
+    let path = Path::new(value);
+    let escapes = path.is_absolute()
+        || path.components().any(|c| !matches!(c, std::path::Component::Normal(_)));
+    if !has_folder || escapes { invalid() } else { None }
+}
+
+/// Check that an output root, when set, is an absolute path
+pub fn check_output_root(field: &str, value: Option<&Path>) -> Option<InvalidField> {
+    value.filter(|root| !root.is_absolute())
+        .map(|root| InvalidField::new(field, root.display(), "an absolute path, or none to write next to each folder"))
+}
+
+/// Check that the latest image, when set, has the extension of an image
+/// format that can be written
+pub fn check_latest_image(field: &str, value: Option<&Path>) -> Option<InvalidField> {
+    value.filter(|path| !image::ImageFormat::from_path(path).is_ok_and(|format| format.writing_enabled()))
+        .map(|path| InvalidField::new(field, path.display(), "an image file name such as \"latest.png\", or none"))
+}
+
+/// Widest index a renumbered trail's name may be padded to
+pub const MAX_RENUMBER_WIDTH: u32 = 12;
+
+/// Check that a renumbering prefix, when set, can start a file name
+pub fn check_renumber_prefix(field: &str, value: Option<&str>) -> Option<InvalidField> {
+    value.filter(|prefix| prefix.chars().any(|c| matches!(c, '/' | '\\' | ',') || c.is_control()))
+        .map(|prefix| InvalidField::new(field, format!("\"{}\"", prefix), "a file name prefix without slashes or commas, or none"))
+}
+
+/// Check a renumbering width; 0 sizes it to the frame count
+pub fn check_renumber_width(field: &str, value: u32) -> Option<InvalidField> {
+    (value > MAX_RENUMBER_WIDTH)
+        .then(|| InvalidField::new(field, value, &format!("at most {} digits, or 0 to fit the frame count", MAX_RENUMBER_WIDTH)))
+}
+
+/// Check that the provenance index, when set, is a JSON or JSON Lines file
+pub fn check_emit_index(field: &str, value: Option<&Path>) -> Option<InvalidField> {
+    value.filter(|path| !path.extension().is_some_and(|extension| extension == "json" || extension == "jsonl"))
+        .map(|path| InvalidField::new(field, path.display(), "a file name ending in .json or .jsonl, or none"))
+}
+
+/// Check that the animated GIF, when set, is a `.gif` file
+pub fn check_animate_gif(field: &str, value: Option<&Path>) -> Option<InvalidField> {
+    value.filter(|path| !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif")))
+        .map(|path| InvalidField::new(field, path.display(), "a file name ending in .gif, or none"))
+}
+
+/// Most colors a GIF frame holds
+pub const MAX_GIF_COLORS: usize = 256;
+
+pub fn default_gif_colors() -> usize {
+    MAX_GIF_COLORS
+}
+
+/// Check the size of the animated GIF's palette, from 2 to 256 colors
+pub fn check_gif_colors(field: &str, value: usize) -> Option<InvalidField> {
+    (!(2..=MAX_GIF_COLORS).contains(&value))
+        .then(|| InvalidField::new(field, value, &format!("2 to {} colors", MAX_GIF_COLORS)))
+}
+
+/// Highest, and worst, H.264 quality an MP4 can be encoded at
+pub const MAX_MP4_CRF: u32 = 51;
+
+/// ffmpeg's own H.264 quality
+pub fn default_mp4_crf() -> u32 {
+    23
+}
+
+/// Check that the MP4 output, when set, is a `.mp4` file
+pub fn check_mp4(field: &str, value: Option<&Path>) -> Option<InvalidField> {
+    value.filter(|path| !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("mp4")))
+        .map(|path| InvalidField::new(field, path.display(), "a file name ending in .mp4, or none"))
+}
+
+/// Check an H.264 quality, from 0, lossless, to 51
+pub fn check_mp4_crf(field: &str, value: u32) -> Option<InvalidField> {
+    (value > MAX_MP4_CRF)
+        .then(|| InvalidField::new(field, value, &format!("0 (lossless) to {}; 23 is ffmpeg's default", MAX_MP4_CRF)))
+}
+
+/// Check that a color setting parses as a hex color
+pub fn check_color(field: &str, value: &str) -> Option<InvalidField> {
+    parse_hex_color(value).err()
+        .map(|_| InvalidField::new(field, format!("\"{}\"", value), "a hex color such as \"#ff7f00\""))
+}
+
+/// Which product of an ODIM_H5 radar file is drawn as the frame
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OdimSelection {
+    /// Scan to read, the `N` of the file's `datasetN` group, counting from 1
+    pub dataset: u32,
+    /// Quantity to draw, such as `DBZH`
+    pub quantity: String,
+}
+
+impl Default for OdimSelection {
+    fn default() -> Self {
+        OdimSelection { dataset: 1, quantity: "DBZH".to_string() }
+    }
+}
+
+impl std::fmt::Display for OdimSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dataset{}/{}", self.dataset, self.quantity)
+    }
+}
+
+/// Check an ODIM scan number; datasets are numbered from 1
+pub fn check_odim_dataset(field: &str, value: u32) -> Option<InvalidField> {
+    (value < 1).then(|| InvalidField::new(field, value, "a scan number of at least 1"))
+}
+
+/// Check an ODIM quantity name, such as `DBZH`
+pub fn check_odim_quantity(field: &str, value: &str) -> Option<InvalidField> {
+    let valid = !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    (!valid).then(|| InvalidField::new(field, format!("\"{}\"", value), "a quantity name such as \"DBZH\""))
+}
+
+/// File format of exported motion grids
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MotionExportFormat {
+    /// One JSON document per frame, `<frame>.uv.json`
+    #[default]
+    Json,
+    /// A JSON header followed by flat little-endian arrays, `<frame>.uv.bin`
+    Binary,
+}
+
+/// Motion arrows drawn over each trail, estimated from the frame before it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionSettings {
+    pub enabled: bool,
+    /// Distance between arrows, and the size of the block each is matched over, in pixels
+    pub grid_spacing: u32,
+    /// Arrow length per pixel of displacement
+    pub vector_scale: f32,
+    /// Blocks whose mean intensity is below this, out of 255, get no arrow
+    pub min_intensity: u8,
+    /// Matches less distinct than this, from 0 to 1, get no arrow
+    pub min_coherence: f32,
+    pub color: String,
+    /// Write each frame's vectors to a `.motion.json` file beside it
+    pub save_json: bool,
+    /// Folder each frame's motion grid is exported to; a relative path is
+    /// taken from the output folder. `None` exports nothing.
+    pub export_dir: Option<PathBuf>,
+    pub export_format: MotionExportFormat,
+    /// Ground size of a pixel, in km, to export speeds in km/h as well
+    pub pixel_size_km: Option<f32>,
+}
+
+impl Default for MotionSettings {
+    fn default() -> Self {
+        MotionSettings {
+            enabled: false,
+            grid_spacing: 16,
+            vector_scale: 3.0,
+            min_intensity: 24,
+            min_coherence: 0.3,
+            color: "#ffffff".to_string(),
+            save_json: false,
+            export_dir: None,
+            export_format: MotionExportFormat::default(),
+            pixel_size_km: None,
+        }
+    }
+}
+
+/// Smallest and largest motion grid spacing, in pixels
+pub const MOTION_GRID_RANGE: (u32, u32) = (4, 256);
+
+/// Check a motion grid spacing
+pub fn check_grid_spacing(field: &str, value: u32) -> Option<InvalidField> {
+    let (min, max) = MOTION_GRID_RANGE;
+    (!(min..=max).contains(&value)).then(|| InvalidField::new(field, value, &format!("a spacing from {} to {} pixels", min, max)))
+}
+
+/// Check a pixel size, when one is given
+pub fn check_pixel_size(field: &str, value: Option<f32>) -> Option<InvalidField> {
+    value.filter(|size| !(*size > 0.0 && size.is_finite()))
+        .map(|size| InvalidField::new(field, size, "a size in km above 0, or none"))
+}
+
+/// Check a motion arrow scale
+pub fn check_vector_scale(field: &str, value: f32) -> Option<InvalidField> {
+    (!(value > 0.0 && value <= 100.0)).then(|| InvalidField::new(field, value, "a scale above 0, up to 100"))
+}
+
+/// Check a motion coherence threshold
+pub fn check_coherence(field: &str, value: f32) -> Option<InvalidField> {
+    (!(0.0..=1.0).contains(&value)).then(|| InvalidField::new(field, value, "a fraction from 0 to 1"))
+}
+
+/// Tracks of isolated echo cells drawn over each trail, through the history window
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackingSettings {
+    pub enabled: bool,
+    /// Pixels at least this intense, out of 255, belong to a cell
+    pub min_intensity: u8,
+    /// Cells smaller than this, in pixels, are not tracked
+    pub min_area: u32,
+    /// Furthest a cell's centroid may move between frames and stay on its track, in pixels
+    pub max_displacement: u32,
+    pub color: String,
+    /// Draw each cell's ID, persistent across the sequence, beside it
+    pub label_ids: bool,
+    /// Frames a cell may go unseen before its ID is retired
+    pub max_missed: u32,
+    /// Write every labelled cell of the sequence to a `cells.csv` in the output folder
+    pub save_csv: bool,
+}
+
+impl Default for TrackingSettings {
+    fn default() -> Self {
+        TrackingSettings {
+            enabled: false,
+            min_intensity: 64,
+            min_area: 16,
+            max_displacement: 32,
+            color: "#ffffff".to_string(),
+            label_ids: false,
+            max_missed: 2,
+            save_csv: false,
+        }
+    }
+}
+
+/// Check a tracking distance or area; zero would track nothing
+pub fn check_tracking_size(field: &str, value: u32) -> Option<InvalidField> {
+    (value < 1).then(|| InvalidField::new(field, value, "a whole number of at least 1"))
+}
+
+/// Forecast frames extrapolated from the last frame's motion and appended to each folder
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NowcastSettings {
+    /// Forecast frames after the last observed one; 0 writes none
+    pub frames: u32,
+}
+
+/// Most forecast frames a folder may get
+pub const MAX_NOWCAST_FRAMES: u32 = 24;
+
+/// Check a forecast frame count
+pub fn check_nowcast_frames(field: &str, value: u32) -> Option<InvalidField> {
+    (value > MAX_NOWCAST_FRAMES)
+        .then(|| InvalidField::new(field, value, &format!("at most {} frames", MAX_NOWCAST_FRAMES)))
+}
+
+/// How fast and how often animated outputs play, shared by every encoder
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationTiming {
+    /// Frames per second; in real time, the rate at the usual scan interval
+    pub fps: f32,
+    /// Times the animation plays; 0 loops forever
+    pub loops: u32,
+    /// Hold each frame in proportion to the time to the next scan, when the
+    /// file names carry scan times
+    pub realtime: bool,
+    /// Longest a frame is held in real time, in milliseconds
+    pub max_frame_ms: u32,
+}
+
+impl Default for AnimationTiming {
+    fn default() -> Self {
+        AnimationTiming { fps: 10.0, loops: 0, realtime: false, max_frame_ms: 2000 }
+    }
+}
+
+/// Fastest and slowest frame rates an animation may play at
+pub const FPS_RANGE: (f32, f32) = (0.1, 100.0);
+
+/// Check a frame rate
+pub fn check_fps(field: &str, value: f32) -> Option<InvalidField> {
+    (!(FPS_RANGE.0..=FPS_RANGE.1).contains(&value))
+        .then(|| InvalidField::new(field, value, &format!("a number from {} to {}", FPS_RANGE.0, FPS_RANGE.1)))
+}
+
+/// Check the longest a frame may be held, at least 10 ms, the finest step GIF frames take
+pub fn check_max_frame_ms(field: &str, value: u32) -> Option<InvalidField> {
+    (value < 10).then(|| InvalidField::new(field, value, "at least 10 milliseconds"))
+}
+
+/// Frames synthesised between each pair of scans
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InterpolationSettings {
+    /// Frames inserted between each pair; 0 inserts none
+    pub frames: u32,
+    /// Write the interpolated trails to the output folder beside the real ones
+    pub write_frames: bool,
+    /// Count interpolated frames in the history window, so trails are drawn
+    /// from them as well as from the scans
+    pub in_history: bool,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        InterpolationSettings { frames: 0, write_frames: true, in_history: false }
+    }
+}
+
+/// Most frames that may be inserted between two scans
+pub const MAX_INTERPOLATED_FRAMES: u32 = 7;
+
+/// Check an interpolated frame count
+pub fn check_interpolated_frames(field: &str, value: u32) -> Option<InvalidField> {
+    (value > MAX_INTERPOLATED_FRAMES)
+        .then(|| InvalidField::new(field, value, &format!("at most {} frames", MAX_INTERPOLATED_FRAMES)))
+}
+
+/// Resampling of each folder onto a regular time grid, from the scan times in the file names
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResampleSettings {
+    /// Minutes between slots of the grid; 0 leaves the sequence as it is
+    pub interval_minutes: u32,
+    pub naming: ResampleNaming,
+}
+
+/// How the trails of a resampled sequence are named
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResampleNaming {
+    /// The source name with its scan time changed to the slot's
+    #[default]
+    Grid,
+    /// The source name, with `_hold1`, `_hold2`, ... added to frames held over
+    Source,
+}
+
+/// Longest resampling interval, a day
+pub const MAX_RESAMPLE_MINUTES: u32 = 1440;
+
+/// Check a resampling interval
+pub fn check_resample_interval(field: &str, value: u32) -> Option<InvalidField> {
+    (value > MAX_RESAMPLE_MINUTES)
+        .then(|| InvalidField::new(field, value, &format!("at most {} minutes", MAX_RESAMPLE_MINUTES)))
+}
+
+/// A second folder of frames drawn over each folder's trail, in colors of its own
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecondarySettings {
+    /// Folder of the second product; a relative path is taken from each primary folder.
+    /// `None` draws no second layer.
+    pub folder: Option<PathBuf>,
+    pub color: String,
+    pub history_color: String,
+    /// Opacity of the whole layer, from 0 to 1
+    pub opacity: f32,
+}
+
+impl Default for SecondarySettings {
+    fn default() -> Self {
+        SecondarySettings {
+            folder: None,
+            color: "#00bfff".to_string(),
+            history_color: "#bf00ff".to_string(),
+            opacity: 1.0,
+        }
+    }
+}
+
+/// Check a layer opacity
+pub fn check_opacity(field: &str, value: f32) -> Option<InvalidField> {
+    (!(0.0..=1.0).contains(&value)).then(|| InvalidField::new(field, value, "a number from 0 to 1"))
+}
+
+/// Overlays burned into every frame, found and masked out as
+/// [`crate::static_mask`] describes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaticMaskSettings {
+    /// Frames compared across each folder; 0 masks nothing
+    pub samples: usize,
+    /// Most any channel of a pixel may change between the frames for it to
+    /// count as the same
+    pub tolerance: u8,
+    /// Pixels the mask is grown by on every side
+    pub dilate: u32,
+}
+
+impl Default for StaticMaskSettings {
+    fn default() -> Self {
+        StaticMaskSettings { samples: 0, tolerance: 8, dilate: 1 }
+    }
+}
+
+/// Most frames compared for a static mask
+pub const MAX_STATIC_MASK_SAMPLES: usize = 32;
+
+/// Most pixels a static mask may be grown by
+pub const MAX_STATIC_MASK_DILATE: u32 = 8;
+
+/// Check a static mask sample count; comparing takes at least two frames
+pub fn check_static_mask_samples(field: &str, value: usize) -> Option<InvalidField> {
+    (value == 1 || value > MAX_STATIC_MASK_SAMPLES)
+        .then(|| InvalidField::new(field, value, &format!("0, or from 2 to {} frames", MAX_STATIC_MASK_SAMPLES)))
+}
+
+/// Check how far a static mask is grown
+pub fn check_static_mask_dilate(field: &str, value: u32) -> Option<InvalidField> {
+    (value > MAX_STATIC_MASK_DILATE)
+        .then(|| InvalidField::new(field, value, &format!("at most {} pixels", MAX_STATIC_MASK_DILATE)))
+}
+
+/// Gain brightening echo with its distance from the radar, as [`crate::range`] describes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RangeCorrection {
+    /// Gain added for every 100 km from the radar, e.g. 0.2 for a fifth
+    /// brighter; 0 corrects nothing
+    pub gain_per_100km: f32,
+    /// Pixel of the radar site, as x and y; `None` is the middle of the frame
+    pub center: Option<(u32, u32)>,
+    /// Ground size of a pixel, in km
+    pub pixel_size_km: f32,
+    /// Highest gain of any pixel, however far out
+    pub max_gain: f32,
+    /// Write the gain over the frame to `range_gain.png` in the output folder
+    pub emit_weights: bool,
+}
+
+impl Default for RangeCorrection {
+    fn default() -> Self {
+        RangeCorrection { gain_per_100km: 0.0, center: None, pixel_size_km: 1.0, max_gain: 3.0, emit_weights: false }
+    }
+}
+
+impl RangeCorrection {
+    /// Whether any pixel is brightened
+    pub fn is_active(&self) -> bool {
+        self.gain_per_100km > 0.0 && self.max_gain > 1.0
+    }
+}
+
+/// Check a gain per 100 km
+pub fn check_range_gain(field: &str, value: f32) -> Option<InvalidField> {
+    (!(0.0..=crate::range::MAX_RANGE_GAIN).contains(&value))
+        .then(|| InvalidField::new(field, value, &format!("a gain from 0 to {}", crate::range::MAX_RANGE_GAIN)))
+}
+
+/// Check the cap on the range gain
+pub fn check_max_range_gain(field: &str, value: f32) -> Option<InvalidField> {
+    (!(1.0..=crate::range::MAX_RANGE_GAIN).contains(&value))
+        .then(|| InvalidField::new(field, value, &format!("a gain from 1 to {}", crate::range::MAX_RANGE_GAIN)))
+}
+
+/// Read a radar site such as `512,480`; an empty one is the middle of the frame
+pub fn parse_radar_center(text: &str) -> Result<Option<(u32, u32)>, String> {
+    parse_pair(text, ',', "x,y", "pixel coordinate")
+}
+
+/// Read two numbers split by `separator`, as `expected` shows them; an empty text is `None`
+fn parse_pair(text: &str, separator: char, expected: &str, what: &str) -> Result<Option<(u32, u32)>, String> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let number = |part: &str| part.trim().parse::<u32>().map_err(|_| format!("\"{}\" is not a {}", part.trim(), what));
+    match text.split_once(separator) {
+        Some((a, b)) => Ok(Some((number(a)?, number(b)?))),
+        None => Err(format!("expected {}", expected)),
+    }
+}
+
+/// Write a radar site back as [`parse_radar_center`] reads it
+pub fn format_radar_center(center: Option<(u32, u32)>) -> String {
+    center.map(|(x, y)| format!("{},{}", x, y)).unwrap_or_default()
+}
+
+/// Canvas each frame is placed on before it is drawn, as [`crate::canvas`] describes
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CanvasSettings {
+    /// Width and height of the canvas; `None` draws on the frames as they are
+    pub size: Option<(u32, u32)>,
+    pub placement: Placement,
+}
+
+/// Where on the canvas a frame is placed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// In the middle, rounded towards the top left
+    #[default]
+    Center,
+    /// In the top left corner
+    TopLeft,
+    /// With the frame's top-left pixel at `x`, `y`
+    Custom { x: u32, y: u32 },
+}
+
+/// Longest side of a canvas, in pixels
+pub const MAX_CANVAS_SIDE: u32 = 32768;
+
+/// Check a canvas size, when one is set
+pub fn check_canvas_size(field: &str, value: Option<(u32, u32)>) -> Option<InvalidField> {
+    value.filter(|&(width, height)| !(1..=MAX_CANVAS_SIDE).contains(&width) || !(1..=MAX_CANVAS_SIDE).contains(&height))
+        .map(|(width, height)| InvalidField::new(
+            field,
+            format!("{}x{}", width, height),
+            &format!("a size from 1x1 to {0}x{0}, or none", MAX_CANVAS_SIDE),
+        ))
+}
+
+/// Read a canvas size such as `1920x1080`; an empty one is no canvas
+pub fn parse_canvas_size(text: &str) -> Result<Option<(u32, u32)>, String> {
+    parse_pair(&text.to_ascii_lowercase(), 'x', "width x height, e.g. 1920x1080", "number of pixels")
+}
+
+/// Write a canvas size back as [`parse_canvas_size`] reads it
+pub fn format_canvas_size(size: Option<(u32, u32)>) -> String {
+    size.map(|(width, height)| format!("{}x{}", width, height)).unwrap_or_default()
+}
+
+/// Read a placement offset such as `40,60`; an empty one is the top left corner
+pub fn parse_canvas_offset(text: &str) -> Result<(u32, u32), String> {
+    parse_pair(text, ',', "x,y", "pixel coordinate").map(Option::unwrap_or_default)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProcessingSettings {
+    pub history_length: usize,
+    pub background_color: String,
+    pub current_color: String,
+    /// Whether the current frame keeps its intensity or is drawn flat
+    #[serde(default)]
+    pub current_style: CurrentStyle,
+    pub history_color: String,
+    /// Colors of the newest and the oldest history frame, such as
+    /// `#ff0000:#0000ff`, with the ages between mixed from them; in place of
+    /// `history_color` when set
+    #[serde(default)]
+    pub history_gradient: String,
+    /// Color space the history gradient is mixed in
+    #[serde(default)]
+    pub gradient_space: GradientSpace,
+    /// Color of each history frame, nearest first; the last colors any older
+    /// frames and any beyond the history length go unused. In place of
+    /// `history_color` and `history_gradient` when set
+    #[serde(default)]
+    pub history_colors: Vec<String>,
+    /// Whether the history is tinted or keeps the frames' own colors
+    #[serde(default)]
+    pub history_style: HistoryStyle,
+    /// Smear each history frame along its motion towards the next into streaks
+    #[serde(default)]
+    pub motion_blur: bool,
+    /// Draw only the history frames a multiple of this many frames back; 0 and
+    /// 1 draw them all
+    #[serde(default)]
+    pub strobe: u32,
+    /// Opacity, from 0 to 1, of the history frames the strobe leaves out; 0
+    /// skips them
+    #[serde(default)]
+    pub strobe_dim: f32,
+    /// Which pixels count as echo, as an [`EchoPredicate`] rule
+    #[serde(default = "default_echo_rule")]
+    pub echo_rule: String,
+    /// Frames mark empty areas white: key the white out and read darker pixels
+    /// as stronger echo
+    #[serde(default)]
+    pub light_background: bool,
+    /// Where each pixel's opacity comes from; with the luminance, dark pixels
+    /// are faint and black ones empty, for frames without an alpha channel
+    #[serde(default)]
+    pub alpha_source: AlphaSource,
+    /// Image the trails are drawn over, such as a map, with the background
+    /// color showing where it is transparent
+    #[serde(default)]
+    pub background_image: Option<PathBuf>,
+    /// Stretch the background image to the frames rather than fail a folder
+    /// whose frames are another size
+    #[serde(default)]
+    pub fit_background_image: bool,
+    /// Leave the background transparent, with the trails' own opacity in the
+    /// output, for compositing them over something else later
+    #[serde(default)]
+    pub transparent_background: bool,
+    /// Fraction of full resolution, from 0.25 to 1, the history is drawn at
+    /// before it is scaled up under the current frame
+    #[serde(default = "default_history_scale")]
+    pub history_scale: f32,
+    /// History drawn under the first frames from before the sequence starts
+    #[serde(default)]
+    pub preroll: Preroll,
+    /// Fade the history as earlier versions did, for byte-identical re-runs
+    #[serde(default)]
+    pub legacy_fade: bool,
+    /// Fraction of full history opacity for each history frame, nearest
+    /// first, in place of the fade. One for each of the history frames.
+    #[serde(default)]
+    pub history_weights: Vec<f32>,
+    /// How the fade is bent, as [`parse_fade_curve`] reads it; empty is linear.
+    /// History weights are used as given.
+    #[serde(default)]
+    pub fade_curve: String,
+    /// Opacity out of 255 of the nearest history frame, which the fade scales
+    /// down for the older ones
+    #[serde(default = "default_history_opacity")]
+    pub history_opacity: u8,
+    /// Whether each frame redraws its history or fades a buffer of it
+    #[serde(default)]
+    pub trail_mode: TrailMode,
+    /// Opacity the decay buffer keeps from one frame to the next, below 1; 0
+    /// derives it from the history length
+    #[serde(default)]
+    pub decay: f32,
+    /// Filter frames are resized with, such as the history below full scale
+    #[serde(default)]
+    pub resize_filter: ResizeFilter,
+    /// What is done with frames of another size than the first of their folder
+    #[serde(default)]
+    pub size_mismatch: SizeMismatch,
+    /// Brightening of echo far from the radar
+    #[serde(default)]
+    pub range_correction: RangeCorrection,
+    /// Finding legends and logos that are in every frame, to mask them out
+    #[serde(default)]
+    pub static_mask: StaticMaskSettings,
+    /// Larger canvas each frame is placed on
+    #[serde(default)]
+    pub canvas: CanvasSettings,
+    pub threads: usize,
+    /// Frames read or written at once; 0 uses one per core, up to [`MAX_AUTO_IO_THREADS`]
+    #[serde(default)]
+    pub io_threads: usize,
+    /// Folders of the queue rendered at once, sharing the threads
+    #[serde(default = "default_folder_parallelism")]
+    pub folder_parallelism: usize,
+    /// Megabytes of decoded frames the workers keep to draw again as history;
+    /// 0 keeps as many as they need, whatever their size
+    #[serde(default)]
+    pub cache_mb: u32,
+    /// Composite frames in square tiles this many pixels wide, in parallel; 0
+    /// composites each frame whole
+    #[serde(default)]
+    pub tile_size: u32,
+    /// Frames of each folder skipped before the first one rendered
+    #[serde(default)]
+    pub offset: usize,
+    /// Frames of each folder rendered at most, counted from `offset` before the stride
+    pub limit: Option<usize>,
+    /// Render every this many of the frames `offset` and `limit` leave
+    #[serde(default = "default_stride")]
+    pub stride: usize,
+    /// Check every frame for corruption before rendering a folder
+    pub preflight: bool,
+    /// Look for frames repeating any earlier frame of their sequence
+    #[serde(default)]
+    pub repeated_frames: RepeatPolicy,
+    pub on_error: ErrorPolicy,
+    /// Use the sorted folder listing even when a frame manifest is present
+    pub ignore_manifests: bool,
+    /// Reprocess folders even when their output already matches these settings
+    #[serde(default)]
+    pub force: bool,
+    /// Render only the frames added since the run in the output directory,
+    /// with its last frames as their history
+    #[serde(default)]
+    pub append: bool,
+    /// Keep the trails an interrupted run left in the output directory that
+    /// are newer than every frame they are drawn from, rendering the rest
+    #[serde(default)]
+    pub resume: bool,
+    #[serde(default)]
+    pub on_collision: CollisionPolicy,
+    /// Directory every output is written under; `None` writes next to each folder
+    #[serde(default)]
+    pub output_root: Option<PathBuf>,
+    /// Output directory name, e.g. `{folder}_trail_{history}`
+    #[serde(default = "default_output_name_template")]
+    pub output_name_template: String,
+    /// History and current layers written beside or instead of each trail
+    #[serde(default)]
+    pub layers: LayerOutput,
+    /// Chart the echo coverage of every frame in the output folder
+    #[serde(default)]
+    pub coverage_plot: bool,
+    /// Write PNG trails with a palette worked out from the settings
+    #[serde(default)]
+    pub png_palette: bool,
+    /// Write each trail as `z/x/y` tiles of `tile_size` instead of one image
+    #[serde(default)]
+    pub tile_output: bool,
+    /// Copy of the newest trail, replaced as frames are written; relative to
+    /// the output directory unless absolute
+    #[serde(default)]
+    pub latest_image: Option<PathBuf>,
+    /// Describe the latest image in a JSON file beside it
+    #[serde(default)]
+    pub latest_json: bool,
+    /// Name each trail by its place in the rendered sequence, after this
+    /// prefix, instead of by its source; `None` keeps the source names
+    #[serde(default)]
+    pub renumber: Option<String>,
+    /// Digits the renumbered index is padded to; 0 fits the frame count
+    #[serde(default)]
+    pub renumber_width: u32,
+    /// Index of the frames each trail was drawn from, as JSON or JSON Lines;
+    /// relative to the output directory unless absolute
+    #[serde(default)]
+    pub emit_index: Option<PathBuf>,
+    /// Animated GIF of the trails, timed by `animation`; relative to the
+    /// output directory unless absolute
+    #[serde(default)]
+    pub animate_gif: Option<PathBuf>,
+    /// Colors in the animated GIF's one palette, shared by every frame
+    #[serde(default = "default_gif_colors")]
+    pub gif_colors: usize,
+    /// How the animated GIF makes up colors its palette lacks
+    #[serde(default)]
+    pub gif_dither: GifDither,
+    /// H.264 MP4 of the trails encoded by ffmpeg, timed by `animation`;
+    /// relative to the output directory unless absolute
+    #[serde(default)]
+    pub mp4: Option<PathBuf>,
+    /// H.264 quality of the MP4, from 0, lossless, to 51
+    #[serde(default = "default_mp4_crf")]
+    pub mp4_crf: u32,
+    /// Also save the trails as images when they are encoded into an MP4
+    #[serde(default)]
+    pub keep_frames: bool,
+    /// Product drawn from ODIM_H5 input files
+    #[serde(default)]
+    pub odim: OdimSelection,
+    /// Motion arrows over the trails
+    #[serde(default)]
+    pub motion: MotionSettings,
+    /// Centroid tracks over the trails
+    #[serde(default)]
+    pub tracking: TrackingSettings,
+    /// Forecast frames after the sequence
+    #[serde(default)]
+    pub nowcast: NowcastSettings,
+    /// Frames synthesised between scans
+    #[serde(default)]
+    pub interpolation: InterpolationSettings,
+    /// Regular time grid each folder is resampled onto
+    #[serde(default)]
+    pub resample: ResampleSettings,
+    /// Frame rate and looping of animated outputs
+    #[serde(default)]
+    pub animation: AnimationTiming,
+    /// Second folder composited over the trails
+    #[serde(default)]
+    pub secondary: SecondarySettings,
+    /// Custom per-pixel shading in place of the color tint, set from code only
+    #[serde(skip)]
+    pub shader: Option<engine::Shader>,
+    /// Overrides of single frames by path, from their sidecar files; set per folder
+    #[serde(skip)]
+    pub frame_overrides: Arc<HashMap<PathBuf, FrameOverrides>>,
+    /// Pixels cleared from every frame, as `static_mask` found them; set per folder
+    #[serde(skip)]
+    pub auto_mask: Option<Arc<StaticMask>>,
+    /// The background image, decoded; set per folder from `background_image`
+    #[serde(skip)]
+    pub background: Option<Arc<RgbaImage>>,
+    /// Size of the first frame, as decoded, that frames of other sizes are
+    /// matched to as `size_mismatch` says; set per folder
+    #[serde(skip)]
+    pub frame_size: Option<(u32, u32)>,
+}
+
+impl Default for ProcessingSettings {
+    fn default() -> Self {
+        ProcessingSettings {
+            history_length: 5,
+            background_color: "#000000".to_string(),
+            current_color: "#00ff00".to_string(),
+            current_style: CurrentStyle::default(),
+            history_color: "#ff7f00".to_string(),
+            history_gradient: String::new(),
+            history_colors: Vec::new(),
+            history_style: HistoryStyle::default(),
+            gradient_space: GradientSpace::default(),
+            motion_blur: false,
+            strobe: 0,
+            strobe_dim: 0.0,
+            echo_rule: default_echo_rule(),
+            light_background: false,
+            alpha_source: AlphaSource::default(),
+            background_image: None,
+            fit_background_image: false,
+            transparent_background: false,
+            preroll: Preroll::default(),
+            legacy_fade: false,
+            history_weights: Vec::new(),
+            fade_curve: String::new(),
+            history_opacity: default_history_opacity(),
+            trail_mode: TrailMode::default(),
+            decay: 0.0,
+            resize_filter: ResizeFilter::default(),
+            size_mismatch: SizeMismatch::default(),
+            range_correction: RangeCorrection::default(),
+            static_mask: StaticMaskSettings::default(),
+            canvas: CanvasSettings::default(),
+            history_scale: default_history_scale(),
+            threads: 0,
+            io_threads: 0,
+            folder_parallelism: default_folder_parallelism(),
+            cache_mb: 0,
+            tile_size: 0,
+            offset: 0,
+            limit: None,
+            stride: 1,
+            preflight: false,
+            repeated_frames: RepeatPolicy::default(),
+            on_error: ErrorPolicy::default(),
+            ignore_manifests: false,
+            force: false,
+            append: false,
+            resume: false,
+            on_collision: CollisionPolicy::default(),
+            output_root: None,
+            output_name_template: default_output_name_template(),
+            layers: LayerOutput::default(),
+            coverage_plot: false,
+            png_palette: false,
+            tile_output: false,
+            latest_image: None,
+            latest_json: false,
+            renumber: None,
+            renumber_width: 0,
+            emit_index: None,
+            animate_gif: None,
+            gif_colors: default_gif_colors(),
+            gif_dither: GifDither::default(),
+            mp4: None,
+            mp4_crf: default_mp4_crf(),
+            keep_frames: false,
+            odim: OdimSelection::default(),
+            motion: MotionSettings::default(),
+            tracking: TrackingSettings::default(),
+            nowcast: NowcastSettings::default(),
+            interpolation: InterpolationSettings::default(),
+            resample: ResampleSettings::default(),
+            animation: AnimationTiming::default(),
+            secondary: SecondarySettings::default(),
+            shader: None,
+            frame_overrides: Arc::default(),
+            auto_mask: None,
+            background: None,
+            frame_size: None,
+        }
+    }
+}
+
+impl ProcessingSettings {
+    /// What counts as echo under `echo_rule`; the default rule when it doesn't parse
+    pub fn echo_predicate(&self) -> EchoPredicate {
+        EchoPredicate::parse(&self.echo_rule).unwrap_or_default()
+    }
+
+    /// The frames of a sorted folder listing to render: `offset` frames
+    /// skipped, then at most `limit` of the rest, then every `stride`-th of
+    /// those, starting with the first. Trails are drawn over this selection
+    /// alone, so their history is the frames selected before each.
+    pub fn select_frames<T: Clone>(&self, files: &[T]) -> Vec<T> {
+        files.iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .step_by(self.stride.max(1))
+            .cloned()
+            .collect()
+    }
+
+    /// How many of `total` frames [`ProcessingSettings::select_frames`] selects
+    pub fn selected_count(&self, total: usize) -> usize {
+        total.saturating_sub(self.offset).min(self.limit.unwrap_or(usize::MAX)).div_ceil(self.stride.max(1))
+    }
+
+    /// Check every field, listing all problems rather than stopping at the first
+    pub fn validate(&self) -> Result<(), Vec<InvalidField>> {
+        let invalid: Vec<InvalidField> = [
+            check_history_length(self.history_length),
+            check_color("background_color", &self.background_color),
+            check_color("current_color", &self.current_color),
+            check_color("history_color", &self.history_color),
+            check_history_gradient("history_gradient", &self.history_gradient),
+            check_history_colors("history_colors", &self.history_colors),
+            check_opacity("strobe_dim", self.strobe_dim),
+            check_echo_rule("echo_rule", &self.echo_rule),
+            check_history_weights("history_weights", &self.history_weights, self.history_length),
+            check_fade_curve("fade_curve", &self.fade_curve),
+            check_history_opacity("history_opacity", self.history_opacity),
+            check_decay("decay", self.decay),
+            check_history_scale("history_scale", self.history_scale),
+            check_range_gain("range_correction.gain_per_100km", self.range_correction.gain_per_100km),
+            check_pixel_size("range_correction.pixel_size_km", Some(self.range_correction.pixel_size_km)),
+            check_max_range_gain("range_correction.max_gain", self.range_correction.max_gain),
+            check_static_mask_samples("static_mask.samples", self.static_mask.samples),
+            check_static_mask_dilate("static_mask.dilate", self.static_mask.dilate),
+            check_canvas_size("canvas.size", self.canvas.size),
+            check_tile_size("tile_size", self.tile_size),
+            check_stride("stride", self.stride),
+            check_folder_parallelism("folder_parallelism", self.folder_parallelism),
+            check_output_root("output_root", self.output_root.as_deref()),
+            check_latest_image("latest_image", self.latest_image.as_deref()),
+            check_output_name_template("output_name_template", &self.output_name_template),
+            check_renumber_prefix("renumber", self.renumber.as_deref()),
+            check_renumber_width("renumber_width", self.renumber_width),
+            check_emit_index("emit_index", self.emit_index.as_deref()),
+            check_animate_gif("animate_gif", self.animate_gif.as_deref()),
+            check_gif_colors("gif_colors", self.gif_colors),
+            check_mp4("mp4", self.mp4.as_deref()),
+            check_mp4_crf("mp4_crf", self.mp4_crf),
+            check_odim_dataset("odim.dataset", self.odim.dataset),
+            check_odim_quantity("odim.quantity", &self.odim.quantity),
+            check_grid_spacing("motion.grid_spacing", self.motion.grid_spacing),
+            check_vector_scale("motion.vector_scale", self.motion.vector_scale),
+            check_coherence("motion.min_coherence", self.motion.min_coherence),
+            check_color("motion.color", &self.motion.color),
+            check_pixel_size("motion.pixel_size_km", self.motion.pixel_size_km),
+            check_tracking_size("tracking.min_area", self.tracking.min_area),
+            check_tracking_size("tracking.max_displacement", self.tracking.max_displacement),
+            check_color("tracking.color", &self.tracking.color),
+            check_nowcast_frames("nowcast.frames", self.nowcast.frames),
+            check_interpolated_frames("interpolation.frames", self.interpolation.frames),
+            check_resample_interval("resample.interval_minutes", self.resample.interval_minutes),
+            check_fps("animation.fps", self.animation.fps),
+            check_max_frame_ms("animation.max_frame_ms", self.animation.max_frame_ms),
+            check_color("secondary.color", &self.secondary.color),
+            check_color("secondary.history_color", &self.secondary.history_color),
+            check_opacity("secondary.opacity", self.secondary.opacity),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if invalid.is_empty() { Ok(()) } else { Err(invalid) }
+    }
+    
+    /// Whether the trails are saved as images: always, unless they are
+    /// encoded into an MP4 without `keep_frames`
+    pub fn writes_trails(&self) -> bool {
+        self.mp4.is_none() || self.keep_frames
+    }
+
+    /// Fingerprint of the settings that shape the rendered frames
+    pub fn output_hash(&self) -> String {
+        let mut key = format!(
+            "{}|{}|{}|{}|{:?}|{}",
+            self.history_length,
+            self.background_color.to_ascii_lowercase(),
+            self.current_color.to_ascii_lowercase(),
+            self.history_color.to_ascii_lowercase(),
+            self.limit,
+            self.ignore_manifests
+        );
+        if self.offset > 0 || self.stride > 1 {
+            key.push_str(&format!("|frames from {} every {}", self.offset, self.stride));
+        }
+        // Only a changed selection counts, so outputs made before it existed still match
+        if self.odim != OdimSelection::default() {
+            key.push_str(&format!("|{}", self.odim));
+        }
+        if self.motion.enabled || self.motion_blur {
+            key.push_str(&format!("|{:?}", self.motion));
+        }
+        if self.motion_blur {
+            key.push_str("|motion blur");
+        }
+        if self.strobe > 1 {
+            key.push_str(&format!("|strobe {} dim {}", self.strobe, self.strobe_dim));
+        }
+        if self.repeated_frames == RepeatPolicy::Drop {
+            key.push_str("|drop repeated frames");
+        }
+        if self.echo_predicate() != EchoPredicate::default() {
+            key.push_str(&format!("|echo {}", self.echo_predicate()));
+        }
+        if self.light_background {
+            key.push_str("|light background");
+        }
+        if self.alpha_source != AlphaSource::AlphaChannel {
+            key.push_str(&format!("|alpha from {}", self.alpha_source));
+        }
+        if self.transparent_background {
+            key.push_str("|transparent background");
+        }
+        if let Some(path) = &self.background_image {
+            key.push_str(&format!("|background {}", path.display()));
+            if self.fit_background_image {
+                key.push_str(" stretched");
+            }
+        }
+        if self.range_correction.is_active() {
+            key.push_str(&format!("|{:?}", self.range_correction));
+        }
+        if self.static_mask.samples > 0 {
+            key.push_str(&format!("|{:?}", self.static_mask));
+        }
+        if self.canvas.size.is_some() {
+            key.push_str(&format!("|{:?}", self.canvas));
+        }
+        if self.current_style != CurrentStyle::Scaled {
+            key.push_str(&format!("|current {}", self.current_style));
+        }
+        if let Ok(Some((newest, oldest))) = parse_history_gradient(&self.history_gradient) {
+            key.push_str(&format!("|gradient {:?} {:?}", newest, oldest));
+            if newest != oldest {
+                key.push_str(&format!(" {}", self.gradient_space));
+            }
+        }
+        if self.history_style != HistoryStyle::Tinted {
+            key.push_str(&format!("|history {}", self.history_style));
+        }
+        if !self.history_colors.is_empty() {
+            key.push_str(&format!("|history colors {}", format_history_colors(&self.history_colors).to_ascii_lowercase()));
+        }
+        if self.history_scale < 1.0 {
+            key.push_str(&format!("|history scale {}", self.history_scale));
+        }
+        if self.size_mismatch != SizeMismatch::Fail {
+            key.push_str(&format!("|other sizes {}", self.size_mismatch));
+        }
+        if self.resize_filter != ResizeFilter::Bilinear {
+            key.push_str(&format!("|resize {}", self.resize_filter));
+        }
+        if self.preroll != Preroll::None {
+            key.push_str(&format!("|preroll {}", self.preroll));
+        }
+        // Outputs made before the exact fade had the legacy one, so it is the
+        // exact fade that marks the key
+        if !self.legacy_fade {
+            key.push_str("|exact fade");
+        }
+        if !self.history_weights.is_empty() {
+            key.push_str(&format!("|weights {}", format_history_weights(&self.history_weights)));
+        }
+        let curve = parse_fade_curve(&self.fade_curve).unwrap_or_default();
+        if curve != FadeCurve::Linear {
+            key.push_str(&format!("|curve {}", curve));
+        }
+        if self.history_opacity != default_history_opacity() {
+            key.push_str(&format!("|history opacity {}", self.history_opacity));
+        }
+        if self.trail_mode == TrailMode::Decay {
+            key.push_str(&format!("|decay {}", self.decay));
+        }
+        if self.tracking.enabled {
+            key.push_str(&format!("|{:?}", self.tracking));
+        }
+        if self.nowcast.frames > 0 {
+            key.push_str(&format!("|nowcast {}", self.nowcast.frames));
+        }
+        if self.interpolation.frames > 0 {
+            key.push_str(&format!("|{:?}", self.interpolation));
+        }
+        if self.resample.interval_minutes > 0 {
+            key.push_str(&format!("|{:?}", self.resample));
+        }
+        if let Some(prefix) = &self.renumber {
+            key.push_str(&format!("|renumber {:?} {}", prefix, self.renumber_width));
+        }
+        if self.secondary.folder.is_some() {
+            key.push_str(&format!("|{:?}", self.secondary));
+        }
+        if self.layers != LayerOutput::Off {
+            key.push_str(&format!("|layers {:?}", self.layers));
+        }
+        if self.coverage_plot {
+            key.push_str("|coverage plot");
+        }
+        if self.png_palette {
+            key.push_str("|png palette");
+        }
+        // Tiling alone draws the same pixels, so only tiled output counts
+        if self.tile_output && self.tile_size > 0 {
+            key.push_str(&format!("|tiles {}", self.tile_size));
+        }
+        if !self.writes_trails() {
+            key.push_str("|no trails");
+        }
+        // FNV-1a, so the hash stays the same across builds unlike the std hasher
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in key.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        format!("{:016x}", hash)
+    }
+    
+    /// Names of all fields that differ from `earlier`
+    pub fn changed_fields(&self, earlier: &ProcessingSettings) -> Vec<String> {
+        let (Ok(serde_json::Value::Object(now)), Ok(serde_json::Value::Object(before))) =
+            (serde_json::to_value(self), serde_json::to_value(earlier))
+        else {
+            return Vec::new();
+        };
+        now.into_iter()
+            .filter(|(name, value)| before.get(name) != Some(value))
+            .map(|(name, _)| name)
+            .collect()
+    }
+    
+    /// Output-shaping settings that differ from `earlier`, described for the user
+    pub fn output_differences(&self, earlier: &ProcessingSettings) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.history_length != earlier.history_length {
+            differences.push(format!("history length {} → {}", earlier.history_length, self.history_length));
+        }
+        let colors = [
+            ("background", &earlier.background_color, &self.background_color),
+            ("current", &earlier.current_color, &self.current_color),
+            ("history", &earlier.history_color, &self.history_color),
+        ];
+        for (name, before, after) in colors {
+            if !before.eq_ignore_ascii_case(after) {
+                differences.push(format!("{} color {} → {}", name, before, after));
+            }
+        }
+        if self.limit != earlier.limit {
+            let describe = |limit: Option<usize>| limit.map_or("none".to_string(), |l| l.to_string());
+            differences.push(format!("frame limit {} → {}", describe(earlier.limit), describe(self.limit)));
+        }
+        if self.offset != earlier.offset {
+            differences.push(format!("frame offset {} → {}", earlier.offset, self.offset));
+        }
+        if self.stride != earlier.stride {
+            differences.push(format!("frame stride {} → {}", earlier.stride, self.stride));
+        }
+        if self.repeated_frames != earlier.repeated_frames {
+            let describe = |policy: RepeatPolicy| match policy {
+                RepeatPolicy::Off => "kept unchecked",
+                RepeatPolicy::Drop => "dropped",
+                RepeatPolicy::Flag => "flagged",
+            };
+            differences.push(format!(
+                "repeated frames {} → {}",
+                describe(earlier.repeated_frames),
+                describe(self.repeated_frames),
+            ));
+        }
+        if self.ignore_manifests != earlier.ignore_manifests {
+            differences.push(format!("ignore manifests {} → {}", earlier.ignore_manifests, self.ignore_manifests));
+        }
+        if self.odim != earlier.odim {
+            differences.push(format!("ODIM product {} → {}", earlier.odim, self.odim));
+        }
+        if self.motion != earlier.motion && (self.motion.enabled || earlier.motion.enabled) {
+            let describe = |motion: &MotionSettings| if motion.enabled {
+                format!("every {} px at scale {}", motion.grid_spacing, motion.vector_scale)
+            } else {
+                "off".to_string()
+            };
+            differences.push(format!("motion vectors {} → {}", describe(&earlier.motion), describe(&self.motion)));
+        }
+        if self.tracking != earlier.tracking && (self.tracking.enabled || earlier.tracking.enabled) {
+            let describe = |tracking: &TrackingSettings| if tracking.enabled {
+                let labels = if tracking.label_ids { ", with IDs" } else { "" };
+                format!("above {} within {} px{}", tracking.min_intensity, tracking.max_displacement, labels)
+            } else {
+                "off".to_string()
+            };
+            differences.push(format!("centroid tracks {} → {}", describe(&earlier.tracking), describe(&self.tracking)));
+        }
+        if self.motion_blur != earlier.motion_blur {
+            differences.push(format!("motion blur {} → {}", earlier.motion_blur, self.motion_blur));
+        }
+        let strobe = |settings: &ProcessingSettings| match settings.strobe {
+            0 | 1 => "off".to_string(),
+            every if settings.strobe_dim > 0.0 => format!("every {} frames, others at {}", every, settings.strobe_dim),
+            every => format!("every {} frames", every),
+        };
+        if strobe(self) != strobe(earlier) {
+            differences.push(format!("strobe {} → {}", strobe(earlier), strobe(self)));
+        }
+        if self.echo_predicate() != earlier.echo_predicate() {
+            differences.push(format!("echo rule {} → {}", earlier.echo_predicate(), self.echo_predicate()));
+        }
+        if self.light_background != earlier.light_background {
+            differences.push(format!("light background {} → {}", earlier.light_background, self.light_background));
+        }
+        let background_image = |settings: &ProcessingSettings| match &settings.background_image {
+            Some(path) if settings.fit_background_image => format!("{} stretched", path.display()),
+            Some(path) => path.display().to_string(),
+            None => "none".to_string(),
+        };
+        if self.transparent_background != earlier.transparent_background {
+            differences.push(format!("transparent background {} → {}", earlier.transparent_background, self.transparent_background));
+        }
+        if background_image(self) != background_image(earlier) {
+            differences.push(format!("background image {} → {}", background_image(earlier), background_image(self)));
+        }
+        if self.alpha_source != earlier.alpha_source {
+            differences.push(format!("alpha from {} → {}", earlier.alpha_source, self.alpha_source));
+        }
+        if self.history_scale != earlier.history_scale {
+            differences.push(format!("history scale {} → {}", earlier.history_scale, self.history_scale));
+        }
+        if self.current_style != earlier.current_style {
+            differences.push(format!("current frame {} → {}", earlier.current_style, self.current_style));
+        }
+        if self.history_style != earlier.history_style {
+            differences.push(format!("history {} → {}", earlier.history_style, self.history_style));
+        }
+        let gradient = |settings: &ProcessingSettings| match parse_history_gradient(&settings.history_gradient) {
+            Ok(Some((newest, oldest))) if newest != oldest => {
+                format!("{} in {}", settings.history_gradient.trim().to_ascii_lowercase(), settings.gradient_space)
+            }
+            Ok(Some(_)) => settings.history_gradient.trim().to_ascii_lowercase(),
+            _ => "off".to_string(),
+        };
+        if gradient(self) != gradient(earlier) {
+            differences.push(format!("history gradient {} → {}", gradient(earlier), gradient(self)));
+        }
+        let history_colors = |settings: &ProcessingSettings| match settings.history_colors.is_empty() {
+            true => "off".to_string(),
+            false => format_history_colors(&settings.history_colors).to_ascii_lowercase(),
+        };
+        if history_colors(self) != history_colors(earlier) {
+            differences.push(format!("history colors {} → {}", history_colors(earlier), history_colors(self)));
+        }
+        if self.size_mismatch != earlier.size_mismatch {
+            differences.push(format!("other frame sizes {} → {}", earlier.size_mismatch, self.size_mismatch));
+        }
+        if self.resize_filter != earlier.resize_filter {
+            differences.push(format!("resize filter {} → {}", earlier.resize_filter, self.resize_filter));
+        }
+        if self.preroll != earlier.preroll {
+            differences.push(format!("pre-roll {} → {}", earlier.preroll, self.preroll));
+        }
+        if self.legacy_fade != earlier.legacy_fade {
+            differences.push(format!("legacy fade {} → {}", earlier.legacy_fade, self.legacy_fade));
+        }
+        if self.history_weights != earlier.history_weights {
+            let weights = |weights: &[f32]| match weights.is_empty() {
+                true => "none".to_string(),
+                false => format_history_weights(weights),
+            };
+            differences.push(format!("history weights {} → {}", weights(&earlier.history_weights), weights(&self.history_weights)));
+        }
+        let curve = |settings: &ProcessingSettings| parse_fade_curve(&settings.fade_curve).unwrap_or_default();
+        if curve(self) != curve(earlier) {
+            differences.push(format!("fade curve {} → {}", curve(earlier), curve(self)));
+        }
+        if self.history_opacity != earlier.history_opacity {
+            differences.push(format!("history opacity {} → {}", earlier.history_opacity, self.history_opacity));
+        }
+        let mode = |settings: &ProcessingSettings| match settings.trail_mode {
+            TrailMode::Decay if settings.decay > 0.0 => format!("decay at {}", settings.decay),
+            TrailMode::Decay => "decay".to_string(),
+            TrailMode::Fade => "fade".to_string(),
+        };
+        if mode(self) != mode(earlier) {
+            differences.push(format!("trail mode {} → {}", mode(earlier), mode(self)));
+        }
+        if self.nowcast != earlier.nowcast {
+            differences.push(format!("forecast frames {} → {}", earlier.nowcast.frames, self.nowcast.frames));
+        }
+        if self.interpolation != earlier.interpolation && (self.interpolation.frames > 0 || earlier.interpolation.frames > 0) {
+            let describe = |interpolation: &InterpolationSettings| match interpolation.frames {
+                0 => "off".to_string(),
+                frames if interpolation.in_history => format!("{} per scan, in history", frames),
+                frames => format!("{} per scan", frames),
+            };
+            differences.push(format!(
+                "interpolated frames {} → {}",
+                describe(&earlier.interpolation),
+                describe(&self.interpolation),
+            ));
+        }
+        if self.resample != earlier.resample && (self.resample.interval_minutes > 0 || earlier.resample.interval_minutes > 0) {
+            let describe = |resample: &ResampleSettings| match resample.interval_minutes {
+                0 => "off".to_string(),
+                minutes if resample.naming == ResampleNaming::Source => format!("every {} min, source names", minutes),
+                minutes => format!("every {} min", minutes),
+            };
+            differences.push(format!("resampling {} → {}", describe(&earlier.resample), describe(&self.resample)));
+        }
+        if self.renumber.is_some() || earlier.renumber.is_some() {
+            let describe = |settings: &ProcessingSettings| match (&settings.renumber, settings.renumber_width) {
+                (None, _) => "off".to_string(),
+                (Some(prefix), 0) => format!("\"{}\"", prefix),
+                (Some(prefix), width) => format!("\"{}\" to {} digits", prefix, width),
+            };
+            let (before, after) = (describe(earlier), describe(self));
+            if before != after {
+                differences.push(format!("renumbering {} → {}", before, after));
+            }
+        }
+        if self.secondary != earlier.secondary && (self.secondary.folder.is_some() || earlier.secondary.folder.is_some()) {
+            let describe = |secondary: &SecondarySettings| match &secondary.folder {
+                Some(folder) => format!("{} at opacity {}", folder.display(), secondary.opacity),
+                None => "none".to_string(),
+            };
+            differences.push(format!("secondary folder {} → {}", describe(&earlier.secondary), describe(&self.secondary)));
+        }
+        if self.range_correction != earlier.range_correction
+            && (self.range_correction.is_active() || earlier.range_correction.is_active())
+        {
+            let describe = |range: &RangeCorrection| if range.is_active() {
+                let site = match range.center {
+                    Some((x, y)) => format!("{},{}", x, y),
+                    None => "the middle".to_string(),
+                };
+                format!("+{} per 100 km from {} up to {}x", range.gain_per_100km, site, range.max_gain)
+            } else {
+                "off".to_string()
+            };
+            differences.push(format!(
+                "range correction {} → {}",
+                describe(&earlier.range_correction),
+                describe(&self.range_correction),
+            ));
+        }
+        if self.static_mask != earlier.static_mask && (self.static_mask.samples > 0 || earlier.static_mask.samples > 0) {
+            let describe = |mask: &StaticMaskSettings| match mask.samples {
+                0 => "off".to_string(),
+                samples => format!("{} frames within {} grown {} px", samples, mask.tolerance, mask.dilate),
+            };
+            differences.push(format!("static mask {} → {}", describe(&earlier.static_mask), describe(&self.static_mask)));
+        }
+        if self.canvas != earlier.canvas && (self.canvas.size.is_some() || earlier.canvas.size.is_some()) {
+            let describe = |canvas: &CanvasSettings| match (canvas.size, canvas.placement) {
+                (None, _) => "off".to_string(),
+                (Some((width, height)), Placement::Center) => format!("{}x{} centered", width, height),
+                (Some((width, height)), Placement::TopLeft) => format!("{}x{} at the top left", width, height),
+                (Some((width, height)), Placement::Custom { x, y }) => format!("{}x{} at {},{}", width, height, x, y),
+            };
+            differences.push(format!("canvas {} → {}", describe(&earlier.canvas), describe(&self.canvas)));
+        }
+        if self.layers != earlier.layers {
+            let describe = |layers: LayerOutput| match layers {
+                LayerOutput::Off => "off",
+                LayerOutput::Alongside => "with the trails",
+                LayerOutput::Only => "instead of the trails",
+            };
+            differences.push(format!("layer outputs {} → {}", describe(earlier.layers), describe(self.layers)));
+        }
+        if self.coverage_plot != earlier.coverage_plot {
+            differences.push(format!("coverage plot {} → {}", earlier.coverage_plot, self.coverage_plot));
+        }
+        if self.png_palette != earlier.png_palette {
+            differences.push(format!("palette PNG {} → {}", earlier.png_palette, self.png_palette));
+        }
+        if self.writes_trails() != earlier.writes_trails() {
+            differences.push(format!("trail images saved {} → {}", earlier.writes_trails(), self.writes_trails()));
+        }
+        let tiles = |settings: &ProcessingSettings| match settings.tile_size {
+            size if settings.tile_output && size > 0 => format!("{}px tiles", size),
+            _ => "whole images".to_string(),
+        };
+        if tiles(self) != tiles(earlier) {
+            differences.push(format!("output {} → {}", tiles(earlier), tiles(self)));
+        }
+        differences
+    }
+}
+
+/// Parse a hex color string to RGB
+pub fn parse_hex_color(hex: &str) -> Result<(u8, u8, u8)> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(anyhow!("Invalid hex color: {}", hex));
+    }
+    
+    let r = u8::from_str_radix(&hex[0..2], 16)?;
+    let g = u8::from_str_radix(&hex[2..4], 16)?;
+    let b = u8::from_str_radix(&hex[4..6], 16)?;
+    
+    Ok((r, g, b))
+}
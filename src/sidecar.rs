@@ -0,0 +1,114 @@
+//! Per-frame overrides read from sidecar files
+//!
+//! A frame that needs special handling, such as a different mask while the
+//! radome was icing, can have a JSON file beside it named after it:
+//! `frame0123.png.json` for `frame0123.png`. The scan picks them up, and each
+//! holds an object of [`FrameOverrides`]:
+//!
+//! ```json
+//! { "skip": false, "exclude_from_history": true, "mask": "icing_mask.png", "intensity_offset": -20 }
+//! ```
+//!
+//! Every key may be left out. A skipped frame is left out of the sequence; one
+//! excluded from history is rendered but never drawn in another frame's trail.
+//! The mask and offset are applied as the frame is decoded, before anything
+//! else sees it: pixels where the mask is black or transparent are cleared,
+//! and the offset is added to every channel of the pixels with echo, as the
+//! settings' [`EchoPredicate`] has it.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::echo::EchoPredicate;
+use crate::motion;
+
+/// Extension added to a frame's file name to name its sidecar
+pub const SIDECAR_EXTENSION: &str = "json";
+
+/// How a sidecar changes the handling of its frame
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrameOverrides {
+    /// Leave the frame out of the sequence
+    pub skip: bool,
+    /// Render the frame, but leave it out of the trails of the frames after it
+    pub exclude_from_history: bool,
+    /// Image whose black or transparent pixels are cleared from the frame,
+    /// relative to the sidecar's folder
+    pub mask: Option<PathBuf>,
+    /// Added to every channel of the pixels with echo, clamped to 0–255
+    pub intensity_offset: i32,
+}
+
+const KNOWN_KEYS: &[&str] = &["skip", "exclude_from_history", "mask", "intensity_offset"];
+
+/// The overrides of one frame, as run metadata lists them
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedOverrides {
+    pub frame: PathBuf,
+    #[serde(flatten)]
+    pub overrides: FrameOverrides,
+}
+
+/// Sidecar of the frame at `frame`, such as `scan.png.json` for `scan.png`
+pub fn sidecar_path(frame: &Path) -> PathBuf {
+    let mut name = frame.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    frame.with_file_name(name)
+}
+
+/// The frame a sidecar at `path` belongs to, if it is named like one
+pub fn frame_path(path: &Path) -> Option<PathBuf> {
+    let is_sidecar = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(SIDECAR_EXTENSION));
+    let frame = path.with_extension("");
+    (is_sidecar && frame.extension().is_some()).then_some(frame)
+}
+
+/// Parse a sidecar's contents, returning the overrides and any keys not
+/// recognised, which are ignored
+pub fn parse(content: &str) -> Result<(FrameOverrides, Vec<String>)> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    let Some(object) = value.as_object() else { bail!("expected a JSON object") };
+    let unknown = object.keys().filter(|key| !KNOWN_KEYS.contains(&key.as_str())).cloned().collect();
+    let overrides = serde_json::from_value(value)?;
+    Ok((overrides, unknown))
+}
+
+/// Read and [`parse`] the sidecar at `path`
+pub fn read(path: &Path) -> Result<(FrameOverrides, Vec<String>)> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    parse(&content).with_context(|| format!("reading {}", path.display()))
+}
+
+/// Apply the mask and intensity offset of `overrides` to `frame`, decoded from
+/// `path`, offsetting the pixels `echo` counts; the mask must be the frame's size
+pub fn apply(frame: &mut RgbaImage, overrides: &FrameOverrides, path: &Path, echo: &EchoPredicate) -> Result<()> {
+    if let Some(mask) = &overrides.mask {
+        let mask_path = path.parent().unwrap_or(Path::new("")).join(mask);
+        let mask = image::open(&mask_path)
+            .with_context(|| format!("loading mask {}", mask_path.display()))?
+            .to_rgba8();
+        if mask.dimensions() != frame.dimensions() {
+            bail!(
+                "mask {} is {}x{}, the frame {}x{}",
+                mask_path.display(), mask.width(), mask.height(), frame.width(), frame.height()
+            );
+        }
+        for (pixel, keep) in frame.pixels_mut().zip(mask.pixels()) {
+            if motion::intensity(keep) < 1.0 {
+                *pixel = Rgba([0, 0, 0, 0]);
+            }
+        }
+    }
+    if overrides.intensity_offset != 0 {
+        let shift = |channel: u8| (channel as i32 + overrides.intensity_offset).clamp(0, 255) as u8;
+        for pixel in frame.pixels_mut().filter(|pixel| echo.is_echo(pixel)) {
+            *pixel = Rgba([shift(pixel[0]), shift(pixel[1]), shift(pixel[2]), pixel[3]]);
+        }
+    }
+    Ok(())
+}
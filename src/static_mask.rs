@@ -0,0 +1,172 @@
+//! Legends, logos and scale bars burned into every frame
+//!
+//! Archived products often carry a legend, a logo or a scale bar in the same
+//! place on every frame. Read as they are, they are echo that never moves,
+//! drawn at full strength in every trail. With static masking on, a few
+//! frames spread across a folder are compared before it is rendered: pixels
+//! with echo in every one of them, each within a tolerance of the same color,
+//! are taken for such an overlay. They are cleared from every frame as it is
+//! decoded, as a sidecar file's mask clears them.
+//!
+//! Weather can hold still over the samples too. To keep it, the overlay must
+//! also be in the emptiest of a wider spread of frames, when one has next to
+//! no echo anywhere else: weather that held still is gone from such a frame,
+//! while the overlay is still there.
+
+use image::{Rgba, RgbaImage};
+
+#[cfg(feature = "native")]
+use {
+    anyhow::{bail, Result},
+    rayon::prelude::*,
+    std::path::PathBuf,
+    crate::engine,
+    crate::settings::{CanvasSettings, ProcessingSettings},
+};
+
+/// File name of the mask written to the output folder
+pub const AUTO_MASK_NAME: &str = "auto_mask.png";
+
+/// Share of a frame's pixels with echo outside the overlay below which the
+/// frame counts as empty
+pub const EMPTY_FRACTION: f32 = 0.005;
+
+/// Frames looked through for an empty one, for each sample
+#[cfg(feature = "native")]
+const PROBES_PER_SAMPLE: usize = 4;
+
+/// Pixels of a frame cleared before it is drawn
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticMask {
+    width: u32,
+    height: u32,
+    /// Row by row, whether each pixel is cleared
+    masked: Vec<bool>,
+}
+
+impl StaticMask {
+    /// A mask of `width` by `height` pixels, row by row
+    pub fn new(width: u32, height: u32, masked: Vec<bool>) -> Self {
+        assert_eq!(masked.len(), width as usize * height as usize, "one entry per pixel");
+        StaticMask { width, height, masked }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Pixels cleared
+    pub fn masked_count(&self) -> usize {
+        self.masked.iter().filter(|&&masked| masked).count()
+    }
+
+    pub fn is_masked(&self, x: u32, y: u32) -> bool {
+        self.masked[(y * self.width + x) as usize]
+    }
+
+    /// Clear the masked pixels of `frame` to transparent. Frames of another
+    /// size are left as they are.
+    pub fn apply(&self, frame: &mut RgbaImage) {
+        if frame.dimensions() != self.dimensions() {
+            return;
+        }
+        for (pixel, &masked) in frame.pixels_mut().zip(&self.masked) {
+            if masked {
+                *pixel = Rgba([0, 0, 0, 0]);
+            }
+        }
+    }
+
+    /// The mask grown by `radius` pixels on every side
+    pub fn dilate(&self, radius: u32) -> StaticMask {
+        if radius == 0 {
+            return self.clone();
+        }
+        let (width, height) = (self.width as i64, self.height as i64);
+        let r = radius as i64;
+        let masked = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                (y - r..=y + r).filter(|ny| (0..height).contains(ny)).any(|ny| {
+                    (x - r..=x + r).filter(|nx| (0..width).contains(nx)).any(|nx| self.masked[(ny * width + nx) as usize])
+                })
+            })
+            .collect();
+        StaticMask { masked, ..*self }
+    }
+
+    /// The mask as a sidecar mask image reads: black where pixels are
+    /// cleared, white where they are kept
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            if self.is_masked(x, y) { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        })
+    }
+}
+
+/// Indices of `samples` of `count` frames spread evenly over them, the first
+/// and last included; all of them when there are no more than `samples`
+pub fn spread(count: usize, samples: usize) -> Vec<usize> {
+    match (count, samples) {
+        (0, _) | (_, 0) => Vec::new(),
+        _ if samples >= count => (0..count).collect(),
+        (_, 1) => vec![0],
+        _ => (0..samples).map(|i| i * (count - 1) / (samples - 1)).collect(),
+    }
+}
+
+/// A mask found by [`detect`]
+#[cfg(feature = "native")]
+#[derive(Clone, Debug)]
+pub struct Detection {
+    pub mask: StaticMask,
+    /// The empty frame the overlay was also found in; `None` when no frame
+    /// looked through was empty, so the overlay could not be told apart from
+    /// weather that held still
+    pub reference: Option<PathBuf>,
+}
+
+/// Find the overlay of the frames at `paths` under `settings.static_mask`, as
+/// the module describes. The frames are decoded as they are for drawing,
+/// without any mask found before and before they are placed on a canvas.
+#[cfg(feature = "native")]
+pub fn detect(paths: &[PathBuf], settings: &ProcessingSettings) -> Result<Detection> {
+    let config = &settings.static_mask;
+    let settings = ProcessingSettings { auto_mask: None, canvas: CanvasSettings::default(), ..settings.clone() };
+    let load = |index: &usize| engine::load_frame(&paths[*index], &settings);
+    let samples: Vec<RgbaImage> = spread(paths.len(), config.samples).par_iter().map(load).collect::<Result<_>>()?;
+    let Some(first) = samples.first() else { bail!("there are no frames to compare") };
+    let size = first.dimensions();
+    if samples.iter().any(|sample| sample.dimensions() != size) {
+        bail!("the frames compared are not all the same size");
+    }
+    let same = |pixel: &Rgba<u8>, other: &Rgba<u8>| {
+        pixel[3] > 0 && pixel.0.iter().zip(other.0).all(|(&a, b)| a.abs_diff(b) <= config.tolerance)
+    };
+    let mut masked: Vec<bool> = first.pixels().map(|pixel| pixel[3] > 0).collect();
+    for sample in &samples[1..] {
+        for ((masked, pixel), other) in masked.iter_mut().zip(sample.pixels()).zip(first.pixels()) {
+            *masked = *masked && same(pixel, other);
+        }
+    }
+
+    let emptiest = spread(paths.len(), config.samples * PROBES_PER_SAMPLE)
+        .par_iter()
+        .filter_map(|index| {
+            let frame = load(index).ok().filter(|frame| frame.dimensions() == size)?;
+            let outside = frame.pixels().zip(&masked).filter(|(pixel, masked)| !**masked && pixel[3] > 0).count();
+            Some((outside, *index, frame))
+        })
+        .min_by_key(|(outside, index, _)| (*outside, *index));
+    let pixels = size.0 as usize * size.1 as usize;
+    let reference = match emptiest {
+        Some((outside, index, frame)) if (outside as f32) < EMPTY_FRACTION * pixels as f32 => {
+            for ((masked, pixel), other) in masked.iter_mut().zip(frame.pixels()).zip(first.pixels()) {
+                *masked = *masked && same(pixel, other);
+            }
+            Some(paths[index].clone())
+        }
+        _ => None,
+    };
+    Ok(Detection { mask: StaticMask::new(size.0, size.1, masked).dilate(config.dilate), reference })
+}
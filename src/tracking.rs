@@ -0,0 +1,257 @@
+//! Tracks of isolated echo cells through the history window
+//!
+//! Each frame is divided into cells: connected regions of pixels at least as
+//! intense as the threshold, big enough to matter. A cell is matched to the
+//! nearest cell of the frame before within the maximum displacement, and the
+//! chain of matches back through the history window is its track, drawn as a
+//! line through the cells' intensity-weighted centroids that fades with age,
+//! ending in a dot at the current position.
+//!
+//! Matching is one to one, nearest pairs first. When a cell splits, the part
+//! nearest the old centroid keeps the track and the other starts a new one;
+//! when two cells merge, the merged cell continues the nearer track and the
+//! other ends.
+//!
+//! Over a whole sequence, a [`CellTracker`] gives each cell an ID that it keeps
+//! for as long as it can be followed, matching the same way. A cell missing
+//! from a few frames picks up its ID again if it reappears close enough.
+
+use image::{Rgba, RgbaImage};
+
+use crate::draw;
+use crate::motion::intensity;
+use crate::settings::{self, TrackingSettings};
+
+/// A connected region of echo in one frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    /// Intensity-weighted centre, in pixels
+    pub centroid: (f32, f32),
+    /// Number of pixels
+    pub area: u32,
+    /// Mean intensity of its pixels, out of 255
+    pub mean_intensity: f32,
+}
+
+/// A position on a track, with the age of its frame as in [`crate::engine::compose_trail`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrackPoint {
+    pub position: (f32, f32),
+    pub age: f32,
+}
+
+/// The positions of one cell through the history window, oldest first, ending
+/// at its position in the current frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track {
+    pub points: Vec<TrackPoint>,
+}
+
+/// Find the cells of `frame`: 8-connected regions at or above
+/// `settings.min_intensity` of at least `settings.min_area` pixels
+pub fn find_cells(frame: &RgbaImage, settings: &TrackingSettings) -> Vec<Cell> {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let levels: Vec<f32> = frame.pixels().map(intensity).collect();
+    let threshold = settings.min_intensity as f32;
+    let mut visited = vec![false; levels.len()];
+    let mut stack = Vec::new();
+    let mut cells = Vec::new();
+
+    for start in 0..levels.len() {
+        if visited[start] || levels[start] < threshold {
+            continue;
+        }
+        visited[start] = true;
+        stack.push(start);
+        let (mut area, mut mass, mut sum_x, mut sum_y) = (0u32, 0.0f64, 0.0f64, 0.0f64);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            let weight = levels[index] as f64;
+            area += 1;
+            mass += weight;
+            sum_x += weight * x as f64;
+            sum_y += weight * y as f64;
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let neighbour = ny * width + nx;
+                    if !visited[neighbour] && levels[neighbour] >= threshold {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        if area >= settings.min_area && mass > 0.0 {
+            cells.push(Cell {
+                centroid: ((sum_x / mass) as f32, (sum_y / mass) as f32),
+                area,
+                mean_intensity: (mass / area as f64) as f32,
+            });
+        }
+    }
+    cells
+}
+
+/// For each cell of `current`, the index of the cell of `previous` it continues,
+/// if any lies within `max_displacement` pixels. Each previous cell continues
+/// at most one current cell; the closest pairs are matched first.
+pub fn match_cells(previous: &[Cell], current: &[Cell], max_displacement: f32) -> Vec<Option<usize>> {
+    let distance = |a: &Cell, b: &Cell| (a.centroid.0 - b.centroid.0).hypot(a.centroid.1 - b.centroid.1);
+    let mut pairs: Vec<(f32, usize, usize)> = current.iter()
+        .enumerate()
+        .flat_map(|(c, now)| previous.iter().enumerate().map(move |(p, before)| (distance(before, now), p, c)))
+        .filter(|&(d, _, _)| d <= max_displacement)
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut matched = vec![None; current.len()];
+    let mut taken = vec![false; previous.len()];
+    for (_, p, c) in pairs {
+        if matched[c].is_none() && !taken[p] {
+            matched[c] = Some(p);
+            taken[p] = true;
+        }
+    }
+    matched
+}
+
+/// Track the cells of `current` back through `history`, given oldest first
+/// with the age of each frame, as for [`crate::engine::compose_trail`]. Every
+/// cell of the current frame has a track, a single point if it is new.
+pub fn track_cells(current: &RgbaImage, history: &[(&RgbaImage, f32)], settings: &TrackingSettings) -> Vec<Track> {
+    let frames: Vec<(Vec<Cell>, f32)> = history.iter()
+        .map(|&(frame, age)| (find_cells(frame, settings), age))
+        .chain(std::iter::once((find_cells(current, settings), 0.0)))
+        .collect();
+    // matches[i] links the cells of frame i + 1 to those of frame i
+    let matches: Vec<Vec<Option<usize>>> = frames.windows(2)
+        .map(|pair| match_cells(&pair[0].0, &pair[1].0, settings.max_displacement as f32))
+        .collect();
+
+    let (cells, _) = frames.last().expect("the current frame is always present");
+    (0..cells.len())
+        .map(|start| {
+            let mut points = Vec::new();
+            let mut cell = Some(start);
+            for frame in (0..frames.len()).rev() {
+                let Some(index) = cell else { break };
+                let (cells, age) = &frames[frame];
+                points.push(TrackPoint { position: cells[index].centroid, age: *age });
+                cell = if frame > 0 { matches[frame - 1][index] } else { None };
+            }
+            points.reverse();
+            Track { points }
+        })
+        .collect()
+}
+
+/// Draw each track as a line fading with the age of its older end, and a dot
+/// at its current position, in `settings.color`
+pub fn draw_tracks(canvas: &mut RgbaImage, tracks: &[Track], settings: &TrackingSettings) {
+    let (r, g, b) = settings::parse_hex_color(&settings.color).unwrap_or((255, 255, 255));
+    for track in tracks {
+        for segment in track.points.windows(2) {
+            let alpha = ((1.0 - segment[0].age.clamp(0.0, 1.0)) * 255.0) as u8;
+            draw::draw_line(canvas, segment[0].position, segment[1].position, Rgba([r, g, b, alpha]));
+        }
+        if let Some(last) = track.points.last() {
+            draw::draw_dot(canvas, last.position, 2.0, Rgba([r, g, b, 255]));
+        }
+    }
+}
+
+/// A cell with the ID it carries through the sequence
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LabelledCell {
+    pub id: u32,
+    pub cell: Cell,
+}
+
+/// A cell being followed: where it was last seen and for how many frames since
+#[derive(Clone, Copy, Debug)]
+struct Followed {
+    id: u32,
+    cell: Cell,
+    missed: u32,
+}
+
+/// Gives the cells of a sequence IDs that persist from frame to frame.
+///
+/// Frames must be given in order. Each cell continues the ID of the nearest
+/// followed cell within the maximum displacement, as [`match_cells`] pairs
+/// them, and unmatched cells get new IDs counting up from 1. A followed cell
+/// that goes unmatched for more than `max_missed` frames in a row is closed,
+/// and its ID is not used again. The IDs depend only on the frames and the
+/// settings, so re-runs of the same sequence label it the same way.
+#[derive(Clone, Debug)]
+pub struct CellTracker {
+    settings: TrackingSettings,
+    followed: Vec<Followed>,
+    next_id: u32,
+}
+
+impl CellTracker {
+    pub fn new(settings: TrackingSettings) -> Self {
+        CellTracker { settings, followed: Vec::new(), next_id: 1 }
+    }
+
+    /// Find the cells of the next frame and label them
+    pub fn push(&mut self, frame: &RgbaImage) -> Vec<LabelledCell> {
+        let cells = find_cells(frame, &self.settings);
+        self.label(&cells)
+    }
+
+    /// Label the cells of the next frame, found by [`find_cells`]
+    pub fn label(&mut self, cells: &[Cell]) -> Vec<LabelledCell> {
+        let known: Vec<Cell> = self.followed.iter().map(|followed| followed.cell).collect();
+        let matches = match_cells(&known, cells, self.settings.max_displacement as f32);
+
+        let mut seen = vec![false; self.followed.len()];
+        let mut labelled = Vec::with_capacity(cells.len());
+        let mut started = Vec::new();
+        for (&cell, matched) in cells.iter().zip(matches) {
+            let id = match matched {
+                Some(index) => {
+                    seen[index] = true;
+                    self.followed[index].cell = cell;
+                    self.followed[index].missed = 0;
+                    self.followed[index].id
+                }
+                None => {
+                    let id = self.next_id;
+                    self.next_id += 1;
+                    started.push(Followed { id, cell, missed: 0 });
+                    id
+                }
+            };
+            labelled.push(LabelledCell { id, cell });
+        }
+
+        let max_missed = self.settings.max_missed;
+        let mut seen = seen.into_iter();
+        self.followed.retain_mut(|followed| {
+            if !seen.next().unwrap_or(true) {
+                followed.missed += 1;
+            }
+            followed.missed <= max_missed
+        });
+        self.followed.extend(started);
+        labelled
+    }
+
+    /// Forget every followed cell and start the IDs from 1 again
+    pub fn reset(&mut self) {
+        self.followed.clear();
+        self.next_id = 1;
+    }
+}
+
+/// Draw each cell's ID beside its centroid, in `settings.color`
+pub fn draw_labels(canvas: &mut RgbaImage, cells: &[LabelledCell], settings: &TrackingSettings) {
+    let (r, g, b) = settings::parse_hex_color(&settings.color).unwrap_or((255, 255, 255));
+    for labelled in cells {
+        let (x, y) = labelled.cell.centroid;
+        let origin = (x.round() as i64 + 4, y.round() as i64 - 12);
+        draw::draw_text(canvas, origin, &labelled.id.to_string(), 2, Rgba([r, g, b, 255]));
+    }
+}
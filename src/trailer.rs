@@ -0,0 +1,137 @@
+//! One entry point for rendering trails from other programs
+//!
+//! A [`Trailer`] is made from [`ProcessingSettings`], checked once, and then
+//! renders frames already in memory with [`Trailer::process_frames`], or a
+//! folder of them from disk with [`Trailer::process_dir`]. Both go through
+//! the same compositing as the app: [`TrailCompositor`] for frames in
+//! memory, [`engine::render_sequence`] for folders. Failures at this boundary
+//! are a [`TrailerError`] rather than an `anyhow` error, so callers can match
+//! on them.
+//!
+//! ```
+//! use image::{Rgba, RgbaImage};
+//! use radar_echo_trails::{ProcessingSettings, Trailer};
+//!
+//! let trailer = Trailer::new(ProcessingSettings { history_length: 3, ..ProcessingSettings::default() })?;
+//! let frames: Vec<RgbaImage> = (0..4)
+//!     .map(|i| RgbaImage::from_fn(4, 1, |x, _| if x == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) }))
+//!     .collect();
+//! let trails = trailer.process_frames(&frames);
+//! assert_eq!(trails.len(), 4);
+//! assert_eq!(trails[3].get_pixel(3, 0), &Rgba([0, 255, 0, 255]));
+//! # Ok::<(), radar_echo_trails::TrailerError>(())
+//! ```
+//!
+//! The queue of folders the app runs, with its output naming, manifests,
+//! resampling, renumbering, animations and appending, is
+//! `processing::process_folders`, which renders each folder with the same engine.
+
+#[cfg(feature = "native")]
+use std::ops::ControlFlow;
+#[cfg(feature = "native")]
+use std::path::{Path, PathBuf};
+
+use image::RgbaImage;
+
+use crate::engine::{TrailCompositor, TrailSettings};
+use crate::settings::{InvalidField, ProcessingSettings};
+#[cfg(feature = "native")]
+use {
+    crate::engine::{self, DirectorySink, RunSummary},
+    crate::queue::{self, ScanError, ScanOptions},
+    crate::settings::LayerOutput,
+};
+
+/// Why a [`Trailer`] could not be made or could not render
+#[derive(Debug)]
+pub enum TrailerError {
+    /// Settings that cannot be used, every one of them
+    InvalidSettings(Vec<InvalidField>),
+    /// The input folder could not be listed
+    #[cfg(feature = "native")]
+    Scan(ScanError),
+    /// The worker threads could not be started
+    #[cfg(feature = "native")]
+    ThreadPool(rayon::ThreadPoolBuildError),
+    /// The output folder could not be created
+    #[cfg(feature = "native")]
+    Output { path: PathBuf, source: std::io::Error },
+}
+
+impl std::fmt::Display for TrailerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrailerError::InvalidSettings(fields) => {
+                let fields: Vec<String> = fields.iter().map(ToString::to_string).collect();
+                write!(f, "invalid settings: {}", fields.join("; "))
+            }
+            #[cfg(feature = "native")]
+            TrailerError::Scan(e) => e.fmt(f),
+            #[cfg(feature = "native")]
+            TrailerError::ThreadPool(e) => write!(f, "cannot start the worker threads: {}", e),
+            #[cfg(feature = "native")]
+            TrailerError::Output { path, source } => write!(f, "cannot create {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for TrailerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TrailerError::InvalidSettings(_) => None,
+            #[cfg(feature = "native")]
+            TrailerError::Scan(e) => Some(e),
+            #[cfg(feature = "native")]
+            TrailerError::ThreadPool(e) => Some(e),
+            #[cfg(feature = "native")]
+            TrailerError::Output { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Renders trails with one set of settings
+#[derive(Clone, Debug)]
+pub struct Trailer {
+    settings: TrailSettings,
+}
+
+impl Trailer {
+    /// A trailer rendering with `settings`, once they pass
+    /// [`ProcessingSettings::validate`]
+    pub fn new(settings: ProcessingSettings) -> Result<Trailer, TrailerError> {
+        settings.validate().map_err(TrailerError::InvalidSettings)?;
+        Ok(Trailer { settings })
+    }
+
+    pub fn settings(&self) -> &TrailSettings {
+        &self.settings
+    }
+
+    /// The trail of each of `frames`, a sequence in order, each drawn over
+    /// the frames before it as [`TrailCompositor::push`] draws it
+    pub fn process_frames(&self, frames: &[RgbaImage]) -> Vec<RgbaImage> {
+        TrailCompositor::new(self.settings.clone()).composite(frames.iter().cloned()).collect()
+    }
+
+    /// Render the frames of the folder `input`, in the order a scan of it
+    /// gives, into `output`, which is created if need be. The trails keep the
+    /// names, formats and subfolders of their sources, and are rendered on
+    /// `settings.threads` worker threads. Frames that fail are
+    /// listed in the summary, as the settings' error policy leaves them.
+    #[cfg(feature = "native")]
+    pub fn process_dir(&self, input: &Path, output: &Path) -> Result<RunSummary, TrailerError> {
+        let settings = &self.settings;
+        let mut options = ScanOptions::default();
+        if settings.ignore_manifests {
+            options.manifest_name = None;
+        }
+        let scan = queue::scan_folder(input, &options).map_err(TrailerError::Scan)?;
+        std::fs::create_dir_all(output)
+            .map_err(|source| TrailerError::Output { path: output.to_path_buf(), source })?;
+        let sink = DirectorySink::new(output, input)
+            .with_interpolated(settings.interpolation.write_frames)
+            .with_layers(settings.layers != LayerOutput::Off);
+        let pool = crate::processing::thread_pool(settings.threads).map_err(TrailerError::ThreadPool)?;
+        Ok(pool.install(|| engine::render_sequence(&scan.files, settings, &sink, |_| ControlFlow::Continue(()))))
+    }
+}
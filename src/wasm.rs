@@ -0,0 +1,72 @@
+//! JavaScript bindings to the compositor, built with the `wasm` feature
+//!
+//! Build for the browser with wasm-bindgen; `examples/wasm` has a page that
+//! previews a trail from dropped frames as the settings change.
+
+use image::RgbaImage;
+use js_sys::Uint8Array;
+use wasm_bindgen::prelude::*;
+
+use crate::engine::{self, TrailSettings};
+
+/// Composite the last of `frames` with the frames before it as its history.
+///
+/// Frames are RGBA pixels, `width` by `height`, oldest first; only the last
+/// `history_length` frames before the current one are drawn. `settings_json`
+/// holds any of the settings fields, such as
+/// `{"history_length": 2, "current_color": "#33bbee"}`, with the rest at their
+/// defaults. Returns the trail as RGBA pixels of the same size.
+#[wasm_bindgen]
+pub fn compose(frames: Vec<Uint8Array>, width: u32, height: u32, settings_json: &str) -> Result<Uint8Array, JsError> {
+    let settings = parse_settings(settings_json)?;
+    let frame_len = width as usize * height as usize * 4;
+    let frames = frames.iter()
+        .enumerate()
+        .map(|(i, pixels)| {
+            RgbaImage::from_raw(width, height, pixels.to_vec())
+                .filter(|frame| frame.len() == frame_len)
+                .ok_or_else(|| JsError::new(&format!(
+                    "frame {} has {} bytes, expected {} for {}x{} RGBA",
+                    i, pixels.length(), frame_len, width, height
+                )))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some((current, earlier)) = frames.split_last() else {
+        return Err(JsError::new("no frames to composite"));
+    };
+
+    let history_count = settings.history_length.min(earlier.len());
+    let history: Vec<(&RgbaImage, f32)> = earlier[earlier.len() - history_count..].iter()
+        .enumerate()
+        .map(|(i, frame)| (frame, engine::history_age(history_count - i, history_count)))
+        .collect();
+    let trail = engine::compose_trail(current, &history, &settings);
+    Ok(Uint8Array::from(trail.as_raw().as_slice()))
+}
+
+/// The default settings as JSON, for filling in a settings form
+#[wasm_bindgen(js_name = defaultSettings)]
+pub fn default_settings() -> String {
+    serde_json::to_string(&TrailSettings::default()).unwrap_or_default()
+}
+
+/// Settings from a JSON object of any of the fields, over the defaults
+fn parse_settings(json: &str) -> Result<TrailSettings, JsError> {
+    let overrides: serde_json::Value = if json.trim().is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_str(json).map_err(|e| JsError::new(&format!("settings are not valid JSON: {}", e)))?
+    };
+    let mut merged = serde_json::to_value(TrailSettings::default())?;
+    match (overrides, &mut merged) {
+        (serde_json::Value::Null, _) => {}
+        (serde_json::Value::Object(fields), serde_json::Value::Object(defaults)) => defaults.extend(fields),
+        _ => return Err(JsError::new("settings must be a JSON object")),
+    }
+    let settings: TrailSettings = serde_json::from_value(merged)?;
+    settings.validate().map_err(|invalid| {
+        let messages: Vec<String> = invalid.iter().map(ToString::to_string).collect();
+        JsError::new(&messages.join("; "))
+    })?;
+    Ok(settings)
+}
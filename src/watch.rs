@@ -0,0 +1,149 @@
+//! Watching a parent directory for new frame folders, and the settings file for edits
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+/// A subfolder that has appeared but may still be receiving frames
+struct PendingFolder {
+    file_count: usize,
+    stable_since: Instant,
+}
+
+/// Reports subfolders of a parent directory once they stop growing
+pub struct FolderWatcher {
+    parent: PathBuf,
+    settle: Duration,
+    // Dropping the watcher stops the notifications
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    pending: HashMap<PathBuf, PendingFolder>,
+    /// Folders already reported (or ignored) by this watcher
+    known: HashSet<PathBuf>,
+}
+
+impl FolderWatcher {
+    /// Start watching `parent`. Subfolders present now are treated like new ones,
+    /// so the caller decides which of them still need processing.
+    pub fn start(parent: &Path, settle: Duration) -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        // New subfolders are enough; their growth is measured by polling
+        watcher.watch(parent, RecursiveMode::NonRecursive)?;
+
+        let mut folder_watcher = FolderWatcher {
+            parent: parent.to_path_buf(),
+            settle,
+            _watcher: watcher,
+            events,
+            pending: HashMap::new(),
+            known: HashSet::new(),
+        };
+        if let Ok(entries) = std::fs::read_dir(parent) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                    folder_watcher.track(entry.path());
+                }
+            }
+        }
+        Ok(folder_watcher)
+    }
+
+    pub fn parent(&self) -> &Path {
+        &self.parent
+    }
+
+    /// Change how long a folder must stay unchanged before it is reported
+    pub fn set_settle(&mut self, settle: Duration) {
+        self.settle = settle;
+    }
+
+    /// Drain filesystem events and return folders whose file count has been
+    /// stable for the settle period. Each folder is returned at most once.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else { continue };
+            for path in event.paths {
+                // Attribute the event to the direct child of the parent it concerns
+                let Ok(relative) = path.strip_prefix(&self.parent) else { continue };
+                let Some(child) = relative.components().next() else { continue };
+                let folder = self.parent.join(child);
+                if folder.is_dir() {
+                    self.track(folder);
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let mut settled = Vec::new();
+        for (folder, pending) in self.pending.iter_mut() {
+            let count = std::fs::read_dir(folder).map(|e| e.count()).unwrap_or(0);
+            if count != pending.file_count {
+                pending.file_count = count;
+                pending.stable_since = now;
+            } else if count > 0 && now.duration_since(pending.stable_since) >= self.settle {
+                settled.push(folder.clone());
+            }
+        }
+        for folder in &settled {
+            self.pending.remove(folder);
+            self.known.insert(folder.clone());
+        }
+        settled.sort();
+        settled
+    }
+
+    fn track(&mut self, folder: PathBuf) {
+        if self.known.contains(&folder) || self.pending.contains_key(&folder) {
+            return;
+        }
+        self.pending.insert(folder, PendingFolder {
+            file_count: usize::MAX,
+            stable_since: Instant::now(),
+        });
+    }
+}
+
+/// Reports when any of a set of files in one directory is written, created or replaced
+pub struct FileWatcher {
+    dir: PathBuf,
+    names: Vec<std::ffi::OsString>,
+    // Dropping the watcher stops the notifications
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl FileWatcher {
+    /// Watch the files of `dir` named `names`. The directory is watched rather
+    /// than the files, since editors often save by replacing the file.
+    pub fn start(dir: &Path, names: &[&str]) -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(FileWatcher {
+            dir: dir.to_path_buf(),
+            names: names.iter().map(|name| name.into()).collect(),
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Drain filesystem events, returning whether any concerned a watched file
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else { continue };
+            if event.kind.is_access() {
+                continue;
+            }
+            changed |= event.paths.iter().any(|path| {
+                path.parent() == Some(self.dir.as_path())
+                    && path.file_name().is_some_and(|name| self.names.iter().any(|n| n == name))
+            });
+        }
+        changed
+    }
+}
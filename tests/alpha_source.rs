@@ -0,0 +1,53 @@
+//! Taking each pixel's opacity from its brightness, for frames without alpha
+#![cfg(feature = "native")]
+
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use radar_echo_trails::echo::alpha_from_luma;
+use radar_echo_trails::engine::{load_frame, TrailSettings};
+use radar_echo_trails::settings::AlphaSource;
+
+#[test]
+fn brightness_becomes_opacity_and_black_is_empty() {
+    let mut frame = RgbaImage::from_fn(4, 1, |x, _| match x {
+        0 => Rgba([0, 0, 0, 255]),
+        1 => Rgba([64, 64, 64, 255]),
+        2 => Rgba([255, 160, 0, 255]),
+        _ => Rgba([255, 255, 255, 128]),
+    });
+    alpha_from_luma(&mut frame);
+    assert_eq!(frame.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+    assert_eq!(frame.get_pixel(1, 0), &Rgba([255, 255, 255, 64]));
+    // The color keeps its hue at full strength
+    assert_eq!(frame.get_pixel(2, 0), &Rgba([255, 160, 0, 170]));
+    // A pixel's own alpha still counts
+    assert_eq!(frame.get_pixel(3, 0), &Rgba([255, 255, 255, 128]));
+}
+
+#[test]
+fn frames_are_read_by_the_chosen_source() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_alpha_source_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // An opaque export with dark noise around an echo
+    let opaque = dir.join("opaque.png");
+    RgbImage::from_fn(3, 1, |x, _| match x {
+        0 => Rgb([0, 0, 0]),
+        1 => Rgb([20, 20, 20]),
+        _ => Rgb([255, 255, 255]),
+    }).save(&opaque).unwrap();
+    let luminance = TrailSettings { alpha_source: AlphaSource::Luminance, ..TrailSettings::default() };
+    let frame = load_frame(&opaque, &luminance).unwrap();
+    assert_eq!(frame.get_pixel(0, 0)[3], 0);
+    assert_eq!(frame.get_pixel(1, 0)[3], 20);
+    assert_eq!(frame.get_pixel(2, 0)[3], 255);
+    assert_eq!(load_frame(&opaque, &TrailSettings::default()).unwrap().get_pixel(1, 0)[3], 255);
+
+    // Real alpha is read as it is from the alpha channel
+    let translucent = dir.join("translucent.png");
+    let pixels = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([40, 40, 40, 200]) } else { Rgba([255, 0, 0, 90]) });
+    pixels.save(&translucent).unwrap();
+    assert_eq!(load_frame(&translucent, &TrailSettings::default()).unwrap(), pixels);
+
+    assert_ne!(luminance.output_hash(), TrailSettings::default().output_hash());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,44 @@
+//! Frame timing shared by the animated outputs
+
+use radar_echo_trails::animation::{frame_durations, interpolated_durations, MIN_FRAME_MS};
+use radar_echo_trails::settings::AnimationTiming;
+
+#[test]
+fn holds_every_frame_alike_at_a_fixed_rate() {
+    let timing = AnimationTiming { fps: 4.0, ..AnimationTiming::default() };
+    assert_eq!(frame_durations(&[Some(0), Some(60), Some(600)], &timing), vec![250, 250, 250]);
+}
+
+#[test]
+fn real_time_follows_the_gaps_between_scans() {
+    let timing = AnimationTiming { fps: 10.0, realtime: true, max_frame_ms: 500, ..AnimationTiming::default() };
+    // One minute scans with a three minute gap and a ten minute one, the latter held at the cap
+    let times = [0, 60, 120, 300, 360, 960].map(Some);
+    assert_eq!(frame_durations(&times, &timing), vec![100, 100, 300, 100, 500, 100]);
+}
+
+#[test]
+fn real_time_without_scan_times_plays_at_the_fixed_rate() {
+    let timing = AnimationTiming { fps: 20.0, realtime: true, ..AnimationTiming::default() };
+    assert_eq!(frame_durations(&[Some(0), None, Some(120)], &timing), vec![50, 50, 50]);
+    assert_eq!(frame_durations(&[Some(0)], &timing), vec![50]);
+}
+
+#[test]
+fn rejects_rates_and_holds_out_of_range() {
+    let valid = |timing: AnimationTiming| radar_echo_trails::settings::ProcessingSettings { animation: timing, ..Default::default() }
+        .validate()
+        .is_ok();
+    assert!(valid(AnimationTiming::default()));
+    assert!(!valid(AnimationTiming { fps: 0.0, ..AnimationTiming::default() }));
+    assert!(!valid(AnimationTiming { fps: 500.0, ..AnimationTiming::default() }));
+    assert!(!valid(AnimationTiming { max_frame_ms: 5, ..AnimationTiming::default() }));
+}
+
+#[test]
+fn interpolated_frames_share_the_time_of_the_scan_before_them() {
+    assert_eq!(interpolated_durations(&[300, 600, 90], 2), vec![100, 100, 100, 200, 200, 200, 90]);
+    assert_eq!(interpolated_durations(&[250, 250], 0), vec![250, 250]);
+    assert_eq!(interpolated_durations(&[15, 15], 3), [vec![MIN_FRAME_MS; 4], vec![15]].concat());
+    assert!(interpolated_durations(&[], 3).is_empty());
+}
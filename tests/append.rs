@@ -0,0 +1,64 @@
+//! Rendering only the frames added since the run already in the output
+#![cfg(feature = "native")]
+
+use std::path::{Path, PathBuf};
+
+use radar_echo_trails::engine;
+use radar_echo_trails::metadata;
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+
+mod common;
+use common::{echo, run};
+
+fn add_frames(source: &Path, frames: std::ops::Range<u32>) -> Vec<PathBuf> {
+    frames
+        .map(|x| {
+            let path = source.join(format!("frame_{}.png", x));
+            echo(x, 8).save(&path).unwrap();
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn appending_renders_only_the_new_frames_with_full_trails() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_append_{}", std::process::id()));
+    let source = dir.join("frames");
+    std::fs::create_dir_all(&source).unwrap();
+    let mut paths = add_frames(&source, 0..4);
+    let settings = ProcessingSettings { history_length: 2, append: true, ..ProcessingSettings::default() };
+    let output = processing::output_dir_for(&source, &settings);
+
+    // Without an earlier run there is nothing to append to, so every frame is rendered
+    assert!(matches!(run(&source, &settings), ProgressUpdate::FolderCompleted { .. }));
+    assert!(output.join("frame_0.png").is_file());
+    for path in &paths {
+        std::fs::remove_file(output.join(path.file_name().unwrap())).unwrap();
+    }
+
+    paths.extend(add_frames(&source, 4..7));
+    assert!(matches!(run(&source, &settings), ProgressUpdate::FolderCompleted { .. }));
+    let rendered: Vec<String> = std::fs::read_dir(&output).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".png"))
+        .collect();
+    assert_eq!(rendered.len(), 3, "{:?}", rendered);
+    for index in 4..7 {
+        let trail = image::open(output.join(format!("frame_{}.png", index))).unwrap().to_rgba8();
+        assert!(trail == engine::composite_frame(&paths, index, &settings).unwrap(), "frame {}", index);
+    }
+    let recorded = metadata::read_run_metadata(&output).unwrap();
+    assert_eq!(recorded.frames_total, 7);
+    assert_eq!(recorded.sources, (0..7).map(|x| PathBuf::from(format!("frame_{}.png", x))).collect::<Vec<_>>());
+
+    let ProgressUpdate::FolderSkipped { reason, .. } = run(&source, &settings) else { panic!("no new frames to render") };
+    assert!(reason.starts_with("no new frames since"), "{}", reason);
+
+    // An output made with other settings is left as it is
+    paths.extend(add_frames(&source, 7..8));
+    let changed = ProcessingSettings { history_color: "#00ff00".to_string(), ..settings.clone() };
+    assert!(matches!(run(&source, &changed), ProgressUpdate::FolderError { .. }));
+    assert!(!output.join("frame_7.png").exists());
+    assert_eq!(metadata::read_run_metadata(&output).unwrap().frames_total, 7);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
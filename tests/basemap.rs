@@ -0,0 +1,63 @@
+//! Drawing the trails over a background image
+#![cfg(feature = "native")]
+
+use std::sync::Arc;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{compose_trail, history_age, TrailSettings};
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+
+mod common;
+use common::{colored_echo, run, WHITE};
+
+const MAP: Rgba<u8> = Rgba([40, 80, 120, 255]);
+
+/// A `width` by 2 map, transparent in its last column
+fn map(width: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, 2, |x, _| if x == width - 1 { Rgba([0, 0, 0, 0]) } else { MAP })
+}
+
+#[test]
+fn trails_are_drawn_over_the_image_and_the_color_fills_the_rest() {
+    let settings = TrailSettings { background: Some(Arc::new(map(4))), ..TrailSettings::default() };
+    let past = colored_echo(0, (6, 2), WHITE);
+    let history = [(&past, history_age(1, 1))];
+    let trail = compose_trail(&colored_echo(5, (6, 2), WHITE), &history, &settings);
+    assert_eq!(trail.get_pixel(1, 0), &MAP);
+    // Transparent in the image, and past its edge
+    assert_eq!(trail.get_pixel(3, 0), &Rgba([0, 0, 0, 255]));
+    assert_eq!(trail.get_pixel(4, 1), &Rgba([0, 0, 0, 255]));
+    // The history is blended onto the map, and the current frame drawn on top
+    assert_ne!(trail.get_pixel(0, 0), &MAP);
+    assert_eq!(trail.get_pixel(5, 0), &Rgba([0, 255, 0, 255]));
+
+    let tiled = TrailSettings { tile_size: 2, ..settings.clone() };
+    assert!(compose_trail(&colored_echo(5, (6, 2), WHITE), &history, &tiled) == trail);
+}
+
+#[test]
+fn a_folder_fails_up_front_unless_the_image_fits() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_basemap_{}", std::process::id()));
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    for i in 0..3 {
+        colored_echo(i, (6, 2), WHITE).save(frames.join(format!("scan_{:03}.png", i))).unwrap();
+    }
+    let small = dir.join("small.png");
+    map(4).save(&small).unwrap();
+    let settings = ProcessingSettings { background_image: Some(small.clone()), ..ProcessingSettings::default() };
+    let output = processing::output_dir_for(&frames, &settings);
+
+    let ended = run(&frames, &settings);
+    assert!(matches!(&ended, ProgressUpdate::FolderError { error, .. } if error.contains("4x2") && error.contains("6x2")), "{:?}", ended);
+    assert!(std::fs::read_dir(&output).map_or(true, |mut entries| entries.next().is_none()));
+
+    let missing = ProcessingSettings { background_image: Some(dir.join("missing.png")), ..settings.clone() };
+    assert!(matches!(run(&frames, &missing), ProgressUpdate::FolderError { error, .. } if error.contains("missing.png")));
+
+    let stretched = ProcessingSettings { fit_background_image: true, ..settings.clone() };
+    assert!(matches!(run(&frames, &stretched), ProgressUpdate::FolderCompleted { frames_rendered: 3, .. }));
+    let trail = image::open(processing::output_dir_for(&frames, &stretched).join("scan_000.png")).unwrap().to_rgba8();
+    assert_eq!(trail.get_pixel(2, 0), &MAP);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
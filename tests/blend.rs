@@ -0,0 +1,81 @@
+//! Invariants of the blend core, checked on random pixels and frames
+
+use image::{Rgba, RgbaImage};
+use proptest::prelude::*;
+use radar_echo_trails::engine::{blend_pixel, overlay_tinted};
+
+fn pixel() -> impl Strategy<Value = Rgba<u8>> {
+    any::<[u8; 4]>().prop_map(Rgba)
+}
+
+fn opaque_pixel() -> impl Strategy<Value = Rgba<u8>> {
+    any::<[u8; 3]>().prop_map(|[r, g, b]| Rgba([r, g, b, 255]))
+}
+
+fn frame(alpha: impl Strategy<Value = u8>) -> impl Strategy<Value = RgbaImage> {
+    prop::collection::vec((any::<[u8; 3]>(), alpha), 16)
+        .prop_map(|pixels| {
+            let raw = pixels.into_iter().flat_map(|([r, g, b], a)| [r, g, b, a]).collect();
+            RgbaImage::from_raw(4, 4, raw).unwrap()
+        })
+}
+
+/// `top` over `bottom` with straight alpha, as one pixel
+fn over(top: Rgba<u8>, bottom: Rgba<u8>) -> Rgba<u8> {
+    let (ta, ba) = (top[3] as f32 / 255.0, bottom[3] as f32 / 255.0);
+    let alpha = ta + ba * (1.0 - ta);
+    if alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |i: usize| ((top[i] as f32 * ta + bottom[i] as f32 * ba * (1.0 - ta)) / alpha).round() as u8;
+    Rgba([channel(0), channel(1), channel(2), (alpha * 255.0).round() as u8])
+}
+
+proptest! {
+    #[test]
+    fn blended_channels_stay_between_source_and_destination(dst in pixel(), src in pixel(), alpha: u8) {
+        let blended = blend_pixel(dst, src, alpha);
+        for i in 0..3 {
+            // Float truncation can land one below equal channels, e.g. 115 for 116 over 116
+            prop_assert!(blended[i] >= src[i].min(dst[i]).saturating_sub(1) && blended[i] <= src[i].max(dst[i]));
+        }
+        prop_assert!(blended[3] == dst[3] || blended[3] == 255);
+    }
+
+    #[test]
+    fn blending_over_opaque_stays_opaque(dst in opaque_pixel(), src in pixel(), alpha: u8) {
+        prop_assert_eq!(blend_pixel(dst, src, alpha)[3], 255);
+    }
+
+    #[test]
+    fn transparent_source_is_identity(dst in pixel(), [r, g, b]: [u8; 3], alpha: u8) {
+        prop_assert_eq!(blend_pixel(dst, Rgba([r, g, b, 0]), alpha), dst);
+        prop_assert_eq!(blend_pixel(dst, Rgba([r, g, b, alpha]), 0), dst);
+    }
+
+    #[test]
+    fn blending_is_associative_within_rounding(dst in opaque_pixel(), bottom in pixel(), top in pixel()) {
+        let stepwise = blend_pixel(blend_pixel(dst, bottom, 255), top, 255);
+        let combined = blend_pixel(dst, over(top, bottom), 255);
+        for i in 0..4 {
+            prop_assert!(
+                stepwise[i].abs_diff(combined[i]) <= 3,
+                "{:?} then {:?} over {:?}: {:?} stepwise, {:?} combined", bottom, top, dst, stepwise, combined
+            );
+        }
+    }
+
+    #[test]
+    fn overlay_over_opaque_canvas_stays_opaque(background in opaque_pixel(), src in frame(any::<u8>()), tint: (u8, u8, u8), alpha: u8) {
+        let mut canvas = RgbaImage::from_pixel(4, 4, background);
+        overlay_tinted(&mut canvas, &src, tint, alpha);
+        prop_assert!(canvas.pixels().all(|p| p[3] == 255));
+    }
+
+    #[test]
+    fn overlaying_transparent_frame_is_identity(canvas in frame(any::<u8>()), src in frame(Just(0)), tint: (u8, u8, u8), alpha: u8) {
+        let mut overlaid = canvas.clone();
+        overlay_tinted(&mut overlaid, &src, tint, alpha);
+        prop_assert_eq!(overlaid, canvas);
+    }
+}
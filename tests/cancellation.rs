@@ -0,0 +1,145 @@
+//! Stopping a run part way: no frame is read or written once it is cancelled
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, TrailSettings};
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressFn, ProgressUpdate};
+use radar_echo_trails::queue::{JobQueue, ScanOptions};
+use radar_echo_trails::settings::TrailMode;
+
+const THREADS: usize = 4;
+
+/// `dir/name` holding `count` frames of an echo moving right one column a frame
+fn frames(dir: &Path, name: &str, count: u32) -> Vec<PathBuf> {
+    let source = dir.join(name);
+    std::fs::create_dir_all(&source).unwrap();
+    (0..count)
+        .map(|i| {
+            let path = source.join(format!("scan_{:03}.png", i));
+            RgbaImage::from_fn(count, 2, |x, _| if x == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn nothing_is_written_once_cancelled() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_cancellation_{}", std::process::id()));
+    let paths = frames(&dir, "frames", 120);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(THREADS).build().unwrap();
+    const TRIPPED_AFTER: usize = 5;
+
+    for trail_mode in [TrailMode::Fade, TrailMode::Decay] {
+        let settings = TrailSettings { history_length: 6, trail_mode, ..TrailSettings::default() };
+        let cancel = AtomicBool::new(false);
+        let written = AtomicUsize::new(0);
+        let sink = |_index: usize, _source: &Path, _frame: RgbaImage| {
+            if written.fetch_add(1, Ordering::Relaxed) + 1 == TRIPPED_AFTER {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            Ok(())
+        };
+        let summary = pool.install(|| {
+            engine::render_paired_sequence_from(&paths, 0, &[], &settings, &sink, || cancel.load(Ordering::Relaxed), |_| ControlFlow::Continue(()))
+        });
+        let written = written.into_inner();
+        assert!((TRIPPED_AFTER..TRIPPED_AFTER + THREADS).contains(&written), "{}: {} written", trail_mode, written);
+        assert!(summary.stopped, "{}", trail_mode);
+        assert_eq!(summary.frames_completed, written, "{}", trail_mode);
+        assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Run the queue of `folders`, calling `on_start` with the queue, the stop
+/// flag and the position of each folder as it starts
+fn run(folders: &[&Path], settings: &ProcessingSettings, on_start: impl Fn(&mut JobQueue, &AtomicBool, usize) + Sync) -> Vec<ProgressUpdate> {
+    let mut queue = JobQueue::default();
+    for folder in folders {
+        queue.add_folder(folder, ScanOptions::default(), false).unwrap();
+    }
+    let queue = Arc::new(Mutex::new(queue));
+    let stop = Arc::new(AtomicBool::new(false));
+    let updates = Mutex::new(Vec::new());
+    let sink = ProgressFn(|update: ProgressUpdate| {
+        if let ProgressUpdate::FolderStarted { folder_id, .. } = update {
+            let mut queue = queue.lock().unwrap();
+            let position = queue.position(folder_id).unwrap();
+            on_start(&mut queue, &stop, position);
+        }
+        updates.lock().unwrap().push(update);
+    });
+    let (_reload, reload) = mpsc::channel();
+    processing::process_folders(queue.clone(), settings.clone(), sink, stop.clone(), reload);
+    updates.into_inner().unwrap()
+}
+
+/// How each folder and the run ended
+fn endings(updates: &[ProgressUpdate]) -> Vec<&ProgressUpdate> {
+    updates.iter()
+        .filter(|update| matches!(
+            update,
+            ProgressUpdate::FolderCompleted { .. }
+                | ProgressUpdate::FolderCancelled { .. }
+                | ProgressUpdate::FolderStopped { .. }
+                | ProgressUpdate::AllComplete
+                | ProgressUpdate::Cancelled
+        ))
+        .collect()
+}
+
+fn trails_in(output: &Path) -> usize {
+    std::fs::read_dir(output).map_or(0, |entries| {
+        entries.filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|ext| ext == "png")).count()
+    })
+}
+
+#[test]
+fn stopped_and_cancelled_folders_say_so() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_cancellation_run_{}", std::process::id()));
+    let first = frames(&dir, "first", 60)[0].parent().unwrap().to_path_buf();
+    let second = frames(&dir, "second", 20)[0].parent().unwrap().to_path_buf();
+    let settings = ProcessingSettings { history_length: 4, threads: THREADS, ..ProcessingSettings::default() };
+    let outputs = [&first, &second].map(|folder| processing::output_dir_for(folder, &settings));
+
+    // Stopping the run as the first folder starts leaves it to run again,
+    // whether it is checking its frames or rendering them
+    for preflight in [true, false] {
+        let settings = ProcessingSettings { preflight, ..settings.clone() };
+        let updates = run(&[&first, &second], &settings, |_, stop, _| stop.store(true, Ordering::Relaxed));
+        let ended = endings(&updates);
+        assert!(matches!(ended[..], [ProgressUpdate::FolderStopped { frames_completed: 0, .. }, ProgressUpdate::Cancelled]), "{:?}", ended);
+        assert_eq!((trails_in(&outputs[0]), trails_in(&outputs[1])), (0, 0));
+    }
+    let recorded = radar_echo_trails::metadata::read_run_metadata(&outputs[0]).unwrap();
+    assert!(recorded.cancelled && recorded.frames_completed == 0);
+
+    // Cancelling one folder lets the rest of the queue carry on
+    let updates = run(&[&first, &second], &settings, |queue, _, position| {
+        if position == 0 {
+            queue.cancel(0);
+        }
+    });
+    let ended = endings(&updates);
+    assert!(
+        matches!(
+            ended[..],
+            [
+                ProgressUpdate::FolderCancelled { frames_completed: 0, .. },
+                ProgressUpdate::FolderCompleted { frames_rendered: 20, .. },
+                ProgressUpdate::AllComplete
+            ]
+        ),
+        "{:?}",
+        ended
+    );
+    assert_eq!((trails_in(&outputs[0]), trails_in(&outputs[1])), (0, 20));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
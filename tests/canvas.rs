@@ -0,0 +1,108 @@
+//! Placing frames on a larger canvas before they are drawn
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::canvas;
+use radar_echo_trails::engine::{self, DirectorySink, TrailCompositor, TrailSettings, CELLS_FILE_NAME};
+use radar_echo_trails::processing::{parse_canvas_offset, parse_canvas_size, CanvasSettings, Placement};
+use radar_echo_trails::settings::TrackingSettings;
+
+const ECHO: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const CURRENT: Rgba<u8> = Rgba([0, 255, 0, 255]);
+const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+fn canvas(width: u32, height: u32, placement: Placement) -> CanvasSettings {
+    CanvasSettings { size: Some((width, height)), placement }
+}
+
+/// A `width` by `height` frame with a 2 by 2 echo whose top-left pixel is at `x`, `y`
+fn frame(width: u32, height: u32, x: u32, y: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |px, py| {
+        if (x..x + 2).contains(&px) && (y..y + 2).contains(&py) { ECHO } else { Rgba([0, 0, 0, 0]) }
+    })
+}
+
+#[test]
+fn frames_are_placed_at_their_offset() {
+    assert_eq!(canvas::origin(&canvas(20, 10, Placement::Center), (5, 4)), (7, 3));
+    assert_eq!(canvas::origin(&canvas(20, 10, Placement::TopLeft), (5, 4)), (0, 0));
+    assert_eq!(canvas::origin(&canvas(20, 10, Placement::Custom { x: 12, y: 1 }), (5, 4)), (12, 1));
+    assert_eq!(canvas::origin(&CanvasSettings::default(), (5, 4)), (0, 0));
+
+    assert!(canvas::check_fits(&canvas(20, 10, Placement::Custom { x: 15, y: 6 }), (5, 4)).is_ok());
+    assert!(canvas::check_fits(&canvas(20, 10, Placement::Custom { x: 16, y: 6 }), (5, 4)).is_err());
+    assert!(canvas::check_fits(&canvas(20, 10, Placement::Center), (21, 4)).is_err());
+
+    let placed = canvas::place(frame(5, 4, 0, 0), &canvas(20, 10, Placement::Center));
+    assert_eq!(placed.dimensions(), (20, 10));
+    assert_eq!(placed.get_pixel(7, 3), &ECHO);
+    assert_eq!(placed.get_pixel(6, 3), &Rgba([0, 0, 0, 0]));
+    assert_eq!(canvas::to_frame((8.5, 4.0), (7, 3)), (1.5, 1.0));
+}
+
+#[test]
+fn the_background_fills_the_canvas() {
+    let settings = TrailSettings { history_length: 1, canvas: canvas(12, 8, Placement::Custom { x: 6, y: 2 }), ..TrailSettings::default() };
+    let mut compositor = TrailCompositor::new(settings);
+    compositor.push(frame(4, 4, 0, 0));
+    let trail = compositor.push(frame(4, 4, 2, 2));
+    assert_eq!(trail.dimensions(), (12, 8));
+    assert_eq!(trail.get_pixel(0, 0), &BACKGROUND);
+    assert_eq!(trail.get_pixel(8, 4), &CURRENT, "the current frame is at the offset");
+    assert!(![BACKGROUND, CURRENT].contains(trail.get_pixel(6, 2)), "so is its history");
+}
+
+#[test]
+fn frames_larger_than_the_canvas_fail_and_cells_stay_in_frame_pixels() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_canvas_{}", std::process::id()));
+    let (source, output) = (dir.join("frames"), dir.join("output"));
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..2)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            frame(10, 10, 4, 4).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let tracking = TrackingSettings { enabled: true, min_area: 1, save_csv: true, ..TrackingSettings::default() };
+    let settings = TrailSettings { canvas: canvas(30, 20, Placement::Center), tracking, ..TrailSettings::default() };
+    let sink = DirectorySink::new(&output, &source);
+    assert!(engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(())).failures.is_empty());
+
+    let trail = image::open(output.join("scan_1.png")).unwrap().to_rgba8();
+    let cells = std::fs::read_to_string(output.join(CELLS_FILE_NAME)).unwrap();
+    assert_eq!(trail.dimensions(), (30, 20));
+    assert_eq!(trail.get_pixel(0, 0), &BACKGROUND);
+    assert_ne!(trail.get_pixel(14, 9), &BACKGROUND, "the echo is in the middle of the canvas");
+    // The echo's centre is at 4.5 in the frame, 14.5 and 9.5 on the canvas
+    assert_eq!(cells.lines().nth(1), Some("0,1,4.50,4.50,4,255.00"));
+
+    let small = TrailSettings { canvas: canvas(8, 8, Placement::TopLeft), ..TrailSettings::default() };
+    let summary = engine::render_sequence(&paths, &small, &sink, |_| ControlFlow::Continue(()));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(summary.failures.len(), 2);
+    assert!(summary.failures[0].1.contains("does not fit the 8x8 canvas"), "{}", summary.failures[0].1);
+}
+
+#[test]
+fn reads_sizes_and_offsets_and_rejects_empty_canvases() {
+    assert_eq!(parse_canvas_size(" 1920 x 1080 "), Ok(Some((1920, 1080))));
+    assert_eq!(parse_canvas_size("1920X1080"), Ok(Some((1920, 1080))));
+    assert_eq!(parse_canvas_size(""), Ok(None));
+    assert!(parse_canvas_size("1920").is_err());
+    assert_eq!(parse_canvas_offset("40,60"), Ok((40, 60)));
+    assert_eq!(parse_canvas_offset(""), Ok((0, 0)));
+
+    for (size, valid) in [(None, true), (Some((1920, 1080)), true), (Some((0, 1080)), false), (Some((1920, 40000)), false)] {
+        let settings = TrailSettings { canvas: CanvasSettings { size, ..CanvasSettings::default() }, ..TrailSettings::default() };
+        assert_eq!(settings.validate().is_ok(), valid, "{:?}", size);
+    }
+    let unchanged = TrailSettings::default();
+    let placed = |placement| TrailSettings { canvas: canvas(64, 64, placement), ..unchanged.clone() };
+    assert_eq!(TrailSettings { canvas: CanvasSettings { size: None, placement: Placement::TopLeft }, ..unchanged.clone() }.output_hash(), unchanged.output_hash());
+    assert_ne!(placed(Placement::Center).output_hash(), unchanged.output_hash());
+    assert_ne!(placed(Placement::Center).output_hash(), placed(Placement::TopLeft).output_hash());
+}
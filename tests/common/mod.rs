@@ -0,0 +1,51 @@
+//! Fixtures shared by the integration tests
+// Each test file is its own crate and uses only some of these
+#![allow(dead_code)]
+
+use image::{Rgba, RgbaImage};
+
+#[cfg(feature = "native")]
+use std::path::Path;
+#[cfg(feature = "native")]
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "native")]
+use std::sync::{mpsc, Arc, Mutex};
+
+#[cfg(feature = "native")]
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressFn, ProgressUpdate};
+#[cfg(feature = "native")]
+use radar_echo_trails::queue::{JobQueue, ScanOptions};
+
+pub const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// A white echo in column `x` of a `width` by 1 frame, transparent elsewhere
+pub fn echo(x: u32, width: u32) -> RgbaImage {
+    colored_echo(x, (width, 1), WHITE)
+}
+
+/// An echo of `pixel` down column `x` of a `width` by `height` frame,
+/// transparent elsewhere
+pub fn colored_echo(x: u32, (width, height): (u32, u32), pixel: Rgba<u8>) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |at, _| if at == x { pixel } else { Rgba([0, 0, 0, 0]) })
+}
+
+/// Render the queue of `folder` once with `settings`, returning every update
+#[cfg(feature = "native")]
+pub fn process(folder: &Path, settings: &ProcessingSettings) -> Vec<ProgressUpdate> {
+    let mut queue = JobQueue::default();
+    queue.add_folder(folder, ScanOptions::default(), false).unwrap();
+    let updates = Mutex::new(Vec::new());
+    let sink = ProgressFn(|update: ProgressUpdate| updates.lock().unwrap().push(update));
+    let (_reload, reload) = mpsc::channel();
+    processing::process_folders(Arc::new(Mutex::new(queue)), settings.clone(), sink, Arc::new(AtomicBool::new(false)), reload);
+    updates.into_inner().unwrap()
+}
+
+/// Render `folder` once with `settings`, returning how it ended
+#[cfg(feature = "native")]
+pub fn run(folder: &Path, settings: &ProcessingSettings) -> ProgressUpdate {
+    process(folder, settings)
+        .into_iter()
+        .find(|update| matches!(update, ProgressUpdate::FolderCompleted { .. } | ProgressUpdate::FolderSkipped { .. } | ProgressUpdate::FolderError { .. }))
+        .unwrap()
+}
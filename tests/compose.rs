@@ -0,0 +1,87 @@
+//! Exact pixels of trails composed in memory
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{compose_trail, history_age, TrailSettings};
+use radar_echo_trails::settings::CurrentStyle;
+
+mod common;
+use common::{colored_echo, echo};
+
+const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+
+/// The trail of an echo moving one column a frame, with `settings.history_length`
+/// history frames
+fn moving(settings: &TrailSettings) -> RgbaImage {
+    let n = settings.history_length;
+    let width = n as u32 + 2;
+    let past: Vec<RgbaImage> = (0..n as u32).map(|x| echo(x, width)).collect();
+    let history: Vec<(&RgbaImage, f32)> = past.iter().enumerate().map(|(i, frame)| (frame, history_age(n - i, n))).collect();
+    compose_trail(&echo(n as u32, width), &history, settings)
+}
+
+fn row(image: &RgbaImage) -> Vec<Rgba<u8>> {
+    (0..image.width()).map(|x| *image.get_pixel(x, 0)).collect()
+}
+
+#[test]
+fn default_settings() {
+    // Orange at 43, 85 and 128 of 255 over black, oldest first, then the current frame in green
+    let trail = moving(&TrailSettings { history_length: 3, ..TrailSettings::default() });
+    assert_eq!(row(&trail), [Rgba([43, 21, 0, 255]), Rgba([85, 42, 0, 255]), Rgba([128, 63, 0, 255]), GREEN, BLACK]);
+
+    // One history frame is drawn at the full history opacity of 128
+    let trail = moving(&TrailSettings { history_length: 1, ..TrailSettings::default() });
+    assert_eq!(row(&trail), [Rgba([128, 63, 0, 255]), GREEN, BLACK]);
+}
+
+#[test]
+fn history_frames_blend_oldest_first() {
+    let settings = TrailSettings { history_length: 2, ..TrailSettings::default() };
+    let past = echo(0, 2);
+    // 64 of orange, then 128 of orange over that
+    let trail = compose_trail(&echo(1, 2), &[(&past, history_age(2, 2)), (&past, history_age(1, 2))], &settings);
+    assert_eq!(row(&trail), [Rgba([159, 79, 0, 255]), GREEN]);
+
+    // The fade follows the age given with the frame, among the frames given:
+    // two thirds of the way back of one frame is two thirds of 128
+    let trail = compose_trail(&echo(1, 2), &[(&past, history_age(2, 2))], &settings);
+    assert_eq!(row(&trail), [Rgba([85, 42, 0, 255]), GREEN]);
+}
+
+#[test]
+fn echo_strength_and_alpha_scale_the_history() {
+    let settings = TrailSettings { history_length: 1, ..TrailSettings::default() };
+    let empty = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+    let mut past = RgbaImage::from_pixel(2, 1, Rgba([255, 255, 255, 128]));
+    past.put_pixel(1, 0, Rgba([128, 128, 128, 255]));
+    // Half the alpha, or half the grey level, is half the opacity of 128
+    let trail = compose_trail(&empty, &[(&past, history_age(1, 1))], &settings);
+    assert_eq!(row(&trail), [Rgba([64, 31, 0, 255]), Rgba([64, 31, 0, 255])]);
+}
+
+#[test]
+fn current_styles() {
+    let grey = colored_echo(0, (1, 1), Rgba([128, 128, 128, 255]));
+    for (style, expected) in [
+        (CurrentStyle::Scaled, Rgba([0, 128, 0, 255])),
+        (CurrentStyle::Solid, GREEN),
+        (CurrentStyle::Original, Rgba([128, 128, 128, 255])),
+    ] {
+        let trail = compose_trail(&grey, &[], &TrailSettings { current_style: style, ..TrailSettings::default() });
+        assert_eq!(trail.get_pixel(0, 0), &expected, "{}", style);
+    }
+}
+
+#[test]
+fn history_opacity_and_blending_over_a_transparent_background() {
+    let settings = TrailSettings { history_length: 3, history_opacity: 255, ..TrailSettings::default() };
+    assert_eq!(row(&moving(&settings)), [Rgba([85, 42, 0, 255]), Rgba([170, 84, 0, 255]), Rgba([255, 127, 0, 255]), GREEN, BLACK]);
+
+    // Over nothing, each step keeps the history color at its own opacity
+    let transparent = TrailSettings { transparent_background: true, ..settings.clone() };
+    assert_eq!(
+        row(&moving(&transparent)),
+        [Rgba([255, 127, 0, 85]), Rgba([255, 127, 0, 170]), Rgba([255, 127, 0, 255]), GREEN, Rgba([0, 0, 0, 0])]
+    );
+}
@@ -0,0 +1,59 @@
+//! What each history age adds to the trails of a run
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, compose_trail, compose_trail_counted, TrailSettings};
+
+/// A frame with echo in the columns from `from` on
+fn frame(from: u32) -> RgbaImage {
+    RgbaImage::from_fn(8, 2, |x, _| if x >= from { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+}
+
+#[test]
+fn counts_only_pixels_no_newer_frame_covers() {
+    let (oldest, older, current) = (frame(2), frame(4), frame(6));
+    let settings = TrailSettings::default();
+    let history = [(&oldest, 2.0 / 3.0), (&older, 1.0 / 3.0)];
+
+    let (trail, added) = compose_trail_counted(&current, &history, &settings);
+    assert_eq!(trail, compose_trail(&current, &history, &settings));
+    // Two columns each, over two rows
+    assert_eq!(added, vec![4, 4]);
+}
+
+#[test]
+fn frames_left_out_by_the_strobe_add_nothing() {
+    let (oldest, older, current) = (frame(2), frame(4), frame(6));
+    let settings = TrailSettings { strobe: 2, strobe_dim: 0.0, ..TrailSettings::default() };
+    let (_, added) = compose_trail_counted(&current, &[(&oldest, 2.0 / 3.0), (&older, 1.0 / 3.0)], &settings);
+    assert_eq!(added, vec![0, 8]);
+}
+
+#[test]
+fn run_summary_averages_each_age_over_the_scans_that_have_it() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_contributions_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<PathBuf> = [2, 4, 6].iter()
+        .enumerate()
+        .map(|(i, &from)| {
+            let path = dir.join(format!("scan_{}.png", i));
+            frame(from).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let sink = |_: usize, _: &Path, _: RgbaImage| Ok(());
+    let settings = TrailSettings { history_length: 2, ..TrailSettings::default() };
+    let summary = engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(()));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    // The echo shrinks by two columns a scan, so each history frame shows four
+    // pixels past the next; only the last scan has a frame two back
+    let contributions: Vec<(usize, f64, f64)> = summary.age_contributions.iter()
+        .map(|age| (age.frames_back, age.mean_pixels, age.share))
+        .collect();
+    assert_eq!(contributions, vec![(1, 4.0, 8.0 / 12.0), (2, 4.0, 4.0 / 12.0)]);
+}
@@ -0,0 +1,73 @@
+//! Echo coverage charted over a sequence
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::coverage::{chart, coverage, CoverageSample, CHART_SIZE, COVERAGE_CHART_NAME};
+use radar_echo_trails::echo::EchoPredicate;
+use radar_echo_trails::engine::{self, DirectorySink, TrailSettings};
+
+const LINE: Rgba<u8> = Rgba([0, 90, 200, 255]);
+
+#[test]
+fn counts_pixels_with_echo() {
+    let mut frame = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 0, 0]));
+    frame.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+    frame.put_pixel(1, 0, Rgba([200, 100, 0, 128]));
+    // Opaque black and transparent white are empty
+    frame.put_pixel(2, 0, Rgba([0, 0, 0, 255]));
+    frame.put_pixel(3, 0, Rgba([255, 255, 255, 0]));
+    assert_eq!(coverage(&frame, &EchoPredicate::default()), 0.25);
+}
+
+/// Columns of the chart with some of the line in them
+fn line_columns(image: &RgbaImage) -> Vec<u32> {
+    (0..image.width()).filter(|&x| (0..image.height()).any(|y| image.get_pixel(x, y) == &LINE)).collect()
+}
+
+#[test]
+fn breaks_the_line_at_missing_scans() {
+    // Five minute scans with the 12:15 and 12:20 scans missing
+    let samples: Vec<CoverageSample> = [0, 5, 10, 25, 30]
+        .into_iter()
+        .map(|minutes| CoverageSample { time: Some(1_717_243_200 + minutes * 60), coverage: Some(0.5) })
+        .collect();
+    let image = chart(&samples);
+    assert_eq!(image.dimensions(), CHART_SIZE);
+    let columns = line_columns(&image);
+    let gap = columns.windows(2).filter(|pair| pair[1] - pair[0] > 1).count();
+    assert_eq!(gap, 1);
+    // Half coverage is drawn halfway up the fixed 0 to 100% scale
+    let middle = columns[columns.len() / 4];
+    let rows: Vec<u32> = (0..image.height()).filter(|&y| image.get_pixel(middle, y) == &LINE).collect();
+    assert!(rows.iter().all(|&y| (193..=197).contains(&y)), "{:?}", rows);
+
+    // A frame that could not be rendered breaks the line too
+    let mut samples = samples;
+    samples[1].coverage = None;
+    let columns = line_columns(&chart(&samples));
+    assert_eq!(columns.windows(2).filter(|pair| pair[1] - pair[0] > 1).count(), 2);
+}
+
+#[test]
+fn writes_the_chart_beside_the_trails() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_coverage_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<PathBuf> = (0..3)
+        .map(|i| {
+            let path = dir.join(format!("scan_{}.png", i));
+            RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255])).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { coverage_plot: true, ..TrailSettings::default() };
+    let sink = DirectorySink::new(dir.join("output"), &dir);
+    let summary = engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(()));
+    let written = image::open(dir.join("output").join(COVERAGE_CHART_NAME)).map(|chart| chart.to_rgba8());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    assert_eq!(written.unwrap().dimensions(), CHART_SIZE);
+}
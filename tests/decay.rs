@@ -0,0 +1,118 @@
+//! The decay trail mode, fading one buffer of the history each frame
+#![cfg(feature = "native")]
+
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::decay::{decay_factor, decay_reach};
+use radar_echo_trails::engine::{self, TrailSettings, HISTORY_MAX_ALPHA};
+use radar_echo_trails::settings::{check_decay, TrailMode};
+
+/// A folder of `count` frames of an echo moving right one column a frame
+fn frames(name: &str, count: u32) -> (PathBuf, Vec<PathBuf>) {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_decay_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths = (0..count)
+        .map(|i| {
+            let path = dir.join(format!("scan_{:02}.png", i));
+            RgbaImage::from_fn(count, 2, |x, _| if x == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    (dir, paths)
+}
+
+/// Render `paths` from `first` on, returning the trails by index and the progress seen
+fn render(paths: &[PathBuf], first: usize, settings: &TrailSettings) -> (BTreeMap<usize, RgbaImage>, Vec<usize>) {
+    let trails = Mutex::new(BTreeMap::new());
+    let sink = |index: usize, _source: &Path, frame: RgbaImage| {
+        trails.lock().unwrap().insert(index, frame);
+        Ok(())
+    };
+    let done = Mutex::new(Vec::new());
+    let summary = engine::render_paired_sequence_from(paths, first, &[], settings, &sink, || false, |progress| {
+        done.lock().unwrap().push(progress.frames_done);
+        ControlFlow::Continue(())
+    });
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    assert_eq!(summary.frames_completed, paths.len() - first);
+    assert!(!summary.stopped);
+    (trails.into_inner().unwrap(), done.into_inner().unwrap())
+}
+
+fn decay(history_length: usize, decay: f32) -> TrailSettings {
+    TrailSettings { history_length, trail_mode: TrailMode::Decay, decay, ..TrailSettings::default() }
+}
+
+#[test]
+fn the_factor_comes_from_the_history_length() {
+    for length in [2, 5, 50, 200] {
+        let factor = decay_factor(&decay(length, 0.0));
+        // The frame a history length back is as faint as the oldest of the fade
+        assert!((factor.powi(length as i32 - 1) - 1.0 / length as f32).abs() < 1e-4, "{}", length);
+    }
+    assert_eq!(decay_factor(&decay(1, 0.0)), 0.0);
+    assert_eq!(decay_factor(&decay(50, 0.85)), 0.85);
+
+    assert_eq!(decay_reach(0.0, 128, 10), 1);
+    assert_eq!(decay_reach(0.5, 128, 100), 8, "128 halved 7 times is the last level above 0");
+    assert_eq!(decay_reach(0.99, 128, 100), 100);
+    assert!(check_decay("decay", 0.85).is_none());
+    assert!(check_decay("decay", 0.0).is_none());
+    assert!(check_decay("decay", 1.0).is_some());
+    assert!(check_decay("decay", -0.1).is_some());
+}
+
+#[test]
+fn a_history_of_one_draws_as_the_fade_does() {
+    let (dir, paths) = frames("one", 6);
+    let (decayed, done) = render(&paths, 0, &decay(1, 0.0));
+    let (faded, _) = render(&paths, 0, &TrailSettings { history_length: 1, ..TrailSettings::default() });
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(done, [1, 2, 3, 4, 5, 6]);
+    assert_eq!(decayed.len(), faded.len());
+    for (index, trail) in &decayed {
+        assert!(trail == &faded[index], "frame {} differs from the fade", index);
+    }
+}
+
+#[test]
+fn history_fades_by_the_factor_each_frame() {
+    let (dir, paths) = frames("long", 12);
+    let settings = decay(60, 0.8);
+    let (trails, _) = render(&paths, 0, &settings);
+
+    // The last frame: current echo in green, the frame k back in orange at 128 * 0.8^(k - 1)
+    let last = &trails[&11];
+    assert_eq!(last.get_pixel(11, 0), &Rgba([0, 255, 0, 255]));
+    for back in 1..=11 {
+        let expected = 255.0 * HISTORY_MAX_ALPHA / 255.0 * 0.8f32.powi(back - 1);
+        let red = last.get_pixel(11 - back as u32, 1)[0] as f32;
+        assert!((red - expected).abs() <= 1.0, "{} back: {} for {}", back, red, expected);
+    }
+
+    // Appending reads back as far as the history shows, so the trails match
+    let (appended, done) = render(&paths, 7, &settings);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(appended.keys().copied().collect::<Vec<_>>(), [7, 8, 9, 10, 11]);
+    assert_eq!(done, [1, 2, 3, 4, 5]);
+    for (index, trail) in &appended {
+        assert!(trail == &trails[index], "appended frame {} differs", index);
+    }
+}
+
+#[test]
+fn the_mode_is_part_of_the_output_hash() {
+    let fade = TrailSettings::default();
+    let decayed = decay(5, 0.0);
+    assert_ne!(fade.output_hash(), decayed.output_hash());
+    assert_ne!(decayed.output_hash(), decay(5, 0.9).output_hash());
+    // The factor does nothing in the fade mode
+    assert_eq!(fade.output_hash(), TrailSettings { decay: 0.9, ..TrailSettings::default() }.output_hash());
+    assert!(decay(5, 0.9).output_differences(&fade).iter().any(|line| line == "trail mode fade → decay at 0.9"));
+}
@@ -0,0 +1,27 @@
+//! Frames republished under a new name
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::dedup::{frame_hash, repeats};
+
+fn frame(width: u32, level: u8) -> RgbaImage {
+    RgbaImage::from_fn(width, 4, |x, _| Rgba([level, x as u8, 0, 255]))
+}
+
+#[test]
+fn equal_pixels_hash_alike() {
+    assert_eq!(frame_hash(&frame(6, 10)), frame_hash(&frame(6, 10)));
+    assert_ne!(frame_hash(&frame(6, 10)), frame_hash(&frame(6, 11)));
+    // The same bytes in a different shape are a different frame
+    let wide = RgbaImage::from_pixel(8, 2, Rgba([1, 2, 3, 4]));
+    let tall = RgbaImage::from_pixel(2, 8, Rgba([1, 2, 3, 4]));
+    assert_ne!(frame_hash(&wide), frame_hash(&tall));
+}
+
+#[test]
+fn repeats_of_any_earlier_frame_are_found() {
+    let [a, b, c] = [frame(6, 1), frame(6, 2), frame(6, 3)].map(|frame| Some(frame_hash(&frame)));
+    // `a` republished two frames later, and `b` twice; unreadable frames repeat nothing
+    let hashes = [a, b, c, a, None, b, None, b];
+    assert_eq!(repeats(&hashes), [(3, 0), (5, 1), (7, 1)]);
+    assert!(repeats(&[a, b, c]).is_empty());
+}
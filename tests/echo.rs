@@ -0,0 +1,74 @@
+//! The echo rule deciding which pixels are drawn and measured
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::echo::{EchoPredicate, KeyColor, DEFAULT_ECHO_RULE};
+use radar_echo_trails::engine::{compose_trail, TrailSettings};
+
+#[test]
+fn a_compact_rule_parses_into_its_tests() {
+    let predicate = EchoPredicate::parse("alpha>10 & luma>30 & !color(#ffffff,12)").unwrap();
+    assert_eq!(predicate.alpha_above, 10);
+    assert_eq!(predicate.luma_above, Some(30));
+    assert_eq!(predicate.excluded, vec![KeyColor { color: (255, 255, 255), tolerance: 12 }]);
+
+    assert!(predicate.is_echo(&Rgba([0, 200, 0, 255])));
+    assert!(!predicate.is_echo(&Rgba([0, 200, 0, 10])), "alpha must be above 10");
+    assert!(!predicate.is_echo(&Rgba([20, 20, 20, 255])), "too dark");
+    assert!(!predicate.is_echo(&Rgba([250, 245, 255, 255])), "near white");
+}
+
+#[test]
+fn the_default_rule_is_the_default_predicate() {
+    let predicate = EchoPredicate::parse(DEFAULT_ECHO_RULE).unwrap();
+    assert_eq!(predicate, EchoPredicate::default());
+    assert_eq!(EchoPredicate::parse("  ").unwrap(), predicate);
+    assert_eq!(TrailSettings::default().echo_predicate(), predicate);
+    assert!(!predicate.is_echo(&Rgba([0, 0, 0, 255])));
+    assert!(!predicate.is_echo(&Rgba([255, 0, 0, 0])));
+    assert!(predicate.is_echo(&Rgba([1, 1, 1, 1])));
+}
+
+#[test]
+fn errors_point_at_the_offending_token() {
+    let error = EchoPredicate::parse("alpha>10 & lumen>3").unwrap_err();
+    assert_eq!(error.column, 12);
+    assert_eq!(error.token, "lumen");
+
+    let error = EchoPredicate::parse("alpha>300").unwrap_err();
+    assert_eq!(error.column, 7);
+    assert_eq!(error.token, "300");
+
+    let error = EchoPredicate::parse("luma>1 & !color(#fffff,4)").unwrap_err();
+    assert_eq!(error.column, 17);
+    assert_eq!(error.token, "#fffff");
+    assert!(error.to_string().starts_with("at column 17, \"#fffff\""));
+
+    assert!(EchoPredicate::parse("alpha>1 && luma>1").is_err());
+    assert!(TrailSettings { echo_rule: "luma<3".to_string(), ..TrailSettings::default() }.validate().is_err());
+}
+
+#[test]
+fn rules_are_described_and_written_back_in_evaluation_order() {
+    let predicate = EchoPredicate::parse("luma>30 & !color(#ffffff,12) & alpha>10").unwrap();
+    assert_eq!(predicate.describe(), "Echo: alpha above 10, then not within 12 of #ffffff, then luma above 30");
+    let rule = predicate.to_string();
+    assert_eq!(rule, "alpha>10 & !color(#ffffff,12) & luma>30");
+    assert_eq!(EchoPredicate::parse(&rule).unwrap(), predicate);
+}
+
+#[test]
+fn pixels_failing_the_rule_are_not_drawn() {
+    let frame = RgbaImage::from_fn(3, 1, |x, _| match x {
+        0 => Rgba([40, 40, 40, 255]),
+        1 => Rgba([200, 200, 200, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    });
+    let settings = TrailSettings {
+        current_color: "#ff0000".to_string(),
+        echo_rule: "luma>100".to_string(),
+        ..TrailSettings::default()
+    };
+    let trail = compose_trail(&frame, &[], &settings);
+    assert_eq!(trail.get_pixel(0, 0), trail.get_pixel(2, 0), "dim pixel is background");
+    assert_ne!(trail.get_pixel(1, 0), trail.get_pixel(2, 0));
+}
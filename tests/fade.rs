@@ -0,0 +1,148 @@
+//! How the history fades with age
+#![cfg(feature = "native")]
+
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::config::Settings;
+use radar_echo_trails::engine::{compose_trail, history_age, history_alpha, history_fade, TrailSettings, HISTORY_MAX_ALPHA};
+use radar_echo_trails::metadata::RunMetadata;
+use radar_echo_trails::processing::{format_history_weights, parse_fade_curve, parse_history_weights, FadeCurve};
+
+mod common;
+use common::echo;
+
+/// The opacity each of `count` history frames is drawn at, nearest first
+fn alphas(count: usize, settings: &TrailSettings) -> Vec<u8> {
+    (1..=count)
+        .map(|back| history_alpha(history_fade(history_age(back, count), count, settings), 1.0, settings))
+        .collect()
+}
+
+#[test]
+fn every_history_frame_is_a_step_of_its_own() {
+    for count in [1, 2, 3, 5, 8, 16, 50] {
+        let settings = TrailSettings { history_length: count, ..TrailSettings::default() };
+        let alphas = alphas(count, &settings);
+        assert_eq!(alphas[0], HISTORY_MAX_ALPHA as u8, "the nearest of {} is at full history opacity", count);
+        assert_eq!(alphas[count - 1], (HISTORY_MAX_ALPHA / count as f32).round() as u8, "the oldest of {}", count);
+        assert!(alphas.windows(2).all(|pair| pair[0] > pair[1]), "{} frames: {:?}", count, alphas);
+
+        // An echo moving one column a frame shows a trail step in every history frame
+        let past: Vec<RgbaImage> = (0..count as u32).map(|x| echo(x, count as u32 + 1)).collect();
+        let history: Vec<(&RgbaImage, f32)> = past.iter()
+            .enumerate()
+            .map(|(i, frame)| (frame, history_age(count - i, count)))
+            .collect();
+        let trail = compose_trail(&echo(count as u32, count as u32 + 1), &history, &settings);
+        let mut steps: Vec<&Rgba<u8>> = (0..count as u32).map(|x| trail.get_pixel(x, 0)).collect();
+        assert!(steps.iter().all(|pixel| **pixel != Rgba([0, 0, 0, 255])), "{} frames", count);
+        steps.dedup();
+        assert_eq!(steps.len(), count);
+    }
+}
+
+#[test]
+fn the_legacy_fade_stays_below_full_opacity() {
+    let settings = TrailSettings { history_length: 5, legacy_fade: true, ..TrailSettings::default() };
+    assert_eq!(alphas(5, &settings), [106, 85, 64, 42, 21]);
+    assert_eq!(alphas(5, &TrailSettings::default()), [128, 102, 77, 51, 26]);
+    assert_ne!(settings.output_hash(), TrailSettings::default().output_hash());
+}
+
+#[test]
+fn history_weights_are_used_as_given() {
+    let weights = vec![0.9, 0.7, 0.5, 0.3, 0.15];
+    let settings = TrailSettings { history_length: 5, history_weights: weights.clone(), ..TrailSettings::default() };
+    let fades: Vec<f32> = (1..=5).map(|back| history_fade(history_age(back, 5), 5, &settings)).collect();
+    assert_eq!(fades, weights);
+    assert_eq!(alphas(5, &settings), [115, 90, 64, 38, 19]);
+    // Halfway between the second and third frames back
+    assert!((history_fade(2.5 / 6.0, 5, &settings) - 0.6).abs() < 1e-5);
+    // Early in a sequence, fewer frames back than there are weights
+    assert_eq!(history_fade(history_age(2, 2), 2, &settings), 0.7);
+
+    let metadata = serde_json::to_value(RunMetadata::new(Path::new("frames"), &settings)).unwrap();
+    assert_eq!(metadata["settings"]["history_weights"].as_array().unwrap().len(), 5);
+    assert_ne!(settings.output_hash(), TrailSettings { history_length: 5, ..TrailSettings::default() }.output_hash());
+}
+
+#[test]
+fn history_weights_are_checked() {
+    assert_eq!(parse_history_weights(" 0.9, 0.5 ,0.1").unwrap(), [0.9, 0.5, 0.1]);
+    assert_eq!(parse_history_weights("").unwrap(), Vec::<f32>::new());
+    assert!(parse_history_weights("0.9,,0.1").is_err());
+    assert_eq!(format_history_weights(&[0.9, 0.5, 0.15]), "0.9,0.5,0.15");
+
+    for (history_length, weights, valid) in [
+        (3, vec![], true),
+        (3, vec![1.0, 0.5, 0.0], true),
+        (2, vec![1.0, 0.5, 0.0], false),
+        (3, vec![1.0, 1.5, 0.0], false),
+        (3, vec![1.0, -0.1, 0.0], false),
+    ] {
+        let settings = TrailSettings { history_length, history_weights: weights.clone(), ..TrailSettings::default() };
+        assert_eq!(settings.validate().is_ok(), valid, "{:?} for {}", weights, history_length);
+        let mut config = Settings::default();
+        config.rendering.history_length = history_length;
+        config.rendering.history_weights = weights;
+        assert_eq!(config.validate().is_ok(), valid);
+    }
+}
+
+#[test]
+fn fade_curves_bend_the_fade_between_its_ends() {
+    let curved = |fade_curve: &str, history_opacity: u8| TrailSettings {
+        history_length: 5,
+        fade_curve: fade_curve.to_string(),
+        history_opacity,
+        ..TrailSettings::default()
+    };
+    for (curve, opacity, expected) in [
+        ("", 128, [128, 102, 77, 51, 26]),
+        ("linear", 128, [128, 102, 77, 51, 26]),
+        ("ease-in", 128, [128, 123, 108, 82, 46]),
+        ("ease-out", 128, [128, 82, 46, 20, 5]),
+        ("2", 128, [128, 82, 46, 20, 5]),
+        ("0.5", 128, [128, 114, 99, 81, 57]),
+        // The nearest and oldest keep their opacities, a constant ratio apart in between
+        ("Exponential", 128, [128, 86, 57, 38, 26]),
+        ("linear", 255, [255, 204, 153, 102, 51]),
+        ("ease-in", 64, [64, 61, 54, 41, 23]),
+    ] {
+        let settings = curved(curve, opacity);
+        assert!(settings.validate().is_ok(), "{}", curve);
+        assert_eq!(alphas(5, &settings), expected, "{} at {}", curve, opacity);
+    }
+
+    // A single history frame is drawn at the history opacity whatever the curve
+    for curve in ["linear", "ease-in", "ease-out", "exponential", "3"] {
+        let settings = TrailSettings { history_length: 1, ..curved(curve, 200) };
+        assert_eq!(alphas(1, &settings), [200], "{}", curve);
+    }
+    // History weights are used as given
+    let weighted = TrailSettings { history_weights: vec![0.9, 0.7, 0.5, 0.3, 0.15], ..curved("ease-out", 128) };
+    assert_eq!(alphas(5, &weighted), [115, 90, 64, 38, 19]);
+
+    assert_ne!(curved("ease-in", 128).output_hash(), TrailSettings { history_length: 5, ..TrailSettings::default() }.output_hash());
+    assert_ne!(curved("", 200).output_hash(), curved("", 128).output_hash());
+    assert_eq!(curved("linear", 128).output_hash(), curved("", 128).output_hash());
+}
+
+#[test]
+fn fade_curves_are_checked() {
+    assert_eq!(parse_fade_curve(" EASE-OUT ").unwrap(), FadeCurve::EaseOut);
+    assert_eq!(parse_fade_curve("2.2").unwrap(), FadeCurve::Gamma(2.2));
+    assert_eq!(parse_fade_curve("").unwrap(), FadeCurve::Linear);
+    for invalid in ["0", "-1", "inf", "steep"] {
+        assert!(parse_fade_curve(invalid).is_err(), "{}", invalid);
+        let settings = TrailSettings { fade_curve: invalid.to_string(), ..TrailSettings::default() };
+        let fields = settings.validate().unwrap_err();
+        assert_eq!(fields[0].field, "fade_curve");
+        let mut config = Settings::default();
+        config.rendering.fade_curve = invalid.to_string();
+        assert_eq!(config.validate().unwrap_err()[0].field, "rendering.fade_curve");
+    }
+    let dark = TrailSettings { history_opacity: 0, ..TrailSettings::default() };
+    assert_eq!(dark.validate().unwrap_err()[0].field, "history_opacity");
+}
@@ -0,0 +1,28 @@
+//! Runs the C example in `examples/ffi`, which build.rs compiles and links
+//! into the test binaries
+#![cfg(feature = "ffi")]
+
+use std::ffi::c_int;
+
+// Link the library, which carries the functions the example calls
+extern crate radar_echo_trails;
+
+unsafe extern "C" {
+    fn ret_example_run() -> c_int;
+}
+
+#[test]
+fn c_example_composites_a_trail() {
+    // SAFETY: the example only calls the C interface with valid buffers
+    assert_eq!(unsafe { ret_example_run() }, 0);
+}
+
+#[test]
+fn checked_in_header_is_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/include/radar_echo_trails.h"));
+    let checked_in = include_str!("../include/radar_echo_trails.h");
+    assert!(
+        generated == checked_in,
+        "include/radar_echo_trails.h is out of date; regenerate it with `cbindgen --config cbindgen.toml --output include/radar_echo_trails.h`"
+    );
+}
@@ -0,0 +1,64 @@
+//! Every frame that fails is reported with its file and why
+#![cfg(feature = "native")]
+
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::processing::{self, ErrorPolicy, ProcessingSettings, ProgressUpdate};
+
+mod common;
+use common::process;
+
+#[test]
+fn a_truncated_frame_is_reported_and_the_rest_rendered() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_file_errors_{}", std::process::id()));
+    let source = dir.join("frames");
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..8)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            RgbaImage::from_fn(8, 8, |x, _| if x == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    let truncated = &paths[3];
+    let bytes = std::fs::read(truncated).unwrap();
+    std::fs::write(truncated, &bytes[..bytes.len() / 2]).unwrap();
+
+    for on_error in [ErrorPolicy::SkipFrame, ErrorPolicy::FailFolder] {
+        let settings = ProcessingSettings { history_length: 2, preflight: false, force: true, on_error, ..ProcessingSettings::default() };
+        let updates = process(&source, &settings);
+
+        let failed: Vec<(&PathBuf, &String)> = updates.iter()
+            .filter_map(|update| match update {
+                ProgressUpdate::FileError { file, error, .. } => Some((file, error)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(failed.len(), 1, "{:?}", failed);
+        let (file, error) = failed[0];
+        assert_eq!(file, truncated);
+        // The cause chain names the file and what the decoder made of it
+        assert!(error.starts_with("loading ") && error.contains("scan_3.png: "), "{}", error);
+        assert!(
+            updates.iter().any(|update| matches!(update, ProgressUpdate::FolderError { error, .. } if error == "1 files failed to process")),
+            "{:?}",
+            updates
+        );
+
+        let output = processing::output_dir_for(&source, &settings);
+        let recorded = radar_echo_trails::metadata::read_run_metadata(&output).unwrap();
+        assert_eq!(recorded.frames_failed, 1);
+        if on_error == ErrorPolicy::SkipFrame {
+            // A bad frame leaves the others to render, with it missing from their history
+            assert_eq!(recorded.frames_completed, 7);
+            for (index, path) in paths.iter().enumerate() {
+                assert_eq!(output.join(path.file_name().unwrap()).exists(), index != 3, "frame {}", index);
+            }
+        }
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
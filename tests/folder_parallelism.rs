@@ -0,0 +1,131 @@
+//! Rendering several folders of the queue at once on one pool
+#![cfg(feature = "native")]
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine;
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressFn, ProgressUpdate};
+use radar_echo_trails::queue::{JobQueue, ScanOptions};
+
+/// A folder `dir/parent/name` of `count` frames of an echo moving right
+fn folder(dir: &Path, parent: &str, name: &str, count: u32) -> (PathBuf, Vec<PathBuf>) {
+    let source = dir.join(parent).join(name);
+    std::fs::create_dir_all(&source).unwrap();
+    let paths = (0..count)
+        .map(|i| {
+            let path = source.join(format!("scan_{:02}.png", i));
+            RgbaImage::from_fn(count, 2, |x, _| if x == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    (source, paths)
+}
+
+fn queue_of(folders: &[&Path]) -> Arc<Mutex<JobQueue>> {
+    let mut queue = JobQueue::default();
+    for folder in folders {
+        queue.add_folder(folder, ScanOptions::default(), false).unwrap();
+    }
+    Arc::new(Mutex::new(queue))
+}
+
+#[test]
+fn folders_render_at_once_into_their_own_outputs() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_folder_parallelism_{}", std::process::id()));
+    let output_root = dir.join("out");
+    let (first, first_paths) = folder(&dir, "north", "case", 12);
+    let (second, second_paths) = folder(&dir, "south", "case", 10);
+    let settings = ProcessingSettings {
+        history_length: 3,
+        folder_parallelism: 2,
+        threads: 2,
+        output_root: Some(output_root.clone()),
+        ..ProcessingSettings::default()
+    };
+
+    // The first folder's frames wait until the second folder has started
+    let started = (Mutex::new(HashSet::new()), Condvar::new());
+    let overlapped = AtomicBool::new(false);
+    let updates = Mutex::new(Vec::new());
+    let sink = ProgressFn(|update: ProgressUpdate| {
+        let (ids, changed) = &started;
+        match &update {
+            ProgressUpdate::FolderStarted { folder_id, .. } => {
+                ids.lock().unwrap().insert(*folder_id);
+                changed.notify_all();
+            }
+            ProgressUpdate::FileProgress { .. } => {
+                let ids = changed.wait_timeout_while(ids.lock().unwrap(), Duration::from_secs(20), |ids| ids.len() < 2).unwrap().0;
+                if ids.len() == 2 {
+                    overlapped.store(true, Ordering::Relaxed);
+                }
+            }
+            _ => {}
+        }
+        updates.lock().unwrap().push(update);
+    });
+    let (_reload, reload) = mpsc::channel();
+    processing::process_folders(queue_of(&[&first, &second]), settings.clone(), sink, Arc::new(AtomicBool::new(false)), reload);
+    assert!(overlapped.into_inner(), "the second folder started while the first rendered");
+
+    let updates = updates.into_inner().unwrap();
+    assert!(matches!(updates.last(), Some(ProgressUpdate::AllComplete)));
+    let completed = updates.iter().filter(|update| matches!(update, ProgressUpdate::FolderCompleted { .. })).count();
+    assert_eq!(completed, 2, "{:?}", updates);
+
+    // Both would write to out/case_trail_3; the later one took case_trail_3_2
+    let mut outputs: Vec<PathBuf> = std::fs::read_dir(&output_root).unwrap().map(|entry| entry.unwrap().path()).collect();
+    outputs.sort();
+    assert_eq!(outputs, [output_root.join("case_trail_3"), output_root.join("case_trail_3_2")]);
+    for output in &outputs {
+        let recorded = radar_echo_trails::metadata::read_run_metadata(output).unwrap();
+        let paths = if recorded.source == first { &first_paths } else { &second_paths };
+        assert_eq!(recorded.frames_completed, paths.len());
+        for (index, path) in paths.iter().enumerate() {
+            let trail = image::open(output.join(path.file_name().unwrap())).unwrap().to_rgba8();
+            assert!(trail == engine::composite_frame(paths, index, &settings).unwrap(), "{} frame {}", output.display(), index);
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stopping_stops_every_folder_in_flight() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_folder_parallelism_stop_{}", std::process::id()));
+    let sources: Vec<PathBuf> = (0..4).map(|i| folder(&dir, "cases", &format!("case_{}", i), 30).0).collect();
+    let settings = ProcessingSettings { history_length: 3, folder_parallelism: 3, threads: 2, ..ProcessingSettings::default() };
+    let outputs: Vec<PathBuf> = sources.iter().map(|source| processing::output_dir_for(source, &settings)).collect();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let updates = Mutex::new(Vec::new());
+    let sink = ProgressFn(|update: ProgressUpdate| {
+        if matches!(update, ProgressUpdate::FolderStarted { .. }) {
+            stop.store(true, Ordering::Relaxed);
+        }
+        updates.lock().unwrap().push(update);
+    });
+    let (_reload, reload) = mpsc::channel();
+    let folders: Vec<&Path> = sources.iter().map(PathBuf::as_path).collect();
+    processing::process_folders(queue_of(&folders), settings, sink, stop.clone(), reload);
+
+    let updates = updates.into_inner().unwrap();
+    let recorded: Vec<usize> = outputs.iter()
+        .filter_map(|output| radar_echo_trails::metadata::read_run_metadata(output))
+        .map(|recorded| recorded.frames_completed)
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(updates.last(), Some(ProgressUpdate::Cancelled)), "{:?}", updates.last());
+    assert!(!updates.iter().any(|update| matches!(update, ProgressUpdate::AllComplete)));
+    // No folder starts once the run is stopped, and those started stop early
+    let started = updates.iter().filter(|update| matches!(update, ProgressUpdate::FolderStarted { .. })).count();
+    assert!((1..sources.len()).contains(&started), "{} started", started);
+    assert_eq!(recorded.len(), started);
+    assert!(recorded.iter().all(|&frames| frames < 30), "{:?}", recorded);
+}
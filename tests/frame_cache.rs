@@ -0,0 +1,95 @@
+//! Decoding each scan once for every trail it is part of
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, DirectorySink, RunSummary, TrailSettings};
+
+const FRAMES: usize = 12;
+
+/// Render `paths` into `output` on `threads` workers, returning the summary and the trails' bytes
+fn render(paths: &[PathBuf], source: &Path, output: &Path, settings: &TrailSettings, threads: usize) -> (RunSummary, Vec<Vec<u8>>) {
+    let sink = DirectorySink::new(output, source);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    let summary = pool.install(|| engine::render_sequence(paths, settings, &sink, |_| ControlFlow::Continue(())));
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    let trails = paths.iter().map(|path| std::fs::read(output.join(path.file_name().unwrap())).unwrap()).collect();
+    (summary, trails)
+}
+
+#[test]
+fn scans_are_decoded_once_and_the_trails_stay_the_same() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_frame_cache_{}", std::process::id()));
+    let source = dir.join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    // 1.1 MB each once decoded, so a 1 MB cache holds none of them
+    let paths: Vec<PathBuf> = (0..FRAMES)
+        .map(|i| {
+            let path = source.join(format!("scan_{:02}.png", i));
+            RgbaImage::from_fn(520, 520, |x, _| if x / 40 == i as u32 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { history_length: 4, ..TrailSettings::default() };
+
+    let (cached, trails) = render(&paths, &source, &dir.join("cached"), &settings, 1);
+    assert_eq!(cached.timing.frames_decoded, FRAMES);
+
+    // Every frame reads its history again when none of it can be kept
+    let uncached = TrailSettings { cache_mb: 1, ..settings.clone() };
+    let (reread, reread_trails) = render(&paths, &source, &dir.join("reread"), &uncached, 1);
+    assert_eq!(reread.timing.frames_decoded, FRAMES + (1..FRAMES).map(|i| i.min(4)).sum::<usize>());
+    assert!(reread_trails == trails, "the cache changed the trails");
+
+    let (parallel, parallel_trails) = render(&paths, &source, &dir.join("parallel"), &settings, 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(parallel.timing.frames_decoded <= reread.timing.frames_decoded);
+    assert!(parallel_trails == trails, "sharing the cache changed the trails");
+}
+
+#[test]
+fn long_sequences_are_rendered_in_order_within_a_window() {
+    const LONG: usize = 300;
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_frame_window_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<PathBuf> = (0..LONG)
+        .map(|i| {
+            let path = dir.join(format!("scan_{:03}.png", i));
+            RgbaImage::from_fn(32, 8, |x, y| if (x + y * 32) as usize == i % 256 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { history_length: 6, ..TrailSettings::default() };
+    let run = |threads: usize| {
+        let trails = Mutex::new(vec![None; LONG]);
+        // How far ahead of the frames written so far each frame was written
+        let ahead = Mutex::new(0);
+        let sink = |index: usize, _source: &Path, frame: RgbaImage| {
+            let mut trails = trails.lock().unwrap();
+            let written = trails.iter().filter(|trail| trail.is_some()).count();
+            let mut ahead = ahead.lock().unwrap();
+            *ahead = (*ahead).max(index.saturating_sub(written));
+            trails[index] = Some(frame);
+            Ok(())
+        };
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let summary = pool.install(|| engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(())));
+        assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+        (summary, trails.into_inner().unwrap(), ahead.into_inner().unwrap())
+    };
+
+    let (one, one_trails, _) = run(1);
+    let (four, four_trails, ahead) = run(4);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(one.timing.frames_decoded, LONG);
+    assert!(four.timing.frames_decoded < 2 * LONG, "{} decodes", four.timing.frames_decoded);
+    assert!(ahead < 64, "a frame was written {} frames ahead of the others", ahead);
+    assert!(four_trails == one_trails, "the threads changed the trails");
+}
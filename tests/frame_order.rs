@@ -0,0 +1,55 @@
+//! The order a scan puts a folder's frames in
+#![cfg(feature = "native")]
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use image::RgbaImage;
+use radar_echo_trails::queue::{self, natural_cmp, ScanOptions, SortOrder};
+
+fn names(folder: &Path, sort: SortOrder) -> Vec<String> {
+    let options = ScanOptions { sort, ..ScanOptions::default() };
+    queue::scan_folder(folder, &options).unwrap()
+        .files
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn numbers_in_names_compare_as_numbers() {
+    assert_eq!(natural_cmp("frame_2.png", "frame_10.png"), Ordering::Less);
+    assert_eq!(natural_cmp("img007", "img10"), Ordering::Less);
+    assert_eq!(natural_cmp("img10", "img007"), Ordering::Greater);
+    assert_eq!(natural_cmp("scan_2024_frame_3", "scan_2024_frame_12"), Ordering::Less);
+    assert_eq!(natural_cmp("scan_2023_frame_30", "scan_2024_frame_3"), Ordering::Less);
+    // Equal as numbers, so the padding decides, and only equal names compare equal
+    assert_eq!(natural_cmp("img007", "img7"), Ordering::Less);
+    assert_eq!(natural_cmp("img7", "img007"), Ordering::Greater);
+    assert_eq!(natural_cmp("img7", "img7"), Ordering::Equal);
+    assert_eq!(natural_cmp("frame", "frame_1"), Ordering::Less);
+    assert_eq!(natural_cmp("99999999999999999999999", "100000000000000000000000"), Ordering::Less);
+}
+
+#[test]
+fn folders_are_scanned_in_the_chosen_order() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_frame_order_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Written newest name first, so modification time runs against the names
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for (age, name) in ["frame_10.png", "frame_2.png", "frame_1.png", "frame_007.png"].into_iter().enumerate() {
+        let path = dir.join(name);
+        RgbaImage::new(2, 2).save(&path).unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(start + Duration::from_secs(age as u64)).unwrap();
+    }
+
+    let natural = names(&dir, SortOrder::default());
+    let lexical = names(&dir, SortOrder::Lexical);
+    let modified = names(&dir, SortOrder::Mtime);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(natural, ["frame_1.png", "frame_2.png", "frame_007.png", "frame_10.png"]);
+    assert_eq!(lexical, ["frame_007.png", "frame_1.png", "frame_10.png", "frame_2.png"]);
+    assert_eq!(modified, ["frame_10.png", "frame_2.png", "frame_1.png", "frame_007.png"]);
+}
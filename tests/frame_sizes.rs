@@ -0,0 +1,80 @@
+//! Matching frames of other sizes to the first frame of their folder
+#![cfg(feature = "native")]
+
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::canvas;
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+use radar_echo_trails::settings::SizeMismatch;
+
+mod common;
+use common::process;
+
+/// A `size` by `size` frame with an echo on the 16 by 16 pixels at the
+/// same eighth of the way across, whatever the size
+fn frame(size: u32) -> RgbaImage {
+    let at = size / 8;
+    let echo = size / 32;
+    RgbaImage::from_fn(size, size, |x, y| {
+        if (at..at + echo).contains(&x) && (at..at + echo).contains(&y) { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) }
+    })
+}
+
+#[test]
+fn a_sequence_changing_resolution_halfway() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_frame_sizes_{}", std::process::id()));
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    for i in 0..4 {
+        frame(if i < 2 { 512 } else { 1024 }).save(frames.join(format!("scan_{:03}.png", i))).unwrap();
+    }
+
+    // The larger frames fail, naming both sizes
+    let fail = ProcessingSettings::default();
+    let updates = process(&frames, &fail);
+    assert!(
+        updates.iter().any(|update| format!("{:?}", update).contains("1024x1024") && format!("{:?}", update).contains("512x512")),
+        "{:?}", updates
+    );
+    assert!(!processing::output_dir_for(&frames, &fail).join("scan_003.png").exists());
+    let _ = std::fs::remove_dir_all(processing::output_dir_for(&frames, &fail));
+
+    // Resized, the echo stays where it was in every frame
+    let resize = ProcessingSettings { size_mismatch: SizeMismatch::Resize, ..ProcessingSettings::default() };
+    let updates = process(&frames, &resize);
+    assert!(updates.iter().any(|update| matches!(update, ProgressUpdate::FolderCompleted { frames_rendered: 4, .. })), "{:?}", updates);
+    let trail = image::open(processing::output_dir_for(&frames, &resize).join("scan_003.png")).unwrap().to_rgba8();
+    assert_eq!(trail.dimensions(), (512, 512));
+    assert_eq!(trail.get_pixel(70, 70), &Rgba([0, 255, 0, 255]));
+    assert_eq!(trail.get_pixel(140, 140), &Rgba([0, 0, 0, 255]));
+    std::fs::remove_dir_all(processing::output_dir_for(&frames, &resize)).unwrap();
+
+    // Padded, the larger frames are cropped about their centers, which cuts
+    // their echo off and leaves only the history of the smaller ones there
+    let pad = ProcessingSettings { size_mismatch: SizeMismatch::Pad, ..ProcessingSettings::default() };
+    let updates = process(&frames, &pad);
+    assert!(updates.iter().any(|update| matches!(update, ProgressUpdate::FolderCompleted { frames_rendered: 4, .. })), "{:?}", updates);
+    let trail = image::open(processing::output_dir_for(&frames, &pad).join("scan_003.png")).unwrap().to_rgba8();
+    assert_eq!(trail.dimensions(), (512, 512));
+    assert_ne!(trail.get_pixel(70, 70), &Rgba([0, 255, 0, 255]));
+    assert_ne!(trail.get_pixel(70, 70), &Rgba([0, 0, 0, 255]));
+
+    assert_ne!(resize.output_hash(), fail.output_hash());
+    assert_ne!(resize.output_hash(), pad.output_hash());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn padding_centers_smaller_frames_and_crops_larger_ones() {
+    let dot = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+    let padded = canvas::center(&dot, (4, 4));
+    assert_eq!(padded.get_pixel(0, 0)[3], 0);
+    assert_eq!(padded.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+    assert_eq!(padded.get_pixel(2, 2), &Rgba([255, 0, 0, 255]));
+    assert_eq!(padded.get_pixel(3, 3)[3], 0);
+
+    let large = RgbaImage::from_fn(4, 4, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+    let cropped = canvas::center(&large, (2, 2));
+    assert_eq!(cropped.get_pixel(0, 0), &Rgba([1, 1, 0, 255]));
+    assert_eq!(cropped.get_pixel(1, 1), &Rgba([2, 2, 0, 255]));
+}
@@ -0,0 +1,305 @@
+//! Encoding the trails into an animated GIF as they are rendered
+#![cfg(feature = "native")]
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, Frame, Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, DirectorySink, TrailSettings};
+use radar_echo_trails::gif_export::{GifOptions, GifSequence};
+use radar_echo_trails::palette::Palette;
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+use radar_echo_trails::settings::GifDither;
+use radar_echo_trails::sequence_writer::SequenceWriter;
+
+mod common;
+use common::process;
+
+fn decode(path: &Path) -> Vec<Frame> {
+    let decoder = GifDecoder::new(BufReader::new(File::open(path).unwrap())).unwrap();
+    decoder.into_frames().collect_frames().unwrap()
+}
+
+fn delay_ms(frame: &Frame) -> u32 {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    numer / denom
+}
+
+/// Times the GIF repeats after playing once, from its NETSCAPE2.0 block; `None` plays it once
+fn repeats(path: &Path) -> Option<u16> {
+    let bytes = std::fs::read(path).unwrap();
+    let at = bytes.windows(11).position(|window| window == b"NETSCAPE2.0")?;
+    Some(u16::from_le_bytes([bytes[at + 13], bytes[at + 14]]))
+}
+
+#[test]
+fn frames_arriving_out_of_order_are_encoded_in_order() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_export_{}", std::process::id()));
+    let path = dir.join("loop.gif");
+    let colors = [Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255]), Rgba([0, 0, 255, 255])];
+    let writer = SequenceWriter::spawn(2, GifSequence::create(&path, vec![100, 200, 300], GifOptions { loops: 3, ..GifOptions::default() }).unwrap()).unwrap();
+    for index in [4, 2, 3] {
+        writer.push(index, RgbaImage::from_pixel(8, 6, colors[index - 2]));
+    }
+    assert_eq!(writer.finish().unwrap(), 3);
+    assert!(!dir.join(".loop.gif.partial").exists());
+
+    let frames = decode(&path);
+    let looped = repeats(&path);
+    assert_eq!(frames.iter().map(|frame| *frame.buffer().get_pixel(4, 3)).collect::<Vec<_>>(), colors);
+    assert_eq!(frames.iter().map(delay_ms).collect::<Vec<_>>(), [100, 200, 300]);
+    assert_eq!(looped, Some(2), "played three times");
+
+    // A frame that never came is skipped, and the GIF loops forever
+    let writer = SequenceWriter::spawn(0, GifSequence::create(&path, vec![50], GifOptions::default()).unwrap()).unwrap();
+    for index in [2, 0] {
+        writer.push(index, RgbaImage::from_pixel(8, 6, colors[index]));
+    }
+    assert_eq!(writer.finish().unwrap(), 2);
+    let frames = decode(&path);
+    let looped = repeats(&path);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(frames.iter().map(|frame| *frame.buffer().get_pixel(0, 0)).collect::<Vec<_>>(), [colors[0], colors[2]]);
+    assert_eq!(frames.iter().map(delay_ms).collect::<Vec<_>>(), [50, 50], "frames past the durations are held as long as the last");
+    assert_eq!(looped, Some(0));
+}
+
+#[test]
+fn rendered_trails_are_encoded_as_written() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_render_{}", std::process::id()));
+    let (source, output) = (dir.join("frames"), dir.join("output"));
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..6)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            RgbaImage::from_fn(24, 8, |x, _| if x / 4 == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { history_length: 3, ..TrailSettings::default() };
+    let gif = GifSequence::create(&output.join("loop.gif"), vec![100; paths.len()], GifOptions::for_settings(&settings)).unwrap();
+    let mut sink = DirectorySink::new(&output, &source).with_animation(SequenceWriter::spawn(0, gif).unwrap());
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    let summary = pool.install(|| engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(())));
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    let finished: Vec<usize> = sink.finish_animations().into_iter().map(Result::unwrap).collect();
+    assert_eq!(finished, [paths.len()]);
+    assert!(sink.finish_animations().is_empty());
+
+    let frames = decode(&output.join("loop.gif"));
+    let trails: Vec<RgbaImage> = paths.iter().map(|path| image::open(output.join(path.file_name().unwrap())).unwrap().to_rgba8()).collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    // The GIF is drawn in the settings' palette, whose nearest entry stands in
+    // where ghosts overlap, as in an indexed PNG
+    let palette = Palette::for_settings(&settings).unwrap();
+    assert_eq!(frames.len(), trails.len());
+    for (i, (frame, trail)) in frames.iter().zip(&trails).enumerate() {
+        assert!(*frame.buffer() == palette.quantize(trail), "frame {} differs from its trail", i);
+    }
+}
+
+#[test]
+fn a_corrupt_frame_does_not_hold_back_those_after_it() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_corrupt_{}", std::process::id()));
+    let (source, output) = (dir.join("frames"), dir.join("output"));
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..8)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            RgbaImage::from_fn(32, 4, |x, _| if x / 4 == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    let bytes = std::fs::read(&paths[3]).unwrap();
+    std::fs::write(&paths[3], &bytes[..bytes.len() / 2]).unwrap();
+
+    let settings = TrailSettings { history_length: 2, ..TrailSettings::default() };
+    let gif = GifSequence::create(&output.join("loop.gif"), vec![100; paths.len()], GifOptions::for_settings(&settings)).unwrap();
+    let mut sink = DirectorySink::new(&output, &source).with_animation(SequenceWriter::spawn(0, gif).unwrap());
+    // One worker renders the frames in order; by the last one, the frames
+    // after the corrupt one are encoded rather than kept until the end
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let encoded_by_last = std::sync::Mutex::new(None);
+    let summary = pool.install(|| {
+        engine::render_sequence(&paths, &settings, &sink, |frame| {
+            if frame.index == paths.len() - 1 {
+                let waited = std::time::Instant::now();
+                while sink.animations_encoded() < Some(paths.len() - 1) && waited.elapsed().as_secs() < 10 {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                *encoded_by_last.lock().unwrap() = sink.animations_encoded();
+            }
+            ControlFlow::Continue(())
+        })
+    });
+    let finished: Vec<usize> = sink.finish_animations().into_iter().map(Result::unwrap).collect();
+    let frames = decode(&output.join("loop.gif"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.failures.len(), 1, "{:?}", summary.failures);
+    assert_eq!(encoded_by_last.into_inner().unwrap(), Some(paths.len() - 1));
+    assert_eq!(finished, [paths.len() - 1]);
+    // The echo of each frame, in order, without the corrupt one
+    let columns: Vec<u32> = frames.iter()
+        .map(|frame| (0..32).rev().find(|&x| frame.buffer().get_pixel(x, 0)[1] == 255).unwrap() / 4)
+        .collect();
+    assert_eq!(columns, [0, 1, 2, 4, 5, 6, 7]);
+}
+
+/// Encode `frames` with `options` into `name` under `dir`, returning the decoded frames and the file's size
+fn encode(dir: &Path, name: &str, frames: &[RgbaImage], options: GifOptions) -> (Vec<Frame>, u64) {
+    let path = dir.join(name);
+    let writer = SequenceWriter::spawn(0, GifSequence::create(&path, vec![100], options).unwrap()).unwrap();
+    for (index, frame) in frames.iter().enumerate() {
+        writer.push(index, frame.clone());
+    }
+    assert_eq!(writer.finish().unwrap(), frames.len());
+    (decode(&path), std::fs::metadata(&path).unwrap().len())
+}
+
+fn distinct_colors(frames: &[Frame]) -> usize {
+    let mut colors: Vec<Rgba<u8>> = frames.iter().flat_map(|frame| frame.buffer().pixels().copied()).collect();
+    colors.sort_by_key(|pixel| pixel.0);
+    colors.dedup();
+    colors.len()
+}
+
+fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| Rgba([(x * 255 / (width - 1)) as u8, (y * 255 / (height - 1)) as u8, 128, 255]))
+}
+
+/// Entries in the global color table of the GIF at `path`
+fn palette_len(path: &Path) -> usize {
+    let packed = std::fs::read(path).unwrap()[10];
+    assert!(packed & 0x80 != 0, "no global color table");
+    2 << (packed & 0x07)
+}
+
+#[test]
+fn gif_colors_count_the_transparent_entry() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_palette_{}", std::process::id()));
+    let frames = [gradient(64, 64)];
+    let mut lengths = Vec::new();
+    for (colors, transparent) in [(4, false), (8, false), (8, true), (256, false), (256, true)] {
+        encode(&dir, "palette.gif", &frames, GifOptions { colors, transparent, ..GifOptions::default() });
+        lengths.push(palette_len(&dir.join("palette.gif")));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(lengths, [4, 8, 8, 256, 256]);
+}
+
+#[test]
+fn gif_colors_bound_the_palette_and_dithering_spreads_it() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_colors_{}", std::process::id()));
+    let frames = [gradient(64, 64)];
+    let (full, _) = encode(&dir, "full.gif", &frames, GifOptions::default());
+    let (eight, _) = encode(&dir, "eight.gif", &frames, GifOptions { colors: 8, ..GifOptions::default() });
+    let dithered: Vec<Vec<Frame>> = [GifDither::Ordered, GifDither::FloydSteinberg]
+        .into_iter()
+        .map(|dither| encode(&dir, "dithered.gif", &frames, GifOptions { colors: 8, dither, ..GifOptions::default() }).0)
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(distinct_colors(&full) > 8);
+    assert!(distinct_colors(&full) <= 256);
+    assert!(distinct_colors(&eight) <= 8, "{} colors", distinct_colors(&eight));
+    // Dithering mixes the few colors into patterns, so more pixels differ from
+    // their left neighbour than in the flat bands of the nearest colors
+    let edges = |frames: &[Frame]| {
+        let buffer = frames[0].buffer();
+        buffer.enumerate_pixels().filter(|(x, y, pixel)| *x > 0 && buffer.get_pixel(x - 1, *y) != *pixel).count()
+    };
+    for frames in &dithered {
+        assert!(distinct_colors(frames) <= 8);
+        assert!(edges(frames) > 2 * edges(&eight), "{} against {}", edges(frames), edges(&eight));
+    }
+}
+
+#[test]
+fn exact_colors_are_never_dithered() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_exact_{}", std::process::id()));
+    let colors = [Rgba([0, 0, 0, 255]), Rgba([255, 128, 0, 255]), Rgba([0, 200, 0, 255])];
+    let frame = RgbaImage::from_fn(30, 10, |x, _| colors[(x / 10) as usize]);
+    let (frames, _) = encode(&dir, "exact.gif", std::slice::from_ref(&frame), GifOptions { dither: GifDither::FloydSteinberg, ..GifOptions::default() });
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(*frames[0].buffer() == frame);
+}
+
+#[test]
+fn only_what_changed_is_written() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_diff_{}", std::process::id()));
+    let background = gradient(128, 128);
+    // An echo moving across a still, busy background
+    let frames: Vec<RgbaImage> = (0..6)
+        .map(|i| {
+            let mut frame = background.clone();
+            for (x, y) in (0..8).flat_map(|x| (0..8).map(move |y| (x, y))) {
+                frame.put_pixel(i * 10 + x, 60 + y, Rgba([255, 255, 255, 255]));
+            }
+            frame
+        })
+        .collect();
+    let (decoded, size) = encode(&dir, "moving.gif", &frames, GifOptions::default());
+    let (_, one) = encode(&dir, "one.gif", &frames[..1], GifOptions::default());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Each color reads back the same in every frame, so nothing of an echo
+    // that moved on is left behind, and the echo stays near white
+    let mut read_back: HashMap<Rgba<u8>, Rgba<u8>> = HashMap::new();
+    assert_eq!(decoded.len(), frames.len());
+    for (i, (frame, trail)) in decoded.iter().zip(&frames).enumerate() {
+        for (pixel, written) in trail.pixels().zip(frame.buffer().pixels()) {
+            let first = *read_back.entry(*pixel).or_insert(*written);
+            assert_eq!(first, *written, "frame {} reads {:?} back as {:?} and {:?}", i, pixel, first, written);
+        }
+    }
+    let echo = read_back[&Rgba([255, 255, 255, 255])];
+    assert!(echo.0.iter().all(|&channel| channel >= 240), "the echo reads back as {:?}", echo);
+    // Five more frames take far less than five more backgrounds would
+    assert!(size < one * 2, "{} bytes against {} for one frame", size, one);
+}
+
+#[test]
+fn transparent_trails_keep_their_transparency() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_transparent_{}", std::process::id()));
+    let echo = Rgba([255, 128, 0, 255]);
+    let frames: Vec<RgbaImage> = (0..3)
+        .map(|i| RgbaImage::from_fn(12, 4, |x, _| if x / 4 == i { echo } else { Rgba([0, 0, 0, 0]) }))
+        .collect();
+    let (decoded, _) = encode(&dir, "clear.gif", &frames, GifOptions { transparent: true, ..GifOptions::default() });
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(decoded.len(), frames.len());
+    for (frame, trail) in decoded.iter().zip(&frames) {
+        assert!(frame.buffer() == trail, "an echo that moved on left its pixels behind");
+    }
+}
+
+#[test]
+fn the_size_of_the_gif_is_reported() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_gif_size_{}", std::process::id()));
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    for i in 0..3 {
+        RgbaImage::from_fn(12, 4, |x, _| if x / 4 == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 255]) })
+            .save(frames.join(format!("scan_{:03}.png", i)))
+            .unwrap();
+    }
+    let settings = ProcessingSettings { animate_gif: Some(PathBuf::from("loop.gif")), ..ProcessingSettings::default() };
+    let updates = process(&frames, &settings);
+    let gif = processing::output_dir_for(&frames, &settings).join("loop.gif");
+    let size = std::fs::metadata(&gif).unwrap().len();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        updates.iter().any(|update| matches!(update, ProgressUpdate::AnimationWritten { path, bytes, .. } if *path == gif && *bytes == size)),
+        "{:?}",
+        updates
+    );
+}
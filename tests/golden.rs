@@ -0,0 +1,381 @@
+//! Golden-image tests: small synthetic sequences rendered through the library
+//! and compared with the PNGs committed under `tests/golden`.
+//!
+//! When a change is meant to alter the output, regenerate the goldens with
+//! `UPDATE_GOLDENS=1 cargo test --test golden` and review the new images
+//! before committing them. A failing frame is saved under the test target
+//! directory next to the path of its golden, for comparison.
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, DirectorySink, PixelShader, Shader, TrailCompositor, TrailSettings};
+use radar_echo_trails::processing::{CurrentStyle, ResizeFilter, TrailMode};
+use radar_echo_trails::queue::{self, ScanOptions};
+
+const SIZE: u32 = 32;
+const FRAMES: u32 = 6;
+
+/// How far a rendered frame may stray from its golden
+#[derive(Clone, Copy, Debug)]
+enum Tolerance {
+    Exact,
+    /// Largest difference allowed in any channel, for output that goes through
+    /// enough float arithmetic to differ in the last bit between platforms
+    Within(u8),
+}
+
+struct Case {
+    name: &'static str,
+    frame: fn(u32) -> RgbaImage,
+    settings: fn() -> TrailSettings,
+    tolerance: Tolerance,
+}
+
+/// A white disk crossing the frame from left to right
+fn moving_disk(index: u32) -> RgbaImage {
+    let cx = 6.0 + index as f32 * 4.0;
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let d = ((x as f32 - cx).powi(2) + (y as f32 - 16.0).powi(2)).sqrt();
+        if d <= 5.0 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) }
+    })
+}
+
+/// A grey blob that grows in the middle of the sequence and is gone again at the end
+fn blob(index: u32) -> RgbaImage {
+    let radius = [0.0, 3.0, 7.0, 7.0, 3.0, 0.0][index as usize];
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let d = ((x as f32 - 12.0).powi(2) + (y as f32 - 18.0).powi(2)).sqrt();
+        if radius > 0.0 && d <= radius { Rgba([180, 180, 180, 255]) } else { Rgba([0, 0, 0, 0]) }
+    })
+}
+
+/// A disk moving diagonally whose alpha falls off over a four pixel rim,
+/// with the grey level varying across it
+fn soft_edges(index: u32) -> RgbaImage {
+    let c = 8.0 + index as f32 * 3.0;
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let d = ((x as f32 - c).powi(2) + (y as f32 - c).powi(2)).sqrt();
+        let alpha = ((9.0 - d) / 4.0).clamp(0.0, 1.0);
+        let level = (96 + x * 5) as u8;
+        Rgba([level, level, level, (alpha * 255.0) as u8])
+    })
+}
+
+/// A wide band of smoothly varying intensity and alpha drifting down the frame
+fn gradient(index: u32) -> RgbaImage {
+    let top = index as f32 * 2.0;
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let alpha = (1.0 - (y as f32 - top - 8.0).abs() / 8.0).clamp(0.0, 1.0);
+        let level = (64 + x * 6) as u8;
+        Rgba([level, 255 - level, 160, (alpha * 255.0) as u8])
+    })
+}
+
+/// History in a ramp from blue to red by age, current frame in white
+struct AgeRamp;
+
+impl PixelShader for AgeRamp {
+    fn shade(&self, src: Rgba<u8>, age: f32, _x: u32, _y: u32) -> Option<Rgba<u8>> {
+        if src[3] == 0 {
+            return None;
+        }
+        if age == 0.0 {
+            return Some(Rgba([255, 255, 255, src[3]]));
+        }
+        Some(Rgba([(255.0 * (1.0 - age)) as u8, 0, (255.0 * age) as u8, src[3]]))
+    }
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "moving_disk",
+        frame: moving_disk,
+        settings: || TrailSettings { history_length: 3, ..TrailSettings::default() },
+        tolerance: Tolerance::Exact,
+    },
+    // The goldens moving_disk had before the exact fade, unchanged
+    Case {
+        name: "moving_disk_legacy_fade",
+        frame: moving_disk,
+        settings: || TrailSettings { history_length: 3, legacy_fade: true, ..TrailSettings::default() },
+        tolerance: Tolerance::Exact,
+    },
+    Case {
+        name: "blob",
+        frame: blob,
+        settings: || TrailSettings {
+            history_length: 2,
+            background_color: "#ffffff".to_string(),
+            current_color: "#0072b2".to_string(),
+            history_color: "#d55e00".to_string(),
+            ..TrailSettings::default()
+        },
+        tolerance: Tolerance::Exact,
+    },
+    // The current frame keeps its intensity and soft rim, as it does by default
+    Case {
+        name: "soft_edges",
+        frame: soft_edges,
+        settings: || TrailSettings { history_length: 4, ..TrailSettings::default() },
+        tolerance: Tolerance::Within(1),
+    },
+    // The same frames with the current frame drawn flat out to its faintest pixel
+    Case {
+        name: "soft_edges_solid",
+        frame: soft_edges,
+        settings: || TrailSettings { history_length: 4, current_style: CurrentStyle::Solid, ..TrailSettings::default() },
+        tolerance: Tolerance::Within(1),
+    },
+    Case {
+        name: "age_shader",
+        frame: moving_disk,
+        settings: || TrailSettings {
+            history_length: 4,
+            shader: Some(Shader(Arc::new(AgeRamp))),
+            ..TrailSettings::default()
+        },
+        tolerance: Tolerance::Within(1),
+    },
+    // Hard-edged echoes keep hard edges at half scale, even under a ringing filter
+    Case {
+        name: "mask_half_scale",
+        frame: moving_disk,
+        settings: || TrailSettings {
+            history_length: 3,
+            history_scale: 0.5,
+            resize_filter: ResizeFilter::Lanczos3,
+            ..TrailSettings::default()
+        },
+        tolerance: Tolerance::Within(1),
+    },
+    // Soft echoes are filtered smoothly, without darkening towards their rim
+    Case {
+        name: "gradient_half_scale",
+        frame: gradient,
+        settings: || TrailSettings { history_length: 3, history_scale: 0.5, ..TrailSettings::default() },
+        tolerance: Tolerance::Within(1),
+    },
+    // One buffer of the history, faded each frame, in place of the history frames
+    Case {
+        name: "moving_disk_decay",
+        frame: moving_disk,
+        settings: || TrailSettings { history_length: 3, trail_mode: TrailMode::Decay, ..TrailSettings::default() },
+        tolerance: Tolerance::Within(1),
+    },
+    // The history's blended opacity kept in the PNG rather than flattened onto a background
+    Case {
+        name: "moving_disk_transparent",
+        frame: moving_disk,
+        settings: || TrailSettings { history_length: 3, transparent_background: true, ..TrailSettings::default() },
+        tolerance: Tolerance::Exact,
+    },
+];
+
+fn case(name: &str) -> &'static Case {
+    CASES.iter().find(|case| case.name == name).unwrap()
+}
+
+fn golden_path(case: &Case, index: u32) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(case.name)
+        .join(format!("frame_{:02}.png", index))
+}
+
+/// Write the case's frames to disk, find them as the app would and render the
+/// folder into an output directory, returning the frames read back in order.
+/// With `emit_index`, the provenance index is written there too.
+fn render_from_disk(case: &Case, emit_index: Option<&str>) -> Vec<RgbaImage> {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden").join(case.name);
+    let _ = std::fs::remove_dir_all(&root);
+    let (source, output) = (root.join("frames"), root.join("output"));
+    std::fs::create_dir_all(&source).unwrap();
+    for index in 0..FRAMES {
+        (case.frame)(index).save(source.join(format!("frame_{:02}.png", index))).unwrap();
+    }
+
+    let scan = queue::scan_folder(&source, &ScanOptions::default()).unwrap();
+    assert_eq!(scan.files.len(), FRAMES as usize);
+    let settings = TrailSettings { emit_index: emit_index.map(PathBuf::from), ..(case.settings)() };
+    let mut sink = DirectorySink::new(&output, &source);
+    if let Some(index) = emit_index {
+        sink = sink.with_index(index);
+    }
+    let summary = engine::render_sequence(&scan.files, &settings, &sink, |_| ControlFlow::Continue(()));
+    assert!(summary.failures.is_empty(), "{}: {:?}", case.name, summary.failures);
+    assert_eq!(summary.frames_completed, FRAMES as usize);
+
+    (0..FRAMES)
+        .map(|index| image::open(output.join(format!("frame_{:02}.png", index))).unwrap().to_rgba8())
+        .collect()
+}
+
+/// Compare `actual` with the golden for frame `index` of `case`, or replace the
+/// golden when regenerating
+fn check(case: &Case, index: u32, actual: &RgbaImage) {
+    let path = golden_path(case, index);
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let golden = match image::open(&path) {
+        Ok(golden) => golden.to_rgba8(),
+        Err(e) => panic!("{}: {} (regenerate with UPDATE_GOLDENS=1)", path.display(), e),
+    };
+    assert_eq!(actual.dimensions(), golden.dimensions(), "{}", path.display());
+
+    let allowed = match case.tolerance {
+        Tolerance::Exact => 0,
+        Tolerance::Within(channel) => channel,
+    };
+    let mismatches: Vec<(u32, u32, Rgba<u8>, Rgba<u8>)> = actual.enumerate_pixels()
+        .zip(golden.pixels())
+        .filter(|((_, _, a), g)| a.0.iter().zip(g.0).any(|(a, g)| a.abs_diff(g) > allowed))
+        .map(|((x, y, a), g)| (x, y, *a, *g))
+        .collect();
+    if let Some(&(x, y, a, g)) = mismatches.first() {
+        let saved = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join("golden-failures")
+            .join(case.name)
+            .join(path.file_name().unwrap());
+        std::fs::create_dir_all(saved.parent().unwrap()).unwrap();
+        actual.save(&saved).unwrap();
+        panic!(
+            "{}: {} pixels differ by more than {}, first at ({}, {}): {:?} against {:?}; output saved to {}",
+            path.display(), mismatches.len(), allowed, x, y, a.0, g.0, saved.display()
+        );
+    }
+}
+
+/// The folder pipeline, from files on disk to files on disk
+fn check_rendered(name: &str) {
+    let case = case(name);
+    for (index, frame) in (0..).zip(render_from_disk(case, None)) {
+        check(case, index, &frame);
+    }
+}
+
+/// The streaming compositor fed the same frames, which must match the same goldens
+fn check_streamed(case: &Case) {
+    let trails = TrailCompositor::new((case.settings)()).composite((0..FRAMES).map(case.frame));
+    for (index, frame) in (0..).zip(trails) {
+        check(case, index, &frame);
+    }
+}
+
+#[test]
+fn moving_disk_renders_as_golden() {
+    check_rendered("moving_disk");
+}
+
+#[test]
+fn legacy_fade_renders_as_before() {
+    check_rendered("moving_disk_legacy_fade");
+}
+
+#[test]
+fn blob_renders_as_golden() {
+    check_rendered("blob");
+}
+
+#[test]
+fn soft_edges_render_as_golden() {
+    check_rendered("soft_edges");
+}
+
+#[test]
+fn solid_current_frame_renders_as_golden() {
+    check_rendered("soft_edges_solid");
+}
+
+#[test]
+fn age_shader_renders_as_golden() {
+    check_rendered("age_shader");
+}
+
+#[test]
+fn mask_renders_as_golden_at_half_scale() {
+    check_rendered("mask_half_scale");
+}
+
+#[test]
+fn gradient_renders_as_golden_at_half_scale() {
+    check_rendered("gradient_half_scale");
+}
+
+#[test]
+fn decay_renders_as_golden() {
+    check_rendered("moving_disk_decay");
+}
+
+#[test]
+fn transparent_background_renders_as_golden() {
+    check_rendered("moving_disk_transparent");
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        return;
+    }
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden/moving_disk_transparent/output/frame_04.png");
+    let written = image::open(output).unwrap();
+    assert!(written.color().has_alpha());
+    let written = written.to_rgba8();
+    // Along the middle row: clear where nothing is drawn, the oldest disk
+    // alone at its faded opacity, two disks over each other more opaque
+    // together, and the current frame opaque
+    let alphas: Vec<u8> = [0, 5, 12, 22].iter().map(|&x| written.get_pixel(x, 16)[3]).collect();
+    assert_eq!(alphas, [0, 43, 114, 255]);
+}
+
+#[test]
+fn streaming_matches_goldens() {
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        return; // the goldens come from the folder pipeline
+    }
+    for case in CASES {
+        check_streamed(case);
+    }
+}
+
+/// The provenance index written beside a golden case names the frames and
+/// opacities its goldens were drawn with
+#[test]
+fn provenance_index_matches_goldens() {
+    if std::env::var_os("UPDATE_GOLDENS").is_some() {
+        return;
+    }
+    let case = case("moving_disk");
+    let settings = (case.settings)();
+    render_from_disk(case, Some("index.jsonl"));
+    let index = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden").join(case.name).join("output/index.jsonl");
+    let records: Vec<serde_json::Value> = std::fs::read_to_string(index).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), FRAMES as usize);
+    assert_eq!(records[0]["history"], serde_json::json!([]));
+
+    let record = &records[4];
+    assert_eq!(record["output"], "frame_04.png");
+    assert_eq!(record["source"], "frame_04.png");
+    assert_eq!(record["tint"], "#00ff00");
+    let history = record["history"].as_array().unwrap();
+    let sources: Vec<&str> = history.iter().map(|past| past["source"].as_str().unwrap()).collect();
+    assert_eq!(sources, ["frame_01.png", "frame_02.png", "frame_03.png"]);
+    for (past, frames_back) in history.iter().zip([3, 2, 1]) {
+        let fade = engine::history_fade(engine::history_age(frames_back, 3), 3, &settings);
+        assert_eq!(past["frames_back"], frames_back);
+        assert_eq!(past["alpha"], engine::history_alpha(fade, 1.0, &settings));
+        assert_eq!(past["tint"], "#ff7f00");
+    }
+
+    // Only the oldest frame, the disk at x = 10, reaches x = 5 on the middle row
+    let golden = image::open(golden_path(case, 4)).unwrap().to_rgba8();
+    let alpha = history[0]["alpha"].as_u64().unwrap() as u8;
+    assert_eq!(golden.get_pixel(5, 16), &engine::blend_pixel(Rgba([0, 0, 0, 255]), Rgba([255, 127, 0, 255]), alpha));
+    assert_eq!(alpha, 43);
+}
@@ -0,0 +1,72 @@
+//! Coloring each history frame by age from a list of colors
+
+use image::RgbaImage;
+use radar_echo_trails::engine::{compose_layers, compose_trail, history_age, TrailSettings};
+use radar_echo_trails::settings::{format_history_colors, parse_history_colors};
+
+mod common;
+use common::echo;
+
+/// The history frames of an echo moving right one column a frame, oldest first
+fn past() -> Vec<RgbaImage> {
+    (0..5).map(|x| echo(x, 6)).collect()
+}
+
+fn history(past: &[RgbaImage]) -> Vec<(&RgbaImage, f32)> {
+    past.iter().enumerate().map(|(i, frame)| (frame, history_age(past.len() - i, past.len()))).collect()
+}
+
+fn listed(text: &str) -> TrailSettings {
+    TrailSettings { history_colors: parse_history_colors(text), ..TrailSettings::default() }
+}
+
+/// Color of the history in each column, without its alpha
+fn colors(settings: &TrailSettings) -> Vec<[u8; 3]> {
+    let past = past();
+    let layers = compose_layers(&echo(5, 6), &history(&past), settings);
+    (0..5).map(|x| {
+        let pixel = layers.history.get_pixel(x, 0);
+        [pixel[0], pixel[1], pixel[2]]
+    }).collect()
+}
+
+#[test]
+fn each_age_takes_its_color_and_the_last_repeats() {
+    // Oldest on the left, newest on the right
+    let drawn = colors(&listed("#ffff00,#ff8800,#aa2200"));
+    assert_eq!(drawn, [[170, 34, 0], [170, 34, 0], [170, 34, 0], [255, 136, 0], [255, 255, 0]]);
+
+    // Colors beyond the history go unused, and the list outranks a gradient
+    let longer = TrailSettings { history_gradient: "#ff0000:#0000ff".to_string(), ..listed("#ffff00,#ff8800,#aa2200,#00ff00,#0000ff,#ff00ff,#00ffff") };
+    assert_eq!(colors(&longer)[0], [0, 0, 255]);
+    assert_eq!(colors(&longer)[4], [255, 255, 0]);
+
+    // The fade still applies: the oldest is the faintest
+    let past = past();
+    let layers = compose_layers(&echo(5, 6), &history(&past), &listed("#ffff00,#ff8800,#aa2200"));
+    assert!(layers.history.get_pixel(0, 0)[3] < layers.history.get_pixel(4, 0)[3]);
+}
+
+#[test]
+fn a_single_color_draws_as_the_history_color() {
+    let past = past();
+    let trail = |settings: &TrailSettings| compose_trail(&echo(5, 6), &history(&past), settings);
+    let plain = TrailSettings { history_color: "#00ffff".to_string(), ..TrailSettings::default() };
+    assert!(trail(&listed("#00ffff")) == trail(&plain));
+    assert!(trail(&listed("")) == trail(&TrailSettings::default()));
+}
+
+#[test]
+fn reads_lists_and_checks_each_color() {
+    assert_eq!(parse_history_colors(" #ffff00, #ff8800 ,"), ["#ffff00", "#ff8800"]);
+    assert!(parse_history_colors(" ").is_empty());
+    assert_eq!(format_history_colors(&parse_history_colors("#ffff00,#ff8800")), "#ffff00,#ff8800");
+    assert!(listed("#ffff00,orange").validate().is_err());
+    assert!(listed("#ffff00,#ff8800").validate().is_ok());
+
+    let plain = TrailSettings::default();
+    assert_ne!(listed("#ffff00,#ff8800").output_hash(), plain.output_hash());
+    assert_ne!(listed("#ffff00,#ff8800").output_hash(), listed("#ff8800,#ffff00").output_hash());
+    assert_eq!(listed("").output_hash(), plain.output_hash());
+    assert_eq!(listed("#FFFF00").output_hash(), listed("#ffff00").output_hash());
+}
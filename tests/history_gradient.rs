@@ -0,0 +1,81 @@
+//! Coloring the history by age along a gradient
+
+use image::RgbaImage;
+use radar_echo_trails::color;
+use radar_echo_trails::engine::{compose_layers, compose_trail, history_age, TrailSettings};
+use radar_echo_trails::settings::{parse_history_gradient, GradientSpace};
+
+mod common;
+use common::echo;
+
+/// The history frames of an echo moving right one column a frame, oldest first
+fn past() -> Vec<RgbaImage> {
+    (0..5).map(|x| echo(x, 6)).collect()
+}
+
+fn history(past: &[RgbaImage]) -> Vec<(&RgbaImage, f32)> {
+    past.iter().enumerate().map(|(i, frame)| (frame, history_age(past.len() - i, past.len()))).collect()
+}
+
+fn gradient(text: &str, space: GradientSpace) -> TrailSettings {
+    TrailSettings { history_gradient: text.to_string(), gradient_space: space, ..TrailSettings::default() }
+}
+
+/// Color of the history in each column, without its alpha
+fn colors(settings: &TrailSettings) -> Vec<[u8; 3]> {
+    let past = past();
+    let layers = compose_layers(&echo(5, 6), &history(&past), settings);
+    (0..5).map(|x| {
+        let pixel = layers.history.get_pixel(x, 0);
+        [pixel[0], pixel[1], pixel[2]]
+    }).collect()
+}
+
+#[test]
+fn history_runs_from_the_newest_color_to_the_oldest() {
+    // Oldest on the left, newest on the right
+    let linear = colors(&gradient("#ff0000:#0000ff", GradientSpace::LinearRgb));
+    assert_eq!(linear[4], [255, 0, 0]);
+    assert_eq!(linear[2], [188, 0, 188]);
+    assert_eq!(linear[0], [0, 0, 255]);
+    let hsv = colors(&gradient("#ff0000:#0000ff", GradientSpace::Hsv));
+    assert_eq!((hsv[4], hsv[2], hsv[0]), ([255, 0, 0], [255, 0, 255], [0, 0, 255]));
+
+    // The fade still applies: the oldest is the faintest
+    let past = past();
+    let layers = compose_layers(&echo(5, 6), &history(&past), &gradient("#ff0000:#0000ff", GradientSpace::LinearRgb));
+    assert!(layers.history.get_pixel(0, 0)[3] < layers.history.get_pixel(4, 0)[3]);
+}
+
+#[test]
+fn a_single_color_draws_as_the_history_color() {
+    let past = past();
+    let trail = |settings: &TrailSettings| compose_trail(&echo(5, 6), &history(&past), settings);
+    let plain = TrailSettings { history_color: "#00ffff".to_string(), ..TrailSettings::default() };
+    assert!(trail(&gradient("#00ffff", GradientSpace::Hsv)) == trail(&plain));
+    assert!(trail(&gradient("#00ffff:#00ffff", GradientSpace::LinearRgb)) == trail(&plain));
+    assert!(trail(&gradient("", GradientSpace::Hsv)) == trail(&TrailSettings::default()));
+}
+
+#[test]
+fn reads_gradients_and_mixes_greys_by_the_other_hue() {
+    assert_eq!(parse_history_gradient("#ff0000 : #0000ff"), Ok(Some(((255, 0, 0), (0, 0, 255)))));
+    assert_eq!(parse_history_gradient("#ff0000"), Ok(Some(((255, 0, 0), (255, 0, 0)))));
+    assert_eq!(parse_history_gradient(" "), Ok(None));
+    assert!(parse_history_gradient("#ff0000:blue").is_err());
+    assert!(parse_history_gradient("#ff0000:#00ff00:#0000ff").is_err());
+    assert!(gradient("#ff0000:", GradientSpace::LinearRgb).validate().is_err());
+
+    // The hue turns the short way round, from red past magenta to blue
+    assert_eq!(color::mix((255, 0, 0), (0, 0, 255), 0.25, GradientSpace::Hsv), (255, 0, 128));
+    // A grey keeps the hue of the other end and gains its saturation
+    assert_eq!(color::mix((255, 255, 255), (0, 0, 255), 0.5, GradientSpace::Hsv), (128, 128, 255));
+
+    let plain = TrailSettings::default();
+    let linear = gradient("#ff0000:#0000ff", GradientSpace::LinearRgb);
+    let hsv = gradient("#ff0000:#0000ff", GradientSpace::Hsv);
+    assert_ne!(linear.output_hash(), plain.output_hash());
+    assert_ne!(linear.output_hash(), hsv.output_hash());
+    assert_eq!(gradient("", GradientSpace::Hsv).output_hash(), plain.output_hash());
+    assert_eq!(gradient("#00ffff", GradientSpace::Hsv).output_hash(), gradient("#00ffff", GradientSpace::LinearRgb).output_hash());
+}
@@ -0,0 +1,104 @@
+//! History drawn at reduced resolution under a sharp current frame
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, compose_trail, downsample_history, TrailSettings};
+use radar_echo_trails::processing::ResizeFilter;
+
+fn close(a: Rgba<u8>, b: Rgba<u8>) -> bool {
+    (0..4).all(|channel| a[channel].abs_diff(b[channel]) <= 2)
+}
+
+#[test]
+fn reduced_history_matches_full_resolution_away_from_edges() {
+    let echo = RgbaImage::from_fn(16, 16, |x, _| if x < 8 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) });
+    let mut current = RgbaImage::new(16, 16);
+    current.put_pixel(12, 3, Rgba([255, 255, 255, 255]));
+    let full = compose_trail(&current, &[(&echo, 0.5)], &TrailSettings::default());
+    let settings = TrailSettings { history_scale: 0.5, ..TrailSettings::default() };
+    let reduced = compose_trail(&current, &[(&echo, 0.5)], &settings);
+
+    assert_eq!(reduced.dimensions(), (16, 16));
+    for (x, y) in [(1, 10), (2, 2), (14, 8), (15, 15)] {
+        assert!(close(*reduced.get_pixel(x, y), *full.get_pixel(x, y)), "{:?} at {},{}", reduced.get_pixel(x, y), x, y);
+    }
+    // A single pixel of the current frame stays sharp
+    assert_eq!(reduced.get_pixel(12, 3), &Rgba([0, 255, 0, 255]));
+    assert_eq!(reduced.get_pixel(13, 3), full.get_pixel(13, 3));
+}
+
+#[test]
+fn history_reduced_beforehand_draws_the_same() {
+    let echo = RgbaImage::from_fn(20, 12, |x, y| Rgba([(x * 12) as u8, 200, (y * 20) as u8, 255]));
+    let current = RgbaImage::new(20, 12);
+    let settings = TrailSettings { history_scale: 0.25, ..TrailSettings::default() };
+    let reduced = downsample_history(&echo, 0.25, ResizeFilter::Bilinear);
+    assert_eq!(reduced.dimensions(), (5, 3));
+    assert_eq!(
+        compose_trail(&current, &[(&echo, 0.5)], &settings),
+        compose_trail(&current, &[(&reduced, 0.5)], &settings),
+    );
+}
+
+#[test]
+fn hard_edges_stay_hard_and_soft_frames_are_filtered() {
+    let mask = RgbaImage::from_fn(16, 16, |x, y| if (x + y) % 5 < 2 { Rgba([200, 40, 40, 255]) } else { Rgba([0, 0, 0, 0]) });
+    let soft = RgbaImage::from_fn(16, 16, |x, _| Rgba([200, 40, 40, (x * 16) as u8]));
+    for filter in [ResizeFilter::Nearest, ResizeFilter::Bilinear, ResizeFilter::CatmullRom, ResizeFilter::Lanczos3] {
+        let reduced = downsample_history(&mask, 0.5, filter);
+        assert!(reduced.pixels().all(|pixel| pixel[3] == 0 || pixel[3] == 255), "{}", filter);
+        // Transparent neighbours don't darken the color
+        assert!(reduced.pixels().filter(|pixel| pixel[3] == 255).all(|pixel| pixel[0] >= 190), "{}", filter);
+    }
+    let reduced = downsample_history(&soft, 0.5, ResizeFilter::Bilinear);
+    assert!(reduced.pixels().any(|pixel| pixel[3] != 0 && pixel[3] != 255));
+    // Premultiplied in 8 bits, so only fairly opaque pixels keep their exact color
+    assert!(reduced.pixels().filter(|pixel| pixel[3] >= 64).all(|pixel| pixel[0].abs_diff(200) <= 2));
+}
+
+#[test]
+fn rejects_scales_outside_the_range() {
+    for (scale, valid) in [(0.1, false), (0.25, true), (0.6, true), (1.0, true), (1.5, false)] {
+        let settings = TrailSettings { history_scale: scale, ..TrailSettings::default() };
+        assert_eq!(settings.validate().is_ok(), valid, "{}", scale);
+    }
+}
+
+#[test]
+fn renders_sequences_at_full_size() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_history_scale_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<PathBuf> = (0..4)
+        .map(|i| {
+            let path = dir.join(format!("scan_{}.png", i));
+            RgbaImage::from_fn(24, 16, |x, _| if x / 4 == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    let written = Mutex::new(Vec::new());
+    let sink = |index: usize, _: &Path, frame: RgbaImage| {
+        written.lock().unwrap().push((index, frame));
+        Ok(())
+    };
+    let settings = TrailSettings { history_length: 3, history_scale: 0.5, ..TrailSettings::default() };
+    let summary = engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(()));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    let mut written = written.into_inner().unwrap();
+    written.sort_by_key(|(index, _)| *index);
+    let (_, last) = &written[3];
+    assert_eq!(last.dimensions(), (24, 16));
+    assert_eq!(last.get_pixel(13, 8), &Rgba([0, 255, 0, 255]));
+    // The frame before, as a ghost
+    assert_ne!(last.get_pixel(9, 8), &Rgba([0, 0, 0, 255]));
+    // Every history age is still counted
+    assert_eq!(summary.age_contributions.len(), 3);
+    assert!(summary.age_contributions.iter().all(|age| age.mean_pixels > 0.0));
+}
@@ -0,0 +1,142 @@
+//! Frames interpolated between scans
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Mutex;
+
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, FrameSink, TrailSettings};
+use radar_echo_trails::interpolation::{along_flow, cross_fade};
+use radar_echo_trails::motion::MotionVector;
+use radar_echo_trails::palette::Palette;
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+use radar_echo_trails::settings::{AnimationTiming, InterpolationSettings};
+
+mod common;
+use common::process;
+
+/// A transparent frame with a white square of side 8 at `left`, 12
+fn blob(left: u32) -> RgbaImage {
+    let mut frame = RgbaImage::from_pixel(48, 32, Rgba([0, 0, 0, 0]));
+    for y in 12..20 {
+        for x in left..left + 8 {
+            frame.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+        }
+    }
+    frame
+}
+
+#[test]
+fn cross_fades_opacity_not_color() {
+    let faded = cross_fade(&blob(4), &blob(30), 0.25);
+    assert_eq!(faded.get_pixel(6, 14), &Rgba([255, 255, 255, 191]));
+    assert_eq!(faded.get_pixel(32, 14), &Rgba([255, 255, 255, 64]));
+}
+
+#[test]
+fn moves_echoes_along_the_flow() {
+    let vectors: Vec<MotionVector> = (0..3)
+        .flat_map(|row| (0..6).map(move |column| (row, column)))
+        .map(|(row, column)| MotionVector { x: column * 8 + 4, y: row * 8 + 4, dx: 8.0, dy: 0.0, coherence: 1.0 })
+        .collect();
+    let halfway = along_flow(&blob(8), &blob(16), 0.5, &vectors, 8);
+    assert_eq!(halfway.get_pixel(12, 14), &Rgba([255, 255, 255, 255]));
+    assert_eq!(halfway.get_pixel(19, 14), &Rgba([255, 255, 255, 255]));
+    assert_eq!(halfway.get_pixel(9, 14)[3], 0);
+}
+
+/// Keeps every trail it is given, interpolated ones under their names
+#[derive(Default)]
+struct Collect(Mutex<Vec<(String, RgbaImage)>>);
+
+impl FrameSink for Collect {
+    fn write_frame(&self, _index: usize, source: &Path, frame: RgbaImage) -> anyhow::Result<()> {
+        self.0.lock().unwrap().push((source.file_name().unwrap().to_string_lossy().into_owned(), frame));
+        Ok(())
+    }
+
+    fn write_interpolated(&self, _before: usize, _step: u32, source: &Path, frame: RgbaImage) -> anyhow::Result<()> {
+        self.write_frame(0, source, frame)
+    }
+}
+
+fn render(dir: &Path, interpolation: InterpolationSettings) -> Vec<(String, RgbaImage)> {
+    let paths: Vec<PathBuf> = (0..3)
+        .map(|i| {
+            let path = dir.join(format!("frame_{}.png", i));
+            blob(4 + 8 * i).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { history_length: 2, interpolation, ..TrailSettings::default() };
+    let sink = Collect::default();
+    let summary = engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(()));
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    let mut written = sink.0.into_inner().unwrap();
+    written.sort_by(|a, b| a.0.cmp(&b.0));
+    written
+}
+
+#[test]
+fn inserts_named_frames_and_keeps_scans_unchanged_unless_in_history() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_interpolation_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let plain = render(&dir, InterpolationSettings { frames: 0, ..InterpolationSettings::default() });
+    let between = render(&dir, InterpolationSettings { frames: 2, ..InterpolationSettings::default() });
+    let in_history = render(&dir, InterpolationSettings { frames: 2, in_history: true, ..InterpolationSettings::default() });
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let names: Vec<&str> = between.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, [
+        "frame_0.png", "frame_0_interp1.png", "frame_0_interp2.png",
+        "frame_1.png", "frame_1_interp1.png", "frame_1_interp2.png",
+        "frame_2.png",
+    ]);
+    let scan = |written: &[(String, RgbaImage)], name: &str| written.iter().find(|(n, _)| n == name).unwrap().1.clone();
+    assert!(scan(&plain, "frame_2.png") == scan(&between, "frame_2.png"));
+    assert!(scan(&plain, "frame_2.png") != scan(&in_history, "frame_2.png"));
+}
+
+#[test]
+fn interpolated_trails_are_animated_between_the_scans() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_interpolation_gif_{}", std::process::id()));
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    for i in 0..3 {
+        blob(4 + 8 * i).save(frames.join(format!("frame_{}.png", i))).unwrap();
+    }
+    let settings = ProcessingSettings {
+        history_length: 2,
+        interpolation: InterpolationSettings { frames: 1, ..InterpolationSettings::default() },
+        animate_gif: Some(PathBuf::from("loop.gif")),
+        animation: AnimationTiming { fps: 5.0, ..AnimationTiming::default() },
+        ..ProcessingSettings::default()
+    };
+    let updates = process(&frames, &settings);
+    assert!(updates.iter().any(|update| matches!(update, ProgressUpdate::FolderCompleted { frames_rendered: 3, .. })), "{:?}", updates);
+
+    let output = processing::output_dir_for(&frames, &settings);
+    let decoder = GifDecoder::new(BufReader::new(File::open(output.join("loop.gif")).unwrap())).unwrap();
+    let animated = decoder.into_frames().collect_frames().unwrap();
+    let names = ["frame_0.png", "frame_0_interp1.png", "frame_1.png", "frame_1_interp1.png", "frame_2.png"];
+    let stills: Vec<RgbaImage> = names.iter().map(|name| image::open(output.join(name)).unwrap().to_rgba8()).collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Each scan shares its fifth of a second with the frame after it
+    let delays: Vec<u32> = animated.iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            numer / denom
+        })
+        .collect();
+    assert_eq!(delays, [100, 100, 100, 100, 200]);
+    let palette = Palette::for_settings(&settings).unwrap();
+    for (name, (frame, still)) in names.iter().zip(animated.iter().zip(&stills)) {
+        assert!(*frame.buffer() == palette.quantize(still), "the GIF's frame differs from {}", name);
+    }
+    assert!(stills[1] != stills[0] && stills[1] != stills[2]);
+}
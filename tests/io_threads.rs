@@ -0,0 +1,46 @@
+//! Limiting the reads and writes of a run, and timing them
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, io_thread_count, DirectorySink, TrailSettings};
+use radar_echo_trails::settings::MAX_AUTO_IO_THREADS;
+
+#[test]
+fn automatic_io_threads_are_capped() {
+    assert_eq!(io_thread_count(3), 3);
+    let auto = io_thread_count(0);
+    assert!((1..=MAX_AUTO_IO_THREADS).contains(&auto), "{}", auto);
+}
+
+#[test]
+fn one_io_thread_renders_every_frame_and_times_the_run() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_io_threads_{}", std::process::id()));
+    let source = dir.join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..6)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            RgbaImage::from_pixel(8, 8, Rgba([40 * i as u8, 0, 0, 255])).save(&path).unwrap();
+            path
+        })
+        .collect();
+
+    let settings = TrailSettings { history_length: 3, threads: 4, io_threads: 1, ..TrailSettings::default() };
+    let sink = DirectorySink::new(dir.join("output"), &source);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    let summary = pool.install(|| engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(())));
+    let written = std::fs::read_dir(dir.join("output")).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    assert_eq!(summary.frames_completed, 6);
+    assert_eq!(written, 6);
+    let timing = summary.timing;
+    assert_eq!(timing.io_threads, 1);
+    assert!(timing.io_seconds > 0.0);
+    assert!(timing.io_wait_seconds >= 0.0 && timing.compute_seconds >= 0.0);
+    assert!(timing.elapsed_seconds > 0.0);
+}
@@ -0,0 +1,56 @@
+//! Keeping a copy of the newest trail for displays that show one image
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use radar_echo_trails::engine::{self, DirectorySink, FrameSink, TrailSettings};
+
+mod common;
+use common::echo;
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn the_latest_image_is_the_newest_trail() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_latest_{}", std::process::id()));
+    let (source, output) = (dir.join("frames"), dir.join("output"));
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..4)
+        .map(|x| {
+            let path = source.join(format!("radar_20240601_12{:02}.png", x * 5));
+            echo(x, 4).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let sink = DirectorySink::new(&output, &source).with_latest("latest.png", true);
+    let settings = TrailSettings { history_length: 2, ..TrailSettings::default() };
+    assert!(engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(())).failures.is_empty());
+
+    let newest = output.join("radar_20240601_1215.png");
+    let latest = image::open(output.join("latest.png")).unwrap().to_rgba8();
+    assert!(latest == image::open(&newest).unwrap().to_rgba8());
+    let json = read_json(&output.join("latest.json"));
+    assert_eq!(json["frame"], 3);
+    assert_eq!(json["source"], "radar_20240601_1215.png");
+    assert_eq!(json["scan_time"], 1_717_244_100);
+    #[cfg(unix)]
+    assert_eq!(std::fs::read_link(output.join("latest")).unwrap(), newest.canonicalize().unwrap());
+
+    // An older frame finishing late leaves the newest in place
+    sink.write_frame(1, &paths[1], echo(0, 4)).unwrap();
+    assert_eq!(read_json(&output.join("latest.json"))["frame"], 3);
+    assert!(image::open(output.join("latest.png")).unwrap().to_rgba8() == latest);
+    assert!(std::fs::read_dir(&output).unwrap().all(|entry| !entry.unwrap().file_name().to_string_lossy().ends_with(".partial")));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn the_latest_image_needs_an_image_name() {
+    for (name, valid) in [(None, true), (Some("latest.png"), true), (Some("/srv/wall/now.jpg"), true), (Some("latest"), false)] {
+        let settings = TrailSettings { latest_image: name.map(PathBuf::from), ..TrailSettings::default() };
+        assert_eq!(settings.validate().is_ok(), valid, "{:?}", name);
+    }
+}
@@ -0,0 +1,74 @@
+//! Trails written as separate transparent layers
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, blend_over, compose_layers, compose_trail, DirectorySink, TrailSettings};
+use radar_echo_trails::settings::LayerOutput;
+
+fn frame(level: u8) -> RgbaImage {
+    RgbaImage::from_fn(6, 4, |x, _| if x >= 2 { Rgba([level, level, level, 255]) } else { Rgba([0, 0, 0, 0]) })
+}
+
+#[test]
+fn layers_flattened_over_the_background_make_the_trail() {
+    let settings = TrailSettings::default();
+    let (old, recent, current) = (frame(255), frame(180), RgbaImage::from_fn(6, 4, |x, _| {
+        if x >= 4 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) }
+    }));
+    let history = [(&old, 2.0 / 3.0), (&recent, 1.0 / 3.0)];
+    let trail = compose_trail(&current, &history, &settings);
+    let layers = compose_layers(&current, &history, &settings);
+
+    let background = Rgba([0, 0, 0, 255]);
+    for (x, y, pixel) in trail.enumerate_pixels() {
+        let history = *layers.history.get_pixel(x, y);
+        let current = *layers.current.get_pixel(x, y);
+        let flattened = blend_over(blend_over(background, history, 255), current, 255);
+        for channel in 0..4 {
+            assert!(flattened[channel].abs_diff(pixel[channel]) <= 2, "{:?} vs {:?} at {},{}", flattened, pixel, x, y);
+        }
+    }
+    // Transparent where nothing was drawn, and the current layer holds no history
+    assert_eq!(layers.history.get_pixel(0, 0)[3], 0);
+    assert_eq!(layers.current.get_pixel(2, 0)[3], 0);
+    assert_eq!(layers.current.get_pixel(5, 0), &Rgba([0, 255, 0, 255]));
+}
+
+fn render(dir: &std::path::Path, layers: LayerOutput) -> Vec<String> {
+    let source = dir.join("source");
+    let output = dir.join(format!("output_{:?}", layers));
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..2)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            frame(200).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { layers, ..TrailSettings::default() };
+    let sink = DirectorySink::new(&output, &source).with_layers(layers != LayerOutput::Off);
+    let summary = engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(()));
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    let mut names: Vec<String> = std::fs::read_dir(&output).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn writes_layers_beside_or_instead_of_the_trail() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_layers_{}", std::process::id()));
+    let alongside = render(&dir, LayerOutput::Alongside);
+    let only = render(&dir, LayerOutput::Only);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(alongside, [
+        "current_scan_0.png", "current_scan_1.png", "history_scan_0.png", "history_scan_1.png",
+        "scan_0.png", "scan_1.png",
+    ]);
+    assert_eq!(only, ["current_scan_0.png", "current_scan_1.png", "history_scan_0.png", "history_scan_1.png"]);
+}
@@ -0,0 +1,46 @@
+//! Settings files saved by the first release, in the flat layout used before schema version 2
+#![cfg(feature = "native")]
+
+use std::path::Path;
+
+use radar_echo_trails::config::{self, ConfigFormat, Settings};
+use radar_echo_trails::processing::{CollisionPolicy, ErrorPolicy};
+
+const LEGACY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/legacy_settings.json");
+
+#[test]
+fn a_legacy_flat_file_loads_with_every_default() {
+    let settings = config::import_settings(Path::new(LEGACY)).unwrap();
+
+    // The six fields of the first release, now in their groups; its limit was
+    // signed, and a negative one limited nothing
+    assert_eq!(settings.rendering.history_length, 5);
+    assert_eq!(settings.rendering.background_color, "#000000");
+    assert_eq!(settings.rendering.current_color, "#00ff00");
+    assert_eq!(settings.rendering.history_color, "#ff7f00");
+    assert_eq!(settings.performance.threads, 0);
+    assert_eq!(settings.limit, None);
+    assert!(!settings.preflight);
+    assert_eq!(settings.on_error, ErrorPolicy::SkipFrame);
+    assert!(!settings.ignore_manifests);
+    assert!(!settings.include_hidden);
+    assert!(!settings.output.force);
+    assert_eq!(settings.output.on_collision, CollisionPolicy::NewSuffix);
+    assert_eq!(settings.output.default_root, None);
+    assert_eq!(settings.output.name_template, "{folder}_trail_{history}");
+    assert!(settings.import_path_mappings.is_empty());
+    assert_eq!(settings.watch_folder, None);
+    assert_eq!(settings.watch_settle_secs, 60);
+    assert!(!settings.watch_auto_start);
+    assert!(!settings.reuse_previous_settings);
+    assert_eq!(settings.config_format, ConfigFormat::Json);
+
+    // Every field added since takes the default that matches how it was drawn then
+    let defaults = serde_json::to_value(Settings::default()).unwrap();
+    let loaded = serde_json::to_value(&settings).unwrap();
+    for (name, value) in defaults.as_object().unwrap() {
+        assert_eq!(loaded.get(name), Some(value), "{}", name);
+    }
+    assert_eq!(loaded, defaults);
+    assert!(settings.changed_fields(&Settings::default()).is_empty());
+}
@@ -0,0 +1,38 @@
+//! Products drawn on a white background
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{compose_trail, TrailSettings};
+use radar_echo_trails::light::{self, key_out, near_white_fraction};
+
+#[test]
+fn white_is_keyed_out_and_darker_pixels_are_stronger() {
+    let mut frame = RgbaImage::from_fn(4, 1, |x, _| match x {
+        0 => Rgba([255, 255, 255, 255]),
+        1 => Rgba([225, 230, 228, 255]),
+        2 => Rgba([128, 128, 128, 255]),
+        _ => Rgba([0, 0, 0, 255]),
+    });
+    assert_eq!(near_white_fraction(&frame), 0.5);
+    key_out(&mut frame);
+    assert_eq!(frame.get_pixel(0, 0)[3], 0);
+    assert_eq!(frame.get_pixel(1, 0)[3], 0, "light grey is within the tolerance");
+    assert_eq!(frame.get_pixel(2, 0), &Rgba([255, 255, 255, 127]));
+    assert_eq!(frame.get_pixel(3, 0), &Rgba([255, 255, 255, 255]));
+}
+
+#[test]
+fn a_keyed_white_background_stays_the_trail_background() {
+    // A mostly white product with one dark echo
+    let mut frame = RgbaImage::from_fn(10, 1, |x, _| if x == 4 { Rgba([20, 20, 20, 255]) } else { Rgba([255, 255, 255, 255]) });
+    assert!(near_white_fraction(&frame) > light::HINT_FRACTION);
+    key_out(&mut frame);
+    let settings = TrailSettings {
+        background_color: "#ffffff".to_string(),
+        current_color: "#1a1a1a".to_string(),
+        light_background: true,
+        ..TrailSettings::default()
+    };
+    let trail = compose_trail(&frame, &[], &settings);
+    assert_eq!(trail.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+    assert!(trail.get_pixel(4, 0)[0] < 60);
+}
@@ -0,0 +1,43 @@
+//! Smearing history frames along their motion
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{compose_trail, TrailSettings};
+use radar_echo_trails::motion::{smear, FlowField, MotionVector};
+
+fn dot(x: u32, y: u32) -> RgbaImage {
+    let mut frame = RgbaImage::from_pixel(16, 16, Rgba([0, 0, 0, 0]));
+    frame.put_pixel(x, y, Rgba([255, 128, 0, 255]));
+    frame
+}
+
+/// The same displacement in every cell of a 16 pixel frame on a grid of 8
+fn uniform(dx: f32, dy: f32) -> FlowField {
+    let vectors: Vec<MotionVector> = [(4, 4), (12, 4), (4, 12), (12, 12)]
+        .into_iter()
+        .map(|(x, y)| MotionVector { x, y, dx, dy, coherence: 1.0 })
+        .collect();
+    FlowField::new(&vectors, 16, 16, 8)
+}
+
+#[test]
+fn sub_pixel_motion_leaves_points_alone() {
+    assert!(smear(&dot(5, 5), &uniform(0.5, 0.0)) == dot(5, 5));
+}
+
+#[test]
+fn streaks_spread_opacity_along_the_displacement() {
+    let smeared = smear(&dot(2, 5), &uniform(4.0, 0.0));
+    for x in 2..=6 {
+        assert_eq!(smeared.get_pixel(x, 5), &Rgba([255, 128, 0, 51]), "x = {}", x);
+    }
+    assert_eq!(smeared.get_pixel(7, 5)[3], 0);
+    assert_eq!(smeared.get_pixel(1, 5)[3], 0);
+}
+
+#[test]
+fn still_history_is_drawn_as_without_blur() {
+    let frame = dot(5, 5);
+    let settings = TrailSettings { motion_blur: true, ..TrailSettings::default() };
+    let blurred = compose_trail(&frame, &[(&frame, 0.5)], &settings);
+    assert!(blurred == compose_trail(&frame, &[(&frame, 0.5)], &TrailSettings::default()));
+}
@@ -0,0 +1,96 @@
+//! Motion grids exported one file per frame
+#![cfg(feature = "native")]
+
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::motion::{estimate, estimate_grid, CellQuality};
+use radar_echo_trails::motion_export::{export_name, to_binary, to_json, FrameMotion};
+use radar_echo_trails::settings::{MotionExportFormat, MotionSettings};
+use serde_json::Value;
+
+/// A textured blob in the left half of a 32 by 16 frame, `shift` pixels right
+fn blob(shift: u32) -> RgbaImage {
+    RgbaImage::from_fn(32, 16, |x, y| match x.checked_sub(shift) {
+        Some(x) if (2..14).contains(&x) && (2..14).contains(&y) => {
+            let level = 80 + ((x * 37 + y * 53) % 170) as u8;
+            Rgba([level, level, level, 255])
+        }
+        _ => Rgba([0, 0, 0, 0]),
+    })
+}
+
+fn settings() -> MotionSettings {
+    MotionSettings { grid_spacing: 8, ..MotionSettings::default() }
+}
+
+#[test]
+fn the_grid_holds_the_same_vectors_as_the_arrows() {
+    let (previous, current) = (blob(0), blob(2));
+    let grid = estimate_grid(&previous, &current, &settings());
+    assert_eq!((grid.spacing, grid.columns, grid.rows), (8, 4, 2));
+    assert_eq!(grid.quality.len(), 8);
+    assert_eq!(grid.vectors, estimate(&previous, &current, &settings()));
+    let valid = grid.quality.iter().filter(|&&quality| quality == CellQuality::Valid).count();
+    assert_eq!(valid, grid.vectors.len());
+    // The right half never has an echo
+    assert_eq!(grid.quality[3], CellQuality::TooDim);
+    assert_eq!(grid.quality[7], CellQuality::TooDim);
+}
+
+#[test]
+fn json_carries_the_time_and_speeds() {
+    let grid = estimate_grid(&blob(0), &blob(2), &settings());
+    let motion = FrameMotion {
+        source: Path::new("dbz_202406011205.png"),
+        time: Some(1_717_243_500),
+        interval: Some(300),
+        grid: &grid,
+    };
+    let document: Value = serde_json::from_slice(&to_json(&motion, Some(0.5))).unwrap();
+    assert_eq!(document["time_utc"], "2024-06-01T12:05:00Z");
+    assert_eq!(document["interval_seconds"], 300);
+    assert_eq!(document["grid_spacing"], 8);
+    assert_eq!(document["u"].as_array().unwrap().len(), 8);
+    assert_eq!(document["u"][3], Value::Null);
+    assert_eq!(document["quality"][3], 1);
+
+    // Half a kilometre per pixel over five minutes is 6 km/h per pixel
+    let cell = grid.quality.iter().position(|&quality| quality == CellQuality::Valid).unwrap();
+    let u = document["u"][cell].as_f64().unwrap();
+    assert!((document["u_kmh"][cell].as_f64().unwrap() - u * 6.0).abs() < 1e-4);
+
+    // Without a pixel size, or without times, there are no speeds
+    let document: Value = serde_json::from_slice(&to_json(&motion, None)).unwrap();
+    assert!(document.get("u_kmh").is_none());
+    let untimed = FrameMotion { time: None, interval: None, ..motion };
+    let document: Value = serde_json::from_slice(&to_json(&untimed, Some(0.5))).unwrap();
+    assert!(document.get("u_kmh").is_none());
+    assert_eq!(document["time_utc"], Value::Null);
+}
+
+#[test]
+fn binary_arrays_follow_the_header_as_described() {
+    let grid = estimate_grid(&blob(0), &blob(2), &settings());
+    let motion = FrameMotion { source: Path::new("scan_0001.png"), time: None, interval: None, grid: &grid };
+    let bytes = to_binary(&motion, None);
+    let length = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+    let header: Value = serde_json::from_slice(&bytes[4..4 + length]).unwrap();
+    let data = &bytes[4 + length..];
+
+    let arrays = header["arrays"].as_array().unwrap();
+    let names: Vec<&str> = arrays.iter().map(|array| array["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["u", "v", "quality"]);
+    let u_offset = arrays[0]["offset"].as_u64().unwrap() as usize;
+    let u: Vec<f32> = data[u_offset..u_offset + 32].chunks(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    let quality_offset = arrays[2]["offset"].as_u64().unwrap() as usize;
+    assert_eq!(data.len(), quality_offset + 8);
+    for (cell, &quality) in data[quality_offset..].iter().enumerate() {
+        assert_eq!(quality == 0, !u[cell].is_nan(), "cell {}", cell);
+    }
+
+    assert_eq!(export_name(Path::new("in/scan_0001.png"), MotionExportFormat::Binary), Path::new("scan_0001.uv.bin"));
+    assert_eq!(export_name(Path::new("scan_0001.png"), MotionExportFormat::Json), Path::new("scan_0001.uv.json"));
+}
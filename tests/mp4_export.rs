@@ -0,0 +1,77 @@
+//! Streaming the trails to ffmpeg for MP4 output
+#![cfg(all(unix, feature = "native"))]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::mp4_export::{self, Mp4Sequence};
+use radar_echo_trails::sequence_writer::SequenceWriter;
+
+/// An executable script standing in for ffmpeg, answering `-version` and running `body` otherwise
+fn fake_ffmpeg(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n[ \"$1\" = -version ] && exit 0\nfor last; do :; done\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn solid(value: u8) -> RgbaImage {
+    RgbaImage::from_pixel(3, 2, Rgba([value, value, value, 255]))
+}
+
+#[test]
+fn frames_are_streamed_in_order_and_held_for_their_duration() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_mp4_export_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Writes the raw frames it is sent as the "video"
+    let ffmpeg = fake_ffmpeg(&dir, "ffmpeg-raw", "cat > \"$last\"");
+    let path = dir.join("trails.mp4");
+    // At 10 fps, 100 ms is one video frame and 300 ms three
+    let video = Mp4Sequence::create(&ffmpeg, &path, vec![100, 300, 100], 10.0, 23).unwrap();
+    let writer = SequenceWriter::spawn(5, video).unwrap();
+    for index in [7, 5, 6] {
+        writer.push(index, solid(index as u8 * 10));
+    }
+    assert_eq!(writer.finish().unwrap(), 3);
+
+    let raw = std::fs::read(&path).unwrap();
+    let frames: Vec<u8> = raw.chunks(3 * 2 * 4).map(|frame| frame[0]).collect();
+    let partial_left = dir.join(".trails.mp4.partial").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(frames, [50, 60, 60, 60, 70]);
+    assert!(!partial_left);
+}
+
+#[test]
+fn ffmpeg_failing_or_missing_is_reported() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_mp4_failure_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ffmpeg = fake_ffmpeg(&dir, "ffmpeg-broken", "cat > \"$last\"\necho \"Unknown encoder 'libx264'\" >&2\nexit 1");
+    let path = dir.join("trails.mp4");
+    let writer = SequenceWriter::spawn(0, Mp4Sequence::create(&ffmpeg, &path, vec![100], 10.0, 23).unwrap()).unwrap();
+    writer.push(0, solid(1));
+    let error = format!("{:#}", writer.finish().unwrap_err());
+    let missing = Mp4Sequence::create(&dir.join("no-ffmpeg-here"), &path, vec![100], 10.0, 23).err().map(|e| format!("{:#}", e));
+    let written = path.exists() || dir.join(".trails.mp4.partial").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(error.contains("ffmpeg failed") && error.contains("Unknown encoder 'libx264'"), "{}", error);
+    assert!(!written, "no truncated video is left behind");
+    assert!(missing.is_some_and(|e| e.contains("was not found")));
+    assert!(mp4_export::check_ffmpeg("radar-echo-trails-no-such-program".as_ref()).is_err());
+}
+
+#[test]
+fn video_only_output_is_told_apart_from_saved_trails() {
+    use radar_echo_trails::engine::TrailSettings;
+    let trails = TrailSettings::default();
+    let video = TrailSettings { mp4: Some("trails.mp4".into()), ..trails.clone() };
+    let kept = TrailSettings { keep_frames: true, ..video.clone() };
+    assert!(trails.writes_trails() && kept.writes_trails() && !video.writes_trails());
+    assert_eq!(kept.output_hash(), trails.output_hash());
+    assert_ne!(video.output_hash(), trails.output_hash());
+
+    assert!(video.validate().is_ok());
+    assert!(TrailSettings { mp4: Some("trails.mov".into()), ..trails.clone() }.validate().is_err());
+    assert!(TrailSettings { mp4_crf: 52, ..video }.validate().is_err());
+}
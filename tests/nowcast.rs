@@ -0,0 +1,96 @@
+//! Forecast frames after a sequence
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, TrailSettings};
+use radar_echo_trails::nowcast::{advect, forecast_name, median_interval, scan_time};
+use radar_echo_trails::settings::NowcastSettings;
+
+#[test]
+fn reads_scan_times_from_file_names() {
+    assert_eq!(scan_time(Path::new("radar_20240601_1205.png")), Some(1_717_243_500));
+    assert_eq!(scan_time(Path::new("KTLX20240601_120530_V06.png")), Some(1_717_243_530));
+    assert_eq!(scan_time(Path::new("frame_0001.png")), None);
+}
+
+#[test]
+fn median_interval_needs_increasing_times() {
+    let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let regular = paths(&["r_202406011200.png", "r_202406011205.png", "r_202406011210.png", "r_202406011220.png"]);
+    assert_eq!(median_interval(&regular), Some(300));
+    assert_eq!(median_interval(&paths(&["r_202406011205.png", "r_202406011200.png"])), None);
+    assert_eq!(median_interval(&paths(&["frame_1.png", "frame_2.png"])), None);
+}
+
+#[test]
+fn names_forecasts_by_lead_time() {
+    assert_eq!(forecast_name(1, Some(300)), "forecast_+05min.png");
+    assert_eq!(forecast_name(6, Some(300)), "forecast_+30min.png");
+    assert_eq!(forecast_name(2, None), "forecast_+2.png");
+}
+
+#[test]
+fn advects_whole_and_fractional_pixels() {
+    let mut frame = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 0]));
+    frame.put_pixel(2, 3, Rgba([200, 100, 50, 255]));
+
+    let moved = advect(&frame, (3.0, -1.0));
+    assert_eq!(moved.get_pixel(5, 2), &Rgba([200, 100, 50, 255]));
+    assert_eq!(moved.get_pixel(2, 3)[3], 0);
+
+    let halfway = advect(&frame, (0.5, 0.0));
+    assert_eq!(halfway.get_pixel(2, 3), &Rgba([200, 100, 50, 128]));
+    assert_eq!(halfway.get_pixel(3, 3), &Rgba([200, 100, 50, 128]));
+}
+
+/// Render a blob moving right by two pixels a frame, with and without forecasts
+fn render(dir: &Path, forecasts: u32) -> Vec<(usize, String, RgbaImage)> {
+    let paths: Vec<PathBuf> = (0..4u32)
+        .map(|i| {
+            let mut frame = RgbaImage::from_pixel(64, 64, Rgba([0, 0, 0, 0]));
+            for y in 24..40 {
+                for x in 10 + 2 * i..26 + 2 * i {
+                    frame.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                }
+            }
+            let path = dir.join(format!("radar_20240601_12{:02}.png", 5 * i));
+            frame.save(&path).unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings {
+        history_length: 2,
+        nowcast: NowcastSettings { frames: forecasts },
+        ..TrailSettings::default()
+    };
+    let written = Mutex::new(Vec::new());
+    let sink = |index: usize, source: &Path, frame: RgbaImage| {
+        let name = source.file_name().unwrap().to_string_lossy().into_owned();
+        written.lock().unwrap().push((index, name, frame));
+        Ok(())
+    };
+    let summary = engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(()));
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    let mut written = written.into_inner().unwrap();
+    written.sort_by_key(|(index, _, _)| *index);
+    written
+}
+
+#[test]
+fn forecasts_follow_the_sequence_without_touching_it() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_nowcast_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let plain = render(&dir, 0);
+    let forecast = render(&dir, 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let names: Vec<&str> = forecast.iter().map(|(_, name, _)| name.as_str()).collect();
+    assert_eq!(names[4..], ["forecast_+05min.png", "forecast_+10min.png"]);
+    for (real, with_forecasts) in plain.iter().zip(&forecast) {
+        assert!(real.2 == with_forecasts.2, "{} changed", real.1);
+    }
+}
@@ -0,0 +1,48 @@
+//! Drawing frames in their own colors instead of tinting them
+#![cfg(feature = "native")]
+
+use image::Rgba;
+use radar_echo_trails::engine::{compose_layers, compose_trail, history_age, TrailSettings};
+use radar_echo_trails::palette::Palette;
+use radar_echo_trails::settings::{CurrentStyle, HistoryStyle};
+
+mod common;
+use common::colored_echo;
+
+#[test]
+fn a_colored_pixel_survives_compositing_unchanged() {
+    let original = TrailSettings { current_style: CurrentStyle::Original, history_style: HistoryStyle::Original, ..TrailSettings::default() };
+    let past = colored_echo(0, (4, 1), Rgba([255, 160, 0, 255]));
+    let history = [(&past, history_age(1, 1))];
+    let trail = compose_trail(&colored_echo(3, (4, 1), Rgba([255, 160, 0, 255])), &history, &original);
+    assert_eq!(trail.get_pixel(3, 0), &Rgba([255, 160, 0, 255]));
+
+    // The history keeps its color and still fades
+    let layers = compose_layers(&colored_echo(3, (4, 1), Rgba([255, 160, 0, 255])), &history, &original);
+    let ghost = layers.history.get_pixel(0, 0);
+    assert_eq!(&ghost.0[..3], &[255, 160, 0]);
+    assert!(ghost[3] > 0 && ghost[3] < 255);
+    assert_eq!(layers.current.get_pixel(3, 0), &Rgba([255, 160, 0, 255]));
+}
+
+#[test]
+fn current_and_history_are_chosen_apart() {
+    let past = colored_echo(0, (4, 1), Rgba([255, 160, 0, 255]));
+    let history = [(&past, history_age(1, 1))];
+    let grey_history = TrailSettings { current_style: CurrentStyle::Original, history_color: "#808080".to_string(), ..TrailSettings::default() };
+    let layers = compose_layers(&colored_echo(3, (4, 1), Rgba([255, 160, 0, 255])), &history, &grey_history);
+    assert_eq!(layers.current.get_pixel(3, 0), &Rgba([255, 160, 0, 255]));
+    let ghost = layers.history.get_pixel(0, 0);
+    assert_eq!(ghost[0], ghost[2], "the history is tinted grey");
+
+    let tinted_current = TrailSettings { history_style: HistoryStyle::Original, ..TrailSettings::default() };
+    let layers = compose_layers(&colored_echo(3, (4, 1), Rgba([255, 160, 0, 255])), &history, &tinted_current);
+    assert_eq!(&layers.history.get_pixel(0, 0).0[..3], &[255, 160, 0]);
+    assert_ne!(layers.current.get_pixel(3, 0), &Rgba([255, 160, 0, 255]));
+
+    // Neither can be foretold by a palette, and both change the output
+    assert!(Palette::for_settings(&grey_history).is_err());
+    assert!(Palette::for_settings(&tinted_current).is_err());
+    assert_ne!(grey_history.output_hash(), TrailSettings { current_style: CurrentStyle::Scaled, ..grey_history.clone() }.output_hash());
+    assert_ne!(tinted_current.output_hash(), TrailSettings::default().output_hash());
+}
@@ -0,0 +1,90 @@
+//! Trails written as indexed-color PNGs
+#![cfg(feature = "native")]
+
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{compose_trail, history_age, DirectorySink, FrameSink, TrailSettings};
+use radar_echo_trails::palette::{Palette, MAX_COLORS};
+use radar_echo_trails::settings::SecondarySettings;
+
+/// A radar-like frame: a disc of echo whose grey level falls off from the centre
+fn disc(center: (f32, f32)) -> RgbaImage {
+    RgbaImage::from_fn(48, 32, |x, y| {
+        let distance = ((x as f32 - center.0).powi(2) + (y as f32 - center.1).powi(2)).sqrt();
+        match distance {
+            d if d < 10.0 => {
+                let level = (255.0 - d * 18.0) as u8;
+                Rgba([level, level, level, 255])
+            }
+            _ => Rgba([0, 0, 0, 0]),
+        }
+    })
+}
+
+fn trail(settings: &TrailSettings) -> RgbaImage {
+    let frames: Vec<RgbaImage> = (0..4).map(|i| disc((10.0 + i as f32 * 8.0, 16.0))).collect();
+    let history: Vec<(&RgbaImage, f32)> = frames[..3].iter()
+        .enumerate()
+        .map(|(i, frame)| (frame, history_age(3 - i, 3)))
+        .collect();
+    compose_trail(&frames[3], &history, settings)
+}
+
+#[test]
+fn palette_reproduces_the_trail_within_tolerance() {
+    let settings = TrailSettings { history_length: 3, ..TrailSettings::default() };
+    let palette = Palette::for_settings(&settings).unwrap();
+    assert!(palette.colors().len() <= MAX_COLORS);
+
+    let trail = trail(&settings);
+    let quantized = palette.quantize(&trail);
+    for (x, y, pixel) in trail.enumerate_pixels() {
+        let written = quantized.get_pixel(x, y);
+        assert!((0..3).all(|channel| pixel[channel].abs_diff(written[channel]) <= 8), "{:?} vs {:?} at {},{}", pixel, written, x, y);
+    }
+    // The background and the brightest current pixel are exact
+    assert_eq!(quantized.get_pixel(0, 0), trail.get_pixel(0, 0));
+    assert_eq!(quantized.get_pixel(34, 16), trail.get_pixel(34, 16));
+}
+
+#[test]
+fn encodes_an_indexed_png_that_reads_back_as_quantized() {
+    let settings = TrailSettings { history_length: 3, ..TrailSettings::default() };
+    let palette = Palette::for_settings(&settings).unwrap();
+    let trail = trail(&settings);
+    let mut bytes = Vec::new();
+    palette.encode(&trail, &mut bytes).unwrap();
+
+    let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+    assert_eq!(reader.info().color_type, png::ColorType::Indexed);
+    let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+    assert_eq!(decoded, palette.quantize(&trail));
+
+    let mut true_color = Vec::new();
+    trail.write_to(&mut Cursor::new(&mut true_color), image::ImageFormat::Png).unwrap();
+    assert!(bytes.len() < true_color.len(), "{} vs {} bytes", bytes.len(), true_color.len());
+}
+
+#[test]
+fn secondary_folders_and_custom_shaders_are_not_palettised() {
+    let settings = TrailSettings {
+        secondary: SecondarySettings { folder: Some(PathBuf::from("velocity")), ..SecondarySettings::default() },
+        ..TrailSettings::default()
+    };
+    assert!(Palette::for_settings(&settings).is_err());
+}
+
+#[test]
+fn directory_sink_writes_png_trails_with_the_palette() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_palette_{}", std::process::id()));
+    let settings = TrailSettings { history_length: 3, ..TrailSettings::default() };
+    let sink = DirectorySink::new(&dir, &dir).with_palette(Palette::for_settings(&settings).unwrap());
+    sink.write_frame(0, &dir.join("scan_0.png"), trail(&settings)).unwrap();
+    let bytes = std::fs::read(dir.join("scan_0.png")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+    assert_eq!(reader.info().color_type, png::ColorType::Indexed);
+}
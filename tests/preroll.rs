@@ -0,0 +1,81 @@
+//! History for the first frames of a sequence from before it starts
+#![cfg(feature = "native")]
+
+use std::path::PathBuf;
+
+use image::Rgba;
+use radar_echo_trails::engine::{composite_frame, preroll_source, TrailCompositor, TrailSettings};
+use radar_echo_trails::processing::Preroll;
+
+mod common;
+use common::echo;
+
+/// Frames of an echo moving right one column a frame, saved to a fresh folder
+fn sequence(name: &str) -> (PathBuf, Vec<PathBuf>) {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_preroll_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths = (0..5)
+        .map(|x| {
+            let path = dir.join(format!("frame{}.png", x));
+            echo(x, 5).save(&path).unwrap();
+            path
+        })
+        .collect();
+    (dir, paths)
+}
+
+fn is_background(pixel: &Rgba<u8>) -> bool {
+    pixel == &Rgba([0, 0, 0, 255])
+}
+
+#[test]
+fn frames_before_the_start_come_from_the_sequence() {
+    assert_eq!(preroll_source(Preroll::None, 1, 5), None);
+    assert_eq!((1..=3).map(|back| preroll_source(Preroll::Hold, back, 5)).collect::<Vec<_>>(), [Some(0); 3]);
+    let mirror: Vec<_> = (1..=10).filter_map(|back| preroll_source(Preroll::Mirror, back, 4)).collect();
+    assert_eq!(mirror, [1, 2, 3, 2, 1, 0, 1, 2, 3, 2]);
+    let wrap: Vec<_> = (1..=6).filter_map(|back| preroll_source(Preroll::Wrap, back, 4)).collect();
+    assert_eq!(wrap, [3, 2, 1, 0, 3, 2]);
+    assert_eq!(preroll_source(Preroll::Mirror, 3, 1), Some(0));
+    assert_eq!(preroll_source(Preroll::Wrap, 1, 0), None);
+}
+
+#[test]
+fn the_first_frame_gets_a_full_trail() {
+    let (dir, paths) = sequence("first");
+    let render = |preroll| composite_frame(&paths, 0, &TrailSettings { history_length: 2, preroll, ..TrailSettings::default() }).unwrap();
+    let plain = render(Preroll::None);
+    let held = render(Preroll::Hold);
+    let mirrored = render(Preroll::Mirror);
+    let wrapped = render(Preroll::Wrap);
+    let later = composite_frame(&paths, 2, &TrailSettings { history_length: 2, preroll: Preroll::Wrap, ..TrailSettings::default() }).unwrap();
+    let unchanged = composite_frame(&paths, 2, &TrailSettings { history_length: 2, ..TrailSettings::default() }).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!((1..5).all(|x| is_background(plain.get_pixel(x, 0))));
+    // Holding draws only the first frame again, which the current frame covers
+    assert!(held == plain);
+    // Mirrored, the echo was at columns 2 and 1 before
+    assert!(!is_background(mirrored.get_pixel(1, 0)) && !is_background(mirrored.get_pixel(2, 0)));
+    assert!(is_background(mirrored.get_pixel(3, 0)));
+    // Wrapped, the last two frames come before the first
+    assert!(!is_background(wrapped.get_pixel(3, 0)) && !is_background(wrapped.get_pixel(4, 0)));
+    assert_eq!(wrapped.get_pixel(3, 0), mirrored.get_pixel(2, 0), "both two frames back");
+    // Frames with a full history of their own are drawn as before
+    assert!(later == unchanged);
+}
+
+#[test]
+fn the_compositor_holds_the_first_frame_and_takes_others_given() {
+    let settings = TrailSettings { history_length: 2, preroll: Preroll::Hold, ..TrailSettings::default() };
+    let mut held = TrailCompositor::new(settings.clone());
+    held.push(echo(0, 5));
+    assert_eq!(held.depth(), 2);
+
+    let mut wrapped = TrailCompositor::new(TrailSettings { preroll: Preroll::Wrap, ..settings });
+    wrapped.preroll([echo(2, 5), echo(3, 5), echo(4, 5)]);
+    assert_eq!(wrapped.depth(), 2);
+    let trail = wrapped.push(echo(0, 5));
+    assert!(!is_background(trail.get_pixel(3, 0)) && !is_background(trail.get_pixel(4, 0)));
+    assert!(is_background(trail.get_pixel(2, 0)));
+}
@@ -0,0 +1,64 @@
+//! Named presets, kept apart from the settings file, and the preset the settings came from
+#![cfg(feature = "native")]
+
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use radar_echo_trails::config::{self, Settings};
+
+/// The config directory, emptied, held for the length of one test
+fn config_dir() -> (MutexGuard<'static, ()>, PathBuf) {
+    static LOCK: Mutex<()> = Mutex::new(());
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    let guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let home = DIR.get_or_init(|| {
+        let home = std::env::temp_dir().join(format!("radar_echo_trails_presets_{}", std::process::id()));
+        // SAFETY: set once, before any test of this binary reads the environment
+        unsafe { std::env::set_var("XDG_CONFIG_HOME", &home) };
+        home
+    });
+    let dir = config::settings_dir().unwrap();
+    assert!(dir.starts_with(home), "{} is not a test directory", dir.display());
+    let _ = std::fs::remove_dir_all(&dir);
+    (guard, dir)
+}
+
+#[test]
+fn presets_survive_saving_the_settings_and_leave_out_the_active_preset() {
+    let (_guard, _dir) = config_dir();
+    let mut velocity = Settings { active_preset: Some("Print Light".to_string()), ..Settings::default() };
+    velocity.rendering.history_length = 9;
+    velocity.rendering.current_color = "#ff0000".to_string();
+    config::save_preset("Velocity", &velocity).unwrap();
+
+    let loaded = config::load_preset("Velocity").unwrap();
+    assert_eq!(loaded.active_preset, None);
+    let json = |settings: Settings| serde_json::to_value(settings).unwrap();
+    assert_eq!(json(loaded.clone()), json(Settings { active_preset: None, ..velocity }));
+
+    // The settings remember the preset they were set from, and saving them keeps the presets
+    config::save_settings(&Settings { active_preset: Some("Velocity".to_string()), ..loaded }).unwrap();
+    assert_eq!(config::load_settings().unwrap().active_preset.as_deref(), Some("Velocity"));
+    let presets = config::list_presets();
+    assert_eq!(presets.last().map(String::as_str), Some("Velocity"));
+    assert!(config::is_builtin_preset(&presets[0]));
+
+    assert!(config::delete_preset("Print Light").is_err());
+    config::delete_preset("Velocity").unwrap();
+    assert!(!config::list_presets().contains(&"Velocity".to_string()));
+    assert!(config::load_preset("Velocity").is_err());
+}
+
+#[test]
+fn settings_saved_before_presets_were_remembered_still_load() {
+    let (_guard, dir) = config_dir();
+    let mut older = serde_json::to_value(Settings::default()).unwrap();
+    older.as_object_mut().unwrap().remove("active_preset").unwrap();
+    older["rendering"]["history_length"] = 6.into();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("settings.json"), older.to_string()).unwrap();
+
+    let settings = config::load_settings().unwrap();
+    assert_eq!(settings.active_preset, None);
+    assert_eq!(settings.rendering.history_length, 6);
+}
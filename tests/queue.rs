@@ -0,0 +1,113 @@
+//! Adding folders to the queue without processing any frame twice
+#![cfg(feature = "native")]
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::queue::{self, AddFolderError, JobQueue, Overlap, ScanOptions};
+
+/// A fresh folder under the temp directory holding `frames` frames
+fn folder(name: &str, frames: usize) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_queue_{}_{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    write_frames(&dir, frames);
+    dir
+}
+
+fn write_frames(dir: &Path, frames: usize) {
+    std::fs::create_dir_all(dir).unwrap();
+    for i in 0..frames {
+        RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 255])).save(dir.join(format!("scan_{:03}.png", i))).unwrap();
+    }
+}
+
+fn recursive() -> ScanOptions {
+    ScanOptions { recursive: true, ..ScanOptions::default() }
+}
+
+#[test]
+fn ancestors_and_descendants_of_recursive_entries_need_the_override() {
+    let root = folder("overlap", 1);
+    let day = root.join("15");
+    write_frames(&day, 2);
+
+    let mut queue = JobQueue::default();
+    assert_eq!(queue.add_folder(&root, recursive(), false).unwrap(), 0);
+    assert!(matches!(queue.add_folder(&day, ScanOptions::default(), false), Err(AddFolderError::Overlaps { existing: 0, overlap: Overlap::Descendant })));
+    assert!(matches!(queue.add_folder(&root, recursive(), true), Err(AddFolderError::Duplicate { existing: 0 })));
+    assert_eq!(queue.add_folder(&day, ScanOptions::default(), true).unwrap(), 1);
+
+    // The other way round: a recursive parent added after its child
+    let mut queue = JobQueue::default();
+    queue.add_folder(&day, ScanOptions::default(), false).unwrap();
+    assert!(matches!(queue.add_folder(&root, recursive(), false), Err(AddFolderError::Overlaps { existing: 0, overlap: Overlap::Ancestor })));
+    // Without recursion the parent doesn't reach the child's frames
+    assert_eq!(queue.add_folder(&root, ScanOptions::default(), false).unwrap(), 1);
+    assert_eq!(queue.folders()[1].file_count, 1);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn a_symlinked_alias_is_the_same_folder() {
+    let real = folder("alias", 2);
+    let alias = real.with_file_name(format!("{}_link", real.file_name().unwrap().to_str().unwrap()));
+    let _ = std::fs::remove_file(&alias);
+    std::os::unix::fs::symlink(&real, &alias).unwrap();
+
+    let mut queue = JobQueue::default();
+    queue.add_folder(&real, ScanOptions::default(), false).unwrap();
+    assert!(matches!(queue.add_folder(&alias, ScanOptions::default(), false), Err(AddFolderError::Duplicate { existing: 0 })));
+    // Even with the override, the same folder is never queued twice
+    assert!(matches!(queue.add_folder(&alias, recursive(), true), Err(AddFolderError::Duplicate { existing: 0 })));
+    assert_eq!(queue.folders().len(), 1);
+
+    // A subfolder reached through the alias still overlaps the real entry
+    let mut queue = JobQueue::default();
+    write_frames(&real.join("15"), 1);
+    queue.add_folder(&real, recursive(), false).unwrap();
+    assert!(matches!(queue.add_folder(&alias.join("15"), ScanOptions::default(), false), Err(AddFolderError::Overlaps { overlap: Overlap::Descendant, .. })));
+    std::fs::remove_file(&alias).unwrap();
+    std::fs::remove_dir_all(&real).unwrap();
+}
+
+#[test]
+fn a_non_recursive_child_takes_only_its_own_frames_from_a_recursive_parent() {
+    let root = folder("plan", 1);
+    let day = root.join("15");
+    write_frames(&day, 2);
+    write_frames(&day.join("radar_b"), 3);
+
+    let mut queue = JobQueue::default();
+    queue.add_folder(&root, recursive(), false).unwrap();
+    queue.add_folder(&day, ScanOptions::default(), true).unwrap();
+    let plan = queue::plan(queue.folders());
+    let parent = &plan.folders[0].files;
+    assert_eq!(parent.len(), 4, "{:?}", parent);
+    assert!(parent.iter().all(|f| f.parent() != Some(day.as_path())));
+    assert_eq!(plan.folders[1].files.len(), 2);
+
+    // A recursive child takes its whole subtree
+    let mut queue = JobQueue::default();
+    queue.add_folder(&root, recursive(), false).unwrap();
+    queue.add_folder(&day, recursive(), true).unwrap();
+    let plan = queue::plan(queue.folders());
+    assert_eq!(plan.folders[0].files.len(), 1);
+    assert_eq!(plan.folders[1].files.len(), 5);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn empty_files_are_skipped_even_with_hidden_files_included() {
+    let dir = folder("empty", 1);
+    std::fs::File::create(dir.join("scan_001.png")).unwrap();
+    std::fs::write(dir.join(".scan_002.png"), std::fs::read(dir.join("scan_000.png")).unwrap()).unwrap();
+
+    let hidden = ScanOptions { include_hidden: true, ..ScanOptions::default() };
+    let scan = queue::scan_folder(&dir, &hidden).unwrap();
+    let names: Vec<_> = scan.files.iter().map(|f| f.file_name().unwrap().to_str().unwrap()).collect();
+    assert_eq!(names, [".scan_002.png", "scan_000.png"]);
+    assert!(scan.skipped.iter().any(|(path, reason)| path.ends_with("scan_001.png") && reason.to_string() == "empty file"));
+    assert_eq!(queue::scan_folder(&dir, &ScanOptions::default()).unwrap().files.len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
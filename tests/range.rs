@@ -0,0 +1,107 @@
+//! Brightening echo with its distance from the radar
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, DirectorySink, TrailCompositor, TrailSettings};
+use radar_echo_trails::processing::{parse_radar_center, RangeCorrection};
+use radar_echo_trails::range;
+
+fn correction(gain_per_100km: f32) -> RangeCorrection {
+    RangeCorrection { gain_per_100km, center: Some((0, 0)), pixel_size_km: 2.0, max_gain: 2.0, ..RangeCorrection::default() }
+}
+
+#[test]
+fn the_gain_grows_with_range_up_to_the_cap() {
+    let correction = correction(0.5);
+    assert_eq!(range::gain(&correction, (200, 1), 0, 0), 1.0);
+    // 50 pixels of 2 km are 100 km
+    assert!((range::gain(&correction, (200, 1), 50, 0) - 1.5).abs() < 1e-6);
+    assert!((range::gain(&correction, (200, 200), 30, 40) - 1.5).abs() < 1e-6);
+    assert_eq!(range::gain(&correction, (200, 1), 199, 0), 2.0);
+    // Without a site set, the radar is in the middle of the frame
+    assert_eq!(range::gain(&RangeCorrection { center: None, ..correction }, (200, 100), 100, 50), 1.0);
+}
+
+#[test]
+fn only_echo_is_brightened() {
+    let mut frame = RgbaImage::from_fn(60, 1, |x, _| match x {
+        50 => Rgba([100, 60, 20, 200]),
+        59 => Rgba([200, 200, 200, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    });
+    range::correct(&mut frame, &correction(0.5));
+    assert_eq!(frame.get_pixel(50, 0), &Rgba([150, 90, 30, 255]));
+    assert_eq!(frame.get_pixel(59, 0), &Rgba([255, 255, 255, 255]));
+    assert_eq!(frame.get_pixel(10, 0), &Rgba([0, 0, 0, 0]));
+}
+
+#[test]
+fn current_and_history_are_corrected_before_the_echo_rule() {
+    // A faint echo far out, below the rule until it is brightened
+    let faint = |x: u32| RgbaImage::from_fn(100, 1, |at, _| if at == x { Rgba([40, 40, 40, 255]) } else { Rgba([0, 0, 0, 0]) });
+    let settings = TrailSettings { history_length: 1, echo_rule: "luma>50".to_string(), ..TrailSettings::default() };
+    let corrected = TrailSettings { range_correction: correction(0.5), ..settings.clone() };
+    let background = Rgba([0, 0, 0, 255]);
+
+    let mut plain = TrailCompositor::new(settings);
+    plain.push(faint(80));
+    let trail = plain.push(faint(90));
+    assert!(trail.pixels().all(|pixel| pixel == &background));
+
+    let mut boosted = TrailCompositor::new(corrected);
+    boosted.push(faint(80));
+    let trail = boosted.push(faint(90));
+    assert_ne!(trail.get_pixel(80, 0), &background, "history is brightened");
+    assert_ne!(trail.get_pixel(90, 0), &background, "the current frame is brightened");
+}
+
+#[test]
+fn the_gain_field_is_written_once_with_the_site_marked() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_range_{}", std::process::id()));
+    let (source, output) = (dir.join("frames"), dir.join("output"));
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..2)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            RgbaImage::from_pixel(40, 30, Rgba([100, 100, 100, 255])).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let correction = RangeCorrection { center: Some((10, 20)), emit_weights: true, ..correction(2.0) };
+    let settings = TrailSettings { range_correction: correction, ..TrailSettings::default() };
+    let sink = DirectorySink::new(&output, &source);
+    assert!(engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(())).failures.is_empty());
+
+    let field = image::open(output.join(range::RANGE_GAIN_NAME)).unwrap().to_rgba8();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(field.dimensions(), (40, 30));
+    assert_eq!(field.get_pixel(10, 20), &Rgba([255, 0, 0, 255]));
+    // 5 pixels out, 10 km, is a fifth of the way to the cap
+    assert_eq!(field.get_pixel(13, 24), &Rgba([51, 51, 51, 255]));
+    assert_eq!(field.get_pixel(25, 0), &Rgba([255, 255, 255, 255]), "the cap is white");
+}
+
+#[test]
+fn rejects_gains_out_of_range_and_unreadable_sites() {
+    for (range_correction, valid) in [
+        (RangeCorrection::default(), true),
+        (correction(0.3), true),
+        (correction(-0.1), false),
+        (RangeCorrection { max_gain: 0.5, ..correction(0.3) }, false),
+        (RangeCorrection { pixel_size_km: 0.0, ..correction(0.3) }, false),
+    ] {
+        let settings = TrailSettings { range_correction: range_correction.clone(), ..TrailSettings::default() };
+        assert_eq!(settings.validate().is_ok(), valid, "{:?}", range_correction);
+    }
+    assert_eq!(parse_radar_center(" 512, 480 "), Ok(Some((512, 480))));
+    assert_eq!(parse_radar_center(""), Ok(None));
+    assert!(parse_radar_center("512").is_err());
+    assert!(parse_radar_center("a,1").is_err());
+
+    let unchanged = TrailSettings::default();
+    assert_eq!(TrailSettings { range_correction: RangeCorrection { pixel_size_km: 3.0, ..RangeCorrection::default() }, ..unchanged.clone() }.output_hash(), unchanged.output_hash());
+    assert_ne!(TrailSettings { range_correction: correction(0.3), ..unchanged.clone() }.output_hash(), unchanged.output_hash());
+}
@@ -0,0 +1,106 @@
+//! Naming trails by their place in the rendered sequence
+#![cfg(feature = "native")]
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+use radar_echo_trails::renumber::{self, MapEntry};
+
+mod common;
+use common::run;
+
+fn add_frames(source: &Path, minutes: std::ops::Range<u32>) -> Vec<PathBuf> {
+    minutes
+        .map(|minute| {
+            let path = source.join(format!("radar_20240601_12{:02}.png", minute));
+            RgbaImage::from_pixel(4, 1, Rgba([0, 200, 0, 255])).save(&path).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn read_map(output: &Path) -> Vec<MapEntry> {
+    renumber::parse_csv(&std::fs::read_to_string(output.join(renumber::INDEX_MAP_NAME)).unwrap()).unwrap()
+}
+
+fn trails(output: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(output).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".png"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn names_are_padded_to_fit_the_frame_count() {
+    assert_eq!([1, 9, 10, 11, 100, 101, 1000].map(renumber::auto_width), [1, 1, 1, 2, 2, 3, 3]);
+    assert_eq!(renumber::name(Path::new("in/scan_a.tif"), "frame_", 7, 4), PathBuf::from("in/frame_0007.tif"));
+    assert_eq!(renumber::name(Path::new("in/scan_a"), "", 12, 1), PathBuf::from("in/12"));
+
+    let entries = vec![
+        MapEntry { index: 0, output: "frame_00.png".into(), time: Some(1_717_243_200), source: "radar_20240601_1200.png".into() },
+        MapEntry { index: 1, output: "frame_01.png".into(), time: None, source: "old, unnamed.png".into() },
+    ];
+    let csv = renumber::to_csv(&entries);
+    assert!(csv.contains("0,frame_00.png,20240601120000,radar_20240601_1200.png\n"), "{}", csv);
+    assert_eq!(renumber::parse_csv(&csv), Some(entries.clone()));
+    assert_eq!(renumber::width_of(&entries, "frame_"), Some(2));
+    assert_eq!(renumber::parse_csv("index,output,time,source\nx,a.png,,b.png\n"), None);
+}
+
+#[test]
+fn numbering_follows_the_selected_frames_and_carries_on_when_appending() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_renumber_{}", std::process::id()));
+    let source = dir.join("frames");
+    std::fs::create_dir_all(&source).unwrap();
+    let paths = add_frames(&source, 0..9);
+    let settings = ProcessingSettings {
+        history_length: 2,
+        offset: 1,
+        stride: 2,
+        append: true,
+        renumber: Some("frame_".to_string()),
+        ..ProcessingSettings::default()
+    };
+    let output = processing::output_dir_for(&source, &settings);
+
+    // Frames 1, 3, 5 and 7 are selected, and numbered 0 to 3
+    assert!(matches!(run(&source, &settings), ProgressUpdate::FolderCompleted { .. }));
+    assert_eq!(trails(&output), ["frame_0.png", "frame_1.png", "frame_2.png", "frame_3.png"]);
+    let map = read_map(&output);
+    assert_eq!(map.iter().map(|entry| entry.source.clone()).collect::<Vec<_>>(), [1, 3, 5, 7].map(|i| PathBuf::from(paths[i].file_name().unwrap())));
+    assert_eq!(map[1].time, Some(1_717_243_380));
+
+    // Appended frames carry on from the map, at its width
+    add_frames(&source, 9..30);
+    assert!(matches!(run(&source, &settings), ProgressUpdate::FolderCompleted { .. }));
+    let map = read_map(&output);
+    assert_eq!(map.len(), 15);
+    assert_eq!(map.iter().map(|entry| entry.index).collect::<Vec<_>>(), (0..15).collect::<Vec<_>>());
+    assert_eq!(map[4].output, PathBuf::from("frame_4.png"));
+    assert_eq!(map[4].source, PathBuf::from("radar_20240601_1209.png"));
+    assert_eq!(map[14].output, PathBuf::from("frame_14.png"));
+    assert!(output.join("frame_14.png").is_file());
+
+    // An append has to find where the numbering stopped
+    std::fs::remove_file(output.join(renumber::INDEX_MAP_NAME)).unwrap();
+    add_frames(&source, 30..32);
+    assert!(matches!(run(&source, &settings), ProgressUpdate::FolderError { .. }));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_prefixes_that_leave_the_folder_and_wide_indices() {
+    let renumbered = |prefix: &str, width| ProcessingSettings {
+        renumber: Some(prefix.to_string()),
+        renumber_width: width,
+        ..ProcessingSettings::default()
+    };
+    for (prefix, width, valid) in [("", 0, true), ("frame_", 12, true), ("a/b", 0, false), ("a,b", 0, false), ("frame_", 13, false)] {
+        assert_eq!(renumbered(prefix, width).validate().is_ok(), valid, "{:?} to {} digits", prefix, width);
+    }
+    assert_ne!(renumbered("", 0).output_hash(), ProcessingSettings::default().output_hash());
+    assert_ne!(renumbered("", 0).output_hash(), renumbered("frame_", 0).output_hash());
+}
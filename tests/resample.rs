@@ -0,0 +1,78 @@
+//! Resampling irregular scans onto a regular time grid
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, DirectorySink, TrailSettings};
+use radar_echo_trails::resample::{resample, to_csv, Slot};
+use radar_echo_trails::settings::ResampleNaming;
+
+fn paths(names: &[&str]) -> Vec<PathBuf> {
+    names.iter().map(PathBuf::from).collect()
+}
+
+/// One minute scans, then a gap of ten minutes
+fn irregular() -> Vec<PathBuf> {
+    paths(&["dbz_2024-06-01_1200.png", "dbz_2024-06-01_1201.png", "dbz_2024-06-01_1202.png", "dbz_2024-06-01_1210.png", "dbz_2024-06-01_1220.png"])
+}
+
+fn names(slots: &[Slot]) -> Vec<&str> {
+    slots.iter().map(|slot| slot.name.to_str().unwrap()).collect()
+}
+
+#[test]
+fn holds_the_most_recent_scan_in_each_slot() {
+    let slots = resample(&irregular(), 300, ResampleNaming::Grid).unwrap();
+    let sources: Vec<&str> = slots.iter().map(|slot| slot.source.to_str().unwrap()).collect();
+    assert_eq!(sources, [
+        "dbz_2024-06-01_1200.png", "dbz_2024-06-01_1202.png", "dbz_2024-06-01_1210.png",
+        "dbz_2024-06-01_1210.png", "dbz_2024-06-01_1220.png",
+    ]);
+    let held: Vec<bool> = slots.iter().map(|slot| slot.held).collect();
+    assert_eq!(held, [false, false, false, true, false]);
+    assert_eq!(names(&slots), [
+        "dbz_2024-06-01_1200.png", "dbz_2024-06-01_1205.png", "dbz_2024-06-01_1210.png",
+        "dbz_2024-06-01_1215.png", "dbz_2024-06-01_1220.png",
+    ]);
+
+    let slots = resample(&irregular(), 300, ResampleNaming::Source).unwrap();
+    assert_eq!(names(&slots)[3], "dbz_2024-06-01_1210_hold1.png");
+    assert!(to_csv(&slots).contains("\n3,20240601121500,dbz_2024-06-01_1210.png,dbz_2024-06-01_1210_hold1.png,true\n"));
+}
+
+#[test]
+fn slots_fall_on_whole_intervals_within_the_scans() {
+    let slots = resample(&paths(&["a_202406011203.png", "a_202406011227.png"]), 600, ResampleNaming::Grid).unwrap();
+    assert_eq!(names(&slots), ["a_202406011210.png", "a_202406011220.png"]);
+    assert!(slots.iter().all(|slot| slot.source.as_os_str() == "a_202406011203.png"));
+    assert!(resample(&paths(&["a_1.png", "a_202406011203.png"]), 600, ResampleNaming::Grid).is_none());
+}
+
+#[test]
+fn held_frames_are_written_under_their_own_names() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_resample_{}", std::process::id()));
+    let source = dir.join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    let frames: Vec<PathBuf> = irregular().iter().map(|name| {
+        let path = source.join(name);
+        RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255])).save(&path).unwrap();
+        path
+    }).collect();
+    let slots = resample(&frames, 300, ResampleNaming::Source).unwrap();
+    let sources: Vec<PathBuf> = slots.iter().map(|slot| slot.source.clone()).collect();
+    let sink = DirectorySink::new(dir.join("output"), &source).with_names(slots.iter().map(|slot| slot.name.clone()).collect());
+    let summary = engine::render_sequence(&sources, &TrailSettings::default(), &sink, |_| ControlFlow::Continue(()));
+    let mut written: Vec<String> = std::fs::read_dir(dir.join("output")).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    written.sort();
+    assert_eq!(written, [
+        "dbz_2024-06-01_1200.png", "dbz_2024-06-01_1202.png", "dbz_2024-06-01_1210.png",
+        "dbz_2024-06-01_1210_hold1.png", "dbz_2024-06-01_1220.png",
+    ]);
+}
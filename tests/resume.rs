@@ -0,0 +1,145 @@
+//! Keeping the trails an interrupted run left up to date
+#![cfg(feature = "native")]
+
+use std::fs::File;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, DirectorySink, RunSummary, TrailSettings};
+use radar_echo_trails::metadata;
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+use radar_echo_trails::settings::TrailMode;
+
+mod common;
+use common::run;
+
+/// A folder of `count` frames of an echo moving right one column a frame
+fn frames(name: &str, count: u32) -> (PathBuf, Vec<PathBuf>) {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_resume_{}_{}", name, std::process::id()));
+    let source = dir.join("frames");
+    std::fs::create_dir_all(&source).unwrap();
+    let paths = (0..count)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            RgbaImage::from_fn(count, 1, |x, _| if x == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    (dir, paths)
+}
+
+/// Set the modification time of `path` to `seconds` from now
+fn touch(path: &Path, seconds: i64) {
+    let now = SystemTime::now();
+    let time = if seconds < 0 { now - Duration::from_secs(seconds.unsigned_abs()) } else { now + Duration::from_secs(seconds as u64) };
+    File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+}
+
+fn render(paths: &[PathBuf], output: &Path, settings: &TrailSettings) -> RunSummary {
+    let sink = DirectorySink::new(output, paths[0].parent().unwrap());
+    let done = Mutex::new(Vec::new());
+    let summary = engine::render_sequence(paths, settings, &sink, |progress| {
+        done.lock().unwrap().push(progress.frames_done);
+        ControlFlow::Continue(())
+    });
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    // Kept frames count towards the progress like rendered ones
+    let mut done = done.into_inner().unwrap();
+    done.sort_unstable();
+    assert_eq!(done, (1..=paths.len()).collect::<Vec<_>>());
+    summary
+}
+
+#[test]
+fn trails_newer_than_their_frames_are_kept() {
+    let (dir, paths) = frames("engine", 8);
+    let output = dir.join("output");
+    let marker = RgbaImage::from_pixel(8, 1, Rgba([1, 2, 3, 255]));
+    for trail_mode in [TrailMode::Fade, TrailMode::Decay] {
+        let settings = TrailSettings { history_length: 2, trail_mode, resume: true, ..TrailSettings::default() };
+        for path in &paths {
+            touch(path, -3600);
+        }
+        let trail = |index: usize| image::open(output.join(format!("scan_{}.png", index))).unwrap().to_rgba8();
+        let expected: Vec<RgbaImage> = {
+            let first = render(&paths, &output, &settings);
+            assert_eq!((first.frames_completed, first.frames_skipped), (8, 0));
+            (0..8).map(trail).collect()
+        };
+
+        // Interrupted after frame 4: the later trails are missing, and the
+        // earlier ones are marked to show they are not written again
+        for index in 0..8 {
+            let path = output.join(format!("scan_{}.png", index));
+            if index <= 4 {
+                marker.save(&path).unwrap();
+                touch(&path, 60);
+            } else {
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+        let resumed = render(&paths, &output, &settings);
+        assert_eq!((resumed.frames_completed, resumed.frames_skipped), (8, 5), "{}", trail_mode);
+        for (index, expected) in expected.iter().enumerate() {
+            let wanted = if index <= 4 { &marker } else { expected };
+            assert!(&trail(index) == wanted, "{} frame {}", trail_mode, index);
+        }
+
+        // A frame changed since is rendered again, with the trails it is history of
+        touch(&paths[2], 120);
+        let changed = render(&paths, &output, &settings);
+        let redrawn: Vec<usize> = (0..5).filter(|&index| trail(index) != marker).collect();
+        assert_eq!(redrawn, [2, 3, 4], "{}", trail_mode);
+        let kept = match trail_mode {
+            TrailMode::Fade => 5,
+            // The buffer still shows frame 2 in every later frame
+            TrailMode::Decay => 2,
+        };
+        assert_eq!(changed.frames_skipped, kept, "{}", trail_mode);
+        for index in redrawn {
+            assert!(trail(index) == expected[index], "{} frame {}", trail_mode, index);
+        }
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_folder_resumes_unless_forced() {
+    let (dir, paths) = frames("folder", 6);
+    let source = paths[0].parent().unwrap();
+    let settings = ProcessingSettings { history_length: 2, resume: true, ..ProcessingSettings::default() };
+    let output = processing::output_dir_for(source, &settings);
+
+    let completed = |update: ProgressUpdate| match update {
+        ProgressUpdate::FolderCompleted { frames_rendered, frames_skipped, .. } => (frames_rendered, frames_skipped),
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(completed(run(source, &settings)), (6, 0));
+
+    // A run that died before recording itself left its first three trails
+    std::fs::remove_file(output.join(metadata::RUN_METADATA_FILE)).unwrap();
+    for index in 3..6 {
+        std::fs::remove_file(output.join(format!("scan_{}.png", index))).unwrap();
+    }
+    assert_eq!(completed(run(source, &settings)), (3, 3));
+    let recorded = metadata::read_run_metadata(&output).unwrap();
+    assert_eq!((recorded.frames_completed, recorded.frames_skipped), (6, 3));
+
+    std::fs::remove_file(output.join(metadata::RUN_METADATA_FILE)).unwrap();
+    assert_eq!(completed(run(source, &ProcessingSettings { force: true, ..settings.clone() })), (6, 0));
+
+    // Trails of other settings are never kept
+    let other = ProcessingSettings { history_color: "#00ff00".to_string(), ..settings.clone() };
+    assert_eq!(processing::output_dir_for(source, &other), output);
+    let mut recorded = metadata::read_run_metadata(&output).unwrap();
+    recorded.cancelled = true;
+    metadata::write_run_metadata(&output, &mut recorded).unwrap();
+    assert_eq!(completed(run(source, &ProcessingSettings { on_collision: processing::CollisionPolicy::Overwrite, ..other })), (6, 0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
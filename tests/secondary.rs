@@ -0,0 +1,85 @@
+//! A second folder's trail composited over the primary one
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, compose_trail, overlay_secondary, TrailSettings};
+use radar_echo_trails::secondary::{pair, PairedBy};
+use radar_echo_trails::settings::SecondarySettings;
+
+fn paths(names: &[&str]) -> Vec<PathBuf> {
+    names.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn pairs_by_position_without_scan_times() {
+    let pairing = pair(&paths(&["a_1.png", "a_2.png", "a_3.png"]), &paths(&["b_1.png", "b_2.png"]));
+    assert_eq!(pairing.paired_by, PairedBy::Index);
+    assert_eq!(pairing.frames, vec![Some("b_1.png".into()), Some("b_2.png".into()), None]);
+    assert_eq!((pairing.unpaired(), pairing.unused), (1, 0));
+
+    let pairing = pair(&paths(&["a_1.png"]), &paths(&["b_1.png", "b_2.png"]));
+    assert_eq!((pairing.unpaired(), pairing.unused), (0, 1));
+}
+
+#[test]
+fn pairs_by_nearest_scan_time_within_half_an_interval() {
+    let primary = paths(&["dbz_202406011200.png", "dbz_202406011205.png", "dbz_202406011210.png"]);
+    // A minute off, one missing, and one too far from any primary scan
+    let secondary = paths(&["vel_202406011201.png", "vel_202406011210.png", "vel_202406011230.png"]);
+    let pairing = pair(&primary, &secondary);
+    assert_eq!(pairing.paired_by, PairedBy::Time);
+    assert_eq!(pairing.frames, vec![
+        Some("vel_202406011201.png".into()),
+        None,
+        Some("vel_202406011210.png".into()),
+    ]);
+    assert_eq!((pairing.unpaired(), pairing.unused), (1, 1));
+}
+
+#[test]
+fn draws_the_secondary_trail_over_the_primary() {
+    let echo = RgbaImage::from_pixel(2, 1, Rgba([255, 255, 255, 255]));
+    let mut half = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+    half.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+    let settings = TrailSettings {
+        secondary: SecondarySettings { color: "#0000ff".into(), opacity: 0.5, ..SecondarySettings::default() },
+        ..TrailSettings::default()
+    };
+
+    let mut trail = compose_trail(&echo, &[], &settings);
+    overlay_secondary(&mut trail, &half, &[], &settings);
+    // Left alone where the secondary frame has no echo, blended at half opacity where it does
+    assert_eq!(trail.get_pixel(0, 0), &Rgba([0, 255, 0, 255]));
+    assert_eq!(trail.get_pixel(1, 0), &Rgba([0, 127, 127, 255]));
+}
+
+#[test]
+fn frames_without_a_secondary_frame_render_as_usual() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_secondary_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let save = |name: &str| {
+        let path = dir.join(name);
+        RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255])).save(&path).unwrap();
+        path
+    };
+    let primary = vec![save("a_0.png"), save("a_1.png")];
+    let secondary = vec![Some(save("b_0.png")), None];
+
+    let written = Mutex::new(Vec::new());
+    let sink = |index: usize, _source: &Path, frame: RgbaImage| {
+        written.lock().unwrap().push((index, *frame.get_pixel(0, 0)));
+        Ok(())
+    };
+    let settings = TrailSettings { history_length: 1, ..TrailSettings::default() };
+    let summary = engine::render_paired_sequence(&primary, &secondary, &settings, &sink, |_| ControlFlow::Continue(()));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+    let mut written = written.into_inner().unwrap();
+    written.sort_by_key(|&(index, _)| index);
+    assert_eq!(written, vec![(0, Rgba([0, 191, 255, 255])), (1, Rgba([0, 255, 0, 255]))]);
+}
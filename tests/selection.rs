@@ -0,0 +1,64 @@
+//! Choosing which of a folder's frames to render by offset, limit and stride
+#![cfg(feature = "native")]
+
+use radar_echo_trails::config::Settings;
+use radar_echo_trails::processing::ProcessingSettings;
+use radar_echo_trails::queue::{JobQueue, ScanOptions};
+
+#[test]
+fn offset_then_limit_then_stride() {
+    let frames: Vec<usize> = (0..3000).collect();
+    let settings = ProcessingSettings { offset: 500, limit: Some(1000), stride: 2, ..ProcessingSettings::default() };
+    let selected = settings.select_frames(&frames);
+    assert_eq!(selected.len(), 500);
+    assert_eq!(selected.first(), Some(&500));
+    assert_eq!(selected.last(), Some(&1498));
+    assert!(selected.windows(2).all(|pair| pair[1] - pair[0] == 2));
+
+    assert_eq!(ProcessingSettings::default().select_frames(&frames), frames);
+}
+
+#[test]
+fn the_count_matches_the_selection() {
+    for (offset, limit, stride) in [(0, None, 1), (3, None, 2), (10, Some(4), 3), (40, Some(7), 1), (2, Some(5), 10)] {
+        let settings = ProcessingSettings { offset, limit, stride, ..ProcessingSettings::default() };
+        for total in [0, 1, 9, 10, 11, 37] {
+            let frames: Vec<usize> = (0..total).collect();
+            assert_eq!(settings.selected_count(total), settings.select_frames(&frames).len(), "{:?} of {}", (offset, limit, stride), total);
+        }
+    }
+}
+
+#[test]
+fn rejects_a_zero_stride() {
+    let settings = ProcessingSettings { stride: 0, ..ProcessingSettings::default() };
+    let invalid = settings.validate().unwrap_err();
+    assert!(invalid.iter().any(|field| field.field == "stride"));
+    assert!(Settings { stride: 0, ..Settings::default() }.validate().is_err());
+
+    let unchanged = ProcessingSettings::default();
+    assert_eq!(ProcessingSettings { stride: 1, ..unchanged.clone() }.output_hash(), unchanged.output_hash());
+    assert_ne!(ProcessingSettings { stride: 3, ..unchanged.clone() }.output_hash(), unchanged.output_hash());
+    assert_ne!(ProcessingSettings { offset: 3, ..unchanged.clone() }.output_hash(), unchanged.output_hash());
+}
+
+#[test]
+fn queued_folders_keep_their_own_selection() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_selection_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for index in 0..10 {
+        image::RgbaImage::new(2, 2).save(dir.join(format!("frame_{:02}.png", index))).unwrap();
+    }
+    let mut queue = JobQueue::default();
+    let index = queue.add_folder(&dir, ScanOptions::default(), false).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let global = ProcessingSettings { offset: 2, stride: 2, ..ProcessingSettings::default() };
+    let folder = queue.iter_mut().nth(index).unwrap();
+    assert_eq!(folder.effective_settings(&global).selected_count(folder.file_count), 4);
+
+    folder.settings = Some(ProcessingSettings { offset: 7, ..ProcessingSettings::default() });
+    let own = folder.effective_settings(&global);
+    assert_eq!((own.offset, own.stride), (7, 1));
+    assert_eq!(own.select_frames(&folder.scan.files), folder.scan.files[7..]);
+}
@@ -0,0 +1,102 @@
+//! Saving settings atomically, keeping backups and falling back to them
+#![cfg(feature = "native")]
+
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use radar_echo_trails::config::{self, Settings, SettingsError};
+
+/// The config directory, emptied, held for the length of one test. Settings
+/// live in one directory per process, so the tests take turns with it.
+fn config_dir() -> (MutexGuard<'static, ()>, PathBuf) {
+    static LOCK: Mutex<()> = Mutex::new(());
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    let guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let home = DIR.get_or_init(|| {
+        let home = std::env::temp_dir().join(format!("radar_echo_trails_settings_backup_{}", std::process::id()));
+        // SAFETY: set once, before any test of this binary reads the environment
+        unsafe { std::env::set_var("XDG_CONFIG_HOME", &home) };
+        home
+    });
+    let dir = config::settings_dir().unwrap();
+    assert!(dir.starts_with(home), "{} is not a test directory", dir.display());
+    let _ = std::fs::remove_dir_all(&dir);
+    (guard, dir)
+}
+
+fn with_history(history_length: usize, settings_backups: usize) -> Settings {
+    let mut settings = Settings { settings_backups, ..Settings::default() };
+    settings.rendering.history_length = history_length;
+    settings
+}
+
+/// History length of the settings file at `path`
+fn history_in(path: &std::path::Path) -> usize {
+    let settings: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    settings["rendering"]["history_length"].as_u64().unwrap() as usize
+}
+
+#[test]
+fn each_save_pushes_the_previous_file_into_the_backups() {
+    let (_guard, dir) = config_dir();
+    for history_length in 1..=4 {
+        config::save_settings(&with_history(history_length, 2)).unwrap();
+    }
+    assert_eq!(history_in(&dir.join("settings.json")), 4);
+    assert_eq!(history_in(&dir.join("settings.json.1")), 3);
+    assert_eq!(history_in(&dir.join("settings.json.2")), 2);
+    assert!(!dir.join("settings.json.3").exists());
+    assert!(!dir.join("settings.json.tmp").exists());
+
+    // Saving the same settings again keeps the history of real changes
+    config::save_settings(&with_history(4, 2)).unwrap();
+    assert_eq!(history_in(&dir.join("settings.json.1")), 3);
+}
+
+#[test]
+fn a_truncated_settings_file_falls_back_to_the_latest_backup() {
+    let (_guard, dir) = config_dir();
+    config::save_settings(&with_history(7, 3)).unwrap();
+    config::save_settings(&with_history(9, 3)).unwrap();
+    let path = dir.join("settings.json");
+    let saved = std::fs::read_to_string(&path).unwrap();
+
+    // Zero bytes, as a crash mid-write used to leave it, and cut off part way
+    for truncated in ["", &saved[..saved.len() / 2]] {
+        std::fs::write(&path, truncated).unwrap();
+        let (settings, report) = config::load_or_default_with_report();
+        assert_eq!(settings.rendering.history_length, 7);
+        match report {
+            Some(SettingsError::RestoredFromBackup { backup, problem }) => {
+                assert_eq!(backup, dir.join("settings.json.1"));
+                assert!(matches!(*problem, SettingsError::Parse { .. }), "{:?}", problem);
+            }
+            other => panic!("expected the backup to be loaded, got {:?}", other),
+        }
+    }
+
+    // A corrupt backup is passed over for an older one
+    std::fs::write(dir.join("settings.json.1"), "{").unwrap();
+    std::fs::write(dir.join("settings.json.2"), &saved).unwrap();
+    let (settings, report) = config::load_or_default_with_report();
+    assert_eq!(settings.rendering.history_length, 9);
+    assert!(matches!(report, Some(SettingsError::RestoredFromBackup { backup, .. }) if backup == dir.join("settings.json.2")));
+}
+
+#[test]
+fn backups_are_trimmed_to_the_configured_depth() {
+    let (_guard, dir) = config_dir();
+    for history_length in 1..=5 {
+        config::save_settings(&with_history(history_length, 4)).unwrap();
+    }
+    assert!(dir.join("settings.json.4").exists());
+
+    config::save_settings(&with_history(6, 1)).unwrap();
+    assert_eq!(history_in(&dir.join("settings.json.1")), 5);
+    assert!((2..=4).all(|n| !dir.join(format!("settings.json.{}", n)).exists()));
+
+    // No backups at all
+    config::save_settings(&with_history(7, 0)).unwrap();
+    assert!(!dir.join("settings.json.1").exists());
+    assert_eq!(history_in(&dir.join("settings.json")), 7);
+}
@@ -0,0 +1,59 @@
+//! Settings fields read with the types the renderer uses
+#![cfg(feature = "native")]
+
+use radar_echo_trails::config::{self, Settings, SettingsError};
+use radar_echo_trails::processing::ProcessingSettings;
+use serde_json::json;
+
+#[test]
+fn a_legacy_zero_limit_means_no_limit() {
+    let settings: Settings = serde_json::from_value(json!({ "limit": 0 })).unwrap();
+    assert_eq!(settings.limit, None);
+    let settings: Settings = serde_json::from_value(json!({ "limit": null })).unwrap();
+    assert_eq!(settings.limit, None);
+    let settings: Settings = serde_json::from_value(json!({ "limit": 12 })).unwrap();
+    assert_eq!(settings.limit, Some(12));
+    assert_eq!(ProcessingSettings::from(settings).limit, Some(12));
+}
+
+#[test]
+fn a_negative_count_is_rejected_by_name() {
+    assert!(serde_json::from_value::<Settings>(json!({ "limit": -5 })).is_err());
+    assert!(serde_json::from_value::<Settings>(json!({ "performance": { "threads": -1 } })).is_err());
+
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_settings_fields_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, content, field) in [
+        ("threads.json", json!({ "performance": { "threads": -1 } }), "performance.threads"),
+        ("history.json", json!({ "rendering": { "history_length": -3 } }), "rendering.history_length"),
+        ("limit.json", json!({ "limit": -5 }), "limit"),
+        // The flat layout of older files is named by where the field lives now
+        ("flat.json", json!({ "threads": -2 }), "performance.threads"),
+    ] {
+        let path = dir.join(file);
+        std::fs::write(&path, content.to_string()).unwrap();
+        let error = config::import_settings(&path).unwrap_err();
+        assert!(matches!(error, SettingsError::Parse { .. }), "{:?}", error);
+        let message = error.to_string();
+        assert!(message.contains(&format!("{}: ", field)) && message.contains("negative"), "{}", message);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn settings_round_trip_through_processing_settings() {
+    let mut settings = Settings { limit: Some(40), offset: 3, stride: 2, ..Settings::default() };
+    settings.rendering.history_length = 7;
+    settings.rendering.history_color = "#3366ff".to_string();
+    settings.performance.threads = 6;
+    settings.performance.io_threads = 2;
+    settings.output.renumber_width = 5;
+
+    let processing = ProcessingSettings::from(settings.clone());
+    assert_eq!((processing.limit, processing.history_length, processing.threads), (Some(40), 7, 6));
+
+    let mut back = Settings::default();
+    back.set_processing(&processing);
+    assert_eq!(serde_json::to_value(&back).unwrap(), serde_json::to_value(&settings).unwrap());
+    assert!(back.changed_fields(&settings).is_empty());
+}
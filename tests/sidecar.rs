@@ -0,0 +1,91 @@
+//! Per-frame overrides from sidecar files
+#![cfg(feature = "native")]
+
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, TrailSettings};
+use radar_echo_trails::queue::{self, ScanOptions};
+use radar_echo_trails::sidecar::{self, FrameOverrides};
+
+#[test]
+fn sidecars_are_named_after_their_frame() {
+    let sidecar = sidecar::sidecar_path(Path::new("event/frame0123.png"));
+    assert_eq!(sidecar, Path::new("event/frame0123.png.json"));
+    assert_eq!(sidecar::frame_path(&sidecar).as_deref(), Some(Path::new("event/frame0123.png")));
+    assert_eq!(sidecar::frame_path(Path::new("event/settings.json")), None);
+}
+
+#[test]
+fn unknown_keys_are_reported_and_malformed_sidecars_rejected() {
+    let (overrides, unknown) = sidecar::parse(r#"{"exclude_from_history": true, "intensity_offset": -20, "colour": "red"}"#).unwrap();
+    assert_eq!(overrides, FrameOverrides { exclude_from_history: true, intensity_offset: -20, ..FrameOverrides::default() });
+    assert_eq!(unknown, ["colour"]);
+    assert!(sidecar::parse(r#"{"skip": "yes"}"#).is_err());
+    assert!(sidecar::parse("[true]").is_err());
+}
+
+#[test]
+fn mask_and_offset_apply_to_the_decoded_frame() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_sidecar_mask_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let frame_path = dir.join("scan.png");
+    RgbaImage::from_pixel(4, 1, Rgba([100, 100, 100, 255])).save(&frame_path).unwrap();
+    // The left half is masked out
+    RgbaImage::from_fn(4, 1, |x, _| if x < 2 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) })
+        .save(dir.join("mask.png"))
+        .unwrap();
+
+    let overrides = FrameOverrides { mask: Some("mask.png".into()), intensity_offset: 200, ..FrameOverrides::default() };
+    let settings = TrailSettings {
+        frame_overrides: Arc::new(HashMap::from([(frame_path.clone(), overrides)])),
+        ..TrailSettings::default()
+    };
+    let frame = engine::load_frame(&frame_path, &settings).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(frame.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+    assert_eq!(frame.get_pixel(3, 0), &Rgba([255, 255, 255, 255]));
+}
+
+#[test]
+fn scans_find_sidecars_and_excluded_frames_leave_the_history() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_sidecar_history_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in 0..3 {
+        // Each frame has echo in its own column
+        RgbaImage::from_fn(3, 1, |x, _| if x == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+            .save(dir.join(format!("scan_{}.png", i)))
+            .unwrap();
+    }
+    std::fs::write(dir.join("scan_0.png.json"), r#"{"exclude_from_history": true}"#).unwrap();
+    let scan = queue::scan_folder(&dir, &ScanOptions::default()).unwrap();
+    assert_eq!(scan.files.len(), 3);
+    assert_eq!(scan.sidecars, [dir.join("scan_0.png.json")]);
+
+    let (overrides, _) = sidecar::read(&scan.sidecars[0]).unwrap();
+    let settings = TrailSettings {
+        history_length: 2,
+        frame_overrides: Arc::new(HashMap::from([(dir.join("scan_0.png"), overrides)])),
+        ..TrailSettings::default()
+    };
+    let last = Mutex::new(None);
+    let sink = |index: usize, _: &Path, frame: RgbaImage| -> anyhow::Result<()> {
+        if index == 2 {
+            *last.lock().unwrap() = Some(frame);
+        }
+        Ok(())
+    };
+    let files: Vec<PathBuf> = scan.files.clone();
+    let summary = engine::render_sequence(&files, &settings, &sink, |_| ControlFlow::Continue(()));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+
+    let last = last.into_inner().unwrap().unwrap();
+    let background = Rgba([0, 0, 0, 255]);
+    assert_eq!(last.get_pixel(0, 0), &background, "the excluded frame is not drawn");
+    assert_ne!(last.get_pixel(1, 0), &background);
+}
@@ -0,0 +1,132 @@
+//! Finding legends and logos burned into every frame and masking them out
+#![cfg(feature = "native")]
+
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate, StaticMaskSettings};
+use radar_echo_trails::static_mask::{self, StaticMask};
+
+mod common;
+use common::run;
+
+const LOGO: Rgba<u8> = Rgba([250, 250, 250, 255]);
+const WEATHER: Rgba<u8> = Rgba([0, 180, 0, 255]);
+
+/// A 20 by 10 frame with a logo in the top left corner, an echo in column
+/// `moving` and, when `still`, a patch of weather that never moves
+fn frame(moving: Option<u32>, still: bool) -> RgbaImage {
+    RgbaImage::from_fn(20, 10, |x, y| match (x, y) {
+        (0..=2, 0..=1) => LOGO,
+        (14..=15, 6..=7) if still => WEATHER,
+        _ if Some(x) == moving && y > 3 => WEATHER,
+        _ => Rgba([0, 0, 0, 0]),
+    })
+}
+
+fn save_frames(name: &str, frames: &[RgbaImage]) -> (PathBuf, Vec<PathBuf>) {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_static_{}_{}", name, std::process::id()));
+    let source = dir.join("frames");
+    std::fs::create_dir_all(&source).unwrap();
+    let paths = frames.iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let path = source.join(format!("scan_{}.png", i));
+            frame.save(&path).unwrap();
+            path
+        })
+        .collect();
+    (dir, paths)
+}
+
+fn masking(samples: usize, dilate: u32) -> ProcessingSettings {
+    ProcessingSettings {
+        history_length: 2,
+        static_mask: StaticMaskSettings { samples, dilate, ..StaticMaskSettings::default() },
+        ..ProcessingSettings::default()
+    }
+}
+
+#[test]
+fn samples_are_spread_over_the_sequence() {
+    assert_eq!(static_mask::spread(9, 3), [0, 4, 8]);
+    assert_eq!(static_mask::spread(100, 4), [0, 33, 66, 99]);
+    assert_eq!(static_mask::spread(3, 8), [0, 1, 2]);
+    assert!(static_mask::spread(0, 8).is_empty());
+}
+
+#[test]
+fn the_mask_grows_and_clears_what_it_covers() {
+    let mut masked = vec![false; 25];
+    masked[12] = true;
+    let mask = StaticMask::new(5, 5, masked);
+    let grown = mask.dilate(1);
+    assert_eq!((mask.masked_count(), grown.masked_count()), (1, 9));
+    assert!(grown.is_masked(1, 1) && grown.is_masked(3, 3) && !grown.is_masked(0, 2));
+    assert_eq!(mask.dilate(4).masked_count(), 25);
+
+    let mut frame = RgbaImage::from_pixel(5, 5, WEATHER);
+    grown.apply(&mut frame);
+    assert_eq!(frame.get_pixel(2, 2), &Rgba([0, 0, 0, 0]));
+    assert_eq!(frame.get_pixel(0, 0), &WEATHER);
+    // As a sidecar mask, cleared pixels are black
+    assert_eq!(grown.to_image().get_pixel(2, 2), &Rgba([0, 0, 0, 255]));
+    assert_eq!(grown.to_image().get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+
+    let mut other = RgbaImage::from_pixel(4, 4, WEATHER);
+    grown.apply(&mut other);
+    assert!(other.pixels().all(|pixel| pixel == &WEATHER), "frames of another size are left alone");
+}
+
+#[test]
+fn weather_that_holds_still_is_kept_when_an_empty_frame_shows_it_is_weather() {
+    // The still weather is missing only from the frame with no other echo either
+    let frames: Vec<RgbaImage> = (0..9).map(|i| if i == 5 { frame(None, false) } else { frame(Some(4 + i), true) }).collect();
+    let (dir, paths) = save_frames("confirmed", &frames);
+    let detection = static_mask::detect(&paths, &masking(3, 0)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(detection.reference, Some(paths[5].clone()));
+    assert_eq!(detection.mask.masked_count(), 6);
+    assert!(detection.mask.is_masked(0, 0) && detection.mask.is_masked(2, 1));
+    assert!(!detection.mask.is_masked(14, 6));
+}
+
+#[test]
+fn without_an_empty_frame_the_mask_is_unconfirmed() {
+    let frames: Vec<RgbaImage> = (0..6).map(|i| frame(Some(4 + i), true)).collect();
+    let (dir, paths) = save_frames("unconfirmed", &frames);
+    let detection = static_mask::detect(&paths, &masking(3, 0)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(detection.reference, None);
+    assert!(detection.mask.is_masked(0, 0) && detection.mask.is_masked(14, 6));
+}
+
+#[test]
+fn the_mask_is_saved_and_the_logo_left_out_of_the_trails() {
+    let frames: Vec<RgbaImage> = (0..4).map(|i| if i == 2 { frame(None, false) } else { frame(Some(6 + i), false) }).collect();
+    let (dir, paths) = save_frames("folder", &frames);
+    let settings = masking(4, 1);
+    assert!(matches!(run(paths[0].parent().unwrap(), &settings), ProgressUpdate::FolderCompleted { .. }));
+
+    let output = processing::output_dir_for(paths[0].parent().unwrap(), &settings);
+    let mask = image::open(output.join(static_mask::AUTO_MASK_NAME)).unwrap().to_rgba8();
+    let trail = image::open(output.join("scan_3.png")).unwrap().to_rgba8();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // The 3 by 2 logo, grown by a pixel
+    assert_eq!(mask.pixels().filter(|pixel| pixel == &&Rgba([0, 0, 0, 255])).count(), 12);
+    assert_eq!(trail.get_pixel(1, 0), &Rgba([0, 0, 0, 255]), "the logo is background");
+    assert_ne!(trail.get_pixel(9, 5), &Rgba([0, 0, 0, 255]), "the echo is drawn");
+}
+
+#[test]
+fn rejects_a_single_sample_and_wide_growth() {
+    for (samples, dilate, valid) in [(0, 1, true), (1, 1, false), (2, 1, true), (32, 8, true), (33, 1, false), (8, 9, false)] {
+        assert_eq!(masking(samples, dilate).validate().is_ok(), valid, "{} samples grown {}", samples, dilate);
+    }
+    let unchanged = ProcessingSettings { history_length: 2, ..ProcessingSettings::default() };
+    assert_eq!(masking(0, 3).output_hash(), unchanged.output_hash());
+    assert_ne!(masking(8, 1).output_hash(), unchanged.output_hash());
+}
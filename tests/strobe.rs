@@ -0,0 +1,34 @@
+//! Drawing every Nth history frame only
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{compose_trail, history_age, TrailSettings};
+
+mod common;
+use common::echo;
+
+/// The trail of an echo moving right one column a frame, with four history frames
+fn trail(settings: &TrailSettings) -> RgbaImage {
+    let past: Vec<RgbaImage> = (0..4).map(|x| echo(x, 6)).collect();
+    let history: Vec<(&RgbaImage, f32)> = past.iter().enumerate().map(|(i, frame)| (frame, history_age(4 - i, 4))).collect();
+    compose_trail(&echo(4, 6), &history, settings)
+}
+
+#[test]
+fn keeps_the_age_of_the_frames_it_draws() {
+    let plain = trail(&TrailSettings::default());
+    let strobed = trail(&TrailSettings { strobe: 2, ..TrailSettings::default() });
+    // Two and four frames back are drawn as without the strobe, one and three not at all
+    for x in [0, 2, 4] {
+        assert_eq!(strobed.get_pixel(x, 0), plain.get_pixel(x, 0), "x = {}", x);
+    }
+    for x in [1, 3] {
+        assert_eq!(strobed.get_pixel(x, 0), &Rgba([0, 0, 0, 255]), "x = {}", x);
+    }
+
+    let dimmed = trail(&TrailSettings { strobe: 2, strobe_dim: 0.5, ..TrailSettings::default() });
+    assert_eq!(dimmed.get_pixel(0, 0), plain.get_pixel(0, 0));
+    // Three frames back of four: (4 + 1 - 3) / 4 * 128 * 0.5 = 32 of orange
+    assert_eq!(dimmed.get_pixel(1, 0), &Rgba([32, 15, 0, 255]));
+
+    assert!(trail(&TrailSettings { strobe: 1, ..TrailSettings::default() }) == plain);
+}
@@ -0,0 +1,62 @@
+//! Running a queue on a thread pool the caller owns
+#![cfg(feature = "native")]
+
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Arc, Mutex};
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressFn, ProgressUpdate};
+use radar_echo_trails::queue::{JobQueue, ScanOptions};
+
+#[test]
+fn every_folder_renders_on_the_callers_pool() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_thread_pool_{}", std::process::id()));
+    let folders: Vec<_> = (0..2).map(|n| dir.join(format!("case_{}", n))).collect();
+    for folder in &folders {
+        std::fs::create_dir_all(folder).unwrap();
+        for i in 0..6 {
+            RgbaImage::from_fn(16, 4, |x, _| if x / 4 == i % 4 { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 255]) })
+                .save(folder.join(format!("scan_{:03}.png", i)))
+                .unwrap();
+        }
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(3)
+        .thread_name(|i| format!("caller-pool-{}", i))
+        .build()
+        .unwrap();
+    // A thread count the run must not build a pool of
+    let settings = ProcessingSettings { threads: 7, ..ProcessingSettings::default() };
+
+    let mut queue = JobQueue::default();
+    for folder in &folders {
+        queue.add_folder(folder, ScanOptions::default(), false).unwrap();
+    }
+    // Where each frame's progress was reported from, and how many threads its pool had
+    let seen = Mutex::new(Vec::new());
+    let completed = Mutex::new(0);
+    let sink = ProgressFn(|update: ProgressUpdate| match update {
+        ProgressUpdate::FileProgress { .. } => {
+            let name = std::thread::current().name().unwrap_or_default().to_string();
+            seen.lock().unwrap().push((name, rayon::current_num_threads()));
+        }
+        ProgressUpdate::FolderCompleted { frames_rendered: 6, .. } => *completed.lock().unwrap() += 1,
+        _ => {}
+    });
+    let (_reload, reload) = mpsc::channel();
+    processing::process_folders_on(&pool, Arc::new(Mutex::new(queue)), settings.clone(), sink, Arc::new(AtomicBool::new(false)), reload);
+    for folder in &folders {
+        let _ = std::fs::remove_dir_all(processing::output_dir_for(folder, &settings));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(completed.into_inner().unwrap(), folders.len());
+    let seen = seen.into_inner().unwrap();
+    assert!(!seen.is_empty());
+    for (name, threads) in &seen {
+        assert!(name.starts_with("caller-pool-"), "a frame rendered on {:?}", name);
+        assert_eq!(*threads, 3);
+    }
+    // The pool is still the caller's to use once the run is done
+    assert_eq!(pool.install(rayon::current_num_threads), 3);
+}
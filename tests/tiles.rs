@@ -0,0 +1,118 @@
+//! Compositing and writing very large frames in tiles
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use image::{GenericImage, Rgba, RgbaImage};
+use radar_echo_trails::engine::{
+    self, compose_trail, compose_trail_counted, tile_zoom, tiles, DirectorySink, PixelShader, Shader, TrailSettings,
+};
+
+const WIDTH: u32 = 150;
+const HEIGHT: u32 = 100;
+
+/// A ring centred on `cx` whose grey level and alpha vary across the frame,
+/// with echo on both sides of every tile seam
+fn ring(cx: f32) -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let d = ((x as f32 - cx).powi(2) + (y as f32 - 50.0).powi(2)).sqrt();
+        if (20.0..34.0).contains(&d) {
+            Rgba([(x + 100) as u8, (y * 2) as u8, 200, (128 + x % 128) as u8])
+        } else {
+            Rgba([0, 0, 0, 0])
+        }
+    })
+}
+
+/// Colors pixels by where they are in the frame
+struct Position;
+
+impl PixelShader for Position {
+    fn shade(&self, src: Rgba<u8>, age: f32, x: u32, y: u32) -> Option<Rgba<u8>> {
+        (src[3] > 0).then(|| Rgba([x as u8, y as u8, (age * 255.0) as u8, src[3]]))
+    }
+}
+
+#[test]
+fn tiles_cover_the_frame_once() {
+    let grid = tiles((WIDTH, HEIGHT), 64);
+    assert_eq!(grid.len(), 6);
+    let mut covered = vec![0; (WIDTH * HEIGHT) as usize];
+    for tile in &grid {
+        for y in tile.y..tile.y + tile.height {
+            for x in tile.x..tile.x + tile.width {
+                covered[(y * WIDTH + x) as usize] += 1;
+            }
+        }
+    }
+    assert!(covered.iter().all(|&count| count == 1));
+    assert_eq!(tile_zoom(&grid), 2, "three columns need a 4 by 4 grid");
+    assert_eq!(tile_zoom(&tiles((64, 64), 64)), 0);
+    assert_eq!(tile_zoom(&tiles((128, 64), 64)), 1);
+}
+
+#[test]
+fn tiled_trails_match_the_whole_frame_exactly() {
+    let past = [ring(40.0), ring(60.0), ring(80.0)];
+    let history: Vec<(&RgbaImage, f32)> = past.iter().enumerate().map(|(i, frame)| (frame, (3 - i) as f32 / 4.0)).collect();
+    let current = ring(100.0);
+    let whole = TrailSettings { history_length: 3, ..TrailSettings::default() };
+    let tiled = TrailSettings { tile_size: 64, ..whole.clone() };
+
+    assert!(compose_trail(&current, &history, &tiled) == compose_trail(&current, &history, &whole));
+    assert_eq!(compose_trail_counted(&current, &history, &tiled), compose_trail_counted(&current, &history, &whole));
+
+    let shaded = TrailSettings { shader: Some(Shader(Arc::new(Position))), ..whole.clone() };
+    let shaded_tiled = TrailSettings { tile_size: 64, ..shaded.clone() };
+    assert!(compose_trail(&current, &history, &shaded_tiled) == compose_trail(&current, &history, &shaded));
+
+    let strobed = TrailSettings { strobe: 2, strobe_dim: 0.3, echo_rule: "alpha>150".to_string(), ..whole };
+    let strobed_tiled = TrailSettings { tile_size: 64, ..strobed.clone() };
+    assert!(compose_trail(&current, &history, &strobed_tiled) == compose_trail(&current, &history, &strobed));
+
+    // Nothing to draw anywhere: every tile is skipped and left as background
+    let empty = RgbaImage::new(WIDTH, HEIGHT);
+    assert!(compose_trail(&empty, &[], &tiled).pixels().all(|pixel| pixel == &Rgba([0, 0, 0, 255])));
+}
+
+#[test]
+fn rejects_tiles_too_small() {
+    for (tile_size, valid) in [(0, true), (16, false), (64, true), (4096, true)] {
+        let settings = TrailSettings { tile_size, ..TrailSettings::default() };
+        assert_eq!(settings.validate().is_ok(), valid, "{}", tile_size);
+    }
+}
+
+#[test]
+fn tiled_output_stitches_back_to_the_whole_trail() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_tiles_{}", std::process::id()));
+    let (source, whole_dir, tiled_dir) = (dir.join("frames"), dir.join("whole"), dir.join("tiled"));
+    std::fs::create_dir_all(&source).unwrap();
+    let paths: Vec<PathBuf> = (0..3)
+        .map(|i| {
+            let path = source.join(format!("scan_{}.png", i));
+            ring(40.0 + i as f32 * 30.0).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { history_length: 2, tile_size: 64, ..TrailSettings::default() };
+    let render = |sink: &DirectorySink| engine::render_sequence(&paths, &settings, sink, |_| ControlFlow::Continue(()));
+    assert!(render(&DirectorySink::new(&whole_dir, &source)).failures.is_empty());
+    assert!(render(&DirectorySink::new(&tiled_dir, &source).with_tiles(64)).failures.is_empty());
+
+    let whole = image::open(whole_dir.join("scan_2.png")).unwrap().to_rgba8();
+    let mut stitched = RgbaImage::new(WIDTH, HEIGHT);
+    for tile in tiles((WIDTH, HEIGHT), 64) {
+        let path = tiled_dir.join(format!("scan_2/2/{}/{}.png", tile.column, tile.row));
+        let image = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (64, 64), "{}", path.display());
+        stitched.copy_from(&*image::imageops::crop_imm(&image, 0, 0, tile.width, tile.height), tile.x, tile.y).unwrap();
+    }
+    let padding = image::open(tiled_dir.join("scan_2/2/2/1.png")).unwrap().to_rgba8();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(stitched == whole);
+    assert_eq!(padding.get_pixel(63, 63), &Rgba([0, 0, 0, 0]));
+}
@@ -0,0 +1,56 @@
+//! Settings files in TOML as well as JSON
+#![cfg(all(feature = "native", feature = "toml"))]
+
+use radar_echo_trails::config::{self, ConfigFormat, Settings, SettingsError};
+
+fn customized() -> Settings {
+    let mut settings = Settings { limit: Some(25), stride: 3, config_format: ConfigFormat::Toml, ..Settings::default() };
+    settings.rendering.history_length = 9;
+    settings.rendering.history_gradient = "#ff0000:#0000ff".to_string();
+    settings.rendering.fade_curve = "ease-out".to_string();
+    settings.performance.threads = 4;
+    settings.output.latest_image = Some("latest.png".into());
+    settings
+}
+
+#[test]
+fn settings_round_trip_identically_through_both_formats() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_toml_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let json = dir.join("settings.json");
+    let toml = dir.join("settings.toml");
+
+    for settings in [Settings::default(), customized()] {
+        config::export_settings(&settings, &json).unwrap();
+        let from_json = config::import_settings(&json).unwrap();
+        config::export_settings(&from_json, &toml).unwrap();
+        assert!(std::fs::read_to_string(&toml).unwrap().contains("[settings.rendering]"));
+        let from_toml = config::import_settings(&toml).unwrap();
+        assert_eq!(serde_json::to_value(&from_toml).unwrap(), serde_json::to_value(&settings).unwrap());
+
+        // And back to the very same JSON file
+        let first = std::fs::read_to_string(&json).unwrap();
+        config::export_settings(&from_toml, &json).unwrap();
+        assert_eq!(std::fs::read_to_string(&json).unwrap(), first);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_toml_parse_error_gives_its_line_and_column() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_toml_error_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("broken.toml");
+    std::fs::write(&path, "stride = 2\n\n[rendering]\nhistory_length = = 5\n").unwrap();
+
+    let error = config::import_settings(&path).unwrap_err();
+    match &error {
+        SettingsError::Parse { format: ConfigFormat::Toml, location: Some((line, column)), .. } => {
+            assert_eq!(*line, 4);
+            assert_eq!(*column, 18);
+        }
+        other => panic!("expected a TOML parse error with a location, got {:?}", other),
+    }
+    assert!(error.to_string().contains("not valid TOML at line 4, column 18"), "{}", error);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,135 @@
+//! Centroid tracking over synthetic moving blobs
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::settings::TrackingSettings;
+use radar_echo_trails::tracking::{find_cells, match_cells, track_cells, Cell, CellTracker};
+
+/// A black frame with a white square of side `size` at each of `blobs`' top-left corners
+fn frame(blobs: &[(u32, u32)], size: u32) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(64, 64, Rgba([0, 0, 0, 255]));
+    for &(left, top) in blobs {
+        for y in top..top + size {
+            for x in left..left + size {
+                image.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+    }
+    image
+}
+
+fn settings() -> TrackingSettings {
+    TrackingSettings { enabled: true, min_area: 4, max_displacement: 8, ..TrackingSettings::default() }
+}
+
+fn cell(x: f32, y: f32) -> Cell {
+    Cell { centroid: (x, y), area: 16, mean_intensity: 255.0 }
+}
+
+#[test]
+fn finds_centroids_and_drops_small_cells() {
+    let mut image = frame(&[(10, 10)], 4);
+    image.put_pixel(40, 40, Rgba([255, 255, 255, 255]));
+    let cells = find_cells(&image, &settings());
+    assert_eq!(cells, vec![Cell { centroid: (11.5, 11.5), area: 16, mean_intensity: 255.0 }]);
+}
+
+#[test]
+fn empty_frames_have_no_cells_or_tracks() {
+    let empty = frame(&[], 4);
+    assert!(find_cells(&empty, &settings()).is_empty());
+    assert!(track_cells(&empty, &[(&empty, 0.5)], &settings()).is_empty());
+    assert!(match_cells(&[], &[], 8.0).is_empty());
+    assert_eq!(match_cells(&[], &[cell(1.0, 1.0)], 8.0), vec![None]);
+}
+
+#[test]
+fn follows_a_moving_blob_through_the_history() {
+    let history = [frame(&[(10, 10)], 4), frame(&[(14, 12)], 4), frame(&[(18, 14)], 4)];
+    let current = frame(&[(22, 16)], 4);
+    let aged: Vec<(&RgbaImage, f32)> = history.iter().zip([0.75, 0.5, 0.25]).collect();
+    let tracks = track_cells(&current, &aged, &settings());
+
+    assert_eq!(tracks.len(), 1);
+    let positions: Vec<(f32, f32)> = tracks[0].points.iter().map(|p| p.position).collect();
+    assert_eq!(positions, vec![(11.5, 11.5), (15.5, 13.5), (19.5, 15.5), (23.5, 17.5)]);
+    let ages: Vec<f32> = tracks[0].points.iter().map(|p| p.age).collect();
+    assert_eq!(ages, vec![0.75, 0.5, 0.25, 0.0]);
+}
+
+#[test]
+fn a_jump_beyond_the_maximum_starts_a_new_track() {
+    let previous = frame(&[(4, 4)], 4);
+    let current = frame(&[(40, 40)], 4);
+    let tracks = track_cells(&current, &[(&previous, 0.5)], &settings());
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].points.len(), 1);
+}
+
+#[test]
+fn a_split_continues_the_nearer_part_and_starts_the_other() {
+    let previous = frame(&[(20, 20)], 6);
+    let current = frame(&[(19, 20), (27, 20)], 4);
+    let tracks = track_cells(&current, &[(&previous, 0.5)], &settings());
+
+    let mut lengths: Vec<usize> = tracks.iter().map(|t| t.points.len()).collect();
+    lengths.sort();
+    assert_eq!(lengths, vec![1, 2]);
+}
+
+#[test]
+fn a_merge_continues_one_track_and_ends_the_other() {
+    let previous = frame(&[(19, 20), (27, 20)], 4);
+    let current = frame(&[(20, 20)], 6);
+    let tracks = track_cells(&current, &[(&previous, 0.5)], &settings());
+
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].points.len(), 2);
+    assert_eq!(tracks[0].points[0].position, (20.5, 21.5));
+}
+
+#[test]
+fn matching_is_one_to_one_nearest_first() {
+    let previous = [cell(10.0, 10.0), cell(20.0, 10.0)];
+    let current = [cell(12.0, 10.0), cell(13.0, 10.0), cell(19.0, 10.0)];
+    assert_eq!(match_cells(&previous, &current, 8.0), vec![Some(0), None, Some(1)]);
+}
+
+fn ids(tracker: &mut CellTracker, blobs: &[(u32, u32)]) -> Vec<u32> {
+    tracker.push(&frame(blobs, 4)).iter().map(|labelled| labelled.id).collect()
+}
+
+#[test]
+fn ids_persist_and_new_cells_count_up() {
+    let mut tracker = CellTracker::new(settings());
+    assert_eq!(ids(&mut tracker, &[(10, 10)]), vec![1]);
+    assert_eq!(ids(&mut tracker, &[(14, 10), (40, 40)]), vec![1, 2]);
+    assert_eq!(ids(&mut tracker, &[(18, 10), (44, 40)]), vec![1, 2]);
+}
+
+#[test]
+fn ids_survive_a_short_gap_and_close_after_a_long_one() {
+    let mut tracker = CellTracker::new(TrackingSettings { max_missed: 2, ..settings() });
+    assert_eq!(ids(&mut tracker, &[(10, 10)]), vec![1]);
+    ids(&mut tracker, &[]);
+    ids(&mut tracker, &[]);
+    assert_eq!(ids(&mut tracker, &[(12, 10)]), vec![1]);
+
+    for _ in 0..3 {
+        ids(&mut tracker, &[]);
+    }
+    assert_eq!(ids(&mut tracker, &[(12, 10)]), vec![2]);
+}
+
+#[test]
+fn ids_are_the_same_on_every_run() {
+    let sequence: Vec<Vec<(u32, u32)>> = (0..6u32)
+        .map(|i| vec![(4 + 3 * i, 10), (50 - 2 * i, 30), (20, 50 - i)])
+        .collect();
+    let run = || {
+        let mut tracker = CellTracker::new(settings());
+        sequence.iter().map(|blobs| ids(&mut tracker, blobs)).collect::<Vec<_>>()
+    };
+    let first = run();
+    assert_eq!(first, run());
+    assert!(first.iter().all(|frame| frame.len() == 3 && frame.iter().all(|&id| id <= 3)));
+}
@@ -0,0 +1,53 @@
+//! The library's single entry point, for frames in memory or a folder
+#![cfg(feature = "native")]
+
+use std::path::PathBuf;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::settings::TrailMode;
+use radar_echo_trails::{ProcessingSettings, Trailer, TrailerError};
+
+/// An echo moving right one column a frame
+fn frames(count: u32) -> Vec<RgbaImage> {
+    (0..count)
+        .map(|i| RgbaImage::from_fn(count, 3, |x, y| if x == i || (x + 1 == i && y == 1) { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) }))
+        .collect()
+}
+
+#[test]
+fn frames_in_memory_match_the_same_frames_from_disk() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_trailer_{}", std::process::id()));
+    let (input, output) = (dir.join("input"), dir.join("output"));
+    std::fs::create_dir_all(&input).unwrap();
+    let frames = frames(8);
+    let names: Vec<PathBuf> = (0..frames.len()).map(|i| PathBuf::from(format!("scan_{}.png", i))).collect();
+    for (frame, name) in frames.iter().zip(&names) {
+        frame.save(input.join(name)).unwrap();
+    }
+
+    for trail_mode in [TrailMode::Fade, TrailMode::Decay] {
+        let trailer = Trailer::new(ProcessingSettings { history_length: 4, trail_mode, ..ProcessingSettings::default() }).unwrap();
+        let in_memory = trailer.process_frames(&frames);
+        let summary = trailer.process_dir(&input, &output).unwrap();
+        assert!(summary.failures.is_empty(), "{:?}", summary.failures);
+        assert_eq!(summary.frames_completed, frames.len());
+        for (i, (trail, name)) in in_memory.iter().zip(&names).enumerate() {
+            let from_disk = image::open(output.join(name)).unwrap().to_rgba8();
+            assert!(trail == &from_disk, "{} frame {} differs", trail_mode, i);
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn errors_say_what_went_wrong() {
+    let invalid = Trailer::new(ProcessingSettings { history_length: 0, decay: 1.5, ..ProcessingSettings::default() });
+    let Err(TrailerError::InvalidSettings(fields)) = invalid else { panic!("settings were accepted") };
+    assert_eq!(fields.iter().map(|field| field.field.as_str()).collect::<Vec<_>>(), ["history_length", "decay"]);
+
+    let missing = std::env::temp_dir().join(format!("radar_echo_trails_trailer_missing_{}", std::process::id()));
+    let error = Trailer::new(ProcessingSettings::default()).unwrap().process_dir(&missing, &missing.join("out")).unwrap_err();
+    assert!(matches!(error, TrailerError::Scan(_)), "{:?}", error);
+    assert!(error.to_string().contains(&missing.display().to_string()));
+    assert!(std::error::Error::source(&error).is_some());
+}
@@ -0,0 +1,90 @@
+//! Trails on a transparent background, for compositing them later
+#![cfg(feature = "native")]
+
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+use radar_echo_trails::engine::{self, compose_trail, history_age, TrailSettings};
+use radar_echo_trails::processing::{self, ProcessingSettings, ProgressUpdate};
+use radar_echo_trails::settings::TrailMode;
+
+mod common;
+use common::{echo, process};
+
+#[test]
+fn a_half_faded_echo_is_half_opaque() {
+    let settings = TrailSettings { transparent_background: true, history_length: 1, ..TrailSettings::default() };
+    let past = echo(0, 4);
+    let trail = compose_trail(&echo(3, 4), &[(&past, history_age(1, 1))], &settings);
+    let ghost = trail.get_pixel(0, 0);
+    assert_eq!(&ghost.0[..3], &[255, 127, 0]);
+    assert!((126..=130).contains(&ghost[3]), "{:?}", ghost);
+    assert_eq!(trail.get_pixel(1, 0), &Rgba([0, 0, 0, 0]));
+    assert_eq!(trail.get_pixel(3, 0), &Rgba([0, 255, 0, 255]));
+
+    // Opaque as before without it
+    let opaque = compose_trail(&echo(3, 4), &[(&past, history_age(1, 1))], &TrailSettings { transparent_background: false, ..settings.clone() });
+    assert_eq!(opaque.get_pixel(0, 0), &Rgba([128, 63, 0, 255]));
+    assert_ne!(settings.output_hash(), TrailSettings { history_length: 1, ..TrailSettings::default() }.output_hash());
+}
+
+#[test]
+fn decay_trails_keep_their_opacity_too() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_transparent_decay_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<_> = (0..2)
+        .map(|i| {
+            let path = dir.join(format!("scan_{:03}.png", i));
+            echo(i * 3, 4).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let settings = TrailSettings { transparent_background: true, history_length: 1, trail_mode: TrailMode::Decay, ..TrailSettings::default() };
+    let trails = Mutex::new(Vec::new());
+    let sink = |index: usize, _source: &Path, frame: RgbaImage| {
+        trails.lock().unwrap().push((index, frame));
+        Ok(())
+    };
+    engine::render_sequence(&paths, &settings, &sink, |_| ControlFlow::Continue(()));
+    let trails = trails.into_inner().unwrap();
+    let (_, last) = trails.iter().find(|(index, _)| *index == 1).unwrap();
+    assert!((126..=130).contains(&last.get_pixel(0, 0)[3]), "{:?}", last.get_pixel(0, 0));
+    assert_eq!(last.get_pixel(1, 0)[3], 0);
+    assert_eq!(last.get_pixel(3, 0), &Rgba([0, 255, 0, 255]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn trails_of_jpeg_frames_are_written_as_png() {
+    let dir = std::env::temp_dir().join(format!("radar_echo_trails_transparent_jpeg_{}", std::process::id()));
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    for i in 0..2 {
+        let frame = image::DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, _| {
+            if x / 2 == i { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 255]) }
+        }));
+        frame.to_rgb8().save(frames.join(format!("scan_{:03}.jpg", i))).unwrap();
+    }
+
+    let run = |settings: &ProcessingSettings| {
+        let updates = process(&frames, settings);
+        assert!(updates.iter().any(|update| matches!(update, ProgressUpdate::FolderCompleted { frames_rendered: 2, .. })), "{:?}", updates);
+        updates
+    };
+
+    // Opaque trails are written as JPEG, without their alpha
+    let opaque = ProcessingSettings::default();
+    run(&opaque);
+    assert!(processing::output_dir_for(&frames, &opaque).join("scan_001.jpg").exists());
+    std::fs::remove_dir_all(processing::output_dir_for(&frames, &opaque)).unwrap();
+
+    let transparent = ProcessingSettings { transparent_background: true, ..ProcessingSettings::default() };
+    let updates = run(&transparent);
+    assert!(updates.iter().any(|update| matches!(update, ProgressUpdate::FolderWarning { message, .. } if message.contains("JPEG"))));
+    let trail = image::open(processing::output_dir_for(&frames, &transparent).join("scan_001.png")).unwrap().to_rgba8();
+    assert_eq!(trail.get_pixel(7, 0)[3], 0);
+    assert_eq!(trail.get_pixel(2, 0)[3], 255);
+    std::fs::remove_dir_all(&dir).unwrap();
+}